@@ -0,0 +1,83 @@
+// Build script: gathers metadata at compile time so `--version` can report
+// exactly which build a user is running (handy when filing bug reports).
+//
+// Each value is handed to the compiler as an environment variable via
+// `cargo:rustc-env=...` and read back in the crate with `env!(...)`.
+
+use std::{
+    env,                                  // Read Cargo-provided environment variables
+    process::Command,                     // Run `git` to find the current commit
+    time::{SystemTime, UNIX_EPOCH},       // Current time for the build timestamp
+};
+
+fn main() {
+    // Step 1: Git commit hash (short form). Falls back to "unknown" when building
+    // from a source tarball or on a machine without git installed.
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=CRABBY_GIT_COMMIT={}", commit);
+
+    // Step 2: Build timestamp in UTC (RFC 3339). Honors SOURCE_DATE_EPOCH so
+    // reproducible builds produce identical output.
+    let epoch_secs = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+    println!("cargo:rustc-env=CRABBY_BUILD_TIMESTAMP={}", rfc3339_utc(epoch_secs));
+
+    // Step 3: Enabled Cargo features. Cargo exposes each one as CARGO_FEATURE_<NAME>
+    // (upper-cased, '-' turned into '_'), so we turn them back into their usual spelling.
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(str::to_string))
+        .map(|name| name.to_lowercase().replace('_', "-"))
+        .filter(|name| name != "default")  // "default" is just a bundle of the others
+        .collect();
+    features.sort(); // Sorted so the output is stable across builds
+    let features = if features.is_empty() { "none".to_string() } else { features.join(",") };
+    println!("cargo:rustc-env=CRABBY_FEATURES={}", features);
+
+    // Step 4: Tell Cargo when to re-run this script (new commit, checkout, or env change)
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
+
+// Formats seconds since the Unix epoch as "YYYY-MM-DDTHH:MM:SSZ" without pulling in a date crate.
+fn rfc3339_utc(epoch_secs: u64) -> String {
+    let days = (epoch_secs / 86_400) as i64;
+    let secs_of_day = epoch_secs % 86_400;
+
+    // Convert days since 1970-01-01 into a civil (year, month, day) date.
+    // Algorithm from Howard Hinnant's "chrono-compatible low-level date algorithms".
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        (secs_of_day % 3_600) / 60,
+        secs_of_day % 60
+    )
+}
@@ -0,0 +1,998 @@
+// Price alerts: rules loaded from a TOML file, evaluated on every update.
+//
+// Example alerts file:
+//
+//     [[alert]]
+//     symbol = "BTC-USD"
+//     above = 70000
+//
+//     [[alert]]
+//     symbol = "ETH-USD"
+//     change_pct = -5.0      # drops 5%...
+//     window = "15m"         # ...within 15 minutes
+//     cooldown = "30m"       # at most one notification per 30 minutes
+//     desktop = true         # also show a desktop notification
+//
+//     [[alert]]
+//     symbol = "BTC-USD"
+//     indicator = "RSI(14)"  # compare an indicator instead of the price...
+//     below = 30             # ...with `above` or `below`
+//
+//     [[alert]]
+//     symbol = "BTC-USD"
+//     volume_spike = 3       # the last minute's volume is 3x the average minute's
+//     window = "1m"          # (needs [trades] enabled)
+//
+//     [[alert]]
+//     symbol = "BTC-USD"
+//     exchange = "binance-futures"
+//     funding_above = 0.05   # the perpetual's funding rate, in percent (see funding.rs)
+//
+//     [[alert]]
+//     symbol = "BTC-USD"
+//     below = 60000
+//     trade = { side = "buy", quantity = 0.001 }  # also place an order (see trading.rs)
+//
+//     [[alert]]
+//     symbol = "BTC-USD"
+//     trailing_stop_pct = 5  # 5% below the highest price since the rule was armed
+//
+//     [[alert]]
+//     symbol = "ETH-USD"
+//     take_profit_pct = 20   # 20% above the position's average cost (or `stop_loss_pct`
+//                            # below it); the position comes from the portfolio
+//
+// Alerts fire on the *edge*: when a condition goes from false to true. A price
+// hovering right at the threshold therefore fires once, not on every tick, and
+// the cooldown additionally limits how often a single rule can fire.
+//
+// A trailing stop is armed at the first price it sees and follows the highest price
+// since. When it fires it re-arms at that price, so the next alert takes another fall
+// of the same size from a new high. Take profit and stop loss follow the position as
+// it changes (e.g. with DCA buys), and never fire for a symbol that isn't held.
+//
+// With `alert_state = "alerts.state.json"` in the config, what the rules know (which
+// conditions already fired and when, the trailing stops' highs) is saved every few
+// seconds and restored at the next start, so a restart neither re-fires an alert nor
+// resets a trailing stop. Give a rule an `id` to keep its state when the rule is edited:
+//
+//     [[alert]]
+//     id = "btc-stop"
+//     symbol = "BTC-USD"
+//     trailing_stop_pct = 4  # was 5; the high since arming is kept
+//
+// A rule without one is known by its description ("BTC-USD trailing stop 5%"), so
+// changing it starts it afresh.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},  // Per-exchange state, price history for % change rules; rule ids
+    error::Error,                      // Trait to return errors from our functions
+    fmt,                               // Rule descriptions
+    fs,                                // Read the alerts file, save the rule state
+    io,                                // A missing state file is a first start
+    path::{Path, PathBuf},             // Path to the alerts and state files
+    sync::Arc,                         // Portfolio shared with the printout
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::deserialize_opt_duration;
+use crate::funding::FundingInfo;
+use crate::indicators::{Indicator, Indicators};
+use crate::notify::Notifier;
+use crate::paper::Side;
+use crate::portfolio::Portfolio;
+use crate::store::{epoch_ms, PriceStore, PriceUpdate};
+use crate::volume;
+
+// Used when a rule doesn't set its own cooldown
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+// How often the rule state is written to the state file, when it changed
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+// Volume spike window when a rule doesn't set one
+const DEFAULT_VOLUME_WINDOW: Duration = Duration::from_secs(60);
+
+// One rule as written in the alerts file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    #[serde(default)]
+    pub id: Option<String>,               // Names the rule's saved state (default: its description)
+    pub symbol: String,                   // e.g. "BTC-USD"
+    #[serde(default)]
+    pub exchange: Option<String>,         // Only watch this exchange (default: all)
+    #[serde(default)]
+    pub above: Option<Decimal>,           // Fire when price rises above this
+    #[serde(default)]
+    pub below: Option<Decimal>,           // Fire when price falls below this
+    #[serde(default)]
+    pub change_pct: Option<Decimal>,      // Fire on a % move (negative = drop) within `window`
+    #[serde(default)]
+    pub volume_spike: Option<Decimal>,    // Fire when the volume of the last `window` is this many times the average
+    #[serde(default)]
+    pub funding_above: Option<Decimal>,   // Fire when a perpetual's funding rate (in %) rises above this
+    #[serde(default)]
+    pub funding_below: Option<Decimal>,   // ...or falls below this (e.g. -0.01 for shorts paying longs)
+    #[serde(default)]
+    pub trailing_stop_pct: Option<Decimal>,  // Fire when the price falls this % below its high since arming
+    #[serde(default)]
+    pub take_profit_pct: Option<Decimal>,    // Fire when the price is this % above the position's average cost
+    #[serde(default)]
+    pub stop_loss_pct: Option<Decimal>,      // ...or this % below it
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub window: Option<Duration>,         // Look-back for `change_pct` (e.g. "15m") or `volume_spike` (default 1m)
+    #[serde(default)]
+    pub indicator: Option<Indicator>,     // Compare this indicator (e.g. "RSI(14)") with above/below instead of the price
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub cooldown: Option<Duration>,       // Minimum time between two notifications
+    #[serde(default)]
+    pub desktop: bool,                    // Also send a native desktop notification
+    #[serde(default)]
+    pub trade: Option<TradeAction>,       // Also place a real order (only with --enable-trading)
+}
+
+// The order an alert rule places when it fires
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TradeAction {
+    pub side: Side,
+    pub quantity: Decimal,                // In coins
+    #[serde(default)]
+    pub limit: Option<Decimal>,           // Limit price (good till cancelled); market order without
+}
+
+impl TradeAction {
+    // What's wrong with this order, if anything
+    pub fn check(&self) -> Result<(), String> {
+        if self.quantity <= Decimal::ZERO {
+            return Err("trade quantity must be greater than zero".to_string());
+        }
+        if self.limit.is_some_and(|l| l <= Decimal::ZERO) {
+            return Err("trade limit price must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TradeAction {
+    // "buy 0.001 at market", "sell 0.5 @ 70000"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.side, self.quantity)?;
+        match self.limit {
+            Some(limit) => write!(f, " @ {}", limit),
+            None => write!(f, " at market"),
+        }
+    }
+}
+
+// Top-level layout of the alerts file: a list of [[alert]] tables
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AlertsFile {
+    #[serde(default)]
+    alert: Vec<RuleConfig>,
+}
+
+// What a rule checks
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Above(Decimal),
+    Below(Decimal),
+    Change { pct: Decimal, window: Duration },
+    IndicatorAbove(Indicator, Decimal),
+    IndicatorBelow(Indicator, Decimal),
+    VolumeSpike { multiple: Decimal, window: Duration },
+    FundingAbove(Decimal),  // Percent per funding interval
+    FundingBelow(Decimal),
+    TrailingStop(Decimal),  // Percent below the high-water mark
+    TakeProfit(Decimal),    // Percent above the position's average cost
+    StopLoss(Decimal),      // Percent below it
+}
+
+// A validated rule, ready to evaluate
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub id: String,                       // The `id` from the config, or the description
+    pub symbol: String,
+    pub exchange: Option<String>,
+    pub condition: Condition,
+    pub cooldown: Duration,
+    pub desktop: bool,
+    pub trade: Option<TradeAction>,
+}
+
+impl fmt::Display for Rule {
+    // Short description used in notifications, e.g. "BTC-USD above 70000"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.condition {
+            Condition::Above(level) => write!(f, "{} above {}", self.symbol, level),
+            Condition::Below(level) => write!(f, "{} below {}", self.symbol, level),
+            Condition::Change { pct, window } => {
+                let verb = if pct.is_sign_negative() { "drops" } else { "rises" };
+                write!(f, "{} {} {}% in {}", self.symbol, verb, pct.abs(), humantime::format_duration(*window))
+            }
+            Condition::IndicatorAbove(indicator, level) => write!(f, "{} {} above {}", self.symbol, indicator, level),
+            Condition::IndicatorBelow(indicator, level) => write!(f, "{} {} below {}", self.symbol, indicator, level),
+            Condition::VolumeSpike { multiple, window } => {
+                write!(f, "{} volume {}x the average {}", self.symbol, multiple, humantime::format_duration(*window))
+            }
+            Condition::FundingAbove(pct) => write!(f, "{} funding rate above {}%", self.symbol, pct),
+            Condition::FundingBelow(pct) => write!(f, "{} funding rate below {}%", self.symbol, pct),
+            Condition::TrailingStop(pct) => write!(f, "{} trailing stop {}%", self.symbol, pct),
+            Condition::TakeProfit(pct) => write!(f, "{} take profit +{}%", self.symbol, pct),
+            Condition::StopLoss(pct) => write!(f, "{} stop loss -{}%", self.symbol, pct),
+        }
+    }
+}
+
+impl TryFrom<RuleConfig> for Rule {
+    type Error = String;
+
+    // Check that exactly one condition is set and that it makes sense
+    fn try_from(cfg: RuleConfig) -> Result<Self, Self::Error> {
+        let funding = match (cfg.funding_above, cfg.funding_below) {
+            (Some(pct), None) => Some(Condition::FundingAbove(pct)),
+            (None, Some(pct)) => Some(Condition::FundingBelow(pct)),
+            (None, None) => None,
+            (Some(_), Some(_)) => return Err(format!("alert for {}: set only one of `funding_above` or `funding_below`", cfg.symbol)),
+        };
+        let stop = match (cfg.trailing_stop_pct, cfg.take_profit_pct, cfg.stop_loss_pct) {
+            (Some(pct), None, None) if pct > Decimal::ZERO && pct < Decimal::ONE_HUNDRED => Some(Condition::TrailingStop(pct)),
+            (None, Some(pct), None) if pct > Decimal::ZERO => Some(Condition::TakeProfit(pct)),
+            (None, None, Some(pct)) if pct > Decimal::ZERO && pct < Decimal::ONE_HUNDRED => Some(Condition::StopLoss(pct)),
+            (None, None, None) => None,
+            (Some(_), None, None) | (None, Some(_), None) | (None, None, Some(_)) => {
+                return Err(format!("alert for {}: a trailing stop, take profit or stop loss must be more than 0% (and a fall less than 100%)", cfg.symbol))
+            }
+            _ => return Err(format!("alert for {}: set only one of `trailing_stop_pct`, `take_profit_pct` or `stop_loss_pct`", cfg.symbol)),
+        };
+        let single = match (funding, stop) {
+            (Some(_), Some(_)) => return Err(format!("alert for {}: a funding rate rule takes no other condition", cfg.symbol)),
+            (funding, stop) => funding.or(stop),
+        };
+        let condition = match (cfg.above, cfg.below, cfg.change_pct, cfg.volume_spike) {
+            (None, None, None, None) if cfg.indicator.is_none() && let Some(single) = single => single,
+            _ if single.is_some() => {
+                return Err(format!("alert for {}: a funding rate, trailing stop, take profit or stop loss rule takes no other condition", cfg.symbol))
+            }
+            (Some(level), None, None, None) => match cfg.indicator {
+                Some(indicator) => Condition::IndicatorAbove(indicator, level),
+                None => Condition::Above(level),
+            },
+            (None, Some(level), None, None) => match cfg.indicator {
+                Some(indicator) => Condition::IndicatorBelow(indicator, level),
+                None => Condition::Below(level),
+            },
+            (None, None, _, _) if cfg.indicator.is_some() => {
+                return Err(format!("alert for {}: `indicator` goes with `above` or `below`", cfg.symbol))
+            }
+            (None, None, Some(pct), None) => {
+                let window = cfg
+                    .window
+                    .ok_or_else(|| format!("alert for {}: change_pct needs a window (e.g. \"15m\")", cfg.symbol))?;
+                if pct.is_zero() {
+                    return Err(format!("alert for {}: change_pct must not be 0", cfg.symbol));
+                }
+                Condition::Change { pct, window }
+            }
+            (None, None, None, Some(multiple)) => {
+                let window = cfg.window.unwrap_or(DEFAULT_VOLUME_WINDOW);
+                if multiple <= Decimal::ONE {
+                    return Err(format!("alert for {}: volume_spike must be more than 1 (times the average)", cfg.symbol));
+                }
+                // The average comes from the rest of the kept hour, so leave at least as much again
+                if window.is_zero() || window > volume::HISTORY / 2 {
+                    return Err(format!("alert for {}: a volume_spike window must be between 1s and 30m", cfg.symbol));
+                }
+                Condition::VolumeSpike { multiple, window }
+            }
+            _ => {
+                return Err(format!(
+                    "alert for {}: set exactly one of `above`, `below`, `change_pct`, `volume_spike`, `funding_above`, `funding_below`, `trailing_stop_pct`, `take_profit_pct` or `stop_loss_pct`",
+                    cfg.symbol
+                ))
+            }
+        };
+
+        if let Some(trade) = &cfg.trade {
+            trade.check().map_err(|e| format!("alert for {}: {}", cfg.symbol, e))?;
+        }
+        if cfg.id.as_ref().is_some_and(|id| id.trim().is_empty()) {
+            return Err(format!("alert for {}: `id` must not be empty", cfg.symbol));
+        }
+
+        let mut rule = Rule {
+            id: String::new(),
+            symbol: cfg.symbol,
+            exchange: cfg.exchange,
+            condition,
+            cooldown: cfg.cooldown.unwrap_or(DEFAULT_COOLDOWN),
+            desktop: cfg.desktop,
+            trade: cfg.trade,
+        };
+        rule.id = cfg.id.unwrap_or_else(|| rule.to_string());
+        Ok(rule)
+    }
+}
+
+impl Rule {
+    // Does this rule compare an indicator rather than the price?
+    pub fn uses_indicator(&self) -> bool {
+        matches!(self.condition, Condition::IndicatorAbove(..) | Condition::IndicatorBelow(..))
+    }
+
+    // Does this rule need trade volumes?
+    pub fn uses_volume(&self) -> bool {
+        matches!(self.condition, Condition::VolumeSpike { .. })
+    }
+
+    // Does this rule need a futures exchange's funding rates?
+    pub fn uses_funding(&self) -> bool {
+        matches!(self.condition, Condition::FundingAbove(_) | Condition::FundingBelow(_))
+    }
+
+    // Does this rule compare the price with a portfolio position?
+    pub fn uses_portfolio(&self) -> bool {
+        matches!(self.condition, Condition::TakeProfit(_) | Condition::StopLoss(_))
+    }
+
+    // Is this rule about the symbol (and exchange) of `update`?
+    fn applies_to(&self, update: &PriceUpdate) -> bool {
+        self.symbol == update.symbol && self.exchange.as_deref().is_none_or(|ex| ex == update.exchange)
+    }
+}
+
+// A rule that fired
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub rule: String,             // Rule description, e.g. "BTC-USD above 70000"
+    pub exchange: &'static str,   // Exchange whose price triggered it
+    pub symbol: String,
+    pub price: Decimal,           // Price that triggered it
+    pub detail: String,           // Extra context, e.g. the measured % change
+    pub fired_at: SystemTime,
+    pub desktop: bool,            // The rule asked for a desktop notification
+    pub trade: Option<TradeAction>,  // The order the rule places, if any
+}
+
+// Per rule, per exchange bookkeeping
+#[derive(Debug, Default)]
+struct RuleState {
+    active: bool,                        // Was the condition true on the previous tick?
+    last_fired: Option<Instant>,         // For the cooldown
+    history: VecDeque<(Instant, Decimal)>,  // Recent prices, only used by % change rules
+    high: Option<Decimal>,               // High-water mark, only used by trailing stops
+}
+
+// One rule's state in the state file, under the rule's id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedState {
+    #[serde(alias = "rule")]             // What older versions called it
+    id: String,                          // e.g. "btc-stop" or "BTC-USD trailing stop 5%"
+    exchange: String,
+    active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_fired_ms: Option<u64>,          // Unix time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    high: Option<Decimal>,
+}
+
+impl SavedState {
+    fn new(rule: &Rule, exchange: &str, state: &RuleState) -> Self {
+        Self {
+            id: rule.id.clone(),
+            exchange: exchange.to_string(),
+            active: state.active,
+            last_fired_ms: state.last_fired.and_then(|t| SystemTime::now().checked_sub(t.elapsed())).map(epoch_ms),
+            high: state.high,
+        }
+    }
+
+    fn restore(&self) -> RuleState {
+        let last_fired = self.last_fired_ms.and_then(|ms| {
+            let ago = SystemTime::now().duration_since(UNIX_EPOCH + Duration::from_millis(ms)).unwrap_or_default();
+            Instant::now().checked_sub(ago)
+        });
+        RuleState { active: self.active, last_fired, history: VecDeque::new(), high: self.high }
+    }
+}
+
+// What some rules read besides the price, fetched from the store for each update by
+// `AlertEngine::inputs`: the figures stay with the store's state thread, and a rule
+// reads them as of the update it's checking
+#[derive(Debug, Default, Clone)]
+pub struct Inputs {
+    spikes: HashMap<Duration, (Decimal, Decimal)>,  // Recent and average volume, per volume spike window
+    funding: Option<FundingInfo>,                   // The contract's latest mark price event
+}
+
+// Evaluates all rules against incoming updates
+pub struct AlertEngine {
+    rules: Vec<Rule>,
+    state: HashMap<(usize, &'static str), RuleState>,  // Keyed by (rule index, exchange)
+    indicators: Option<Indicators>,                     // Needed by indicator rules
+    portfolio: Option<Arc<Portfolio>>,                  // Needed by take profit and stop loss rules
+    state_file: Option<PathBuf>,                        // Where the state is saved across restarts
+    restored: HashMap<(String, String), SavedState>,    // Saved state not claimed by a price yet
+    dirty: bool,                                        // The state changed since it was saved
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            state: HashMap::new(),
+            indicators: None,
+            portfolio: None,
+            state_file: None,
+            restored: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    // Where indicator rules read their values from. Without it they never fire.
+    pub fn use_indicators(&mut self, indicators: Indicators) {
+        self.indicators = Some(indicators);
+    }
+
+    // Where take profit and stop loss rules find the positions. Without it they never fire.
+    pub fn use_portfolio(&mut self, portfolio: Arc<Portfolio>) {
+        self.portfolio = Some(portfolio);
+    }
+
+    // Keep the rules' state in `path` across restarts: restore what's there now (over
+    // what a warm-up worked out), and `save_state` writes it back. Returns how many
+    // rules had state to restore.
+    pub fn persist_to(&mut self, path: &Path) -> Result<usize, Box<dyn Error>> {
+        let saved: Vec<SavedState> = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let (known, unknown): (Vec<SavedState>, _) = saved.into_iter().partition(|s| self.rules.iter().any(|r| r.id == s.id));
+        for state in unknown {
+            tracing::warn!(rule = %state.id, exchange = %state.exchange, "Saved alert state matches no rule, dropping it");
+        }
+        self.restored = known.into_iter().map(|s| ((s.id.clone(), s.exchange.clone()), s)).collect();
+        let count = self.restored.len();
+        for ((idx, exchange), state) in self.state.iter_mut() {
+            if let Some(saved) = self.restored.remove(&(self.rules[*idx].id.clone(), exchange.to_string())) {
+                *state = saved.restore();
+            }
+        }
+        self.state_file = Some(path.to_path_buf());
+        Ok(count)
+    }
+
+    // Write the rules' state to the `persist_to` file, if it changed since the last time
+    pub fn save_state(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.state_file else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+        let mut saved: Vec<SavedState> = self.restored.values().cloned().collect();
+        saved.extend(self.state.iter().map(|((idx, exchange), state)| SavedState::new(&self.rules[*idx], exchange, state)));
+        saved.sort_by(|a, b| (&a.id, &a.exchange).cmp(&(&b.id, &b.exchange)));
+        // Through a temporary file, so a crash mid-write leaves the old state
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, serde_json::to_string_pretty(&saved)?)?;
+        fs::rename(&temp, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    // Validate rules as written in a file and build an engine from them. Two rules
+    // can't share an `id`: they would share their saved state.
+    pub fn from_configs(configs: Vec<RuleConfig>) -> Result<Self, String> {
+        let named: Vec<bool> = configs.iter().map(|c| c.id.is_some()).collect();
+        let rules = configs.into_iter().map(Rule::try_from).collect::<Result<Vec<_>, _>>()?;
+        // Checked on the ids the rules end up with: an unnamed rule goes by its description,
+        // which another rule can have as well (or as its `id`)
+        let mut ids = HashSet::new();
+        if let Some((i, rule)) = rules.iter().enumerate().find(|(_, r)| !ids.insert(r.id.as_str())) {
+            let hint = if named[i] { "" } else { " (rules without an `id` go by their description; give one of them an `id`)" };
+            return Err(format!("alert id \"{}\" is used by more than one rule{}", rule.id, hint));
+        }
+        Ok(Self::new(rules))
+    }
+
+    // Load and validate rules from a TOML alerts file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_configs(load_rule_file(path)?)?)
+    }
+
+    // Parse rules from TOML text (see the module comment for the format)
+    pub fn from_toml(text: &str) -> Result<Self, Box<dyn Error>> {
+        let file: AlertsFile = toml::from_str(text)?;
+        Ok(Self::from_configs(file.alert)?)
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    // Replay historical prices (oldest first) so % change rules have a window to compare
+    // against from the start. Nothing is notified; a condition that was already true in
+    // the history counts as already reported, so it doesn't fire the moment we start.
+    pub fn warm_up(&mut self, history: &[PriceUpdate], now: Instant) {
+        let wall_now = SystemTime::now();
+        for update in history {
+            let age = wall_now.duration_since(update.received_at).unwrap_or_default();
+            if let Some(at) = now.checked_sub(age) {
+                self.evaluate(update, at);
+            }
+        }
+    }
+
+    // What the rules matching `update` read from the store besides the price: the trade
+    // volumes of volume spike rules and the contract's funding rate
+    pub async fn inputs(&self, update: &PriceUpdate, store: &PriceStore) -> Inputs {
+        let mut inputs = Inputs::default();
+        let rules: Vec<&Rule> = self.rules.iter().filter(|r| r.applies_to(update)).collect();
+        for rule in &rules {
+            if let Condition::VolumeSpike { window, .. } = rule.condition
+                && !inputs.spikes.contains_key(&window)
+                && let Some(spike) = store.volume_spike(update.exchange, &update.symbol, window, update.received_at).await
+            {
+                inputs.spikes.insert(window, spike);
+            }
+        }
+        if rules.iter().any(|r| r.uses_funding()) {
+            inputs.funding = store.funding(update.exchange, &update.symbol).await;
+        }
+        inputs
+    }
+
+    // Check every matching rule against one update and return the alerts that fire.
+    // `now` is passed in (rather than read from the clock) so tests can control time.
+    // Rules that need `inputs` don't fire here; see `evaluate_with`.
+    pub fn evaluate(&mut self, update: &PriceUpdate, now: Instant) -> Vec<Alert> {
+        self.evaluate_with(update, &Inputs::default(), now)
+    }
+
+    // `evaluate`, with what `inputs` fetched for this update
+    pub fn evaluate_with(&mut self, update: &PriceUpdate, inputs: &Inputs, now: Instant) -> Vec<Alert> {
+        let price = update.price;
+        let mut fired = Vec::new();
+        for (idx, rule) in self.rules.iter().enumerate() {
+            if !rule.applies_to(update) {
+                continue;
+            }
+
+            let restored = &mut self.restored;
+            let state = self
+                .state
+                .entry((idx, update.exchange))
+                .or_insert_with(|| restored.remove(&(rule.id.clone(), update.exchange.to_string())).map(|s| s.restore()).unwrap_or_default());
+            let before = (state.active, state.last_fired, state.high);
+            let (triggered, detail) = match &rule.condition {
+                Condition::Above(level) => (price > *level, format!("price {} > {}", price, level)),
+                Condition::Below(level) => (price < *level, format!("price {} < {}", price, level)),
+                Condition::Change { pct, window } => {
+                    // Forget prices older than the window, then compare with the oldest remaining
+                    state.history.push_back((now, price));
+                    while state.history.front().is_some_and(|(t, _)| now.duration_since(*t) > *window) {
+                        state.history.pop_front();
+                    }
+                    let base = state.history.front().map(|(_, p)| *p).unwrap_or(price);
+                    let change =
+                        if base.is_zero() { Decimal::ZERO } else { (price - base) / base * Decimal::ONE_HUNDRED };
+                    let hit = if pct.is_sign_negative() { change <= *pct } else { change >= *pct };
+                    (hit, format!("{:+.2}% from {}", change, base))
+                }
+                Condition::IndicatorAbove(indicator, level) | Condition::IndicatorBelow(indicator, level) => {
+                    // Not enough candles yet counts as "not triggered"
+                    match self.indicators.as_ref().and_then(|i| i.reading(indicator, update.exchange, &update.symbol)) {
+                        Some(reading) if matches!(rule.condition, Condition::IndicatorAbove(..)) => {
+                            (reading.value > *level, format!("{} {} > {}", indicator, reading.value, level))
+                        }
+                        Some(reading) => (reading.value < *level, format!("{} {} < {}", indicator, reading.value, level)),
+                        None => (false, String::new()),
+                    }
+                }
+                Condition::VolumeSpike { multiple, window } => {
+                    // Measured up to this update; less than an hour of trades may still do
+                    match inputs.spikes.get(window) {
+                        Some((recent, average)) => {
+                            let ratio = (recent / average).round_dp(2);
+                            let detail = format!(
+                                "{} volume {} is {}x the average {}",
+                                humantime::format_duration(*window),
+                                recent,
+                                ratio,
+                                average.round_dp(4).normalize()
+                            );
+                            (ratio >= *multiple, detail)
+                        }
+                        None => (false, String::new()),
+                    }
+                }
+                Condition::FundingAbove(level) | Condition::FundingBelow(level) => {
+                    // Checked on the contract's price updates; spot venues have no rate
+                    match &inputs.funding {
+                        Some(info) if matches!(rule.condition, Condition::FundingAbove(_)) => {
+                            (info.rate_pct() > *level, format!("funding {}% > {}% (mark {}, index {})", info.rate_pct(), level, info.mark_price, info.index_price))
+                        }
+                        Some(info) => {
+                            (info.rate_pct() < *level, format!("funding {}% < {}% (mark {}, index {})", info.rate_pct(), level, info.mark_price, info.index_price))
+                        }
+                        None => (false, String::new()),
+                    }
+                }
+                Condition::TrailingStop(pct) => {
+                    // Armed at the first price; the mark only moves up
+                    let high = *state.high.insert(state.high.map_or(price, |h| h.max(price)));
+                    let fall = if high.is_zero() { Decimal::ZERO } else { (high - price) / high * Decimal::ONE_HUNDRED };
+                    (fall >= *pct, format!("{:.2}% below the high of {}", fall, high))
+                }
+                Condition::TakeProfit(pct) | Condition::StopLoss(pct) => {
+                    // Only while the symbol is held; the cost follows the position
+                    match self.portfolio.as_ref().and_then(|p| p.average_cost(&update.symbol)) {
+                        Some(cost) if !cost.is_zero() => {
+                            let gain = (price - cost) / cost * Decimal::ONE_HUNDRED;
+                            let hit = if matches!(rule.condition, Condition::TakeProfit(_)) { gain >= *pct } else { -gain >= *pct };
+                            (hit, format!("{:+.2}% on the position's average cost of {}", gain, cost.round_dp(8).normalize()))
+                        }
+                        _ => (false, String::new()),
+                    }
+                }
+            };
+
+            // Edge-triggered with cooldown: only fire when the condition has just become true
+            let cooled_down = state.last_fired.is_none_or(|t| now.duration_since(t) >= rule.cooldown);
+            if triggered && !state.active && cooled_down {
+                if matches!(rule.condition, Condition::TrailingStop(_)) {
+                    state.high = Some(price);  // Re-armed from here
+                }
+                state.last_fired = Some(now);
+                fired.push(Alert {
+                    rule: rule.to_string(),
+                    exchange: update.exchange,
+                    symbol: update.symbol.clone(),
+                    price: update.price,
+                    detail,
+                    fired_at: SystemTime::now(),
+                    desktop: rule.desktop,
+                    trade: rule.trade.clone(),
+                });
+            }
+            state.active = triggered;
+            self.dirty |= before != (state.active, state.last_fired, state.high);
+        }
+        fired
+    }
+}
+
+// Read the [[alert]] tables of an alerts file without validating them yet
+pub fn load_rule_file<P: AsRef<Path>>(path: P) -> Result<Vec<RuleConfig>, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let file: AlertsFile = toml::from_str(&text)?;
+    Ok(file.alert)
+}
+
+// Run the engine against every update from `store`, sending fired alerts to all notifiers
+pub fn spawn_alerts(
+    mut engine: AlertEngine,
+    store: &PriceStore,
+    notifiers: Vec<Box<dyn Notifier>>,
+) -> tokio::task::JoinHandle<()> {
+    let mut rx = store.subscribe_updates();
+    let store = store.clone();
+    let metrics = store.metrics().clone();  // Counts what the task misses
+    tokio::spawn(async move {
+        let mut save = tokio::time::interval(STATE_SAVE_INTERVAL);
+        loop {
+            tokio::select! {
+                received = rx.recv() => match received {
+                    Ok(update) => {
+                        let inputs = engine.inputs(&update, &store).await;
+                        for alert in engine.evaluate_with(&update, &inputs, Instant::now()) {
+                            for notifier in &notifiers {
+                                notifier.notify(&alert);
+                            }
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        tracing::warn!(skipped = n, "Alert engine fell behind");
+                        metrics.record_dropped_events("alerts", n);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = save.tick() => save_state(&mut engine),
+            }
+        }
+        save_state(&mut engine);
+    })
+}
+
+// Save the rule state, if it's kept; a failure is logged and tried again next time
+fn save_state(engine: &mut AlertEngine) {
+    if let Err(e) = engine.save_state() {
+        tracing::warn!(path = ?engine.state_file, error = %e, "Couldn't save the alert rule state");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(price: &str) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn threshold_fires_once_per_crossing() {
+        let mut engine = AlertEngine::from_toml(
+            r#"
+            [[alert]]
+            symbol = "BTC-USD"
+            above = 70000
+            cooldown = "0s"
+            "#,
+        )
+        .unwrap();
+        let t0 = Instant::now();
+
+        assert!(engine.evaluate(&tick("69999"), t0).is_empty());
+        assert_eq!(engine.evaluate(&tick("70001"), t0).len(), 1);
+        assert!(engine.evaluate(&tick("70002"), t0).is_empty());   // Still above: no repeat
+        assert!(engine.evaluate(&tick("69990"), t0).is_empty());
+        assert_eq!(engine.evaluate(&tick("70010"), t0).len(), 1);  // Crossed again
+    }
+
+    #[test]
+    fn cooldown_suppresses_quick_recrossing() {
+        let mut engine = AlertEngine::from_toml(
+            "[[alert]]\nsymbol = \"BTC-USD\"\nbelow = 100\ncooldown = \"10m\"\n",
+        )
+        .unwrap();
+        let t0 = Instant::now();
+
+        assert_eq!(engine.evaluate(&tick("99"), t0).len(), 1);
+        engine.evaluate(&tick("101"), t0 + Duration::from_secs(60));
+        assert!(engine.evaluate(&tick("99"), t0 + Duration::from_secs(120)).is_empty());
+        engine.evaluate(&tick("101"), t0 + Duration::from_secs(700));
+        assert_eq!(engine.evaluate(&tick("99"), t0 + Duration::from_secs(701)).len(), 1);
+    }
+
+    #[test]
+    fn percent_drop_within_window() {
+        let mut engine = AlertEngine::from_toml(
+            "[[alert]]\nsymbol = \"BTC-USD\"\nchange_pct = -5\nwindow = \"15m\"\n",
+        )
+        .unwrap();
+        let t0 = Instant::now();
+
+        assert!(engine.evaluate(&tick("100"), t0).is_empty());
+        assert!(engine.evaluate(&tick("97"), t0 + Duration::from_secs(300)).is_empty());
+        let fired = engine.evaluate(&tick("94"), t0 + Duration::from_secs(600));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].rule, "BTC-USD drops 5% in 15m");
+        assert_eq!(fired[0].detail, "-6.00% from 100");
+    }
+
+    #[test]
+    fn indicator_rule_compares_the_indicator_value() {
+        use crate::candles::CandleAggregator;
+        use std::sync::{Arc, Mutex};
+
+        let mut engine = AlertEngine::from_toml(
+            "[[alert]]\nsymbol = \"BTC-USD\"\nindicator = \"RSI(3)\"\nbelow = 30\n",
+        )
+        .unwrap();
+        assert_eq!(engine.rules()[0].to_string(), "BTC-USD RSI(3) below 30");
+
+        // One-second candles, falling every second: RSI 0
+        let candles = Arc::new(Mutex::new(CandleAggregator::new(vec![Duration::from_secs(1)], 10)));
+        engine.use_indicators(Indicators::new(Arc::clone(&candles), Duration::from_secs(1), Vec::new()));
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let t0 = Instant::now();
+        for (i, price) in ["100", "99", "98"].iter().enumerate() {
+            let update = PriceUpdate { received_at: start + Duration::from_secs(i as u64), ..tick(price) };
+            candles.lock().unwrap().ingest(&update);
+            assert!(engine.evaluate(&update, t0).is_empty(), "needs 4 candles for RSI(3)");
+        }
+        let update = PriceUpdate { received_at: start + Duration::from_secs(3), ..tick("97") };
+        candles.lock().unwrap().ingest(&update);
+        let fired = engine.evaluate(&update, t0);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].detail, "RSI(3) 0 < 30");
+    }
+
+    #[test]
+    fn volume_spike_compares_with_the_average_window() {
+        let mut engine = AlertEngine::from_toml("[[alert]]\nsymbol = \"BTC-USD\"\nvolume_spike = 3\n").unwrap();
+        assert_eq!(engine.rules()[0].to_string(), "BTC-USD volume 3x the average 1m");
+
+        // One unit traded a minute for nine minutes, then five in one go a minute later;
+        // the volumes come from the store, as they do in `spawn_alerts`
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let store = PriceStore::new();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let t0 = Instant::now();
+        let trade = |engine: &AlertEngine, update: &PriceUpdate, size: Decimal| {
+            runtime.block_on(async {
+                store.record_trade("coinbase", "BTC-USD", update.received_at, update.price, size).await;
+                engine.inputs(update, &store).await
+            })
+        };
+        let mut fired = Vec::new();
+        for m in 0..9 {
+            let update = PriceUpdate { received_at: start + Duration::from_secs(m * 60 + 30), ..tick("100") };
+            let inputs = trade(&engine, &update, Decimal::ONE);
+            fired.extend(engine.evaluate_with(&update, &inputs, t0));
+        }
+        assert!(fired.is_empty());
+        let update = PriceUpdate { received_at: start + Duration::from_secs(10 * 60 + 30), ..tick("100") };
+        let inputs = trade(&engine, &update, Decimal::from(5));
+        assert!(engine.evaluate(&update, t0).is_empty(), "without the volumes the rule can't tell");
+        let fired = engine.evaluate_with(&update, &inputs, t0);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].detail, "1m volume 5 is 5x the average 1");
+    }
+
+    #[test]
+    fn funding_rule_reads_the_contract_rate() {
+        use crate::funding::FundingInfo;
+
+        let mut engine = AlertEngine::from_toml("[[alert]]\nsymbol = \"BTC-USD\"\nfunding_above = 0.05\ncooldown = \"0s\"\n").unwrap();
+        assert_eq!(engine.rules()[0].to_string(), "BTC-USD funding rate above 0.05%");
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let store = PriceStore::new();
+        let t0 = Instant::now();
+        let check = |engine: &mut AlertEngine| {
+            let inputs = runtime.block_on(engine.inputs(&tick("65000"), &store));
+            engine.evaluate_with(&tick("65000"), &inputs, t0)
+        };
+
+        // No mark price event yet: nothing to compare
+        assert!(check(&mut engine).is_empty());
+        let info = |rate: &str| FundingInfo {
+            mark_price: "65010".parse().unwrap(),
+            index_price: "65000".parse().unwrap(),
+            funding_rate: rate.parse().unwrap(),
+            next_funding_at: None,
+            updated_at: SystemTime::now(),
+        };
+        runtime.block_on(store.record_funding("coinbase", "BTC-USD", info("0.0001")));
+        assert!(check(&mut engine).is_empty());
+        runtime.block_on(store.record_funding("coinbase", "BTC-USD", info("0.00075")));
+        let fired = check(&mut engine);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].detail, "funding 0.075% > 0.05% (mark 65010, index 65000)");
+    }
+
+    #[test]
+    fn trailing_stop_follows_the_high_and_survives_a_restart() {
+        let rules = "[[alert]]\nsymbol = \"BTC-USD\"\ntrailing_stop_pct = 5\ncooldown = \"0s\"\n";
+        let mut engine = AlertEngine::from_toml(rules).unwrap();
+        assert_eq!(engine.rules()[0].to_string(), "BTC-USD trailing stop 5%");
+        let path = std::env::temp_dir().join(format!("crabby-alert-state-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert_eq!(engine.persist_to(&path).unwrap(), 0);
+        let t0 = Instant::now();
+
+        // Armed at 100, the high climbs to 120: 114 is exactly 5% below it
+        for price in ["100", "110", "120", "116"] {
+            assert!(engine.evaluate(&tick(price), t0).is_empty());
+        }
+        let fired = engine.evaluate(&tick("114"), t0);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].detail, "5.00% below the high of 120");
+
+        // Re-armed at 114; the new high of 130 is kept across a restart
+        engine.evaluate(&tick("130"), t0);
+        engine.save_state().unwrap();
+        let mut restarted = AlertEngine::from_toml(rules).unwrap();
+        assert_eq!(restarted.persist_to(&path).unwrap(), 1);
+        assert!(restarted.evaluate(&tick("124"), t0).is_empty());
+        assert_eq!(restarted.evaluate(&tick("123"), t0)[0].detail, "5.38% below the high of 130");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn state_follows_the_rule_id() {
+        let path = std::env::temp_dir().join(format!("crabby-alert-ids-{}.json", std::process::id()));
+        let t0 = Instant::now();
+        let rules = "[[alert]]\nid = \"btc-stop\"\nsymbol = \"BTC-USD\"\ntrailing_stop_pct = 5\n";
+        let mut engine = AlertEngine::from_toml(rules).unwrap();
+        assert_eq!(engine.rules()[0].id, "btc-stop");
+        let _ = fs::remove_file(&path);
+        engine.persist_to(&path).unwrap();
+        for price in ["100", "130"] {
+            engine.evaluate(&tick(price), t0);
+        }
+        engine.save_state().unwrap();
+
+        // Tightened to 4%: the same id keeps the high of 130
+        let mut edited = AlertEngine::from_toml(&rules.replace("= 5", "= 4")).unwrap();
+        assert_eq!(edited.persist_to(&path).unwrap(), 1);
+        assert_eq!(edited.evaluate(&tick("124"), t0)[0].detail, "4.61% below the high of 130");
+
+        // State files from before ids are keyed by the description, the default id;
+        // state that matches no rule is dropped
+        let old = r#"[{"rule":"BTC-USD trailing stop 5%","exchange":"coinbase","active":false,"high":"130"},
+                      {"rule":"ETH-USD trailing stop 5%","exchange":"coinbase","active":false,"high":"4000"}]"#;
+        fs::write(&path, old).unwrap();
+        let mut unnamed = AlertEngine::from_toml(&rules.replace("id = \"btc-stop\"\n", "")).unwrap();
+        assert_eq!(unnamed.persist_to(&path).unwrap(), 1);
+        assert_eq!(unnamed.evaluate(&tick("123"), t0)[0].detail, "5.38% below the high of 130");
+        fs::remove_file(&path).unwrap();
+
+        let twice = format!("{}{}", rules, rules.replace("trailing_stop_pct = 5", "above = 1"));
+        assert_eq!(AlertEngine::from_toml(&twice).err().unwrap().to_string(), "alert id \"btc-stop\" is used by more than one rule");
+        assert!(AlertEngine::from_toml(&rules.replace("btc-stop", " ")).is_err());
+
+        // Rules without an id go by their description, so two alike ones clash too,
+        // and so does an id that's another rule's description
+        let unnamed = rules.replace("id = \"btc-stop\"\n", "");
+        assert_eq!(
+            AlertEngine::from_toml(&format!("{}{}", unnamed, unnamed)).err().unwrap().to_string(),
+            "alert id \"BTC-USD trailing stop 5%\" is used by more than one rule (rules without an `id` go by their description; give one of them an `id`)"
+        );
+        let taken = rules.replace("btc-stop", "BTC-USD above 1");
+        let other = "[[alert]]\nsymbol = \"BTC-USD\"\nabove = 1\n";
+        assert_eq!(
+            AlertEngine::from_toml(&format!("{}{}", taken, other)).err().unwrap().to_string(),
+            "alert id \"BTC-USD above 1\" is used by more than one rule (rules without an `id` go by their description; give one of them an `id`)"
+        );
+        assert!(AlertEngine::from_toml(&format!("{}{}", unnamed, rules)).is_ok(), "one named, one by description");
+    }
+
+    #[test]
+    fn take_profit_and_stop_loss_follow_the_position() {
+        let mut engine = AlertEngine::from_toml(
+            "[[alert]]\nsymbol = \"BTC-USD\"\ntake_profit_pct = 20\n\n[[alert]]\nsymbol = \"BTC-USD\"\nstop_loss_pct = 10\n",
+        )
+        .unwrap();
+        assert_eq!(engine.rules()[0].to_string(), "BTC-USD take profit +20%");
+        assert_eq!(engine.rules()[1].to_string(), "BTC-USD stop loss -10%");
+        let t0 = Instant::now();
+
+        // Nothing held: nothing to compare
+        let portfolio = Arc::new(Portfolio::new(Vec::new()));
+        engine.use_portfolio(Arc::clone(&portfolio));
+        assert!(engine.evaluate(&tick("200"), t0).is_empty());
+
+        // 2 coins for 200 in all: an average cost of 100
+        portfolio.add("BTC-USD", Decimal::from(2), Decimal::from(200));
+        let fired = engine.evaluate(&tick("121"), t0);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].detail, "+21.00% on the position's average cost of 100");
+        let fired = engine.evaluate(&tick("89"), t0);
+        assert_eq!((fired.len(), fired[0].rule.as_str()), (1, "BTC-USD stop loss -10%"));
+
+        // A third coin bought at 80 brings the cost down to 93.33: 89 is no longer 10% off
+        portfolio.add("BTC-USD", Decimal::ONE, Decimal::from(80));
+        engine.evaluate(&tick("95"), t0);
+        assert!(engine.evaluate(&tick("89"), t0 + DEFAULT_COOLDOWN).is_empty());
+    }
+
+    #[test]
+    fn rejects_rules_without_exactly_one_condition() {
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"BTC-USD\"\n").is_err());
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"X\"\nabove = 1\nbelow = 2\n").is_err());
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"X\"\nchange_pct = 5\n").is_err());
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"X\"\nindicator = \"RSI\"\nchange_pct = 5\nwindow = \"1m\"\n").is_err());
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"X\"\nvolume_spike = 3\nwindow = \"1h\"\n").is_err());
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"X\"\nfunding_above = 0.05\nabove = 1\n").is_err());
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"X\"\nfunding_above = 0.05\nfunding_below = -0.01\n").is_err());
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"X\"\ntrailing_stop_pct = 100\n").is_err());
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"X\"\ntake_profit_pct = 20\nstop_loss_pct = 10\n").is_err());
+        assert!(AlertEngine::from_toml("[[alert]]\nsymbol = \"X\"\nstop_loss_pct = 10\nbelow = 1\n").is_err());
+    }
+}
@@ -0,0 +1,447 @@
+// Embedded REST API so other programs on this machine can read current prices
+// without opening their own exchange connection.
+//
+//   GET /prices                          every latest price (one entry per exchange and symbol),
+//                                        with best bid/ask and spread when order books are tracked,
+//                                        display_currency/display_value with --currency, and
+//                                        market_cap, market_cap_rank... with [market]
+//   GET /prices/{symbol}                 latest price for one symbol, from any exchange
+//   GET /prices/{symbol}?exchange=kraken latest price for one symbol on one exchange
+//   GET /portfolio                       holdings valued at the latest prices, with P&L
+//                                        (404 unless a holdings file was given)
+//   GET /history/{symbol}?window=5m      recent ticks for one symbol, oldest first, with a summary
+//                                        (first/last/change/high/low); all that's kept without
+//                                        ?window=, also takes ?exchange=kraken
+//   GET /indicators/{symbol}             configured indicators (RSI, EMA...) for one symbol;
+//                                        also takes ?exchange=kraken
+//   GET /funding                         perpetual futures contracts: mark/index price, premium and
+//                                        funding rate (see funding.rs)
+//   GET /feeds                           feed health per exchange and symbol: messages/sec and
+//                                        latency from the exchange's timestamps (see health.rs)
+//   GET /health                          liveness check with a few basic numbers
+//   GET /metrics                         Prometheus metrics (text exposition format)
+
+use std::{
+    net::SocketAddr,     // Address to listen on
+    sync::Arc,           // Portfolio shared between handlers
+    time::{Duration, Instant, SystemTime},  // Uptime for /health; indicator candle interval; "now" for /feeds
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+
+use crate::fx::Converted;
+use crate::health::FeedStats;
+use crate::indicators::{Indicators, NamedReading};
+use crate::market::MarketInfo;
+use crate::metrics::TEXT_CONTENT_TYPE;
+use crate::orderbook::TopOfBook;
+use crate::portfolio::{Portfolio, Valuation};
+use crate::store::{epoch_ms, PriceStore, PriceUpdate, PriceView};
+use crate::ticks::TickSummary;
+
+// Shared with every request handler
+#[derive(Clone)]
+struct ApiState {
+    store: PriceStore,
+    portfolio: Option<Arc<Portfolio>>,
+    indicators: Option<Indicators>,
+    started: Instant,
+}
+
+// One entry of /prices: the latest update plus the top of the order book, if known
+#[derive(Debug, Serialize)]
+struct Quote {
+    #[serde(flatten)]
+    update: PriceUpdate,
+    #[serde(flatten)]
+    book: Option<TopOfBook>,
+    #[serde(flatten)]
+    converted: Option<Converted>,  // Price in the display currency, when one is set
+    #[serde(flatten)]
+    market: Option<MarketInfo>,    // Market cap at this price, rank and supply, with [market]
+}
+
+impl Quote {
+    fn new(store: &PriceStore, prices: &PriceView, update: PriceUpdate) -> Self {
+        let book = prices.top(update.exchange, &update.symbol);
+        let converted = store.fx().convert(update.price, &update.symbol);
+        let market = store.market().info(&update.symbol, update.price);
+        Self { update, book, converted, market }
+    }
+}
+
+// Optional query parameters for /prices/{symbol}
+#[derive(Debug, Deserialize)]
+struct PriceQuery {
+    exchange: Option<String>,
+}
+
+// Query parameters for /history/{symbol}
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    exchange: Option<String>,
+    window: Option<String>,  // e.g. "5m"
+}
+
+// Body of GET /history/{symbol}
+#[derive(Debug, Serialize)]
+struct HistoryBody {
+    exchange: &'static str,
+    symbol: String,
+    window: Option<String>,           // As asked for; none means everything kept
+    summary: Option<TickSummary>,     // None while there are no ticks in the window
+    ticks: Vec<PriceUpdate>,
+}
+
+// Body of GET /indicators/{symbol}
+#[derive(Debug, Serialize)]
+struct IndicatorValues {
+    exchange: &'static str,
+    symbol: String,
+    #[serde(serialize_with = "serialize_interval")]
+    interval: Duration,               // Candles the values are computed on, e.g. "1m"
+    indicators: Vec<NamedReading>,    // Only those with enough candles yet
+}
+
+// One entry of GET /funding
+#[derive(Debug, Serialize)]
+struct Contract {
+    exchange: &'static str,
+    symbol: String,
+    mark_price: Decimal,
+    index_price: Decimal,
+    premium_pct: Option<Decimal>,     // Mark over index
+    funding_rate_pct: Decimal,        // Per funding interval
+    next_funding_ms: Option<u64>,
+    timestamp_ms: u64,                // When the figures arrived
+}
+
+// Body of GET /health
+#[derive(Debug, Serialize)]
+struct Health {
+    status: &'static str,
+    tracked: usize,       // Number of (exchange, symbol) pairs with a price
+    uptime_secs: u64,
+}
+
+// Build the router; handy on its own for tests or for mounting under another app
+pub fn router(store: PriceStore, portfolio: Option<Arc<Portfolio>>, indicators: Option<Indicators>) -> Router {
+    let state = ApiState { store, portfolio, indicators, started: Instant::now() };
+    Router::new()
+        .route("/prices", get(all_prices))
+        .route("/prices/{symbol}", get(one_price))
+        .route("/portfolio", get(portfolio_value))
+        .route("/history/{symbol}", get(price_history))
+        .route("/indicators/{symbol}", get(indicator_values))
+        .route("/funding", get(funding_rates))
+        .route("/feeds", get(feed_health))
+        .route("/health", get(health))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+// Listen on `addr` and serve requests until `shutdown` completes (in-flight requests are
+// allowed to finish) or the task is dropped
+pub async fn serve<F>(
+    addr: SocketAddr,
+    store: PriceStore,
+    portfolio: Option<Arc<Portfolio>>,
+    indicators: Option<Indicators>,
+    shutdown: F,
+) -> std::io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("REST API listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, router(store, portfolio, indicators)).with_graceful_shutdown(shutdown).await
+}
+
+async fn all_prices(State(state): State<ApiState>) -> Json<Vec<Quote>> {
+    let prices = state.store.view().await;
+    Json(prices.snapshot().iter().map(|u| Quote::new(&state.store, &prices, u.clone())).collect())
+}
+
+async fn one_price(
+    State(state): State<ApiState>,
+    Path(symbol): Path<String>,
+    Query(query): Query<PriceQuery>,
+) -> Result<Json<Quote>, StatusCode> {
+    let symbol = symbol.to_uppercase();  // Accept btc-usd as well as BTC-USD
+    let prices = state.store.view().await;
+    let found = match query.exchange {
+        Some(exchange) => prices.latest_on(&exchange.to_lowercase(), &symbol),
+        None => prices.latest(&symbol),
+    };
+    found.map(|u| Json(Quote::new(&state.store, &prices, u))).ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn portfolio_value(State(state): State<ApiState>) -> Result<Json<Valuation>, StatusCode> {
+    let portfolio = state.portfolio.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(portfolio.value(&state.store).await))
+}
+
+async fn price_history(
+    State(state): State<ApiState>,
+    Path(symbol): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryBody>, (StatusCode, String)> {
+    let symbol = symbol.to_uppercase();
+    let window = match &query.window {
+        Some(text) => Some(humantime::parse_duration(text).map_err(|e| (StatusCode::BAD_REQUEST, format!("window: {}", e)))?),
+        None => None,
+    };
+    // Without ?exchange=, use whichever venue has the latest price
+    let latest = match query.exchange {
+        Some(exchange) => state.store.latest_on(&exchange.to_lowercase(), &symbol).await,
+        None => state.store.latest(&symbol).await,
+    };
+    let exchange = latest.ok_or((StatusCode::NOT_FOUND, format!("no price for {}", symbol)))?.exchange;
+    let ticks = state.store.ticks(exchange, &symbol, window).await;
+    Ok(Json(HistoryBody { exchange, symbol, window: query.window, summary: TickSummary::of(&ticks), ticks }))
+}
+
+async fn indicator_values(
+    State(state): State<ApiState>,
+    Path(symbol): Path<String>,
+    Query(query): Query<PriceQuery>,
+) -> Result<Json<IndicatorValues>, StatusCode> {
+    let indicators = state.indicators.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let symbol = symbol.to_uppercase();
+    // Without ?exchange=, use whichever venue has the latest price
+    let latest = match query.exchange {
+        Some(exchange) => state.store.latest_on(&exchange.to_lowercase(), &symbol).await,
+        None => state.store.latest(&symbol).await,
+    };
+    let exchange = latest.ok_or(StatusCode::NOT_FOUND)?.exchange;
+    Ok(Json(IndicatorValues {
+        exchange,
+        indicators: indicators.readings(exchange, &symbol),
+        symbol,
+        interval: indicators.interval(),
+    }))
+}
+
+async fn funding_rates(State(state): State<ApiState>) -> Json<Vec<Contract>> {
+    let contracts = state.store.funding_rates().await.into_iter().map(|(exchange, symbol, info)| Contract {
+        exchange,
+        symbol,
+        mark_price: info.mark_price,
+        index_price: info.index_price,
+        premium_pct: info.premium_pct(),
+        funding_rate_pct: info.rate_pct(),
+        next_funding_ms: info.next_funding_at.map(epoch_ms),
+        timestamp_ms: epoch_ms(info.updated_at),
+    });
+    Json(contracts.collect())
+}
+
+async fn feed_health(State(state): State<ApiState>) -> Json<Vec<FeedStats>> {
+    Json(state.store.feed_stats(SystemTime::now()).await)
+}
+
+async fn health(State(state): State<ApiState>) -> Json<Health> {
+    Json(Health {
+        status: "ok",
+        tracked: state.store.snapshot().await.len(),
+        uptime_secs: state.started.elapsed().as_secs(),
+    })
+}
+
+async fn metrics(State(state): State<ApiState>) -> ([(header::HeaderName, &'static str); 1], String) {
+    ([(header::CONTENT_TYPE, TEXT_CONTENT_TYPE)], state.store.metrics().render())
+}
+
+// Durations as humans write them, e.g. "1m"
+fn serialize_interval<S: serde::Serializer>(interval: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&humantime::format_duration(*interval))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use serde_json::Value;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use crate::candles::CandleAggregator;
+    use crate::funding::FundingInfo;
+    use crate::indicators::parse_list;
+    use crate::portfolio::Holding;
+
+    fn run<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    fn tick(exchange: &'static str, symbol: &str, price: &str) -> PriceUpdate {
+        PriceUpdate {
+            exchange,
+            symbol: symbol.to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    // Serve `router` on a free port
+    async fn start(router: Router) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        addr
+    }
+
+    // GET `path` over plain HTTP/1.1; the status code and the body
+    async fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+        socket.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    // GET `path`, expecting 200 and JSON
+    async fn get_json(addr: SocketAddr, path: &str) -> Value {
+        let (status, body) = get(addr, path).await;
+        assert_eq!(status, 200, "{}: {}", path, body);
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[test]
+    fn prices_by_symbol_and_exchange() {
+        run(async {
+            let store = PriceStore::new();
+            store.update(tick("coinbase", "BTC-USD", "65000")).await;
+            store.update(tick("kraken", "BTC-USD", "64990")).await;
+            store.update(tick("coinbase", "ETH-USD", "3200")).await;
+            let addr = start(router(store, None, None)).await;
+
+            assert_eq!(get_json(addr, "/prices").await.as_array().unwrap().len(), 3);
+            let quote = get_json(addr, "/prices/btc-usd?exchange=Kraken").await;
+            assert_eq!((&quote["exchange"], &quote["price"]), (&Value::from("kraken"), &Value::from("64990")));
+            assert_eq!(get_json(addr, "/prices/ETH-USD").await["price"], "3200");
+
+            // Unknown symbols, and known ones on an exchange that doesn't have them
+            assert_eq!(get(addr, "/prices/DOGE-USD").await.0, 404);
+            assert_eq!(get(addr, "/prices/ETH-USD?exchange=kraken").await.0, 404);
+            assert_eq!(get(addr, "/nope").await.0, 404);
+
+            // A query string that doesn't fit PriceQuery
+            let (status, body) = get(addr, "/prices/BTC-USD?exchange=kraken&exchange=coinbase").await;
+            assert_eq!(status, 400);
+            assert!(body.contains("duplicate field `exchange`"), "{}", body);
+        });
+    }
+
+    #[test]
+    fn history_with_and_without_a_window() {
+        run(async {
+            let store = PriceStore::new();
+            for price in ["100", "104", "102"] {
+                store.update(tick("coinbase", "BTC-USD", price)).await;
+            }
+            let addr = start(router(store, None, None)).await;
+
+            let history = get_json(addr, "/history/btc-usd?window=5m").await;
+            assert_eq!((&history["exchange"], &history["window"]), (&Value::from("coinbase"), &Value::from("5m")));
+            assert_eq!(history["ticks"].as_array().unwrap().len(), 3);
+            assert_eq!((&history["summary"]["high"], &history["summary"]["change_pct"]), (&Value::from("104"), &Value::from("2.00")));
+            assert_eq!(get_json(addr, "/history/BTC-USD").await["window"], Value::Null);
+
+            let (status, body) = get(addr, "/history/BTC-USD?window=soon").await;
+            assert_eq!(status, 400);
+            assert!(body.starts_with("window: "), "{}", body);
+            assert_eq!(get(addr, "/history/DOGE-USD").await, (404, "no price for DOGE-USD".to_string()));
+            assert_eq!(get(addr, "/history/BTC-USD?exchange=kraken").await.0, 404);
+        });
+    }
+
+    #[test]
+    fn portfolio_needs_a_holdings_file() {
+        run(async {
+            let store = PriceStore::new();
+            store.update(tick("coinbase", "BTC-USD", "65000")).await;
+            assert_eq!(get(start(router(store.clone(), None, None)).await, "/portfolio").await.0, 404);
+
+            let holding = Holding { symbol: "BTC-USD".to_string(), quantity: "0.5".parse().unwrap(), cost_basis: Decimal::from(30_000) };
+            let portfolio = Arc::new(Portfolio::new(vec![holding]));
+            let valuation = get_json(start(router(store, Some(portfolio), None)).await, "/portfolio").await;
+            assert_eq!((&valuation["total_value"], &valuation["unrealized_pnl"]), (&Value::from("32500.0"), &Value::from("2500.0")));
+        });
+    }
+
+    #[test]
+    fn indicators_need_configuring_and_a_price() {
+        run(async {
+            let store = PriceStore::new();
+            let interval = Duration::from_secs(1);
+            let candles = Arc::new(Mutex::new(CandleAggregator::new(vec![interval], 10)));
+            let start_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+            for (i, price) in ["100", "102"].into_iter().enumerate() {
+                let update = PriceUpdate { received_at: start_at + interval * i as u32, ..tick("coinbase", "BTC-USD", price) };
+                candles.lock().unwrap().ingest(&update);
+                store.update(update).await;
+            }
+            assert_eq!(get(start(router(store.clone(), None, None)).await, "/indicators/BTC-USD").await.0, 404);
+
+            let indicators = Indicators::new(candles, interval, parse_list("SMA(2), SMA(50)").unwrap());
+            let addr = start(router(store, None, Some(indicators))).await;
+            let values = get_json(addr, "/indicators/btc-usd").await;
+            assert_eq!((&values["exchange"], &values["interval"]), (&Value::from("coinbase"), &Value::from("1s")));
+            let readings = values["indicators"].as_array().unwrap();
+            assert_eq!(readings.len(), 1, "SMA(50) has too few candles yet");
+            assert_eq!((&readings[0]["name"], &readings[0]["value"]), (&Value::from("SMA(2)"), &Value::from("101")));
+
+            assert_eq!(get(addr, "/indicators/DOGE-USD").await.0, 404);
+            assert_eq!(get(addr, "/indicators/BTC-USD?exchange=kraken").await.0, 404);
+        });
+    }
+
+    #[test]
+    fn funding_feeds_health_and_metrics() {
+        run(async {
+            let store = PriceStore::new();
+            let addr = start(router(store.clone(), None, None)).await;
+            assert_eq!(get_json(addr, "/funding").await, Value::Array(Vec::new()));
+            assert_eq!(get_json(addr, "/health").await["tracked"], 0);
+
+            store.update(tick("binance", "BTC-USDT", "65000")).await;
+            let now = SystemTime::now();
+            store.record_message("binance", "BTC-USDT", now, Some(now - Duration::from_millis(40))).await;
+            let info = FundingInfo {
+                mark_price: "65010".parse().unwrap(),
+                index_price: "65000".parse().unwrap(),
+                funding_rate: "0.0001".parse().unwrap(),
+                next_funding_at: None,
+                updated_at: now,
+            };
+            store.record_funding("binance", "BTC-USDT", info).await;
+
+            let funding = get_json(addr, "/funding").await;
+            assert_eq!((&funding[0]["symbol"], &funding[0]["funding_rate_pct"]), (&Value::from("BTC-USDT"), &Value::from("0.01")));
+            let feeds = get_json(addr, "/feeds").await;
+            assert_eq!((&feeds[0]["exchange"], &feeds[0]["messages"]), (&Value::from("binance"), &Value::from(1)));
+            let health = get_json(addr, "/health").await;
+            assert_eq!((&health["status"], &health["tracked"]), (&Value::from("ok"), &Value::from(1)));
+
+            let (status, body) = get(addr, "/metrics").await;
+            assert_eq!(status, 200);
+            assert!(body.contains("crabby_price{exchange=\"binance\",symbol=\"BTC-USDT\"} 65000"), "{}", body);
+        });
+    }
+}
@@ -0,0 +1,228 @@
+// Cross-exchange arbitrage: the same pair priced differently on two venues. With
+// more than one exchange connected, every update is compared against the latest
+// price of that symbol on the other venues; when the gap between the cheapest and
+// the dearest exceeds a threshold, an alert goes out through the usual notifiers.
+//
+// Prices are last trades, not executable quotes, and fees aren't counted, so an
+// "opportunity" here is a signal worth a look rather than free money.
+
+use std::{
+    collections::HashMap,             // Latest price per symbol per exchange
+    time::{Duration, Instant, SystemTime},  // Price age, cooldowns
+};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::alerts::Alert;
+use crate::config::deserialize_duration;
+use crate::notify::Notifier;
+use crate::store::{PriceStore, PriceUpdate};
+
+// [arbitrage] section of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArbitrageConfig {
+    pub threshold_pct: Option<Decimal>,  // Alert when the spread is at least this; off unless set
+    pub trade_size: Decimal,             // Quote-currency amount the implied profit is worked out for
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_age: Duration,               // Ignore venues whose last price is older than this
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub cooldown: Duration,              // Minimum time between alerts for one symbol
+    pub desktop: bool,                   // Also show a desktop notification
+}
+
+impl Default for ArbitrageConfig {
+    fn default() -> Self {
+        Self {
+            threshold_pct: None,
+            trade_size: Decimal::ONE_THOUSAND,
+            max_age: Duration::from_secs(10),
+            cooldown: Duration::from_secs(300),
+            desktop: false,
+        }
+    }
+}
+
+// A price gap between two venues
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opportunity {
+    pub symbol: String,
+    pub buy_exchange: &'static str,   // Cheapest venue
+    pub buy_price: Decimal,
+    pub sell_exchange: &'static str,  // Dearest venue
+    pub sell_price: Decimal,
+    pub spread_pct: Decimal,          // (sell − buy) / buy, in percent
+    pub profit: Decimal,              // Before fees, buying `trade_size` worth and selling it on the other venue
+}
+
+// Per symbol bookkeeping, like the alert engine's: edge-triggered with a cooldown
+#[derive(Debug, Default)]
+struct SymbolState {
+    prices: HashMap<&'static str, (Decimal, SystemTime)>,  // Latest price and when it arrived, per exchange
+    active: bool,                                          // Was the spread over the threshold last time?
+    last_fired: Option<Instant>,
+}
+
+// Watches the update stream for cross-exchange spreads
+pub struct ArbitrageDetector {
+    threshold_pct: Decimal,
+    trade_size: Decimal,
+    max_age: Duration,
+    cooldown: Duration,
+    desktop: bool,
+    symbols: HashMap<String, SymbolState>,
+}
+
+impl ArbitrageDetector {
+    pub fn new(threshold_pct: Decimal, trade_size: Decimal, max_age: Duration, cooldown: Duration) -> Self {
+        Self { threshold_pct, trade_size, max_age, cooldown, desktop: false, symbols: HashMap::new() }
+    }
+
+    // A detector for the [arbitrage] settings, or None when no threshold is set
+    pub fn from_config(config: &ArbitrageConfig) -> Option<Self> {
+        let mut detector = Self::new(config.threshold_pct?, config.trade_size, config.max_age, config.cooldown);
+        detector.desktop = config.desktop;
+        Some(detector)
+    }
+
+    pub fn threshold_pct(&self) -> Decimal {
+        self.threshold_pct
+    }
+
+    // Record one update and compare its symbol across venues. Returns an opportunity when
+    // the spread has just crossed the threshold (and the cooldown has passed). `now` is
+    // passed in so tests can control time.
+    pub fn evaluate(&mut self, update: &PriceUpdate, now: Instant) -> Option<Opportunity> {
+        let state = self.symbols.entry(update.symbol.clone()).or_default();
+        state.prices.insert(update.exchange, (update.price, update.received_at));
+
+        // Cheapest and dearest venue among the recent, non-zero prices
+        let fresh: Vec<(&'static str, Decimal)> = state
+            .prices
+            .iter()
+            .filter(|(_, (price, at))| !price.is_zero() && update.received_at.duration_since(*at).unwrap_or_default() <= self.max_age)
+            .map(|(&exchange, &(price, _))| (exchange, price))
+            .collect();
+        let (Some(&(buy_exchange, buy_price)), Some(&(sell_exchange, sell_price))) =
+            (fresh.iter().min_by_key(|(_, p)| *p), fresh.iter().max_by_key(|(_, p)| *p))
+        else {
+            return None;
+        };
+        if fresh.len() < 2 {
+            state.active = false;  // Only one venue has a recent price
+            return None;
+        }
+
+        let spread_pct = (sell_price - buy_price) / buy_price * Decimal::ONE_HUNDRED;
+        let triggered = spread_pct >= self.threshold_pct;
+        let cooled_down = state.last_fired.is_none_or(|t| now.duration_since(t) >= self.cooldown);
+        let fire = triggered && !state.active && cooled_down;
+        state.active = triggered;
+        if !fire {
+            return None;
+        }
+        state.last_fired = Some(now);
+        Some(Opportunity {
+            symbol: update.symbol.clone(),
+            buy_exchange,
+            buy_price,
+            sell_exchange,
+            sell_price,
+            spread_pct: spread_pct.round_dp(4),
+            profit: (self.trade_size / buy_price * (sell_price - buy_price)).round_dp(2),
+        })
+    }
+
+    // Present an opportunity as an alert so every notifier (console, webhooks, desktop)
+    // can deliver it
+    pub fn alert(&self, opportunity: &Opportunity) -> Alert {
+        Alert {
+            rule: format!("{} arbitrage spread >= {}%", opportunity.symbol, self.threshold_pct),
+            exchange: opportunity.buy_exchange,
+            symbol: opportunity.symbol.clone(),
+            price: opportunity.buy_price,
+            detail: format!(
+                "buy on {} at {}, sell on {} at {}: spread {:.2}%, about {} on a {} trade",
+                opportunity.buy_exchange,
+                opportunity.buy_price,
+                opportunity.sell_exchange,
+                opportunity.sell_price,
+                opportunity.spread_pct,
+                opportunity.profit,
+                self.trade_size
+            ),
+            fired_at: SystemTime::now(),
+            desktop: self.desktop,
+            trade: None,
+        }
+    }
+}
+
+// Compare every update from `store` across exchanges, sending opportunities to all notifiers
+pub fn spawn_arbitrage(
+    mut detector: ArbitrageDetector,
+    store: &PriceStore,
+    notifiers: Vec<Box<dyn Notifier>>,
+) -> tokio::task::JoinHandle<()> {
+    let mut rx = store.subscribe_updates();
+    let metrics = store.metrics().clone();  // Counts what the task misses
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(update) => {
+                    if let Some(opportunity) = detector.evaluate(&update, Instant::now()) {
+                        let alert = detector.alert(&opportunity);
+                        for notifier in &notifiers {
+                            notifier.notify(&alert);
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!(skipped = n, "Arbitrage detector fell behind");
+                    metrics.record_dropped_events("arbitrage", n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(exchange: &'static str, price: &str, at: SystemTime) -> PriceUpdate {
+        PriceUpdate {
+            exchange,
+            symbol: "BTC-USD".to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: at,
+        }
+    }
+
+    #[test]
+    fn fires_once_when_the_spread_opens_and_ignores_stale_venues() {
+        let mut detector =
+            ArbitrageDetector::new("0.5".parse().unwrap(), Decimal::ONE_THOUSAND, Duration::from_secs(10), Duration::ZERO);
+        let (t0, wall) = (Instant::now(), SystemTime::now());
+
+        assert_eq!(detector.evaluate(&tick("coinbase", "60000", wall), t0), None);  // Nothing to compare with
+        assert_eq!(detector.evaluate(&tick("kraken", "60100", wall), t0), None);    // 0.17%: under the threshold
+
+        let opportunity = detector.evaluate(&tick("kraken", "60600", wall), t0).unwrap();
+        assert_eq!((opportunity.buy_exchange, opportunity.sell_exchange), ("coinbase", "kraken"));
+        assert_eq!(opportunity.spread_pct, "1".parse().unwrap());
+        assert_eq!(opportunity.profit, "10".parse().unwrap());  // 1000 / 60000 BTC × 600
+        assert_eq!(detector.evaluate(&tick("kraken", "60700", wall), t0), None);  // Still open: no repeat
+
+        // A minute later Coinbase's price is too old to compare against
+        let later = wall + Duration::from_secs(60);
+        assert_eq!(detector.evaluate(&tick("kraken", "60100", later), t0), None);
+        assert_eq!(detector.evaluate(&tick("kraken", "61000", later), t0), None);
+        assert!(detector.evaluate(&tick("coinbase", "60000", later), t0).is_some());
+    }
+}
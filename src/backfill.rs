@@ -0,0 +1,175 @@
+// Historical backfill: on startup, fetch recent candles from the Coinbase REST API so
+// the price map, 24h change, candles and % change alert rules have context right away
+// instead of starting from nothing.
+//
+// One request per symbol: the last 300 five-minute candles (25 hours), trimmed to the
+// configured look-back. Fetching needs the "backfill" feature (reqwest); parsing and
+// `History` are always available.
+
+use std::{
+    error::Error,                         // Trait to return errors from our functions
+    time::{Duration, SystemTime},         // Look-back window, candle times
+};
+
+use rust_decimal::Decimal;
+use serde_json::Value;
+#[cfg(feature = "backfill")]
+use tracing::warn;
+
+use crate::candles::Candle;
+use crate::store::{epoch_ms, parse_decimal, PriceUpdate};
+
+// Coinbase Exchange REST API
+#[cfg(feature = "backfill")]
+const PRODUCTS_URL: &str = "https://api.exchange.coinbase.com/products";
+
+// Width of the fetched candles. Coinbase returns at most 300 per request,
+// so five-minute candles cover 25 hours in a single call.
+pub const GRANULARITY: Duration = Duration::from_secs(300);
+
+// Longest look-back a single request can cover
+pub const MAX_LOOKBACK: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Recent candles for one symbol, oldest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    pub symbol: String,
+    pub candles: Vec<Candle>,
+}
+
+impl History {
+    // What the price map should show before the first live tick: the last close,
+    // with the opening price of the window as the 24h open
+    pub fn latest_update(&self) -> Option<PriceUpdate> {
+        let (first, last) = (self.candles.first()?, self.candles.last()?);
+        Some(PriceUpdate {
+            exchange: "coinbase",
+            symbol: self.symbol.clone(),
+            price: last.close,
+            open_24h: Some(first.open),
+            size: None,
+            received_at: candle_end(last),
+        })
+    }
+
+    // One price point per candle close, for warming up alert rules
+    pub fn price_points(&self) -> Vec<PriceUpdate> {
+        self.candles
+            .iter()
+            .map(|c| PriceUpdate {
+                exchange: "coinbase",
+                symbol: self.symbol.clone(),
+                price: c.close,
+                open_24h: None,
+                size: None,
+                received_at: candle_end(c),
+            })
+            .collect()
+    }
+
+    // Highest and lowest trade over the window
+    pub fn high_low(&self) -> Option<(Decimal, Decimal)> {
+        let high = self.candles.iter().map(|c| c.high).max()?;
+        let low = self.candles.iter().map(|c| c.low).min()?;
+        Some((high, low))
+    }
+}
+
+// When a candle's bucket ended
+fn candle_end(candle: &Candle) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(candle.start_ms) + candle.interval
+}
+
+// Fetch the history of every symbol. A symbol that fails is logged and skipped:
+// backfill is a nice-to-have and must never stop the tracker from starting.
+#[cfg(feature = "backfill")]
+pub async fn backfill(symbols: &[String], lookback: Duration, network: &crate::net::Network) -> Vec<History> {
+    let client = match network
+        .http_client()
+        .user_agent(concat!("crabbycryptotracker/", env!("CARGO_PKG_VERSION")))  // Coinbase rejects requests without one
+        .timeout(Duration::from_secs(10))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            warn!(error = %e, "Backfill disabled");
+            return Vec::new();
+        }
+    };
+
+    let mut histories = Vec::new();
+    for symbol in symbols {
+        match fetch_history(&client, symbol, lookback).await {
+            Ok(history) => histories.push(history),
+            Err(e) => warn!(%symbol, error = %e, "Backfill failed"),
+        }
+    }
+    histories
+}
+
+// Fetch and parse the candles of one symbol
+#[cfg(feature = "backfill")]
+async fn fetch_history(client: &reqwest::Client, symbol: &str, lookback: Duration) -> Result<History, Box<dyn Error>> {
+    let url = format!("{}/{}/candles?granularity={}", PRODUCTS_URL, symbol, GRANULARITY.as_secs());
+    let body: Value = client.get(&url).send().await?.error_for_status()?.json().await?;
+    let candles = parse_candles(symbol, &body, SystemTime::now(), lookback)?;
+    Ok(History { symbol: symbol.to_string(), candles })
+}
+
+// Turn Coinbase's `[[time, low, high, open, close, volume], ...]` (newest first) into
+// candles, oldest first. Keeps only finished buckets that started within `lookback` of `now`.
+pub fn parse_candles(symbol: &str, body: &Value, now: SystemTime, lookback: Duration) -> Result<Vec<Candle>, Box<dyn Error>> {
+    let rows = body.as_array().ok_or("expected a JSON array of candles")?;
+    let now_ms = epoch_ms(now);
+    let width_ms = GRANULARITY.as_millis() as u64;
+    let since_ms = now_ms.saturating_sub(lookback.as_millis() as u64);
+
+    let mut candles = Vec::with_capacity(rows.len());
+    for row in rows {
+        let fields = row.as_array().filter(|f| f.len() >= 6).ok_or("expected [time, low, high, open, close, volume]")?;
+        let number = |i: usize, name: &'static str| parse_decimal(name, &fields[i].to_string());
+        let start_ms = fields[0].as_u64().ok_or("candle time is not a number")? * 1000;
+        if start_ms < since_ms || start_ms + width_ms > now_ms {
+            continue;  // Too old, or still in progress (live ticks will fill that bucket)
+        }
+        candles.push(Candle {
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            interval: GRANULARITY,
+            start_ms,
+            low: number(1, "low")?,
+            high: number(2, "high")?,
+            open: number(3, "open")?,
+            close: number(4, "close")?,
+            volume: number(5, "volume")?,
+            trades: 0,  // Not reported by the candles endpoint
+        });
+    }
+    candles.sort_by_key(|c| c.start_ms);
+    Ok(candles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_finished_candles_oldest_first() {
+        // Newest first, as Coinbase sends them; the 1200 bucket is still open at t=1400
+        let body: Value = serde_json::from_str(
+            "[[1200, 99, 102, 100, 101, 1.5], [900, 98.5, 100.5, 99, 100, 2], [600, 97, 99.5, 98, 99, 0.5], [0, 1, 1, 1, 1, 1]]",
+        )
+        .unwrap();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1400);
+        let candles = parse_candles("BTC-USD", &body, now, Duration::from_secs(900)).unwrap();
+
+        // 0 is older than the look-back, 1200 hasn't finished yet
+        assert_eq!(candles.iter().map(|c| c.start_ms).collect::<Vec<_>>(), [600_000, 900_000]);
+        assert_eq!(candles[1].high, "100.5".parse().unwrap());
+
+        let history = History { symbol: "BTC-USD".into(), candles };
+        let latest = history.latest_update().unwrap();
+        assert_eq!((latest.price, latest.open_24h), (Decimal::from(100), Some(Decimal::from(98))));
+        assert_eq!(history.high_low(), Some(("100.5".parse().unwrap(), Decimal::from(97))));
+    }
+}
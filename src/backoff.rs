@@ -0,0 +1,62 @@
+// Exponential backoff with jitter, used to space out reconnect attempts so we
+// don't hammer the exchange when the network (or the exchange) is having trouble.
+
+use rand::Rng;                 // Random numbers for jitter
+use tokio::time::Duration;     // Delay type shared with tokio::time::sleep
+
+// Tracks how many consecutive attempts have failed and hands out the next delay
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,  // Delay before the first retry
+    max: Duration,   // Upper bound on any single delay
+    attempt: u32,    // How many delays have been handed out since the last reset
+}
+
+impl Backoff {
+    // Create a backoff starting at `base` and never exceeding `max`
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max, attempt: 0 }
+    }
+
+    // The un-jittered delay for the current attempt: base * 2^attempt, capped at max
+    fn ceiling(&self) -> Duration {
+        let factor = 1u32.checked_shl(self.attempt).unwrap_or(u32::MAX); // Avoid overflow on long outages
+        self.base.saturating_mul(factor).min(self.max)
+    }
+
+    // Next delay to wait before reconnecting. Uses "equal jitter": half of the delay
+    // is fixed and the other half random, so many clients don't retry in lockstep.
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = self.ceiling();
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = ceiling / 2;
+        let jitter_ms = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter_ms)
+    }
+
+    // Call after a connection succeeds so the next outage starts from `base` again
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_grow_and_are_capped() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(8));
+        let ceilings = [1, 2, 4, 8, 8];
+
+        for secs in ceilings {
+            let ceiling = Duration::from_secs(secs);
+            let delay = backoff.next_delay();
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "{:?} not within {:?}", delay, ceiling);
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(1));
+    }
+}
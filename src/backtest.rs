@@ -0,0 +1,412 @@
+// `backtest`: alert rules, the alert script and paper trading rules run over recorded
+// history instead of live prices, to see what they would have done before trusting them
+// with the real thing.
+//
+//     crabbycryptotracker backtest --script strategy.rhai --since 30d
+//     crabbycryptotracker backtest --from prices.csv --alerts alerts.toml --resample 1m
+//     crabbycryptotracker backtest --from messages.ndjson --alerts alerts.toml
+//
+// The history is read like `export` reads it (the database, or snapshot/NDJSON files)
+// and fed in timestamp order through a fresh price store, candle aggregator and
+// indicators. A recording made with `--record` is replayed instead, frame by frame at
+// full speed, through the same frame handling as a live feed (recording.rs), and every
+// price that comes out of it goes the same way. Either way the rules see what they
+// would have seen live, so change(), rsi() and the rules see what they would have seen live. The
+// script runs every `[script] interval` of history time, and cooldowns count in history
+// time too. With --resample only each candle's close goes through, which is quicker over
+// long ranges. Nothing is sent anywhere: no notifiers, no real orders.
+//
+// Every order (script buy()/sell(), alert rules with a `trade`, [[paper.rules]]) goes to
+// a paper trader with the [paper] cash and fees, and fills at the price that triggered
+// it. The report lists each alert, fill and rejected order, then the alerts per rule
+// with the times they fired, the final account and the largest drop in equity along
+// the way.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},      // Alerts per rule, sorted by rule; symbols to follow in a recording
+    fmt,                                    // The printed report
+    sync::{Arc, Mutex},                     // Candles shared with the indicators
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rust_decimal::Decimal;
+
+use crate::alerts::{Alert, AlertEngine};
+use crate::candles::{Candle, CandleAggregator, SharedCandles};
+use crate::config::Config;
+use crate::export::Tick;
+use crate::indicators::Indicators;
+use crate::paper::{Fill, PaperSummary, PaperTrader, Side};
+use crate::recording::{FrameFeeder, RecordedFrame};
+use crate::script::ScriptRunner;
+use crate::store::{PriceStore, PriceUpdate};
+
+// One run over the history
+pub struct Backtest {
+    store: PriceStore,
+    candles: SharedCandles,
+    engine: Option<AlertEngine>,
+    script: Option<ScriptRunner>,
+    script_interval: Duration,
+    next_script_run: Option<SystemTime>,      // History time the script is due next
+    paper: PaperTrader,
+    clock: Option<(SystemTime, Instant)>,     // The first tick's time, pinned to a clock reading for the cooldowns
+    ticks: usize,
+    last: Option<SystemTime>,
+    alerts: Vec<Alert>,
+    rejected: Vec<(SystemTime, String, String)>,  // When, the order, why
+    peak_equity: Decimal,
+    max_drawdown: Decimal,
+}
+
+impl Backtest {
+    // Rules and script as loaded for `track`; candles, indicators and the paper account
+    // as configured
+    pub fn new(config: &Config, mut engine: Option<AlertEngine>, mut script: Option<ScriptRunner>) -> Result<Self, String> {
+        // Indicators need their own interval even when [candles] doesn't list it
+        let mut intervals = config.candles.intervals.clone();
+        if !intervals.contains(&config.indicators.interval) {
+            intervals.push(config.indicators.interval);
+        }
+        let candles: SharedCandles = Arc::new(Mutex::new(CandleAggregator::new(intervals, config.candles.keep)));
+        let indicators = Indicators::from_config(Arc::clone(&candles), &config.indicators);
+        if let Some(engine) = &mut engine {
+            if engine.rules().iter().any(|r| r.uses_volume()) {
+                return Err("volume spike alerts can't be backtested: the recorded history has no trades".to_string());
+            }
+            if engine.rules().iter().any(|r| r.uses_portfolio()) {
+                return Err("take profit and stop loss alerts can't be backtested: they follow the live holdings".to_string());
+            }
+            engine.use_indicators(indicators.clone());
+        }
+        if let Some(script) = &mut script {
+            script.use_indicators(indicators);
+        }
+        Ok(Self {
+            store: PriceStore::new(),
+            candles,
+            engine,
+            script,
+            script_interval: config.script.interval,
+            next_script_run: None,
+            paper: PaperTrader::new(config.paper.clone()),
+            clock: None,
+            ticks: 0,
+            last: None,
+            alerts: Vec::new(),
+            rejected: Vec::new(),
+            peak_equity: config.paper.cash,
+            max_drawdown: Decimal::ZERO,
+        })
+    }
+
+    // Recorded ticks, oldest first (those from unknown exchanges are skipped)
+    pub async fn run(&mut self, ticks: &[Tick]) {
+        for update in ticks.iter().filter_map(Tick::to_update) {
+            self.step(update).await;
+        }
+    }
+
+    // Symbols the alert rules and paper trading rules follow
+    pub fn rule_symbols(&self) -> BTreeSet<String> {
+        let alerts = self.engine.iter().flat_map(|e| e.rules()).map(|r| r.symbol.clone());
+        alerts.chain(self.paper.rule_symbols()).collect()
+    }
+
+    // Recorded frames, in the order they arrived: whatever prices they carry for `symbols`
+    // (frames from unknown exchanges are skipped)
+    pub async fn run_frames(&mut self, frames: &[RecordedFrame], symbols: BTreeSet<String>) {
+        let feed = PriceStore::new();  // Separate from ours, which `step` updates
+        let mut updates = feed.subscribe_updates();
+        let mut feeder = FrameFeeder::new(Vec::new(), symbols, false);
+        for frame in frames {
+            feeder.feed(frame, &feed).await;
+            while let Ok(update) = updates.try_recv() {
+                self.step(update).await;
+            }
+        }
+    }
+
+    // Candles, oldest first: each one's close, just before the candle ends
+    pub async fn run_candles(&mut self, candles: &[Candle]) {
+        for candle in candles {
+            let end_ms = candle.start_ms + (candle.interval.as_millis() as u64).max(1) - 1;
+            self.step(PriceUpdate {
+                exchange: candle.exchange,
+                symbol: candle.symbol.clone(),
+                price: candle.close,
+                open_24h: None,
+                size: Some(candle.volume),
+                received_at: UNIX_EPOCH + Duration::from_millis(end_ms),
+            })
+            .await;
+        }
+    }
+
+    // One price, as if it had just arrived
+    pub async fn step(&mut self, update: PriceUpdate) {
+        let at = update.received_at;
+        let (started_at, started) = *self.clock.get_or_insert((at, Instant::now()));
+        let now = started + at.duration_since(started_at).unwrap_or_default();
+        self.ticks += 1;
+        self.last = Some(at);
+
+        self.store.update(update.clone()).await;
+        self.candles.lock().unwrap().ingest(&update);
+
+        // Rules on every tick, the script on its interval
+        let mut fired = self.engine.as_mut().map(|e| e.evaluate(&update, now)).unwrap_or_default();
+        if let Some(script) = &mut self.script
+            && self.next_script_run.is_none_or(|due| at >= due)
+        {
+            self.next_script_run = Some(at + self.script_interval);
+            fired.extend(script.evaluate(&self.store.view().await, now));
+        }
+        for mut alert in fired {
+            alert.fired_at = at;
+            if let Some(trade) = &alert.trade
+                && let Err(reason) = self.paper.place(trade.side, &alert.symbol, trade.quantity, trade.limit, &alert.rule)
+            {
+                self.rejected.push((at, format!("{} {} ({})", trade, alert.symbol, alert.rule), reason));
+            }
+            self.alerts.push(alert);
+        }
+
+        // Orders placed above fill at this price
+        let (_, rejected) = self.paper.on_update(&update);
+        self.rejected.extend(rejected.into_iter().map(|(order, reason)| (at, order.to_string(), reason)));
+
+        let equity = self.paper.equity();
+        self.peak_equity = self.peak_equity.max(equity);
+        self.max_drawdown = self.max_drawdown.max(self.peak_equity - equity);
+    }
+
+    // The simulated account, e.g. to export its fills
+    pub fn paper(&self) -> &PaperTrader {
+        &self.paper
+    }
+
+    pub fn report(&self) -> BacktestReport {
+        BacktestReport {
+            ticks: self.ticks,
+            first: self.clock.map(|(at, _)| at),
+            last: self.last,
+            alerts: self.alerts.clone(),
+            fills: self.paper.fills().to_vec(),
+            rejected: self.rejected.clone(),
+            account: self.paper.summary(),
+            max_drawdown: self.max_drawdown,
+            peak_equity: self.peak_equity,
+        }
+    }
+}
+
+// What happened over the history
+#[derive(Debug, Clone)]
+pub struct BacktestReport {
+    pub ticks: usize,
+    pub first: Option<SystemTime>,
+    pub last: Option<SystemTime>,
+    pub alerts: Vec<Alert>,
+    pub fills: Vec<Fill>,
+    pub rejected: Vec<(SystemTime, String, String)>,
+    pub account: PaperSummary,
+    pub max_drawdown: Decimal,   // Largest fall from a previous equity high
+    pub peak_equity: Decimal,
+}
+
+impl BacktestReport {
+    // Alerts fired per rule ("script strategy.rhai" for the script)
+    pub fn alerts_by_rule(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for alert in &self.alerts {
+            *counts.entry(alert.rule.as_str()).or_default() += 1;
+        }
+        counts
+    }
+
+    // When each rule fired, oldest first
+    pub fn fired_by_rule(&self) -> BTreeMap<&str, Vec<SystemTime>> {
+        let mut fired: BTreeMap<&str, Vec<SystemTime>> = BTreeMap::new();
+        for alert in &self.alerts {
+            fired.entry(alert.rule.as_str()).or_default().push(alert.fired_at);
+        }
+        fired
+    }
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (Some(first), Some(last)) = (self.first, self.last) else {
+            return write!(f, "No history to backtest");
+        };
+
+        // Step 1: Everything that happened, in time order
+        let mut events: Vec<(SystemTime, String)> = Vec::new();
+        for alert in &self.alerts {
+            events.push((alert.fired_at, format!("alert   {} {}  {}: {}", alert.symbol, alert.price, alert.rule, alert.detail)));
+        }
+        for fill in &self.fills {
+            let pnl = match fill.side {
+                Side::Sell => format!(", P&L {:+.2}", fill.realized_pnl),
+                Side::Buy => String::new(),
+            };
+            let at = UNIX_EPOCH + Duration::from_millis(fill.timestamp_ms);
+            events.push((at, format!("fill    {} {} {} @ {}, fee {}{}", fill.side, fill.quantity, fill.symbol, fill.price, fill.fee, pnl)));
+        }
+        for (at, order, reason) in &self.rejected {
+            events.push((*at, format!("reject  {}: {}", order, reason)));
+        }
+        events.sort_by_key(|(at, _)| *at);  // Stable: an alert comes before the fill it caused
+        for (at, event) in &events {
+            writeln!(f, "{}  {}", humantime::format_rfc3339_seconds(*at), event)?;
+        }
+        if !events.is_empty() {
+            writeln!(f)?;
+        }
+
+        // Step 2: Totals
+        let span = Duration::from_secs(last.duration_since(first).unwrap_or_default().as_secs());
+        writeln!(
+            f,
+            "{} ticks from {} to {} ({})",
+            self.ticks,
+            humantime::format_rfc3339_seconds(first),
+            humantime::format_rfc3339_seconds(last),
+            humantime::format_duration(span)
+        )?;
+        writeln!(f, "{} alerts, {} fills, {} rejected orders", self.alerts.len(), self.fills.len(), self.rejected.len())?;
+        for (rule, times) in self.fired_by_rule() {
+            writeln!(f, "{:>6}  {}", times.len(), rule)?;
+            let times: Vec<String> = times.iter().map(|at| humantime::format_rfc3339_seconds(*at).to_string()).collect();
+            writeln!(f, "        {}", times.join(", "))?;
+        }
+        let drawdown_pct =
+            if self.peak_equity.is_zero() { Decimal::ZERO } else { self.max_drawdown / self.peak_equity * Decimal::ONE_HUNDRED };
+        writeln!(f, "Max drawdown {:.2} {} ({:.2}%)", self.max_drawdown, self.account.currency, drawdown_pct)?;
+        write!(f, "{}", self.account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::AlertEngine;
+    use crate::script::ScriptConfig;
+
+    fn tick(secs: i64, price: &str) -> Tick {
+        Tick {
+            timestamp_ms: 1_700_000_000_000 + secs * 1000,
+            exchange: "coinbase".to_string(),
+            symbol: "BTC-USD".to_string(),
+            price: price.parse().unwrap(),
+            size: None,
+        }
+    }
+
+    #[test]
+    fn rules_and_script_trade_over_history() {
+        let path = std::env::temp_dir().join(format!("crabby-backtest-{}.rhai", std::process::id()));
+        std::fs::write(&path, "if price(\"BTC-USD\") > 55000 { sell(\"BTC-USD\", 0.1); }").unwrap();
+        let mut config = Config::default();
+        config.paper.fee_pct = Decimal::ZERO;
+        config.script = ScriptConfig { path: Some(path.clone()), interval: Duration::from_secs(60), cooldown: Duration::ZERO };
+        let engine = AlertEngine::from_toml(
+            r#"
+            [[alert]]
+            symbol = "BTC-USD"
+            below = 50000
+            cooldown = "1h"
+            trade = { side = "buy", quantity = 0.1 }
+            "#,
+        )
+        .unwrap();
+        let script = ScriptRunner::load(&config.script).unwrap();
+        let mut backtest = Backtest::new(&config, Some(engine), script).unwrap();
+
+        // Buys below 50000 (the second dip is within the cooldown) and sells above 55000 when
+        // the script runs a minute later; the next sell has nothing left to sell
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(backtest.run(&[
+            tick(0, "51000"),
+            tick(10, "49000"),
+            tick(20, "51000"),
+            tick(30, "48000"),
+            tick(70, "56000"),
+            tick(130, "54000"),
+            tick(200, "57000"),
+        ]));
+        let report = backtest.report();
+        assert_eq!(report.ticks, 7);
+        assert_eq!(report.alerts.len(), 3);
+        assert_eq!(report.alerts[0].fired_at, UNIX_EPOCH + Duration::from_secs(1_700_000_010));
+        let counts = report.alerts_by_rule();
+        assert_eq!(counts["BTC-USD below 50000"], 1);
+        assert_eq!(counts[format!("script {}", path.file_name().unwrap().to_string_lossy()).as_str()], 2);
+        let fills: Vec<(Side, Decimal)> = report.fills.iter().map(|f| (f.side, f.price)).collect();
+        assert_eq!(fills, [(Side::Buy, "49000".parse().unwrap()), (Side::Sell, "56000".parse().unwrap())]);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.account.realized_pnl, Decimal::from(700));
+        // Equity went up to 10200 at 51000, then down to 9900 at 48000
+        assert_eq!(report.max_drawdown, Decimal::from(300));
+        let text = report.to_string();
+        assert!(text.contains("2023-11-14T22:13:30Z  fill    buy 0.1 BTC-USD @ 49000"), "{}", text);
+        assert!(text.contains("7 ticks from 2023-11-14T22:13:20Z"), "{}", text);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recorded_frames_go_through_the_feed_into_the_rules() {
+        let path = std::env::temp_dir().join(format!("crabby-backtest-recording-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let recorder = crate::recording::Recorder::create(&path).unwrap();
+        let sink = recorder.sink();
+        let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs);
+        let ticker = |symbol: &str, price: &str| format!(r#"{{"type":"ticker","product_id":"{}","price":"{}"}}"#, symbol, price);
+        sink.record("coinbase", &ticker("BTC-USD", "65000"), at(0));
+        sink.record("coinbase", &ticker("BTC-USD", "71000"), at(10));
+        sink.record("coinbase", &ticker("DOGE-USD", "1"), at(15));  // Not followed
+        sink.record("coinbase", &ticker("BTC-USD", "69000"), at(20));
+        sink.record("nowhere", &ticker("BTC-USD", "90000"), at(25));  // No such connector
+        sink.record("coinbase", r#"{"type":"ticker","product_id":"#, at(26));  // Truncated
+        sink.record("coinbase", &ticker("BTC-USD", "72000"), at(30));
+        sink.record("coinbase", &ticker("BTC-USD", "59000"), at(40));
+        recorder.close();
+        assert!(crate::recording::is_recording(&path));
+
+        let engine = AlertEngine::from_toml(
+            r#"
+            [[alert]]
+            symbol = "BTC-USD"
+            above = 70000
+            cooldown = "5s"
+
+            [[alert]]
+            symbol = "BTC-USD"
+            below = 60000
+
+            [[alert]]
+            symbol = "ETH-USD"
+            above = 4000
+            "#,
+        )
+        .unwrap();
+        let mut backtest = Backtest::new(&Config::default(), Some(engine), None).unwrap();
+        let symbols = backtest.rule_symbols();
+        assert_eq!(symbols, BTreeSet::from(["BTC-USD".to_string(), "ETH-USD".to_string()]));
+
+        let frames = crate::recording::read_recording(&path).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(backtest.run_frames(&frames, symbols));
+        let report = backtest.report();
+
+        assert_eq!(report.ticks, 5);  // The BTC-USD tickers from coinbase
+        let fired = report.fired_by_rule();
+        assert_eq!(fired.len(), 2, "{:?}", fired);
+        assert_eq!(fired["BTC-USD above 70000"], [at(10), at(30)]);
+        assert_eq!(fired["BTC-USD below 60000"], [at(40)]);
+        let text = report.to_string();
+        assert!(text.contains("     2  BTC-USD above 70000\n        2023-11-14T22:13:30Z, 2023-11-14T22:13:50Z\n"), "{}", text);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
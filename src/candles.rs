@@ -0,0 +1,281 @@
+// OHLCV candle aggregation: buckets incoming ticks into fixed intervals
+// (e.g. 1m, 5m, 1h) per exchange and symbol, kept in memory.
+//
+// Buckets are aligned to the Unix epoch, so a 5m candle always starts at
+// :00, :05, :10... A candle is "closed" when the first tick of the next bucket
+// arrives; closed candles are returned from `ingest` so callers can persist them.
+
+use std::{
+    collections::{HashMap, VecDeque},   // Current + recent candles per series
+    sync::{Arc, Mutex},                 // Shared between the aggregation task and readers
+    time::Duration,                     // Candle intervals
+};
+
+use rust_decimal::Decimal;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::store::{epoch_ms, PriceStore, PriceUpdate};
+
+// One candle
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candle {
+    pub exchange: &'static str,
+    pub symbol: String,
+    pub interval: Duration,   // Width of the bucket
+    pub start_ms: u64,        // Bucket start, milliseconds since the Unix epoch
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,      // Sum of trade sizes (0 when the exchange doesn't report sizes)
+    pub trades: u64,          // Number of ticks folded into this candle
+}
+
+impl Candle {
+    // A new candle opened by its first tick
+    fn open_with(update: &PriceUpdate, interval: Duration, start_ms: u64) -> Self {
+        let (price, size) = (update.price, update.size.unwrap_or_default());
+        Self {
+            exchange: update.exchange,
+            symbol: update.symbol.clone(),
+            interval,
+            start_ms,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: size,
+            trades: 1,
+        }
+    }
+
+    // Fold one more tick into this candle
+    fn add(&mut self, price: Decimal, size: Decimal) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += size;
+        self.trades += 1;
+    }
+}
+
+// Identifies one candle series: (exchange, symbol, interval)
+type SeriesKey = (&'static str, String, Duration);
+
+// The candles of one series: finished ones plus the one still being built
+#[derive(Debug, Default)]
+struct Series {
+    closed: VecDeque<Candle>,  // Oldest first, at most `keep` entries
+    current: Option<Candle>,
+}
+
+// Turns ticks into candles for every configured interval
+#[derive(Debug)]
+pub struct CandleAggregator {
+    intervals: Vec<Duration>,
+    keep: usize,                             // Closed candles remembered per series
+    series: HashMap<SeriesKey, Series>,
+}
+
+// Aggregator shared between the background task and whoever reads candles
+pub type SharedCandles = Arc<Mutex<CandleAggregator>>;
+
+// Somewhere closed candles go (the database, relay clients...)
+pub type CandleSink = Box<dyn Fn(&Candle) + Send>;
+
+impl CandleAggregator {
+    // `intervals` to build (e.g. 1m, 5m, 1h); keep the last `keep` closed candles of each
+    pub fn new(intervals: Vec<Duration>, keep: usize) -> Self {
+        Self { intervals, keep, series: HashMap::new() }
+    }
+
+    pub fn intervals(&self) -> &[Duration] {
+        &self.intervals
+    }
+
+    // Add one tick to every interval. Returns the candles this tick closed.
+    pub fn ingest(&mut self, update: &PriceUpdate) -> Vec<Candle> {
+        let now_ms = epoch_ms(update.received_at);
+
+        let mut closed = Vec::new();
+        for &interval in &self.intervals {
+            let width = (interval.as_millis() as u64).max(1);
+            let start_ms = now_ms - now_ms % width;
+
+            let key = (update.exchange, update.symbol.clone(), interval);
+            let series = self.series.entry(key).or_default();
+            match &mut series.current {
+                Some(candle) if candle.start_ms == start_ms => candle.add(update.price, update.size.unwrap_or_default()),
+                Some(candle) if start_ms < candle.start_ms => {}  // Late tick for a finished bucket; ignore
+                _ => {
+                    // First tick of a new bucket: the previous candle (if any) is done
+                    let new = Candle::open_with(update, interval, start_ms);
+                    if let Some(done) = series.current.replace(new) {
+                        series.closed.push_back(done.clone());
+                        if series.closed.len() > self.keep {
+                            series.closed.pop_front();
+                        }
+                        closed.push(done);
+                    }
+                }
+            }
+        }
+        closed
+    }
+
+    // Load historical candles (oldest first, all the same width, e.g. from the backfill)
+    // into every configured interval they can be rolled up into: 5m history fills the
+    // 5m and 1h series, but not 1m. A last bucket that the history only partly covers
+    // becomes the candle in progress, so live ticks carry on filling it.
+    pub fn seed(&mut self, history: &[Candle]) {
+        let Some(last) = history.last() else { return };
+        let history_end_ms = last.start_ms + last.interval.as_millis() as u64;
+
+        for &interval in &self.intervals {
+            let (width, source) = (interval.as_millis() as u64, last.interval.as_millis() as u64);
+            if source == 0 || width < source || width % source != 0 {
+                continue;  // Can't build this interval from the history
+            }
+
+            let mut rolled: Vec<Candle> = Vec::new();
+            for c in history {
+                let start_ms = c.start_ms - c.start_ms % width;
+                match rolled.last_mut() {
+                    Some(candle) if candle.start_ms == start_ms => {
+                        candle.high = candle.high.max(c.high);
+                        candle.low = candle.low.min(c.low);
+                        candle.close = c.close;
+                        candle.volume += c.volume;
+                        candle.trades += c.trades;
+                    }
+                    _ => rolled.push(Candle { interval, start_ms, ..c.clone() }),
+                }
+            }
+
+            let series = self.series.entry((last.exchange, last.symbol.clone(), interval)).or_default();
+            if rolled.last().is_some_and(|c| c.start_ms + width > history_end_ms) {
+                series.current = rolled.pop();
+            }
+            series.closed.extend(rolled);
+            while series.closed.len() > self.keep {
+                series.closed.pop_front();
+            }
+        }
+    }
+
+    // Closed candles for one series (oldest first), followed by the one in progress
+    pub fn candles(&self, exchange: &str, symbol: &str, interval: Duration) -> Vec<Candle> {
+        self.series
+            .iter()
+            .find(|((ex, sym, iv), _)| *ex == exchange && sym == symbol && *iv == interval)
+            .map(|(_, s)| s.closed.iter().chain(s.current.iter()).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    // Every candle still being built (e.g. to save them on shutdown)
+    pub fn open_candles(&self) -> Vec<Candle> {
+        self.series.values().filter_map(|s| s.current.clone()).collect()
+    }
+
+    // Only the finished candles for one series, oldest first
+    pub fn closed_candles(&self, exchange: &str, symbol: &str, interval: Duration) -> Vec<Candle> {
+        self.series
+            .iter()
+            .find(|((ex, sym, iv), _)| *ex == exchange && sym == symbol && *iv == interval)
+            .map(|(_, s)| s.closed.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+// Feed every update from `store` into `candles`, calling `on_close` for each finished candle
+pub fn spawn_candles<F>(candles: SharedCandles, store: &PriceStore, on_close: F) -> tokio::task::JoinHandle<()>
+where
+    F: Fn(&Candle) + Send + 'static,
+{
+    let mut rx = store.subscribe_updates();
+    let metrics = store.metrics().clone();  // Counts what the task misses
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(update) => {
+                    let closed = candles.lock().unwrap().ingest(&update);
+                    for candle in &closed {
+                        on_close(candle);
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!(skipped = n, "Candle aggregation fell behind");
+                    metrics.record_dropped_events("candles", n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn tick(price: &str, size: &str, secs: u64) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: Some(size.parse().unwrap()),
+            received_at: UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn ticks_are_bucketed_into_ohlcv() {
+        let minute = Duration::from_secs(60);
+        let mut agg = CandleAggregator::new(vec![minute], 10);
+
+        assert!(agg.ingest(&tick("100", "1", 60)).is_empty());
+        agg.ingest(&tick("105", "2", 70));
+        agg.ingest(&tick("95", "0.5", 80));
+        agg.ingest(&tick("101", "1", 119));
+
+        // First tick of the next minute closes the previous candle
+        let closed = agg.ingest(&tick("102", "1", 120));
+        assert_eq!(closed.len(), 1);
+        let c = &closed[0];
+        let d = |n: i64| Decimal::from(n);
+        assert_eq!((c.start_ms, c.open, c.high, c.low, c.close), (60_000, d(100), d(105), d(95), d(101)));
+        assert_eq!((c.volume, c.trades), (Decimal::new(45, 1), 4));
+
+        let all = agg.candles("coinbase", "BTC-USD", minute);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].open, d(102));
+    }
+
+    #[test]
+    fn history_is_rolled_up_into_longer_intervals() {
+        let (minute, two) = (Duration::from_secs(60), Duration::from_secs(120));
+        let mut agg = CandleAggregator::new(vec![Duration::from_secs(30), minute, two], 10);
+
+        // Three finished one-minute candles: 0-60s, 60-120s, 120-180s
+        let history: Vec<Candle> = (0..3i64)
+            .map(|i| {
+                let mut c = Candle::open_with(&tick("100", "1", 0), minute, i as u64 * 60_000);
+                c.add(Decimal::from(100 + i * 10), Decimal::ONE);
+                c
+            })
+            .collect();
+        agg.seed(&history);
+
+        assert!(agg.candles("coinbase", "BTC-USD", Duration::from_secs(30)).is_empty());  // Finer than the history
+        assert_eq!(agg.closed_candles("coinbase", "BTC-USD", minute).len(), 3);
+
+        // 2m: one full bucket (0-120s) and one only half covered (120-180s), left open
+        let closed = agg.closed_candles("coinbase", "BTC-USD", two);
+        assert_eq!(closed.len(), 1);
+        assert_eq!((closed[0].high, closed[0].close, closed[0].volume), (Decimal::from(110), Decimal::from(110), Decimal::from(4)));
+        let live = agg.ingest(&tick("125", "1", 150));
+        assert!(live.is_empty());  // Same bucket as the seeded one in progress
+        assert_eq!(agg.candles("coinbase", "BTC-USD", two)[1].high, Decimal::from(125));
+    }
+}
@@ -0,0 +1,371 @@
+// Command-line arguments (clap). Options shared by every mode are global, so
+// they can be given before or after the subcommand.
+
+use std::{path::PathBuf, time::Duration};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rust_decimal::Decimal;
+use crabbycryptotracker::{
+    config::{ColorMode, OutputFormat, UnknownSymbols},
+    export::Format,
+    indicators::Indicator,
+    recording::ReplaySpeed,
+    report::{EveryNth, QuietHours},
+    snapshots::Schedule,
+};
+
+// `--version` prints build metadata collected by build.rs; one "key: value" pair per
+// line so it is easy to read by eye and easy to parse by scripts
+const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "\ncommit: ",
+    env!("CRABBY_GIT_COMMIT"),
+    "\nbuilt: ",
+    env!("CRABBY_BUILD_TIMESTAMP"),
+    "\nfeatures: ",
+    env!("CRABBY_FEATURES"),
+);
+
+#[derive(Debug, Parser)]
+#[command(name = "crabbycryptotracker", version, long_version = LONG_VERSION, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    // What to do; defaults to `track`
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// Options that apply to every mode. Each one overrides the same setting from
+// config.toml and the environment; leaving it out keeps the configured value.
+#[derive(Debug, Args)]
+pub struct GlobalArgs {
+    /// Log level or filter, e.g. debug or crabbycryptotracker::feed=debug (default: RUST_LOG, else info)
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Log format: text, or json (one object per line) for log collection agents
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text, env = "CRABBY_LOG_FORMAT")]
+    pub log_format: LogFormat,
+
+    /// Configuration file (default: config.toml if it exists)
+    #[arg(long, global = true, env = "CRABBY_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// CSV file with a "symbol" column listing the products to track
+    #[arg(long, global = true)]
+    pub symbols_file: Option<PathBuf>,
+
+    /// Symbols to track, e.g. BTC-USD,ETH-USD (overrides --symbols-file)
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+
+    /// Exchanges to connect to: coinbase, binance, kraken (comma-separated)
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub exchange: Vec<String>,
+
+    /// Symbols an exchange doesn't list: fail (default), skip them, or ignore (don't check)
+    #[arg(long, global = true, value_name = "fail|skip|ignore")]
+    pub unknown_symbols: Option<UnknownSymbols>,
+
+    /// Also track level 2 order books (best bid/ask and spread; Coinbase only)
+    #[arg(long, global = true)]
+    pub order_books: bool,
+
+    /// Also follow every trade for rolling volume, trade count and VWAP (and volume spike alerts)
+    #[arg(long, global = true)]
+    pub trades: bool,
+
+    /// Reconnect when a symbol has had no data or heartbeat for this long (Coinbase; 0s turns it off)
+    #[arg(long, global = true, value_parser = humantime::parse_duration, value_name = "DURATION")]
+    pub stale_after: Option<Duration>,
+
+    /// Resubscribe a symbol (fresh order book snapshot, if any) when sequence numbers show dropped messages
+    #[arg(long, global = true)]
+    pub resync_on_gap: bool,
+
+    /// Spread each exchange's symbols over several connections with at most this many each (0: one connection)
+    #[arg(long, global = true, value_name = "N")]
+    pub symbols_per_connection: Option<usize>,
+
+    /// Skip fetching recent history from the Coinbase REST API on startup
+    #[cfg(feature = "backfill")]
+    #[arg(long, global = true)]
+    pub no_backfill: bool,
+
+    /// Output format: table (every --interval) or ndjson (one JSON line per update)
+    #[arg(long, global = true, value_name = "FORMAT")]
+    pub output: Option<OutputFormat>,
+
+    /// Color the table output green/red by direction: auto (when stdout is a terminal), always, never
+    #[arg(long, global = true, value_name = "WHEN")]
+    pub color: Option<ColorMode>,
+
+    /// Highlight a symbol that moved at least this many % since the previous printout (0 turns it off)
+    #[arg(long, global = true, value_name = "PCT")]
+    pub highlight_pct: Option<Decimal>,
+
+    /// How often to print the latest prices, e.g. 10s, 1m
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    pub interval: Option<Duration>,
+
+    /// Don't print the price table during these hours, e.g. 22:00-07:00 (comma-separated; alerts still fire)
+    #[arg(long, global = true, value_delimiter = ',', value_name = "HH:MM-HH:MM")]
+    pub quiet_hours: Vec<QuietHours>,
+
+    /// Between printouts, also print every Nth update of a symbol as it arrives, e.g. BTC-USD=10 (comma-separated)
+    #[arg(long, global = true, value_delimiter = ',', value_name = "SYMBOL=N")]
+    pub every_nth: Vec<EveryNth>,
+
+    /// Record every price update to this SQLite database
+    #[cfg(feature = "sqlite")]
+    #[arg(long, global = true)]
+    pub db: Option<PathBuf>,
+
+    /// Publish every price update to this MQTT broker, e.g. mqtt://localhost:1883
+    #[arg(long, global = true, value_name = "URL")]
+    pub mqtt_url: Option<String>,
+
+    /// Keep price:{symbol} keys in this Redis and publish updates on a channel, e.g. redis://localhost:6379
+    #[arg(long, global = true, value_name = "URL")]
+    pub redis_url: Option<String>,
+
+    /// Produce every price update to these Kafka brokers, e.g. localhost:9092 (comma-separated)
+    #[arg(long, global = true, value_delimiter = ',', value_name = "HOST:PORT")]
+    pub kafka_brokers: Vec<String>,
+
+    /// Also write ticks and candles to InfluxDB (http://host:8086) or TimescaleDB (postgres://user@host/db)
+    #[arg(long, global = true, value_name = "URL")]
+    pub tsdb_url: Option<String>,
+
+    /// Append price snapshots to this CSV file (rotated as it grows)
+    #[arg(long, global = true, value_name = "FILE")]
+    pub snapshots: Option<PathBuf>,
+
+    /// When to write snapshots: "tick" for every update, or an interval like 1m
+    #[arg(long, global = true, value_name = "tick|DURATION")]
+    pub snapshot_every: Option<Schedule>,
+
+    /// Record every raw WebSocket message to this file (NDJSON), for --replay later
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Play back a file made with --record instead of connecting to the exchanges
+    #[arg(long, global = true, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    /// Replay speed: 1 (as recorded), a factor like 10x, or max
+    #[arg(long, global = true, value_name = "SPEED")]
+    pub replay_speed: Option<ReplaySpeed>,
+
+    /// TOML file with additional alert rules
+    #[arg(long, global = true)]
+    pub alerts: Option<PathBuf>,
+
+    /// Script of alert conditions over live prices and indicators, reloaded when the file changes
+    #[arg(long, global = true, value_name = "PATH")]
+    pub script: Option<PathBuf>,
+
+    /// Technical indicator to show for every symbol, e.g. RSI(14), EMA(50) or MACD(12,26,9); repeatable
+    #[arg(long, global = true, value_name = "INDICATOR")]
+    pub indicator: Vec<Indicator>,
+
+    /// Alert when a symbol's price differs between exchanges by at least this percentage
+    #[arg(long, global = true, value_name = "PCT")]
+    pub arbitrage: Option<Decimal>,
+
+    /// Watch the stablecoins (USDT-USD, USDC-USD, DAI-USD) and alert when one loses its 1.00 peg
+    #[arg(long, global = true)]
+    pub depeg: bool,
+
+    /// Paper trading: place simulated orders (buy/sell at the prompt, or [paper] rules) against live prices
+    #[arg(long, global = true)]
+    pub paper: bool,
+
+    /// Allow real orders on the Coinbase account (`trade` at the prompt, alert rules with `trade`); see [trading] for dry run and limits
+    #[arg(long, global = true)]
+    pub enable_trading: bool,
+
+    /// Show market cap, rank, circulating supply and 24h volume from CoinGecko with the prices
+    #[cfg(feature = "market")]
+    #[arg(long, global = true)]
+    pub market_data: bool,
+
+    /// Also show USD prices and portfolio totals in this currency, e.g. EUR, GBP or JPY
+    #[arg(long, global = true, value_name = "CODE")]
+    pub currency: Option<String>,
+
+    /// Rebroadcast updates and candle closes to WebSocket clients on this address, e.g. 127.0.0.1:9001
+    #[arg(long, global = true, value_name = "ADDR")]
+    pub relay_addr: Option<std::net::SocketAddr>,
+
+    /// Serve the gRPC API (GetSnapshot, StreamPrices) on this address, e.g. 127.0.0.1:50051
+    #[arg(long, global = true, value_name = "ADDR")]
+    pub grpc_addr: Option<std::net::SocketAddr>,
+
+    /// Connect to the exchanges and REST APIs through this proxy: socks5://host:1080 or http://host:3128
+    #[arg(long, global = true, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// Also trust the CA certificates in this PEM file (repeatable)
+    #[arg(long = "ca-cert", global = true, value_name = "PATH")]
+    pub ca_certs: Vec<PathBuf>,
+
+    /// CSV file of holdings (symbol,quantity,cost_basis) to value and show P&L for
+    #[arg(long, global = true)]
+    pub portfolio: Option<PathBuf>,
+
+    /// Control socket of the background tracker, for track --daemon, status and stop
+    #[arg(long, global = true, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+}
+
+// How log lines are written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Text,  // Human-readable, one line per event
+    Json,  // One JSON object per event, with span fields
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Stream prices and print them periodically (the default)
+    Track(TrackArgs),
+    /// Serve the REST API without printing prices to the terminal
+    #[cfg(feature = "api")]
+    Serve(ServeArgs),
+    /// Write recorded price history (SQLite database or snapshot/NDJSON files) as CSV, JSON or Parquet
+    Export(ExportArgs),
+    /// Run the alert rules, alert script and paper trading rules over recorded history and report what they'd have done
+    Backtest(BacktestArgs),
+    /// Show the latest prices of the tracker running in the background (track --daemon)
+    #[cfg(unix)]
+    Status,
+    /// Stop the tracker running in the background, waiting until it has shut down
+    #[cfg(unix)]
+    Stop,
+}
+
+#[derive(Debug, Args, Default)]
+pub struct TrackArgs {
+    /// Show the interactive dashboard instead of the periodic printout
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    pub tui: bool,
+
+    /// Also serve the REST API on this address, e.g. 127.0.0.1:8080
+    #[cfg(feature = "api")]
+    #[arg(long)]
+    pub api_addr: Option<std::net::SocketAddr>,
+
+    /// Run in the background, logging to [daemon] log_file; see `status` and `stop`
+    #[cfg(unix)]
+    #[arg(long)]
+    pub daemon: bool,
+}
+
+#[cfg(feature = "api")]
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address for the REST API (default: [api] addr from the config, else 127.0.0.1:8080)
+    #[arg(long)]
+    pub addr: Option<std::net::SocketAddr>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// File to write (default: stdout); the format follows its extension (.csv, .json, .parquet)
+    pub file: Option<PathBuf>,
+
+    /// Output format: csv, json (one object per line) or parquet (default: from the file name, else csv)
+    #[arg(long, value_name = "FORMAT")]
+    pub format: Option<Format>,
+
+    /// Read these snapshot CSV or NDJSON files instead of the database; repeatable
+    #[arg(long, value_name = "FILE")]
+    pub from: Vec<PathBuf>,
+
+    /// Only export this symbol
+    #[arg(long)]
+    pub symbol: Option<String>,
+
+    /// Start of the range: a date (2024-06-01), a timestamp (2024-06-01T12:00:00Z) or a duration ago (24h)
+    #[arg(long, value_name = "TIME")]
+    pub since: Option<String>,
+
+    /// End of the range (exclusive), in the same forms as --since
+    #[arg(long, value_name = "TIME")]
+    pub until: Option<String>,
+
+    /// Resample into OHLCV candles of this width, e.g. 1m, 15m, 1h
+    #[arg(long, value_name = "INTERVAL", value_parser = humantime::parse_duration)]
+    pub resample: Option<Duration>,
+}
+
+#[derive(Debug, Args)]
+pub struct BacktestArgs {
+    /// Read these snapshot CSV or NDJSON files, or recordings made with --record, instead of the database; repeatable
+    #[arg(long, value_name = "FILE")]
+    pub from: Vec<PathBuf>,
+
+    /// Only replay this symbol
+    #[arg(long)]
+    pub symbol: Option<String>,
+
+    /// Start of the range: a date (2024-06-01), a timestamp (2024-06-01T12:00:00Z) or a duration ago (24h)
+    #[arg(long, value_name = "TIME")]
+    pub since: Option<String>,
+
+    /// End of the range (exclusive), in the same forms as --since
+    #[arg(long, value_name = "TIME")]
+    pub until: Option<String>,
+
+    /// Replay only the close of each candle of this width, e.g. 1m or 1h (quicker over long ranges)
+    #[arg(long, value_name = "INTERVAL", value_parser = humantime::parse_duration)]
+    pub resample: Option<Duration>,
+
+    /// Write the simulated fills to this CSV file
+    #[arg(long, value_name = "FILE")]
+    pub fills: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn global_options_parse_after_subcommand() {
+        let cli = Cli::parse_from(["crabbycryptotracker", "track", "--symbols", "btc-usd,ETH-USD", "--interval", "10s"]);
+        assert_eq!(cli.global.symbols, ["btc-usd", "ETH-USD"]);
+        assert_eq!(cli.global.interval, Some(Duration::from_secs(10)));
+        assert!(cli.global.exchange.is_empty());
+    }
+
+    #[test]
+    fn long_version_lists_the_build_metadata() {
+        let text = Cli::command().render_long_version();
+        let lines: Vec<&str> = text.trim_end().lines().collect();
+        assert_eq!(lines.len(), 4, "{}", text);
+        assert_eq!(lines[0], format!("crabbycryptotracker {}", env!("CARGO_PKG_VERSION")));
+
+        let commit = lines[1].strip_prefix("commit: ").unwrap();
+        assert!(commit == "unknown" || (commit.len() == 12 && commit.chars().all(|c| c.is_ascii_hexdigit())), "{}", commit);
+
+        let built = lines[2].strip_prefix("built: ").unwrap();
+        assert!(built.ends_with('Z') && humantime::parse_rfc3339(built).is_ok(), "{}", built);
+
+        // The features this binary was compiled with, sorted, without "default"
+        let features: Vec<&str> = lines[3].strip_prefix("features: ").unwrap().split(',').collect();
+        assert!(features.is_sorted() && !features.contains(&"default"), "{:?}", features);
+        assert_eq!(features.contains(&"sqlite"), cfg!(feature = "sqlite"));
+        assert_eq!(features.contains(&"redis"), cfg!(feature = "redis"));
+        assert_eq!(features.contains(&"tui"), cfg!(feature = "tui"));
+    }
+}
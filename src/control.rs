@@ -0,0 +1,296 @@
+// Commands typed on stdin while the tracker runs, to change what it tracks without
+// restarting:
+//
+//   add BTC-USD SOL-USD      start tracking symbols (aliases: subscribe, +)
+//   remove ETH-USD           stop tracking symbols  (aliases: unsubscribe, rm, -)
+//   list                     show the tracked symbols
+//   stats                    show messages/sec and exchange latency per symbol (see health.rs)
+//   help                     show this list
+//   (empty line)             print the price table now instead of at the next interval
+//
+// With paper trading on (--paper), simulated orders too (see paper.rs):
+//
+//   buy BTC-USD 0.01         market order, filled at the next price
+//   sell BTC-USD 0.01 @ 70000  limit order, filled once the price reaches 70000
+//   orders                   show the orders waiting to fill
+//   cancel 3                 cancel order #3
+//   paper                    show cash, positions and P&L (alias: positions)
+//   export fills.csv         write every fill so far as CSV
+//
+// With real trading on (--enable-trading), orders on the Coinbase account (see trading.rs):
+//
+//   trade buy BTC-USD 0.001 [@ 59500]  market or limit order, within the [trading] limits
+//   trade orders             show the account's open orders
+//   trade cancel ORDER_ID    cancel one of them
+//
+// Replies are logged (to stderr), so they never mix with NDJSON on stdout.
+//
+// Stdin is read on a plain thread rather than with tokio's stdin: a tokio stdin read
+// that never completes would keep the runtime from shutting down on Ctrl-C.
+
+use std::io::{self, BufRead};        // Blocking line-by-line stdin
+use std::path::PathBuf;              // Export destination
+use std::time::SystemTime;           // "Now" for the feed stats
+use rust_decimal::Decimal;           // Order quantities and limit prices
+use tokio::sync::{mpsc, Notify};     // Lines from the reader thread; "print now" for the table
+use tracing::{info, warn};
+
+use crabbycryptotracker::{
+    alerts::TradeAction,
+    config::check_symbol,
+    paper::{SharedPaper, Side},
+    PriceTracker,
+};
+
+// Real orders go through the account API; a build without it has no trader to hold
+#[cfg(feature = "account")]
+pub use crabbycryptotracker::trading::Trader;
+#[cfg(not(feature = "account"))]
+pub enum Trader {}
+
+const HELP: &str = "Commands: add SYMBOL..., remove SYMBOL..., list, stats, help, Enter to print the prices now; \
+    with --paper: buy/sell SYMBOL QTY [@ PRICE], orders, cancel ID, paper, export PATH; \
+    with --enable-trading: trade buy/sell SYMBOL QTY [@ PRICE], trade orders, trade cancel ORDER_ID";
+
+// One parsed command line
+#[derive(Debug, PartialEq)]
+pub enum ControlCommand {
+    Add(Vec<String>),
+    Remove(Vec<String>),
+    List,
+    Stats,
+    Help,
+    Order { side: Side, symbol: String, quantity: Decimal, limit: Option<Decimal> },
+    Orders,
+    Cancel(u64),
+    Paper,
+    Export(PathBuf),
+    Trade { symbol: String, action: TradeAction },
+    TradeOrders,
+    TradeCancel(String),
+}
+
+impl ControlCommand {
+    // Parse one line; symbols may be separated by spaces or commas
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split(|c: char| c.is_whitespace() || c == ',').filter(|w| !w.is_empty());
+        let command = words.next().unwrap_or_default().to_lowercase();
+        let args: Vec<&str> = words.collect();
+        let symbols = || -> Result<Vec<String>, String> {
+            let symbols: Vec<String> = args.iter().map(|w| w.to_uppercase()).collect();
+            if symbols.is_empty() {
+                return Err(format!("`{}` needs at least one symbol, e.g. `{} BTC-USD`", command, command));
+            }
+            symbols.iter().try_for_each(|s| check_symbol(s))?;
+            Ok(symbols)
+        };
+        match command.as_str() {
+            "add" | "subscribe" | "+" => Ok(ControlCommand::Add(symbols()?)),
+            "remove" | "unsubscribe" | "rm" | "-" => Ok(ControlCommand::Remove(symbols()?)),
+            "list" | "ls" => Ok(ControlCommand::List),
+            "stats" => Ok(ControlCommand::Stats),
+            "help" | "?" => Ok(ControlCommand::Help),
+            "buy" => parse_order(Side::Buy, &args),
+            "sell" => parse_order(Side::Sell, &args),
+            "orders" => Ok(ControlCommand::Orders),
+            "cancel" => match args.as_slice() {
+                [id] => Ok(ControlCommand::Cancel(id.trim_start_matches('#').parse().map_err(|_| format!("`{}` isn't an order id", id))?)),
+                _ => Err("usage: cancel ID, e.g. `cancel 3` (see `orders`)".to_string()),
+            },
+            "paper" | "positions" => Ok(ControlCommand::Paper),
+            "export" => match args.as_slice() {
+                [path] => Ok(ControlCommand::Export(PathBuf::from(path))),
+                _ => Err("usage: export PATH, e.g. `export fills.csv`".to_string()),
+            },
+            "trade" => {
+                let (action, rest) = args.split_first().map(|(a, rest)| (a.to_lowercase(), rest)).unwrap_or_default();
+                match (action.as_str(), rest) {
+                    ("buy" | "sell", rest) => {
+                        let side = if action == "buy" { Side::Buy } else { Side::Sell };
+                        match parse_order(side, rest)? {
+                            ControlCommand::Order { side, symbol, quantity, limit } => {
+                                Ok(ControlCommand::Trade { symbol, action: TradeAction { side, quantity, limit } })
+                            }
+                            other => Ok(other),
+                        }
+                    }
+                    ("orders", []) => Ok(ControlCommand::TradeOrders),
+                    ("cancel", [id]) => Ok(ControlCommand::TradeCancel(id.to_string())),
+                    _ => Err("usage: trade buy|sell SYMBOL QUANTITY [@ PRICE], trade orders, trade cancel ORDER_ID".to_string()),
+                }
+            }
+            other => Err(format!("unknown command \"{}\". {}", other, HELP)),
+        }
+    }
+}
+
+// `buy SYMBOL QTY [@ PRICE]` / `sell ...`; the limit may also be written `@70000`
+fn parse_order(side: Side, args: &[&str]) -> Result<ControlCommand, String> {
+    let usage = || format!("usage: {} SYMBOL QUANTITY [@ PRICE], e.g. `{} BTC-USD 0.01 @ 65000`", side, side);
+    let number = |text: &str| text.parse::<Decimal>().map_err(|_| format!("`{}` isn't a number. {}", text, usage()));
+    let (symbol, quantity, limit) = match args {
+        [symbol, quantity] => (symbol, quantity, None),
+        [symbol, quantity, "@", limit] => (symbol, quantity, Some(*limit)),
+        [symbol, quantity, limit] if limit.starts_with('@') => (symbol, quantity, Some(&limit[1..])),
+        _ => return Err(usage()),
+    };
+    let symbol = symbol.to_uppercase();
+    check_symbol(&symbol)?;
+    Ok(ControlCommand::Order { side, symbol, quantity: number(quantity)?, limit: limit.map(number).transpose()? })
+}
+
+// Read commands from stdin and apply them to `tracker` (and to `paper` and `trader`, when they're on); an
+// empty line nudges `print_now`. Never returns: when stdin is closed (e.g. running under a service manager)
+// the tracker simply keeps going.
+pub async fn run(tracker: &PriceTracker, paper: Option<&SharedPaper>, trader: Option<&Trader>, print_now: &Notify) {
+    let (tx, mut lines) = mpsc::unbounded_channel();
+    std::thread::Builder::new()
+        .name("stdin-commands".to_string())
+        .spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("failed to spawn stdin thread");
+
+    while let Some(line) = lines.recv().await {
+        if line.trim().is_empty() {
+            print_now.notify_one();
+            continue;
+        }
+        match ControlCommand::parse(&line) {
+            Ok(ControlCommand::Add(symbols)) => {
+                tracker.subscribe(&symbols);
+                info!(symbols = ?tracker.symbols(), "Tracking symbols");
+            }
+            Ok(ControlCommand::Remove(symbols)) => {
+                tracker.unsubscribe(&symbols).await;
+                info!(symbols = ?tracker.symbols(), "Tracking symbols");
+            }
+            Ok(ControlCommand::List) => info!(symbols = ?tracker.symbols(), "Tracking symbols"),
+            Ok(ControlCommand::Stats) => {
+                let stats = tracker.store().feed_stats(SystemTime::now()).await;
+                if stats.is_empty() {
+                    info!("No messages yet");
+                }
+                for feed in stats {
+                    info!("{}", feed);
+                }
+            }
+            Ok(ControlCommand::Help) => info!("{}", HELP),
+            Ok(command @ (ControlCommand::Trade { .. } | ControlCommand::TradeOrders | ControlCommand::TradeCancel(_))) => {
+                match trader {
+                    Some(trader) => trade_command(command, tracker, trader).await,
+                    None => warn!("Trading is off; start with --enable-trading to place real orders"),
+                }
+            }
+            Ok(command) => match paper {
+                Some(paper) => paper_command(command, tracker, paper),
+                None => warn!("Paper trading is off; start with --paper to place simulated orders"),
+            },
+            Err(e) => warn!("{}", e),
+        }
+    }
+    std::future::pending::<()>().await;
+}
+
+// The paper trading commands
+fn paper_command(command: ControlCommand, tracker: &PriceTracker, paper: &SharedPaper) {
+    let mut paper = paper.lock().unwrap();
+    match command {
+        ControlCommand::Order { side, symbol, quantity, limit } => {
+            match paper.place(side, &symbol, quantity, limit, "manual") {
+                Ok(id) => {
+                    info!(order = id, "Placed paper order; it fills on the next matching price");
+                    // An order on an untracked symbol would never see a price
+                    if !tracker.symbols().contains(&symbol) {
+                        tracker.subscribe(std::slice::from_ref(&symbol));
+                        info!(symbols = ?tracker.symbols(), "Tracking symbols");
+                    }
+                }
+                Err(e) => warn!("{}", e),
+            }
+        }
+        ControlCommand::Orders if paper.orders().is_empty() => info!("No open paper orders"),
+        ControlCommand::Orders => {
+            let orders: Vec<String> = paper.orders().iter().map(|o| o.to_string()).collect();
+            info!("Open paper orders\n{}", orders.join("\n"));
+        }
+        ControlCommand::Cancel(id) if paper.cancel(id) => info!(order = id, "Cancelled paper order"),
+        ControlCommand::Cancel(id) => warn!(order = id, "No such open paper order"),
+        ControlCommand::Paper => info!("Paper trading\n{}", paper.summary()),
+        ControlCommand::Export(path) => match paper.export_csv(&path) {
+            Ok(fills) => info!(path = %path.display(), fills, "Exported paper fills"),
+            Err(e) => warn!(path = %path.display(), error = %e, "Couldn't export paper fills"),
+        },
+        _ => {}
+    }
+}
+
+// The real trading commands; replies are logged like the rest
+#[cfg(feature = "account")]
+async fn trade_command(command: ControlCommand, tracker: &PriceTracker, trader: &Trader) {
+    match command {
+        ControlCommand::Trade { symbol, action } => {
+            // A market order is valued at the latest price from any exchange
+            let price = tracker.store().latest(&symbol).await.map(|u| u.price);
+            if let Err(e) = trader.place(&symbol, &action, price, "manual").await {
+                warn!(%symbol, order = %action, "{}", e);
+            }
+        }
+        ControlCommand::TradeOrders => match trader.open_orders().await {
+            Ok(orders) if orders.is_empty() => info!("No open orders on the account"),
+            Ok(orders) => {
+                let orders: Vec<String> = orders.iter().map(|o| o.to_string()).collect();
+                info!("Open orders\n{}", orders.join("\n"));
+            }
+            Err(e) => warn!(error = %e, "Couldn't list the open orders"),
+        },
+        ControlCommand::TradeCancel(id) => match trader.cancel(&id).await {
+            Ok(()) if trader.dry_run() => {}
+            Ok(()) => info!(order_id = %id, "Cancelled order"),
+            Err(e) => warn!(order_id = %id, error = %e, "Couldn't cancel the order"),
+        },
+        _ => {}
+    }
+}
+
+#[cfg(not(feature = "account"))]
+async fn trade_command(_: ControlCommand, _: &PriceTracker, trader: &Trader) {
+    match *trader {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_and_rejects_bad_symbols() {
+        assert_eq!(
+            ControlCommand::parse("add btc-usd, sol-usd"),
+            Ok(ControlCommand::Add(vec!["BTC-USD".into(), "SOL-USD".into()]))
+        );
+        assert_eq!(ControlCommand::parse("- ETH-USD"), Ok(ControlCommand::Remove(vec!["ETH-USD".into()])));
+        assert_eq!(ControlCommand::parse(" LIST "), Ok(ControlCommand::List));
+        assert_eq!(ControlCommand::parse("stats"), Ok(ControlCommand::Stats));
+        assert!(ControlCommand::parse("add").is_err());
+        assert!(ControlCommand::parse("add BTCUSD").is_err());
+        assert!(ControlCommand::parse("buy BTC-USD").is_err());
+        assert_eq!(
+            ControlCommand::parse("sell btc-usd 0.5 @70000"),
+            Ok(ControlCommand::Order { side: Side::Sell, symbol: "BTC-USD".into(), quantity: "0.5".parse().unwrap(), limit: Some("70000".parse().unwrap()) })
+        );
+        assert_eq!(ControlCommand::parse("cancel #3"), Ok(ControlCommand::Cancel(3)));
+        assert_eq!(
+            ControlCommand::parse("trade buy btc-usd 0.001"),
+            Ok(ControlCommand::Trade {
+                symbol: "BTC-USD".into(),
+                action: TradeAction { side: Side::Buy, quantity: "0.001".parse().unwrap(), limit: None }
+            })
+        );
+        assert_eq!(ControlCommand::parse("trade cancel 8f1c"), Ok(ControlCommand::TradeCancel("8f1c".into())));
+        assert!(ControlCommand::parse("trade").is_err());
+    }
+}
@@ -0,0 +1,320 @@
+// Running in the background. `track --daemon` starts a second copy of the program,
+// detached from the terminal, and returns once that copy answers on its control socket:
+//
+//     [daemon]
+//     pid_file = "crabbycryptotracker.pid"
+//     log_file = "crabbycryptotracker.log"   # Its price table and logs
+//     socket = "crabbycryptotracker.sock"    # Where `status` and `stop` find it (CRABBY_DAEMON_SOCKET)
+//
+// The control protocol is one line per connection: the client sends a command ("status"
+// or "stop"), the daemon answers with one JSON object and closes the connection. `stop`
+// (or a SIGTERM) shuts the tracker down the same way Ctrl-C does, flushing the database
+// and the other sinks; the PID file and socket are removed once that's done.
+//
+// Re-running the program rather than forking keeps things simple: forking a process that
+// already runs a multi-threaded Tokio runtime isn't safe.
+
+use std::{
+    env,                                   // Our own executable and arguments, for the copy
+    error::Error,                          // Trait to return errors from our functions
+    fmt,                                   // `status` as a small table
+    fs,                                    // PID file, log file, stale sockets
+    io,
+    os::unix::process::CommandExt,         // Own process group for the copy
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant, SystemTime},
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{sleep, timeout};
+use tracing::{debug, info, warn};
+
+use crate::config::DaemonConfig;
+use crate::store::epoch_ms;
+use crate::PriceTracker;
+
+// Set in the background copy's environment: it's the daemon, not the one launching it
+const CHILD_ENV: &str = "CRABBY_DAEMON_CHILD";
+
+// How long the launcher waits for the daemon to answer (startup may fetch history first)
+const STARTUP_WAIT: Duration = Duration::from_secs(30);
+
+// How long `stop` waits for the daemon to finish shutting down
+const STOP_WAIT: Duration = Duration::from_secs(30);
+
+// How long either side waits for the other's line
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Whether this process is the background copy started by `launch`
+pub fn is_daemon() -> bool {
+    env::var_os(CHILD_ENV).is_some()
+}
+
+// What the daemon answers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "lowercase")]
+pub enum Reply {
+    Status(Status),
+    Stopping { pid: u32 },
+    Error { message: String },
+}
+
+// Answer to "status"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub pid: u32,
+    pub uptime_secs: u64,
+    pub symbols: Vec<String>,   // Tracked, sorted
+    pub updates: u64,           // Received since startup
+    pub prices: Vec<StatusPrice>,
+}
+
+// One latest price in a status answer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusPrice {
+    pub exchange: String,
+    pub symbol: String,
+    pub price: Decimal,
+    pub timestamp_ms: u64,
+}
+
+// "Running (pid 4242), up 2h 3m, 3 symbols, 1520 updates" then one line per price
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Running (pid {}), up {}, {} symbols, {} updates",
+            self.pid,
+            humantime::format_duration(Duration::from_secs(self.uptime_secs)),
+            self.symbols.len(),
+            self.updates
+        )?;
+        let now = epoch_ms(SystemTime::now());
+        for price in &self.prices {
+            let age = Duration::from_secs(now.saturating_sub(price.timestamp_ms) / 1000);
+            write!(f, "\n  {:<12} {:<9} {:>14}  {} ago", price.symbol, price.exchange, price.price, humantime::format_duration(age))?;
+        }
+        Ok(())
+    }
+}
+
+// Start the background copy of this program (same arguments, output appended to the
+// log file) and wait until it answers on the socket. Returns its process id.
+pub async fn launch(config: &DaemonConfig) -> Result<u32, Box<dyn Error>> {
+    if let Ok(Reply::Status(status)) = request(&config.socket, "status").await {
+        return Err(format!("already running (pid {}, socket {})", status.pid, config.socket.display()).into());
+    }
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.log_file)
+        .map_err(|e| format!("{}: {}", config.log_file.display(), e))?;
+    let mut child = Command::new(env::current_exe()?)
+        .args(env::args_os().skip(1))
+        .env(CHILD_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .process_group(0)  // Its own process group: Ctrl-C and a closing terminal don't reach it
+        .spawn()?;
+
+    let started = Instant::now();
+    while started.elapsed() < STARTUP_WAIT {
+        if let Some(status) = child.try_wait()? {
+            return Err(format!("the tracker stopped during startup ({}); see {}", status, config.log_file.display()).into());
+        }
+        if let Ok(Reply::Status(status)) = request(&config.socket, "status").await
+            && status.pid == child.id()
+        {
+            return Ok(child.id());
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    warn!(pid = child.id(), log = %config.log_file.display(), "Started, but not answering on the control socket yet");
+    Ok(child.id())
+}
+
+// Send one command to a running daemon and read its answer
+pub async fn request(socket: &Path, command: &str) -> Result<Reply, Box<dyn Error>> {
+    let stream = UnixStream::connect(socket)
+        .await
+        .map_err(|e| format!("no tracker running at {} ({})", socket.display(), e))?;
+    let (read, mut write) = stream.into_split();
+    write.write_all(format!("{}\n", command).as_bytes()).await?;
+    let mut line = String::new();
+    timeout(REQUEST_TIMEOUT, BufReader::new(read).read_line(&mut line))
+        .await
+        .map_err(|_| format!("no answer from {}", socket.display()))??;
+    Ok(serde_json::from_str(&line)?)
+}
+
+// Ask a running daemon to stop and wait until it has finished (its socket is gone).
+// Returns its process id.
+pub async fn stop(socket: &Path) -> Result<u32, Box<dyn Error>> {
+    let pid = match request(socket, "stop").await? {
+        Reply::Stopping { pid } => pid,
+        Reply::Error { message } => return Err(message.into()),
+        Reply::Status(_) => return Err("unexpected answer to stop".into()),
+    };
+    let started = Instant::now();
+    while socket.exists() {
+        if started.elapsed() > STOP_WAIT {
+            return Err(format!("pid {} is still shutting down after {}", pid, humantime::format_duration(STOP_WAIT)).into());
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    Ok(pid)
+}
+
+// The daemon's side: the PID file and the listening socket, both removed on drop
+pub struct Daemon {
+    listener: UnixListener,
+    socket: PathBuf,
+    pid_file: PathBuf,
+    started: Instant,
+}
+
+impl Daemon {
+    // Write the PID file and listen on the socket. A socket left behind by a daemon that
+    // crashed is replaced; one that still answers means another daemon is running.
+    pub async fn start(config: &DaemonConfig) -> Result<Self, Box<dyn Error>> {
+        if config.socket.exists() {
+            if UnixStream::connect(&config.socket).await.is_ok() {
+                return Err(format!("another tracker is already listening on {}", config.socket.display()).into());
+            }
+            fs::remove_file(&config.socket)?;
+        }
+        let listener = UnixListener::bind(&config.socket).map_err(|e| format!("{}: {}", config.socket.display(), e))?;
+        fs::write(&config.pid_file, format!("{}\n", std::process::id())).map_err(|e| format!("{}: {}", config.pid_file.display(), e))?;
+        info!(
+            pid = std::process::id(),
+            socket = %config.socket.display(),
+            pid_file = %config.pid_file.display(),
+            "Running in the background"
+        );
+        Ok(Self { listener, socket: config.socket.clone(), pid_file: config.pid_file.clone(), started: Instant::now() })
+    }
+
+    // Answer requests until one says "stop" or a SIGTERM arrives
+    pub async fn serve(&self, tracker: &PriceTracker) {
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => Some(terminate),
+            Err(e) => {
+                warn!(error = %e, "Can't listen for SIGTERM; use `stop` to shut down");
+                None
+            }
+        };
+        loop {
+            let stream = tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!(error = %e, "Control socket accept failed");
+                        continue;
+                    }
+                },
+                Some(()) = async { terminate.as_mut()?.recv().await } => {
+                    info!("SIGTERM received, shutting down");
+                    return;
+                }
+            };
+            // One connection at a time: requests are tiny, and a client that never sends
+            // its line only holds the others up until the timeout
+            match timeout(REQUEST_TIMEOUT, self.answer(stream, tracker)).await {
+                Ok(Ok(true)) => return,
+                Ok(Ok(false)) => {}
+                Ok(Err(e)) => debug!(error = %e, "Control connection failed"),
+                Err(_) => debug!("Control connection timed out"),
+            }
+        }
+    }
+
+    // Read one command and answer it; true means "stop"
+    async fn answer(&self, stream: UnixStream, tracker: &PriceTracker) -> io::Result<bool> {
+        let (read, mut write) = stream.into_split();
+        let mut line = String::new();
+        BufReader::new(read).read_line(&mut line).await?;
+        let pid = std::process::id();
+        let (reply, stop) = match line.trim() {
+            "status" => (Reply::Status(self.status(tracker).await), false),
+            "stop" => {
+                info!("Stop requested over the control socket, shutting down");
+                (Reply::Stopping { pid }, true)
+            }
+            other => (Reply::Error { message: format!("unknown command \"{}\" (expected: status, stop)", other) }, false),
+        };
+        let mut json = serde_json::to_vec(&reply)?;
+        json.push(b'\n');
+        write.write_all(&json).await?;
+        Ok(stop)
+    }
+
+    async fn status(&self, tracker: &PriceTracker) -> Status {
+        let store = tracker.store();
+        Status {
+            pid: std::process::id(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            symbols: tracker.symbols(),
+            updates: store.update_count(),
+            prices: store
+                .snapshot()
+                .await
+                .into_iter()
+                .map(|u| StatusPrice {
+                    exchange: u.exchange.to_string(),
+                    symbol: u.symbol,
+                    price: u.price,
+                    timestamp_ms: epoch_ms(u.received_at),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket);    // Err if someone removed it already
+        let _ = fs::remove_file(&self.pid_file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_and_stop_over_the_socket() {
+        let dir = env::temp_dir().join(format!("crabby-daemon-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = DaemonConfig { pid_file: dir.join("t.pid"), log_file: dir.join("t.log"), socket: dir.join("t.sock") };
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let tracker = PriceTracker::new();
+            let daemon = Daemon::start(&config).await.unwrap();
+            assert_eq!(fs::read_to_string(&config.pid_file).unwrap().trim(), std::process::id().to_string());
+            assert!(Daemon::start(&config).await.is_err(), "the socket is taken");
+
+            let client = async {
+                let Reply::Status(status) = request(&config.socket, "status").await.unwrap() else { panic!("not a status") };
+                assert_eq!((status.pid, status.updates), (std::process::id(), 0));
+                assert!(status.to_string().starts_with(&format!("Running (pid {}), up 0s", status.pid)));
+                let Reply::Error { message } = request(&config.socket, "restart").await.unwrap() else { panic!("not an error") };
+                assert!(message.contains("restart"));
+                request(&config.socket, "stop").await.unwrap()
+            };
+            let ((), stopping) = tokio::join!(daemon.serve(&tracker), client);
+            assert_eq!(stopping, Reply::Stopping { pid: std::process::id() });
+
+            drop(daemon);
+            assert!(!config.socket.exists() && !config.pid_file.exists());
+            assert!(request(&config.socket, "status").await.is_err());
+        });
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,524 @@
+// End-of-day report: once a day at a set local time, a summary of the day written to a
+// file, and optionally sent through the webhooks and Telegram.
+//
+//     [daily_report]
+//     enabled = true
+//     at = "18:00"              # Local time, in `utc_offset`
+//     utc_offset = "+01:00"
+//     format = "html"           # text (default), html or json
+//     dir = "reports"           # reports/report-2026-10-15.html
+//     notify = true             # Also send a digest to the webhooks and Telegram
+//
+// A report covers the time since the previous one (or since the tracker started):
+//
+//   - open, high, low and close per symbol and exchange, with the % change
+//   - the biggest movers up and down
+//   - every alert that fired (price rules, scripts, arbitrage, depeg)
+//   - the portfolio's value at the open and the close, when there is a portfolio
+//
+// After a report the next day opens at the closing prices. A report whose time passed
+// while the tracker wasn't running isn't made up.
+
+use std::{
+    collections::BTreeMap,                // Per (symbol, exchange), in order
+    error::Error,                         // Trait to return errors from our functions
+    fmt::{self, Write as _},              // Building the text and HTML
+    fs,                                   // Writing the report
+    path::PathBuf,                        // Where it goes
+    str::FromStr,                         // "18:00"
+    sync::{Arc, Mutex},                   // Alerts recorded by the alert tasks
+    time::{Duration, SystemTime},         // Report times
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::alerts::Alert;
+use crate::dca::{Day, Schedule};
+use crate::notify::Notifier;
+use crate::portfolio::Portfolio;
+use crate::report::{clock, UtcOffset};
+use crate::store::{epoch_ms, PriceStore, PriceUpdate};
+
+// Movers listed each way
+const TOP_MOVERS: usize = 3;
+
+// Alerts kept for one report; beyond that only counted
+const MAX_ALERTS: usize = 1000;
+
+// [daily_report] section of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DailyReportConfig {
+    pub enabled: bool,
+    pub at: TimeOfDay,               // When the report is made, local time
+    pub utc_offset: UtcOffset,       // Time zone of `at` and of the report's date
+    pub format: ReportFormat,
+    pub dir: PathBuf,                // Directory the reports are written to
+    pub notify: bool,                // Also send a digest through the notifiers
+}
+
+impl Default for DailyReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            at: TimeOfDay(18 * 60),
+            utc_offset: UtcOffset::default(),
+            format: ReportFormat::default(),
+            dir: PathBuf::from("reports"),
+            notify: false,
+        }
+    }
+}
+
+// "18:00", as minutes after midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(pub u16);
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        clock(s).map(Self)
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+// What the report file looks like
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Html,
+    Json,
+}
+
+impl ReportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Text => "txt",
+            ReportFormat::Html => "html",
+            ReportFormat::Json => "json",
+        }
+    }
+}
+
+// Fired alerts, recorded for the report. Handed to the alert tasks as one more
+// notifier; cheap to clone.
+#[derive(Clone, Default)]
+pub struct AlertLog {
+    alerts: Arc<Mutex<(Vec<Alert>, usize)>>,   // Kept alerts, and how many fired in all
+}
+
+impl AlertLog {
+    // Everything recorded since the last call, and how many fired in all
+    fn take(&self) -> (Vec<Alert>, usize) {
+        std::mem::take(&mut *self.alerts.lock().unwrap())
+    }
+}
+
+impl Notifier for AlertLog {
+    fn notify(&self, alert: &Alert) {
+        let mut alerts = self.alerts.lock().unwrap();
+        if alerts.0.len() < MAX_ALERTS {
+            alerts.0.push(alert.clone());
+        }
+        alerts.1 += 1;
+    }
+}
+
+// One symbol on one exchange over the report's period
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SymbolDay {
+    pub symbol: String,
+    pub exchange: &'static str,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub change_pct: Option<Decimal>,
+    pub updates: u64,
+    #[serde(skip)]
+    last: Option<PriceUpdate>,   // For valuing the portfolio
+}
+
+// An alert as listed in the report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FiredAlert {
+    pub timestamp_ms: u64,
+    pub rule: String,
+    pub exchange: &'static str,
+    pub symbol: String,
+    pub price: Decimal,
+    pub detail: String,
+}
+
+// The portfolio at the open and now
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PortfolioDay {
+    pub value_open: Decimal,
+    pub value_close: Decimal,
+    pub change: Decimal,
+    pub change_pct: Option<Decimal>,
+}
+
+// One day's report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyReport {
+    pub date: String,                 // Local date, e.g. "2026-10-15"
+    pub from_ms: u64,
+    pub to_ms: u64,
+    pub symbols: Vec<SymbolDay>,
+    pub gainers: Vec<(String, Decimal)>,   // Biggest % moves up...
+    pub losers: Vec<(String, Decimal)>,    // ...and down
+    pub alerts: Vec<FiredAlert>,
+    pub alerts_fired: usize,               // Including any beyond the kept ones
+    pub portfolio: Option<PortfolioDay>,
+}
+
+// The day so far, from every update
+pub struct DayTracker {
+    started: SystemTime,
+    days: BTreeMap<(String, &'static str), SymbolDay>,
+}
+
+impl DayTracker {
+    pub fn new(started: SystemTime) -> Self {
+        Self { started, days: BTreeMap::new() }
+    }
+
+    pub fn record(&mut self, update: &PriceUpdate) {
+        let day = self.days.entry((update.symbol.clone(), update.exchange)).or_insert_with(|| SymbolDay {
+            symbol: update.symbol.clone(),
+            exchange: update.exchange,
+            open: update.price,
+            high: update.price,
+            low: update.price,
+            close: update.price,
+            change_pct: None,
+            updates: 0,
+            last: None,
+        });
+        day.high = day.high.max(update.price);
+        day.low = day.low.min(update.price);
+        day.close = update.price;
+        day.updates += 1;
+        day.last = Some(update.clone());
+    }
+
+    // The report up to `now`; the next day then opens at the closing prices
+    pub fn report(&mut self, now: SystemTime, offset: UtcOffset, alerts: (Vec<Alert>, usize), portfolio: Option<&Portfolio>) -> DailyReport {
+        let mut symbols: Vec<SymbolDay> = self.days.values().cloned().collect();
+        for day in &mut symbols {
+            day.change_pct = (!day.open.is_zero()).then(|| ((day.close - day.open) / day.open * Decimal::ONE_HUNDRED).round_dp(2));
+        }
+        let mut moves: Vec<(String, Decimal)> = symbols
+            .iter()
+            .filter_map(|d| d.change_pct.map(|c| (format!("{} ({})", d.symbol, d.exchange), c)))
+            .collect();
+        moves.sort_by_key(|(_, c)| std::cmp::Reverse(*c));  // Biggest rise first
+        let gainers = moves.iter().filter(|(_, c)| c.is_sign_positive() && !c.is_zero()).take(TOP_MOVERS).cloned().collect();
+        let losers = moves.iter().rev().filter(|(_, c)| c.is_sign_negative()).take(TOP_MOVERS).cloned().collect();
+
+        // The holdings at each symbol's latest opening and closing price
+        let portfolio = portfolio.map(|p| {
+            let at = |open: bool| {
+                let symbols = &symbols;
+                move |symbol: &str| {
+                    let day = symbols.iter().filter(|d| d.symbol == symbol).max_by_key(|d| d.last.as_ref().map(|u| u.received_at))?;
+                    let last = day.last.clone()?;
+                    Some(PriceUpdate { price: if open { day.open } else { day.close }, ..last })
+                }
+            };
+            let (value_open, value_close) = (p.value_with(at(true)).total_value, p.value_with(at(false)).total_value);
+            let change = value_close - value_open;
+            PortfolioDay {
+                value_open: value_open.round_dp(2),
+                value_close: value_close.round_dp(2),
+                change: change.round_dp(2),
+                change_pct: (!value_open.is_zero()).then(|| (change / value_open * Decimal::ONE_HUNDRED).round_dp(2)),
+            }
+        });
+
+        let (alerts, alerts_fired) = alerts;
+        let report = DailyReport {
+            date: offset.date(now.checked_sub(Duration::from_secs(1)).unwrap_or(now)),  // A midnight report is the day before's
+            from_ms: epoch_ms(self.started),
+            to_ms: epoch_ms(now),
+            symbols,
+            gainers,
+            losers,
+            alerts: alerts
+                .into_iter()
+                .map(|a| FiredAlert { timestamp_ms: epoch_ms(a.fired_at), rule: a.rule, exchange: a.exchange, symbol: a.symbol, price: a.price, detail: a.detail })
+                .collect(),
+            alerts_fired,
+            portfolio,
+        };
+
+        // The next day opens where this one closed
+        self.started = now;
+        for day in self.days.values_mut() {
+            (day.open, day.high, day.low, day.updates) = (day.close, day.close, day.close, 0);
+        }
+        report
+    }
+}
+
+impl DailyReport {
+    // The file contents in `format`
+    pub fn render(&self, format: ReportFormat) -> Result<String, Box<dyn Error>> {
+        Ok(match format {
+            ReportFormat::Text => self.to_string(),
+            ReportFormat::Html => self.html(),
+            ReportFormat::Json => serde_json::to_string_pretty(self)? + "\n",
+        })
+    }
+
+    // A few lines for the notifiers: the movers, the portfolio and the alert count
+    pub fn digest(&self) -> String {
+        let mut text = format!("📊 Daily report {}", self.date);
+        let movers = |moves: &[(String, Decimal)]| moves.iter().map(|(s, c)| format!("{} {:+}%", s, c)).collect::<Vec<_>>().join(", ");
+        if !self.gainers.is_empty() {
+            let _ = write!(text, "\nUp: {}", movers(&self.gainers));
+        }
+        if !self.losers.is_empty() {
+            let _ = write!(text, "\nDown: {}", movers(&self.losers));
+        }
+        if let Some(p) = &self.portfolio {
+            let _ = write!(text, "\nPortfolio: {}", portfolio_line(p));
+        }
+        let _ = write!(text, "\nAlerts fired: {}", self.alerts_fired);
+        text
+    }
+
+    fn html(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Daily report {date}</title></head><body>\n<h1>Daily report {date}</h1>\n",
+            date = escape(&self.date)
+        );
+        let _ = writeln!(html, "<table>\n<tr><th>Symbol</th><th>Exchange</th><th>Open</th><th>High</th><th>Low</th><th>Close</th><th>Change</th></tr>");
+        for d in &self.symbols {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&d.symbol), d.exchange, d.open, d.high, d.low, d.close, change(d.change_pct)
+            );
+        }
+        let _ = writeln!(html, "</table>");
+        for (title, moves) in [("Biggest gainers", &self.gainers), ("Biggest losers", &self.losers)] {
+            if !moves.is_empty() {
+                let items: String = moves.iter().map(|(s, c)| format!("<li>{} {:+}%</li>", escape(s), c)).collect();
+                let _ = writeln!(html, "<h2>{}</h2>\n<ul>{}</ul>", title, items);
+            }
+        }
+        if let Some(p) = &self.portfolio {
+            let _ = writeln!(html, "<h2>Portfolio</h2>\n<p>{}</p>", escape(&portfolio_line(p)));
+        }
+        let _ = writeln!(html, "<h2>Alerts ({})</h2>\n<ul>", self.alerts_fired);
+        for a in &self.alerts {
+            let _ = writeln!(html, "<li>{}</li>", escape(&alert_line(a)));
+        }
+        let _ = writeln!(html, "</ul>\n</body></html>");
+        html
+    }
+}
+
+// The text format
+impl fmt::Display for DailyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let since = humantime::format_rfc3339_seconds(std::time::UNIX_EPOCH + Duration::from_millis(self.from_ms));
+        writeln!(f, "Daily report {} (since {})", self.date, since)?;
+        for d in &self.symbols {
+            writeln!(
+                f,
+                "  {:<10} {:<12} open {}  high {}  low {}  close {}  {}",
+                d.exchange, d.symbol, d.open, d.high, d.low, d.close, change(d.change_pct)
+            )?;
+        }
+        let movers = |moves: &[(String, Decimal)]| moves.iter().map(|(s, c)| format!("{} {:+}%", s, c)).collect::<Vec<_>>().join(", ");
+        if !self.gainers.is_empty() || !self.losers.is_empty() {
+            writeln!(f, "Biggest movers: up {} / down {}", or_none(movers(&self.gainers)), or_none(movers(&self.losers)))?;
+        }
+        if let Some(p) = &self.portfolio {
+            writeln!(f, "Portfolio: {}", portfolio_line(p))?;
+        }
+        writeln!(f, "Alerts fired: {}", self.alerts_fired)?;
+        for a in &self.alerts {
+            writeln!(f, "  {}", alert_line(a))?;
+        }
+        Ok(())
+    }
+}
+
+fn change(pct: Option<Decimal>) -> String {
+    pct.map_or("—".to_string(), |c| format!("{:+.2}%", c))
+}
+
+fn or_none(text: String) -> String {
+    if text.is_empty() { "none".to_string() } else { text }
+}
+
+// "10000.00 → 10250.00 (+250.00, +2.50%)"
+fn portfolio_line(p: &PortfolioDay) -> String {
+    format!("{} → {} ({:+}, {})", p.value_open, p.value_close, p.change, change(p.change_pct))
+}
+
+fn alert_line(a: &FiredAlert) -> String {
+    let at = humantime::format_rfc3339_seconds(std::time::UNIX_EPOCH + Duration::from_millis(a.timestamp_ms));
+    format!("{} {} on {} at {}: {}", at, a.rule, a.exchange, a.price, a.detail)
+}
+
+// Text made safe to put in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Makes the report at the configured time every day
+pub struct DailyReporter {
+    config: DailyReportConfig,
+    alerts: AlertLog,
+    portfolio: Option<Arc<Portfolio>>,
+    notifiers: Vec<Box<dyn Notifier>>,   // Only used with `notify`
+}
+
+impl DailyReporter {
+    pub fn new(config: &DailyReportConfig, alerts: AlertLog, portfolio: Option<Arc<Portfolio>>, notifiers: Vec<Box<dyn Notifier>>) -> Self {
+        Self { config: config.clone(), alerts, portfolio, notifiers }
+    }
+
+    // The time of the next report after `after`
+    pub fn next_after(&self, after: SystemTime) -> SystemTime {
+        Schedule { day: Day::Daily, minute: self.config.at.0 }.next_after(after, self.config.utc_offset)
+    }
+
+    // Write the day's report, and send the digest when asked to. Returns where it went.
+    pub fn publish(&self, tracker: &mut DayTracker, now: SystemTime) -> Result<PathBuf, Box<dyn Error>> {
+        let report = tracker.report(now, self.config.utc_offset, self.alerts.take(), self.portfolio.as_deref());
+        let path = self.config.dir.join(format!("report-{}.{}", report.date, self.config.format.extension()));
+        fs::create_dir_all(&self.config.dir)?;
+        fs::write(&path, report.render(self.config.format)?)?;
+        if self.config.notify {
+            let digest = report.digest();
+            for notifier in &self.notifiers {
+                notifier.message(&digest);
+            }
+        }
+        Ok(path)
+    }
+}
+
+// Follow every update in `store` and publish a report at the set time each day
+pub fn spawn_daily_report(reporter: DailyReporter, store: &PriceStore) -> tokio::task::JoinHandle<()> {
+    let mut rx = store.subscribe_updates();
+    let metrics = store.metrics().clone();  // Counts what the task misses
+    tokio::spawn(async move {
+        let mut tracker = DayTracker::new(SystemTime::now());
+        let mut next = reporter.next_after(SystemTime::now());
+        info!(at = %reporter.config.at, next = %humantime::format_rfc3339_seconds(next), dir = %reporter.config.dir.display(), "Daily report");
+        let mut clock = tokio::time::interval(Duration::from_secs(1));  // Wall-clock check, so a suspend can't skew it
+        loop {
+            tokio::select! {
+                received = rx.recv() => match received {
+                    Ok(update) => tracker.record(&update),
+                    Err(RecvError::Lagged(n)) => {
+                        warn!(skipped = n, "Daily report fell behind");
+                        metrics.record_dropped_events("daily_report", n);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = clock.tick() => {
+                    let now = SystemTime::now();
+                    if now < next {
+                        continue;
+                    }
+                    match reporter.publish(&mut tracker, now) {
+                        Ok(path) => info!(path = %path.display(), "Wrote the daily report"),
+                        Err(e) => warn!(dir = %reporter.config.dir.display(), error = %e, "Couldn't write the daily report"),
+                    }
+                    next = reporter.next_after(now);
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portfolio::Holding;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn report_sums_up_the_day() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_704_240_000);  // 2024-01-03 00:00 UTC
+        let tick = |symbol: &str, price: &str| PriceUpdate {
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: start,
+        };
+        let mut tracker = DayTracker::new(start);
+        for (symbol, price) in [("BTC-USD", "100"), ("ETH-USD", "10"), ("BTC-USD", "120"), ("BTC-USD", "90"), ("BTC-USD", "110"), ("ETH-USD", "9.5")] {
+            tracker.record(&tick(symbol, price));
+        }
+        let log = AlertLog::default();
+        log.notify(&Alert {
+            rule: "ETH-USD below 9.6".to_string(),
+            exchange: "coinbase",
+            symbol: "ETH-USD".to_string(),
+            price: "9.5".parse().unwrap(),
+            detail: "price 9.5 < 9.6".to_string(),
+            fired_at: start,
+            desktop: false,
+            trade: None,
+        });
+        let portfolio = Portfolio::new(vec![Holding { symbol: "BTC-USD".to_string(), quantity: Decimal::from(2), cost_basis: Decimal::from(150) }]);
+
+        // Made at 18:00 at +01:00, so it's that day's
+        let now = start + Duration::from_secs(17 * 3600);
+        let report = tracker.report(now, "+01:00".parse().unwrap(), log.take(), Some(&portfolio));
+        assert_eq!(report.date, "2024-01-03");
+        let btc = &report.symbols[0];
+        assert_eq!((btc.open, btc.high, btc.low, btc.close, btc.change_pct), (d("100"), d("120"), d("90"), d("110"), Some(d("10"))));
+        assert_eq!((report.gainers.clone(), report.losers.clone()), (vec![("BTC-USD (coinbase)".to_string(), d("10"))], vec![("ETH-USD (coinbase)".to_string(), d("-5"))]));
+        assert_eq!(report.portfolio.as_ref().map(|p| (p.value_open, p.value_close, p.change_pct)), Some((d("200"), d("220"), Some(d("10")))));
+
+        let text = report.render(ReportFormat::Text).unwrap();
+        assert!(text.contains("coinbase   BTC-USD      open 100  high 120  low 90  close 110  +10.00%"), "{}", text);
+        assert!(text.contains("Portfolio: 200 → 220 (+20, +10.00%)") && text.contains("Alerts fired: 1"), "{}", text);
+        assert!(report.render(ReportFormat::Html).unwrap().contains("ETH-USD below 9.6 on coinbase at 9.5: price 9.5 &lt; 9.6"));
+        let json: serde_json::Value = serde_json::from_str(&report.render(ReportFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["symbols"][1]["change_pct"], "-5.00");
+        assert_eq!(report.digest(), "📊 Daily report 2024-01-03\nUp: BTC-USD (coinbase) +10.00%\nDown: ETH-USD (coinbase) -5.00%\nPortfolio: 200 → 220 (+20, +10.00%)\nAlerts fired: 1");
+
+        // The next day opens at the close, with the alerts cleared
+        let next = tracker.report(now + Duration::from_secs(86_400), UtcOffset::default(), log.take(), None);
+        assert_eq!((next.symbols[0].open, next.symbols[0].change_pct, next.alerts_fired), (d("110"), Some(Decimal::ZERO), 0));
+    }
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+}
@@ -0,0 +1,463 @@
+// Interactive terminal dashboard (ratatui): a live table of every tracked symbol
+// with last price, 24h change, a small sparkline of recent prices and, when
+// configured, technical indicators (RSI, EMA...). Watchlists get one line each under
+// the table: index, average 24h change and top mover.
+//
+// Keys:
+//   q / Esc      quit
+//   s            cycle the sort column (symbol, price, 24h change)
+//   r            reverse the sort order
+//   /            type a filter (Enter to keep it, Esc to clear)
+//   w            show one watchlist's symbols, then the next, then all again
+//   p / Space    pause / resume updates
+
+use std::{
+    collections::{BTreeMap, VecDeque},  // Rows by (symbol, exchange); recent prices per row
+    io,                                 // Terminal errors
+    time::Duration,                     // Input polling interval
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+use crate::fx::FxRates;
+use crate::indicators::Indicators;
+use crate::metrics::Metrics;
+use crate::store::{PriceStore, PriceUpdate};
+use crate::watchlist::Watchlists;
+
+// How many recent prices each sparkline shows
+const SPARKLINE_LEN: usize = 30;
+// Characters used to draw the sparkline, lowest to highest
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// Column the table is sorted by
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortBy {
+    Symbol,
+    Price,
+    Change,
+}
+
+impl SortBy {
+    fn next(self) -> Self {
+        match self {
+            SortBy::Symbol => SortBy::Price,
+            SortBy::Price => SortBy::Change,
+            SortBy::Change => SortBy::Symbol,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortBy::Symbol => "symbol",
+            SortBy::Price => "price",
+            SortBy::Change => "24h change",
+        }
+    }
+}
+
+// One line of the table
+struct SymbolRow {
+    latest: PriceUpdate,
+    history: VecDeque<f64>,  // Last SPARKLINE_LEN prices
+}
+
+impl SymbolRow {
+    fn price(&self) -> f64 {
+        self.latest.price.to_f64().unwrap_or(f64::NAN)
+    }
+
+    // Percent change versus the 24h open, when the exchange reports one
+    fn change_24h(&self) -> Option<f64> {
+        let open = self.latest.open_24h?;
+        (!open.is_zero()).then(|| (self.latest.price - open) / open * Decimal::ONE_HUNDRED)?.to_f64()
+    }
+
+    // Text sparkline scaled between the lowest and highest recent price
+    fn sparkline(&self) -> String {
+        let (lo, hi) = self
+            .history
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| (lo.min(*p), hi.max(*p)));
+        let range = hi - lo;
+        self.history
+            .iter()
+            .map(|p| {
+                let level = if range > 0.0 { ((p - lo) / range * 7.0).round() as usize } else { 3 };
+                SPARK_CHARS[level.min(7)]
+            })
+            .collect()
+    }
+}
+
+// Dashboard state
+struct App {
+    rows: BTreeMap<(String, &'static str), SymbolRow>,
+    updates: broadcast::Receiver<PriceUpdate>,
+    metrics: Metrics,                // Counts updates skipped while behind
+    sort: SortBy,
+    descending: bool,
+    filter: String,
+    editing_filter: bool,
+    paused: bool,
+    indicators: Option<Indicators>,  // Extra column, when any are configured
+    fx: FxRates,                     // Display currency rate, if any
+    watchlists: Watchlists,          // Summed up under the table
+    watchlist: Option<usize>,        // Only this one's symbols in the table
+}
+
+impl App {
+    fn new(store: &PriceStore, indicators: Option<Indicators>, watchlists: Watchlists) -> Self {
+        let mut app = App {
+            rows: BTreeMap::new(),
+            updates: store.subscribe_updates(),
+            metrics: store.metrics().clone(),
+            sort: SortBy::Symbol,
+            descending: false,
+            filter: String::new(),
+            editing_filter: false,
+            paused: false,
+            indicators: indicators.filter(|i| !i.is_empty()),
+            fx: store.fx().clone(),
+            watchlists,
+            watchlist: None,
+        };
+        // Start from whatever is already known so the table isn't empty
+        for update in store.blocking_snapshot() {
+            app.apply(update);
+        }
+        app
+    }
+
+    // Fold one update into its row
+    fn apply(&mut self, update: PriceUpdate) {
+        let key = (update.symbol.clone(), update.exchange);
+        let row = self.rows.entry(key).or_insert_with(|| SymbolRow {
+            latest: update.clone(),
+            history: VecDeque::with_capacity(SPARKLINE_LEN),
+        });
+        if let Some(price) = update.price.to_f64() {
+            if row.history.len() == SPARKLINE_LEN {
+                row.history.pop_front();
+            }
+            row.history.push_back(price);
+        }
+        row.latest = update;
+    }
+
+    // Pull in everything that arrived since the last frame (unless paused)
+    fn drain_updates(&mut self) {
+        if self.paused {
+            return;
+        }
+        loop {
+            match self.updates.try_recv() {
+                Ok(update) => self.apply(update),
+                Err(TryRecvError::Lagged(n)) => self.metrics.record_dropped_events("dashboard", n),  // Too slow; skip to the newest
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+    }
+
+    // The watchlist picked with `w`, if any
+    fn current_watchlist(&self) -> Option<&str> {
+        self.watchlists.names().nth(self.watchlist?)
+    }
+
+    // Rows that match the filter and the watchlist, in the chosen order
+    fn visible_rows(&self) -> Vec<&SymbolRow> {
+        let filter = self.filter.to_uppercase();
+        let members = self.current_watchlist().and_then(|name| self.watchlists.symbols(name));
+        let mut rows: Vec<&SymbolRow> = self
+            .rows
+            .values()
+            .filter(|r| filter.is_empty() || r.latest.symbol.to_uppercase().contains(&filter))
+            .filter(|r| members.is_none_or(|m| m.contains(&r.latest.symbol)))
+            .collect();
+        match self.sort {
+            SortBy::Symbol => {}  // BTreeMap order is already by symbol
+            SortBy::Price => rows.sort_by(|a, b| a.price().total_cmp(&b.price())),
+            SortBy::Change => rows.sort_by(|a, b| {
+                let (a, b) = (a.change_24h().unwrap_or(f64::NAN), b.change_24h().unwrap_or(f64::NAN));
+                a.total_cmp(&b)
+            }),
+        }
+        if self.descending {
+            rows.reverse();
+        }
+        rows
+    }
+
+    // Handle one key press; returns false when the user wants to quit
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        if self.editing_filter {
+            match code {
+                KeyCode::Enter => self.editing_filter = false,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.editing_filter = false;
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            return true;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('s') => self.sort = self.sort.next(),
+            KeyCode::Char('r') => self.descending = !self.descending,
+            KeyCode::Char('/') => self.editing_filter = true,
+            KeyCode::Char('w') if !self.watchlists.is_empty() => {
+                let next = self.watchlist.map_or(0, |i| i + 1);
+                self.watchlist = (next < self.watchlists.names().count()).then_some(next);
+            }
+            KeyCode::Char('p') | KeyCode::Char(' ') => self.paused = !self.paused,
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        // Room for one line per watchlist, inside a border
+        let lists = self.watchlists.names().count();
+        let lists_height = if lists == 0 { 0 } else { lists as u16 + 2 };
+        let [table_area, lists_area, status_area] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(lists_height), Constraint::Length(1)]).areas(frame.area());
+
+        let mut columns = vec!["Symbol", "Exchange", "Last", "24h", "Recent"];
+        if self.indicators.is_some() {
+            columns.push("Indicators");
+        }
+        let header = Row::new(columns).style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = self.visible_rows().into_iter().map(|r| {
+            let (change_text, color) = match r.change_24h() {
+                Some(c) if c >= 0.0 => (format!("{:+.2}%", c), Color::Green),
+                Some(c) => (format!("{:+.2}%", c), Color::Red),
+                None => ("—".to_string(), Color::Reset),
+            };
+            let mut cells = vec![
+                Cell::from(r.latest.symbol.clone()),
+                Cell::from(r.latest.exchange),
+                Cell::from(match self.fx.convert(r.latest.price, &r.latest.symbol) {
+                    Some(converted) => format!("${} {}", r.latest.price, converted),
+                    None => format!("${}", r.latest.price),
+                }),
+                Cell::from(change_text).style(Style::default().fg(color)),
+                Cell::from(r.sparkline()).style(Style::default().fg(Color::Cyan)),
+            ];
+            if let Some(indicators) = &self.indicators {
+                let readings = indicators.readings(r.latest.exchange, &r.latest.symbol);
+                let text: Vec<String> = readings.iter().map(|reading| reading.to_string()).collect();
+                cells.push(Cell::from(text.join("  ")));
+            }
+            Row::new(cells)
+        });
+        let widths = [
+            Constraint::Length(12),
+            Constraint::Length(10),
+            Constraint::Length(if self.fx.current().is_some() { 34 } else { 16 }),  // Room for "≈ 58500.45 EUR"
+            Constraint::Length(9),
+            Constraint::Length(SPARKLINE_LEN as u16),
+            Constraint::Fill(1),  // Indicators, when shown
+        ];
+        let title = if self.paused { " CrabbyCryptoTracker (paused) " } else { " CrabbyCryptoTracker " };
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(table, table_area);
+
+        // The watchlists at the rows' prices, so pausing freezes them too
+        if lists > 0 {
+            let summaries = self.watchlists.summaries_with(|symbol| {
+                self.rows.values().filter(|r| r.latest.symbol == symbol).max_by_key(|r| r.latest.received_at).map(|r| r.latest.clone())
+            });
+            let lines: Vec<Line> = summaries.iter().map(|s| Line::from(s.to_string())).collect();
+            let block = Block::default().borders(Borders::ALL).title(" Watchlists ");
+            frame.render_widget(Paragraph::new(lines).block(block), lists_area);
+        }
+
+        let filter = if self.editing_filter {
+            format!("filter: {}▏", self.filter)
+        } else if !self.filter.is_empty() {
+            format!("filter: {}", self.filter)
+        } else {
+            String::new()
+        };
+        let watchlist = self.current_watchlist().map(|name| format!("watchlist: {}  ", name)).unwrap_or_default();
+        let status = format!(
+            " sort: {}{}  {}{}   [s]ort [r]everse [/]filter [w]atchlist [p]ause [q]uit",
+            self.sort.label(),
+            if self.descending { " ↓" } else { " ↑" },
+            watchlist,
+            filter
+        );
+        frame.render_widget(Paragraph::new(Line::from(status)), status_area);
+    }
+}
+
+// Run the dashboard until the user quits. This blocks the calling thread, so call it
+// via `tokio::task::spawn_blocking` when inside an async runtime.
+pub fn run(store: PriceStore, indicators: Option<Indicators>, watchlists: Watchlists) -> io::Result<()> {
+    let mut terminal = ratatui::init();  // Raw mode + alternate screen
+    let result = event_loop(&mut terminal, App::new(&store, indicators, watchlists));
+    ratatui::restore();                  // Always give the terminal back, even on error
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, mut app: App) -> io::Result<()> {
+    loop {
+        app.drain_updates();
+        terminal.draw(|frame| app.draw(frame))?;
+
+        // Wait briefly for input; this also sets the refresh rate
+        if event::poll(Duration::from_millis(250))?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && !app.handle_key(key.code)
+        {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::SystemTime;
+
+    use ratatui::{backend::TestBackend, Terminal};
+
+    use crate::watchlist::WatchlistConfig;
+
+    fn tick(symbol: &str, price: &str, open: Option<&str>) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            price: price.parse().unwrap(),
+            open_24h: open.map(|o| o.parse().unwrap()),
+            size: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    // A store with three prices, and an App started from it
+    fn app(watchlists: Watchlists) -> (PriceStore, tokio::runtime::Runtime, App) {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let store = PriceStore::new();
+        runtime.block_on(async {
+            store.update(tick("BTC-USD", "65000", Some("60000"))).await;
+            store.update(tick("ETH-USD", "3200", Some("3300"))).await;
+            store.update(tick("SOL-USD", "150", None)).await;
+        });
+        let app = App::new(&store, None, watchlists);
+        (store, runtime, app)
+    }
+
+    fn symbols(app: &App) -> Vec<&str> {
+        app.visible_rows().iter().map(|r| r.latest.symbol.as_str()).collect()
+    }
+
+    // The frame as text, one string per line
+    fn screen(app: &App) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(110, 12)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer.content().chunks(110).map(|line| line.iter().map(|c| c.symbol()).collect::<String>().trim_end().to_string()).collect()
+    }
+
+    #[test]
+    fn sort_reverse_and_filter_keys() {
+        let (_store, _runtime, mut app) = app(Watchlists::default());
+        assert_eq!(symbols(&app), ["BTC-USD", "ETH-USD", "SOL-USD"]);
+        assert!(app.handle_key(KeyCode::Char('s')));
+        assert_eq!(symbols(&app), ["SOL-USD", "ETH-USD", "BTC-USD"]);
+        app.handle_key(KeyCode::Char('s'));  // 24h change; SOL has none, so it goes last
+        assert_eq!(symbols(&app), ["ETH-USD", "BTC-USD", "SOL-USD"]);
+        app.handle_key(KeyCode::Char('r'));
+        assert_eq!(symbols(&app), ["SOL-USD", "BTC-USD", "ETH-USD"]);
+        app.handle_key(KeyCode::Char('s'));
+        assert_eq!(symbols(&app), ["SOL-USD", "ETH-USD", "BTC-USD"]);
+
+        // Keys typed after `/` go to the filter, `q` included
+        for key in [KeyCode::Char('/'), KeyCode::Char('e'), KeyCode::Char('q'), KeyCode::Backspace, KeyCode::Char('t')] {
+            assert!(app.handle_key(key));
+        }
+        assert_eq!(symbols(&app), ["ETH-USD"]);
+        app.handle_key(KeyCode::Enter);
+        assert_eq!((app.editing_filter, app.filter.as_str()), (false, "et"));
+        app.handle_key(KeyCode::Char('/'));
+        app.handle_key(KeyCode::Esc);
+        assert_eq!(symbols(&app).len(), 3);
+
+        assert!(!app.handle_key(KeyCode::Char('q')));
+        assert!(!app.handle_key(KeyCode::Esc));
+    }
+
+    #[test]
+    fn pausing_holds_back_updates() {
+        let (store, runtime, mut app) = app(Watchlists::default());
+        app.handle_key(KeyCode::Char(' '));
+        runtime.block_on(store.update(tick("BTC-USD", "66000", Some("60000"))));
+        runtime.block_on(store.update(tick("DOGE-USD", "0.1", None)));
+        app.drain_updates();
+        assert_eq!(symbols(&app).len(), 3);
+        assert!(screen(&app)[0].contains("CrabbyCryptoTracker (paused)"));
+
+        app.handle_key(KeyCode::Char('p'));
+        app.drain_updates();
+        assert_eq!(symbols(&app), ["BTC-USD", "DOGE-USD", "ETH-USD", "SOL-USD"]);
+        let btc = &app.rows[&("BTC-USD".to_string(), "coinbase")];
+        assert_eq!((btc.latest.price, btc.history.len()), (Decimal::from(66_000), 2));
+    }
+
+    #[test]
+    fn draws_the_table_and_the_status_line() {
+        let (_store, _runtime, mut app) = app(Watchlists::default());
+        let lines = screen(&app);
+        let row = |symbol: &str| lines.iter().find(|l| l.contains(symbol)).cloned().unwrap_or_default();
+        assert!(lines[1].contains("Symbol") && lines[1].contains("Recent") && !lines[1].contains("Indicators"), "{:?}", lines);
+        assert!(row("BTC-USD").contains("coinbase") && row("BTC-USD").contains("$65000") && row("BTC-USD").contains("+8.33%"));
+        assert!(row("ETH-USD").contains("-3.03%"));
+        assert!(row("SOL-USD").contains("—"));
+        assert!(lines[11].starts_with(" sort: symbol ↑  "), "{}", lines[11]);
+
+        app.handle_key(KeyCode::Char('r'));
+        app.handle_key(KeyCode::Char('/'));
+        app.handle_key(KeyCode::Char('s'));
+        assert!(screen(&app)[11].starts_with(" sort: symbol ↓  filter: s▏"), "{}", screen(&app)[11]);
+    }
+
+    #[test]
+    fn watchlists_are_summed_up_and_picked_with_w() {
+        let majors = WatchlistConfig { symbols: vec!["btc-usd".to_string(), "eth-usd".to_string()], ..WatchlistConfig::default() };
+        let alts = WatchlistConfig { symbols: vec!["SOL-USD".to_string()], ..WatchlistConfig::default() };
+        let (_store, _runtime, mut app) = app(Watchlists::new(&BTreeMap::from([("majors".to_string(), majors), ("alts".to_string(), alts)])));
+
+        let lines = screen(&app);
+        assert!(lines.iter().any(|l| l.contains("Watchlists")));
+        assert!(lines.iter().any(|l| l.contains("majors (equal, 2/2): index 100.00")), "{:?}", lines);
+        assert!(lines.iter().any(|l| l.contains("alts (equal, 1/1)")));
+
+        app.handle_key(KeyCode::Char('w'));  // Names are in sorted order: alts first
+        assert_eq!(symbols(&app), ["SOL-USD"]);
+        app.handle_key(KeyCode::Char('w'));
+        assert_eq!(symbols(&app), ["BTC-USD", "ETH-USD"]);
+        assert!(screen(&app)[11].contains("watchlist: majors"));
+        app.handle_key(KeyCode::Char('w'));  // Back to everything
+        assert_eq!(symbols(&app).len(), 3);
+    }
+}
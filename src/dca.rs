@@ -0,0 +1,406 @@
+// Recurring purchases (dollar-cost averaging): a fixed amount of a symbol bought on a
+// schedule, e.g. $50 of BTC-USD every Monday at 09:00.
+//
+//     [dca]
+//     utc_offset = "+01:00"          # Schedules are in this time zone (default UTC)
+//     audit_log = "dca.ndjson"       # Every execution appended here, one JSON object per line
+//     live = false                   # true: real orders through --enable-trading
+//
+//     [[dca.plans]]
+//     symbol = "BTC-USD"
+//     amount = 50                    # In the quote currency (USD here)
+//     every = "monday 09:00"         # or "mon 09:00", "daily 18:30", "monthly 1 09:00"
+//
+// Simulated (the default), a purchase fills at the latest price and is added to the
+// portfolio's holdings, and written back to `portfolio_file` when they came from one.
+// Live, it's a market order for `amount` of the quote currency, placed by the trader
+// with its dry run and size limits (trading.rs); the holdings then follow the account.
+//
+// Runs missed while the tracker wasn't running are skipped, not caught up on. A run is
+// skipped too while its symbol has no price yet or the feed quoting it is stale. Every
+// run, whatever happened, is logged and written to the audit log.
+
+use std::{
+    fmt,                                  // Schedules and executions, printed
+    fs::OpenOptions,                      // The audit log
+    io::Write,                            // ...one line per execution
+    path::{Path, PathBuf},                // Audit log and holdings file
+    str::FromStr,                         // "monday 09:00" schedules
+    sync::Arc,                            // Portfolio shared with the printout
+    time::{Duration, SystemTime},         // When plans run
+};
+
+use futures_util::future::BoxFuture;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::fx::quote_currency;
+use crate::portfolio::Portfolio;
+use crate::report::{civil_date, clock, UtcOffset};
+use crate::store::{epoch_ms, PriceStore};
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+const WEEKDAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+// Longest the scheduler sleeps at a time, so a clock change or suspend can't make it
+// oversleep a run by much
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+// [dca] section of the config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DcaConfig {
+    pub live: bool,                   // Place real orders instead of simulating the fills
+    pub audit_log: Option<PathBuf>,   // NDJSON record of every execution
+    pub utc_offset: UtcOffset,        // Time zone of the schedules
+    pub plans: Vec<DcaPlan>,
+}
+
+// One [[dca.plans]] table
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DcaPlan {
+    pub symbol: String,
+    pub amount: Decimal,      // Spent per run, in the symbol's quote currency
+    pub every: Schedule,
+}
+
+// "50 USD of BTC-USD (monday 09:00)"
+impl fmt::Display for DcaPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} of {} ({})", self.amount, quote_currency(&self.symbol), self.symbol, self.every)
+    }
+}
+
+// Which days a plan runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Day {
+    Daily,
+    Weekly(u8),    // 0 = Monday
+    Monthly(u8),   // Day of the month, 1 to 28 so every month has it
+}
+
+impl Day {
+    // Does the plan run on this day (counted from 1970-01-01)?
+    fn matches(self, day: i64) -> bool {
+        match self {
+            Day::Daily => true,
+            Day::Weekly(weekday) => (day + 3).rem_euclid(7) == i64::from(weekday),  // 1970-01-01 was a Thursday
+            Day::Monthly(date) => civil_date(day).2 == date,
+        }
+    }
+}
+
+// When a plan runs: a day and a local time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Schedule {
+    pub day: Day,
+    pub minute: u16,   // Minutes after local midnight
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    // "monday 09:00", "mon 09:00", "daily 18:30" or "monthly 1 09:00", optionally after "every"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("\"{}\" is not a schedule like \"monday 09:00\", \"daily 18:30\" or \"monthly 1 09:00\"", s);
+        let text = s.trim().to_lowercase();
+        let mut words: Vec<&str> = text.split_whitespace().collect();
+        if words.first() == Some(&"every") {
+            words.remove(0);
+        }
+        let (day, time) = match words.as_slice() {
+            ["daily" | "day", time] => (Day::Daily, *time),
+            ["monthly", date, time] => match date.parse::<u8>() {
+                Ok(date) if (1..=28).contains(&date) => (Day::Monthly(date), *time),
+                _ => return Err(format!("\"{}\": the day of the month must be 1 to 28", s)),
+            },
+            [weekday, time] if weekday.len() >= 3 => {
+                let index = WEEKDAYS.iter().position(|d| d.starts_with(weekday)).ok_or_else(invalid)?;
+                (Day::Weekly(index as u8), *time)
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(Self { day, minute: clock(time).map_err(|_| invalid())? })
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.day {
+            Day::Daily => write!(f, "daily")?,
+            Day::Weekly(weekday) => write!(f, "{}", WEEKDAYS[usize::from(weekday)])?,
+            Day::Monthly(date) => write!(f, "monthly {}", date)?,
+        }
+        write!(f, " {:02}:{:02}", self.minute / 60, self.minute % 60)
+    }
+}
+
+impl Schedule {
+    // The first run strictly after `after`, on the clock of `offset`'s time zone
+    pub fn next_after(&self, after: SystemTime, offset: UtcOffset) -> SystemTime {
+        let now = offset.local_minutes(after);
+        let today = now.div_euclid(MINUTES_PER_DAY);
+        (today..today + 62)  // A monthly run is at most 31 days away
+            .filter(|day| self.day.matches(*day))
+            .map(|day| day * MINUTES_PER_DAY + i64::from(self.minute))
+            .find(|at| *at > now)
+            .map(|at| offset.instant(at))
+            .expect("every schedule runs within two months")
+    }
+}
+
+// Places the live orders; implemented by the trader (trading.rs)
+pub trait OrderPlacer: Send + Sync {
+    // Buy `amount` of the quote currency's worth of `symbol` at market; `price` is the
+    // latest one, for the size limits. Some(order id) when placed, None for a dry run.
+    fn buy<'a>(&'a self, symbol: &'a str, amount: Decimal, price: Decimal, source: &'a str) -> BoxFuture<'a, Result<Option<String>, String>>;
+}
+
+// What became of one run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Filled,    // Simulated, added to the holdings
+    DryRun,    // Live, but the trader only logged it
+    Placed,    // Live order accepted by the exchange
+    Skipped,   // No usable price
+    Failed,    // Refused by the size limits or the exchange
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Filled => "filled",
+            Status::DryRun => "dry run",
+            Status::Placed => "placed",
+            Status::Skipped => "skipped",
+            Status::Failed => "failed",
+        })
+    }
+}
+
+// One line of the audit log
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Execution {
+    pub timestamp_ms: u64,
+    pub plan: String,                 // The plan, as printed
+    pub symbol: String,
+    pub live: bool,
+    pub status: Status,
+    pub amount: Decimal,
+    pub price: Option<Decimal>,       // Latest price at the time
+    pub quantity: Option<Decimal>,    // Coins bought (estimated, for a live market order)
+    pub order_id: Option<String>,     // The exchange's, for a placed order
+    pub reason: Option<String>,       // Why it was skipped or failed
+}
+
+// "DCA 50 USD of BTC-USD (monday 09:00): filled 0.00076923 at 65000"
+impl fmt::Display for Execution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DCA {}: {}", self.plan, self.status)?;
+        if let (Some(quantity), Some(price)) = (self.quantity, self.price) {
+            write!(f, " {} at {}", quantity, price)?;
+        }
+        if let Some(order_id) = &self.order_id {
+            write!(f, " (order {})", order_id)?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+// Runs the plans against the store's prices: into the portfolio, or through the trader
+pub struct DcaScheduler {
+    plans: Vec<DcaPlan>,
+    utc_offset: UtcOffset,
+    audit_log: Option<PathBuf>,
+    portfolio: Arc<Portfolio>,
+    holdings_file: Option<PathBuf>,             // Where simulated buys are written back
+    placer: Option<Arc<dyn OrderPlacer>>,       // Live: who places the orders
+}
+
+impl DcaScheduler {
+    // Simulated buys into `portfolio`; symbols are upper-cased like everywhere else
+    pub fn new(config: &DcaConfig, portfolio: Arc<Portfolio>) -> Self {
+        let plans = config.plans.iter().map(|p| DcaPlan { symbol: p.symbol.trim().to_uppercase(), ..p.clone() }).collect();
+        Self { plans, utc_offset: config.utc_offset, audit_log: config.audit_log.clone(), portfolio, holdings_file: None, placer: None }
+    }
+
+    // Write the holdings to this CSV file after every simulated buy
+    pub fn save_holdings_to(mut self, path: &Path) -> Self {
+        self.holdings_file = Some(path.to_path_buf());
+        self
+    }
+
+    // Place real orders through `placer` instead of simulating the fills
+    pub fn live(mut self, placer: Arc<dyn OrderPlacer>) -> Self {
+        self.placer = Some(placer);
+        self
+    }
+
+    pub fn plans(&self) -> &[DcaPlan] {
+        &self.plans
+    }
+
+    // Run one plan now at the store's latest price, and record what happened
+    pub async fn execute(&self, plan: &DcaPlan, store: &PriceStore, now: SystemTime) -> Execution {
+        let mut execution = Execution {
+            timestamp_ms: epoch_ms(now),
+            plan: plan.to_string(),
+            symbol: plan.symbol.clone(),
+            live: self.placer.is_some(),
+            status: Status::Skipped,
+            amount: plan.amount,
+            price: None,
+            quantity: None,
+            order_id: None,
+            reason: None,
+        };
+        let price = match store.latest(&plan.symbol).await {
+            Some(update) if store.is_stale(update.exchange, &update.symbol).await => Err("the price is stale"),
+            Some(update) if update.price > Decimal::ZERO => Ok(update.price),
+            _ => Err("no price yet"),
+        };
+        match price {
+            Ok(price) => {
+                execution.price = Some(price);
+                execution.quantity = Some((plan.amount / price).round_dp(8));
+            }
+            Err(reason) => {
+                execution.reason = Some(reason.to_string());
+                return self.record(execution);
+            }
+        }
+
+        match &self.placer {
+            None => {
+                self.portfolio.add(&plan.symbol, execution.quantity.unwrap_or_default(), plan.amount);
+                execution.status = Status::Filled;
+                if let Some(path) = &self.holdings_file
+                    && let Err(e) = self.portfolio.save_csv(path)
+                {
+                    warn!(path = %path.display(), error = %e, "Couldn't save the holdings");
+                }
+            }
+            Some(placer) => match placer.buy(&plan.symbol, plan.amount, execution.price.unwrap_or_default(), "dca").await {
+                Ok(None) => execution.status = Status::DryRun,
+                Ok(Some(order_id)) => {
+                    execution.status = Status::Placed;
+                    execution.order_id = Some(order_id);
+                }
+                Err(e) => {
+                    execution.status = Status::Failed;
+                    execution.reason = Some(e);
+                }
+            },
+        }
+        self.record(execution)
+    }
+
+    // Log an execution and append it to the audit log
+    fn record(&self, execution: Execution) -> Execution {
+        match execution.status {
+            Status::Skipped | Status::Failed => warn!("{}", execution),
+            _ => info!("{}", execution),
+        }
+        if let Some(path) = &self.audit_log
+            && let Err(e) = append_json(path, &execution)
+        {
+            warn!(path = %path.display(), error = %e, "Couldn't write the DCA audit log");
+        }
+        execution
+    }
+
+    // Sleep until the next run, execute it, repeat. Runs whose time has passed when the
+    // scheduler starts are not made up for.
+    pub async fn run(self, store: PriceStore) {
+        let start = SystemTime::now();
+        let mut next: Vec<SystemTime> = self.plans.iter().map(|p| p.every.next_after(start, self.utc_offset)).collect();
+        for (plan, at) in self.plans.iter().zip(&next) {
+            info!(plan = %plan, next = %humantime::format_rfc3339_seconds(*at), live = self.placer.is_some(), "DCA plan");
+        }
+        loop {
+            let Some((i, at)) = next.iter().copied().enumerate().min_by_key(|(_, at)| *at) else {
+                return;
+            };
+            let now = SystemTime::now();
+            if let Ok(wait) = at.duration_since(now) {
+                tokio::time::sleep(wait.min(MAX_SLEEP)).await;
+                continue;
+            }
+            let plan = &self.plans[i];
+            self.execute(plan, &store, now).await;
+            next[i] = plan.every.next_after(now.max(at), self.utc_offset);
+        }
+    }
+}
+
+// Run the scheduler in its own task
+pub fn spawn_dca(scheduler: DcaScheduler, store: &PriceStore) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(scheduler.run(store.clone()))
+}
+
+fn append_json(path: &Path, execution: &Execution) -> Result<(), Box<dyn std::error::Error>> {
+    let mut line = serde_json::to_vec(execution)?;
+    line.push(b'\n');
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(&line)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portfolio::Holding;
+    use crate::store::PriceUpdate;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn schedules_find_the_next_run_and_simulated_buys_reach_the_holdings() {
+        let every = |s: &str| s.parse::<Schedule>().unwrap();
+        assert_eq!(every("every Mon 9:00"), Schedule { day: Day::Weekly(0), minute: 540 });
+        assert_eq!(every("monthly 15 18:30").to_string(), "monthly 15 18:30");
+        assert!("monthly 31 09:00".parse::<Schedule>().is_err());
+        assert!("someday 09:00".parse::<Schedule>().is_err());
+
+        // 2024-01-03 was a Wednesday; at 12:00 UTC it's 13:00 at +01:00
+        let wednesday = UNIX_EPOCH + Duration::from_secs(1_704_283_200);
+        let plus_one: UtcOffset = "+01:00".parse().unwrap();
+        let at = |days: u64, hh: u64, mm: u64| UNIX_EPOCH + Duration::from_secs(1_704_240_000 + days * 86_400 + hh * 3600 + mm * 60);  // Days after 2024-01-03 00:00 UTC
+        assert_eq!(every("monday 09:00").next_after(wednesday, plus_one), at(5, 8, 0));
+        assert_eq!(every("daily 13:00").next_after(wednesday, plus_one), at(1, 12, 0), "13:00 local is now, so tomorrow");
+        assert_eq!(every("daily 13:01").next_after(wednesday, plus_one), at(0, 12, 1));
+        assert_eq!(every("monthly 1 00:00").next_after(wednesday, UtcOffset::default()), at(29, 0, 0));
+
+        let portfolio = Arc::new(Portfolio::new(vec![Holding { symbol: "BTC-USD".to_string(), quantity: Decimal::ONE, cost_basis: Decimal::from(30_000) }]));
+        let dca = DcaConfig { plans: vec![DcaPlan { symbol: "btc-usd".to_string(), amount: Decimal::from(50), every: every("mon 09:00") }], ..DcaConfig::default() };
+        let scheduler = DcaScheduler::new(&dca, Arc::clone(&portfolio));
+        let plan = scheduler.plans()[0].clone();
+        assert_eq!(plan.to_string(), "50 USD of BTC-USD (monday 09:00)");
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let store = PriceStore::new();
+            let skipped = scheduler.execute(&plan, &store, wednesday).await;
+            assert_eq!((skipped.status, skipped.reason.as_deref()), (Status::Skipped, Some("no price yet")));
+
+            store.update(PriceUpdate { exchange: "coinbase", symbol: "BTC-USD".to_string(), price: Decimal::from(40_000), open_24h: None, size: None, received_at: SystemTime::now() }).await;
+            let filled = scheduler.execute(&plan, &store, wednesday).await;
+            assert_eq!(filled.to_string(), "DCA 50 USD of BTC-USD (monday 09:00): filled 0.00125 at 40000");
+        });
+        let btc = &portfolio.holdings()[0];
+        assert_eq!((btc.quantity, btc.cost_basis), ("1.00125".parse().unwrap(), Decimal::from(30_050)));
+    }
+}
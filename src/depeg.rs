@@ -0,0 +1,229 @@
+// Stablecoin depeg monitor: a built-in preset that watches USDT-USD, USDC-USD and
+// DAI-USD (or whatever `symbols` lists) and alerts when one trades away from 1.00.
+//
+//     [depeg]
+//     enabled = true
+//     threshold_pct = 0.5     # 0.995 or 1.005 counts as off the peg...
+//     sustain = "5m"          # ...once it has stayed off for 5 minutes
+//     recover_pct = 0.2       # back on the peg only within 0.998..1.002
+//
+// A price has to stay beyond the threshold for `sustain` before the alert goes out, so
+// one odd trade doesn't page anyone. Once depegged, the symbol only counts as recovered
+// when it's back within the tighter `recover_pct` band: a price flickering around the
+// threshold sends one alert, not one per crossing. Recovery is announced too.
+
+use std::{
+    collections::HashMap,                   // State per (exchange, symbol)
+    time::{Duration, SystemTime},           // Sustain period
+};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::alerts::Alert;
+use crate::config::deserialize_duration;
+use crate::notify::Notifier;
+use crate::store::{PriceStore, PriceUpdate};
+
+// [depeg] section of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DepegConfig {
+    pub enabled: bool,
+    pub symbols: Vec<String>,       // Pairs that should trade at 1.00
+    pub threshold_pct: Decimal,     // Distance from 1.00 that counts as a depeg
+    pub recover_pct: Decimal,       // Distance it has to come back within; at most `threshold_pct`
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub sustain: Duration,          // How long it has to stay beyond the threshold
+    pub desktop: bool,              // Also show a desktop notification
+}
+
+impl Default for DepegConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            symbols: vec!["USDT-USD".to_string(), "USDC-USD".to_string(), "DAI-USD".to_string()],
+            threshold_pct: Decimal::new(5, 1),   // 0.5%
+            recover_pct: Decimal::new(2, 1),     // 0.2%
+            sustain: Duration::from_secs(5 * 60),
+            desktop: false,
+        }
+    }
+}
+
+// Where one symbol stands
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Peg {
+    Holding,                  // Within the threshold (or recovered)
+    Drifting(SystemTime),     // Beyond the threshold since then, not long enough yet
+    Broken,                   // Alerted; waiting to come back within `recover_pct`
+}
+
+// What an update changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum DepegEvent {
+    Depegged { exchange: &'static str, symbol: String, price: Decimal, deviation_pct: Decimal, since: SystemTime },
+    Recovered { exchange: &'static str, symbol: String, price: Decimal, deviation_pct: Decimal },
+}
+
+// Watches the update stream of the stablecoin pairs
+pub struct DepegMonitor {
+    config: DepegConfig,
+    state: HashMap<(&'static str, String), Peg>,
+}
+
+impl DepegMonitor {
+    pub fn new(config: DepegConfig) -> Self {
+        Self { config, state: HashMap::new() }
+    }
+
+    // A monitor for the [depeg] settings, or None when it's off
+    pub fn from_config(config: &DepegConfig) -> Option<Self> {
+        config.enabled.then(|| Self::new(config.clone()))
+    }
+
+    pub fn symbols(&self) -> &[String] {
+        &self.config.symbols
+    }
+
+    // Fold one update into its symbol's state. The update's own timestamp is the clock,
+    // so a replayed recording behaves like the live feed did.
+    pub fn evaluate(&mut self, update: &PriceUpdate) -> Option<DepegEvent> {
+        if !self.config.symbols.contains(&update.symbol) {
+            return None;
+        }
+        let deviation_pct = ((update.price - Decimal::ONE) * Decimal::ONE_HUNDRED).round_dp(4);
+        let off = deviation_pct.abs() > self.config.threshold_pct;
+        let back = deviation_pct.abs() <= self.config.recover_pct;
+
+        let now = update.received_at;
+        let state = self.state.entry((update.exchange, update.symbol.clone())).or_insert(Peg::Holding);
+        match *state {
+            Peg::Holding if off => *state = Peg::Drifting(now),
+            // Back inside the threshold before `sustain` was up: start over next time
+            Peg::Drifting(_) if !off => *state = Peg::Holding,
+            Peg::Drifting(since) if now.duration_since(since).unwrap_or_default() >= self.config.sustain => {
+                *state = Peg::Broken;
+                return Some(DepegEvent::Depegged {
+                    exchange: update.exchange,
+                    symbol: update.symbol.clone(),
+                    price: update.price,
+                    deviation_pct,
+                    since,
+                });
+            }
+            Peg::Broken if back => {
+                *state = Peg::Holding;
+                return Some(DepegEvent::Recovered {
+                    exchange: update.exchange,
+                    symbol: update.symbol.clone(),
+                    price: update.price,
+                    deviation_pct,
+                });
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // Present an event as an alert so every notifier (console, webhooks, Telegram,
+    // desktop) can deliver it
+    pub fn alert(&self, event: &DepegEvent) -> Alert {
+        let (exchange, symbol, price, rule, detail) = match event {
+            DepegEvent::Depegged { exchange, symbol, price, deviation_pct, since } => {
+                let lasted = SystemTime::now().duration_since(*since).unwrap_or_default();
+                (
+                    *exchange,
+                    symbol,
+                    *price,
+                    format!("{} depegged (more than {}% from 1.00)", symbol, self.config.threshold_pct),
+                    format!("{:+}% from 1.00 for {}", deviation_pct, humantime::format_duration(Duration::from_secs(lasted.as_secs()))),
+                )
+            }
+            DepegEvent::Recovered { exchange, symbol, price, deviation_pct } => (
+                *exchange,
+                symbol,
+                *price,
+                format!("{} back on its peg", symbol),
+                format!("{:+}% from 1.00, within {}%", deviation_pct, self.config.recover_pct),
+            ),
+        };
+        Alert {
+            rule,
+            exchange,
+            symbol: symbol.clone(),
+            price,
+            detail,
+            fired_at: SystemTime::now(),
+            desktop: self.config.desktop,
+            trade: None,
+        }
+    }
+}
+
+// Check every update from `store` against the peg, sending events to all notifiers
+pub fn spawn_depeg(
+    mut monitor: DepegMonitor,
+    store: &PriceStore,
+    notifiers: Vec<Box<dyn Notifier>>,
+) -> tokio::task::JoinHandle<()> {
+    let mut rx = store.subscribe_updates();
+    let metrics = store.metrics().clone();  // Counts what the task misses
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(update) => {
+                    if let Some(event) = monitor.evaluate(&update) {
+                        let alert = monitor.alert(&event);
+                        for notifier in &notifiers {
+                            notifier.notify(&alert);
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!(skipped = n, "Depeg monitor fell behind");
+                    metrics.record_dropped_events("depeg", n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(price: &str, secs: u64) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: "USDT-USD".to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs),
+        }
+    }
+
+    #[test]
+    fn alerts_after_a_sustained_deviation_and_recovers_with_hysteresis() {
+        let config = DepegConfig { enabled: true, sustain: Duration::from_secs(60), ..DepegConfig::default() };
+        let mut monitor = DepegMonitor::from_config(&config).unwrap();
+
+        // A short dip doesn't count
+        assert_eq!(monitor.evaluate(&tick("0.990", 0)), None);
+        assert_eq!(monitor.evaluate(&tick("0.999", 30)), None);
+        assert_eq!(monitor.evaluate(&tick("0.990", 70)), None);  // The clock starts again
+
+        let event = monitor.evaluate(&tick("0.992", 130)).unwrap();
+        assert!(matches!(event, DepegEvent::Depegged { ref deviation_pct, .. } if *deviation_pct == "-0.8".parse().unwrap()));
+        assert_eq!(monitor.alert(&event).rule, "USDT-USD depegged (more than 0.5% from 1.00)");
+
+        // Flickering around the threshold stays depegged; only the recovery band clears it
+        assert_eq!(monitor.evaluate(&tick("0.996", 140)), None);
+        assert_eq!(monitor.evaluate(&tick("0.994", 150)), None);
+        assert!(matches!(monitor.evaluate(&tick("0.999", 160)), Some(DepegEvent::Recovered { .. })));
+        assert_eq!(monitor.evaluate(&tick("1.001", 170)), None);
+    }
+}
@@ -0,0 +1,164 @@
+// Binance spot feed: wss://stream.binance.com:9443/ws, "<symbol>@ticker" streams, plus
+// "<symbol>@trade" for individual trades. Trade ids go up one by one per symbol, so a
+// jump in them means trades went missing on the way.
+//
+// Binance has no USD order books, so a "-USD" symbol is tracked against USDT
+// (e.g. BTC-USD -> BTCUSDT). Other quotes are passed through unchanged.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{split_symbol, Exchange, Ticker, Trade};
+use crate::sequence::Numbering;
+
+pub struct Binance;
+
+// The fields we need from a 24hr ticker event
+#[derive(Debug, Deserialize)]
+struct TickerEvent {
+    #[serde(rename = "e")]
+    event: String,   // Event type, "24hrTicker"
+    #[serde(rename = "s")]
+    symbol: String,  // e.g. "BTCUSDT"
+    #[serde(rename = "c")]
+    close: String,   // Last price
+    #[serde(rename = "o")]
+    open: Option<String>,  // Price 24 hours ago
+    #[serde(rename = "Q")]
+    last_qty: Option<String>,  // Size of the last trade
+}
+
+// The fields we need from a trade event
+#[derive(Debug, Deserialize)]
+struct TradeEvent {
+    #[serde(rename = "e")]
+    event: String,   // Event type, "trade"
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "p")]
+    price: String,
+    #[serde(rename = "q")]
+    quantity: String,
+}
+
+impl Exchange for Binance {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    fn url(&self) -> &str {
+        "wss://stream.binance.com:9443/ws"
+    }
+
+    fn native_symbol(&self, symbol: &str) -> String {
+        let (base, quote) = split_symbol(symbol);
+        let quote = if quote.eq_ignore_ascii_case("USD") { "USDT" } else { quote };
+        format!("{}{}", base, quote).to_uppercase()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![self.stream_message("SUBSCRIBE", symbols, "ticker", 1)]
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![self.stream_message("UNSUBSCRIBE", symbols, "ticker", 2)]
+    }
+
+    fn trade_subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![self.stream_message("SUBSCRIBE", symbols, "trade", 3)]
+    }
+
+    fn trade_unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![self.stream_message("UNSUBSCRIBE", symbols, "trade", 4)]
+    }
+
+    fn parse_trades(&self, message: &Value) -> Vec<Trade> {
+        match TradeEvent::deserialize(message) {
+            Ok(ev) if ev.event == "trade" => vec![Trade { symbol: ev.symbol, price: ev.price, size: ev.quantity }],
+            _ => Vec::new(),
+        }
+    }
+
+    // `{"e": "trade", "s": "BTCUSDT", "t": 12345, ...}`: every trade of the symbol, numbered one by one
+    fn parse_sequence(&self, message: &Value) -> Option<(String, u64, Numbering)> {
+        trade_id(message, "trade", "t")
+    }
+
+    // Every stream event carries its event time, `"E": 1700000000000` (milliseconds)
+    fn parse_timestamp(&self, message: &Value) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_millis(message["E"].as_u64()?))
+    }
+
+    fn products_url(&self) -> Option<&'static str> {
+        Some("https://api.binance.com/api/v3/exchangeInfo")
+    }
+
+    // `{"symbols": [{"symbol": "BTCUSDT", "status": "TRADING", ...}, ...]}`
+    fn parse_products(&self, body: &Value) -> Vec<String> {
+        let products = body["symbols"].as_array().map(Vec::as_slice).unwrap_or_default();
+        products
+            .iter()
+            .filter(|p| p["status"] == "TRADING")
+            .filter_map(|p| p["symbol"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    fn parse(&self, message: &Value) -> Vec<Ticker> {
+        match TickerEvent::deserialize(message) {
+            Ok(ev) if ev.event == "24hrTicker" => {
+                vec![Ticker { symbol: ev.symbol, price: ev.close, open_24h: ev.open, size: ev.last_qty }]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Binance {
+    // A SUBSCRIBE / UNSUBSCRIBE request for one kind of stream ("ticker", "trade") of `symbols`
+    pub(super) fn stream_message(&self, method: &str, symbols: &[String], stream: &str, id: u32) -> String {
+        // Stream names are lowercase, e.g. "btcusdt@ticker"
+        let streams: Vec<String> = symbols
+            .iter()
+            .map(|s| format!("{}@{}", self.native_symbol(s).to_lowercase(), stream))
+            .collect();
+        json!({ "method": method, "params": streams, "id": id }).to_string()
+    }
+}
+
+// Symbol and id of an `event` numbered by its `id` field, for `parse_sequence`
+pub(super) fn trade_id(message: &Value, event: &str, id: &str) -> Option<(String, u64, Numbering)> {
+    if message["e"] != event {
+        return None;
+    }
+    Some((message["s"].as_str()?.to_string(), message[id].as_u64()?, Numbering::Contiguous))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_symbols_and_parses_ticker_events() {
+        assert_eq!(Binance.native_symbol("BTC-USD"), "BTCUSDT");
+        assert_eq!(Binance.native_symbol("ETH-BTC"), "ETHBTC");
+
+        let event = json!({"e": "24hrTicker", "s": "BTCUSDT", "c": "65000.10", "o": "64000.00", "Q": "0.01"});
+        assert_eq!(
+            Binance.parse(&event),
+            vec![Ticker { symbol: "BTCUSDT".into(), price: "65000.10".into(), open_24h: Some("64000.00".into()), size: Some("0.01".into()) }]
+        );
+        assert!(Binance.parse(&json!({"result": null, "id": 1})).is_empty());
+
+        let trade = json!({"e": "trade", "s": "BTCUSDT", "p": "65000.10", "q": "0.5"});
+        assert_eq!(Binance.parse_trades(&trade), vec![Trade { symbol: "BTCUSDT".into(), price: "65000.10".into(), size: "0.5".into() }]);
+        assert!(Binance.parse_trades(&event).is_empty());
+        assert_eq!(Binance.parse_sequence(&json!({"e": "trade", "s": "BTCUSDT", "t": 4_211_007})), Some(("BTCUSDT".into(), 4_211_007, Numbering::Contiguous)));
+        assert_eq!(Binance.parse_sequence(&event), None);
+
+        let timed = json!({"e": "trade", "E": 1_700_000_000_123u64, "s": "BTCUSDT"});
+        assert_eq!(Binance.parse_timestamp(&timed), Some(UNIX_EPOCH + Duration::from_millis(1_700_000_000_123)));
+        assert_eq!(Binance.parse_timestamp(&event), None);
+    }
+}
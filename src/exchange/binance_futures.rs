@@ -0,0 +1,163 @@
+// Binance USDⓈ-M perpetual futures: wss://fstream.binance.com/ws. The contracts'
+// "<symbol>@ticker" streams carry the last trade price like the spot ones, and
+// "<symbol>@markPrice@1s" the mark price, index price and funding rate every second.
+// "<symbol>@aggTrade" stands in for the spot "trade" stream; aggregate trade ids go up
+// one by one per contract like the spot trade ids.
+//
+// Symbols map like on spot: BTC-USD is the BTCUSDT perpetual.
+
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::binance::trade_id;
+use super::{Binance, Exchange, Funding, Ticker, Trade};
+use crate::sequence::Numbering;
+
+pub struct BinanceFutures;
+
+// The fields we need from a mark price event
+#[derive(Debug, Deserialize)]
+struct MarkPriceEvent {
+    #[serde(rename = "e")]
+    event: String,         // Event type, "markPriceUpdate"
+    #[serde(rename = "s")]
+    symbol: String,        // e.g. "BTCUSDT"
+    #[serde(rename = "p")]
+    mark_price: String,
+    #[serde(rename = "i")]
+    index_price: String,
+    #[serde(rename = "r")]
+    funding_rate: String,  // Empty for contracts without funding (delivery futures)
+    #[serde(rename = "T")]
+    next_funding_time: Option<u64>,  // Milliseconds
+}
+
+// Aggregate trades: one event per taker order, however many makers it filled against
+#[derive(Debug, Deserialize)]
+struct AggTradeEvent {
+    #[serde(rename = "e")]
+    event: String,   // "aggTrade"
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "p")]
+    price: String,
+    #[serde(rename = "q")]
+    quantity: String,
+}
+
+impl Exchange for BinanceFutures {
+    fn name(&self) -> &'static str {
+        "binance-futures"
+    }
+
+    fn url(&self) -> &str {
+        "wss://fstream.binance.com/ws"
+    }
+
+    fn native_symbol(&self, symbol: &str) -> String {
+        Binance.native_symbol(symbol)
+    }
+
+    // The funding figures come with every subscription: they're the point of a futures feed
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![Binance.stream_message("SUBSCRIBE", symbols, "ticker", 1), Binance.stream_message("SUBSCRIBE", symbols, "markPrice@1s", 5)]
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![Binance.stream_message("UNSUBSCRIBE", symbols, "ticker", 2), Binance.stream_message("UNSUBSCRIBE", symbols, "markPrice@1s", 6)]
+    }
+
+    fn trade_subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![Binance.stream_message("SUBSCRIBE", symbols, "aggTrade", 3)]
+    }
+
+    fn trade_unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![Binance.stream_message("UNSUBSCRIBE", symbols, "aggTrade", 4)]
+    }
+
+    fn parse_trades(&self, message: &Value) -> Vec<Trade> {
+        match AggTradeEvent::deserialize(message) {
+            Ok(ev) if ev.event == "aggTrade" => vec![Trade { symbol: ev.symbol, price: ev.price, size: ev.quantity }],
+            _ => Vec::new(),
+        }
+    }
+
+    // `{"e": "aggTrade", "s": "BTCUSDT", "a": 5933014, ...}`
+    fn parse_sequence(&self, message: &Value) -> Option<(String, u64, Numbering)> {
+        trade_id(message, "aggTrade", "a")
+    }
+
+    fn parse_funding(&self, message: &Value) -> Option<Funding> {
+        match MarkPriceEvent::deserialize(message) {
+            Ok(ev) if ev.event == "markPriceUpdate" && !ev.funding_rate.is_empty() => Some(Funding {
+                symbol: ev.symbol,
+                mark_price: ev.mark_price,
+                index_price: ev.index_price,
+                funding_rate: ev.funding_rate,
+                next_funding_ms: ev.next_funding_time.filter(|&t| t > 0),
+            }),
+            _ => None,
+        }
+    }
+
+    // Same `"E"` event time as on spot
+    fn parse_timestamp(&self, message: &Value) -> Option<SystemTime> {
+        Binance.parse_timestamp(message)
+    }
+
+    fn products_url(&self) -> Option<&'static str> {
+        Some("https://fapi.binance.com/fapi/v1/exchangeInfo")
+    }
+
+    // Like spot's list, but only the perpetuals: the quarterly contracts are "BTCUSDT_250926"
+    fn parse_products(&self, body: &Value) -> Vec<String> {
+        let products = body["symbols"].as_array().map(Vec::as_slice).unwrap_or_default();
+        products
+            .iter()
+            .filter(|p| p["status"] == "TRADING" && p["contractType"] == "PERPETUAL")
+            .filter_map(|p| p["symbol"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    // The 24hr ticker event has the spot layout
+    fn parse(&self, message: &Value) -> Vec<Ticker> {
+        Binance.parse(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_mark_price_and_agg_trade_events() {
+        assert_eq!(BinanceFutures.native_symbol("BTC-USD"), "BTCUSDT");
+        let subscribe: Value = serde_json::from_str(&BinanceFutures.subscribe_messages(&["BTC-USD".into()])[1]).unwrap();
+        assert_eq!(subscribe["params"], json!(["btcusdt@markPrice@1s"]));
+
+        let mark = json!({
+            "e": "markPriceUpdate", "E": 1_700_000_000_000u64, "s": "BTCUSDT",
+            "p": "65010.40000000", "i": "65000.00000000", "P": "65005.1", "r": "0.00010000", "T": 1_700_006_400_000u64
+        });
+        assert_eq!(
+            BinanceFutures.parse_funding(&mark),
+            Some(Funding {
+                symbol: "BTCUSDT".into(),
+                mark_price: "65010.40000000".into(),
+                index_price: "65000.00000000".into(),
+                funding_rate: "0.00010000".into(),
+                next_funding_ms: Some(1_700_006_400_000),
+            })
+        );
+        assert!(BinanceFutures.parse(&mark).is_empty());
+
+        let trade = json!({"e": "aggTrade", "s": "BTCUSDT", "p": "65010.1", "q": "0.25", "m": true});
+        assert_eq!(BinanceFutures.parse_trades(&trade), vec![Trade { symbol: "BTCUSDT".into(), price: "65010.1".into(), size: "0.25".into() }]);
+        assert_eq!(BinanceFutures.parse_funding(&trade), None);
+        assert_eq!(BinanceFutures.parse_sequence(&json!({"e": "aggTrade", "s": "BTCUSDT", "a": 5_933_014})), Some(("BTCUSDT".into(), 5_933_014, Numbering::Contiguous)));
+        assert_eq!(BinanceFutures.parse_sequence(&mark), None);
+    }
+}
@@ -0,0 +1,225 @@
+// Coinbase Exchange feed: wss://ws-feed.exchange.coinbase.com, "ticker" channel,
+// plus "level2_batch" for order books (the unbatched "level2" channel needs an API key).
+// The "heartbeat" channel sends one message per product every second, so a product
+// that goes quiet can be told apart from a stalled connection. Trades come from the
+// "matches" channel.
+
+use std::time::SystemTime;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{Exchange, Ticker, Trade};
+use crate::orderbook::{BookEvent, Side};
+use crate::sequence::Numbering;
+
+pub struct Coinbase;
+
+// Struct representing the JSON format of ticker messages we receive from Coinbase
+#[derive(Debug, Deserialize)]
+struct TickerMessage {
+    #[serde(rename = "type")]
+    msg_type: String,        // The message type (e.g., "ticker")
+    product_id: String,      // The trading pair (e.g., "BTC-USD")
+    price: Option<String>,   // The price (may be None if not present)
+    #[serde(default)]
+    open_24h: Option<String>, // Price 24 hours ago
+    #[serde(default)]
+    last_size: Option<String>, // Size of the trade that set `price`
+}
+
+// Order book messages: a full "snapshot" first, then "l2update" changes
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum BookMessage {
+    Snapshot {
+        product_id: String,
+        bids: Vec<(Decimal, Decimal)>,  // [price, size], as strings on the wire
+        asks: Vec<(Decimal, Decimal)>,
+    },
+    L2update {
+        product_id: String,
+        changes: Vec<(String, Decimal, Decimal)>,  // ["buy" | "sell", price, new size]
+    },
+}
+
+impl Exchange for Coinbase {
+    fn name(&self) -> &'static str {
+        "coinbase"
+    }
+
+    fn url(&self) -> &str {
+        "wss://ws-feed.exchange.coinbase.com"
+    }
+
+    // Coinbase already uses our "BTC-USD" format
+    fn native_symbol(&self, symbol: &str) -> String {
+        symbol.to_string()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![channel_message("subscribe", &["ticker", "heartbeat"], symbols)]
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![channel_message("unsubscribe", &["ticker", "heartbeat"], symbols)]
+    }
+
+    fn parse(&self, message: &Value) -> Vec<Ticker> {
+        // Only act on messages of type "ticker" that have a price
+        match TickerMessage::deserialize(message) {
+            Ok(TickerMessage { msg_type, product_id, price: Some(price), open_24h, last_size }) if msg_type == "ticker" => {
+                vec![Ticker { symbol: product_id, price, open_24h, size: last_size }]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn heartbeats(&self) -> bool {
+        true
+    }
+
+    // `{"type": "heartbeat", "product_id": "BTC-USD", "sequence": 90, ...}`
+    fn parse_heartbeat(&self, message: &Value) -> Option<String> {
+        if message["type"] != "heartbeat" {
+            return None;
+        }
+        message["product_id"].as_str().map(str::to_string)
+    }
+
+    // Tickers carry the product's sequence number, which every channel of the product
+    // shares: it only goes up from one ticker to the next. (level2_batch updates carry
+    // none; only the full channel, not subscribed, numbers a product's messages one by one.)
+    // Gaps show up on Binance's trade ids instead.
+    fn parse_sequence(&self, message: &Value) -> Option<(String, u64, Numbering)> {
+        if message["type"] != "ticker" {
+            return None;
+        }
+        Some((message["product_id"].as_str()?.to_string(), message["sequence"].as_u64()?, Numbering::Increasing))
+    }
+
+    // Tickers, heartbeats, matches and book updates all carry `"time": "2024-01-01T00:00:00.123456Z"`
+    fn parse_timestamp(&self, message: &Value) -> Option<SystemTime> {
+        humantime::parse_rfc3339_weak(message["time"].as_str()?).ok()
+    }
+
+    fn products_url(&self) -> Option<&'static str> {
+        Some("https://api.exchange.coinbase.com/products")
+    }
+
+    // `[{"id": "BTC-USD", "status": "online", ...}, ...]`
+    fn parse_products(&self, body: &Value) -> Vec<String> {
+        let products = body.as_array().map(Vec::as_slice).unwrap_or_default();
+        products
+            .iter()
+            .filter(|p| p["status"] != "delisted")
+            .filter_map(|p| p["id"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    fn book_subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![channel_message("subscribe", &["level2_batch"], symbols)]
+    }
+
+    fn book_unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![channel_message("unsubscribe", &["level2_batch"], symbols)]
+    }
+
+    fn trade_subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![channel_message("subscribe", &["matches"], symbols)]
+    }
+
+    fn trade_unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![channel_message("unsubscribe", &["matches"], symbols)]
+    }
+
+    // `{"type": "match", "product_id": "BTC-USD", "price": "65000", "size": "0.01", ...}`.
+    // The "last_match" sent on subscribing is an older trade and is skipped.
+    fn parse_trades(&self, message: &Value) -> Vec<Trade> {
+        if message["type"] != "match" {
+            return Vec::new();
+        }
+        let field = |name: &str| message[name].as_str().map(str::to_string);
+        match (field("product_id"), field("price"), field("size")) {
+            (Some(symbol), Some(price), Some(size)) => vec![Trade { symbol, price, size }],
+            _ => Vec::new(),
+        }
+    }
+
+    fn parse_book(&self, message: &Value) -> Option<BookEvent> {
+        match BookMessage::deserialize(message).ok()? {
+            BookMessage::Snapshot { product_id, bids, asks } => Some(BookEvent::Snapshot { symbol: product_id, bids, asks }),
+            BookMessage::L2update { product_id, changes } => {
+                let changes = changes
+                    .into_iter()
+                    .filter_map(|(side, price, size)| match side.as_str() {
+                        "buy" => Some((Side::Bid, price, size)),
+                        "sell" => Some((Side::Ask, price, size)),
+                        _ => None,
+                    })
+                    .collect();
+                Some(BookEvent::Update { symbol: product_id, changes })
+            }
+        }
+    }
+}
+
+// A "subscribe" or "unsubscribe" message for some channels
+fn channel_message(kind: &str, channels: &[&str], symbols: &[String]) -> String {
+    let channels: Vec<Value> = channels.iter().map(|name| json!({ "name": name, "product_ids": symbols })).collect();
+    json!({ "type": kind, "channels": channels }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ticker_and_ignores_other_messages() {
+        let ticker = json!({"type": "ticker", "product_id": "BTC-USD", "price": "65000.01", "open_24h": "64000", "last_size": "0.5"});
+        assert_eq!(
+            Coinbase.parse(&ticker),
+            vec![Ticker { symbol: "BTC-USD".into(), price: "65000.01".into(), open_24h: Some("64000".into()), size: Some("0.5".into()) }]
+        );
+
+        let ack = json!({"type": "subscriptions", "channels": []});
+        assert!(Coinbase.parse(&ack).is_empty());
+
+        let heartbeat = json!({"type": "heartbeat", "product_id": "BTC-USD", "sequence": 90, "last_trade_id": 20});
+        assert!(Coinbase.parse(&heartbeat).is_empty());
+        assert_eq!(Coinbase.parse_heartbeat(&heartbeat), Some("BTC-USD".into()));
+        assert_eq!(Coinbase.parse_heartbeat(&ticker), None);
+
+        let numbered = json!({"type": "ticker", "product_id": "BTC-USD", "price": "1", "sequence": 42});
+        assert_eq!(Coinbase.parse_sequence(&numbered), Some(("BTC-USD".into(), 42, Numbering::Increasing)));
+        assert_eq!(Coinbase.parse_sequence(&heartbeat), None);
+
+        let matched = json!({"type": "match", "product_id": "BTC-USD", "price": "65000", "size": "0.01", "side": "buy"});
+        assert_eq!(Coinbase.parse_trades(&matched), vec![Trade { symbol: "BTC-USD".into(), price: "65000".into(), size: "0.01".into() }]);
+        assert!(Coinbase.parse_trades(&json!({"type": "last_match", "product_id": "BTC-USD", "price": "1", "size": "1"})).is_empty());
+        assert!(Coinbase.parse(&matched).is_empty());
+
+        let timed = json!({"type": "ticker", "product_id": "BTC-USD", "time": "2023-11-14T22:13:20.250000Z"});
+        let at = Coinbase.parse_timestamp(&timed).unwrap();
+        assert_eq!(at.duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(), 1_700_000_000_250);
+        assert_eq!(Coinbase.parse_timestamp(&ticker), None);
+    }
+
+    #[test]
+    fn parses_level2_snapshot_and_updates() {
+        let d = |s: &str| s.parse::<Decimal>().unwrap();
+        let snapshot = json!({"type": "snapshot", "product_id": "BTC-USD", "bids": [["100.5", "2"]], "asks": [["101", "1.5"]]});
+        assert_eq!(
+            Coinbase.parse_book(&snapshot),
+            Some(BookEvent::Snapshot { symbol: "BTC-USD".into(), bids: vec![(d("100.5"), d("2"))], asks: vec![(d("101"), d("1.5"))] })
+        );
+
+        let update = json!({"type": "l2update", "product_id": "BTC-USD", "time": "2024-01-01T00:00:00Z", "changes": [["sell", "101", "0"]]});
+        assert_eq!(
+            Coinbase.parse_book(&update),
+            Some(BookEvent::Update { symbol: "BTC-USD".into(), changes: vec![(Side::Ask, d("101"), d("0"))] })
+        );
+        assert_eq!(Coinbase.parse_book(&json!({"type": "ticker", "product_id": "BTC-USD"})), None);
+    }
+}
@@ -0,0 +1,179 @@
+// Kraken spot feed (WebSocket API v2): wss://ws.kraken.com/v2, "ticker" channel, plus
+// "trade" for individual trades.
+
+use std::time::SystemTime;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{split_symbol, Exchange, Ticker, Trade};
+
+pub struct Kraken;
+
+// Kraken batches updates: one message carries a list of tickers
+#[derive(Debug, Deserialize)]
+struct TickerMessage {
+    channel: String,        // "ticker" for the messages we care about
+    data: Vec<TickerData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TickerData {
+    symbol: String,  // e.g. "BTC/USD"
+    last: Value,     // Last trade price, sent as a JSON number
+    #[serde(default)]
+    change: Option<Value>,  // Absolute price change over the last 24 hours, also a JSON number
+}
+
+// Trades are batched the same way
+#[derive(Debug, Deserialize)]
+struct TradeMessage {
+    channel: String,        // "trade"
+    #[serde(rename = "type")]
+    kind: String,           // "snapshot" (recent trades, on subscribing) or "update"
+    data: Vec<TradeData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TradeData {
+    symbol: String,
+    price: Value,  // JSON numbers again
+    qty: Value,
+}
+
+impl Exchange for Kraken {
+    fn name(&self) -> &'static str {
+        "kraken"
+    }
+
+    fn url(&self) -> &str {
+        "wss://ws.kraken.com/v2"
+    }
+
+    // Kraken spells pairs with a slash: "BTC/USD"
+    fn native_symbol(&self, symbol: &str) -> String {
+        let (base, quote) = split_symbol(symbol);
+        format!("{}/{}", base, quote).to_uppercase()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![self.channel_message("subscribe", "ticker", symbols)]
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![self.channel_message("unsubscribe", "ticker", symbols)]
+    }
+
+    fn trade_subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![self.channel_message("subscribe", "trade", symbols)]
+    }
+
+    fn trade_unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        vec![self.channel_message("unsubscribe", "trade", symbols)]
+    }
+
+    // Only "update" messages: the "snapshot" on subscribing repeats trades we may have seen
+    fn parse_trades(&self, message: &Value) -> Vec<Trade> {
+        match TradeMessage::deserialize(message) {
+            Ok(msg) if msg.channel == "trade" && msg.kind == "update" => msg
+                .data
+                .into_iter()
+                .filter(|d| d.price.is_number() && d.qty.is_number())
+                .map(|d| Trade { symbol: d.symbol, price: d.price.to_string(), size: d.qty.to_string() })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Trades and book updates carry `"timestamp": "2024-01-01T00:00:00.123456Z"` per entry;
+    // the ticker channel has none
+    fn parse_timestamp(&self, message: &Value) -> Option<SystemTime> {
+        humantime::parse_rfc3339_weak(message["data"][0]["timestamp"].as_str()?).ok()
+    }
+
+    fn products_url(&self) -> Option<&'static str> {
+        Some("https://api.kraken.com/0/public/AssetPairs")
+    }
+
+    // `{"result": {"XXBTZUSD": {"wsname": "XBT/USD", ...}, ...}}`. The REST API still uses
+    // Kraken's legacy asset codes, while WebSocket v2 uses the common ones (BTC, DOGE).
+    fn parse_products(&self, body: &Value) -> Vec<String> {
+        let Some(pairs) = body["result"].as_object() else { return Vec::new() };
+        pairs
+            .values()
+            .filter_map(|p| p["wsname"].as_str())
+            .map(|name| {
+                let (base, quote) = name.split_once('/').unwrap_or((name, ""));
+                let common = |code: &str| match code {
+                    "XBT" => "BTC".to_string(),
+                    "XDG" => "DOGE".to_string(),
+                    other => other.to_string(),
+                };
+                format!("{}/{}", common(base), common(quote))
+            })
+            .collect()
+    }
+
+    fn parse(&self, message: &Value) -> Vec<Ticker> {
+        match TickerMessage::deserialize(message) {
+            Ok(msg) if msg.channel == "ticker" => msg
+                .data
+                .into_iter()
+                // Prices arrive as JSON numbers; store them as text like the other exchanges
+                .filter(|d| d.last.is_number())
+                .map(|d| {
+                    // Kraken sends the 24h change rather than the opening price, so work it back out
+                    // (in exact decimal arithmetic, so 0.3 - 0.1 doesn't become 0.19999999999999998)
+                    let decimal = |v: &Value| v.to_string().parse::<Decimal>().ok();
+                    let open_24h = decimal(&d.last)
+                        .zip(d.change.as_ref().and_then(decimal))
+                        .map(|(last, change)| (last - change).to_string());
+                    // The ticker channel carries no per-trade size
+                    Ticker { symbol: d.symbol, price: d.last.to_string(), open_24h, size: None }
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Kraken {
+    // A "subscribe" / "unsubscribe" request for one channel ("ticker", "trade")
+    fn channel_message(&self, method: &str, channel: &str, symbols: &[String]) -> String {
+        let pairs: Vec<String> = symbols.iter().map(|s| self.native_symbol(s)).collect();
+        json!({
+            "method": method,
+            "params": { "channel": channel, "symbol": pairs }
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_symbols_and_parses_ticker_batches() {
+        assert_eq!(Kraken.native_symbol("BTC-USD"), "BTC/USD");
+
+        let msg: Value = serde_json::from_str(
+            r#"{"channel":"ticker","type":"update","data":[{"symbol":"BTC/USD","last":65000.5,"change":1000.5}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Kraken.parse(&msg),
+            vec![Ticker { symbol: "BTC/USD".into(), price: "65000.5".into(), open_24h: Some("64000.0".into()), size: None }]
+        );
+        assert!(Kraken.parse(&json!({"channel": "heartbeat"})).is_empty());
+
+        let trades: Value = serde_json::from_str(
+            r#"{"channel":"trade","type":"update","data":[{"symbol":"BTC/USD","side":"buy","price":65000.5,"qty":0.25}]}"#,
+        )
+        .unwrap();
+        assert_eq!(Kraken.parse_trades(&trades), vec![Trade { symbol: "BTC/USD".into(), price: "65000.5".into(), size: "0.25".into() }]);
+        let snapshot = json!({"channel": "trade", "type": "snapshot", "data": [{"symbol": "BTC/USD", "price": 1, "qty": 1}]});
+        assert!(Kraken.parse_trades(&snapshot).is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+// Exchange abstraction: every venue speaks its own WebSocket dialect (a DEX speaks
+// Ethereum JSON-RPC), so each one gets a small connector that knows its URL, how to
+// subscribe, and how to turn its ticker messages into our common `Ticker` type (and,
+// optionally, trades into `Trade`).
+
+use std::time::SystemTime;  // When an exchange sent a message
+
+use serde_json::Value;  // Already-validated JSON frame handed over by the feed loop
+
+use crate::orderbook::BookEvent;
+use crate::sequence::Numbering;
+
+mod binance;
+mod binance_futures;
+mod coinbase;
+mod kraken;
+mod uniswap;
+
+pub use binance::Binance;
+pub use binance_futures::BinanceFutures;
+pub use coinbase::Coinbase;
+pub use kraken::Kraken;
+pub use uniswap::{DexConfig, DexPool, Uniswap};
+
+// A normalized price update, independent of which exchange it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ticker {
+    pub symbol: String,  // Exchange-native product id (e.g. "BTC-USD", "BTCUSDT", "BTC/USD")
+    pub price: String,   // Last trade price, kept as the exchange sent it
+    pub open_24h: Option<String>,  // Price 24 hours ago, when the exchange provides it
+    pub size: Option<String>,      // Size of the last trade, when the exchange provides it
+}
+
+// One executed trade from an exchange's trade channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    pub symbol: String,  // Exchange-native product id
+    pub price: String,   // Kept as the exchange sent it, like `Ticker`
+    pub size: String,    // Base currency amount
+}
+
+// A perpetual futures contract's mark price event
+#[derive(Debug, Clone, PartialEq)]
+pub struct Funding {
+    pub symbol: String,        // Exchange-native contract id
+    pub mark_price: String,    // Kept as the exchange sent them, like `Ticker`
+    pub index_price: String,
+    pub funding_rate: String,  // Fraction per funding interval, e.g. "0.00010000"
+    pub next_funding_ms: Option<u64>,  // When it's next paid, in Unix milliseconds
+}
+
+// What the feed loop needs to know about an exchange
+pub trait Exchange: Send + Sync {
+    // Short lowercase name used in config and output (e.g. "coinbase")
+    fn name(&self) -> &'static str;
+
+    // WebSocket endpoint to connect to
+    fn url(&self) -> &str;
+
+    // Translate one of our symbols ("BTC-USD") into the exchange's own spelling
+    fn native_symbol(&self, symbol: &str) -> String;
+
+    // Message(s) to send right after connecting to subscribe to ticker updates
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String>;
+
+    // Message(s) to stop ticker updates for symbols we no longer track, without reconnecting
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String>;
+
+    // Extract ticker updates from one JSON message. Anything that isn't a ticker
+    // (subscription acks, heartbeats...) simply yields an empty Vec.
+    fn parse(&self, message: &Value) -> Vec<Ticker>;
+
+    // Whether the exchange sends a heartbeat per symbol. Only then can a symbol with no
+    // messages for a while be called stale: otherwise it may just be a quiet market.
+    fn heartbeats(&self) -> bool {
+        false
+    }
+
+    // Exchange-native symbol a heartbeat message is for, if it is one
+    fn parse_heartbeat(&self, _message: &Value) -> Option<String> {
+        None
+    }
+
+    // Exchange-native symbol and sequence number of a message, for channels that number
+    // each symbol's messages, and whether the numbers are contiguous (so a jump means
+    // some were dropped) or only increasing
+    fn parse_sequence(&self, _message: &Value) -> Option<(String, u64, Numbering)> {
+        None
+    }
+
+    // When the exchange says it sent a message, to measure how long it took to reach us.
+    // None when the message carries no timestamp.
+    fn parse_timestamp(&self, _message: &Value) -> Option<SystemTime> {
+        None
+    }
+
+    // REST endpoint listing every product the exchange trades, used to catch typos
+    // in symbol names at startup. None when we don't know one.
+    fn products_url(&self) -> Option<&'static str> {
+        None
+    }
+
+    // Exchange-native symbols of the tradable products in the `products_url` response
+    fn parse_products(&self, _body: &Value) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Extra subscription message(s) for level 2 order book data. Exchanges without
+    // order book support send nothing extra.
+    fn book_subscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Counterpart of `book_subscribe_messages`
+    fn book_unsubscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Extract an order book snapshot or update from one JSON message, if it is one
+    fn parse_book(&self, _message: &Value) -> Option<BookEvent> {
+        None
+    }
+
+    // Extra subscription message(s) for every executed trade (for volume tracking)
+    fn trade_subscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Counterpart of `trade_subscribe_messages`
+    fn trade_unsubscribe_messages(&self, _symbols: &[String]) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Extract live trades from one JSON message. Replays of older trades that some
+    // exchanges send on subscribing are skipped, so a reconnect doesn't count them twice.
+    fn parse_trades(&self, _message: &Value) -> Vec<Trade> {
+        Vec::new()
+    }
+
+    // Extract a futures contract's mark price, index price and funding rate from one JSON
+    // message, if it is one. Spot exchanges never send any.
+    fn parse_funding(&self, _message: &Value) -> Option<Funding> {
+        None
+    }
+}
+
+// Look up an exchange connector by its config name. The DEX connector comes without
+// any pools this way; use `connector` to get one that follows the [dex] settings.
+pub fn by_name(name: &str) -> Option<Box<dyn Exchange>> {
+    connector(name, &DexConfig::default())
+}
+
+// Look up a connector by name, set up with the [dex] settings where they apply
+pub fn connector(name: &str, dex: &DexConfig) -> Option<Box<dyn Exchange>> {
+    match name.trim().to_lowercase().as_str() {
+        "coinbase" => Some(Box::new(Coinbase)),
+        "binance" => Some(Box::new(Binance)),
+        "binance-futures" => Some(Box::new(BinanceFutures)),
+        "kraken" => Some(Box::new(Kraken)),
+        "uniswap" => Some(Box::new(Uniswap::new(dex))),
+        _ => None,
+    }
+}
+
+// Splits our "BASE-QUOTE" symbol format into its two halves
+pub(crate) fn split_symbol(symbol: &str) -> (&str, &str) {
+    symbol.split_once('-').unwrap_or((symbol, ""))
+}
@@ -0,0 +1,254 @@
+// Uniswap v3 pools, read through an Ethereum node's JSON-RPC WebSocket endpoint (a local
+// node or any provider: Infura, Alchemy...). There is no ticker channel on-chain, so:
+//   - `eth_call` of the pool's slot0() gives its current price as soon as we subscribe
+//   - `eth_subscribe` to the pool's Swap events then gives the new price after every swap
+// Each configured pool stands for one of our symbols, so DEX prices land next to the
+// exchanges' for the same pair (and the arbitrage detector compares them like any venue).
+
+use std::collections::HashMap;  // Pool address -> subscription id
+use std::sync::Mutex;           // `parse` only gets &self, and the feed may share us between connections
+
+use rust_decimal::prelude::FromPrimitive;  // Decimal::from_f64
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{Exchange, Ticker};
+
+// keccak256("Swap(address,address,int256,int256,uint160,uint128,int24)"), topic 0 of every swap log
+const SWAP_TOPIC: &str = "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67";
+
+// First 4 bytes of keccak256("slot0()"): sqrtPriceX96, tick, ... of the pool right now
+const SLOT0_SELECTOR: &str = "0x3850c7bd";
+
+// [dex] section of the config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DexConfig {
+    pub url: Option<String>,  // JSON-RPC WebSocket endpoint, e.g. "wss://mainnet.infura.io/ws/v3/<key>"
+    pub pools: Vec<DexPool>,  // Pools to follow ([[dex.pools]] tables)
+}
+
+// One Uniswap v3 pool and the symbol its price is tracked under
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DexPool {
+    pub symbol: String,   // Our symbol, e.g. "ETH-USD" for the USDC/WETH pool
+    pub address: String,  // Pool contract address ("0x" + 40 hex digits)
+    pub decimals0: u32,   // Decimals of the pool's token0 (USDC: 6)
+    pub decimals1: u32,   // ...and of its token1 (WETH: 18)
+    #[serde(default)]
+    pub base: u8,         // Which token is the symbol's base currency: 0 or 1
+}
+
+pub struct Uniswap {
+    url: String,
+    pools: Vec<DexPool>,
+    subscriptions: Mutex<HashMap<String, String>>,  // Pool address -> `eth_subscribe` id, for unsubscribing
+}
+
+impl Uniswap {
+    // A connector for the [dex] settings. Without a URL or pools it still works as a
+    // name (for validation and recordings), it just never delivers a price.
+    pub fn new(config: &DexConfig) -> Self {
+        let pools = config
+            .pools
+            .iter()
+            .map(|pool| DexPool { address: pool.address.to_lowercase(), ..pool.clone() })
+            .collect();
+        Uniswap { url: config.url.clone().unwrap_or_default(), pools, subscriptions: Mutex::new(HashMap::new()) }
+    }
+
+    // The configured pool for one of our symbols, with its position in the list
+    fn pool_for(&self, symbol: &str) -> Option<(usize, &DexPool)> {
+        self.pools.iter().enumerate().find(|(_, pool)| pool.symbol.eq_ignore_ascii_case(symbol))
+    }
+
+    // A ticker for `pool` from the price word and, for swaps, the amounts of both tokens
+    fn ticker(&self, pool: &DexPool, sqrt_price_x96: &str, amounts: Option<(&str, &str)>) -> Option<Ticker> {
+        let price = pool_price(pool, sqrt_price_x96)?;
+        let size = amounts.and_then(|(amount0, amount1)| {
+            let (amount, decimals) = if pool.base == 1 { (amount1, pool.decimals1) } else { (amount0, pool.decimals0) };
+            let size = Decimal::from_f64(abs_int256(amount)? / 10f64.powi(decimals as i32))?;
+            Some(size.round_sf(10)?.normalize().to_string())
+        });
+        Some(Ticker { symbol: pool.address.clone(), price, open_24h: None, size })
+    }
+}
+
+impl Exchange for Uniswap {
+    fn name(&self) -> &'static str {
+        "uniswap"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    // A pool is known by its address; symbols without a pool keep their own name and
+    // are simply never subscribed
+    fn native_symbol(&self, symbol: &str) -> String {
+        match self.pool_for(symbol) {
+            Some((_, pool)) => pool.address.clone(),
+            None => symbol.to_string(),
+        }
+    }
+
+    // Two requests per pool. Their ids say which pool and which request a response is
+    // for: 2k+1 is pool k's slot0() call, 2k+2 its log subscription.
+    fn subscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        let mut messages = Vec::new();
+        for (k, pool) in symbols.iter().filter_map(|s| self.pool_for(s)) {
+            let call = json!({"to": pool.address, "data": SLOT0_SELECTOR});
+            messages.push(request(2 * k as u64 + 1, "eth_call", json!([call, "latest"])));
+            let filter = json!({"address": pool.address, "topics": [SWAP_TOPIC]});
+            messages.push(request(2 * k as u64 + 2, "eth_subscribe", json!(["logs", filter])));
+        }
+        messages
+    }
+
+    // Subscriptions we haven't heard the id of yet can't be cancelled; their logs are
+    // dropped by the feed anyway once the symbol is gone
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Vec<String> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        symbols
+            .iter()
+            .filter_map(|s| self.pool_for(s))
+            .filter_map(|(_, pool)| subscriptions.remove(&pool.address))
+            .map(|id| request(0, "eth_unsubscribe", json!([id])))
+            .collect()
+    }
+
+    fn parse(&self, message: &Value) -> Vec<Ticker> {
+        // Responses to our own requests (id 0, the unsubscribes, needs no answer)
+        if let Some(id) = message["id"].as_u64().filter(|id| *id > 0) {
+            let Some(pool) = self.pools.get(((id - 1) / 2) as usize) else { return Vec::new() };
+            let Some(result) = message["result"].as_str() else {
+                tracing::warn!(pool = %pool.address, error = %message["error"], "JSON-RPC request failed");
+                return Vec::new();
+            };
+            if id.is_multiple_of(2) {
+                self.subscriptions.lock().unwrap().insert(pool.address.clone(), result.to_string());
+                return Vec::new();
+            }
+            // slot0() returns sqrtPriceX96 first; no trade went with it, so no size
+            return word(result, 0).and_then(|price| self.ticker(pool, price, None)).into_iter().collect();
+        }
+
+        // Swap(sender, recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, ...):
+        // the two addresses are indexed topics, the rest is the log's data
+        if message["method"] != "eth_subscription" {
+            return Vec::new();
+        }
+        let log = &message["params"]["result"];
+        if log["removed"].as_bool() == Some(true) {
+            return Vec::new();  // Undone by a chain reorganization
+        }
+        let address = log["address"].as_str().unwrap_or_default().to_lowercase();
+        let data = log["data"].as_str().unwrap_or_default();
+        let Some(pool) = self.pools.iter().find(|pool| pool.address == address) else { return Vec::new() };
+        match (word(data, 0), word(data, 1), word(data, 2)) {
+            (Some(amount0), Some(amount1), Some(price)) => self.ticker(pool, price, Some((amount0, amount1))).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// A JSON-RPC 2.0 request
+fn request(id: u64, method: &str, params: Value) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}).to_string()
+}
+
+// The `index`th 32-byte word of ABI-encoded hex data ("0x" + 64 hex digits per word)
+fn word(data: &str, index: usize) -> Option<&str> {
+    let hex = data.strip_prefix("0x")?;
+    hex.get(index * 64..(index + 1) * 64).filter(|w| w.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+// An unsigned word as a float: exact enough for a price, and it fits 256 bits
+fn uint_to_f64(word: &str) -> Option<f64> {
+    word.chars().try_fold(0.0, |acc, c| Some(acc * 16.0 + c.to_digit(16)? as f64))
+}
+
+// The size of a signed (two's complement) word, whichever direction the tokens went
+fn abs_int256(word: &str) -> Option<f64> {
+    if word.chars().next()?.to_digit(16)? < 8 {
+        return uint_to_f64(word);
+    }
+    // Negative: the magnitude is the bits flipped, plus one
+    let flipped = word.chars().try_fold(0.0, |acc, c| Some(acc * 16.0 + (15 - c.to_digit(16)?) as f64))?;
+    Some(flipped + 1.0)
+}
+
+// Uniswap stores √price × 2^96, where price is token0 in units of token1 (raw amounts,
+// so it still needs the decimals difference). Ten significant digits are plenty, and
+// hide the float noise.
+fn pool_price(pool: &DexPool, sqrt_price_x96: &str) -> Option<String> {
+    let sqrt = uint_to_f64(sqrt_price_x96)? / 2f64.powi(96);
+    let token0_in_token1 = sqrt * sqrt * 10f64.powi(pool.decimals0 as i32 - pool.decimals1 as i32);
+    let price = if pool.base == 1 { 1.0 / token0_in_token1 } else { token0_in_token1 };
+    if !price.is_finite() || price <= 0.0 {
+        return None;
+    }
+    Some(Decimal::from_f64(price)?.round_sf(10)?.normalize().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_prices_from_slot0_and_swap_logs() {
+        // USDC (6 decimals) / WETH (18): ETH's price is token1 in token0
+        let config = DexConfig {
+            url: Some("wss://node.example".into()),
+            pools: vec![DexPool {
+                symbol: "ETH-USD".into(),
+                address: "0x88E6A0c2dDD26FEEb64F039a2c41296FcB3f5640".into(),
+                decimals0: 6,
+                decimals1: 18,
+                base: 1,
+            }],
+        };
+        let uniswap = Uniswap::new(&config);
+        let pool = "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640";
+        assert_eq!(uniswap.native_symbol("ETH-USD"), pool);
+        assert_eq!(uniswap.subscribe_messages(&["ETH-USD".into(), "BTC-USD".into()]).len(), 2, "no pool, no requests");
+
+        // √(1/2000 × 10^12) × 2^96, i.e. 2000 USDC per WETH
+        let sqrt_price = format!("{:0>64}", "5758ae05bbf89b1e32f83635685c");
+        let slot0 = json!({"jsonrpc": "2.0", "id": 1, "result": format!("0x{}{:0>64}", sqrt_price, "1")});
+        assert_eq!(
+            uniswap.parse(&slot0),
+            vec![Ticker { symbol: pool.into(), price: "2000".into(), open_24h: None, size: None }]
+        );
+
+        // The subscription id is kept for unsubscribing
+        assert!(uniswap.parse(&json!({"jsonrpc": "2.0", "id": 2, "result": "0xcd0c"})).is_empty());
+        let unsubscribe: Value = serde_json::from_str(&uniswap.unsubscribe_messages(&["ETH-USD".into()])[0]).unwrap();
+        assert_eq!(unsubscribe["params"], json!(["0xcd0c"]));
+
+        // A swap paying out 3000 USDC for 1.5 WETH
+        let amount0 = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffff4d2fa200";
+        let amount1 = format!("{:0>64}", "14d1120d7b160000");
+        let log = json!({
+            "jsonrpc": "2.0",
+            "method": "eth_subscription",
+            "params": {"subscription": "0xcd0c", "result": {
+                "address": "0x88E6A0c2dDD26FEEb64F039a2c41296FcB3f5640",
+                "topics": [SWAP_TOPIC],
+                "data": format!("0x{}{}{}{:0>64}{:0>64}", amount0, amount1, sqrt_price, "1", "1"),
+                "removed": false
+            }}
+        });
+        assert_eq!(
+            uniswap.parse(&log),
+            vec![Ticker { symbol: pool.into(), price: "2000".into(), open_24h: None, size: Some("1.5".into()) }]
+        );
+        assert_eq!(abs_int256(amount0), Some(3e9));
+
+        let mut removed = log.clone();
+        removed["params"]["result"]["removed"] = json!(true);
+        assert!(uniswap.parse(&removed).is_empty());
+    }
+}
@@ -0,0 +1,424 @@
+// `export`: recorded price history out to a file for pandas, Polars & co., as CSV,
+// JSON (one object per line) or Parquet, optionally resampled into candles.
+//
+// The history comes from the SQLite database (--db) or from files: snapshot CSVs
+// (--snapshots, rotated ones included) and NDJSON output (--output ndjson) both work.
+//
+//     crabbycryptotracker export btc.parquet --symbol BTC-USD --since 2024-06-01 --resample 1m
+//     crabbycryptotracker export --from prices.csv --from prices.1.csv --since 24h > recent.csv
+//
+// Tick columns:   timestamp_ms, exchange, symbol, price
+// Candle columns: timestamp_ms (bucket start), exchange, symbol, open, high, low, close, volume, trades
+//
+// CSV and JSON keep prices as exact decimal text; Parquet stores them as doubles and
+// timestamp_ms as a UTC timestamp, so both load with the right types. Parquet needs the
+// `parquet` feature.
+
+#[cfg(feature = "parquet")]
+mod parquet;
+
+use std::{
+    error::Error,                       // Trait to return errors from our functions
+    fs::File,                           // Snapshot and NDJSON input files
+    io::{self, BufRead, BufReader, Write},  // Reading NDJSON line by line; the output
+    path::Path,                         // Input and output file names
+    str::FromStr,                       // "csv" / "json" / "parquet"
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "parquet")]
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::candles::{Candle, CandleAggregator};
+use crate::exchange;
+use crate::fx::Converted;
+use crate::market::MarketInfo;
+use crate::store::PriceUpdate;
+#[cfg(feature = "parquet")]
+use parquet::Column;
+
+// What to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,      // One object per line (NDJSON)
+    Parquet,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" | "ndjson" | "jsonl" => Ok(Format::Json),
+            "parquet" => Ok(Format::Parquet),
+            other => Err(format!("unknown export format \"{}\" (expected: csv, json, parquet)", other)),
+        }
+    }
+}
+
+impl Format {
+    // From the output file's extension, e.g. "btc.parquet"
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+// One recorded price, wherever it was read from
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Tick {
+    pub timestamp_ms: i64,
+    pub exchange: String,
+    pub symbol: String,
+    pub price: Decimal,
+    #[serde(default)]
+    pub size: Option<Decimal>,   // Trade size, when the source has it (not the database)
+}
+
+impl Tick {
+    // As a live update, received at its timestamp; None for exchanges this build doesn't know
+    pub fn to_update(&self) -> Option<PriceUpdate> {
+        Some(PriceUpdate {
+            exchange: exchange::by_name(&self.exchange)?.name(),
+            symbol: self.symbol.clone(),
+            price: self.price,
+            open_24h: None,
+            size: self.size,
+            received_at: UNIX_EPOCH + Duration::from_millis(self.timestamp_ms.max(0) as u64),
+        })
+    }
+}
+
+// Which ticks to export
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    pub symbol: Option<String>,
+    pub since_ms: Option<i64>,   // Inclusive
+    pub until_ms: Option<i64>,   // Exclusive
+}
+
+impl Filter {
+    pub fn keeps(&self, tick: &Tick) -> bool {
+        self.symbol.as_ref().is_none_or(|s| s.eq_ignore_ascii_case(&tick.symbol)) && self.keeps_time(tick.timestamp_ms)
+    }
+
+    // Whether `timestamp_ms` is within --since/--until
+    pub fn keeps_time(&self, timestamp_ms: i64) -> bool {
+        self.since_ms.is_none_or(|t| timestamp_ms >= t) && self.until_ms.is_none_or(|t| timestamp_ms < t)
+    }
+}
+
+// A --since/--until value: a date ("2024-06-01", midnight UTC), a timestamp
+// ("2024-06-01T12:00:00Z") or a duration back from `now` ("24h", "7d")
+pub fn parse_time(text: &str, now: SystemTime) -> Result<i64, String> {
+    let text = text.trim();
+    let at = if let Ok(ago) = humantime::parse_duration(text) {
+        now.checked_sub(ago).ok_or_else(|| format!("{} ago is before 1970", text))?
+    } else if text.len() == 10 {
+        humantime::parse_rfc3339_weak(&format!("{}T00:00:00Z", text)).map_err(|e| format!("{}: {}", text, e))?
+    } else {
+        humantime::parse_rfc3339_weak(text).map_err(|e| format!("{}: {} (expected a date, a timestamp or a duration like 24h)", text, e))?
+    };
+    Ok(at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as i64))
+}
+
+// Ticks from the SQLite database, oldest first
+#[cfg(feature = "sqlite")]
+pub fn read_database(path: &Path, filter: &Filter) -> Result<Vec<Tick>, Box<dyn Error>> {
+    let symbol = filter.symbol.as_ref().map(|s| s.to_uppercase());
+    let rows = crate::storage::read_range(path, symbol.as_deref(), filter.since_ms, filter.until_ms)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    rows.into_iter()
+        .map(|row| {
+            let price = row.price.parse().map_err(|e| format!("{}: bad price {:?}: {}", path.display(), row.price, e))?;
+            Ok(Tick { timestamp_ms: row.ts_ms, exchange: row.exchange, symbol: row.symbol, price, size: None })
+        })
+        .collect()
+}
+
+// Ticks from a snapshot CSV (by its header) or an NDJSON file (anything else), in file order
+// One line of `--output ndjson`: exchange, symbol, price, open_24h, size and timestamp_ms,
+// plus display_currency and display_value with a display currency and the [market]
+// figures when there are any. `read_file` takes these lines back as ticks.
+pub fn write_update_line(out: &mut impl Write, update: &PriceUpdate, converted: Option<Converted>, market: Option<MarketInfo>) -> io::Result<()> {
+    #[derive(Serialize)]
+    struct Line<'a> {
+        #[serde(flatten)]
+        update: &'a PriceUpdate,
+        #[serde(flatten)]
+        converted: Option<Converted>,
+        #[serde(flatten)]
+        market: Option<MarketInfo>,
+    }
+    serde_json::to_writer(&mut *out, &Line { update, converted, market })?;
+    writeln!(out)
+}
+
+pub fn read_file(path: &Path, filter: &Filter) -> Result<Vec<Tick>, Box<dyn Error>> {
+    let context = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+    let file = File::open(path).map_err(|e| context(&e))?;
+    let mut ticks = Vec::new();
+    if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv")) {
+        // Extra columns (time, open_24h) are ignored; empty sizes are None
+        let mut reader = csv::Reader::from_reader(file);
+        for row in reader.deserialize::<Tick>() {
+            let tick = row.map_err(|e| context(&e))?;
+            if filter.keeps(&tick) {
+                ticks.push(tick);
+            }
+        }
+    } else {
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| context(&e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let tick: Tick = serde_json::from_str(&line).map_err(|e| context(&format!("line {}: {}", i + 1, e)))?;
+            if filter.keeps(&tick) {
+                ticks.push(tick);
+            }
+        }
+    }
+    Ok(ticks)
+}
+
+// Fold ticks (oldest first) into candles of `interval`, with the same aggregator as the
+// live candles. Ticks from exchanges this build doesn't know are skipped.
+pub fn resample(ticks: &[Tick], interval: Duration) -> Vec<Candle> {
+    let mut aggregator = CandleAggregator::new(vec![interval], 0);
+    let mut candles = Vec::new();
+    for update in ticks.iter().filter_map(Tick::to_update) {
+        candles.extend(aggregator.ingest(&update));
+    }
+    candles.extend(aggregator.open_candles());  // The last bucket of every series
+    candles.sort_by(|a, b| (a.start_ms, a.exchange, &a.symbol).cmp(&(b.start_ms, b.exchange, &b.symbol)));
+    candles
+}
+
+// What gets written: raw ticks or candles
+pub enum Table {
+    Ticks(Vec<Tick>),
+    Candles(Vec<Candle>),
+}
+
+impl Table {
+    pub fn len(&self) -> usize {
+        match self {
+            Table::Ticks(ticks) => ticks.len(),
+            Table::Candles(candles) => candles.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Write the table in `format`
+    pub fn write(&self, format: Format, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        match format {
+            Format::Csv => self.write_csv(out),
+            Format::Json => self.write_json(out),
+            #[cfg(feature = "parquet")]
+            Format::Parquet => Ok(parquet::write(out, &self.columns())?),
+            #[cfg(not(feature = "parquet"))]
+            Format::Parquet => Err("this build has no Parquet support".into()),
+        }
+    }
+
+    fn write_csv(&self, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(out);
+        match self {
+            Table::Ticks(ticks) => {
+                writer.write_record(["timestamp_ms", "exchange", "symbol", "price"])?;
+                for t in ticks {
+                    writer.write_record([t.timestamp_ms.to_string(), t.exchange.clone(), t.symbol.clone(), t.price.to_string()])?;
+                }
+            }
+            Table::Candles(candles) => {
+                writer.write_record(["timestamp_ms", "exchange", "symbol", "open", "high", "low", "close", "volume", "trades"])?;
+                for c in candles {
+                    writer.write_record([
+                        c.start_ms.to_string(),
+                        c.exchange.to_string(),
+                        c.symbol.clone(),
+                        c.open.to_string(),
+                        c.high.to_string(),
+                        c.low.to_string(),
+                        c.close.to_string(),
+                        c.volume.to_string(),
+                        c.trades.to_string(),
+                    ])?;
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn write_json(&self, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        // Fields in the same order as the CSV columns
+        #[derive(Serialize)]
+        struct TickLine<'a> {
+            timestamp_ms: i64,
+            exchange: &'a str,
+            symbol: &'a str,
+            price: Decimal,
+        }
+        #[derive(Serialize)]
+        struct CandleLine<'a> {
+            timestamp_ms: u64,
+            exchange: &'a str,
+            symbol: &'a str,
+            open: Decimal,
+            high: Decimal,
+            low: Decimal,
+            close: Decimal,
+            volume: Decimal,
+            trades: u64,
+        }
+
+        match self {
+            Table::Ticks(ticks) => {
+                for t in ticks {
+                    let line = TickLine { timestamp_ms: t.timestamp_ms, exchange: &t.exchange, symbol: &t.symbol, price: t.price };
+                    serde_json::to_writer(&mut *out, &line)?;
+                    writeln!(out)?;
+                }
+            }
+            Table::Candles(candles) => {
+                for c in candles {
+                    let line = CandleLine {
+                        timestamp_ms: c.start_ms,
+                        exchange: c.exchange,
+                        symbol: &c.symbol,
+                        open: c.open,
+                        high: c.high,
+                        low: c.low,
+                        close: c.close,
+                        volume: c.volume,
+                        trades: c.trades,
+                    };
+                    serde_json::to_writer(&mut *out, &line)?;
+                    writeln!(out)?;
+                }
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    // The same columns, typed for Parquet
+    #[cfg(feature = "parquet")]
+    fn columns(&self) -> Vec<Column> {
+        let float = |d: &Decimal| d.to_f64().unwrap_or(f64::NAN);
+        match self {
+            Table::Ticks(ticks) => vec![
+                Column::Timestamp("timestamp_ms", ticks.iter().map(|t| t.timestamp_ms).collect()),
+                Column::Text("exchange", ticks.iter().map(|t| t.exchange.clone()).collect()),
+                Column::Text("symbol", ticks.iter().map(|t| t.symbol.clone()).collect()),
+                Column::Double("price", ticks.iter().map(|t| float(&t.price)).collect()),
+            ],
+            Table::Candles(candles) => vec![
+                Column::Timestamp("timestamp_ms", candles.iter().map(|c| c.start_ms as i64).collect()),
+                Column::Text("exchange", candles.iter().map(|c| c.exchange.to_string()).collect()),
+                Column::Text("symbol", candles.iter().map(|c| c.symbol.clone()).collect()),
+                Column::Double("open", candles.iter().map(|c| float(&c.open)).collect()),
+                Column::Double("high", candles.iter().map(|c| float(&c.high)).collect()),
+                Column::Double("low", candles.iter().map(|c| float(&c.low)).collect()),
+                Column::Double("close", candles.iter().map(|c| float(&c.close)).collect()),
+                Column::Double("volume", candles.iter().map(|c| float(&c.volume)).collect()),
+                Column::Int("trades", candles.iter().map(|c| c.trades as i64).collect()),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(timestamp_ms: i64, symbol: &str, price: &str) -> Tick {
+        Tick { timestamp_ms, exchange: "coinbase".to_string(), symbol: symbol.to_string(), price: price.parse().unwrap(), size: None }
+    }
+
+    #[test]
+    fn files_are_filtered_resampled_and_written() {
+        let path = std::env::temp_dir().join(format!("crabby-export-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "time,timestamp_ms,exchange,symbol,price,open_24h,size\n\
+             2024-06-10T06:13:20.000Z,1718000000000,coinbase,BTC-USD,65000,,0.5\n\
+             2024-06-10T06:13:30.000Z,1718000010000,coinbase,ETH-USD,3500,,\n\
+             2024-06-10T06:13:50.000Z,1718000030000,coinbase,BTC-USD,65100.5,,0.25\n\
+             2024-06-10T06:14:20.000Z,1718000060000,coinbase,BTC-USD,64900,,\n",
+        )
+        .unwrap();
+        let filter = Filter { symbol: Some("btc-usd".to_string()), until_ms: Some(1_718_000_060_000), ..Filter::default() };
+        let ticks = read_file(&path, &filter).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ticks.len(), 2);
+        assert_eq!(ticks[1].size, Some("0.25".parse().unwrap()));
+
+        let mut csv = Vec::new();
+        Table::Ticks(vec![tick(1, "BTC-USD", "65000.10")]).write(Format::Csv, &mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "timestamp_ms,exchange,symbol,price\n1,coinbase,BTC-USD,65000.10\n");
+
+        assert!(read_file(&path, &Filter::default()).is_err(), "gone");
+
+        // 1718000000000 is 06:13:20, so the first two ticks share the 06:13 minute
+        let ticks = [tick(1_718_000_000_000, "BTC-USD", "65000"), tick(1_718_000_030_000, "BTC-USD", "65100"), tick(1_718_000_060_000, "BTC-USD", "64900")];
+        let candles = resample(&ticks, Duration::from_secs(60));
+        assert_eq!(candles.len(), 2);
+        assert_eq!((candles[0].start_ms, candles[0].open, candles[0].close, candles[0].trades), (1_717_999_980_000, 65000.into(), 65100.into(), 2));
+        let mut json = Vec::new();
+        Table::Candles(candles).write(Format::Json, &mut json).unwrap();
+        let first: serde_json::Value = serde_json::from_slice(json.split(|b| *b == b'\n').next().unwrap()).unwrap();
+        assert_eq!((first["high"].as_str(), first["trades"].as_u64()), (Some("65100"), Some(2)));
+
+        let since = parse_time("2024-06-10", SystemTime::now()).unwrap();
+        assert_eq!(since, 1_717_977_600_000);
+        assert_eq!(parse_time("1h", UNIX_EPOCH + Duration::from_secs(7200)), Ok(3_600_000));
+        assert_eq!(Format::from_path(Path::new("out.PARQUET")), Some(Format::Parquet));
+    }
+
+    #[test]
+    fn update_lines_are_one_object_each_and_read_back() {
+        let update = |symbol: &str, price: &str, ms: u64| PriceUpdate {
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            price: price.parse().unwrap(),
+            open_24h: Some("64000".parse().unwrap()),
+            size: None,
+            received_at: UNIX_EPOCH + Duration::from_millis(ms),
+        };
+        let mut out = Vec::new();
+        write_update_line(&mut out, &update("BTC-USD", "65000.10", 1_718_000_000_123), None, None).unwrap();
+        let eur = Converted { currency: "EUR".to_string(), amount: "3220.50".parse().unwrap() };
+        let market = MarketInfo { market_cap: Some("420000000000".parse().unwrap()), rank: Some(2), circulating_supply: None, volume_24h_usd: None, quote: "USD".to_string() };
+        write_update_line(&mut out, &update("ETH-USD", "3500.25", 1_718_000_001_000), Some(eur), Some(market)).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.ends_with('\n'));
+        let lines: Vec<serde_json::Value> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2, "{}", text);
+        assert_eq!(
+            lines[0],
+            serde_json::json!({"exchange": "coinbase", "symbol": "BTC-USD", "price": "65000.10", "open_24h": "64000", "size": null, "timestamp_ms": 1_718_000_000_123u64})
+        );
+        // The display currency and market figures are extra fields on the same object
+        assert_eq!((lines[1]["symbol"].as_str(), lines[1]["price"].as_str()), (Some("ETH-USD"), Some("3500.25")));
+        assert_eq!((lines[1]["display_currency"].as_str(), lines[1]["display_value"].as_str()), (Some("EUR"), Some("3220.50")));
+        assert_eq!((lines[1]["market_cap"].as_str(), lines[1]["market_cap_rank"].as_u64()), (Some("420000000000"), Some(2)));
+        assert!(lines[1].get("circulating_supply").is_none() && lines[1].get("quote").is_none());
+
+        // ...and `export --from` reads them back
+        let path = std::env::temp_dir().join(format!("crabby-output-{}.ndjson", std::process::id()));
+        std::fs::write(&path, &text).unwrap();
+        let ticks = read_file(&path, &Filter::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ticks, [tick(1_718_000_000_123, "BTC-USD", "65000.10"), tick(1_718_000_001_000, "ETH-USD", "3500.25")]);
+    }
+}
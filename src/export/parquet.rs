@@ -0,0 +1,567 @@
+// Just enough of the Parquet format to hand a table to pandas, Polars or DuckDB: one
+// row group, one uncompressed PLAIN-encoded page per column, every column required.
+// No Arrow dependency for what is a few hundred kilobytes of ticks.
+//
+// A file is "PAR1", the column chunks one after another, then the metadata (Thrift
+// compact protocol), its length as 4 bytes little-endian, and "PAR1" again.
+// Format reference: https://github.com/apache/parquet-format
+
+use std::io::{self, Write};
+
+const MAGIC: &[u8] = b"PAR1";
+
+// Physical types
+const INT64: i32 = 2;
+const DOUBLE: i32 = 5;
+const BYTE_ARRAY: i32 = 6;
+
+const REQUIRED: i32 = 0;   // Repetition: no nulls, so no definition levels in the pages
+const PLAIN: i32 = 0;      // Encoding
+const RLE: i32 = 3;        // Level encoding (unused, but the page header names one)
+const UNCOMPRESSED: i32 = 0;
+const DATA_PAGE: i32 = 0;
+
+// Converted types, for readers that predate logical types
+const UTF8: i32 = 0;
+const TIMESTAMP_MILLIS: i32 = 9;
+
+// One column of the table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Timestamp(&'static str, Vec<i64>),   // Milliseconds since the Unix epoch, UTC
+    Int(&'static str, Vec<i64>),
+    Double(&'static str, Vec<f64>),
+    Text(&'static str, Vec<String>),
+}
+
+impl Column {
+    fn name(&self) -> &'static str {
+        match self {
+            Column::Timestamp(name, _) | Column::Int(name, _) | Column::Double(name, _) | Column::Text(name, _) => name,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Column::Timestamp(_, v) | Column::Int(_, v) => v.len(),
+            Column::Double(_, v) => v.len(),
+            Column::Text(_, v) => v.len(),
+        }
+    }
+
+    fn physical_type(&self) -> i32 {
+        match self {
+            Column::Timestamp(..) | Column::Int(..) => INT64,
+            Column::Double(..) => DOUBLE,
+            Column::Text(..) => BYTE_ARRAY,
+        }
+    }
+
+    // The values, PLAIN-encoded: little-endian numbers, strings as length + bytes
+    fn plain(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Column::Timestamp(_, v) | Column::Int(_, v) => v.iter().for_each(|n| out.extend(n.to_le_bytes())),
+            Column::Double(_, v) => v.iter().for_each(|n| out.extend(n.to_le_bytes())),
+            Column::Text(_, v) => {
+                for s in v {
+                    out.extend((s.len() as u32).to_le_bytes());
+                    out.extend(s.as_bytes());
+                }
+            }
+        }
+        out
+    }
+}
+
+// Where each column chunk ended up, for the metadata
+struct Chunk {
+    offset: u64,   // Of its page header
+    size: u64,     // Header and data
+}
+
+// Write `columns` (all the same length) as a Parquet file
+pub fn write(out: &mut impl Write, columns: &[Column]) -> io::Result<()> {
+    let rows = columns.first().map_or(0, Column::len);
+    if columns.iter().any(|c| c.len() != rows) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "columns of different lengths"));
+    }
+
+    out.write_all(MAGIC)?;
+    let mut position = MAGIC.len() as u64;
+    let mut chunks = Vec::new();
+    for column in columns {
+        let data = column.plain();
+        let header = page_header(rows, data.len());
+        out.write_all(&header)?;
+        out.write_all(&data)?;
+        let size = (header.len() + data.len()) as u64;
+        chunks.push(Chunk { offset: position, size });
+        position += size;
+    }
+
+    let metadata = file_metadata(columns, &chunks, rows);
+    out.write_all(&metadata)?;
+    out.write_all(&(metadata.len() as u32).to_le_bytes())?;
+    out.write_all(MAGIC)
+}
+
+fn page_header(rows: usize, data_len: usize) -> Vec<u8> {
+    let mut t = Thrift::default();
+    t.i32(1, DATA_PAGE);
+    t.i32(2, data_len as i32);   // Uncompressed size...
+    t.i32(3, data_len as i32);   // ...and compressed size, the same
+    t.begin_struct(5);           // DataPageHeader
+    t.i32(1, rows as i32);
+    t.i32(2, PLAIN);
+    t.i32(3, RLE);
+    t.i32(4, RLE);
+    t.end_struct();
+    t.finish()
+}
+
+fn file_metadata(columns: &[Column], chunks: &[Chunk], rows: usize) -> Vec<u8> {
+    let mut t = Thrift::default();
+    t.i32(1, 1);  // Format version
+
+    // The schema is a tree flattened into a list: the root, then its columns
+    t.begin_list(2, STRUCT, columns.len() + 1);
+    t.begin_element();
+    t.binary(4, b"schema");
+    t.i32(5, columns.len() as i32);
+    t.end_struct();
+    for column in columns {
+        t.begin_element();
+        t.i32(1, column.physical_type());
+        t.i32(3, REQUIRED);
+        t.binary(4, column.name().as_bytes());
+        match column {
+            Column::Timestamp(..) => {
+                t.i32(6, TIMESTAMP_MILLIS);
+                t.begin_struct(10);  // LogicalType
+                t.begin_struct(8);   // TIMESTAMP
+                t.bool(1, true);     // isAdjustedToUTC
+                t.begin_struct(2);   // unit
+                t.begin_struct(1);   // MILLIS
+                t.end_struct();
+                t.end_struct();
+                t.end_struct();
+                t.end_struct();
+            }
+            Column::Text(..) => {
+                t.i32(6, UTF8);
+                t.begin_struct(10);  // LogicalType
+                t.begin_struct(1);   // STRING
+                t.end_struct();
+                t.end_struct();
+            }
+            Column::Int(..) | Column::Double(..) => {}
+        }
+        t.end_struct();
+    }
+
+    t.i64(3, rows as i64);
+
+    // One row group holding every column chunk
+    let total: u64 = chunks.iter().map(|c| c.size).sum();
+    t.begin_list(4, STRUCT, 1);
+    t.begin_element();
+    t.begin_list(1, STRUCT, columns.len());
+    for (column, chunk) in columns.iter().zip(chunks) {
+        t.begin_element();
+        t.i64(2, chunk.offset as i64);
+        t.begin_struct(3);  // ColumnMetaData
+        t.i32(1, column.physical_type());
+        t.begin_list(2, I32, 1);
+        t.list_i32(PLAIN);
+        t.begin_list(3, BINARY, 1);
+        t.list_binary(column.name().as_bytes());
+        t.i32(4, UNCOMPRESSED);
+        t.i64(5, rows as i64);
+        t.i64(6, chunk.size as i64);
+        t.i64(7, chunk.size as i64);
+        t.i64(9, chunk.offset as i64);
+        t.end_struct();
+        t.end_struct();
+    }
+    t.i64(2, total as i64);
+    t.i64(3, rows as i64);
+    t.end_struct();
+
+    t.binary(6, concat!("crabbycryptotracker ", env!("CARGO_PKG_VERSION")).as_bytes());
+    t.finish()
+}
+
+// Thrift compact protocol type ids
+const BOOL_TRUE: u8 = 1;
+const BOOL_FALSE: u8 = 2;
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+// Thrift compact protocol writer. Field ids are written as deltas from the previous
+// field of the same struct, so it keeps one "last id" per open struct.
+#[derive(Default)]
+struct Thrift {
+    out: Vec<u8>,
+    last: i16,             // Last field id in the current struct
+    outer: Vec<i16>,       // ...and in the structs around it
+}
+
+impl Thrift {
+    fn field(&mut self, id: i16, kind: u8) {
+        let delta = id - self.last;
+        if (1..=15).contains(&delta) {
+            self.out.push((delta as u8) << 4 | kind);
+        } else {
+            self.out.push(kind);
+            self.varint(zigzag(id.into()));
+        }
+        self.last = id;
+    }
+
+    fn varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.out.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.out.push(n as u8);
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, I32);
+        self.varint(zigzag(value.into()));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, I64);
+        self.varint(zigzag(value));
+    }
+
+    fn bool(&mut self, id: i16, value: bool) {
+        self.field(id, if value { BOOL_TRUE } else { BOOL_FALSE });
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field(id, BINARY);
+        self.list_binary(value);
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, STRUCT);
+        self.begin_element();
+    }
+
+    // A struct inside a list: no field header
+    fn begin_element(&mut self) {
+        self.outer.push(self.last);
+        self.last = 0;
+    }
+
+    fn end_struct(&mut self) {
+        self.out.push(0);  // Stop
+        self.last = self.outer.pop().unwrap_or(0);
+    }
+
+    fn begin_list(&mut self, id: i16, element: u8, len: usize) {
+        self.field(id, LIST);
+        if len < 15 {
+            self.out.push((len as u8) << 4 | element);
+        } else {
+            self.out.push(0xf0 | element);
+            self.varint(len as u64);
+        }
+    }
+
+    fn list_i32(&mut self, value: i32) {
+        self.varint(zigzag(value.into()));
+    }
+
+    fn list_binary(&mut self, value: &[u8]) {
+        self.varint(value.len() as u64);
+        self.out.extend(value);
+    }
+
+    // The whole top-level struct
+    fn finish(mut self) -> Vec<u8> {
+        self.out.push(0);
+        self.out
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    // A Thrift compact protocol value, as far as the writer uses them
+    #[derive(Debug, Clone, PartialEq)]
+    enum Value {
+        Bool(bool),
+        Int(i64),
+        Binary(Vec<u8>),
+        List(Vec<Value>),
+        Struct(BTreeMap<i16, Value>),
+    }
+
+    impl Value {
+        fn field(&self, id: i16) -> Result<&Value, String> {
+            match self {
+                Value::Struct(fields) => fields.get(&id).ok_or(format!("no field {}", id)),
+                _ => Err("not a struct".to_string()),
+            }
+        }
+
+        fn int(&self, id: i16) -> Result<i64, String> {
+            match self.field(id)? {
+                Value::Int(n) => Ok(*n),
+                other => Err(format!("field {}: {:?} is not an integer", id, other)),
+            }
+        }
+
+        fn list(&self, id: i16) -> Result<&[Value], String> {
+            match self.field(id)? {
+                Value::List(items) => Ok(items),
+                other => Err(format!("field {}: {:?} is not a list", id, other)),
+            }
+        }
+
+        fn text(&self, id: i16) -> Result<String, String> {
+            match self.field(id)? {
+                Value::Binary(bytes) => String::from_utf8(bytes.clone()).map_err(|e| e.to_string()),
+                other => Err(format!("field {}: {:?} is not binary", id, other)),
+            }
+        }
+    }
+
+    // Thrift compact protocol reader
+    struct Reader<'a>(&'a [u8]);
+
+    impl Reader<'_> {
+        fn byte(&mut self) -> Result<u8, String> {
+            let (first, rest) = self.0.split_first().ok_or("cut short")?;
+            self.0 = rest;
+            Ok(*first)
+        }
+
+        fn varint(&mut self) -> Result<u64, String> {
+            let (mut n, mut shift) = (0u64, 0);
+            loop {
+                let byte = self.byte()?;
+                n |= ((byte & 0x7f) as u64).checked_shl(shift).ok_or("varint too long")?;
+                if byte < 0x80 {
+                    return Ok(n);
+                }
+                shift += 7;
+            }
+        }
+
+        fn int(&mut self) -> Result<i64, String> {
+            let n = self.varint()?;
+            Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+        }
+
+        fn value(&mut self, kind: u8) -> Result<Value, String> {
+            Ok(match kind {
+                BOOL_TRUE => Value::Bool(true),
+                BOOL_FALSE => Value::Bool(false),
+                I32 | I64 => Value::Int(self.int()?),
+                BINARY => {
+                    let len = self.varint()? as usize;
+                    let (bytes, rest) = self.0.split_at_checked(len).ok_or("cut short")?;
+                    self.0 = rest;
+                    Value::Binary(bytes.to_vec())
+                }
+                LIST => {
+                    let header = self.byte()?;
+                    let len = match header >> 4 {
+                        15 => self.varint()? as usize,
+                        len => len as usize,
+                    };
+                    Value::List((0..len).map(|_| self.value(header & 0x0f)).collect::<Result<_, _>>()?)
+                }
+                STRUCT => {
+                    let (mut fields, mut last) = (BTreeMap::new(), 0i16);
+                    loop {
+                        let header = self.byte()?;
+                        if header == 0 {
+                            break Value::Struct(fields);
+                        }
+                        last = match header >> 4 {
+                            0 => self.int()? as i16,
+                            delta => last + delta as i16,
+                        };
+                        fields.insert(last, self.value(header & 0x0f)?);
+                    }
+                }
+                other => return Err(format!("unknown type {}", other)),
+            })
+        }
+    }
+
+    // One Thrift struct at the start of `bytes`, and how many bytes it took
+    fn read_struct(bytes: &[u8]) -> Result<(Value, usize), String> {
+        let mut reader = Reader(bytes);
+        let value = reader.value(STRUCT)?;
+        Ok((value, bytes.len() - reader.0.len()))
+    }
+
+    // The columns back out of a file, the way a reader finds them: footer, metadata,
+    // then each chunk's page header and values
+    fn read_file(file: &[u8]) -> Result<Vec<Column>, String> {
+        if file.len() < 12 || !file.starts_with(MAGIC) || !file.ends_with(MAGIC) {
+            return Err("not a Parquet file".to_string());
+        }
+        let footer = u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap()) as usize;
+        let start = (file.len() - 8).checked_sub(footer).filter(|s| *s >= 4).ok_or("footer length past the start")?;
+        let (metadata, used) = read_struct(&file[start..file.len() - 8])?;
+        if used != footer {
+            return Err("metadata shorter than its footer".to_string());
+        }
+        let rows = metadata.int(3)? as usize;
+        let schema = metadata.list(2)?;
+        let chunks = metadata.list(4)?.first().ok_or("no row group")?.list(1)?;
+        if schema.first().ok_or("no schema")?.int(5)? as usize != chunks.len() || schema.len() != chunks.len() + 1 {
+            return Err("schema and chunks disagree".to_string());
+        }
+
+        let mut columns = Vec::new();
+        for (element, chunk) in schema[1..].iter().zip(chunks) {
+            let meta = chunk.field(3)?;
+            let offset = meta.int(9)? as usize;
+            let (header, header_len) = read_struct(file.get(offset..start).ok_or("chunk past the metadata")?)?;
+            let data_len = header.int(3)? as usize;
+            if header.int(1)? != DATA_PAGE as i64 || header_len + data_len != meta.int(7)? as usize {
+                return Err("bad page header".to_string());
+            }
+            let values = header.field(5)?.int(1)? as usize;
+            let data = file.get(offset + header_len..offset + header_len + data_len).ok_or("page past the metadata")?;
+            if values != rows || meta.int(5)? as usize != rows {
+                return Err("value count differs from the row count".to_string());
+            }
+
+            let name: &'static str = Box::leak(element.text(4)?.into_boxed_str());
+            let numbers = || data.chunks_exact(8).map(|b| b.try_into().unwrap());
+            let column = match (element.int(1)? as i32, element.int(6).ok().map(|t| t as i32)) {
+                (INT64, Some(TIMESTAMP_MILLIS)) => Column::Timestamp(name, numbers().map(i64::from_le_bytes).collect()),
+                (INT64, None) => Column::Int(name, numbers().map(i64::from_le_bytes).collect()),
+                (DOUBLE, None) => Column::Double(name, numbers().map(f64::from_le_bytes).collect()),
+                (BYTE_ARRAY, Some(UTF8)) => {
+                    let (mut texts, mut rest) = (Vec::new(), data);
+                    while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+                        let (text, tail) = tail.split_at_checked(u32::from_le_bytes(*len) as usize).ok_or("string cut short")?;
+                        texts.push(String::from_utf8(text.to_vec()).map_err(|e| e.to_string())?);
+                        rest = tail;
+                    }
+                    Column::Text(name, texts)
+                }
+                other => return Err(format!("unexpected column type {:?}", other)),
+            };
+            if column.len() != rows {
+                return Err(format!("{}: {} values for {} rows", name, column.len(), rows));
+            }
+            columns.push(column);
+        }
+        Ok(columns)
+    }
+
+    fn file(columns: &[Column]) -> Vec<u8> {
+        let mut file = Vec::new();
+        write(&mut file, columns).unwrap();
+        file
+    }
+
+    #[test]
+    fn every_column_type_round_trips() {
+        let columns = vec![
+            Column::Timestamp("timestamp_ms", vec![1_718_000_000_000, -1, 0]),
+            Column::Text("symbol", vec!["BTC-USD".to_string(), String::new(), "€URO-ÜSD".to_string()]),
+            Column::Double("price", vec![65000.5, f64::MIN_POSITIVE, -0.0]),
+            Column::Int("trades", vec![i64::MAX, i64::MIN, 7]),
+        ];
+        assert_eq!(read_file(&file(&columns)).unwrap(), columns);
+
+        // No rows at all, and more columns than a short list header holds (15+)
+        let empty = vec![Column::Int("n", Vec::new()), Column::Text("s", Vec::new())];
+        assert_eq!(read_file(&file(&empty)).unwrap(), empty);
+        let wide: Vec<Column> = (0..20).map(|i| Column::Int(["c"; 20][i], vec![i as i64])).collect();
+        assert_eq!(read_file(&file(&wide)).unwrap(), wide);
+    }
+
+    #[test]
+    fn truncated_or_corrupted_files_do_not_decode() {
+        let columns = [Column::Timestamp("t", vec![1, 2]), Column::Text("s", vec!["a".to_string(), "bc".to_string()])];
+        let good = file(&columns);
+        for len in 0..good.len() {
+            assert!(read_file(&good[..len]).is_err(), "cut at {}", len);
+        }
+
+        // A footer length that doesn't match the metadata
+        let mut bad = good.clone();
+        let at = bad.len() - 8;
+        bad[at] = bad[at].wrapping_add(1);
+        assert!(read_file(&bad).is_err());
+
+        // A page header that lies about its size
+        let mut bad = good.clone();
+        let size_field = 4 + 2;  // After the magic and the page type field: field 2's value
+        bad[size_field] += 2;
+        assert!(read_file(&bad).is_err());
+    }
+
+    #[test]
+    fn write_errors_are_passed_on() {
+        // A destination that fails partway through
+        struct Full(usize);
+        impl Write for Full {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.0 < buf.len() {
+                    return Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"));
+                }
+                self.0 -= buf.len();
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let columns = [Column::Double("price", vec![1.0; 100])];
+        let err = write(&mut Full(100), &columns).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+        let err = write(&mut Vec::new(), &[Column::Int("a", vec![1]), Column::Int("b", vec![])]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn file_has_magic_pages_and_footer() {
+        let columns = [
+            Column::Timestamp("timestamp_ms", vec![1_718_000_000_000, 1_718_000_001_000]),
+            Column::Text("symbol", vec!["BTC-USD".to_string(), "ETH-USD".to_string()]),
+            Column::Double("price", vec![65000.5, 3500.25]),
+        ];
+        let mut file = Vec::new();
+        write(&mut file, &columns).unwrap();
+
+        assert_eq!((&file[..4], &file[file.len() - 4..]), (MAGIC, MAGIC));
+        let footer = u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap()) as usize;
+        let metadata = &file[file.len() - 8 - footer..file.len() - 8];
+        assert_eq!(metadata.last(), Some(&0), "metadata ends with a stop byte");
+        assert!(metadata.windows(12).any(|w| w == b"timestamp_ms"));
+
+        // The first page follows the magic: header, then the two timestamps
+        let data = 1_718_000_000_000i64.to_le_bytes();
+        let first = file.windows(8).position(|w| w == data).unwrap();
+        assert_eq!(file[4], 1 << 4 | I32, "page type is field 1");
+        assert!(first < 30, "page header is short");
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(3), 6);
+        assert!(write(&mut Vec::new(), &[Column::Int("a", vec![1]), Column::Int("b", vec![])]).is_err());
+    }
+}
@@ -0,0 +1,606 @@
+// The WebSocket feed: connects to one exchange, subscribes, validates and parses
+// every frame, writes prices into the store, and reconnects with backoff on failure.
+// The set of symbols can change while connected: the feed subscribes to added
+// symbols and unsubscribes from removed ones on the open connection.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},  // Tracked symbols; exchange-native symbol -> our symbol; stale symbols
+    error::Error,             // Trait to return errors from our functions
+    sync::Arc,                // Exchange connectors are shared with spawned tasks
+    time::{Instant, SystemTime},  // Processing latency; timestamp for each update
+};
+
+use futures_util::{SinkExt, StreamExt};            // For working with WebSocket input/output
+use tokio::sync::watch;                            // Symbol set and shutdown signal
+use tokio::time::{sleep, Duration};                // Async sleep and timing
+use tokio_tungstenite::tungstenite::Message;       // A single WebSocket message (text, binary, ping...)
+use tracing::{debug, info, info_span, warn, Instrument};  // Logging, with one span per connection
+
+use crate::backoff::Backoff;
+use crate::exchange::Exchange;
+use crate::funding::FundingInfo;
+use crate::recording::RecordSink;
+use crate::sequence::{SequenceCheck, SequenceTracker};
+use crate::source::FeedSource;
+use crate::store::{parse_decimal, PriceStore, PriceUpdate};
+
+// Why a text frame from the feed could not be used
+#[derive(Debug)]
+pub enum FrameError {
+    Truncated(serde_json::Error),  // JSON ended early (e.g. a fragmented frame that was cut off)
+    Invalid(serde_json::Error),    // Complete frame, but not valid JSON
+}
+
+// Human-readable description used in log lines
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Truncated(e) => write!(f, "truncated frame: {}", e),
+            FrameError::Invalid(e) => write!(f, "invalid JSON: {}", e),
+        }
+    }
+}
+
+// Running counters of bad frames, so problems are visible instead of silently dropped
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    pub truncated: u64,  // Frames that ended before the JSON document was complete
+    pub invalid: u64,    // Frames that were complete but malformed
+    pub bad_prices: u64, // Tickers whose price/size wasn't a valid number
+    pub dropped: u64,    // Messages that never arrived, going by sequence numbers
+    pub sequences: SequenceTracker,  // Last sequence number per symbol
+    pub stale: HashSet<String>,      // Symbols marked stale in the store, until data arrives again
+}
+
+impl FrameStats {
+    // Count one bad frame under the right bucket
+    pub fn record(&mut self, err: &FrameError) {
+        match err {
+            FrameError::Truncated(_) => self.truncated += 1,
+            FrameError::Invalid(_) => self.invalid += 1,
+        }
+    }
+}
+
+// Checks that a (reassembled) text frame holds one complete JSON document before we
+// try to interpret it as a ticker. Large messages such as order book snapshots can
+// arrive in several WebSocket fragments; tungstenite joins them back together, but if
+// the stream is cut mid-message we want to know about it rather than guess.
+pub fn validate_text_frame(text: &str) -> Result<serde_json::Value, FrameError> {
+    serde_json::from_str::<serde_json::Value>(text).map_err(|e| {
+        if e.is_eof() {
+            FrameError::Truncated(e)  // Input ran out in the middle of a value
+        } else {
+            FrameError::Invalid(e)    // Syntax error or trailing garbage
+        }
+    })
+}
+
+// How often symbols are checked for staleness
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Per-feed settings chosen by the tracker
+#[derive(Clone)]
+pub struct FeedOptions {
+    pub order_books: bool,               // Subscribe to and maintain level 2 order books
+    pub trades: bool,                    // Subscribe to every trade, for volume tracking
+    pub stale_after: Option<Duration>,   // Reconnect when a symbol has been silent this long
+    pub resync_on_gap: bool,             // Resubscribe a symbol when its sequence numbers jump
+    pub recorder: Option<RecordSink>,    // Where to copy every raw frame (--record)
+    pub source: Arc<dyn FeedSource>,     // Where connections come from: the exchange's WebSocket, or a mock
+}
+
+// How a single connection ended
+enum ConnectionEnd {
+    Closed,    // The server closed the stream
+    Stale,     // Some symbols went silent; reconnecting usually brings them back
+    Shutdown,  // We were asked to stop
+}
+
+// Keep one exchange connected to the symbols in `symbols`, reconnecting with backoff
+// whenever the connection drops, until `shutdown` flips to true (or its sender goes away).
+// Each connection gets its own "connection" span; `PriceTracker` wraps the whole feed
+// in a "feed" span carrying the exchange name.
+pub async fn run_feed(
+    exchange: Arc<dyn Exchange>,
+    mut symbols: watch::Receiver<BTreeSet<String>>,
+    store: PriceStore,
+    options: FeedOptions,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+    let mut frame_stats = FrameStats::default();  // Counters for truncated/invalid frames, kept across reconnects (not the sequence numbers)
+    let mut attempt = 0u64;
+
+    while !*shutdown.borrow() {
+        attempt += 1;
+        let connection_span = info_span!("connection", url = exchange.url(), attempt);
+        match run_connection(exchange.as_ref(), &mut symbols, &store, &options, &mut frame_stats, &mut backoff, &mut shutdown)
+            .instrument(connection_span)
+            .await
+        {
+            Ok(ConnectionEnd::Shutdown) => break,
+            Ok(ConnectionEnd::Closed) => warn!("WebSocket closed by server"),
+            Ok(ConnectionEnd::Stale) => {}  // Already logged, with the symbols
+
+            Err(e) => warn!(error = %e, "WebSocket error"),
+        }
+        // Missed order book changes can't be replayed; the next snapshot rebuilds the books.
+        // Only this connection's: with sharding, the exchange's other connections are fine.
+        let tracked: Vec<String> = symbols.borrow().iter().cloned().collect();
+        for symbol in &tracked {
+            store.forget_book(exchange.name(), symbol).await;
+        }
+
+        // Wait a little longer after each consecutive failure before trying again
+        let delay = backoff.next_delay();
+        store.metrics().record_reconnect(exchange.name());
+        info!(delay = ?delay, "Reconnecting");
+        tokio::select! {
+            _ = sleep(delay) => {}
+            _ = shutdown.changed() => break,  // Don't sit out the backoff when asked to stop
+        }
+    }
+    info!("Feed stopped");
+}
+
+// One connection's lifetime: connect, subscribe, then read messages until the stream
+// ends, fails (Err), a symbol goes stale, or shutdown is requested. The caller decides
+// when to reconnect.
+async fn run_connection(
+    exchange: &dyn Exchange,
+    symbols: &mut watch::Receiver<BTreeSet<String>>,
+    store: &PriceStore,
+    options: &FeedOptions,
+    frame_stats: &mut FrameStats,
+    backoff: &mut Backoff,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<ConnectionEnd, Box<dyn Error>> {
+    // What this connection is subscribed to, and how the exchange spells each of those symbols
+    let mut subscribed = symbols.borrow_and_update().clone();
+    let mut to_common = native_symbols(exchange, &subscribed);
+
+    // A new connection numbers from wherever the exchange is now: what was missed while
+    // disconnected isn't a gap in this stream
+    for symbol in &subscribed {
+        frame_stats.sequences.forget(symbol);
+    }
+
+    // Connect to the exchange's WebSocket server securely over wss://, through the proxy if
+    // there is one (unless told to stop first). The source hands back the two halves.
+    let (mut write, mut read) = tokio::select! {
+        connected = options.source.connect(exchange) => connected?,
+        _ = shutdown.changed() => return Ok(ConnectionEnd::Shutdown),
+    };
+
+    // Send the subscription message(s) so the exchange knows what you want (again after every reconnect)
+    let initial: Vec<String> = subscribed.iter().cloned().collect();
+    for msg in subscribe_messages(exchange, &initial, options) {
+        write.send(Message::Text(msg)).await?;
+    }
+    info!(symbols = ?initial, "Connected and subscribed");
+    // The backoff starts over once data flows, not on connecting: a server that accepts the
+    // connection and then drops it, or only ever sends errors, keeps the delays growing
+    let mut receiving = false;
+
+    // When each symbol last had a ticker, book update or heartbeat. Only exchanges with
+    // per-symbol heartbeats are checked: elsewhere silence may just mean nobody traded.
+    let stale_after = options.stale_after.filter(|_| exchange.heartbeats());
+    let mut last_seen: HashMap<String, Instant> = subscribed.iter().map(|s| (s.clone(), Instant::now())).collect();
+    let mut stale_check = tokio::time::interval(STALE_CHECK_INTERVAL);
+
+    // Main WebSocket reading loop — receive messages until the stream ends or we're told to stop
+    loop {
+        let msg = tokio::select! {
+            msg = read.next() => msg,
+            changed = symbols.changed() => {
+                if changed.is_err() {
+                    return Ok(ConnectionEnd::Shutdown);  // The tracker is gone
+                }
+                // Symbols were added or removed: adjust the subscriptions on this connection
+                let wanted = symbols.borrow_and_update().clone();
+                let added: Vec<String> = wanted.difference(&subscribed).cloned().collect();
+                let removed: Vec<String> = subscribed.difference(&wanted).cloned().collect();
+                let mut messages = subscribe_messages(exchange, &added, options);
+                messages.extend(unsubscribe_messages(exchange, &removed, options));
+                for msg in messages {
+                    write.send(Message::Text(msg)).await?;
+                }
+                if !added.is_empty() || !removed.is_empty() {
+                    info!(?added, ?removed, "Subscriptions changed");
+                }
+                for symbol in &removed {
+                    store.remove(symbol).await;  // Again, in case a tick slipped in before we got here
+                    frame_stats.stale.remove(symbol);
+                }
+                to_common = native_symbols(exchange, &wanted);
+                last_seen.retain(|s, _| wanted.contains(s));
+                for symbol in added {
+                    last_seen.insert(symbol, Instant::now());  // Give new subscriptions a full window
+                }
+                subscribed = wanted;
+                continue;
+            }
+            _ = stale_check.tick(), if stale_after.is_some() => {
+                let window = stale_after.unwrap_or_default();
+                let stale: Vec<&String> = last_seen.iter().filter(|(_, at)| at.elapsed() > window).map(|(s, _)| s).collect();
+                if stale.is_empty() {
+                    continue;
+                }
+                for symbol in &stale {
+                    store.set_stale(exchange.name(), symbol, true).await;
+                    frame_stats.stale.insert(symbol.to_string());
+                }
+                warn!(symbols = ?stale, window = ?window, "No data or heartbeat, reconnecting");
+                return Ok(ConnectionEnd::Stale);
+            }
+            _ = shutdown.changed() => {
+                // Say goodbye properly: send a Close frame and flush it before dropping the socket
+                let _ = write.send(Message::Close(None)).await;
+                let _ = write.close().await;
+                return Ok(ConnectionEnd::Shutdown);
+            }
+        };
+        let Some(msg) = msg else { break };
+
+        let m = msg?;  // Any WebSocket error ends this connection; the caller reconnects
+        store.metrics().record_message(exchange.name());
+        if m.is_text() {
+            let text = m.to_text()?;
+            let received_at = SystemTime::now();
+            if let Some(recorder) = &options.recorder {
+                recorder.record(exchange.name(), text, received_at);
+            }
+            let outcome = handle_text_frame(exchange, text, received_at, &to_common, options.order_books, store, frame_stats).await;
+            if !receiving && !outcome.active.is_empty() {
+                backoff.reset();  // A working connection means the next outage starts with a short delay
+                receiving = true;
+            }
+            for symbol in outcome.active {
+                // Only a change goes to the store: this runs for nearly every frame
+                if frame_stats.stale.remove(symbol) {
+                    store.set_stale(exchange.name(), symbol, false).await;
+                }
+                if let Some(seen) = last_seen.get_mut(symbol) {
+                    *seen = Instant::now();
+                }
+            }
+
+            // Missed messages may have been order book changes: resubscribing gets a fresh snapshot
+            if options.resync_on_gap && !outcome.gaps.is_empty() {
+                let gaps: Vec<String> = outcome.gaps.into_iter().cloned().collect();
+                let mut messages = unsubscribe_messages(exchange, &gaps, options);
+                messages.extend(subscribe_messages(exchange, &gaps, options));
+                for msg in messages {
+                    write.send(Message::Text(msg)).await?;
+                }
+                for symbol in &gaps {
+                    store.forget_book(exchange.name(), symbol).await;
+                    frame_stats.sequences.forget(symbol);
+                }
+                info!(symbols = ?gaps, "Resubscribed to resync after a sequence gap");
+            }
+        }
+    }
+
+    Ok(ConnectionEnd::Closed)  // Server closed the stream cleanly
+}
+
+// What one frame told us about the symbols it mentioned (our names)
+#[derive(Debug, Default)]
+pub(crate) struct FrameOutcome<'a> {
+    pub active: Vec<&'a String>,  // Showed signs of life (data or heartbeat)
+    pub gaps: Vec<&'a String>,    // Sequence numbers jumped: messages were dropped
+}
+
+// Validate one text frame and apply it: order book events to the books, tickers to the
+// price store under our symbol names. Live connections and replays of recorded frames
+// both come through here, so a replay goes through exactly the same steps.
+pub(crate) async fn handle_text_frame<'a>(
+    exchange: &dyn Exchange,
+    text: &str,
+    received_at: SystemTime,
+    to_common: &'a HashMap<String, String>,
+    order_books: bool,
+    store: &PriceStore,
+    frame_stats: &mut FrameStats,
+) -> FrameOutcome<'a> {
+    let started = Instant::now();  // For the processing-latency histogram
+    debug!(frame = text, "Received message");
+
+    // Make sure the whole frame is valid JSON before interpreting it
+    let value = match validate_text_frame(text) {
+        Ok(value) => value,
+        Err(err) => {
+            frame_stats.record(&err);
+            warn!(
+                bytes = text.len(),
+                error = %err,
+                truncated = frame_stats.truncated,
+                invalid = frame_stats.invalid,
+                "Dropped bad frame"
+            );
+            return FrameOutcome::default();
+        }
+    };
+
+    // Every symbol the message was about counts towards its feed health
+    let outcome = apply_message(exchange, &value, received_at, to_common, order_books, store, frame_stats, started).await;
+    let sent_at = exchange.parse_timestamp(&value);
+    let latency = sent_at.map(|sent| received_at.duration_since(sent).map_or(0.0, |d| d.as_secs_f64()));
+    for symbol in &outcome.active {
+        store.record_message(exchange.name(), symbol, received_at, sent_at).await;
+        store.metrics().record_symbol_message(exchange.name(), symbol, latency);
+    }
+    outcome
+}
+
+// Apply one valid message: heartbeats, sequence numbers, order books, trades, tickers
+#[allow(clippy::too_many_arguments)]
+async fn apply_message<'a>(
+    exchange: &dyn Exchange,
+    value: &serde_json::Value,
+    received_at: SystemTime,
+    to_common: &'a HashMap<String, String>,
+    order_books: bool,
+    store: &PriceStore,
+    frame_stats: &mut FrameStats,
+    started: Instant,
+) -> FrameOutcome<'a> {
+    // Heartbeats carry no data, they only show the symbol's feed is alive
+    if let Some(native) = exchange.parse_heartbeat(value) {
+        return FrameOutcome { active: to_common.get(&native).into_iter().collect(), gaps: Vec::new() };
+    }
+
+    // Numbered messages: count the ones that went missing, skip the ones that arrive late
+    let mut gaps = Vec::new();
+    if let Some((native, sequence, numbering)) = exchange.parse_sequence(value)
+        && let Some(symbol) = to_common.get(&native)
+    {
+        match frame_stats.sequences.observe(symbol, sequence, numbering) {
+            SequenceCheck::First | SequenceCheck::InOrder => {}
+            SequenceCheck::Gap(missed) => {
+                frame_stats.dropped += missed;
+                store.metrics().record_dropped(exchange.name(), missed);
+                warn!(%symbol, sequence, missed, dropped = frame_stats.dropped, "Sequence gap, messages were dropped");
+                gaps.push(symbol);
+            }
+            SequenceCheck::Old => {
+                debug!(%symbol, sequence, "Skipped an out-of-order message");
+                return FrameOutcome { active: vec![symbol], gaps };
+            }
+        }
+    }
+
+    // Order book snapshots and changes go to the books, not the price map
+    if order_books
+        && let Some(event) = exchange.parse_book(value)
+    {
+        let symbol = to_common.get(event.symbol());
+        if let Some(symbol) = symbol {
+            store.apply_book(exchange.name(), symbol, event).await;
+        }
+        store.metrics().observe_processing(exchange.name(), started.elapsed());
+        return FrameOutcome { active: symbol.into_iter().collect(), gaps };
+    }
+
+    // Futures mark price events: mark, index and funding go with the contract, not the price map
+    if let Some(funding) = exchange.parse_funding(value) {
+        let symbol = to_common.get(&funding.symbol);
+        if let Some(symbol) = symbol {
+            let mark_price = parse_decimal("mark price", &funding.mark_price);
+            let index_price = parse_decimal("index price", &funding.index_price);
+            match (mark_price, index_price, parse_decimal("funding rate", &funding.funding_rate)) {
+                (Ok(mark_price), Ok(index_price), Ok(funding_rate)) => {
+                    let next_funding_at = funding.next_funding_ms.map(|ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms));
+                    let info = FundingInfo { mark_price, index_price, funding_rate, next_funding_at, updated_at: received_at };
+                    store.record_funding(exchange.name(), symbol, info).await;
+                }
+                (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => {
+                    frame_stats.bad_prices += 1;
+                    warn!(symbol = %funding.symbol, error = %err, bad_prices = frame_stats.bad_prices, "Dropped funding update");
+                }
+            }
+        }
+        store.metrics().observe_processing(exchange.name(), started.elapsed());
+        return FrameOutcome { active: symbol.into_iter().collect(), gaps };
+    }
+
+    // Trades only feed the volume figures; the ticker that follows a trade sets the price
+    let trades = exchange.parse_trades(value);
+    if !trades.is_empty() {
+        let mut active = Vec::new();
+        for trade in trades {
+            let Some(symbol) = to_common.get(&trade.symbol) else { continue };
+            active.push(symbol);
+            match (parse_decimal("price", &trade.price), parse_decimal("size", &trade.size)) {
+                (Ok(price), Ok(size)) => store.record_trade(exchange.name(), symbol, received_at, price, size).await,
+                (Err(err), _) | (_, Err(err)) => {
+                    frame_stats.bad_prices += 1;
+                    warn!(symbol = %trade.symbol, error = %err, bad_prices = frame_stats.bad_prices, "Dropped trade");
+                }
+            }
+        }
+        store.metrics().observe_processing(exchange.name(), started.elapsed());
+        return FrameOutcome { active, gaps };
+    }
+
+    // Let the connector pick out any ticker updates and store them under our symbol names.
+    // Symbols we don't (or no longer) track are skipped: an unsubscribe takes a moment
+    // to reach the exchange, and a late tick must not bring a removed symbol back.
+    let mut active = Vec::new();
+    for ticker in exchange.parse(value) {
+        let Some(symbol) = to_common.get(&ticker.symbol) else { continue };
+        active.push(symbol);
+        match PriceUpdate::from_ticker(exchange.name(), symbol.clone(), &ticker, received_at) {
+            Ok(update) => store.update(update).await,
+            Err(err) => {
+                frame_stats.bad_prices += 1;
+                warn!(symbol = %ticker.symbol, error = %err, bad_prices = frame_stats.bad_prices, "Dropped update");
+            }
+        }
+    }
+    store.metrics().observe_processing(exchange.name(), started.elapsed());
+    FrameOutcome { active, gaps }
+}
+
+// Map the exchange's own symbol spelling back to ours ("BTCUSDT" -> "BTC-USD")
+pub(crate) fn native_symbols(exchange: &dyn Exchange, symbols: &BTreeSet<String>) -> HashMap<String, String> {
+    symbols.iter().map(|s| (exchange.native_symbol(s), s.clone())).collect()
+}
+
+// Ticker (and order book, trade) subscriptions for `symbols`; nothing at all for an empty list
+fn subscribe_messages(exchange: &dyn Exchange, symbols: &[String], options: &FeedOptions) -> Vec<String> {
+    if symbols.is_empty() {
+        return Vec::new();
+    }
+    let mut messages = exchange.subscribe_messages(symbols);
+    if options.order_books {
+        messages.extend(exchange.book_subscribe_messages(symbols));
+    }
+    if options.trades {
+        messages.extend(exchange.trade_subscribe_messages(symbols));
+    }
+    messages
+}
+
+// The matching unsubscriptions
+fn unsubscribe_messages(exchange: &dyn Exchange, symbols: &[String], options: &FeedOptions) -> Vec<String> {
+    if symbols.is_empty() {
+        return Vec::new();
+    }
+    let mut messages = exchange.unsubscribe_messages(symbols);
+    if options.order_books {
+        messages.extend(exchange.book_unsubscribe_messages(symbols));
+    }
+    if options.trades {
+        messages.extend(exchange.trade_unsubscribe_messages(symbols));
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::{Binance, Coinbase};
+    use crate::source::MockSource;
+
+    #[test]
+    fn truncated_frame_is_counted_not_panicked() {
+        let mut stats = FrameStats::default();
+        let frame = r#"{"type":"ticker","product_id":"BTC-USD","price":"6500"#;
+
+        let err = validate_text_frame(frame).unwrap_err();
+        assert!(matches!(err, FrameError::Truncated(_)));
+
+        stats.record(&err);
+        assert_eq!(stats.truncated, 1);
+        assert_eq!(stats.invalid, 0);
+    }
+
+    #[test]
+    fn malformed_frame_is_counted_as_invalid() {
+        let mut stats = FrameStats::default();
+        let err = validate_text_frame(r#"{"type": ticker}"#).unwrap_err();
+        stats.record(&err);
+        assert_eq!(stats.invalid, 1);
+    }
+
+    #[test]
+    fn product_wide_ticker_sequences_are_not_gaps() {
+        // Coinbase tickers as they arrive on a healthy feed: the numbers are shared with
+        // the product's other channels, so they jump, and one late ticker comes in
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let store = PriceStore::new();
+        let to_common = native_symbols(&Coinbase, &BTreeSet::from(["BTC-USD".to_string(), "ETH-USD".to_string()]));
+        let mut stats = FrameStats::default();
+        for (symbol, sequence, price) in
+            [("BTC-USD", 8_841_207, "65000"), ("ETH-USD", 3_122_090, "3400"), ("BTC-USD", 8_841_251, "65010"), ("BTC-USD", 8_841_230, "64990"), ("ETH-USD", 3_122_187, "3401"), ("BTC-USD", 8_841_402, "65020")]
+        {
+            let frame = format!(r#"{{"type":"ticker","product_id":"{}","price":"{}","sequence":{}}}"#, symbol, price, sequence);
+            let outcome = runtime.block_on(handle_text_frame(&Coinbase, &frame, SystemTime::now(), &to_common, false, &store, &mut stats));
+            assert!(outcome.gaps.is_empty(), "{} {} reported as a gap", symbol, sequence);
+        }
+        assert_eq!(stats.dropped, 0);
+        assert_eq!(runtime.block_on(store.latest("BTC-USD")).unwrap().price, "65020".parse().unwrap(), "the late 64990 was skipped");
+        assert_eq!(store.update_count(), 5);
+    }
+
+    #[test]
+    fn skipped_trade_ids_are_counted_and_resynced() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let mock = MockSource::new();
+        let options = FeedOptions {
+            order_books: false,
+            trades: true,
+            stale_after: None,
+            resync_on_gap: true,
+            recorder: None,
+            source: Arc::new(mock.clone()),
+        };
+        let (_symbols_tx, mut symbols) = watch::channel(BTreeSet::from(["BTC-USD".to_string()]));
+        let (_shutdown_tx, mut shutdown) = watch::channel(false);
+        let store = PriceStore::new();
+        let mut stats = FrameStats::default();
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        let trade = |id: u64| format!(r#"{{"e":"trade","s":"BTCUSDT","t":{},"p":"65000","q":"0.1"}}"#, id);
+        // The repeated 101 is skipped; 103 to 105 never arrive
+        let frames = [trade(100), trade(101), trade(101), trade(102), trade(106), trade(107)];
+        mock.script_disconnect("binance", &frames.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let end = runtime.block_on(run_connection(&Binance, &mut symbols, &store, &options, &mut stats, &mut backoff, &mut shutdown));
+        assert!(matches!(end, Ok(ConnectionEnd::Closed)));
+        assert_eq!(stats.dropped, 3);
+        let metrics = store.metrics().render();
+        assert!(metrics.contains(r#"crabby_dropped_messages_total{exchange="binance"} 3"#), "{}", metrics);
+
+        // Subscribed once on connecting, then resubscribed after the gap
+        let symbol = ["BTC-USD".to_string()];
+        let mut expected = subscribe_messages(&Binance, &symbol, &options);
+        expected.extend(unsubscribe_messages(&Binance, &symbol, &options));
+        expected.extend(subscribe_messages(&Binance, &symbol, &options));
+        assert_eq!(expected.len(), 6);  // Tickers and trades each time
+        assert_eq!(mock.sent("binance"), expected);
+    }
+
+    #[test]
+    fn backoff_resets_only_once_data_arrives() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let mock = MockSource::new();
+        let options = FeedOptions {
+            order_books: false,
+            trades: false,
+            stale_after: None,
+            resync_on_gap: false,
+            recorder: None,
+            source: Arc::new(mock.clone()),
+        };
+        let (_symbols_tx, mut symbols) = watch::channel(BTreeSet::from(["BTC-USD".to_string()]));
+        let (_shutdown_tx, mut shutdown) = watch::channel(false);
+        let store = PriceStore::new();
+        let mut stats = FrameStats::default();
+        // Three failures in a row: the next delay is at least 4s
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        for _ in 0..3 {
+            backoff.next_delay();
+        }
+        // The delay after one connection that sent `frames` and was then dropped
+        let mut delay_after = |frames: &[&str]| {
+            let mut backoff = backoff.clone();
+            mock.script_disconnect("coinbase", frames);
+            let end = runtime.block_on(run_connection(&Coinbase, &mut symbols, &store, &options, &mut stats, &mut backoff, &mut shutdown));
+            assert!(matches!(end, Ok(ConnectionEnd::Closed)));
+            backoff.next_delay()
+        };
+        assert!(delay_after(&[]) >= Duration::from_secs(4), "connected, nothing sent");
+        let noise = [r#"{"type":"subscriptions","channels":[]}"#, r#"{"type":"error","message":"Failed to subscribe"}"#, r#"{"type": nope"#];
+        assert!(delay_after(&noise) >= Duration::from_secs(4), "no data for our symbols");
+        assert!(delay_after(&[r#"{"type":"ticker","product_id":"BTC-USD","price":"65000"}"#]) <= Duration::from_secs(1), "data arrived");
+    }
+
+    #[test]
+    fn complete_frame_passes() {
+        let value = validate_text_frame(r#"{"type":"ticker","product_id":"BTC-USD","price":"1"}"#).unwrap();
+        assert_eq!(value["price"], "1");
+    }
+}
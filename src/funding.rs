@@ -0,0 +1,130 @@
+// Perpetual futures: mark price, index price and funding rate per contract, from the
+// futures exchanges' mark price streams (Binance "<symbol>@markPrice").
+//
+// A perpetual never expires; instead, every few hours (8h on Binance) longs pay shorts
+// the funding rate times their position, or the other way round when it's negative.
+// The rate follows the gap between the contract's mark price and the spot index, so
+// it's what basis traders watch. Alert rules can fire on it:
+//
+//     [[alert]]
+//     symbol = "BTC-USD"
+//     exchange = "binance-futures"
+//     funding_above = 0.05   # percent per funding interval
+//
+// The contract's last trade price goes through the normal ticker pipeline, under the
+// futures exchange's name, so it shows next to the spot venues' prices.
+
+use std::{
+    fmt,                           // One-line summary for the terminal output
+    time::SystemTime,              // When the next funding happens
+};
+
+use rust_decimal::Decimal;
+
+use crate::store::{get, BySymbol};  // One entry per (exchange, symbol)
+
+// The latest mark price event of one contract
+#[derive(Debug, Clone, PartialEq)]
+pub struct FundingInfo {
+    pub mark_price: Decimal,                 // What positions are valued (and liquidated) at
+    pub index_price: Decimal,                // Average spot price across venues
+    pub funding_rate: Decimal,               // As a fraction, e.g. 0.0001 for 0.01%
+    pub next_funding_at: Option<SystemTime>, // When the rate is next paid
+    pub updated_at: SystemTime,              // When we received this
+}
+
+impl FundingInfo {
+    // The funding rate in percent, as the exchanges show it
+    pub fn rate_pct(&self) -> Decimal {
+        (self.funding_rate * Decimal::ONE_HUNDRED).normalize()
+    }
+
+    // Mark minus index, in percent of the index: how far the contract trades from spot
+    pub fn premium_pct(&self) -> Option<Decimal> {
+        (!self.index_price.is_zero()).then(|| ((self.mark_price - self.index_price) / self.index_price * Decimal::ONE_HUNDRED).round_dp(4))
+    }
+}
+
+// "mark 65010.5  index 65000.1  premium +0.0160%  funding 0.0100% in 3h 12m"
+impl fmt::Display for FundingInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mark {}  index {}", self.mark_price, self.index_price)?;
+        if let Some(premium) = self.premium_pct() {
+            write!(f, "  premium {:+.4}%", premium)?;
+        }
+        write!(f, "  funding {:.4}%", self.funding_rate * Decimal::ONE_HUNDRED)?;
+        if let Some(next) = self.next_funding_at
+            && let Ok(wait) = next.duration_since(SystemTime::now())
+        {
+            // Whole minutes are plenty
+            let wait = std::time::Duration::from_secs(wait.as_secs() / 60 * 60);
+            write!(f, " in {}", humantime::format_duration(wait))?;
+        }
+        Ok(())
+    }
+}
+
+// Every contract, keyed by (exchange, our symbol). Owned by the store's state thread, like
+// `TradeVolumes`, so there's no lock; readers ask the store (`PriceStore::funding`).
+#[derive(Debug, Default)]
+pub struct FundingRates {
+    contracts: BySymbol<FundingInfo>,
+}
+
+impl FundingRates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, exchange: &'static str, symbol: &str, info: FundingInfo) {
+        self.contracts.entry(symbol.to_string()).or_default().insert(exchange, info);
+    }
+
+    // The latest figures for one contract, if its exchange sends any
+    pub fn get(&self, exchange: &str, symbol: &str) -> Option<FundingInfo> {
+        get(&self.contracts, exchange, symbol).cloned()
+    }
+
+    // Every contract, sorted by symbol then exchange
+    pub fn all(&self) -> Vec<(&'static str, String, FundingInfo)> {
+        let mut all: Vec<_> = self
+            .contracts
+            .iter()
+            .flat_map(|(sym, exchanges)| exchanges.iter().map(move |(ex, info)| (*ex, sym.clone(), info.clone())))
+            .collect();
+        all.sort_by(|a, b| (&a.1, a.0).cmp(&(&b.1, b.0)));
+        all
+    }
+
+    // Forget one symbol on every exchange (it's no longer tracked)
+    pub fn remove_symbol(&mut self, symbol: &str) {
+        self.contracts.remove(symbol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn funding_summary_and_lookup() {
+        let info = FundingInfo {
+            mark_price: "65010.4".parse().unwrap(),
+            index_price: "65000".parse().unwrap(),
+            funding_rate: "0.0001".parse().unwrap(),
+            next_funding_at: Some(SystemTime::now() + Duration::from_secs(3 * 3600 + 12 * 60 + 30)),
+            updated_at: SystemTime::now(),
+        };
+        assert_eq!(info.rate_pct(), "0.01".parse().unwrap());
+        assert_eq!(info.premium_pct(), Some("0.016".parse().unwrap()));
+        assert_eq!(info.to_string(), "mark 65010.4  index 65000  premium +0.0160%  funding 0.0100% in 3h 12m");
+
+        let mut rates = FundingRates::new();
+        rates.record("binance-futures", "BTC-USD", info.clone());
+        assert_eq!(rates.get("binance-futures", "BTC-USD"), Some(info));
+        assert_eq!(rates.get("binance", "BTC-USD"), None);
+        rates.remove_symbol("BTC-USD");
+        assert!(rates.all().is_empty());
+    }
+}
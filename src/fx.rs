@@ -0,0 +1,150 @@
+// Fiat display currency: show USD-quoted prices in EUR, GBP, JPY... as well.
+//
+// The USD→currency rate comes from the Coinbase exchange-rates API and is refreshed
+// periodically ([output] fx_refresh). It lives in the price store, so every output
+// (terminal, NDJSON, REST API, dashboard, Telegram, portfolio) can convert with the
+// same rate. Converted values are always shown next to the original and marked with
+// "≈", because they are only as fresh as the last rate fetch.
+//
+// Only symbols quoted in USD are converted; BTC-EUR is already in a fiat currency and
+// BTC-USDT is quoted in a stablecoin, so both are left alone. Fetching needs the "fx"
+// feature (reqwest); the conversion itself is always available.
+
+use std::{
+    fmt,                          // "≈ 59800.12 EUR"
+    sync::{Arc, RwLock},          // The current rate, shared by every reader
+    time::SystemTime,             // When the rate was fetched
+};
+#[cfg(feature = "fx")]
+use std::time::Duration;          // Request timeout, refresh interval
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+#[cfg(feature = "fx")]
+use serde_json::Value;
+#[cfg(feature = "fx")]
+use tracing::{info, warn};
+
+// Coinbase's public exchange rates: {"data":{"currency":"USD","rates":{"EUR":"0.92",...}}}
+#[cfg(feature = "fx")]
+const RATES_URL: &str = "https://api.coinbase.com/v2/exchange-rates?currency=USD";
+
+// One USD→currency rate
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rate {
+    pub currency: String,       // e.g. "EUR"
+    pub per_usd: Decimal,       // Units of `currency` one USD buys
+    pub fetched_at: SystemTime,
+}
+
+// A value converted to the display currency
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Converted {
+    #[serde(rename = "display_currency")]
+    pub currency: String,
+    #[serde(rename = "display_value")]
+    pub amount: Decimal,
+}
+
+impl fmt::Display for Converted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "≈ {} {}", self.amount, self.currency)
+    }
+}
+
+// The current rate, if any. Cheap to clone: every clone sees the same rate.
+#[derive(Debug, Clone, Default)]
+pub struct FxRates {
+    rate: Arc<RwLock<Option<Rate>>>,
+}
+
+impl FxRates {
+    pub fn set(&self, rate: Rate) {
+        *self.rate.write().unwrap() = Some(rate);
+    }
+
+    pub fn current(&self) -> Option<Rate> {
+        self.rate.read().unwrap().clone()
+    }
+
+    // `amount` (in the quote currency of `symbol`) in the display currency, or None when
+    // there's no rate yet or the symbol isn't quoted in USD
+    pub fn convert(&self, amount: Decimal, symbol: &str) -> Option<Converted> {
+        if quote_currency(symbol) != "USD" {
+            return None;
+        }
+        self.convert_usd(amount)
+    }
+
+    // A USD amount in the display currency
+    pub fn convert_usd(&self, amount: Decimal) -> Option<Converted> {
+        let guard = self.rate.read().unwrap();
+        let rate = guard.as_ref()?;
+        let converted = amount * rate.per_usd;
+        // Cents for ordinary amounts, more digits for tiny prices like 0.00001234
+        let places = if converted.abs() >= Decimal::ONE { 2 } else { 8 };
+        Some(Converted { currency: rate.currency.clone(), amount: converted.round_dp(places).normalize() })
+    }
+}
+
+// "USD" for "BTC-USD"
+pub fn quote_currency(symbol: &str) -> &str {
+    symbol.rsplit_once('-').map(|(_, quote)| quote).unwrap_or("")
+}
+
+// Fetch the current rate for `currency` from Coinbase
+#[cfg(feature = "fx")]
+pub async fn fetch_rate(client: &reqwest::Client, currency: &str) -> Result<Rate, Box<dyn std::error::Error>> {
+    let body: Value = client.get(RATES_URL).send().await?.error_for_status()?.json().await?;
+    let text = body["data"]["rates"][currency].as_str().ok_or_else(|| format!("no USD/{} rate in the response", currency))?;
+    let per_usd: Decimal = text.parse().map_err(|e| format!("bad {} rate \"{}\": {}", currency, text, e))?;
+    if per_usd <= Decimal::ZERO {
+        return Err(format!("bad {} rate \"{}\"", currency, text).into());
+    }
+    Ok(Rate { currency: currency.to_string(), per_usd, fetched_at: SystemTime::now() })
+}
+
+// Fetch the rate now (so the first output is already converted), then keep refreshing
+// it every `every` in the background. A failed fetch keeps the previous rate.
+#[cfg(feature = "fx")]
+pub async fn start_refresh(rates: FxRates, currency: String, every: Duration, network: crate::net::Network) {
+    let client = network
+        .http_client()
+        .user_agent(concat!("crabbycryptotracker/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+
+    let refresh = async move || match fetch_rate(&client, &currency).await {
+        Ok(rate) => {
+            info!(currency = %rate.currency, per_usd = %rate.per_usd, "Fetched exchange rate");
+            rates.set(rate);
+        }
+        Err(e) => warn!(%currency, error = %e, "Couldn't fetch the exchange rate; keeping the previous one"),
+    };
+    refresh().await;
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(every).await;
+            refresh().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_only_usd_quoted_symbols_once_a_rate_is_known() {
+        let rates = FxRates::default();
+        assert_eq!(rates.convert(Decimal::ONE_HUNDRED, "BTC-USD"), None);  // No rate yet
+
+        rates.set(Rate { currency: "EUR".into(), per_usd: "0.9".parse().unwrap(), fetched_at: SystemTime::now() });
+        let converted = rates.convert("65000.5".parse().unwrap(), "BTC-USD").unwrap();
+        assert_eq!(converted.to_string(), "≈ 58500.45 EUR");
+        assert_eq!(rates.convert("0.00001".parse().unwrap(), "SHIB-USD").unwrap().amount, "0.000009".parse().unwrap());
+        assert_eq!(rates.convert(Decimal::ONE, "BTC-EUR"), None);
+        assert_eq!(rates.convert(Decimal::ONE, "BTC-USDT"), None);
+    }
+}
@@ -0,0 +1,210 @@
+// Symbol groups: alert defaults shared by a set of symbols, with per-symbol overrides
+// and their own notification routing.
+//
+//     [symbol_groups.majors]
+//     symbols = ["BTC-USD", "ETH-USD"]
+//     change_pct = 5            # alert on a 5% move either way...
+//     window = "1h"             # ...within an hour
+//     cooldown = "30m"
+//
+//     [symbol_groups.speculative]
+//     symbols = ["DOGE-USD", "PEPE-USD", "WIF-USD"]
+//     change_pct = 3            # tighter than the majors
+//     window = "15m"
+//     volume_spike = 4          # the last minute's volume is 4x the average (needs [trades])
+//     cooldown = "10m"
+//     webhooks = [{ url = "https://discord.com/api/webhooks/...", format = "discord" }]
+//
+//     [symbol_groups.speculative.overrides.PEPE-USD]
+//     change_pct = 8            # PEPE moves 3% all day long
+//
+// Every symbol of a group gets the group's rules: a `change_pct` move up and one down,
+// and a `volume_spike`, with the symbol's overrides taking the place of the group's
+// values. Rules written out in [[alerts]] (or the alerts file) for a grouped symbol keep
+// their own settings, and take the `cooldown` and `window` they leave out from the group.
+//
+// Alerts for a group with `webhooks` go to those instead of the global [[webhooks]]
+// (the console, Telegram and desktop notifications still get them); see
+// `notify::GroupRouter`. A symbol belongs to at most one group.
+//
+// The group's rules have ids of their own for the alert state file (see alerts.rs):
+// "group.speculative.PEPE-USD.up", ".down" and ".volume". Changing the group's
+// thresholds keeps what the rules knew.
+
+use std::{
+    collections::BTreeMap,  // Groups by name; overrides by symbol
+    time::Duration,         // Windows and cooldowns
+};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::alerts::RuleConfig;
+use crate::config::deserialize_opt_duration;
+use crate::notify::WebhookConfig;
+
+// What a group sets for its symbols, and what a symbol can override
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertDefaults {
+    pub change_pct: Option<Decimal>,    // Alert on a move of this % either way within `window`
+    pub volume_spike: Option<Decimal>,  // Alert when the last minute's volume is this many times the average
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub window: Option<Duration>,       // Look-back for `change_pct`, and for rules that don't set one
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub cooldown: Option<Duration>,     // For the group's rules and rules that don't set one
+}
+
+impl AlertDefaults {
+    // These values, with the unset ones taken from `fallback`
+    fn or(&self, fallback: &AlertDefaults) -> AlertDefaults {
+        AlertDefaults {
+            change_pct: self.change_pct.or(fallback.change_pct),
+            volume_spike: self.volume_spike.or(fallback.volume_spike),
+            window: self.window.or(fallback.window),
+            cooldown: self.cooldown.or(fallback.cooldown),
+        }
+    }
+}
+
+// One [symbol_groups.<name>] table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GroupConfig {
+    pub symbols: Vec<String>,
+    pub change_pct: Option<Decimal>,
+    pub volume_spike: Option<Decimal>,
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub window: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub cooldown: Option<Duration>,
+    pub webhooks: Vec<WebhookConfig>,                  // Where the group's alerts go instead of [[webhooks]]
+    pub overrides: BTreeMap<String, AlertDefaults>,    // Per symbol ([symbol_groups.<name>.overrides.<symbol>])
+}
+
+impl GroupConfig {
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.symbols.iter().any(|s| s.eq_ignore_ascii_case(symbol))
+    }
+
+    // The group's values, with `symbol`'s overrides applied
+    pub fn defaults_for(&self, symbol: &str) -> AlertDefaults {
+        let group = AlertDefaults { change_pct: self.change_pct, volume_spike: self.volume_spike, window: self.window, cooldown: self.cooldown };
+        match self.overrides.iter().find(|(s, _)| s.eq_ignore_ascii_case(symbol)) {
+            Some((_, overrides)) => overrides.or(&group),
+            None => group,
+        }
+    }
+
+    // The rules every member gets from the group called `name`
+    pub fn rules(&self, name: &str) -> Vec<RuleConfig> {
+        let mut rules = Vec::new();
+        for symbol in &self.symbols {
+            let defaults = self.defaults_for(symbol);
+            let rule = RuleConfig { symbol: symbol.clone(), cooldown: defaults.cooldown, ..RuleConfig::default() };
+            let id = |kind: &str| Some(format!("group.{}.{}.{}", name, symbol, kind));
+            if let Some(pct) = defaults.change_pct {
+                let pct = pct.abs();
+                rules.push(RuleConfig { id: id("up"), change_pct: Some(pct), window: defaults.window, ..rule.clone() });
+                rules.push(RuleConfig { id: id("down"), change_pct: Some(-pct), window: defaults.window, ..rule.clone() });
+            }
+            if let Some(multiple) = defaults.volume_spike {
+                rules.push(RuleConfig { id: id("volume"), volume_spike: Some(multiple), ..rule });
+            }
+        }
+        rules
+    }
+}
+
+// The group `symbol` belongs to, if any
+pub fn group_of<'a>(groups: &'a BTreeMap<String, GroupConfig>, symbol: &str) -> Option<(&'a str, &'a GroupConfig)> {
+    groups.iter().find(|(_, group)| group.contains(symbol)).map(|(name, group)| (name.as_str(), group))
+}
+
+// The rules to run: `rules` (from [[alerts]] and the alerts file) with what they leave
+// out taken from their symbol's group, followed by the groups' own rules
+pub fn resolve(groups: &BTreeMap<String, GroupConfig>, mut rules: Vec<RuleConfig>) -> Vec<RuleConfig> {
+    for rule in &mut rules {
+        if let Some((_, group)) = group_of(groups, &rule.symbol) {
+            let defaults = group.defaults_for(&rule.symbol);
+            rule.cooldown = rule.cooldown.or(defaults.cooldown);
+            if rule.change_pct.is_some() || rule.volume_spike.is_some() {
+                rule.window = rule.window.or(defaults.window);
+            }
+        }
+    }
+    rules.extend(groups.iter().flat_map(|(name, group)| group.rules(name)));
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::alerts::{Condition, Rule};
+
+    fn speculative() -> BTreeMap<String, GroupConfig> {
+        let toml = r#"
+            symbols = ["DOGE-USD", "PEPE-USD"]
+            change_pct = 3
+            window = "15m"
+            cooldown = "10m"
+
+            [overrides.PEPE-USD]
+            change_pct = 8
+            cooldown = "1h"
+        "#;
+        BTreeMap::from([("speculative".to_string(), toml::from_str(toml).unwrap())])
+    }
+
+    fn conditions(rules: &[RuleConfig], symbol: &str) -> Vec<(Condition, Duration)> {
+        rules
+            .iter()
+            .filter(|r| r.symbol == symbol)
+            .map(|r| Rule::try_from(r.clone()).unwrap())
+            .map(|r| (r.condition, r.cooldown))
+            .collect()
+    }
+
+    #[test]
+    fn members_inherit_the_group_defaults() {
+        let rules = resolve(&speculative(), Vec::new());
+        let ids: Vec<&str> = rules.iter().filter_map(|r| r.id.as_deref()).collect();
+        assert_eq!(ids, ["group.speculative.DOGE-USD.up", "group.speculative.DOGE-USD.down", "group.speculative.PEPE-USD.up", "group.speculative.PEPE-USD.down"]);
+        let window = Duration::from_secs(15 * 60);
+        assert_eq!(
+            conditions(&rules, "DOGE-USD"),
+            vec![
+                (Condition::Change { pct: Decimal::from(3), window }, Duration::from_secs(600)),
+                (Condition::Change { pct: Decimal::from(-3), window }, Duration::from_secs(600)),
+            ]
+        );
+    }
+
+    #[test]
+    fn symbol_overrides_replace_the_group_values() {
+        let rules = resolve(&speculative(), Vec::new());
+        let window = Duration::from_secs(15 * 60);  // Not overridden, so the group's
+        assert_eq!(
+            conditions(&rules, "PEPE-USD"),
+            vec![
+                (Condition::Change { pct: Decimal::from(8), window }, Duration::from_secs(3600)),
+                (Condition::Change { pct: Decimal::from(-8), window }, Duration::from_secs(3600)),
+            ]
+        );
+    }
+
+    #[test]
+    fn explicit_rules_keep_their_settings_and_fill_the_rest_from_the_group() {
+        let own = RuleConfig { symbol: "DOGE-USD".to_string(), above: Some(Decimal::ONE), cooldown: Some(Duration::from_secs(5)), ..RuleConfig::default() };
+        let bare = RuleConfig { symbol: "PEPE-USD".to_string(), change_pct: Some(Decimal::from(20)), ..RuleConfig::default() };
+        let outside = RuleConfig { symbol: "BTC-USD".to_string(), above: Some(Decimal::from(70_000)), ..RuleConfig::default() };
+        let rules = resolve(&speculative(), vec![own, bare, outside]);
+
+        assert_eq!(rules.len(), 3 + 4);  // Plus two rules per member
+        assert_eq!(rules[0].cooldown, Some(Duration::from_secs(5)));
+        assert_eq!((rules[1].cooldown, rules[1].window), (Some(Duration::from_secs(3600)), Some(Duration::from_secs(15 * 60))));
+        assert_eq!((rules[2].cooldown, rules[2].window), (None, None));
+        assert_eq!(group_of(&speculative(), "doge-usd").map(|(name, _)| name), Some("speculative"));
+    }
+}
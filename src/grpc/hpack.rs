@@ -0,0 +1,340 @@
+// HPACK, the header compression of HTTP/2 (RFC 7541). The decoder has to follow every
+// header block a client sends, since each one may add entries to a table that later
+// blocks refer back to. Our own headers are few and short, so the encoder writes each
+// one as a plain literal that adds nothing to the client's table.
+
+use std::collections::VecDeque;  // Dynamic table, newest first
+
+// Size of the dynamic table until the client says otherwise (SETTINGS_HEADER_TABLE_SIZE)
+const DEFAULT_TABLE_SIZE: usize = 4096;
+
+// Every entry counts its name, its value and this much overhead against the table size
+const ENTRY_OVERHEAD: usize = 32;
+
+// Indices 1 to 61, the same for everyone (RFC 7541 Appendix A)
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// Length in bits of the Huffman code of each byte value, then of end-of-string (256)
+// (RFC 7541 Appendix B). The code is canonical: codes of one length are consecutive
+// and follow the byte order, so the lengths are all it takes to rebuild it.
+const HUFFMAN_LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28,  // 0..
+    28, 28, 28, 28, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 28,  // 16..
+    6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6,          // 32..
+    5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10,               // 48..
+    13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,                 // 64..
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6,              // 80..
+    15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6, 6, 5,                 // 96..
+    6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28,             // 112..
+    20, 22, 20, 20, 22, 22, 22, 23, 22, 23, 23, 23, 23, 23, 24, 23,  // 128..
+    24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24,  // 144..
+    22, 21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23,  // 160..
+    21, 21, 22, 21, 23, 22, 23, 23, 20, 22, 22, 22, 23, 22, 22, 23,  // 176..
+    26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25,  // 192..
+    19, 21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27,  // 208..
+    20, 24, 20, 21, 22, 21, 21, 23, 22, 22, 25, 25, 24, 24, 26, 23,  // 224..
+    26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26,  // 240..
+    30,                                                              // end of string
+];
+
+const LONGEST_CODE: usize = 30;
+
+// A header field list decoded from one header block
+pub type Headers = Vec<(String, String)>;
+
+// One connection's decoding state
+pub struct Decoder {
+    table: VecDeque<(String, String)>,  // Dynamic table, newest first (index 62 onwards)
+    size: usize,                        // Its current size, as HPACK counts it
+    max_size: usize,                    // Its current limit, set by the client's size updates
+    huffman: Huffman,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self { table: VecDeque::new(), size: 0, max_size: DEFAULT_TABLE_SIZE, huffman: Huffman::new() }
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Every header field of one complete header block, in order
+    pub fn decode(&mut self, mut block: &[u8]) -> Result<Headers, String> {
+        let mut headers = Vec::new();
+        while let Some(&first) = block.first() {
+            if first & 0x80 != 0 {
+                // Indexed field: the whole entry from the table
+                let index = integer(&mut block, 7)?;
+                headers.push(self.entry(index)?);
+            } else if first & 0x40 != 0 {
+                // Literal to be added to the table
+                let field = self.literal(&mut block, 6)?;
+                self.insert(field.clone());
+                headers.push(field);
+            } else if first & 0x20 != 0 {
+                // Table size update (at most the default we advertise)
+                let size = integer(&mut block, 5)?;
+                if size > DEFAULT_TABLE_SIZE {
+                    return Err(format!("table size {} is more than allowed", size));
+                }
+                self.max_size = size;
+                self.evict();
+            } else {
+                // Literal not added to the table (0000) or never to be (0001)
+                headers.push(self.literal(&mut block, 4)?);
+            }
+        }
+        Ok(headers)
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), String> {
+        match index {
+            0 => Err("header index 0".to_string()),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((name.to_string(), value.to_string()))
+            }
+            _ => self.table.get(index - 62).cloned().ok_or_else(|| format!("header index {} is past the table", index)),
+        }
+    }
+
+    // A literal field whose name is either indexed (non-zero prefix) or follows as a string
+    fn literal(&self, block: &mut &[u8], prefix: u8) -> Result<(String, String), String> {
+        let name = match integer(block, prefix)? {
+            0 => self.string(block)?,
+            index => self.entry(index)?.0,
+        };
+        Ok((name, self.string(block)?))
+    }
+
+    fn string(&self, block: &mut &[u8]) -> Result<String, String> {
+        let huffman = block.first().is_some_and(|b| b & 0x80 != 0);
+        let len = integer(block, 7)?;
+        if len > block.len() {
+            return Err("string runs past the header block".to_string());
+        }
+        let (bytes, rest) = block.split_at(len);
+        *block = rest;
+        let bytes = if huffman { self.huffman.decode(bytes)? } else { bytes.to_vec() };
+        String::from_utf8(bytes).map_err(|_| "header is not UTF-8".to_string())
+    }
+
+    fn insert(&mut self, field: (String, String)) {
+        self.size += field.0.len() + field.1.len() + ENTRY_OVERHEAD;
+        self.table.push_front(field);
+        self.evict();
+    }
+
+    // Drop the oldest entries until the table fits its limit (an entry larger than the
+    // whole table just empties it)
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((name, value)) = self.table.pop_back() else { break };
+            self.size -= name.len() + value.len() + ENTRY_OVERHEAD;
+        }
+    }
+}
+
+// An integer with an N-bit prefix: the low bits of the first byte, continued 7 bits at
+// a time while the prefix is all ones
+fn integer(block: &mut &[u8], prefix: u8) -> Result<usize, String> {
+    let (&first, mut rest) = block.split_first().ok_or("header block ends early")?;
+    let max = (1usize << prefix) - 1;
+    let mut value = usize::from(first) & max;
+    if value == max {
+        let mut shift = 0;
+        loop {
+            let (&byte, tail) = rest.split_first().ok_or("header block ends early")?;
+            rest = tail;
+            if shift > 28 {
+                return Err("header integer too large".to_string());
+            }
+            value += usize::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+    *block = rest;
+    Ok(value)
+}
+
+// The canonical Huffman code, as counts per length and symbols in code order
+struct Huffman {
+    counts: [u32; LONGEST_CODE + 1],  // How many codes have each length
+    symbols: Vec<u16>,                // Sorted by (length, symbol): the order of their codes
+}
+
+impl Huffman {
+    fn new() -> Self {
+        let mut counts = [0; LONGEST_CODE + 1];
+        for &len in &HUFFMAN_LENGTHS {
+            counts[usize::from(len)] += 1;
+        }
+        let mut symbols: Vec<u16> = (0..=256).collect();
+        symbols.sort_by_key(|&s| (HUFFMAN_LENGTHS[usize::from(s)], s));
+        Self { counts, symbols }
+    }
+
+    // Bit by bit: at each length, the codes of that length are the `count` values
+    // starting at `first`
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        let mut out = Vec::with_capacity(bytes.len() * 8 / 5);
+        let (mut code, mut first, mut index, mut len) = (0u32, 0u32, 0usize, 0usize);
+        let mut all_ones = true;  // The bits since the last symbol; the padding must be all ones
+        for byte in bytes {
+            for bit in (0..8).rev() {
+                let one = (byte >> bit) & 1;
+                all_ones &= one == 1;
+                code |= u32::from(one);
+                len += 1;
+                let count = self.counts[len];
+                if code < first + count {
+                    match self.symbols[index + (code - first) as usize] {
+                        256 => return Err("end-of-string in a Huffman string".to_string()),
+                        symbol => out.push(symbol as u8),
+                    }
+                    (code, first, index, len, all_ones) = (0, 0, 0, 0, true);
+                    continue;
+                }
+                if len == LONGEST_CODE {
+                    return Err("invalid Huffman code".to_string());
+                }
+                index += count as usize;
+                first = (first + count) << 1;
+                code <<= 1;
+            }
+        }
+        if len >= 8 || !all_ones {
+            return Err("invalid Huffman padding".to_string());
+        }
+        Ok(out)
+    }
+}
+
+// A header block with each field as a literal that isn't added to the table
+pub fn encode(headers: &[(&str, &str)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, value) in headers {
+        out.push(0);  // Literal without indexing, new name
+        for text in [name, value] {
+            encode_integer(&mut out, text.len(), 7);  // Not Huffman-coded
+            out.extend(text.as_bytes());
+        }
+    }
+    out
+}
+
+fn encode_integer(out: &mut Vec<u8>, mut value: usize, prefix: u8) {
+    let max = (1usize << prefix) - 1;
+    if value < max {
+        out.push(value as u8);
+        return;
+    }
+    out.push(max as u8);
+    value -= max;
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn decodes_the_rfc_request_examples() {
+        // RFC 7541 C.4: three requests on one connection, Huffman-coded, sharing the table
+        let mut decoder = Decoder::new();
+        let first = decoder.decode(&hex("828684418cf1e3c2e5f23a6ba0ab90f4ff")).unwrap();
+        assert_eq!(first[3], (":authority".to_string(), "www.example.com".to_string()));
+        assert_eq!(decoder.size, 57);
+
+        let second = decoder.decode(&hex("828684be5886a8eb10649cbf")).unwrap();
+        assert_eq!(second[3], (":authority".to_string(), "www.example.com".to_string()));  // From the table
+        assert_eq!(second[4], ("cache-control".to_string(), "no-cache".to_string()));
+
+        let third = decoder.decode(&hex("828785bf408825a849e95ba97d7f8925a849e95bb8e8b4bf")).unwrap();
+        assert_eq!(third[1], (":scheme".to_string(), "https".to_string()));
+        assert_eq!(third[4], ("custom-key".to_string(), "custom-value".to_string()));
+        assert_eq!((decoder.table.len(), decoder.size), (3, 164));
+
+        // Our own encoding reads back, long values included
+        let long = "x".repeat(300);
+        let block = encode(&[(":status", "200"), ("grpc-message", &long)]);
+        assert_eq!(Decoder::new().decode(&block).unwrap()[1].1, long);
+        assert!(Decoder::new().decode(&hex("82ff")).is_err(), "truncated integer");
+    }
+}
@@ -0,0 +1,720 @@
+// Just enough of HTTP/2 (RFC 9113) to serve gRPC: cleartext connections that start
+// with the HTTP/2 preface ("prior knowledge", which is what gRPC clients do without
+// TLS), requests read in full before they're handled, and responses of headers, data
+// and trailers sent within the client's flow control windows. No server push, no
+// priorities, no TLS.
+//
+// Each connection runs a reader task that parses frames and one loop that owns the
+// socket's write half: it answers SETTINGS and PING, tracks the windows, hands complete
+// requests to the handler and writes out what the handlers queue for their streams.
+
+use std::{
+    collections::{HashMap, VecDeque},  // Open streams; parts waiting for flow control
+    io,
+    sync::Arc,                         // Handler shared by every connection
+};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+use super::hpack::{self, Headers};
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+// Frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+// Flags
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+// Settings
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+// Error codes
+const NO_ERROR: u32 = 0x0;
+const PROTOCOL_ERROR: u32 = 0x1;
+const FLOW_CONTROL_ERROR: u32 = 0x3;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+const REFUSED_STREAM: u32 = 0x7;
+const ENHANCE_YOUR_CALM: u32 = 0xb;
+
+const DEFAULT_WINDOW: i64 = 65_535;
+const MAX_WINDOW: i64 = (1 << 31) - 1;
+const DEFAULT_FRAME_SIZE: usize = 16_384;
+const MAX_FRAME_SIZE: usize = (1 << 24) - 1;
+
+// Streams one client may have open at once
+const MAX_STREAMS: usize = 100;
+
+// Largest request body we accept (requests are a few symbol names), and largest header
+// block: a client that keeps sending CONTINUATION frames is cut off, not buffered
+const MAX_REQUEST: usize = 64 * 1024;
+
+// Response bytes a stream may have waiting for the client's window before new data is
+// dropped: a client that doesn't read skips updates rather than growing our memory
+const MAX_PENDING: usize = 1 << 20;
+
+// Frames read ahead of the connection loop
+const FRAME_QUEUE: usize = 64;
+
+// Parts queued by all of a connection's handlers
+const PART_QUEUE: usize = 256;
+
+// One frame off the wire
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream: u32,
+    payload: Vec<u8>,
+}
+
+// A complete request, as handed to the handler
+#[derive(Debug)]
+pub struct Request {
+    pub path: String,
+    pub headers: Headers,   // All of them, pseudo-headers included
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    // First value of a header
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+// Something to send on a stream
+#[derive(Debug)]
+enum Part {
+    Headers { fields: Vec<(String, String)>, end_stream: bool },
+    Data { bytes: Vec<u8>, sent: usize },  // `sent` bytes of it are already out
+}
+
+// A handler's way of answering one request. The stream ends with headers that have
+// `end_stream` set (trailers, or a headers-only response).
+pub struct Responder {
+    stream: u32,
+    parts: mpsc::Sender<(u32, Part)>,
+    reset: oneshot::Receiver<()>,  // Completes when the client resets the stream or goes away
+}
+
+impl Responder {
+    // False once the connection is gone
+    pub async fn headers(&self, fields: &[(&str, &str)], end_stream: bool) -> bool {
+        let fields = fields.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect();
+        self.parts.send((self.stream, Part::Headers { fields, end_stream })).await.is_ok()
+    }
+
+    pub async fn data(&self, bytes: Vec<u8>) -> bool {
+        self.parts.send((self.stream, Part::Data { bytes, sent: 0 })).await.is_ok()
+    }
+
+    // Completes when nobody is listening any more
+    pub async fn cancelled(&mut self) {
+        let _ = (&mut self.reset).await;
+    }
+}
+
+// What the server does with each request; it should answer through the responder
+// without holding up the connection (spawning a task if it takes a while)
+pub type Handler = Arc<dyn Fn(Request, Responder) + Send + Sync>;
+
+// One stream's state, from its first HEADERS frame until its last frame is sent
+#[derive(Default)]
+struct Stream {
+    request: Option<(String, Headers)>,  // Path and headers, once the header block is complete
+    body: Vec<u8>,
+    window: i64,                         // What we may still send on it
+    pending: VecDeque<Part>,             // Waiting for the window (or for earlier parts)
+    pending_bytes: usize,
+    skipped: u64,                        // Data dropped because the client wasn't reading
+    reset: Option<oneshot::Sender<()>>,  // Dropped to tell the handler to stop
+}
+
+// Serve one client connection until it closes
+pub async fn serve_connection(socket: TcpStream, handler: Handler) {
+    let (read, write) = socket.into_split();
+    let (frames_tx, frames) = mpsc::channel(FRAME_QUEUE);
+    let reader = tokio::spawn(read_frames(read, frames_tx));
+    let mut connection = Connection::new(write, handler);
+    if let Err(e) = connection.run(frames).await {
+        debug!(error = %e, "gRPC connection closed");
+    }
+    reader.abort();
+}
+
+// Read the preface, then frames until the socket closes
+async fn read_frames(read: OwnedReadHalf, frames: mpsc::Sender<io::Result<Frame>>) {
+    let mut read = BufReader::new(read);
+    let mut preface = [0; PREFACE.len()];
+    if let Err(e) = read.read_exact(&mut preface).await {
+        let _ = frames.send(Err(e)).await;
+        return;
+    }
+    if preface != PREFACE {
+        let _ = frames.send(Err(io::Error::new(io::ErrorKind::InvalidData, "not an HTTP/2 connection"))).await;
+        return;
+    }
+    loop {
+        let frame = read_frame(&mut read).await;
+        let failed = frame.is_err();
+        if frames.send(frame).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+// 9-byte header (24-bit length, type, flags, 31-bit stream id), then the payload.
+// Clients may not send more than the default frame size, since we never raise it.
+async fn read_frame(read: &mut BufReader<OwnedReadHalf>) -> io::Result<Frame> {
+    let mut header = [0; 9];
+    read.read_exact(&mut header).await?;
+    let len = usize::from(header[0]) << 16 | usize::from(header[1]) << 8 | usize::from(header[2]);
+    if len > DEFAULT_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {} bytes", len)));
+    }
+    let stream = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff;
+    let mut payload = vec![0; len];
+    read.read_exact(&mut payload).await?;
+    Ok(Frame { kind: header[3], flags: header[4], stream, payload })
+}
+
+struct Connection {
+    write: OwnedWriteHalf,
+    handler: Handler,
+    decoder: hpack::Decoder,
+    streams: HashMap<u32, Stream>,
+    last_stream: u32,                     // Highest stream id the client has opened
+    continuation: Option<(u32, u8, Vec<u8>)>,  // Header block still arriving: stream, flags, bytes so far
+    window: i64,                          // What we may still send on the connection
+    initial_window: i64,                  // Each new stream's window (the client's setting)
+    max_frame: usize,                     // Largest frame the client accepts
+    parts_tx: mpsc::Sender<(u32, Part)>,  // Cloned into each responder
+    parts: mpsc::Receiver<(u32, Part)>,
+}
+
+impl Connection {
+    fn new(write: OwnedWriteHalf, handler: Handler) -> Self {
+        let (parts_tx, parts) = mpsc::channel(PART_QUEUE);
+        Self {
+            write,
+            handler,
+            decoder: hpack::Decoder::new(),
+            streams: HashMap::new(),
+            last_stream: 0,
+            continuation: None,
+            window: DEFAULT_WINDOW,
+            initial_window: DEFAULT_WINDOW,
+            max_frame: DEFAULT_FRAME_SIZE,
+            parts_tx,
+            parts,
+        }
+    }
+
+    async fn run(&mut self, mut frames: mpsc::Receiver<io::Result<Frame>>) -> io::Result<()> {
+        let mut settings = Vec::new();
+        settings.extend(SETTINGS_MAX_CONCURRENT_STREAMS.to_be_bytes());
+        settings.extend((MAX_STREAMS as u32).to_be_bytes());
+        self.send(SETTINGS, 0, 0, &settings).await?;
+
+        loop {
+            tokio::select! {
+                frame = frames.recv() => match frame {
+                    Some(Ok(frame)) => {
+                        if !self.handle(frame).await? {
+                            return Ok(());
+                        }
+                    }
+                    Some(Err(e)) => {
+                        if e.kind() == io::ErrorKind::InvalidData {
+                            self.go_away(FRAME_SIZE_ERROR).await?;
+                        }
+                        return Err(e);
+                    }
+                    None => return Ok(()),
+                },
+                Some((stream, part)) = self.parts.recv() => self.queue(stream, part).await?,
+            }
+        }
+    }
+
+    // Act on one frame from the client; false ends the connection
+    async fn handle(&mut self, frame: Frame) -> io::Result<bool> {
+        // A header block split over several frames must continue without interruption
+        if let Some((stream, flags, mut block)) = self.continuation.take() {
+            if frame.kind != CONTINUATION || frame.stream != stream {
+                self.go_away(PROTOCOL_ERROR).await?;
+                return Ok(false);
+            }
+            block.extend(&frame.payload);
+            if block.len() > MAX_REQUEST {
+                debug!(stream, bytes = block.len(), "Header block too large");
+                self.go_away(ENHANCE_YOUR_CALM).await?;
+                return Ok(false);
+            }
+            if frame.flags & END_HEADERS == 0 {
+                self.continuation = Some((stream, flags, block));
+                return Ok(true);
+            }
+            return self.header_block(stream, flags, &block).await;
+        }
+
+        match frame.kind {
+            HEADERS => {
+                let Some(block) = unpad(&frame, frame.flags & PRIORITY != 0) else {
+                    self.go_away(PROTOCOL_ERROR).await?;
+                    return Ok(false);
+                };
+                if frame.flags & END_HEADERS == 0 {
+                    self.continuation = Some((frame.stream, frame.flags, block.to_vec()));
+                    return Ok(true);
+                }
+                self.header_block(frame.stream, frame.flags, block).await
+            }
+            DATA => {
+                let Some(data) = unpad(&frame, false) else {
+                    self.go_away(PROTOCOL_ERROR).await?;
+                    return Ok(false);
+                };
+                // Give the client its window back straight away: bodies are small, and
+                // one that isn't gets the stream reset below
+                if !frame.payload.is_empty() {
+                    let increment = (frame.payload.len() as u32).to_be_bytes();
+                    self.send(WINDOW_UPDATE, 0, 0, &increment).await?;
+                    self.send(WINDOW_UPDATE, 0, frame.stream, &increment).await?;
+                }
+                let Some(stream) = self.streams.get_mut(&frame.stream) else { return Ok(true) };
+                stream.body.extend(data);
+                if stream.body.len() > MAX_REQUEST {
+                    self.reset(frame.stream, REFUSED_STREAM).await?;
+                } else if frame.flags & END_STREAM != 0 {
+                    self.dispatch(frame.stream);
+                }
+                Ok(true)
+            }
+            SETTINGS if frame.flags & ACK == 0 => {
+                for setting in frame.payload.chunks_exact(6) {
+                    let id = u16::from_be_bytes([setting[0], setting[1]]);
+                    let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+                    match id {
+                        SETTINGS_INITIAL_WINDOW_SIZE => {
+                            if i64::from(value) > MAX_WINDOW {
+                                self.go_away(FLOW_CONTROL_ERROR).await?;
+                                return Ok(false);
+                            }
+                            // Applies to open streams too, as a change from the old value,
+                            // and mustn't take any of them past the limit (RFC 9113 6.9.2)
+                            let delta = i64::from(value) - self.initial_window;
+                            if self.streams.values().any(|s| s.window + delta > MAX_WINDOW) {
+                                self.go_away(FLOW_CONTROL_ERROR).await?;
+                                return Ok(false);
+                            }
+                            self.initial_window = i64::from(value);
+                            self.streams.values_mut().for_each(|s| s.window += delta);
+                        }
+                        SETTINGS_MAX_FRAME_SIZE => {
+                            // Anything outside 16 KiB..16 MiB is a protocol error (RFC 9113 6.5.2)
+                            if !(DEFAULT_FRAME_SIZE..=MAX_FRAME_SIZE).contains(&(value as usize)) {
+                                self.go_away(PROTOCOL_ERROR).await?;
+                                return Ok(false);
+                            }
+                            self.max_frame = value as usize;
+                        }
+                        _ => {}
+                    }
+                }
+                self.send(SETTINGS, ACK, 0, &[]).await?;
+                self.flush().await?;
+                Ok(true)
+            }
+            PING if frame.flags & ACK == 0 => {
+                self.send(PING, ACK, 0, &frame.payload).await?;
+                Ok(true)
+            }
+            WINDOW_UPDATE | RST_STREAM if frame.payload.len() != 4 => {
+                self.go_away(FRAME_SIZE_ERROR).await?;
+                Ok(false)
+            }
+            WINDOW_UPDATE => {
+                // A zero increment is a protocol error, and no window may go past 2^31-1
+                // (RFC 9113 6.9): for the connection's window that ends the connection,
+                // for a stream's only the stream
+                let increment = i64::from(u32::from_be_bytes(frame.payload[..4].try_into().unwrap()) & 0x7fff_ffff);
+                match frame.stream {
+                    0 if increment == 0 => {
+                        self.go_away(PROTOCOL_ERROR).await?;
+                        return Ok(false);
+                    }
+                    0 if self.window + increment > MAX_WINDOW => {
+                        self.go_away(FLOW_CONTROL_ERROR).await?;
+                        return Ok(false);
+                    }
+                    0 => self.window += increment,
+                    id => {
+                        let Some(stream) = self.streams.get_mut(&id) else { return Ok(true) };  // Closed already
+                        if increment == 0 {
+                            self.reset(id, PROTOCOL_ERROR).await?;
+                            return Ok(true);
+                        }
+                        if stream.window + increment > MAX_WINDOW {
+                            self.reset(id, FLOW_CONTROL_ERROR).await?;
+                            return Ok(true);
+                        }
+                        stream.window += increment;
+                    }
+                }
+                self.flush().await?;
+                Ok(true)
+            }
+            RST_STREAM => {
+                if frame.stream == 0 {
+                    self.go_away(PROTOCOL_ERROR).await?;
+                    return Ok(false);
+                }
+                self.streams.remove(&frame.stream);  // Drops `reset`, which stops the handler
+                Ok(true)
+            }
+            GOAWAY => Ok(false),
+            _ => Ok(true),  // PRIORITY, acknowledgements, unknown frame types
+        }
+    }
+
+    // A complete header block: a new request, or (ignored) trailers on an open one
+    async fn header_block(&mut self, id: u32, flags: u8, block: &[u8]) -> io::Result<bool> {
+        let headers = match self.decoder.decode(block) {
+            Ok(headers) => headers,
+            Err(e) => {
+                debug!(error = %e, "Undecodable header block");
+                self.go_away(PROTOCOL_ERROR).await?;  // The header table is out of step now
+                return Ok(false);
+            }
+        };
+        // Clients open odd-numbered streams, in increasing order
+        if id.is_multiple_of(2) || (id <= self.last_stream && !self.streams.contains_key(&id)) {
+            self.go_away(PROTOCOL_ERROR).await?;
+            return Ok(false);
+        }
+        if id > self.last_stream {
+            self.last_stream = id;
+            if self.streams.len() >= MAX_STREAMS {
+                self.reset(id, REFUSED_STREAM).await?;
+                return Ok(true);
+            }
+            let path = headers.iter().find(|(n, _)| n == ":path").map(|(_, v)| v.clone()).unwrap_or_default();
+            let stream = Stream { request: Some((path, headers)), window: self.initial_window, ..Stream::default() };
+            self.streams.insert(id, stream);
+        }
+        if flags & END_STREAM != 0 {
+            self.dispatch(id);
+        }
+        Ok(true)
+    }
+
+    // The client has sent the whole request: hand it to the handler
+    fn dispatch(&mut self, id: u32) {
+        let Some(stream) = self.streams.get_mut(&id) else { return };
+        let Some((path, headers)) = stream.request.take() else { return };
+        let (reset_tx, reset) = oneshot::channel();
+        stream.reset = Some(reset_tx);
+        let request = Request { path, headers, body: std::mem::take(&mut stream.body) };
+        (self.handler)(request, Responder { stream: id, parts: self.parts_tx.clone(), reset });
+    }
+
+    // A handler's part for a stream: send it now if the windows allow, else queue it
+    async fn queue(&mut self, id: u32, part: Part) -> io::Result<()> {
+        let Some(stream) = self.streams.get_mut(&id) else { return Ok(()) };  // Reset meanwhile
+        if let Part::Data { bytes, .. } = &part {
+            if stream.pending_bytes + bytes.len() > MAX_PENDING {
+                stream.skipped += 1;
+                debug!(stream = id, skipped = stream.skipped, "gRPC client isn't reading, dropped a message");
+                return Ok(());
+            }
+            stream.pending_bytes += bytes.len();
+        }
+        stream.pending.push_back(part);
+        self.flush_stream(id).await
+    }
+
+    // Send what the windows allow on every stream
+    async fn flush(&mut self) -> io::Result<()> {
+        let ids: Vec<u32> = self.streams.keys().copied().collect();
+        for id in ids {
+            self.flush_stream(id).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush_stream(&mut self, id: u32) -> io::Result<()> {
+        loop {
+            let Some(stream) = self.streams.get_mut(&id) else { return Ok(()) };
+            match stream.pending.front_mut() {
+                None => return Ok(()),
+                Some(Part::Headers { fields, end_stream }) => {
+                    let fields: Vec<(&str, &str)> = fields.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+                    let block = hpack::encode(&fields);
+                    let end_stream = *end_stream;
+                    stream.pending.pop_front();
+                    if end_stream {
+                        self.streams.remove(&id);  // Done: the handler's part is over
+                    }
+                    self.send_headers(id, &block, end_stream).await?;
+                }
+                Some(Part::Data { bytes, sent }) => {
+                    let allowed = self.window.min(stream.window).min(self.max_frame as i64);
+                    if allowed <= 0 {
+                        return Ok(());  // Wait for a WINDOW_UPDATE
+                    }
+                    let chunk = (bytes.len() - *sent).min(allowed as usize);
+                    let frame = bytes[*sent..*sent + chunk].to_vec();
+                    *sent += chunk;
+                    if *sent == bytes.len() {
+                        stream.pending_bytes -= bytes.len();
+                        stream.pending.pop_front();
+                    }
+                    stream.window -= chunk as i64;
+                    self.window -= chunk as i64;
+                    self.send(DATA, 0, id, &frame).await?;
+                }
+            }
+        }
+    }
+
+    // A header block, split into CONTINUATION frames if it's larger than a frame
+    async fn send_headers(&mut self, id: u32, block: &[u8], end_stream: bool) -> io::Result<()> {
+        let mut chunks = block.chunks(self.max_frame).peekable();
+        let first = chunks.next().unwrap_or_default();
+        let end = if end_stream { END_STREAM } else { 0 };
+        let last = if chunks.peek().is_none() { END_HEADERS } else { 0 };
+        self.send(HEADERS, end | last, id, first).await?;
+        while let Some(chunk) = chunks.next() {
+            let last = if chunks.peek().is_none() { END_HEADERS } else { 0 };
+            self.send(CONTINUATION, last, id, chunk).await?;
+        }
+        Ok(())
+    }
+
+    async fn reset(&mut self, id: u32, code: u32) -> io::Result<()> {
+        self.streams.remove(&id);
+        self.send(RST_STREAM, 0, id, &code.to_be_bytes()).await
+    }
+
+    async fn go_away(&mut self, code: u32) -> io::Result<()> {
+        let mut payload = self.last_stream.to_be_bytes().to_vec();
+        payload.extend(code.to_be_bytes());
+        self.send(GOAWAY, 0, 0, &payload).await?;
+        if code != NO_ERROR {
+            debug!(code, "Sent GOAWAY");
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, kind: u8, flags: u8, stream: u32, payload: &[u8]) -> io::Result<()> {
+        let len = (payload.len() as u32).to_be_bytes();
+        let mut frame = Vec::with_capacity(9 + payload.len());
+        frame.extend(&len[1..]);
+        frame.extend([kind, flags]);
+        frame.extend(stream.to_be_bytes());
+        frame.extend(payload);
+        self.write.write_all(&frame).await
+    }
+}
+
+// The payload of a HEADERS or DATA frame without its padding (and, for HEADERS with the
+// PRIORITY flag, the 5 bytes of priority); None if the padding doesn't fit
+fn unpad(frame: &Frame, priority: bool) -> Option<&[u8]> {
+    let mut payload = frame.payload.as_slice();
+    if frame.flags & PADDED != 0 {
+        let (&pad, rest) = payload.split_first()?;
+        payload = rest.get(..rest.len().checked_sub(usize::from(pad))?)?;
+    }
+    if priority {
+        payload = payload.get(5..)?;
+    }
+    Some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // A connection served by `serve_connection` on loopback, past the preface, with the
+    // server's own SETTINGS already read
+    async fn connect() -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler: Handler = Arc::new(|_, _| {});
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            serve_connection(socket, handler).await;
+        });
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        socket.write_all(PREFACE).await.unwrap();
+        assert_eq!(read(&mut socket).await.0, SETTINGS);
+        socket
+    }
+
+    async fn write(socket: &mut TcpStream, kind: u8, flags: u8, stream: u32, payload: &[u8]) {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend([kind, flags]);
+        frame.extend(stream.to_be_bytes());
+        frame.extend(payload);
+        socket.write_all(&frame).await.unwrap();
+    }
+
+    // Type, flags and payload of the next frame from the server
+    async fn read(socket: &mut TcpStream) -> (u8, u8, Vec<u8>) {
+        let mut header = [0; 9];
+        socket.read_exact(&mut header).await.unwrap();
+        let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let mut payload = vec![0; len];
+        socket.read_exact(&mut payload).await.unwrap();
+        (header[3], header[4], payload)
+    }
+
+    // The error code of the GOAWAY the server ends with, and that it then closes
+    async fn goaway_code(socket: &mut TcpStream) -> u32 {
+        let (kind, _, payload) = read(socket).await;
+        assert_eq!(kind, GOAWAY);
+        assert_eq!(socket.read(&mut [0; 1]).await.unwrap(), 0, "connection closed after GOAWAY");
+        u32::from_be_bytes(payload[4..8].try_into().unwrap())
+    }
+
+    fn setting(id: u16, value: u32) -> Vec<u8> {
+        let mut payload = id.to_be_bytes().to_vec();
+        payload.extend(value.to_be_bytes());
+        payload
+    }
+
+    #[test]
+    fn max_frame_size_outside_the_allowed_range_is_a_protocol_error() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            for value in [0, 1, 16_383, 1 << 24] {
+                let mut socket = connect().await;
+                write(&mut socket, SETTINGS, 0, 0, &setting(SETTINGS_MAX_FRAME_SIZE, value)).await;
+                assert_eq!(goaway_code(&mut socket).await, PROTOCOL_ERROR, "max frame size {}", value);
+            }
+            let mut socket = connect().await;
+            write(&mut socket, SETTINGS, 0, 0, &setting(SETTINGS_INITIAL_WINDOW_SIZE, 1 << 31)).await;
+            assert_eq!(goaway_code(&mut socket).await, FLOW_CONTROL_ERROR);
+
+            // Both ends of the range are fine, and acknowledged
+            let mut socket = connect().await;
+            write(&mut socket, SETTINGS, 0, 0, &[setting(SETTINGS_MAX_FRAME_SIZE, 16_384), setting(SETTINGS_MAX_FRAME_SIZE, (1 << 24) - 1)].concat()).await;
+            assert_eq!(read(&mut socket).await, (SETTINGS, ACK, Vec::new()));
+            write(&mut socket, PING, 0, 0, &[7; 8]).await;
+            assert_eq!(read(&mut socket).await, (PING, ACK, vec![7; 8]));
+        });
+    }
+
+    // Open stream `id` with a request the handler never answers
+    async fn open_stream(socket: &mut TcpStream, id: u32) {
+        let block = hpack::encode(&[(":method", "POST"), (":path", "/crabby.Prices/StreamPrices")]);
+        write(socket, HEADERS, END_HEADERS | END_STREAM, id, &block).await;
+    }
+
+    #[test]
+    fn window_updates_past_the_limit_are_flow_control_errors() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let window = |increment: u32| increment.to_be_bytes();
+            // The connection's window starts at 65,535: room for 2^31-1 - 65,535 more, not one byte over
+            let mut socket = connect().await;
+            write(&mut socket, WINDOW_UPDATE, 0, 0, &window(((1 << 31) - 1) - 65_535)).await;
+            write(&mut socket, PING, 0, 0, &[1; 8]).await;
+            assert_eq!(read(&mut socket).await, (PING, ACK, vec![1; 8]), "still open at the limit");
+            write(&mut socket, WINDOW_UPDATE, 0, 0, &window(1)).await;
+            assert_eq!(goaway_code(&mut socket).await, FLOW_CONTROL_ERROR);
+
+            // A stream's overflow resets only that stream
+            let mut socket = connect().await;
+            open_stream(&mut socket, 1).await;
+            write(&mut socket, WINDOW_UPDATE, 0, 1, &window((1 << 31) - 1)).await;
+            let (kind, _, payload) = read(&mut socket).await;
+            assert_eq!((kind, u32::from_be_bytes(payload[..4].try_into().unwrap())), (RST_STREAM, FLOW_CONTROL_ERROR));
+            write(&mut socket, PING, 0, 0, &[2; 8]).await;
+            assert_eq!(read(&mut socket).await, (PING, ACK, vec![2; 8]), "the connection carries on");
+
+            // So does a new initial window that would take an open stream past the limit
+            let mut socket = connect().await;
+            open_stream(&mut socket, 1).await;
+            write(&mut socket, WINDOW_UPDATE, 0, 1, &window(1 << 30)).await;
+            write(&mut socket, SETTINGS, 0, 0, &setting(SETTINGS_INITIAL_WINDOW_SIZE, 1 << 30)).await;
+            assert_eq!(goaway_code(&mut socket).await, FLOW_CONTROL_ERROR);
+        });
+    }
+
+    #[test]
+    fn zero_window_updates_are_protocol_errors() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut socket = connect().await;
+            write(&mut socket, WINDOW_UPDATE, 0, 0, &0u32.to_be_bytes()).await;
+            assert_eq!(goaway_code(&mut socket).await, PROTOCOL_ERROR);
+
+            let mut socket = connect().await;
+            open_stream(&mut socket, 1).await;
+            write(&mut socket, WINDOW_UPDATE, 0, 1, &0u32.to_be_bytes()).await;
+            let (kind, _, payload) = read(&mut socket).await;
+            assert_eq!((kind, u32::from_be_bytes(payload[..4].try_into().unwrap())), (RST_STREAM, PROTOCOL_ERROR));
+
+            // Not four bytes: the frame itself is malformed
+            let mut socket = connect().await;
+            write(&mut socket, WINDOW_UPDATE, 0, 0, &[0, 0, 1]).await;
+            assert_eq!(goaway_code(&mut socket).await, FRAME_SIZE_ERROR);
+        });
+    }
+
+    #[test]
+    fn rst_stream_on_the_connection_is_a_protocol_error() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut socket = connect().await;
+            write(&mut socket, RST_STREAM, 0, 0, &NO_ERROR.to_be_bytes()).await;
+            assert_eq!(goaway_code(&mut socket).await, PROTOCOL_ERROR);
+
+            // On a stream it just closes the stream
+            let mut socket = connect().await;
+            open_stream(&mut socket, 1).await;
+            write(&mut socket, RST_STREAM, 0, 1, &NO_ERROR.to_be_bytes()).await;
+            write(&mut socket, PING, 0, 0, &[3; 8]).await;
+            assert_eq!(read(&mut socket).await, (PING, ACK, vec![3; 8]));
+        });
+    }
+
+    #[test]
+    fn endless_continuation_frames_are_cut_off() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut socket = connect().await;
+            write(&mut socket, HEADERS, 0, 1, &[0; DEFAULT_FRAME_SIZE]).await;
+            for _ in 0..MAX_REQUEST / DEFAULT_FRAME_SIZE {
+                write(&mut socket, CONTINUATION, 0, 1, &[0; DEFAULT_FRAME_SIZE]).await;
+            }
+            assert_eq!(goaway_code(&mut socket).await, ENHANCE_YOUR_CALM);
+
+            // A CONTINUATION for another stream in the middle of a header block is an error too
+            let mut socket = connect().await;
+            write(&mut socket, HEADERS, 0, 1, &[0; 16]).await;
+            write(&mut socket, CONTINUATION, END_HEADERS, 3, &[0; 16]).await;
+            assert_eq!(goaway_code(&mut socket).await, PROTOCOL_ERROR);
+        });
+    }
+}
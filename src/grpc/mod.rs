@@ -0,0 +1,439 @@
+// gRPC API for programs that would rather not parse JSON off a WebSocket: the
+// `crabby.v1.PriceTracker` service from proto/tracker.proto, served on `[grpc] addr`
+// (--grpc-addr, CRABBY_GRPC_ADDR) over cleartext HTTP/2.
+//
+//   GetSnapshot(SnapshotRequest) returns (Snapshot)
+//       The latest price of every symbol asked for (all of them if none are).
+//   StreamPrices(StreamPricesRequest) returns (stream PriceUpdate)
+//       The same snapshot first, then every update for those symbols as it arrives.
+//       A client that can't keep up skips updates rather than slowing the others down.
+//
+// Generate a client from the .proto with any gRPC toolchain, or try it with
+// grpcurl -plaintext -import-path proto -proto tracker.proto -d '{"symbols":["BTC-USD"]}' \
+//     127.0.0.1:50051 crabby.v1.PriceTracker/StreamPrices
+//
+// Like the Kafka and MQTT clients, this speaks the protocol itself rather than pulling
+// in a framework: the service is two methods over four small messages, and tonic would
+// bring h2, prost and a protoc step at build time for them. HTTP/2 and HPACK are in
+// `http2` and `hpack`; the protobuf encoding (varints and length-delimited fields) is
+// below. Messages are never compressed.
+
+mod hpack;
+mod http2;
+
+use std::{
+    collections::BTreeSet,   // A call's symbol filter
+    io,                      // Bind errors
+    net::SocketAddr,         // Listen address
+    sync::Arc,               // The handler, shared by every connection
+};
+
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+
+use crate::store::{epoch_ms, PriceStore, PriceUpdate};
+use http2::{Handler, Request, Responder};
+
+const GET_SNAPSHOT: &str = "/crabby.v1.PriceTracker/GetSnapshot";
+const STREAM_PRICES: &str = "/crabby.v1.PriceTracker/StreamPrices";
+
+// gRPC status codes we answer with
+const OK: u32 = 0;
+const INVALID_ARGUMENT: u32 = 3;
+const UNIMPLEMENTED: u32 = 12;
+
+// Every message is prefixed by a compressed flag and a 4-byte big-endian length
+const MESSAGE_PREFIX: usize = 5;
+
+// The listening server. Bind it first (so a busy port fails at startup), then `run` it.
+pub struct GrpcServer {
+    listener: TcpListener,
+}
+
+impl GrpcServer {
+    pub async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self { listener: TcpListener::bind(addr).await? })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Accept clients until the task is dropped
+    pub async fn run(self, store: PriceStore) {
+        if let Ok(addr) = self.listener.local_addr() {
+            info!("gRPC API listening on http://{}", addr);
+        }
+        let handler: Handler = Arc::new(move |request, responder| {
+            tokio::spawn(call(store.clone(), request, responder));
+        });
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    debug!(%peer, "gRPC client connected");
+                    let _ = stream.set_nodelay(true);  // Updates are small and should go out at once
+                    tokio::spawn(http2::serve_connection(stream, Arc::clone(&handler)));
+                }
+                Err(e) => warn!(error = %e, "gRPC server couldn't accept a connection"),
+            }
+        }
+    }
+}
+
+// One RPC, from request to trailers
+async fn call(store: PriceStore, request: Request, mut responder: Responder) {
+    if !request.header("content-type").is_some_and(|t| t.starts_with("application/grpc")) {
+        responder.headers(&[(":status", "415")], true).await;
+        return;
+    }
+    if request.path != GET_SNAPSHOT && request.path != STREAM_PRICES {
+        finish(&responder, false, UNIMPLEMENTED, &format!("unknown method {}", request.path)).await;
+        return;
+    }
+    let symbols = match unframe(&request.body).and_then(decode_symbols) {
+        Ok(symbols) => symbols,
+        Err((code, message)) => {
+            finish(&responder, false, code, &message).await;
+            return;
+        }
+    };
+    let matches = |update: &PriceUpdate| symbols.is_empty() || symbols.contains(&update.symbol);
+
+    if request.path == GET_SNAPSHOT {
+        let mut snapshot = Vec::new();
+        for update in store.snapshot().await.iter().filter(|u| matches(u)) {
+            length_delimited(&mut snapshot, 1, &encode_update(update));
+        }
+        let _ = start(&responder).await && responder.data(frame(&snapshot)).await && finish(&responder, true, OK, "").await;
+        return;
+    }
+
+    // Subscribe before taking the snapshot so nothing slips through in between
+    let mut updates = store.subscribe_updates();
+    if !start(&responder).await {
+        return;
+    }
+    for update in store.snapshot().await.iter().filter(|u| matches(u)) {
+        if !responder.data(frame(&encode_update(update))).await {
+            return;
+        }
+    }
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) if matches(&update) => {
+                    if !responder.data(frame(&encode_update(&update))).await {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => {
+                    debug!(skipped = n, "gRPC stream fell behind");
+                    store.metrics().record_dropped_events("grpc", n);
+                }
+                Err(RecvError::Closed) => {
+                    finish(&responder, true, OK, "").await;
+                    return;
+                }
+            },
+            () = responder.cancelled() => return,  // The client hung up
+        }
+    }
+}
+
+// Response headers, before any message
+async fn start(responder: &Responder) -> bool {
+    responder.headers(&[(":status", "200"), ("content-type", "application/grpc")], false).await
+}
+
+// Trailers carrying the call's status. Without earlier headers they're the whole
+// response ("trailers-only"), so they need the HTTP status too.
+async fn finish(responder: &Responder, started: bool, code: u32, message: &str) -> bool {
+    let code = code.to_string();
+    let message = percent_encode(message);
+    let mut fields = vec![("grpc-status", code.as_str())];
+    if !message.is_empty() {
+        fields.push(("grpc-message", &message));
+    }
+    if !started {
+        fields.splice(0..0, [(":status", "200"), ("content-type", "application/grpc")]);
+    }
+    responder.headers(&fields, true).await
+}
+
+// grpc-message is percent-encoded outside of printable ASCII
+fn percent_encode(text: &str) -> String {
+    let mut out = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b' '..=b'~' if byte != b'%' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+// A request body to its one message
+fn unframe(body: &[u8]) -> Result<&[u8], (u32, String)> {
+    if body.is_empty() {
+        return Ok(&[]);  // Some clients send nothing at all for an empty message
+    }
+    let invalid = |message: &str| (INVALID_ARGUMENT, message.to_string());
+    let prefix = body.get(..MESSAGE_PREFIX).ok_or_else(|| invalid("truncated message"))?;
+    if prefix[0] != 0 {
+        return Err((UNIMPLEMENTED, "compressed messages aren't supported".to_string()));
+    }
+    let len = u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as usize;
+    match body.len() - MESSAGE_PREFIX {
+        n if n < len => Err(invalid("truncated message")),
+        n if n > len => Err(invalid("expected exactly one request message")),
+        _ => Ok(&body[MESSAGE_PREFIX..]),
+    }
+}
+
+// A response message with its prefix
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(MESSAGE_PREFIX + message.len());
+    out.push(0);
+    out.extend((message.len() as u32).to_be_bytes());
+    out.extend(message);
+    out
+}
+
+// SnapshotRequest and StreamPricesRequest alike: `repeated string symbols = 1`.
+// Fields we don't know are skipped, as protobuf requires.
+fn decode_symbols(mut message: &[u8]) -> Result<BTreeSet<String>, (u32, String)> {
+    let invalid = |e: &str| (INVALID_ARGUMENT, format!("invalid request message: {}", e));
+    let mut symbols = BTreeSet::new();
+    while !message.is_empty() {
+        let key = read_varint(&mut message).ok_or_else(|| invalid("bad field key"))?;
+        match (key >> 3, key & 7) {
+            (1, 2) => {
+                let bytes = read_bytes(&mut message).ok_or_else(|| invalid("truncated symbol"))?;
+                let symbol = std::str::from_utf8(bytes).map_err(|_| invalid("symbol isn't UTF-8"))?;
+                symbols.insert(symbol.trim().to_uppercase());
+            }
+            (_, 0) => drop(read_varint(&mut message).ok_or_else(|| invalid("truncated varint"))?),
+            (_, 1) => message = message.get(8..).ok_or_else(|| invalid("truncated field"))?,
+            (_, 2) => drop(read_bytes(&mut message).ok_or_else(|| invalid("truncated field"))?),
+            (_, 5) => message = message.get(4..).ok_or_else(|| invalid("truncated field"))?,
+            (_, wire) => return Err(invalid(&format!("wire type {}", wire))),
+        }
+    }
+    Ok(symbols)
+}
+
+// message PriceUpdate {
+//   string exchange = 1; string symbol = 2; string price = 3;
+//   optional string open_24h = 4; optional string size = 5; int64 timestamp_ms = 6;
+// }
+// Prices are decimal text, exactly as the exchange sent them.
+fn encode_update(update: &PriceUpdate) -> Vec<u8> {
+    let mut out = Vec::new();
+    length_delimited(&mut out, 1, update.exchange.as_bytes());
+    length_delimited(&mut out, 2, update.symbol.as_bytes());
+    length_delimited(&mut out, 3, update.price.to_string().as_bytes());
+    if let Some(open) = update.open_24h {
+        length_delimited(&mut out, 4, open.to_string().as_bytes());
+    }
+    if let Some(size) = update.size {
+        length_delimited(&mut out, 5, size.to_string().as_bytes());
+    }
+    write_varint(&mut out, 6 << 3);
+    write_varint(&mut out, epoch_ms(update.received_at));
+    out
+}
+
+// A string, bytes or embedded message field
+fn length_delimited(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(out, field << 3 | 2);
+    write_varint(out, bytes.len() as u64);
+    out.extend(bytes);
+}
+
+// 7 bits per byte, least significant first, high bit set on all but the last
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None  // More than 10 bytes
+}
+
+fn read_bytes<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = usize::try_from(read_varint(input)?).ok()?;
+    let bytes = input.get(..len)?;
+    *input = &input[len..];
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    fn tick(symbol: &str, price: &str) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: Some("0.5".parse().unwrap()),
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_000),
+        }
+    }
+
+    async fn send_frame(socket: &mut TcpStream, kind: u8, flags: u8, stream: u32, payload: &[u8]) {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend([kind, flags]);
+        frame.extend(stream.to_be_bytes());
+        frame.extend(payload);
+        socket.write_all(&frame).await.unwrap();
+    }
+
+    // Call a method: HEADERS, then the request message as DATA with END_STREAM
+    async fn start_call(socket: &mut TcpStream, stream: u32, method: &str, symbols: &[&str]) {
+        let path = format!("/crabby.v1.PriceTracker/{}", method);
+        let headers = hpack::encode(&[
+            (":method", "POST"),
+            (":scheme", "http"),
+            (":path", &path),
+            (":authority", "localhost"),
+            ("content-type", "application/grpc"),
+            ("te", "trailers"),
+        ]);
+        send_frame(socket, 0x1, 0x4, stream, &headers).await;
+        let mut request = Vec::new();
+        for symbol in symbols {
+            length_delimited(&mut request, 1, symbol.as_bytes());
+        }
+        send_frame(socket, 0x0, 0x1, stream, &frame(&request)).await;
+    }
+
+    // Frames until the next HEADERS or DATA frame, skipping SETTINGS and WINDOW_UPDATE
+    async fn next_frame(socket: &mut TcpStream, decoder: &mut hpack::Decoder) -> (u32, Result<hpack::Headers, Vec<u8>>, bool) {
+        loop {
+            let mut header = [0; 9];
+            socket.read_exact(&mut header).await.unwrap();
+            let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+            let stream = u32::from_be_bytes(header[5..].try_into().unwrap());
+            let mut payload = vec![0; len];
+            socket.read_exact(&mut payload).await.unwrap();
+            let end_stream = header[4] & 0x1 != 0;
+            match header[3] {
+                0x0 => return (stream, Err(payload), end_stream),
+                0x1 => return (stream, Ok(decoder.decode(&payload).unwrap()), end_stream),
+                _ => {}
+            }
+        }
+    }
+
+    // The symbols and prices in a sequence of length-prefixed PriceUpdate messages
+    fn prices(mut messages: &[u8]) -> Vec<(String, String)> {
+        let mut prices = Vec::new();
+        while !messages.is_empty() {
+            let mut update = read_bytes(&mut messages).unwrap();
+            let (mut symbol, mut price) = (String::new(), String::new());
+            while !update.is_empty() {
+                match read_varint(&mut update).unwrap() {
+                    0x12 => symbol = String::from_utf8(read_bytes(&mut update).unwrap().to_vec()).unwrap(),
+                    0x1a => price = String::from_utf8(read_bytes(&mut update).unwrap().to_vec()).unwrap(),
+                    0x30 => assert_eq!(read_varint(&mut update), Some(1_700_000_000_000)),
+                    key if key & 7 == 2 => drop(read_bytes(&mut update)),
+                    key => panic!("unexpected key {}", key),
+                }
+            }
+            prices.push((symbol, price));
+        }
+        prices
+    }
+
+    #[test]
+    fn snapshot_and_stream_over_http2() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let store = PriceStore::new();
+            store.seed(tick("BTC-USD", "65000.5")).await;
+            store.seed(tick("ETH-USD", "3200")).await;
+            let server = GrpcServer::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+            let addr = server.local_addr().unwrap();
+            tokio::spawn(server.run(store.clone()));
+
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            socket.write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n").await.unwrap();
+            send_frame(&mut socket, 0x4, 0, 0, &[]).await;
+            let mut decoder = hpack::Decoder::new();
+            let header = |headers: &hpack::Headers, name: &str| {
+                headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone())
+            };
+
+            // GetSnapshot for one symbol (lowercase is fine): headers, one message, trailers
+            start_call(&mut socket, 1, "GetSnapshot", &["eth-usd"]).await;
+            let (_, headers, _) = next_frame(&mut socket, &mut decoder).await;
+            assert_eq!(header(&headers.unwrap(), "content-type").as_deref(), Some("application/grpc"));
+            let (_, data, _) = next_frame(&mut socket, &mut decoder).await;
+            let data = data.unwrap_err();
+            let snapshot = unframe(&data).unwrap();
+            let mut fields = snapshot;
+            assert_eq!(read_varint(&mut fields), Some(0x0a));  // Snapshot.prices
+            assert_eq!(prices(&snapshot[1..]), [("ETH-USD".to_string(), "3200".to_string())]);
+            let (stream, trailers, end) = next_frame(&mut socket, &mut decoder).await;
+            assert_eq!((stream, header(&trailers.unwrap(), "grpc-status").as_deref(), end), (1, Some("0"), true));
+
+            // An unknown method is answered with trailers only
+            start_call(&mut socket, 3, "Nope", &[]).await;
+            let (_, trailers, end) = next_frame(&mut socket, &mut decoder).await;
+            assert_eq!((header(&trailers.unwrap(), "grpc-status").as_deref(), end), (Some("12"), true));
+
+            // StreamPrices: the known price first, then live updates for the symbol only
+            start_call(&mut socket, 5, "StreamPrices", &["BTC-USD"]).await;
+            next_frame(&mut socket, &mut decoder).await.1.unwrap();
+            let message = |data: Vec<u8>| {
+                let mut with_len = Vec::new();
+                write_varint(&mut with_len, (data.len() - MESSAGE_PREFIX) as u64);
+                with_len.extend(&data[MESSAGE_PREFIX..]);
+                prices(&with_len)
+            };
+            let first = next_frame(&mut socket, &mut decoder).await.1.unwrap_err();
+            assert_eq!(message(first), [("BTC-USD".to_string(), "65000.5".to_string())]);
+            store.update(tick("ETH-USD", "3300")).await;
+            store.update(tick("BTC-USD", "65100")).await;
+            let (stream, data, end) = next_frame(&mut socket, &mut decoder).await;
+            assert_eq!((stream, end), (5, false));
+            assert_eq!(message(data.unwrap_err()), [("BTC-USD".to_string(), "65100".to_string())]);
+
+            // Cancelling the stream leaves the connection usable
+            send_frame(&mut socket, 0x3, 0, 5, &8u32.to_be_bytes()).await;
+            start_call(&mut socket, 7, "GetSnapshot", &[]).await;
+            let (stream, _, _) = next_frame(&mut socket, &mut decoder).await;
+            assert_eq!(stream, 7);
+        });
+    }
+
+    #[test]
+    fn bad_requests() {
+        assert_eq!(unframe(&[1, 0, 0, 0, 0]).unwrap_err().0, UNIMPLEMENTED);
+        assert_eq!(unframe(&[0, 0, 0, 0, 2, 0x0a]).unwrap_err().0, INVALID_ARGUMENT);
+        assert_eq!(decode_symbols(&[0x0a, 5, b'a']).unwrap_err().0, INVALID_ARGUMENT);
+        // Unknown fields are skipped
+        let symbols = decode_symbols(&[0x10, 0x96, 0x01, 0x0a, 3, b'b', b't', b'c']).unwrap();
+        assert_eq!(symbols.into_iter().collect::<Vec<_>>(), ["BTC"]);
+        assert_eq!(percent_encode("100% ünknown"), "100%25 %C3%BCnknown");
+    }
+}
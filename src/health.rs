@@ -0,0 +1,219 @@
+// Feed health per symbol: how many messages arrive per second, and how long they take
+// to get here, measured as local receive time minus the timestamp the exchange put in
+// the message. A high latency on every exchange points at our network; a high latency
+// (or a low message rate) on one exchange only points at that exchange.
+//
+// Shown by the `stats` command at the prompt, served as GET /feeds by the REST API, and
+// exported as the crabby_feed_latency_seconds and crabby_symbol_messages_total metrics.
+//
+// Latencies are kept signed: a clock that runs behind the exchange's shows up as a
+// negative latency rather than being hidden.
+
+use std::{
+    collections::VecDeque,             // Samples, oldest first
+    fmt,                               // One-line summary for the prompt
+    time::{Duration, SystemTime},      // Receive times and the window
+};
+
+use serde::Serialize;
+
+use crate::store::{entry, epoch_ms, BySymbol};  // One series per (exchange, symbol)
+
+// Rates and latencies are over this much recent traffic
+pub const WINDOW: Duration = Duration::from_secs(60);
+
+// Cap on the samples kept per symbol, so a very busy feed stays cheap
+const MAX_SAMPLES: usize = 10_000;
+
+// One message: when it arrived, and its latency if the exchange timestamped it
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    received_at: SystemTime,
+    latency_ms: Option<i64>,
+}
+
+// Recent messages about one symbol on one exchange
+#[derive(Debug, Default, Clone)]
+pub struct SymbolHealth {
+    samples: VecDeque<Sample>,
+    total: u64,                      // Every message since we started
+    first: Option<SystemTime>,       // To know how much of the window we've seen
+}
+
+impl SymbolHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // One message received at `received_at`, sent by the exchange at `sent_at` (if known).
+    // Samples older than `WINDOW` (measured from this one) are dropped.
+    pub fn record(&mut self, received_at: SystemTime, sent_at: Option<SystemTime>) {
+        self.total += 1;
+        self.first.get_or_insert(received_at);
+        let latency_ms = sent_at.map(|sent| signed_ms(received_at, sent));
+        self.samples.push_back(Sample { received_at, latency_ms });
+        while self.samples.len() > MAX_SAMPLES
+            || self.samples.front().is_some_and(|s| received_at.duration_since(s.received_at).unwrap_or_default() > WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    // Rate and latency over the `WINDOW` before `now`
+    pub fn summary(&self, now: SystemTime) -> (f64, Option<Latency>, Option<SystemTime>) {
+        let recent: Vec<&Sample> = self
+            .samples
+            .iter()
+            .filter(|s| now.duration_since(s.received_at).unwrap_or_default() <= WINDOW)
+            .collect();
+
+        // Over the whole window, or since the first message if that's more recent
+        let seen = self.first.map_or(WINDOW, |first| now.duration_since(first).unwrap_or_default().min(WINDOW));
+        let per_second = if recent.is_empty() { 0.0 } else { recent.len() as f64 / seen.as_secs_f64().max(1.0) };
+
+        let mut latencies: Vec<i64> = recent.iter().filter_map(|s| s.latency_ms).collect();
+        latencies.sort_unstable();
+        (per_second, Latency::of(&latencies), self.samples.back().map(|s| s.received_at))
+    }
+}
+
+// Latency figures over the window, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Latency {
+    pub mean_ms: f64,
+    pub p50_ms: i64,
+    pub p95_ms: i64,
+    pub max_ms: i64,
+    pub samples: usize,
+}
+
+impl Latency {
+    // From sorted latencies; None when there are none
+    fn of(sorted: &[i64]) -> Option<Self> {
+        let max_ms = *sorted.last()?;
+        let percentile = |p: usize| sorted[(sorted.len() * p).div_ceil(100).saturating_sub(1)];
+        Some(Self {
+            mean_ms: sorted.iter().sum::<i64>() as f64 / sorted.len() as f64,
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            max_ms,
+            samples: sorted.len(),
+        })
+    }
+}
+
+// Health of one symbol on one exchange (an entry of GET /feeds)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeedStats {
+    pub exchange: &'static str,
+    pub symbol: String,
+    pub messages: u64,                // Since startup
+    pub per_second: f64,              // Over the last minute
+    pub latency: Option<Latency>,     // None when the messages carry no timestamp
+    pub last_message_ms: Option<u64>, // Local time of the last message
+}
+
+// "coinbase BTC-USD: 4.2 msg/s, latency 38ms (p95 120ms, max 310ms), 1520 messages"
+impl fmt::Display for FeedStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {:.1} msg/s", self.exchange, self.symbol, self.per_second)?;
+        match &self.latency {
+            Some(l) => write!(f, ", latency {}ms (p95 {}ms, max {}ms)", l.p50_ms, l.p95_ms, l.max_ms)?,
+            None => write!(f, ", no exchange timestamps")?,
+        }
+        write!(f, ", {} messages", self.messages)
+    }
+}
+
+// Every series, keyed by (exchange, our symbol). Recorded for nearly every frame, so it's
+// owned by the store's state thread like the prices, without a lock of its own; readers
+// ask the store (`PriceStore::feed_stats`).
+#[derive(Debug, Default)]
+pub struct FeedHealth {
+    series: BySymbol<SymbolHealth>,
+}
+
+impl FeedHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, exchange: &'static str, symbol: &str, received_at: SystemTime, sent_at: Option<SystemTime>) {
+        entry(&mut self.series, exchange, symbol).record(received_at, sent_at);
+    }
+
+    // Every symbol on every exchange, sorted by symbol then exchange
+    pub fn stats(&self, now: SystemTime) -> Vec<FeedStats> {
+        let mut stats: Vec<FeedStats> = self
+            .series
+            .iter()
+            .flat_map(|(symbol, exchanges)| exchanges.iter().map(move |(exchange, health)| (exchange, symbol, health)))
+            .map(|(exchange, symbol, health)| {
+                let (per_second, latency, last) = health.summary(now);
+                FeedStats {
+                    exchange,
+                    symbol: symbol.clone(),
+                    messages: health.total,
+                    per_second,
+                    latency,
+                    last_message_ms: last.map(epoch_ms),
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| (&a.symbol, a.exchange).cmp(&(&b.symbol, b.exchange)));
+        stats
+    }
+
+    // Forget one symbol on every exchange (it's no longer tracked)
+    pub fn remove_symbol(&mut self, symbol: &str) {
+        self.series.remove(symbol);
+    }
+}
+
+// `later - earlier` in milliseconds, negative if `later` is actually earlier
+fn signed_ms(later: SystemTime, earlier: SystemTime) -> i64 {
+    match later.duration_since(earlier) {
+        Ok(d) => d.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_000 + ms)
+    }
+
+    #[test]
+    fn message_rate_and_latency_percentiles() {
+        let mut health = FeedHealth::new();
+        // 20 messages over 10 seconds, 10..200ms late, plus one without a timestamp
+        for i in 0..20 {
+            health.record("coinbase", "BTC-USD", at(i * 500 + (i + 1) * 10), Some(at(i * 500)));
+        }
+        health.record("coinbase", "BTC-USD", at(10_000), None);
+        // A clock behind the exchange's: the message arrives "before" it was sent
+        health.record("kraken", "BTC-USD", at(1_000), Some(at(1_250)));
+
+        let stats = health.stats(at(10_000));
+        assert_eq!(stats.iter().map(|s| s.exchange).collect::<Vec<_>>(), ["coinbase", "kraken"]);
+        let coinbase = &stats[0];
+        assert_eq!(coinbase.messages, 21);
+        assert!((coinbase.per_second - 2.1).abs() < 0.01, "{}", coinbase.per_second);
+        let latency = coinbase.latency.unwrap();
+        assert_eq!((latency.p50_ms, latency.p95_ms, latency.max_ms, latency.samples), (100, 190, 200, 20));
+        assert_eq!(latency.mean_ms, 105.0);
+        assert_eq!(coinbase.last_message_ms, Some(1_700_000_010_000));
+        assert_eq!(coinbase.to_string(), "coinbase BTC-USD: 2.1 msg/s, latency 100ms (p95 190ms, max 200ms), 21 messages");
+        assert_eq!(stats[1].latency.unwrap().max_ms, -250);
+
+        // A minute of silence: the rate drops to zero, the total stays
+        let quiet = &health.stats(at(100_000))[0];
+        assert_eq!((quiet.per_second, quiet.latency, quiet.messages), (0.0, None, 21));
+
+        health.remove_symbol("BTC-USD");
+        assert!(health.stats(at(100_000)).is_empty());
+    }
+}
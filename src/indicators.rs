@@ -0,0 +1,306 @@
+// Technical indicators computed from the candle aggregator's closes: simple and
+// exponential moving averages, RSI and MACD. Written the way traders write them:
+//
+//   SMA(20)          average of the last 20 closes
+//   EMA(50)          exponential moving average, seeded with the SMA of the first 50
+//   RSI(14)          Wilder's relative strength index, 0..100
+//   MACD(12,26,9)    EMA(12) − EMA(26), with a 9-period EMA of that as the signal line
+//
+// `Indicators` computes them on one candle interval (e.g. 1m) and is shared by the
+// terminal output, the dashboard, the REST API and alert rules ("RSI(14) below 30").
+//
+//     [indicators]
+//     interval = "1m"
+//     list = ["RSI(14)", "EMA(50)", "MACD(12,26,9)"]   # shown for every symbol
+//
+//     [indicators.symbols]
+//     "BTC-USD" = ["RSI(14)", "EMA(200)"]               # replaces `list` for one symbol
+
+use std::{
+    collections::HashMap, // Per-symbol indicator lists
+    fmt,                  // "RSI(14)" names
+    str::FromStr,         // Parsing "RSI(14)"
+    time::Duration,       // Candle interval the indicators are computed on
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::candles::SharedCandles;
+use crate::config::deserialize_duration;
+
+// [indicators] section of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndicatorConfig {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,                        // Candles to compute on; one of the [candles] intervals
+    pub list: Vec<Indicator>,                      // Shown for every symbol; none unless set
+    pub symbols: HashMap<String, Vec<Indicator>>,  // Per-symbol lists, used instead of `list`
+}
+
+impl Default for IndicatorConfig {
+    fn default() -> Self {
+        Self { interval: Duration::from_secs(60), list: Vec::new(), symbols: HashMap::new() }
+    }
+}
+
+// One indicator and its parameters; written "RSI(14)" in config files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Indicator {
+    Sma(usize),
+    Ema(usize),
+    Rsi(usize),
+    Macd { fast: usize, slow: usize, signal: usize },
+}
+
+impl fmt::Display for Indicator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Indicator::Sma(n) => write!(f, "SMA({})", n),
+            Indicator::Ema(n) => write!(f, "EMA({})", n),
+            Indicator::Rsi(n) => write!(f, "RSI({})", n),
+            Indicator::Macd { fast, slow, signal } => write!(f, "MACD({},{},{})", fast, slow, signal),
+        }
+    }
+}
+
+impl FromStr for Indicator {
+    type Err = String;
+
+    // "RSI(14)", "ema(50)", "MACD(12,26,9)"; "RSI" and "MACD" alone use the usual periods
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim().to_uppercase().replace(' ', "");
+        let (name, args) = match text.split_once('(') {
+            Some((name, rest)) => {
+                let args = rest.strip_suffix(')').ok_or_else(|| format!("\"{}\": missing closing parenthesis", s))?;
+                (name.to_string(), args.split(',').map(str::to_string).collect::<Vec<_>>())
+            }
+            None => (text.clone(), Vec::new()),
+        };
+        let periods = args
+            .iter()
+            .map(|a| match a.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => Err(format!("\"{}\": periods must be whole numbers above 0", s)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let indicator = match (name.as_str(), periods.as_slice()) {
+            ("SMA", [n]) => Indicator::Sma(*n),
+            ("EMA", [n]) => Indicator::Ema(*n),
+            ("RSI", []) => Indicator::Rsi(14),
+            ("RSI", [n]) => Indicator::Rsi(*n),
+            ("MACD", []) => Indicator::Macd { fast: 12, slow: 26, signal: 9 },
+            ("MACD", [fast, slow, signal]) if fast < slow => Indicator::Macd { fast: *fast, slow: *slow, signal: *signal },
+            ("MACD", [_, _, _]) => return Err(format!("\"{}\": the fast period must be shorter than the slow one", s)),
+            _ => {
+                return Err(format!(
+                    "unknown indicator \"{}\" (expected e.g. SMA(20), EMA(50), RSI(14), MACD(12,26,9))",
+                    s
+                ))
+            }
+        };
+        Ok(indicator)
+    }
+}
+
+// A comma-separated list like "RSI(14), MACD(12,26,9)" (commas inside parentheses
+// belong to the indicator)
+pub fn parse_list(text: &str) -> Result<Vec<Indicator>, String> {
+    let (mut items, mut start, mut depth) = (Vec::new(), 0, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&text[start..]);
+    items.into_iter().filter(|item| !item.trim().is_empty()).map(str::parse).collect()
+}
+
+impl TryFrom<String> for Indicator {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// The value of an indicator right now
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Reading {
+    pub value: Decimal,           // The indicator itself (for MACD, the MACD line)
+    pub signal: Option<Decimal>,  // MACD's signal line
+}
+
+impl Indicator {
+    // Compute from closing prices, oldest first. None until there are enough of them.
+    pub fn compute(&self, closes: &[Decimal]) -> Option<Reading> {
+        let value = match *self {
+            Indicator::Sma(n) => sma(closes, n)?,
+            Indicator::Ema(n) => *ema_series(closes, n).last()?,
+            Indicator::Rsi(n) => rsi(closes, n)?,
+            Indicator::Macd { fast, slow, signal } => {
+                let (fast, slow) = (ema_series(closes, fast), ema_series(closes, slow));
+                // Line up the two series at the end; the slow one is shorter
+                let line: Vec<Decimal> = fast[fast.len() - slow.len()..].iter().zip(&slow).map(|(f, s)| f - s).collect();
+                let signal = *ema_series(&line, signal).last()?;
+                return Some(Reading { value: line.last()?.round_dp(4), signal: Some(signal.round_dp(4)) });
+            }
+        };
+        Some(Reading { value: value.round_dp(4), signal: None })
+    }
+}
+
+fn sma(closes: &[Decimal], n: usize) -> Option<Decimal> {
+    if closes.len() < n {
+        return None;
+    }
+    Some(closes[closes.len() - n..].iter().sum::<Decimal>() / Decimal::from(n))
+}
+
+// EMA after each close from the n-th on (empty with fewer than n closes)
+fn ema_series(closes: &[Decimal], n: usize) -> Vec<Decimal> {
+    let Some(seed) = closes.get(..n).and_then(|first| sma(first, n)) else { return Vec::new() };
+    let k = Decimal::TWO / Decimal::from(n + 1);
+    let mut series = vec![seed];
+    for close in &closes[n..] {
+        let prev = *series.last().unwrap_or(&seed);
+        series.push((close - prev) * k + prev);
+    }
+    series
+}
+
+// Wilder's RSI: average gain vs average loss over n changes, smoothed
+fn rsi(closes: &[Decimal], n: usize) -> Option<Decimal> {
+    if closes.len() <= n {
+        return None;
+    }
+    let changes: Vec<Decimal> = closes.windows(2).map(|w| w[1] - w[0]).collect();
+    let gain = |c: &Decimal| (*c).max(Decimal::ZERO);
+    let loss = |c: &Decimal| (-c).max(Decimal::ZERO);
+    let periods = Decimal::from(n);
+    let mut avg_gain = changes[..n].iter().map(gain).sum::<Decimal>() / periods;
+    let mut avg_loss = changes[..n].iter().map(loss).sum::<Decimal>() / periods;
+    for c in &changes[n..] {
+        avg_gain = (avg_gain * (periods - Decimal::ONE) + gain(c)) / periods;
+        avg_loss = (avg_loss * (periods - Decimal::ONE) + loss(c)) / periods;
+    }
+    if avg_loss.is_zero() {
+        return Some(Decimal::ONE_HUNDRED);  // Only gains (or no movement at all)
+    }
+    let rs = avg_gain / avg_loss;
+    Some(Decimal::ONE_HUNDRED - Decimal::ONE_HUNDRED / (Decimal::ONE + rs))
+}
+
+// One indicator's reading, for output
+#[derive(Debug, Clone, Serialize)]
+pub struct NamedReading {
+    pub name: String,  // e.g. "RSI(14)"
+    #[serde(flatten)]
+    pub reading: Reading,
+}
+
+impl fmt::Display for NamedReading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.reading.value)?;
+        if let Some(signal) = self.reading.signal {
+            write!(f, " (signal {})", signal)?;
+        }
+        Ok(())
+    }
+}
+
+// The configured indicators, computed on one candle interval. Cheap to clone: every
+// clone reads the same candles.
+#[derive(Clone)]
+pub struct Indicators {
+    candles: SharedCandles,
+    interval: Duration,
+    list: Vec<Indicator>,
+    symbols: HashMap<String, Vec<Indicator>>,
+}
+
+impl Indicators {
+    pub fn new(candles: SharedCandles, interval: Duration, list: Vec<Indicator>) -> Self {
+        Self { candles, interval, list, symbols: HashMap::new() }
+    }
+
+    // Indicators for the [indicators] settings, reading from `candles`
+    pub fn from_config(candles: SharedCandles, config: &IndicatorConfig) -> Self {
+        Self { candles, interval: config.interval, list: config.list.clone(), symbols: config.symbols.clone() }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    // What to show for `symbol`: its own list if it has one, else the common one
+    pub fn for_symbol(&self, symbol: &str) -> &[Indicator] {
+        self.symbols.get(symbol).unwrap_or(&self.list)
+    }
+
+    // Is anything configured to be shown at all?
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty() && self.symbols.values().all(Vec::is_empty)
+    }
+
+    // One indicator for one symbol on one exchange. The candle still in progress counts
+    // too, so the value follows the live price.
+    pub fn reading(&self, indicator: &Indicator, exchange: &str, symbol: &str) -> Option<Reading> {
+        let candles = self.candles.lock().unwrap().candles(exchange, symbol, self.interval);
+        let closes: Vec<Decimal> = candles.iter().map(|c| c.close).collect();
+        indicator.compute(&closes)
+    }
+
+    // Every indicator configured for `symbol` that has enough history yet
+    pub fn readings(&self, exchange: &str, symbol: &str) -> Vec<NamedReading> {
+        let candles = self.candles.lock().unwrap().candles(exchange, symbol, self.interval);
+        let closes: Vec<Decimal> = candles.iter().map(|c| c.close).collect();
+        self.for_symbol(symbol)
+            .iter()
+            .filter_map(|i| i.compute(&closes).map(|reading| NamedReading { name: i.to_string(), reading }))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closes(values: &[i64]) -> Vec<Decimal> {
+        values.iter().map(|&v| Decimal::from(v)).collect()
+    }
+
+    #[test]
+    fn parses_names_and_computes_values() {
+        assert_eq!("rsi(14)".parse(), Ok(Indicator::Rsi(14)));
+        assert_eq!("MACD".parse(), Ok(Indicator::Macd { fast: 12, slow: 26, signal: 9 }));
+        assert_eq!(Indicator::Ema(50).to_string(), "EMA(50)");
+        assert!("SMA(0)".parse::<Indicator>().is_err());
+        assert!("VWAP(10)".parse::<Indicator>().is_err());
+        assert_eq!(parse_list("RSI(14), MACD(12,26,9)").unwrap().len(), 2);
+
+        let prices = closes(&[10, 11, 12, 13, 14]);
+        assert_eq!(Indicator::Sma(3).compute(&prices).unwrap().value, Decimal::from(13));
+        assert_eq!(Indicator::Sma(6).compute(&prices), None);
+        // EMA(3): seed 11, then k = 0.5: 12, 13
+        assert_eq!(Indicator::Ema(3).compute(&prices).unwrap().value, Decimal::from(13));
+
+        // Only gains: RSI 100; equal gains and losses: RSI 50
+        assert_eq!(Indicator::Rsi(4).compute(&prices).unwrap().value, Decimal::ONE_HUNDRED);
+        assert_eq!(Indicator::Rsi(4).compute(&closes(&[10, 12, 10, 12, 10])).unwrap().value, Decimal::from(50));
+
+        // A steady climb keeps the fast EMA above the slow one
+        let climb: Vec<Decimal> = (1..=40).map(Decimal::from).collect();
+        let macd = Indicator::Macd { fast: 3, slow: 6, signal: 4 }.compute(&climb).unwrap();
+        assert!(macd.value > Decimal::ZERO && macd.signal.is_some());
+    }
+}
@@ -0,0 +1,81 @@
+// Avro encoding of an update, for pipelines that want a schema. Messages are plain
+// Avro binary written with SCHEMA (register it with a schema registry and set
+// `[kafka] schema_id` to get the Confluent wire format, or hand it to consumers).
+// Prices stay decimal strings, as in the JSON, so nothing is lost to floating point.
+
+use super::protocol::varint;  // Avro longs and lengths are the same zigzag varints
+use crate::store::{epoch_ms, PriceUpdate};
+
+pub const SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "Ticker",
+  "namespace": "crabbycryptotracker",
+  "fields": [
+    {"name": "exchange", "type": "string"},
+    {"name": "symbol", "type": "string"},
+    {"name": "price", "type": "string"},
+    {"name": "open_24h", "type": ["null", "string"], "default": null},
+    {"name": "size", "type": ["null", "string"], "default": null},
+    {"name": "timestamp_ms", "type": {"type": "long", "logicalType": "timestamp-millis"}}
+  ]
+}"#;
+
+// One update as an Avro record, optionally behind the Confluent header
+// (a zero byte and the big-endian schema id)
+pub fn encode(update: &PriceUpdate, schema_id: Option<u32>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(64);
+    if let Some(id) = schema_id {
+        out.push(0);
+        out.extend_from_slice(&id.to_be_bytes());
+    }
+    string(&mut out, update.exchange);
+    string(&mut out, &update.symbol);
+    string(&mut out, &update.price.to_string());
+    for value in [update.open_24h, update.size] {
+        // Unions are the branch index, then the value: 0 is null, 1 is string
+        match value {
+            Some(v) => {
+                varint(&mut out, 1);
+                string(&mut out, &v.to_string());
+            }
+            None => varint(&mut out, 0),
+        }
+    }
+    varint(&mut out, epoch_ms(update.received_at) as i64);
+    out
+}
+
+fn string(out: &mut Vec<u8>, value: &str) {
+    varint(out, value.len() as i64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn encodes_updates_with_the_schema_field_order() {
+        let update = PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: "65000.01".parse().unwrap(),
+            open_24h: None,
+            size: Some("0.5".parse().unwrap()),
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+        };
+        let expected = [
+            &[0x10][..], b"coinbase",
+            &[0x0e], b"BTC-USD",
+            &[0x10], b"65000.01",
+            &[0x00],                                  // open_24h: null
+            &[0x02, 0x06], b"0.5",                    // size: string "0.5"
+            &[0xf6, 0xa1, 0xab, 0xfe, 0xf9, 0x62],   // timestamp_ms
+        ]
+        .concat();
+        assert_eq!(encode(&update, None), expected);
+        assert_eq!(encode(&update, Some(42))[..5], [0, 0, 0, 0, 42]);
+        assert!(serde_json::from_str::<serde_json::Value>(SCHEMA).is_ok());
+    }
+}
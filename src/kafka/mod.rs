@@ -0,0 +1,414 @@
+// Kafka output: every normalized update produced to a topic, keyed by symbol (so each
+// symbol's updates stay in order on one partition), as JSON or Avro. That makes the
+// tracker the ingestion edge of a bigger streaming pipeline: Flink, Spark, ksqlDB, a
+// Connect sink into a warehouse...
+//
+//     [kafka]
+//     brokers = ["localhost:9092"]
+//     topic = "crypto-tickers"
+//     format = "avro"     # or "json"
+//
+// The producer speaks the Kafka protocol itself (see protocol.rs) and keeps to what the
+// tracker needs: no compression, transactions or idempotence. Updates are collected for
+// `linger`, spread over partitions the way the Java client does it (murmur2 of the key),
+// and each partition's batch goes to its leader. A failed batch is retried with fresh
+// metadata and backoff, then dropped; delivery is at least once, so a retried batch can
+// repeat updates one of the leaders already took. As with the other sinks, a full queue
+// drops updates instead of holding up the feeds.
+
+use std::{
+    collections::{btree_map::Entry, BTreeMap},  // Partitions grouped by leader; connections by node id
+    io,
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::{broadcast::error::RecvError, mpsc},
+    task::JoinHandle,
+    time::{sleep, timeout, timeout_at, Instant},
+};
+use tracing::{error, info, warn};
+
+use crate::backoff::Backoff;
+use crate::config::{KafkaConfig, KafkaFormat};
+use crate::store::{epoch_ms, PriceStore, PriceUpdate};
+
+pub mod avro;
+mod protocol;
+
+pub use protocol::Record;
+
+// Updates waiting for the brokers before new ones are dropped
+const QUEUE_CAPACITY: usize = 8192;
+
+// Per request, connecting included; also how long the broker may take to replicate
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Extra attempts per batch
+const RETRIES: u32 = 3;
+
+// Biggest response we're willing to read (metadata for a large cluster fits easily)
+const MAX_RESPONSE: usize = 16 << 20;
+
+// Messages understood by the producer task
+enum Command {
+    Update(PriceUpdate),
+    Close,  // Send what's queued and stop
+}
+
+// Why a batch didn't make it
+enum SendError {
+    Retry(String),  // Connection trouble, a leader moved, not enough replicas: worth another go
+    Fatal(String),  // The brokers won't ever take it (authorization, message too large...)
+}
+
+// Handle to the producer task
+pub struct KafkaSink {
+    queue: mpsc::Sender<Command>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl KafkaSink {
+    // A sink for the [kafka] settings, or None when no brokers are set.
+    // Must be called from inside a Tokio runtime.
+    pub fn from_config(config: &KafkaConfig) -> Result<Option<Self>, String> {
+        if config.brokers.is_empty() {
+            return Ok(None);
+        }
+        let mut brokers = Vec::new();
+        for broker in &config.brokers {
+            let (host, port) = broker.rsplit_once(':').ok_or_else(|| format!("\"{}\" should look like host:9092", broker))?;
+            let port = port.parse().map_err(|_| format!("\"{}\" has no valid port", broker))?;
+            brokers.push((host.to_string(), port));
+        }
+        let producer = Producer { config: config.clone(), brokers, metadata: None, connections: BTreeMap::new(), correlation_id: 0 };
+        let (queue, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let task = tokio::spawn(run(producer, rx));
+        Ok(Some(Self { queue, task: Some(task) }))
+    }
+
+    // Queue one update. Never blocks: with the queue full, it's dropped.
+    pub fn record(&self, update: PriceUpdate) {
+        if self.queue.try_send(Command::Update(update)).is_err() {
+            warn!("Kafka queue full, dropped a price update");
+        }
+    }
+
+    // Spawn a task that produces every update from `store`
+    pub fn attach(&self, store: &PriceStore) -> JoinHandle<()> {
+        let queue = self.queue.clone();
+        let mut rx = store.subscribe_updates();
+        tokio::spawn(async move {
+            let mut dropped = 0u64;
+            loop {
+                match rx.recv().await {
+                    Ok(update) => match queue.try_send(Command::Update(update)) {
+                        Ok(()) if dropped > 0 => {
+                            warn!(dropped, "Kafka caught up again, some updates were dropped");
+                            dropped = 0;
+                        }
+                        Ok(()) => {}
+                        Err(mpsc::error::TrySendError::Full(_)) => dropped += 1,
+                        Err(mpsc::error::TrySendError::Closed(_)) => break,  // Sink was closed
+                    },
+                    Err(RecvError::Lagged(n)) => dropped += n,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    // Send what's queued and wait for the task (at most IO_TIMEOUT)
+    pub async fn close(mut self) {
+        let _ = timeout(IO_TIMEOUT, self.queue.send(Command::Close)).await;
+        if let Some(task) = self.task.take()
+            && timeout(IO_TIMEOUT, task).await.is_err()
+        {
+            warn!("Kafka didn't take the last updates in time");
+        }
+    }
+}
+
+// One update as a message: the symbol as key, JSON or Avro as value
+pub fn message(config: &KafkaConfig, update: &PriceUpdate) -> Record {
+    let value = match config.format {
+        KafkaFormat::Json => serde_json::to_vec(update).unwrap_or_default(),
+        KafkaFormat::Avro => avro::encode(update, config.schema_id),
+    };
+    Record { key: update.symbol.clone(), value, timestamp_ms: epoch_ms(update.received_at) as i64 }
+}
+
+// The producer task: collect a batch, send it, repeat until Close
+async fn run(mut producer: Producer, mut rx: mpsc::Receiver<Command>) {
+    let linger = producer.config.linger;
+    let batch_size = producer.config.batch_size;
+    loop {
+        // Wait for one update, then give the rest of the batch `linger` to arrive
+        let mut records = Vec::new();
+        let mut closing = false;
+        match rx.recv().await {
+            Some(Command::Update(update)) => records.push(message(&producer.config, &update)),
+            Some(Command::Close) | None => closing = true,
+        }
+        let deadline = Instant::now() + linger;
+        while !closing && records.len() < batch_size {
+            match timeout_at(deadline, rx.recv()).await {
+                Ok(Some(Command::Update(update))) => records.push(message(&producer.config, &update)),
+                Ok(Some(Command::Close) | None) => closing = true,
+                Err(_) => break,
+            }
+        }
+
+        if !records.is_empty() {
+            send_with_retries(&mut producer, &records).await;
+        }
+        if closing {
+            break;
+        }
+    }
+    info!("Kafka producer stopped");
+}
+
+// Send one batch, retrying what's worth retrying; a batch that still fails is dropped
+async fn send_with_retries(producer: &mut Producer, records: &[Record]) {
+    let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(10));
+    let mut attempt = 0;
+    loop {
+        let e = match producer.send(records).await {
+            Ok(()) => return,
+            Err(SendError::Retry(e)) if attempt < RETRIES => e,
+            Err(SendError::Retry(e) | SendError::Fatal(e)) => {
+                error!(topic = %producer.config.topic, records = records.len(), error = %e, "Kafka produce failed, batch dropped");
+                return;
+            }
+        };
+        // Connections and leaders may both be stale now; start over from the bootstrap brokers
+        producer.reset();
+        let delay = backoff.next_delay();
+        warn!(topic = %producer.config.topic, attempt = attempt + 1, error = %e, ?delay, "Kafka produce failed, retrying");
+        sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+// Connections to the brokers and what we know about the topic
+struct Producer {
+    config: KafkaConfig,
+    brokers: Vec<(String, u16)>,              // Bootstrap brokers
+    metadata: Option<protocol::Metadata>,     // Fetched on first use and after failures
+    connections: BTreeMap<i32, TcpStream>,    // By broker node id
+    correlation_id: i32,
+}
+
+impl Producer {
+    fn reset(&mut self) {
+        self.metadata = None;
+        self.connections.clear();
+    }
+
+    // Partition the records, then send each leader its partitions' batches
+    async fn send(&mut self, records: &[Record]) -> Result<(), SendError> {
+        if self.metadata.is_none() {
+            self.metadata = Some(self.fetch_metadata().await.map_err(SendError::Retry)?);
+        }
+        let metadata = self.metadata.as_ref().expect("fetched above");
+
+        let mut by_leader: BTreeMap<i32, BTreeMap<i32, Vec<&Record>>> = BTreeMap::new();
+        for record in records {
+            let (partition, leader) = metadata.partitions[protocol::partition_for(record.key.as_bytes(), metadata.partitions.len())];
+            by_leader.entry(leader).or_default().entry(partition).or_default().push(record);
+        }
+        let requests: Vec<(i32, (String, u16), Vec<u8>)> = by_leader
+            .into_iter()
+            .map(|(leader, partitions)| {
+                let batches: Vec<(i32, Vec<u8>)> = partitions.into_iter().map(|(p, records)| (p, protocol::record_batch(&records))).collect();
+                let body = protocol::produce_request(self.config.acks, IO_TIMEOUT.as_millis() as i32, &self.config.topic, &batches);
+                (leader, metadata.brokers[&leader].clone(), body)
+            })
+            .collect();
+
+        for (leader, (host, port), body) in requests {
+            let stream = match self.connections.entry(leader) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(connect(&host, port).await.map_err(|e| SendError::Retry(format!("{}:{}: {}", host, port, e)))?),
+            };
+            self.correlation_id = self.correlation_id.wrapping_add(1);
+            let request = protocol::request(protocol::PRODUCE, protocol::PRODUCE_VERSION, self.correlation_id, &self.config.client_id, &body);
+            // With acks = 0 the broker doesn't answer at all
+            let expect_reply = self.config.acks != 0;
+            let response = call(stream, &request, self.correlation_id, expect_reply)
+                .await
+                .map_err(|e| SendError::Retry(format!("{}:{}: {}", host, port, e)))?;
+            if let Some(response) = response {
+                let failed = protocol::parse_produce(&response).map_err(SendError::Retry)?;
+                if let Some(&(partition, code)) = failed.first() {
+                    let message = format!("partition {}: {}", partition, protocol::error_name(code));
+                    return Err(if protocol::retryable(code) { SendError::Retry(message) } else { SendError::Fatal(message) });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Ask the bootstrap brokers, first one that answers, where the partitions live
+    async fn fetch_metadata(&mut self) -> Result<protocol::Metadata, String> {
+        let body = protocol::metadata_request(&self.config.topic);
+        let mut last_error = String::new();
+        for (host, port) in &self.brokers {
+            self.correlation_id = self.correlation_id.wrapping_add(1);
+            let request = protocol::request(protocol::METADATA, protocol::METADATA_VERSION, self.correlation_id, &self.config.client_id, &body);
+            let result = match connect(host, *port).await {
+                Ok(mut stream) => call(&mut stream, &request, self.correlation_id, true).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(Some(response)) => return protocol::parse_metadata(&response, &self.config.topic),
+                Ok(None) => unreachable!("metadata always has a reply"),
+                Err(e) => last_error = format!("{}:{}: {}", host, port, e),
+            }
+        }
+        Err(last_error)
+    }
+}
+
+async fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let stream = timeout(IO_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+// Send one request and (if there is one) read its response body
+async fn call(stream: &mut TcpStream, request: &[u8], correlation_id: i32, expect_reply: bool) -> io::Result<Option<Vec<u8>>> {
+    let exchange = async {
+        stream.write_all(request).await?;
+        if !expect_reply {
+            return Ok(None);
+        }
+        let len = stream.read_i32().await? as usize;
+        if !(4..=MAX_RESPONSE).contains(&len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bad response length {}", len)));
+        }
+        let mut response = vec![0; len];
+        stream.read_exact(&mut response).await?;
+        if response[..4] != correlation_id.to_be_bytes() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "response to a different request"));
+        }
+        response.drain(..4);
+        Ok(Some(response))
+    };
+    timeout(IO_TIMEOUT * 2, exchange).await.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no response from the broker"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // Metadata v4 response: one broker (node 1) leading both partitions of `topic`
+    fn metadata_response(topic: &str, port: u16) -> Vec<u8> {
+        let mut out = 0i32.to_be_bytes().to_vec();  // throttle
+        out.extend_from_slice(&1i32.to_be_bytes());
+        out.extend_from_slice(&1i32.to_be_bytes());
+        out.extend_from_slice(&9i16.to_be_bytes());
+        out.extend_from_slice(b"127.0.0.1");
+        out.extend_from_slice(&(port as i32).to_be_bytes());
+        out.extend_from_slice(&(-1i16).to_be_bytes());  // rack
+        out.extend_from_slice(&(-1i16).to_be_bytes());  // cluster_id
+        out.extend_from_slice(&1i32.to_be_bytes());     // controller
+        out.extend_from_slice(&1i32.to_be_bytes());
+        out.extend_from_slice(&0i16.to_be_bytes());
+        out.extend_from_slice(&(topic.len() as i16).to_be_bytes());
+        out.extend_from_slice(topic.as_bytes());
+        out.push(0);
+        out.extend_from_slice(&2i32.to_be_bytes());
+        for partition in 0..2i32 {
+            out.extend_from_slice(&0i16.to_be_bytes());
+            out.extend_from_slice(&partition.to_be_bytes());
+            out.extend_from_slice(&1i32.to_be_bytes());   // leader
+            out.extend_from_slice(&0i32.to_be_bytes());   // replicas
+            out.extend_from_slice(&0i32.to_be_bytes());   // isr
+        }
+        out
+    }
+
+    // Produce v3 response: every partition accepted
+    fn produce_response(topic: &str, partitions: i32) -> Vec<u8> {
+        let mut out = 1i32.to_be_bytes().to_vec();
+        out.extend_from_slice(&(topic.len() as i16).to_be_bytes());
+        out.extend_from_slice(topic.as_bytes());
+        out.extend_from_slice(&partitions.to_be_bytes());
+        for partition in 0..partitions {
+            out.extend_from_slice(&partition.to_be_bytes());
+            out.extend_from_slice(&0i16.to_be_bytes());
+            out.extend_from_slice(&0i64.to_be_bytes());
+            out.extend_from_slice(&(-1i64).to_be_bytes());
+        }
+        out.extend_from_slice(&0i32.to_be_bytes());
+        out
+    }
+
+    #[test]
+    fn finds_the_leader_and_produces_keyed_batches() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            // A broker that answers Metadata and Produce and hands over every produce request
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let (produced, mut produced_rx) = mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                loop {
+                    let (mut client, _) = listener.accept().await.unwrap();
+                    let produced = produced.clone();
+                    tokio::spawn(async move {
+                        while let Ok(len) = client.read_i32().await {
+                            let mut request = vec![0; len as usize];
+                            client.read_exact(&mut request).await.unwrap();
+                            let api_key = i16::from_be_bytes([request[0], request[1]]);
+                            let client_id_len = i16::from_be_bytes([request[8], request[9]]) as usize;
+                            let body = request[10 + client_id_len..].to_vec();
+                            let reply = if api_key == protocol::METADATA {
+                                metadata_response("crypto-tickers", port)
+                            } else {
+                                let partitions = i32::from_be_bytes(body[28..32].try_into().unwrap());
+                                produced.send(body).unwrap();
+                                produce_response("crypto-tickers", partitions)
+                            };
+                            let mut frame = ((reply.len() + 4) as i32).to_be_bytes().to_vec();
+                            frame.extend_from_slice(&request[4..8]);  // correlation id
+                            frame.extend_from_slice(&reply);
+                            client.write_all(&frame).await.unwrap();
+                        }
+                    });
+                }
+            });
+
+            let config = KafkaConfig { brokers: vec![format!("127.0.0.1:{}", port)], format: KafkaFormat::Avro, ..KafkaConfig::default() };
+            let sink = KafkaSink::from_config(&config).unwrap().unwrap();
+            let update = |symbol: &str| PriceUpdate {
+                exchange: "coinbase",
+                symbol: symbol.to_string(),
+                price: "1".parse().unwrap(),
+                open_24h: None,
+                size: None,
+                received_at: std::time::SystemTime::UNIX_EPOCH,
+            };
+            for symbol in ["BTC-USD", "ETH-USD", "BTC-USD"] {
+                sink.record(update(symbol));
+            }
+            sink.close().await;
+
+            // One request for the whole batch, each record with its symbol as key and Avro as value
+            let body = produced_rx.recv().await.unwrap();
+            assert!(produced_rx.try_recv().is_err());
+            for symbol in ["BTC-USD", "ETH-USD"] {
+                let value = avro::encode(&update(symbol), None);
+                let record = [&[symbol.len() as u8 * 2][..], symbol.as_bytes(), &[value.len() as u8 * 2], &value].concat();
+                assert!(body.windows(record.len()).any(|w| w == record), "no record for {}", symbol);
+            }
+        });
+    }
+}
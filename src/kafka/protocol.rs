@@ -0,0 +1,539 @@
+// The little of the Kafka wire protocol a producer needs: Metadata (v4) to find each
+// partition's leader, and Produce (v3) carrying v2 record batches. Brokers from 0.11
+// up to 4.x all speak both versions.
+//
+// Every request and response is a big-endian 4-byte length followed by that many bytes:
+//
+//     request:  api_key i16, api_version i16, correlation_id i32, client_id string, body
+//     response: correlation_id i32, body
+//
+// Strings are an i16 length plus UTF-8 (-1 for null), arrays an i32 count plus elements.
+
+use std::collections::HashMap;
+
+pub const PRODUCE: i16 = 0;
+pub const METADATA: i16 = 3;
+pub const PRODUCE_VERSION: i16 = 3;
+pub const METADATA_VERSION: i16 = 4;
+
+// One message for the topic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub key: String,        // The symbol; picks the partition
+    pub value: Vec<u8>,     // JSON or Avro
+    pub timestamp_ms: i64,  // When the update arrived
+}
+
+// Where the topic's partitions live
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub brokers: HashMap<i32, (String, u16)>,  // Node id → host and port
+    pub partitions: Vec<(i32, i32)>,           // (partition, leader node id), by partition
+}
+
+// A whole request, length prefix included
+pub fn request(api_key: i16, api_version: i16, correlation_id: i32, client_id: &str, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![0; 4];  // Length, filled in below
+    out.extend_from_slice(&api_key.to_be_bytes());
+    out.extend_from_slice(&api_version.to_be_bytes());
+    out.extend_from_slice(&correlation_id.to_be_bytes());
+    string(&mut out, client_id);
+    out.extend_from_slice(body);
+    let len = (out.len() - 4) as i32;
+    out[..4].copy_from_slice(&len.to_be_bytes());
+    out
+}
+
+// Metadata for one topic, creating it if the brokers allow that
+pub fn metadata_request(topic: &str) -> Vec<u8> {
+    let mut out = 1i32.to_be_bytes().to_vec();
+    string(&mut out, topic);
+    out.push(1);  // allow_auto_topic_creation
+    out
+}
+
+pub fn parse_metadata(body: &[u8], topic: &str) -> Result<Metadata, String> {
+    let mut r = Reader(body);
+    r.i32()?;  // throttle_time_ms
+    let mut brokers = HashMap::new();
+    for _ in 0..r.count()? {
+        let node = r.i32()?;
+        let host = r.string()?;
+        let port = r.i32()?;
+        r.string()?;  // rack
+        brokers.insert(node, (host, port as u16));
+    }
+    r.string()?;  // cluster_id
+    r.i32()?;     // controller_id
+    for _ in 0..r.count()? {
+        let error = r.i16()?;
+        let name = r.string()?;
+        r.i8()?;  // is_internal
+        let mut partitions = Vec::new();
+        for _ in 0..r.count()? {
+            let partition_error = r.i16()?;
+            let partition = r.i32()?;
+            let leader = r.i32()?;
+            r.skip_i32_array()?;  // replicas
+            r.skip_i32_array()?;  // in-sync replicas
+            if name == topic {
+                // Right after the topic is created (or during an election) a partition has no leader yet
+                if partition_error != 0 || !brokers.contains_key(&leader) {
+                    return Err(format!("partition {} has no leader ({})", partition, error_name(partition_error)));
+                }
+                partitions.push((partition, leader));
+            }
+        }
+        if name == topic {
+            if error != 0 {
+                return Err(format!("topic \"{}\": {}", topic, error_name(error)));
+            }
+            if partitions.is_empty() {
+                return Err(format!("topic \"{}\" has no partitions", topic));
+            }
+            partitions.sort();
+            return Ok(Metadata { brokers, partitions });
+        }
+    }
+    Err(format!("no metadata for topic \"{}\"", topic))
+}
+
+// One Produce request for a single topic: (partition, record batch) pairs
+pub fn produce_request(acks: i16, timeout_ms: i32, topic: &str, batches: &[(i32, Vec<u8>)]) -> Vec<u8> {
+    let mut out = (-1i16).to_be_bytes().to_vec();  // transactional_id: none
+    out.extend_from_slice(&acks.to_be_bytes());
+    out.extend_from_slice(&timeout_ms.to_be_bytes());
+    out.extend_from_slice(&1i32.to_be_bytes());
+    string(&mut out, topic);
+    out.extend_from_slice(&(batches.len() as i32).to_be_bytes());
+    for (partition, batch) in batches {
+        out.extend_from_slice(&partition.to_be_bytes());
+        out.extend_from_slice(&(batch.len() as i32).to_be_bytes());
+        out.extend_from_slice(batch);
+    }
+    out
+}
+
+// The partitions the broker refused, with its error codes
+pub fn parse_produce(body: &[u8]) -> Result<Vec<(i32, i16)>, String> {
+    let mut r = Reader(body);
+    let mut failed = Vec::new();
+    for _ in 0..r.count()? {
+        r.string()?;  // topic
+        for _ in 0..r.count()? {
+            let partition = r.i32()?;
+            let error = r.i16()?;
+            r.i64()?;  // base_offset
+            r.i64()?;  // log_append_time_ms
+            if error != 0 {
+                failed.push((partition, error));
+            }
+        }
+    }
+    Ok(failed)
+}
+
+// A v2 ("magic 2") record batch: uncompressed, no producer id, no transactions.
+//
+//     base_offset i64, batch_length i32, partition_leader_epoch i32, magic i8, crc u32,
+//     attributes i16, last_offset_delta i32, base_timestamp i64, max_timestamp i64,
+//     producer_id i64, producer_epoch i16, base_sequence i32, records [Record]
+//
+// The CRC (CRC-32C) covers everything from `attributes` on. Inside a record, lengths
+// and deltas are zigzag varints.
+pub fn record_batch(records: &[&Record]) -> Vec<u8> {
+    let base_timestamp = records.first().map_or(0, |r| r.timestamp_ms);
+    let max_timestamp = records.iter().map(|r| r.timestamp_ms).max().unwrap_or(0);
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&0i16.to_be_bytes());  // attributes: no compression, create time
+    body.extend_from_slice(&(records.len() as i32 - 1).to_be_bytes());
+    body.extend_from_slice(&base_timestamp.to_be_bytes());
+    body.extend_from_slice(&max_timestamp.to_be_bytes());
+    body.extend_from_slice(&(-1i64).to_be_bytes());  // producer_id
+    body.extend_from_slice(&(-1i16).to_be_bytes());  // producer_epoch
+    body.extend_from_slice(&(-1i32).to_be_bytes());  // base_sequence
+    body.extend_from_slice(&(records.len() as i32).to_be_bytes());
+    for (offset, record) in records.iter().enumerate() {
+        let mut encoded = vec![0];  // attributes (unused)
+        varint(&mut encoded, record.timestamp_ms - base_timestamp);
+        varint(&mut encoded, offset as i64);
+        varint(&mut encoded, record.key.len() as i64);
+        encoded.extend_from_slice(record.key.as_bytes());
+        varint(&mut encoded, record.value.len() as i64);
+        encoded.extend_from_slice(&record.value);
+        varint(&mut encoded, 0);  // No headers
+        varint(&mut body, encoded.len() as i64);
+        body.extend_from_slice(&encoded);
+    }
+
+    let mut batch = Vec::with_capacity(body.len() + 21);
+    batch.extend_from_slice(&0i64.to_be_bytes());  // base_offset; the broker assigns the real one
+    batch.extend_from_slice(&(body.len() as i32 + 9).to_be_bytes());  // Leader epoch, magic and CRC, then the body
+    batch.extend_from_slice(&(-1i32).to_be_bytes());
+    batch.push(2);
+    batch.extend_from_slice(&crc32c(&body).to_be_bytes());
+    batch.extend_from_slice(&body);
+    batch
+}
+
+// The partition the Java client's default partitioner picks for a key, so our
+// messages land where any other producer's messages for the same symbol do
+pub fn partition_for(key: &[u8], partitions: usize) -> usize {
+    (murmur2(key) & 0x7fff_ffff) as usize % partitions
+}
+
+// Kafka's flavour of MurmurHash2 (fixed seed, little-endian blocks)
+pub fn murmur2(data: &[u8]) -> i32 {
+    const M: u32 = 0x5bd1_e995;
+    let mut h = 0x9747_b28c ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M) ^ k;
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h as i32
+}
+
+// CRC-32C (Castagnoli), table-driven
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82f6_3b78 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32c(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+// Zigzag varint, as used inside records (and by Avro)
+pub fn varint(out: &mut Vec<u8>, value: i64) {
+    let mut v = ((value << 1) ^ (value >> 63)) as u64;
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as i16).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+// Whether sending again (after fresh metadata) might succeed
+pub fn retryable(code: i16) -> bool {
+    matches!(code, 3 | 5 | 6 | 7 | 13 | 19 | 20)
+}
+
+// Names for the error codes a producer is likely to meet
+pub fn error_name(code: i16) -> String {
+    let name = match code {
+        0 => "NONE",
+        2 => "CORRUPT_MESSAGE",
+        3 => "UNKNOWN_TOPIC_OR_PARTITION",
+        5 => "LEADER_NOT_AVAILABLE",
+        6 => "NOT_LEADER_OR_FOLLOWER",
+        7 => "REQUEST_TIMED_OUT",
+        10 => "MESSAGE_TOO_LARGE",
+        13 => "NETWORK_EXCEPTION",
+        17 => "INVALID_TOPIC_EXCEPTION",
+        19 => "NOT_ENOUGH_REPLICAS",
+        20 => "NOT_ENOUGH_REPLICAS_AFTER_APPEND",
+        29 => "TOPIC_AUTHORIZATION_FAILED",
+        31 => "CLUSTER_AUTHORIZATION_FAILED",
+        87 => "INVALID_RECORD",
+        _ => return format!("error {}", code),
+    };
+    name.to_string()
+}
+
+// Reads big-endian fields off the front of a response
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let (head, rest) = self.0.split_first_chunk::<N>().ok_or("response cut short")?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    fn i8(&mut self) -> Result<i8, String> {
+        Ok(i8::from_be_bytes(self.take()?))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_be_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.take()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_be_bytes(self.take()?))
+    }
+
+    // Array length; a null array counts as empty
+    fn count(&mut self) -> Result<usize, String> {
+        Ok(self.i32()?.max(0) as usize)
+    }
+
+    // A (nullable) string; null comes back empty
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.i16()?.max(0) as usize;
+        let (text, rest) = self.0.split_at_checked(len).ok_or("response cut short")?;
+        self.0 = rest;
+        Ok(String::from_utf8_lossy(text).into_owned())
+    }
+
+    fn skip_i32_array(&mut self) -> Result<(), String> {
+        for _ in 0..self.count()? {
+            self.i32()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn i16s(value: i16) -> [u8; 2] {
+        value.to_be_bytes()
+    }
+
+    fn i32s(value: i32) -> [u8; 4] {
+        value.to_be_bytes()
+    }
+
+    fn text(value: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        string(&mut out, value);
+        out
+    }
+
+    // (name, error, partitions as (partition, error, leader))
+    type Topic<'a> = (&'a str, i16, &'a [(i32, i16, i32)]);
+
+    // A Metadata v4 response as a broker writes it
+    fn metadata_response(brokers: &[(i32, &str, u16)], topics: &[Topic]) -> Vec<u8> {
+        let mut out = i32s(0).to_vec();  // throttle
+        out.extend(i32s(brokers.len() as i32));
+        for (node, host, port) in brokers {
+            out.extend(i32s(*node));
+            out.extend(text(host));
+            out.extend(i32s(*port as i32));
+            out.extend(i16s(-1));  // rack
+        }
+        out.extend(text("cluster"));
+        out.extend(i32s(1));  // controller
+        out.extend(i32s(topics.len() as i32));
+        for (name, error, partitions) in topics {
+            out.extend(i16s(*error));
+            out.extend(text(name));
+            out.push(0);
+            out.extend(i32s(partitions.len() as i32));
+            for (partition, error, leader) in partitions.iter() {
+                out.extend(i16s(*error));
+                out.extend(i32s(*partition));
+                out.extend(i32s(*leader));
+                out.extend([i32s(1), i32s(*leader)].concat());  // replicas
+                out.extend(i32s(-1));                           // in-sync replicas: null
+            }
+        }
+        out
+    }
+
+    // A Produce v3 response for one topic, without the trailing throttle time
+    fn produce_response(topic: &str, partitions: &[(i32, i16)]) -> Vec<u8> {
+        let mut out = i32s(1).to_vec();
+        out.extend(text(topic));
+        out.extend(i32s(partitions.len() as i32));
+        for (partition, error) in partitions {
+            out.extend(i32s(*partition));
+            out.extend(i16s(*error));
+            out.extend(42i64.to_be_bytes());
+            out.extend((-1i64).to_be_bytes());
+        }
+        out
+    }
+
+    fn read_varint(bytes: &mut &[u8]) -> i64 {
+        let (mut value, mut shift) = (0u64, 0);
+        loop {
+            let byte = bytes[0];
+            *bytes = &bytes[1..];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return ((value >> 1) as i64) ^ -((value & 1) as i64);
+            }
+            shift += 7;
+        }
+    }
+
+    // The records back out of a v2 batch, checking its framing and CRC on the way
+    fn decode_batch(batch: &[u8]) -> Vec<Record> {
+        let mut r = Reader(batch);
+        assert_eq!(r.i64().unwrap(), 0);
+        assert_eq!(r.i32().unwrap() as usize, r.0.len());
+        r.i32().unwrap();                  // leader epoch
+        assert_eq!(r.i8().unwrap(), 2);    // magic
+        assert_eq!(r.i32().unwrap() as u32, crc32c(r.0));
+        assert_eq!(r.i16().unwrap(), 0);   // attributes
+        let last_offset_delta = r.i32().unwrap();
+        let base_timestamp = r.i64().unwrap();
+        r.i64().unwrap();                  // max timestamp
+        assert_eq!((r.i64().unwrap(), r.i16().unwrap(), r.i32().unwrap()), (-1, -1, -1));
+        let count = r.count().unwrap();
+        assert_eq!(count as i32 - 1, last_offset_delta);
+
+        let mut rest = r.0;
+        let mut records = Vec::new();
+        for offset in 0..count {
+            let len = read_varint(&mut rest) as usize;
+            let (mut record, tail) = rest.split_at(len);
+            rest = tail;
+            assert_eq!(record[0], 0);
+            record = &record[1..];
+            let timestamp_ms = base_timestamp + read_varint(&mut record);
+            assert_eq!(read_varint(&mut record), offset as i64);
+            let key_len = read_varint(&mut record) as usize;
+            let key = String::from_utf8(record[..key_len].to_vec()).unwrap();
+            record = &record[key_len..];
+            let value_len = read_varint(&mut record) as usize;
+            let value = record[..value_len].to_vec();
+            record = &record[value_len..];
+            assert_eq!(read_varint(&mut record), 0);  // headers
+            assert!(record.is_empty());
+            records.push(Record { key, value, timestamp_ms });
+        }
+        assert!(rest.is_empty());
+        records
+    }
+
+    #[test]
+    fn requests_carry_the_header_and_body() {
+        let message = request(METADATA, METADATA_VERSION, 7, "crabby", &metadata_request("crypto-tickers"));
+        let mut r = Reader(&message);
+        assert_eq!(r.i32().unwrap() as usize, message.len() - 4);
+        assert_eq!((r.i16().unwrap(), r.i16().unwrap(), r.i32().unwrap()), (METADATA, METADATA_VERSION, 7));
+        assert_eq!(r.string().unwrap(), "crabby");
+        assert_eq!(r.count().unwrap(), 1);
+        assert_eq!(r.string().unwrap(), "crypto-tickers");
+        assert_eq!(r.i8().unwrap(), 1);  // allow_auto_topic_creation
+        assert!(r.0.is_empty());
+    }
+
+    #[test]
+    fn metadata_round_trips_and_rejects_what_cannot_be_produced_to() {
+        let brokers = [(1, "kafka-1", 9092), (2, "kafka-2", 9093)];
+        let body = metadata_response(&brokers, &[("other", 0, &[(0, 0, 2)]), ("crypto-tickers", 0, &[(1, 0, 2), (0, 0, 1)])]);
+        let metadata = parse_metadata(&body, "crypto-tickers").unwrap();
+        assert_eq!(metadata.partitions, [(0, 1), (1, 2)]);  // Sorted by partition
+        assert_eq!(metadata.brokers[&2], ("kafka-2".to_string(), 9093));
+
+        let missing = parse_metadata(&body, "nope").unwrap_err();
+        assert_eq!(missing, "no metadata for topic \"nope\"");
+        let denied = metadata_response(&brokers, &[("crypto-tickers", 29, &[])]);
+        assert_eq!(parse_metadata(&denied, "crypto-tickers").unwrap_err(), "topic \"crypto-tickers\": TOPIC_AUTHORIZATION_FAILED");
+        let electing = metadata_response(&brokers, &[("crypto-tickers", 0, &[(0, 5, -1)])]);
+        assert_eq!(parse_metadata(&electing, "crypto-tickers").unwrap_err(), "partition 0 has no leader (LEADER_NOT_AVAILABLE)");
+        let empty = metadata_response(&brokers, &[("crypto-tickers", 0, &[])]);
+        assert!(parse_metadata(&empty, "crypto-tickers").is_err());
+
+        // Cut anywhere, the response is an error rather than a panic or a wrong answer
+        for len in 0..body.len() {
+            assert!(parse_metadata(&body[..len], "crypto-tickers").is_err(), "cut at {}", len);
+        }
+    }
+
+    #[test]
+    fn produce_requests_and_responses_round_trip() {
+        let records = [Record { key: "ETH-USD".to_string(), value: b"{}".to_vec(), timestamp_ms: 5 }];
+        let batches = [(0, record_batch(&records.iter().collect::<Vec<_>>())), (3, Vec::new())];
+        let body = produce_request(-1, 30_000, "crypto-tickers", &batches);
+        let mut r = Reader(&body);
+        assert_eq!(r.i16().unwrap(), -1);  // transactional_id: null
+        assert_eq!((r.i16().unwrap(), r.i32().unwrap()), (-1, 30_000));
+        assert_eq!(r.count().unwrap(), 1);
+        assert_eq!(r.string().unwrap(), "crypto-tickers");
+        assert_eq!(r.count().unwrap(), 2);
+        for (partition, batch) in &batches {
+            assert_eq!(r.i32().unwrap(), *partition);
+            let len = r.i32().unwrap() as usize;
+            assert_eq!(&r.0[..len], &batch[..]);
+            r.0 = &r.0[len..];
+        }
+        assert!(r.0.is_empty());
+
+        let response = produce_response("crypto-tickers", &[(0, 0), (3, 6)]);
+        assert_eq!(parse_produce(&response).unwrap(), [(3, 6)]);
+        assert!(retryable(6) && !retryable(29));
+        for len in 0..response.len() {
+            assert!(parse_produce(&response[..len]).is_err(), "cut at {}", len);
+        }
+    }
+
+    #[test]
+    fn record_batches_round_trip() {
+        let records = vec![
+            Record { key: "BTC-USD".to_string(), value: b"65000.01".to_vec(), timestamp_ms: 1_700_000_000_000 },
+            Record { key: "ETH-USD".to_string(), value: vec![0; 300], timestamp_ms: 1_700_000_000_250 },  // Multi-byte varint lengths
+            Record { key: String::new(), value: Vec::new(), timestamp_ms: 1_699_999_999_000 },             // Negative delta
+        ];
+        assert_eq!(decode_batch(&record_batch(&records.iter().collect::<Vec<_>>())), records);
+    }
+
+    #[test]
+    fn hashes_checksums_and_batches_like_the_java_client() {
+        // Test vectors from Kafka's own UtilsTest and the CRC-32C check value
+        assert_eq!(murmur2(b"21"), -973932308);
+        assert_eq!(murmur2(b"foobar"), -790332482);
+        assert_eq!(murmur2(b"a-little-bit-long-string"), -985981536);
+        assert_eq!(murmur2(b"a-little-bit-longer-string"), -1486304829);
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+
+        let mut out = Vec::new();
+        for value in [0, -1, 1, 300, -300] {
+            varint(&mut out, value);
+        }
+        assert_eq!(out, [0x00, 0x01, 0x02, 0xd8, 0x04, 0xd7, 0x04]);
+
+        let records = [
+            Record { key: "BTC-USD".to_string(), value: b"1".to_vec(), timestamp_ms: 1000 },
+            Record { key: "BTC-USD".to_string(), value: b"2".to_vec(), timestamp_ms: 1005 },
+        ];
+        let batch = record_batch(&records.iter().collect::<Vec<_>>());
+        let length = i32::from_be_bytes(batch[8..12].try_into().unwrap()) as usize;
+        assert_eq!(length, batch.len() - 12);
+        assert_eq!(batch[16], 2);  // magic
+        assert_eq!(u32::from_be_bytes(batch[17..21].try_into().unwrap()), crc32c(&batch[21..]));
+        // The second record: length, attributes, timestamp delta 5, offset delta 1, key, value, no headers
+        let second = [&[0x1c, 0x00, 0x0a, 0x02, 0x0e][..], b"BTC-USD", &[0x02], b"2", &[0x00]].concat();
+        assert!(batch.ends_with(&second));
+    }
+}
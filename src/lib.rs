@@ -0,0 +1,359 @@
+//! CrabbyCryptoTracker as a library: connect to one or more exchanges, keep the
+//! latest price per symbol, and stream updates to whoever is interested.
+//!
+//! ```no_run
+//! # async fn demo() {
+//! use crabbycryptotracker::PriceTracker;
+//! use futures_util::StreamExt;
+//!
+//! let tracker = PriceTracker::new();
+//! tracker.subscribe(&["BTC-USD".to_string()]);
+//! let mut updates = tracker.updates();
+//! while let Some(update) = updates.next().await {
+//!     println!("{} {}: {}", update.exchange, update.symbol, update.price);
+//! }
+//! # }
+//! ```
+
+#[cfg(feature = "account")]
+pub mod account;      // Coinbase Advanced Trade account: balances and fills (authenticated)
+pub mod alerts;       // Price alert rules and the engine that evaluates them
+#[cfg(feature = "api")]
+pub mod api;          // Embedded REST API (axum)
+pub mod arbitrage;    // Cross-exchange spread alerts
+pub mod backfill;     // Recent history from the Coinbase REST API at startup
+pub mod backtest;     // Alert rules, scripts and paper trading rules run over recorded history, with P&L
+pub mod backoff;      // Exponential backoff for reconnects
+pub mod candles;      // OHLCV candle aggregation (1m/5m/1h...)
+pub mod config;       // config.toml loading, env overrides and validation
+#[cfg(unix)]
+pub mod daemon;       // Running in the background: PID file, log file, status/stop over a Unix socket
+pub mod daily;        // End-of-day report: OHLC per symbol, movers, alerts fired and the portfolio's change
+pub mod dca;          // Recurring purchases on a schedule: simulated into the holdings, or real orders
+#[cfg(feature = "tui")]
+pub mod dashboard;    // Interactive terminal dashboard (ratatui)
+pub mod depeg;        // Stablecoin depeg monitor (USDT, USDC, DAI against 1.00)
+pub mod exchange;     // Per-exchange connectors (Coinbase, Binance spot and futures, Kraken, Uniswap pools)
+pub mod health;       // Feed health: messages per second and exchange-to-us latency per symbol
+pub mod indicators;   // SMA, EMA, RSI and MACD computed from the candles
+pub mod export;       // Recorded history out as CSV, JSON or Parquet, optionally resampled into candles
+pub mod feed;         // WebSocket connection, frame validation, reconnect loop
+pub mod funding;      // Perpetual futures: mark price, index price and funding rate per contract
+pub mod fx;           // Fiat display currency: USD→EUR/GBP/JPY rates and conversion
+pub mod groups;       // Symbol groups: alert defaults, per-symbol overrides and their own webhooks
+#[cfg(feature = "grpc")]
+pub mod grpc;         // gRPC API (GetSnapshot, StreamPrices) over a minimal HTTP/2 server
+#[cfg(feature = "kafka")]
+pub mod kafka;        // Kafka producer: updates keyed by symbol, as JSON or Avro
+#[cfg(feature = "mqtt")]
+pub mod mqtt;         // MQTT publishing of price updates (Home Assistant etc.)
+pub mod market;       // Market cap, rank, supply and 24h volume from CoinGecko next to the live price
+pub mod metrics;      // Prometheus counters, gauges and histograms
+pub mod net;          // Proxies (SOCKS5, HTTP CONNECT) and extra CA certificates for outbound connections
+pub mod notify;       // Where fired alerts get delivered
+pub mod paper;        // Paper trading: simulated orders filled against live prices
+pub mod orderbook;    // Level 2 order books: best bid/ask and spread
+pub mod portfolio;    // Holdings file + live valuation and P&L
+pub mod products;     // Checking symbols against each exchange's product list
+pub mod queue;        // Bounded queues with overflow policies and drop counters, for slow consumers
+#[cfg(feature = "redis")]
+pub mod redis;        // Redis sink: price:{symbol} keys with a TTL, updates on a pub/sub channel
+pub mod recording;    // Recording raw WebSocket messages and replaying them
+pub mod relay;        // Local WebSocket server rebroadcasting updates and candles
+pub mod report;       // When the price table is printed: per-symbol intervals and quiet hours
+pub mod script;       // Alert rules as scripts over live prices and indicators, reloaded on change
+pub mod sequence;     // Sequence-number gap detection
+pub mod shard;        // Spreading an exchange's symbols over several connections
+pub mod snapshots;    // Price snapshots appended to rotating CSV files
+pub mod source;       // Where feeds get their connections: exchange WebSockets, or scripted mocks for tests
+pub mod stats;        // Rolling % change, high/low and volatility per symbol
+#[cfg(feature = "sqlite")]
+pub mod storage;      // Batched SQLite persistence of every update
+pub mod store;        // Latest prices owned by a state thread (message passing) + broadcast of updates
+pub mod style;        // Green/red prices, arrows and highlighting for the table output
+pub mod symbols;      // Loading symbol lists (CSV)
+pub mod synthetic;    // Cross prices implied from the tracked pairs (ETH-USD from ETH-BTC and BTC-USD)
+#[cfg(feature = "telegram")]
+pub mod telegram;     // Telegram bot: alert messages and /price, /portfolio commands
+pub mod ticks;        // Ring buffer of recent ticks per symbol (GET /history)
+#[cfg(feature = "account")]
+pub mod trading;      // Real orders on the Coinbase account, behind --enable-trading, dry run and size limits
+#[cfg(feature = "tsdb")]
+pub mod tsdb;         // InfluxDB / TimescaleDB sink for ticks and candles
+pub mod volume;       // Rolling trade volume, trade count and VWAP from the trade channels
+pub mod watchlist;    // Named groups of symbols with an index, average change and top mover
+
+use std::{
+    collections::BTreeSet,   // Tracked symbols, sorted
+    path::PathBuf,           // Recording to replay
+    sync::{Arc, Mutex},      // Feed handles, so subscribing only needs `&self`
+};
+
+use futures_util::stream::{self, BoxStream, StreamExt};
+use tokio::sync::{broadcast::error::RecvError, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
+use tracing::{info_span, Instrument};
+
+use net::Network;
+use recording::{RecordSink, ReplaySpeed};
+use source::{FeedSource, WebSocketSource};
+
+pub use exchange::{Exchange, Funding, Ticker, Trade};
+pub use store::{PriceStore, PriceUpdate};
+
+// The main entry point for embedding the tracker in another application
+pub struct PriceTracker {
+    exchanges: Vec<Arc<dyn Exchange>>,  // Venues every subscription connects to
+    store: PriceStore,                  // Latest prices and live update channel
+    feeds: Mutex<Vec<JoinHandle<()>>>,  // Running feed tasks (one per exchange, sharded or not), aborted on drop
+    symbols: watch::Sender<BTreeSet<String>>,  // Symbols every feed should be subscribed to
+    shutdown: watch::Sender<bool>,      // Flipped to true to ask feeds to stop
+    order_books: bool,                  // Also subscribe to level 2 order books
+    trades: bool,                       // Also subscribe to every trade (volume, VWAP)
+    stale_after: Option<Duration>,      // Reconnect when a symbol's heartbeats stop this long
+    resync_on_gap: bool,                // Resubscribe a symbol when messages were dropped
+    per_connection: Option<usize>,      // Shard each exchange's symbols over connections of this size
+    recorder: Option<RecordSink>,       // Copy every raw frame here (--record)
+    source: Arc<dyn FeedSource>,        // Opens the connections: WebSockets (through the proxy), or a mock
+    replay: Option<(PathBuf, ReplaySpeed)>,  // Read frames from a recording instead of connecting
+    finished: watch::Sender<bool>,      // Flipped to true when a replay reaches the end
+}
+
+// How long `shutdown` waits for feeds to close their connections
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(3);
+
+// How long a symbol may go without data or heartbeat before its feed reconnects
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(30);
+
+// Pause before a replay starts
+const REPLAY_LEAD_IN: Duration = Duration::from_millis(250);
+
+impl Default for PriceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceTracker {
+    // A tracker that reads from Coinbase only
+    pub fn new() -> Self {
+        Self::with_exchanges(vec![Box::new(exchange::Coinbase)])
+    }
+
+    // A tracker that reads the same symbols from several exchanges at once
+    pub fn with_exchanges(exchanges: Vec<Box<dyn Exchange>>) -> Self {
+        Self {
+            exchanges: exchanges.into_iter().map(Arc::from).collect(),
+            store: PriceStore::new(),
+            feeds: Mutex::new(Vec::new()),
+            symbols: watch::channel(BTreeSet::new()).0,
+            shutdown: watch::channel(false).0,
+            order_books: false,
+            trades: false,
+            stale_after: Some(DEFAULT_STALE_AFTER),
+            resync_on_gap: false,
+            per_connection: None,
+            recorder: None,
+            source: Arc::new(WebSocketSource::default()),
+            replay: None,
+            finished: watch::channel(false).0,
+        }
+    }
+
+    // Also maintain level 2 order books (best bid/ask, spread) on exchanges that
+    // support them. Affects feeds started by later `subscribe` calls.
+    pub fn track_order_books(&mut self, enabled: bool) {
+        self.order_books = enabled;
+    }
+
+    // Also follow every trade (Coinbase matches, Binance and Kraken trades) for rolling
+    // volume, trade count and VWAP. Affects feeds started by later `subscribe` calls.
+    pub fn track_trades(&mut self, enabled: bool) {
+        self.trades = enabled;
+    }
+
+    // Mark a symbol stale and reconnect when it has had no data or heartbeat for
+    // `after` (None turns the check off). Only exchanges with per-symbol heartbeats
+    // (Coinbase) are checked. Affects feeds started by later `subscribe` calls.
+    pub fn detect_stale_feeds(&mut self, after: Option<Duration>) {
+        self.stale_after = after;
+    }
+
+    // Resubscribe a symbol (fetching a fresh order book snapshot, if it has a book) whenever
+    // its sequence numbers show dropped messages. Gaps are always counted and logged; this only adds
+    // the resync. Affects feeds started by later `subscribe` calls.
+    pub fn resync_on_gap(&mut self, enabled: bool) {
+        self.resync_on_gap = enabled;
+    }
+
+    // Open another connection to an exchange for every `per_connection` symbols instead
+    // of putting them all on one (None: one connection per exchange). Each connection
+    // reconnects on its own. Affects feeds started by later `subscribe` calls.
+    pub fn symbols_per_connection(&mut self, per_connection: Option<usize>) {
+        self.per_connection = per_connection;
+    }
+
+    // Copy every raw message the feeds receive to a recording (see `recording`).
+    // Affects feeds started by later `subscribe` calls.
+    pub fn record_to(&mut self, sink: RecordSink) {
+        self.recorder = Some(sink);
+    }
+
+    // Connect through a proxy and/or trust extra CA certificates (see `net`). Affects
+    // feeds started by later `subscribe` calls.
+    pub fn use_network(&mut self, network: Network) {
+        self.source = Arc::new(WebSocketSource::new(network));
+    }
+
+    // Take the feeds' connections from `source` instead of the exchanges' WebSockets,
+    // e.g. a `MockSource` in tests. Affects feeds started by later `subscribe` calls.
+    pub fn use_source(&mut self, source: Arc<dyn FeedSource>) {
+        self.source = source;
+    }
+
+    // Don't connect to the exchanges at all: play back a recording made with `record_to`
+    // instead, through the same message handling. Affects later `subscribe` calls.
+    pub fn replay_from(&mut self, path: PathBuf, speed: ReplaySpeed) {
+        self.replay = Some((path, speed));
+    }
+
+    // Resolves once a replay has played the whole recording; never for live feeds
+    pub async fn finished(&self) {
+        let mut finished = self.finished.subscribe();
+        let _ = finished.wait_for(|done| *done).await;
+    }
+
+    // Start tracking `symbols` on every configured exchange. The first call spawns one
+    // reconnecting feed task per exchange, so it must be called from inside a Tokio
+    // runtime; later calls (even while running) add to the symbols those feeds follow.
+    pub fn subscribe(&self, symbols: &[String]) {
+        self.symbols.send_modify(|tracked| tracked.extend(symbols.iter().cloned()));
+
+        let mut feeds = self.feeds.lock().unwrap();
+        if feeds.is_empty()
+            && let Some((path, speed)) = &self.replay
+        {
+            let replay = recording::run_replay(
+                path.clone(),
+                self.exchanges.clone(),
+                *speed,
+                self.symbols.subscribe(),
+                self.store.clone(),
+                self.order_books,
+                self.shutdown.subscribe(),
+            );
+            let (store, finished) = (self.store.clone(), self.finished.clone());
+            feeds.push(tokio::spawn(
+                async move {
+                    // A live feed takes a moment to connect; give whoever subscribes to
+                    // updates right after startup (the output, the API) the same chance
+                    tokio::time::sleep(REPLAY_LEAD_IN).await;
+                    replay.await;
+                    // Give the consumers (output, storage, alerts) a moment to catch up
+                    let drained = async {
+                        while store.pending_updates() > 0 {
+                            tokio::time::sleep(Duration::from_millis(10)).await;
+                        }
+                    };
+                    let _ = timeout(SHUTDOWN_GRACE, drained).await;
+                    finished.send_replace(true);
+                }
+                .instrument(info_span!("replay")),
+            ));
+        } else if feeds.is_empty() {
+            for exchange in &self.exchanges {
+                let options = feed::FeedOptions {
+                    order_books: self.order_books,
+                    trades: self.trades,
+                    stale_after: self.stale_after,
+                    resync_on_gap: self.resync_on_gap,
+                    recorder: self.recorder.clone(),
+                    source: Arc::clone(&self.source),
+                };
+                let (symbols, shutdown) = (self.symbols.subscribe(), self.shutdown.subscribe());
+                let span = info_span!("feed", exchange = exchange.name());
+                let task = match self.per_connection {
+                    Some(n) => tokio::spawn(shard::run_sharded(Arc::clone(exchange), symbols, self.store.clone(), options, n, shutdown).instrument(span)),
+                    None => tokio::spawn(feed::run_feed(Arc::clone(exchange), symbols, self.store.clone(), options, shutdown).instrument(span)),
+                };
+                feeds.push(task);
+            }
+        }
+    }
+
+    // Stop tracking `symbols`: the feeds unsubscribe on their open connections and the
+    // symbols' prices and order books are removed from the store
+    pub async fn unsubscribe(&self, symbols: &[String]) {
+        self.symbols.send_modify(|tracked| tracked.retain(|s| !symbols.contains(s)));
+        for symbol in symbols {
+            self.store.remove(symbol).await;
+        }
+    }
+
+    // The symbols currently tracked, sorted
+    pub fn symbols(&self) -> Vec<String> {
+        self.symbols.borrow().iter().cloned().collect()
+    }
+
+    // The symbols as they change, for tasks that follow them (market data...)
+    pub fn watch_symbols(&self) -> watch::Receiver<BTreeSet<String>> {
+        self.symbols.subscribe()
+    }
+
+    // Most recent price for a symbol, from whichever exchange updated it last
+    pub async fn latest(&self, symbol: &str) -> Option<PriceUpdate> {
+        self.store.latest(symbol).await
+    }
+
+    // Recent ticks of a symbol (oldest first) from whichever exchange updated it last,
+    // over the last `window` or everything kept (see `PriceStore::keep_ticks`)
+    pub async fn history(&self, symbol: &str, window: Option<Duration>) -> Vec<PriceUpdate> {
+        match self.store.latest(symbol).await {
+            Some(latest) => self.store.ticks(latest.exchange, symbol, window).await,
+            None => Vec::new(),
+        }
+    }
+
+    // Async stream of every price update from now on. A consumer that falls far
+    // behind skips the updates it missed rather than stalling the feed.
+    pub fn updates(&self) -> BoxStream<'static, PriceUpdate> {
+        let metrics = self.store.metrics().clone();
+        stream::unfold((self.store.subscribe_updates(), metrics), |(mut rx, metrics)| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(update) => return Some((update, (rx, metrics))),
+                    // Dropped some; carry on with the newest
+                    Err(RecvError::Lagged(n)) => metrics.record_dropped_events("updates", n),
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()  // Boxed so callers can use `.next()` without pinning it themselves
+    }
+
+    // Direct access to the shared store (snapshots, per-exchange lookups)
+    pub fn store(&self) -> &PriceStore {
+        &self.store
+    }
+
+    // Ask every feed to close its WebSocket cleanly and wait (briefly) for them to finish
+    pub async fn shutdown(&mut self) {
+        let _ = self.shutdown.send(true);
+        for feed in self.feeds.get_mut().unwrap().drain(..) {
+            let abort = feed.abort_handle();
+            if timeout(SHUTDOWN_GRACE, feed).await.is_err() {
+                abort.abort();  // Stuck (e.g. a dead TCP connection); stop waiting
+            }
+        }
+    }
+}
+
+impl Drop for PriceTracker {
+    // Dropping the tracker stops its feed tasks
+    fn drop(&mut self) {
+        for feed in self.feeds.get_mut().unwrap().iter() {
+            feed.abort();
+        }
+    }
+}
@@ -1,118 +1,1359 @@
-// Standard library imports
+// Command-line wrapper around the crabbycryptotracker library: parses arguments,
+// loads the configuration, starts the tracker, and runs the chosen mode.
+
 use std::{
-    collections::HashMap,     // For storing the latest price per crypto symbol
-    error::Error,             // Trait to return errors from our main() and functions
-    fs::File,                 // Used to open the CSV file
-    path::Path,               // Used to handle file paths
-    sync::{Arc, Mutex},       // Arc for shared state across tasks, Mutex for thread-safe mutation
+    collections::{BTreeSet, HashMap},  // Symbols to follow in a recording; prices at the previous printout
+    error::Error,             // Trait to return errors from our main()
+    io::{self, IsTerminal, Write},  // NDJSON output; colored logs only on a terminal
+    sync::{Arc, Mutex},       // Shared candle aggregator
+    time::{Duration, Instant, SystemTime},  // Session runtime for the final summary; volume windows end now
 };
 
-// Async + WebSocket + JSON + CSV handling
-use futures_util::{SinkExt, StreamExt};            // For working with WebSocket input/output
-use serde::Deserialize;                            // For converting JSON messages into Rust structs
-use tokio::time::{sleep, Duration};                // Async sleep and timing
-use tokio_tungstenite::connect_async;              // WebSocket client for Tokio
-use url::Url;                                      // To parse the wss:// URL
-use csv::ReaderBuilder;                            // CSV parser
+use clap::Parser;                    // Derive-based argument parsing
+use futures_util::StreamExt;         // `next()` on the update stream
+use rust_decimal::Decimal;           // Prices
+use tokio::{signal, sync::Notify, time::sleep};    // Ctrl-C handling, on-demand printouts, async sleep
+use tracing::{info, warn};           // Status messages (stderr)
+use tracing_subscriber::EnvFilter;   // --log-level / RUST_LOG filtering
+
+#[cfg(feature = "account")]
+use crabbycryptotracker::account::CoinbaseAccount;
+use crabbycryptotracker::{
+    alerts::{load_rule_file, spawn_alerts, AlertEngine},
+    arbitrage::{spawn_arbitrage, ArbitrageDetector},
+    backtest::Backtest,
+    depeg::{spawn_depeg, DepegMonitor},
+    export::{self, Filter, Format, Table},
+    candles::{spawn_candles, Candle, CandleAggregator, CandleSink, SharedCandles},
+    config::{Config, OutputFormat},
+    daily::{spawn_daily_report, AlertLog, DailyReporter},
+    dca::{spawn_dca, DcaScheduler},
+    exchange,
+    groups,
+    indicators::Indicators,
+    net::Network,
+    notify::{ConsoleNotifier, Notifier},
+    orderbook::TopOfBook,
+    paper::{spawn_paper, PaperTrader, SharedPaper},
+    portfolio::Portfolio,
+    recording::{self, Recorder},
+    relay::Relay,
+    report::{Reporter, UpdateSampler},
+    script::{spawn_script, ScriptRunner},
+    synthetic::{self, spawn_synthetic, Route, SyntheticPrices},
+    snapshots::{SnapshotConfig, SnapshotWriter},
+    style::{Movement, Styler},
+    symbols::load_symbols_from_csv,
+    ticks::TickLimits,
+    watchlist::Watchlists,
+    Exchange, PriceTracker,
+};
+
+mod cli;
+mod control;
+use cli::{Cli, Command, GlobalArgs, LogFormat, TrackArgs};
+
+// The background side of `track --daemon`; other platforms have no Unix socket to serve
+#[cfg(unix)]
+use crabbycryptotracker::daemon::{self, Daemon};
+#[cfg(not(unix))]
+enum Daemon {}
 
-// Struct representing the JSON format of messages we receive from Coinbase
-#[derive(Debug, Deserialize)]
-struct TickerMessage {
-    #[serde(rename = "type")]
-    msg_type: String,        // The message type (e.g., "ticker")
-    product_id: String,      // The trading pair (e.g., "BTC-USD")
-    price: Option<String>,   // The price (may be None if not present)
+// The async entry point of your application (runs inside the Tokio runtime)
+#[tokio::main]
+async fn main() {
+    // Print errors with their Display text (e.g. "invalid value for `output.interval`: ...")
+    if let Err(e) = run().await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
 
-// Function that reads a CSV file and extracts a list of product IDs (symbols)
-fn load_symbols_from_csv<P: AsRef<Path>>(path: P) -> Result<Vec<String>, Box<dyn Error>> {
-    let file = File::open(path)?;  // Open the file, `?` handles error forwarding
-    let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(file); // CSV reader that skips the header
-    let mut symbols = Vec::new();  // To store our symbols
+async fn run() -> Result<(), Box<dyn Error>> {
+    // Step 0: Parse the command line (`--help` and `--version` exit here)
+    let cli = Cli::parse();
+    init_logging(&cli.global)?;
+
+    // Step 1: Defaults < config.toml < environment < command-line flags, checked once up front
+    let config = load_config(&cli.global)?;
+
+    match cli.command.unwrap_or(Command::Track(TrackArgs::default())) {
+        Command::Track(args) => track(&config, args).await,
+        #[cfg(feature = "api")]
+        Command::Serve(args) => serve(&config, args).await,
+        Command::Export(args) => export(&config, args),
+        Command::Backtest(args) => backtest(&config, args).await,
+        #[cfg(unix)]
+        Command::Status => status(&config).await,
+        #[cfg(unix)]
+        Command::Stop => stop(&config).await,
+    }
+}
+
+// Send log events to stderr: `--log-level` wins over RUST_LOG, which wins over "info"
+fn init_logging(global: &GlobalArgs) -> Result<(), Box<dyn Error>> {
+    let filter = match &global.log_level {
+        Some(level) => EnvFilter::try_new(level).map_err(|e| format!("invalid --log-level \"{}\": {}", level, e))?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let logs = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());  // No color codes in redirected logs
+    match global.log_format {
+        LogFormat::Text => logs.with_target(false).init(),
+        LogFormat::Json => logs.json().init(),
+    }
+    Ok(())
+}
+
+// Build the final configuration and report any problem with the offending field
+fn load_config(global: &GlobalArgs) -> Result<Config, Box<dyn Error>> {
+    let mut config = Config::load(global.config.as_deref())?;
+    config.apply_env()?;
+
+    // Command-line flags win over everything else
+    if let Some(path) = &global.symbols_file {
+        config.symbols_file = path.clone();
+        config.symbols.clear();  // An explicit file means "read the symbols from it"
+    }
+    if !global.symbols.is_empty() {
+        config.symbols = global.symbols.iter().map(|s| s.trim().to_uppercase()).collect();
+    }
+    if !global.exchange.is_empty() {
+        config.exchanges = global.exchange.clone();
+    }
+    if let Some(unknown) = global.unknown_symbols {
+        config.unknown_symbols = unknown;
+    }
+    #[cfg(feature = "backfill")]
+    if global.no_backfill {
+        config.backfill.enabled = false;
+    }
+    #[cfg(feature = "market")]
+    if global.market_data {
+        config.market.enabled = true;
+    }
+    if global.order_books {
+        config.order_books = true;
+    }
+    if global.trades {
+        config.trades.enabled = true;
+    }
+    if global.resync_on_gap {
+        config.resync_on_gap = true;
+    }
+    if let Some(after) = global.stale_after {
+        config.stale_after = after;
+    }
+    if let Some(n) = global.symbols_per_connection {
+        config.symbols_per_connection = n;
+    }
+    if let Some(format) = global.output {
+        config.output.format = format;
+    }
+    if let Some(color) = global.color {
+        config.output.color = color;
+    }
+    if let Some(pct) = global.highlight_pct {
+        config.output.highlight_pct = pct;
+    }
+    if let Some(interval) = global.interval {
+        config.output.interval = interval;
+    }
+    if !global.quiet_hours.is_empty() {
+        config.output.quiet_hours = global.quiet_hours.clone();
+    }
+    if !global.every_nth.is_empty() {
+        config.output.every_nth = global.every_nth.iter().map(|e| (e.symbol.clone(), e.n)).collect();
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(db) = &global.db {
+        config.storage.path = Some(db.clone());
+    }
+    if let Some(url) = &global.mqtt_url {
+        config.mqtt.url = Some(url.clone());
+    }
+    if let Some(url) = &global.redis_url {
+        config.redis.url = Some(url.clone());
+    }
+    if !global.kafka_brokers.is_empty() {
+        config.kafka.brokers = global.kafka_brokers.clone();
+    }
+    if let Some(url) = &global.tsdb_url {
+        config.tsdb.url = Some(url.clone());
+    }
+    if let Some(path) = &global.snapshots {
+        config.snapshots.path = Some(path.clone());
+    }
+    if let Some(every) = global.snapshot_every {
+        config.snapshots.every = every;
+    }
+    if let Some(path) = &global.record {
+        config.recording.record = Some(path.clone());
+    }
+    if let Some(path) = &global.replay {
+        config.recording.replay = Some(path.clone());
+    }
+    if let Some(speed) = global.replay_speed {
+        config.recording.speed = speed;
+    }
+    if let Some(alerts) = &global.alerts {
+        config.alerts_file = Some(alerts.clone());
+    }
+    if let Some(script) = &global.script {
+        config.script.path = Some(script.clone());
+    }
+    if !global.indicator.is_empty() {
+        config.indicators.list = global.indicator.clone();
+    }
+    if global.depeg {
+        config.depeg.enabled = true;
+    }
+    if global.paper {
+        config.paper.enabled = true;
+    }
+    if global.enable_trading {
+        config.trading.enabled = true;
+    }
+    if let Some(pct) = global.arbitrage {
+        config.arbitrage.threshold_pct = Some(pct);
+    }
+    if let Some(currency) = &global.currency {
+        config.output.currency = Some(currency.trim().to_uppercase());
+    }
+    if let Some(addr) = global.relay_addr {
+        config.relay.addr = Some(addr);
+    }
+    if let Some(addr) = global.grpc_addr {
+        config.grpc.addr = Some(addr);
+    }
+    if let Some(proxy) = &global.proxy {
+        config.network.proxy = Some(proxy.clone());
+    }
+    if !global.ca_certs.is_empty() {
+        config.network.ca_certs = global.ca_certs.clone();
+    }
+    if let Some(portfolio) = &global.portfolio {
+        config.portfolio_file = Some(portfolio.clone());
+    }
+    if let Some(socket) = &global.socket {
+        config.daemon.socket = socket.clone();
+    }
 
-    // Iterate over each row in the CSV
-    for result in rdr.records() {
-        let record = result?;  // Handle any row-level parsing errors
+    config.validate()?;
+    Ok(config)
+}
 
-        // Extract the first column (assumes CSV has a single "symbol" column)
-        if let Some(sym) = record.get(0) {
-            symbols.push(sym.trim().to_string()); // Trim whitespace and store symbol
+// Default mode: stream prices and print them every `interval` (or show the dashboard)
+#[cfg_attr(not(any(feature = "api", feature = "tui")), allow(unused_variables))]
+async fn track(config: &Config, args: TrackArgs) -> Result<(), Box<dyn Error>> {
+    // `--daemon` starts a background copy of this command and leaves it to that copy
+    #[cfg(unix)]
+    if args.daemon && !daemon::is_daemon() {
+        #[cfg(feature = "tui")]
+        if args.tui {
+            return Err("--daemon runs without a terminal, so it can't show the dashboard (--tui)".into());
         }
+        let pid = daemon::launch(&config.daemon).await?;
+        info!(pid, log = %config.daemon.log_file.display(), socket = %config.daemon.socket.display(), "Tracker started in the background");
+        return Ok(());
+    }
+
+    let session = start_session(config).await?;
+
+    // In the background copy: the PID file and the socket `status` and `stop` talk to
+    #[cfg(unix)]
+    let daemon = match args.daemon {
+        true => Some(Daemon::start(&config.daemon).await?),
+        false => None,
+    };
+    #[cfg(not(unix))]
+    let daemon: Option<Daemon> = None;
+
+    // Optionally serve the REST API alongside the printout
+    #[cfg(feature = "api")]
+    if let Some(addr) = args.api_addr.or(config.api.addr) {
+        spawn_api(addr, &session);
+    }
+
+    // `--tui` shows the interactive dashboard instead of the periodic printout.
+    // The dashboard owns the terminal (raw mode), so `q` is how you leave it.
+    #[cfg(feature = "tui")]
+    if args.tui {
+        let (store, indicators, watchlists) = (session.tracker.store().clone(), session.indicators.clone(), session.watchlists.clone());
+        let result = tokio::task::spawn_blocking(move || crabbycryptotracker::dashboard::run(store, indicators, watchlists)).await;
+        session.shutdown().await;
+        result??;
+        return Ok(());
     }
 
-    Ok(symbols)  // Return the vector of symbols
+    // Print prices until Ctrl-C (or until whoever reads our NDJSON goes away),
+    // taking add/remove commands from stdin meanwhile. An empty line or SIGUSR1
+    // prints the table right away.
+    let print_now = Notify::new();
+    let output = async {
+        match config.output.format {
+            OutputFormat::Table => {
+                let styler = Styler::new(config.output.color.enabled(), config.output.highlight_pct);
+                let sampler = UpdateSampler::from_config(&config.output);
+                tokio::select! {
+                    result = print_prices(&session, Reporter::from_config(&config.output), styler, &print_now) => result,
+                    result = print_every_nth(&session, sampler, styler) => result,
+                }
+            }
+            OutputFormat::Ndjson => print_ndjson(&session).await,
+        }
+    };
+    tokio::select! {
+        result = output => {
+            if let Err(e) = result {
+                info!(error = %e, "Output closed, shutting down");
+            }
+        }
+        () = control::run(&session.tracker, session.paper.as_ref(), session.trader.as_deref(), &print_now) => {}  // Never finishes, even once stdin is closed
+        () = print_on_signal(&print_now) => {}  // Never finishes either
+        () = follow_symbols(config, &session) => {}  // Never finishes either
+        () = session.tracker.finished() => {}  // Only a replay ever finishes
+        () = stop_requested(daemon.as_ref(), &session.tracker) => {}  // `stop` or SIGTERM, in the background
+        result = signal::ctrl_c() => {
+            result?;
+            info!("Ctrl-C received, shutting down");
+        }
+    }
+    session.shutdown().await;
+    drop(daemon);  // Only now remove the socket: `stop` waits for that
+    Ok(())
 }
 
-// The async entry point of your application (runs inside the Tokio runtime)
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    // Step 1: Load trading symbols like BTC-USD, ETH-USD from your CSV file
-    let product_ids = load_symbols_from_csv("crypto.csv")?;  // Handle error if file not found
-
-    println!("Loaded symbols from CSV: {:?}", product_ids);
-
-    // Step 2: Format those symbols into the JSON structure that Coinbase expects
-    let joined_ids = product_ids.join(r#"", ""#);  // Join with commas and quotes
-    let subscribe_msg = format!(
-        r#"{{
-            "type": "subscribe",
-            "channels": [{{ "name": "ticker", "product_ids": ["{}"] }}]
-        }}"#,
-        joined_ids
-    );
-
-    // Step 3: Connect to the Coinbase WebSocket server securely over wss://
-    let url = Url::parse("wss://ws-feed.exchange.coinbase.com")?;
-    let (ws_stream, _) = connect_async(url).await?;  // Connect and await success
-    let (mut write, mut read) = ws_stream.split();   // Split into read/write halves
-
-    // Step 4: Send the subscription message so Coinbase knows what you want
-    write
-        .send(tokio_tungstenite::tungstenite::Message::Text(subscribe_msg))
-        .await?;
-
-    // Step 5: Create shared memory (a HashMap) that stores the latest price for each symbol
-    let prices = Arc::new(Mutex::new(HashMap::new()));  // Use Arc to share across threads/tasks
-    let prices_clone = Arc::clone(&prices);             // Clone for use in the background task
-
-    // Step 6: Spawn a background task that runs every 30 seconds and prints the latest prices
-    tokio::spawn(async move {
-        loop {
-            sleep(Duration::from_secs(30)).await;  // Wait 30 seconds
+// Answers `status` for a background tracker until it's asked to stop; never finishes
+// in the foreground
+async fn stop_requested(daemon: Option<&Daemon>, tracker: &PriceTracker) {
+    match daemon {
+        #[cfg(unix)]
+        Some(daemon) => daemon.serve(tracker).await,
+        _ => std::future::pending().await,
+    }
+}
 
-            let prices = prices_clone.lock().unwrap();  // Safely access shared memory
-            println!("\n==== Latest Prices (every 30 seconds) ====");
-            for (symbol, price) in prices.iter() {
-                println!("{}: ${}", symbol, price);  // Print each symbol and its latest price
+// `kill -USR1 <pid>` prints the table right away, like Enter on stdin; never finishes
+async fn print_on_signal(print_now: &Notify) {
+    #[cfg(unix)]
+    match signal::unix::signal(signal::unix::SignalKind::user_defined1()) {
+        Ok(mut usr1) => {
+            while usr1.recv().await.is_some() {
+                print_now.notify_one();
             }
-            println!("===========================================\n");
         }
-    });
+        Err(e) => warn!(error = %e, "Can't listen for SIGUSR1; press Enter to print the prices instead"),
+    }
+    #[cfg(not(unix))]
+    let _ = print_now;
+    std::future::pending().await
+}
 
-    // Step 7: Main WebSocket reading loop — receive messages from Coinbase continuously
-    while let Some(msg) = read.next().await {
-        match msg {
-            Ok(m) => {
-                if m.is_text() {
-                    let text = m.to_text().unwrap();
-
-                    // Try to parse the incoming message into our TickerMessage struct
-                    if let Ok(parsed) = serde_json::from_str::<TickerMessage>(text) {
-                        // Only act on messages of type "ticker" that have a price
-                        if parsed.msg_type == "ticker" && parsed.price.is_some() {
-                            let mut map = prices.lock().unwrap();  // Get write access to shared price map
-                            map.insert(parsed.product_id.clone(), parsed.price.unwrap()); // Update the latest price
-                        }
-                    }
+// `status`: ask the background tracker for its latest prices
+#[cfg(unix)]
+async fn status(config: &Config) -> Result<(), Box<dyn Error>> {
+    match daemon::request(&config.daemon.socket, "status").await? {
+        daemon::Reply::Status(status) => println!("{}", status),
+        daemon::Reply::Error { message } => return Err(message.into()),
+        daemon::Reply::Stopping { .. } => return Err("unexpected answer to status".into()),
+    }
+    Ok(())
+}
+
+// `stop`: shut the background tracker down and wait until it's done
+#[cfg(unix)]
+async fn stop(config: &Config) -> Result<(), Box<dyn Error>> {
+    let pid = daemon::stop(&config.daemon.socket).await?;
+    info!(pid, "Tracker stopped");
+    Ok(())
+}
+
+// Write every update as one JSON object per line, as it arrives. Status messages
+// go to stderr, so stdout can be piped straight into jq or a log shipper.
+async fn print_ndjson(session: &Session) -> io::Result<()> {
+    let (fx, market) = (session.tracker.store().fx(), session.tracker.store().market());
+    let mut updates = session.tracker.updates();
+    while let Some(update) = updates.next().await {
+        let mut out = io::stdout().lock();
+        let converted = fx.convert(update.price, &update.symbol);
+        let market = market.info(&update.symbol, update.price);
+        export::write_update_line(&mut out, &update, converted, market)?;
+        out.flush()?;  // One line at a time, even when stdout is a pipe
+    }
+    Ok(())
+}
+
+// When the symbols come from the CSV file, keep the subscriptions in sync with it.
+// Never finishes, so it can sit in a `select!` next to the real work.
+#[cfg_attr(not(feature = "watch"), allow(unused_variables))]
+async fn follow_symbols(config: &Config, session: &Session) {
+    #[cfg(feature = "watch")]
+    if config.symbols.is_empty() && config.watch_symbols_file {
+        // Holdings stay tracked even if they're removed from the file
+        let keep: Vec<String> = session.portfolio.iter().flat_map(|p| p.holdings()).map(|h| h.symbol.clone()).collect();
+        let path = &config.symbols_file;
+        if let Err(e) = crabbycryptotracker::symbols::follow_symbols_file(path, &session.tracker, &keep).await {
+            warn!(path = %path.display(), error = %e, "Not watching the symbols file for changes");
+        }
+    }
+    std::future::pending::<()>().await;
+}
+
+// Every `interval`, print the latest prices (runs until cancelled). A nudge on
+// `print_now` prints every symbol and the summaries at once, quiet hours or not.
+async fn print_prices(session: &Session, mut reporter: Reporter, styler: Styler, print_now: &Notify) -> io::Result<()> {
+    let store = session.tracker.store();
+    let mut previous: HashMap<(&'static str, String), Decimal> = HashMap::new();
+    let mut wake = 0;
+    let mut tick = Box::pin(sleep(reporter.tick()));
+    loop {
+        let on_demand = tokio::select! {
+            () = &mut tick => false,
+            () = print_now.notified() => true,
+        };
+        let prices = store.view().await;  // Prices, stats and stale marks for the whole printout
+        let (snapshot, summary, heading) = if on_demand {
+            (prices.snapshot().to_vec(), true, "now".to_string())
+        } else {
+            tick.as_mut().reset(tokio::time::Instant::now() + reporter.tick());
+            wake += 1;
+            if reporter.is_quiet(SystemTime::now()) {
+                continue;
+            }
+
+            // Only the symbols whose interval is up (all of them, without [[output.groups]])
+            let snapshot: Vec<_> = prices.snapshot().iter().filter(|u| reporter.due(&u.symbol, wake)).cloned().collect();
+            let summary = reporter.summary_due(wake);
+            if snapshot.is_empty() && !summary {
+                continue;
+            }
+            let mut intervals: Vec<Duration> = snapshot.iter().map(|u| reporter.interval(&u.symbol)).collect();
+            if summary {
+                intervals.push(reporter.summary_interval());
+            }
+            intervals.sort();
+            intervals.dedup();
+            let intervals: Vec<String> = intervals.into_iter().map(|i| humantime::format_duration(i).to_string()).collect();
+            (snapshot, summary, format!("every {}", intervals.join(", ")))
+        };
+
+        println!("\n==== Latest Prices ({}) ====", heading);
+        for update in &snapshot {
+            // Print each symbol and its latest price (with its move since the last printout),
+            // plus the top of its order book when we have one,
+            // flagging prices from a feed that has gone silent
+            // and the price in the display currency next to the original
+            let movement = previous.get(&(update.exchange, update.symbol.clone())).map(|&p| Movement::between(p, update.price));
+            let price = styler.price(&format!("${}", update.price), movement);
+            let stale = if prices.is_stale(update.exchange, &update.symbol) { "  [STALE]" } else { "" };
+            let converted = store.fx().convert(update.price, &update.symbol).map(|c| format!("  ({})", c)).unwrap_or_default();
+            let line = match prices.top(update.exchange, &update.symbol) {
+                Some(TopOfBook { best_bid: Some(bid), best_ask: Some(ask), spread: Some(spread) }) => format!(
+                    "{} {}: {}{}  (bid {} / ask {}, spread {}){}",
+                    update.exchange, update.symbol, price, converted, bid, ask, spread, stale
+                ),
+                _ => format!("{} {}: {}{}{}", update.exchange, update.symbol, price, converted, stale),
+            };
+            println!("{}", styler.line(&line, movement));
+            // ...and how it has moved recently
+            if let Some(stats) = prices.stats(update.exchange, &update.symbol) {
+                println!("    {}", stats);
+            }
+            // ...and its market cap at this price, with [market]
+            if let Some(market) = store.market().info(&update.symbol, update.price) {
+                println!("    market {}", market);
+            }
+            // ...and how much of it traded, once trades are followed
+            if let Some(volumes) = store.volumes(update.exchange, &update.symbol, &session.trade_windows, SystemTime::now()).await
+                && !volumes.is_empty()
+            {
+                let volumes: Vec<String> = volumes.iter().map(|v| v.to_string()).collect();
+                println!("    volume {}", volumes.join("  "));
+            }
+            // ...and, for a perpetual, its mark price and funding rate
+            if let Some(funding) = store.funding(update.exchange, &update.symbol).await {
+                println!("    {}", funding);
+            }
+            // ...and its technical indicators, once there are enough candles
+            if let Some(indicators) = &session.indicators {
+                let readings: Vec<String> =
+                    indicators.readings(update.exchange, &update.symbol).iter().map(|r| r.to_string()).collect();
+                if !readings.is_empty() {
+                    println!("    {}", readings.join("  "));
                 }
             }
-            Err(e) => {
-                // Log any WebSocket errors that happen
-                eprintln!("WebSocket error: {}", e);
-                break; // Exit the loop on error (optional — you could reconnect instead)
+        }
+        previous.extend(snapshot.into_iter().map(|u| ((u.exchange, u.symbol), u.price)));
+        if summary && !session.watchlists.is_empty() {
+            println!("---- Watchlists ----");
+            for line in session.watchlists.summaries(store).await {
+                println!("{}", line);
             }
         }
+        if let (true, Some(portfolio)) = (summary, &session.portfolio) {
+            println!("---- Portfolio ----");
+            println!("{}", portfolio.value(store).await);
+        }
+        if let (true, Some(paper)) = (summary, &session.paper) {
+            println!("---- Paper trading ----");
+            println!("{}", paper.lock().unwrap().summary());
+        }
+        println!("===========================================\n");
     }
+}
 
-    Ok(())  // Signal successful execution to Rust
+// Between printouts, every Nth update of the `[output] every_nth` symbols, as it arrives
+// (runs until cancelled; does nothing without any)
+async fn print_every_nth(session: &Session, mut sampler: UpdateSampler, styler: Styler) -> io::Result<()> {
+    if sampler.is_empty() {
+        return std::future::pending().await;
+    }
+    let mut previous: HashMap<(&'static str, String), Decimal> = HashMap::new();
+    let mut updates = session.tracker.updates();
+    while let Some(update) = updates.next().await {
+        let Some(count) = sampler.sample(&update) else { continue };
+        let movement = previous.insert((update.exchange, update.symbol.clone()), update.price).map(|p| Movement::between(p, update.price));
+        let price = styler.price(&format!("${}", update.price), movement);
+        let line = format!("{} {}: {}  (update #{})", update.exchange, update.symbol, price, count);
+        println!("{}", styler.line(&line, movement));
+    }
+    Ok(())
+}
+
+// `serve`: run the tracker and the REST API with no terminal output
+#[cfg(feature = "api")]
+async fn serve(config: &Config, args: cli::ServeArgs) -> Result<(), Box<dyn Error>> {
+    let addr = args.addr.or(config.api.addr).unwrap_or_else(|| ([127, 0, 0, 1], 8080).into());
+    let session = start_session(config).await?;
+
+    // Ctrl-C stops accepting connections, lets in-flight requests finish, then shuts down
+    let ctrl_c = async {
+        let _ = signal::ctrl_c().await;
+        info!("Ctrl-C received, shutting down");
+    };
+    let store = session.tracker.store().clone();
+    let served = tokio::select! {
+        served = crabbycryptotracker::api::serve(addr, store, session.portfolio.clone(), session.indicators.clone(), ctrl_c) => served,
+        () = follow_symbols(config, &session) => Ok(()),  // Never finishes
+    };
+    session.shutdown().await;
+    served?;
+    Ok(())
+}
+
+// `export`: recorded history from the database or snapshot files, as CSV, JSON or Parquet
+fn export(config: &Config, args: cli::ExportArgs) -> Result<(), Box<dyn Error>> {
+    // Step 1: The ticks, oldest first
+    let ticks = recorded_ticks(config, "export", &args.from, args.symbol.clone(), args.since.as_deref(), args.until.as_deref())?;
+
+    // Step 2: Optionally candles instead
+    let table = match args.resample {
+        Some(interval) => Table::Candles(export::resample(&ticks, interval)),
+        None => Table::Ticks(ticks),
+    };
+
+    // Step 3: Write them out
+    let format = args.format.or_else(|| args.file.as_deref().and_then(Format::from_path)).unwrap_or(Format::Csv);
+    match &args.file {
+        Some(path) => {
+            let mut out = io::BufWriter::new(std::fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?);
+            table.write(format, &mut out)?;
+            out.flush()?;
+            info!(path = %path.display(), rows = table.len(), ?format, "Exported history");
+        }
+        None if format == Format::Parquet && io::stdout().is_terminal() => {
+            return Err("Parquet is binary: give a file name, or redirect stdout".into());
+        }
+        None => table.write(format, &mut io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+// `backtest`: the alert rules, alert script and paper trading rules over recorded history
+async fn backtest(config: &Config, args: cli::BacktestArgs) -> Result<(), Box<dyn Error>> {
+    // Step 1: What to test: the same rules (with the symbol groups') and script `track` would load
+    let mut rules = config.alerts.clone();
+    if let Some(path) = &config.alerts_file {
+        rules.extend(load_rule_file(path).map_err(|e| format!("{}: {}", path.display(), e))?);
+    }
+    let rules = groups::resolve(&config.symbol_groups, rules);
+    let engine = if rules.is_empty() { None } else { Some(AlertEngine::from_configs(rules)?) };
+    let script = ScriptRunner::load(&config.script)?;
+    if engine.is_none() && script.is_none() && config.paper.rules.is_empty() {
+        return Err("nothing to backtest: give alert rules (--alerts or [[alerts]]), a script (--script) or [[paper.rules]]".into());
+    }
+    let mut backtest = Backtest::new(config, engine, script)?;
+
+    // Step 2: The history, tick by tick or candle by candle, or a recording frame by frame
+    let (recordings, files): (Vec<_>, Vec<_>) = args.from.iter().cloned().partition(|path| recording::is_recording(path));
+    if recordings.is_empty() {
+        let ticks = recorded_ticks(config, "backtest", &files, args.symbol.clone(), args.since.as_deref(), args.until.as_deref())?;
+        info!(ticks = ticks.len(), "Backtesting");
+        match args.resample {
+            Some(interval) => backtest.run_candles(&export::resample(&ticks, interval)).await,
+            None => backtest.run(&ticks).await,
+        }
+    } else {
+        if !files.is_empty() || args.resample.is_some() {
+            return Err("--from recordings (made with --record) can't be mixed with snapshot files or --resample".into());
+        }
+        // The symbols `track` would follow, and whatever the rules need
+        let symbols = match &args.symbol {
+            Some(symbol) => BTreeSet::from([symbol.to_uppercase()]),
+            None => {
+                let mut symbols = backtest.rule_symbols();
+                symbols.extend(config.symbols.iter().cloned());
+                if config.symbols.is_empty() && config.symbols_file.exists() {
+                    symbols.extend(load_symbols_from_csv(&config.symbols_file).map_err(|e| format!("{}: {}", config.symbols_file.display(), e))?);
+                }
+                symbols
+            }
+        };
+        let filter = history_filter(None, args.since.as_deref(), args.until.as_deref())?;
+        let mut frames = Vec::new();
+        for path in &recordings {
+            let recorded = recording::read_recording(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            frames.extend(recorded.into_iter().filter(|f| filter.keeps_time(f.ts_ms as i64)));
+        }
+        info!(frames = frames.len(), symbols = symbols.len(), "Backtesting a recording");
+        backtest.run_frames(&frames, symbols).await;
+    }
+
+    // Step 3: The report, and the fills for a closer look
+    println!("{}", backtest.report());
+    if let Some(path) = &args.fills {
+        let rows = backtest.paper().export_csv(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        info!(path = %path.display(), rows, "Wrote the simulated fills");
+    }
+    Ok(())
+}
+
+// Recorded ticks for `export` and `backtest`, oldest first: from the database, or from
+// snapshot/NDJSON files when any are given
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+fn recorded_ticks(
+    config: &Config,
+    command: &str,
+    from: &[std::path::PathBuf],
+    symbol: Option<String>,
+    since: Option<&str>,
+    until: Option<&str>,
+) -> Result<Vec<export::Tick>, Box<dyn Error>> {
+    let filter = history_filter(symbol, since, until)?;
+    let mut ticks = Vec::new();
+    if from.is_empty() {
+        #[cfg(feature = "sqlite")]
+        {
+            let db = config.storage.path.as_ref().ok_or_else(|| format!("{} needs a database (--db, CRABBY_DB or [storage] path) or --from files", command))?;
+            ticks = export::read_database(db, &filter)?;
+        }
+        #[cfg(not(feature = "sqlite"))]
+        return Err(format!("this build has no SQLite support; {} from snapshot files with --from", command).into());
+    }
+    for path in from {
+        ticks.extend(export::read_file(path, &filter)?);
+    }
+    ticks.sort_by_key(|t| t.timestamp_ms);  // Rotated files come newest first; stable, so equal times keep file order
+    Ok(ticks)
+}
+
+// The --symbol, --since and --until of `export` and `backtest`
+fn history_filter(symbol: Option<String>, since: Option<&str>, until: Option<&str>) -> Result<Filter, Box<dyn Error>> {
+    let now = SystemTime::now();
+    Ok(Filter {
+        symbol,
+        since_ms: since.map(|t| export::parse_time(t, now)).transpose().map_err(|e| format!("--since {}", e))?,
+        until_ms: until.map(|t| export::parse_time(t, now)).transpose().map_err(|e| format!("--until {}", e))?,
+    })
+}
+
+// Everything a streaming mode starts. Keep it alive for as long as the mode runs:
+// dropping it stops the feeds and flushes any queued database writes, but
+// `shutdown` does it politely and prints a summary.
+struct Session {
+    tracker: PriceTracker,
+    portfolio: Option<Arc<Portfolio>>,
+    paper: Option<SharedPaper>,
+    trader: Option<Arc<control::Trader>>,  // Real orders, with --enable-trading
+    paper_export: Option<std::path::PathBuf>,  // Where to write the fills on shutdown
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]  // Only read back to persist open candles
+    candles: Option<SharedCandles>,
+    indicators: Option<Indicators>,  // Computed from `candles`
+    trade_windows: Vec<Duration>,    // Volume windows shown in the output; empty without --trades
+    watchlists: Watchlists,          // Summed up with the portfolio; empty without [watchlists]
+    #[cfg(feature = "sqlite")]
+    persist_candles: bool,  // Save still-open candles on shutdown too
+    #[cfg(feature = "sqlite")]
+    storage: Option<crabbycryptotracker::storage::Storage>,
+    #[cfg(feature = "tsdb")]
+    tsdb: Option<crabbycryptotracker::tsdb::TsdbSink>,
+    snapshots: Option<SnapshotWriter>,
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<crabbycryptotracker::mqtt::MqttPublisher>,
+    #[cfg(feature = "redis")]
+    redis: Option<crabbycryptotracker::redis::RedisSink>,
+    #[cfg(feature = "kafka")]
+    kafka: Option<crabbycryptotracker::kafka::KafkaSink>,
+    recorder: Option<Recorder>,
+    started: Instant,
+}
+
+impl Session {
+    // Close the WebSockets, flush pending writes and print what this session saw
+    async fn shutdown(mut self) {
+        // Step 1: Stop the feeds so no new updates arrive while we flush
+        self.tracker.shutdown().await;
+
+        // Step 2: Save the candles still in progress and wait for the writer threads to finish
+        #[cfg(feature = "sqlite")]
+        if let Some(storage) = self.storage.take() {
+            if let (true, Some(candles)) = (self.persist_candles, &self.candles) {
+                for candle in candles.lock().unwrap().open_candles() {
+                    storage.record_candle(candle);
+                }
+            }
+            let dropped = storage.dropped();
+            storage.close();
+            info!(dropped, "Flushed pending writes to the database");
+        }
+        #[cfg(feature = "tsdb")]
+        if let Some(tsdb) = self.tsdb.take() {
+            let dropped = tsdb.close().await;
+            info!(dropped, "Flushed pending writes to the time-series database");
+        }
+        if let Some(snapshots) = self.snapshots.take() {
+            snapshots.close();
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = self.mqtt.take() {
+            mqtt.close().await;
+        }
+        #[cfg(feature = "redis")]
+        if let Some(redis) = self.redis.take() {
+            redis.close().await;
+        }
+        #[cfg(feature = "kafka")]
+        if let Some(kafka) = self.kafka.take() {
+            kafka.close().await;
+        }
+        if let Some(recorder) = self.recorder.take() {
+            recorder.close();
+        }
+
+        // Step 3: Final summary
+        let runtime = Duration::from_secs(self.started.elapsed().as_secs());
+        let store = self.tracker.store();
+        info!(runtime = %humantime::format_duration(runtime), updates = store.update_count(), "Session summary");
+        for update in store.snapshot().await {
+            info!(exchange = update.exchange, symbol = %update.symbol, price = %update.price, "Last price");
+        }
+        if let Some(portfolio) = &self.portfolio {
+            info!("Portfolio\n{}", portfolio.value(store).await);
+        }
+        if let Some(paper) = &self.paper {
+            let paper = paper.lock().unwrap();
+            info!("Paper trading\n{}", paper.summary());
+            if let Some(path) = &self.paper_export {
+                match paper.export_csv(path) {
+                    Ok(fills) => info!(path = %path.display(), fills, "Exported paper fills"),
+                    Err(e) => warn!(path = %path.display(), error = %e, "Couldn't export paper fills"),
+                }
+            }
+        }
+    }
+}
+
+// Holdings from the Coinbase account, when its API key is set (a holdings file takes precedence)
+async fn account_portfolio(config: &Config, network: &Network) -> Result<Option<Portfolio>, Box<dyn Error>> {
+    if config.account.api_key.is_none() {
+        return Ok(None);
+    }
+    #[cfg(feature = "account")]
+    {
+        let Some(account) = CoinbaseAccount::from_config(&config.account, network).map_err(|e| format!("[account] {}", e))? else {
+            return Ok(None);
+        };
+        let holdings = account.holdings(&config.account).await.map_err(|e| format!("Coinbase account: {}", e))?;
+        info!(holdings = holdings.len(), "Read holdings from the Coinbase account");
+        Ok(Some(Portfolio::new(holdings)))
+    }
+    #[cfg(not(feature = "account"))]
+    {
+        let _ = network;
+        warn!("A Coinbase API key is configured, but this build has no account support");
+        Ok(None)
+    }
+}
+
+// Shared setup for the streaming modes: symbols, exchanges, storage, alerts, feeds
+async fn start_session(config: &Config) -> Result<Session, Box<dyn Error>> {
+    // Proxy and CA certificates for every outbound connection (files are read here, once)
+    let network = Network::from_config(&config.network).map_err(|e| format!("[network] {}", e))?;
+
+    // Step 1: Symbols from the config/flags, or else from the CSV file
+    let mut product_ids = if config.symbols.is_empty() {
+        load_symbols_from_csv(&config.symbols_file)
+            .map_err(|e| format!("{}: {}", config.symbols_file.display(), e))?
+    } else {
+        config.symbols.clone()
+    };
+    let listed = product_ids.clone();
+
+    // Holdings need prices too, so track their symbols even if they weren't listed
+    let portfolio = match &config.portfolio_file {
+        Some(path) => Some(Portfolio::from_csv(path).map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => account_portfolio(config, &network).await?,
+    };
+    if let Some(portfolio) = &portfolio {
+        for holding in portfolio.holdings() {
+            if !product_ids.contains(&holding.symbol) {
+                product_ids.push(holding.symbol.clone());
+            }
+        }
+        info!(holdings = portfolio.holdings().len(), "Loaded portfolio");
+    }
+    let portfolio = portfolio.map(Arc::new);
+
+    // ...and so do the paper trading rules
+    let paper = PaperTrader::from_config(&config.paper);
+    for symbol in paper.iter().flat_map(|p| p.rule_symbols()) {
+        if !product_ids.contains(&symbol) {
+            product_ids.push(symbol);
+        }
+    }
+
+    // ...and the DCA plans
+    for plan in &config.dca.plans {
+        let symbol = plan.symbol.trim().to_uppercase();
+        if !product_ids.contains(&symbol) {
+            product_ids.push(symbol);
+        }
+    }
+
+    // ...and the symbols named in the alert script
+    let script = ScriptRunner::load(&config.script)?;
+    for symbol in script.iter().flat_map(|s| s.symbols()) {
+        if !product_ids.contains(&symbol) {
+            product_ids.push(symbol);
+        }
+    }
+
+    // The DEX pools' symbols, so the exchanges quote them too and the prices can be compared
+    if config.exchanges.iter().any(|n| n.trim().eq_ignore_ascii_case("uniswap")) {
+        for pool in &config.dex.pools {
+            let symbol = pool.symbol.to_uppercase();
+            if !product_ids.contains(&symbol) {
+                product_ids.push(symbol);
+            }
+        }
+    }
+
+    // ...and the watchlists' members
+    let watchlists = Watchlists::new(&config.watchlists);
+    for symbol in watchlists.all_symbols() {
+        if !product_ids.contains(&symbol) {
+            product_ids.push(symbol);
+        }
+    }
+
+    // The depeg monitor's stablecoins, likewise
+    let mut preset = Vec::new();
+    if config.depeg.enabled {
+        for symbol in &config.depeg.symbols {
+            if !product_ids.contains(symbol) {
+                product_ids.push(symbol.clone());
+                preset.push(symbol.clone());
+            }
+        }
+    }
+    // Synthetic pairs are derived from the others rather than subscribed, unless listed themselves
+    let synthetic_symbols: Vec<String> = config.synthetic.symbols.iter().map(|s| synthetic::normalize(s)).collect();
+    product_ids.retain(|s| !synthetic_symbols.contains(s) || listed.contains(s));
+    info!(symbols = ?product_ids, "Tracking symbols");
+
+    // Step 2: Exchange connectors (names were checked by Config::validate)
+    let exchanges: Vec<Box<dyn Exchange>> = config.exchanges.iter().filter_map(|n| exchange::connector(n, &config.dex)).collect();
+
+    // A replay doesn't talk to the exchanges at all, so it skips the product check and backfill
+    let replay = config.recording.replay.as_ref();
+    if let Some(path) = replay {
+        std::fs::metadata(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    // Catch typos before subscribing: an unknown symbol would otherwise just never get prices
+    #[cfg(feature = "validate")]
+    if replay.is_none() && config.unknown_symbols != crabbycryptotracker::config::UnknownSymbols::Ignore {
+        let mut unknown = crabbycryptotracker::products::check_symbols(&exchanges, &product_ids, &network).await;
+        // Not every venue lists every stablecoin pair; the preset's missing ones are just left out
+        for (exchange, symbols) in &mut unknown {
+            let skipped: Vec<String> = symbols.extract_if(.., |s| preset.contains(s)).collect();
+            if !skipped.is_empty() {
+                warn!(exchange, symbols = %skipped.join(", "), "Depeg monitor symbols not listed, skipping them");
+                product_ids.retain(|s| !skipped.contains(s));
+            }
+        }
+        unknown.retain(|(_, symbols)| !symbols.is_empty());
+        for (exchange, symbols) in &unknown {
+            tracing::error!(exchange, symbols = %symbols.join(", "), "Unknown symbols");
+        }
+        if !unknown.is_empty() {
+            if config.unknown_symbols == crabbycryptotracker::config::UnknownSymbols::Fail {
+                return Err("some symbols aren't listed by the exchange (use --unknown-symbols skip to track the rest)".into());
+            }
+            product_ids.retain(|s| !unknown.iter().any(|(_, symbols)| symbols.contains(s)));
+            warn!(symbols = ?product_ids, "Skipping the unknown symbols");
+        }
+    }
+
+    // Step 3: Create the tracker (nothing is connected yet)
+    let mut tracker = PriceTracker::with_exchanges(exchanges);
+    tracker.use_network(network.clone());
+    if let Some(path) = replay {
+        tracker.replay_from(path.clone(), config.recording.speed);
+    }
+    let recorder = match &config.recording.record {
+        Some(path) => {
+            let recorder = Recorder::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            tracker.record_to(recorder.sink());
+            info!(path = %path.display(), "Recording raw messages");
+            Some(recorder)
+        }
+        None => None,
+    };
+
+    // Display currency: fetch the exchange rate before anything is printed, then keep it fresh
+    if let Some(currency) = &config.output.currency {
+        #[cfg(feature = "fx")]
+        crabbycryptotracker::fx::start_refresh(tracker.store().fx().clone(), currency.clone(), config.output.fx_refresh, network.clone()).await;
+        #[cfg(not(feature = "fx"))]
+        warn!(%currency, "A display currency is set, but this build can't fetch exchange rates");
+    }
+
+    // Market cap and rank: supply and volume from CoinGecko, refreshed in the background
+    if config.market.enabled {
+        #[cfg(feature = "market")]
+        crabbycryptotracker::market::start_refresh(tracker.store().market().clone(), config.market.clone(), tracker.watch_symbols(), network.clone());
+        #[cfg(not(feature = "market"))]
+        warn!("Market data is enabled, but this build can't fetch it");
+    }
+
+    // Step 4: Recent history from the Coinbase REST API, so nothing starts from zero
+    #[cfg(feature = "backfill")]
+    let history = if config.backfill.enabled && replay.is_none() && config.exchanges.iter().any(|e| e.eq_ignore_ascii_case("coinbase")) {
+        crabbycryptotracker::backfill::backfill(&product_ids, config.backfill.lookback, &network).await
+    } else {
+        Vec::new()
+    };
+    #[cfg(not(feature = "backfill"))]
+    let history: Vec<crabbycryptotracker::backfill::History> = Vec::new();
+    for h in &history {
+        if let (Some(update), Some((high, low))) = (h.latest_update(), h.high_low()) {
+            info!(symbol = %h.symbol, candles = h.candles.len(), last = %update.price, %high, %low, "Backfilled");
+            tracker.store().seed(update).await;
+            tracker.store().seed_history(&h.price_points()).await;
+        }
+    }
+
+    // Step 5: With a database path, write every update to SQLite
+    #[cfg(feature = "sqlite")]
+    let storage = match &config.storage.path {
+        Some(path) => {
+            use crabbycryptotracker::storage::{Storage, StorageConfig};
+            let storage = Storage::open(StorageConfig {
+                path: path.clone(),
+                batch_size: config.storage.batch_size,
+                flush_interval: config.storage.flush_interval,
+                queue: config.storage.queue,
+                queue_timeout: config.storage.queue_timeout,
+            })?;
+            storage.attach(tracker.store());
+            info!(path = %path.display(), "Recording price history");
+            Some(storage)
+        }
+        None => None,
+    };
+
+    // ...and with a time-series database URL, to InfluxDB or TimescaleDB as well
+    #[cfg(feature = "tsdb")]
+    let tsdb = match crabbycryptotracker::tsdb::TsdbSink::from_config(&config.tsdb, &network).map_err(|e| format!("[tsdb] {}", e))? {
+        Some(sink) => {
+            sink.attach(tracker.store());
+            // Leave the password out of the log
+            let mut url = url::Url::parse(config.tsdb.url.as_deref().unwrap_or_default())?;
+            let _ = url.set_password(None);
+            info!(%url, "Writing ticks to the time-series database");
+            Some(sink)
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "tsdb"))]
+    if config.tsdb.url.is_some() {
+        warn!("A time-series database is configured, but this build has no InfluxDB/TimescaleDB support");
+    }
+
+    // Step 6: Optionally append price snapshots to a rotating CSV file
+    let snapshots = match &config.snapshots.path {
+        Some(path) => {
+            let writer = SnapshotWriter::open(SnapshotConfig {
+                path: path.clone(),
+                schedule: config.snapshots.every,
+                max_rows: config.snapshots.max_rows,
+                keep: config.snapshots.keep,
+            })
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+            writer.attach(tracker.store());
+            info!(path = %path.display(), "Writing price snapshots");
+            Some(writer)
+        }
+        None => None,
+    };
+
+    // Bind the WebSocket relay now so a busy port fails at startup; it starts serving below
+    let relay = match config.relay.addr {
+        Some(addr) => Some(Relay::bind(addr).await.map_err(|e| format!("relay on {}: {}", addr, e))?),
+        None => None,
+    };
+    // ...and the gRPC API
+    #[cfg(feature = "grpc")]
+    let grpc = match config.grpc.addr {
+        Some(addr) => Some(crabbycryptotracker::grpc::GrpcServer::bind(addr).await.map_err(|e| format!("gRPC API on {}: {}", addr, e))?),
+        None => None,
+    };
+    #[cfg(not(feature = "grpc"))]
+    if config.grpc.addr.is_some() {
+        warn!("A gRPC address is configured, but this build has no gRPC support");
+    }
+
+    // Step 7: Aggregate ticks into OHLCV candles, optionally saving closed ones to the database
+    // and passing them on to relay clients
+    let candles = if config.candles.enabled {
+        let mut aggregator = CandleAggregator::new(config.candles.intervals.clone(), config.candles.keep);
+        for h in &history {
+            aggregator.seed(&h.candles);
+        }
+        let shared: SharedCandles = Arc::new(Mutex::new(aggregator));
+        let mut sinks: Vec<CandleSink> = Vec::new();
+        #[cfg(feature = "sqlite")]
+        if let (true, Some(storage)) = (config.candles.persist, &storage) {
+            sinks.push(Box::new(storage.candle_sink()));
+        }
+        #[cfg(feature = "tsdb")]
+        if let (true, Some(tsdb)) = (config.tsdb.candles, &tsdb) {
+            sinks.push(Box::new(tsdb.candle_sink()));
+        }
+        if let Some(relay) = &relay {
+            sinks.push(Box::new(relay.candle_sink()));
+        }
+        spawn_candles(Arc::clone(&shared), tracker.store(), move |candle: &Candle| sinks.iter().for_each(|sink| sink(candle)));
+        Some(shared)
+    } else {
+        None
+    };
+    let indicators = candles.as_ref().map(|c| Indicators::from_config(Arc::clone(c), &config.indicators));
+
+    // Real orders on the Coinbase account, only with --enable-trading
+    let trader = start_trader(config, &network)?;
+
+    // Recurring purchases: simulated into the holdings (an empty portfolio when there is
+    // none, so they show up), or real orders through the trader
+    let portfolio = if config.dca.plans.is_empty() {
+        portfolio
+    } else {
+        let portfolio = portfolio.unwrap_or_else(|| Arc::new(Portfolio::new(Vec::new())));
+        #[allow(unused_mut)]  // Only made live when trading is compiled in
+        let mut scheduler = DcaScheduler::new(&config.dca, Arc::clone(&portfolio));
+        if config.dca.live {
+            match &trader {
+                #[cfg(feature = "account")]
+                Some(trader) => scheduler = scheduler.live(Arc::clone(trader) as Arc<dyn crabbycryptotracker::dca::OrderPlacer>),
+                _ => return Err("[dca] live = true needs --enable-trading".into()),
+            }
+        } else if let Some(path) = &config.portfolio_file {
+            scheduler = scheduler.save_holdings_to(path);
+        }
+        info!(plans = config.dca.plans.len(), live = config.dca.live, audit_log = ?config.dca.audit_log, "Recurring purchases");
+        spawn_dca(scheduler, tracker.store());
+        Some(portfolio)
+    };
+
+    // Cross prices implied from the tracked pairs, put back into the store as "derived"
+    if !synthetic_symbols.is_empty() {
+        let routes = synthetic_symbols
+            .iter()
+            .map(|s| Route::find(s, &product_ids).ok_or_else(|| format!("[synthetic] {} can't be priced from the tracked pairs ({})", s, product_ids.join(", "))))
+            .collect::<Result<Vec<_>, _>>()?;
+        for route in &routes {
+            info!(%route, "Deriving synthetic price");
+        }
+        spawn_synthetic(SyntheticPrices::new(routes), tracker.store());
+    }
+
+    // The end-of-day report lists every alert that fired, so it gets them all too
+    let alert_log = config.daily_report.enabled.then(AlertLog::default);
+
+    // Step 8: Alert rules from the config plus the optional separate alerts file, and the symbol groups'
+    let mut rules = config.alerts.clone();
+    if let Some(path) = &config.alerts_file {
+        rules.extend(load_rule_file(path).map_err(|e| format!("{}: {}", path.display(), e))?);
+    }
+    let rules = groups::resolve(&config.symbol_groups, rules);
+    if !rules.is_empty() {
+        let mut engine = AlertEngine::from_configs(rules)?;
+        if engine.rules().iter().any(|r| r.uses_indicator()) {
+            // Rules from --alerts files weren't seen by Config::validate
+            engine.use_indicators(indicators.clone().ok_or("indicator alerts need candles ([candles] enabled = true)")?);
+        }
+        // The volumes themselves are read from the store for each update
+        if engine.rules().iter().any(|r| r.uses_volume()) && !config.trades.enabled {
+            return Err("volume alerts need the trade channel (--trades or [trades] enabled = true)".into());
+        }
+        if engine.rules().iter().any(|r| r.uses_portfolio()) {
+            engine.use_portfolio(portfolio.clone().ok_or("take profit and stop loss alerts need the holdings (portfolio_file or [account])")?);
+        }
+        for h in &history {
+            engine.warm_up(&h.price_points(), std::time::Instant::now());
+        }
+        if let Some(path) = &config.alert_state {
+            let restored = engine.persist_to(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            info!(path = %path.display(), restored, "Keeping alert rule state");
+        }
+        info!(rules = engine.rules().len(), "Loaded alert rules");
+        let desktop = engine.rules().iter().any(|r| r.desktop);
+        #[allow(unused_mut)]  // Only extended when trading is compiled in
+        let mut notifiers = notifiers(config, &network, desktop, alert_log.as_ref());
+        if engine.rules().iter().any(|r| r.trade.is_some()) {
+            match &trader {
+                #[cfg(feature = "account")]
+                Some(trader) => notifiers.push(Box::new(trader.notifier())),
+                _ => info!("Alert rules with a `trade` action only notify; start with --enable-trading to place their orders"),
+            }
+        }
+        spawn_alerts(engine, tracker.store(), notifiers);
+    }
+
+    // Conditions written as a script, run every interval and reloaded when the file changes
+    if let Some(mut runner) = script {
+        if runner.uses_indicators() {
+            runner.use_indicators(indicators.clone().ok_or("scripts calling sma/ema/rsi/macd need candles ([candles] enabled = true)")?);
+        }
+        info!(path = %runner.path().display(), interval = ?config.script.interval, "Running alert script");
+        #[allow(unused_mut)]  // Only extended when trading is compiled in
+        let mut notifiers = notifiers(config, &network, false, alert_log.as_ref());
+        if runner.places_orders() {
+            match &trader {
+                #[cfg(feature = "account")]
+                Some(trader) => notifiers.push(Box::new(trader.notifier())),
+                _ => info!("The script's buy() and sell() only notify; start with --enable-trading to place their orders"),
+            }
+        }
+        spawn_script(runner, tracker.store(), config.script.interval, notifiers);
+    }
+
+    // Cross-exchange spreads, delivered like alerts
+    if let Some(detector) = ArbitrageDetector::from_config(&config.arbitrage) {
+        if config.exchanges.len() < 2 {
+            warn!("Arbitrage detection needs at least two exchanges (e.g. --exchange coinbase,kraken)");
+        }
+        info!(threshold_pct = %detector.threshold_pct(), "Watching for arbitrage spreads");
+        spawn_arbitrage(detector, tracker.store(), notifiers(config, &network, config.arbitrage.desktop, alert_log.as_ref()));
+    }
+
+    // Stablecoins drifting off 1.00, delivered like alerts too
+    if let Some(monitor) = DepegMonitor::from_config(&config.depeg) {
+        info!(symbols = ?monitor.symbols(), threshold_pct = %config.depeg.threshold_pct, "Watching stablecoin pegs");
+        spawn_depeg(monitor, tracker.store(), notifiers(config, &network, config.depeg.desktop, alert_log.as_ref()));
+    }
+
+    // At the set time each day, a summary of the day to a file (and the webhooks and Telegram)
+    if let Some(alert_log) = alert_log {
+        let report = &config.daily_report;
+        let notifiers = if report.notify { notifiers(config, &network, false, None) } else { Vec::new() };
+        spawn_daily_report(DailyReporter::new(report, alert_log, portfolio.clone(), notifiers), tracker.store());
+    }
+
+    // Simulated orders, filled against the same updates
+    let paper = paper.map(|trader| {
+        info!(cash = %config.paper.cash, currency = %config.paper.currency, rules = config.paper.rules.len(), "Paper trading");
+        let shared: SharedPaper = Arc::new(Mutex::new(trader));
+        spawn_paper(Arc::clone(&shared), tracker.store());
+        shared
+    });
+
+    // Every update to an MQTT broker, for home automation
+    #[cfg(feature = "mqtt")]
+    let mqtt = crabbycryptotracker::mqtt::MqttPublisher::from_config(&config.mqtt).map_err(|e| format!("[mqtt] {}", e))?;
+    #[cfg(feature = "mqtt")]
+    if let Some(publisher) = &mqtt {
+        publisher.attach(tracker.store());
+        info!(prefix = %config.mqtt.topic_prefix, qos = config.mqtt.qos, retain = config.mqtt.retain, "Publishing prices over MQTT");
+    }
+    #[cfg(not(feature = "mqtt"))]
+    if config.mqtt.url.is_some() {
+        warn!("An MQTT broker is configured, but this build has no MQTT support");
+    }
+
+    // ...and to Redis, for services that just want to GET the latest price
+    #[cfg(feature = "redis")]
+    let redis = crabbycryptotracker::redis::RedisSink::from_config(&config.redis).map_err(|e| format!("[redis] {}", e))?;
+    #[cfg(feature = "redis")]
+    if let Some(sink) = &redis {
+        sink.attach(tracker.store());
+        info!(keys = %format!("{}:{{symbol}}", config.redis.key_prefix), channel = %config.redis.channel, "Writing prices to Redis");
+    }
+    #[cfg(not(feature = "redis"))]
+    if config.redis.url.is_some() {
+        warn!("A Redis server is configured, but this build has no Redis support");
+    }
+
+    // ...and to a Kafka topic, for streaming pipelines
+    #[cfg(feature = "kafka")]
+    let kafka = crabbycryptotracker::kafka::KafkaSink::from_config(&config.kafka).map_err(|e| format!("[kafka] {}", e))?;
+    #[cfg(feature = "kafka")]
+    if let Some(sink) = &kafka {
+        sink.attach(tracker.store());
+        info!(brokers = %config.kafka.brokers.join(","), topic = %config.kafka.topic, format = ?config.kafka.format, "Producing prices to Kafka");
+    }
+    #[cfg(not(feature = "kafka"))]
+    if !config.kafka.brokers.is_empty() {
+        warn!("Kafka is configured, but this build has no Kafka support");
+    }
+
+    if let Some(relay) = relay {
+        tokio::spawn(relay.run(tracker.store().clone()));
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(grpc) = grpc {
+        tokio::spawn(grpc.run(tracker.store().clone()));
+    }
+
+    // The Telegram bot answers /price and /portfolio in its own task, from the same store
+    if config.telegram.token.is_some() {
+        #[cfg(feature = "telegram")]
+        if let Some(bot) = crabbycryptotracker::telegram::TelegramBot::from_config(&config.telegram, &network) {
+            tokio::spawn(bot.run(tracker.store().clone(), portfolio.clone(), indicators.clone()));
+        }
+        #[cfg(not(feature = "telegram"))]
+        warn!("A Telegram bot is configured, but this build has no Telegram support");
+    }
+
+    // Step 9: Start tracking; each exchange gets its own reconnecting feed task
+    tracker.track_order_books(config.order_books);
+    tracker.track_trades(config.trades.enabled);
+    tracker.resync_on_gap(config.resync_on_gap);
+    tracker.store().keep_ticks(TickLimits { max_ticks: config.history.ticks, max_age: config.history.max_age }).await;
+    tracker.symbols_per_connection((config.symbols_per_connection > 0).then_some(config.symbols_per_connection));
+    tracker.detect_stale_feeds((!config.stale_after.is_zero()).then_some(config.stale_after));
+    tracker.subscribe(&product_ids);
+    Ok(Session {
+        tracker,
+        portfolio,
+        paper,
+        trader,
+        paper_export: config.paper.export.clone(),
+        candles,
+        indicators,
+        trade_windows: if config.trades.enabled { config.trades.windows.clone() } else { Vec::new() },
+        watchlists,
+        #[cfg(feature = "sqlite")]
+        persist_candles: config.candles.persist,
+        #[cfg(feature = "sqlite")]
+        storage,
+        #[cfg(feature = "tsdb")]
+        tsdb,
+        snapshots,
+        #[cfg(feature = "mqtt")]
+        mqtt,
+        #[cfg(feature = "redis")]
+        redis,
+        #[cfg(feature = "kafka")]
+        kafka,
+        recorder,
+        started: Instant::now(),
+    })
+}
+
+// The trader for --enable-trading: orders are checked against the [trading] limits and,
+// while `dry_run` is on (the default), only logged
+#[cfg(feature = "account")]
+fn start_trader(config: &Config, network: &Network) -> Result<Option<Arc<control::Trader>>, Box<dyn Error>> {
+    if !config.trading.enabled {
+        return Ok(None);
+    }
+    let account = CoinbaseAccount::from_config(&config.account, network)
+        .map_err(|e| format!("[account] {}", e))?
+        .ok_or("--enable-trading needs the Coinbase API key and secret ([account])")?;
+    let limits = (config.trading.max_order_value, config.trading.max_order_quantity);
+    if config.trading.dry_run {
+        warn!(max_order_value = %limits.0, max_order_quantity = ?limits.1, "Trading enabled as a dry run: orders are only logged");
+    } else {
+        warn!(max_order_value = %limits.0, max_order_quantity = ?limits.1, "Trading enabled: orders are sent to Coinbase");
+    }
+    Ok(Some(Arc::new(control::Trader::new(config.trading.clone(), account))))
+}
+
+#[cfg(not(feature = "account"))]
+fn start_trader(config: &Config, _network: &Network) -> Result<Option<Arc<control::Trader>>, Box<dyn Error>> {
+    if config.trading.enabled {
+        return Err("--enable-trading: this build has no trading support".into());
+    }
+    Ok(None)
+}
+
+// Where fired alerts go: always the console, plus webhooks, Telegram and desktop
+// notifications when configured, and the daily report's list of them
+#[allow(unused_variables)]  // `network` is only used by webhooks and Telegram, when compiled in
+fn notifiers(config: &Config, network: &Network, desktop: bool, log: Option<&AlertLog>) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(ConsoleNotifier)];
+    if let Some(log) = log {
+        notifiers.push(Box::new(log.clone()));
+    }
+    let routed = config.symbol_groups.values().any(|group| !group.webhooks.is_empty());
+    if !config.webhooks.is_empty() || routed {
+        #[cfg(feature = "webhook")]
+        {
+            use crabbycryptotracker::notify::{GroupRouter, WebhookNotifier};
+            let global = (!config.webhooks.is_empty()).then(|| Box::new(WebhookNotifier::new(config.webhooks.clone(), network)) as Box<dyn Notifier>);
+            if routed {
+                // Groups with their own webhooks take their symbols' alerts off the global ones
+                let mut router = GroupRouter::new(global);
+                for group in config.symbol_groups.values().filter(|group| !group.webhooks.is_empty()) {
+                    router.route(&group.symbols, Box::new(WebhookNotifier::new(group.webhooks.clone(), network)));
+                }
+                notifiers.push(Box::new(router));
+            } else {
+                notifiers.extend(global);
+            }
+        }
+        #[cfg(not(feature = "webhook"))]
+        warn!("Webhooks are configured, but this build has no webhook support");
+    }
+    #[cfg(feature = "telegram")]
+    if config.telegram.alerts
+        && let Some(bot) = crabbycryptotracker::telegram::TelegramBot::from_config(&config.telegram, network)
+    {
+        notifiers.push(Box::new(bot.notifier()));
+    }
+    if desktop {
+        #[cfg(feature = "desktop")]
+        notifiers.push(Box::new(crabbycryptotracker::notify::DesktopNotifier::new()));
+        #[cfg(not(feature = "desktop"))]
+        warn!("Desktop notifications are configured, but this build has no desktop support");
+    }
+    notifiers
+}
+
+// Serve the REST API in the background, logging if it stops
+#[cfg(feature = "api")]
+fn spawn_api(addr: std::net::SocketAddr, session: &Session) {
+    let store = session.tracker.store().clone();
+    let (portfolio, indicators) = (session.portfolio.clone(), session.indicators.clone());
+    tokio::spawn(async move {
+        // Runs until the process exits; `track` shuts the rest down on Ctrl-C
+        if let Err(e) = crabbycryptotracker::api::serve(addr, store, portfolio, indicators, std::future::pending()).await {
+            tracing::error!(error = %e, "REST API stopped");
+        }
+    });
 }
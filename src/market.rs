@@ -0,0 +1,265 @@
+// Market cap and rank next to the live price: circulating supply, 24h volume and market
+// cap rank per asset from a metadata provider (CoinGecko), refreshed every few minutes.
+//
+//     [market]
+//     enabled = true
+//     refresh = "5m"
+//     ids = { BTC = "bitcoin" }   # CoinGecko ids, for tickers it might get wrong
+//
+// No exchange feed sends supply, so it comes from CoinGecko's /coins/markets; the market
+// cap is then that supply times the live exchange price, so it moves with every tick
+// instead of with CoinGecko's own (delayed) figure. Assets are matched by the base of
+// the symbol (BTC for BTC-USD): by ticker, where CoinGecko picks its top coin for it,
+// or by the id given in `ids`.
+//
+// The figures live in the price store, like the display currency rate, so every output
+// can show them. Fetching needs the "market" feature (reqwest).
+
+use std::{
+    collections::{BTreeSet, HashMap},  // Tracked symbols; figures per base asset
+    fmt,                               // One-line summary for the terminal output
+    sync::{Arc, RwLock},               // Shared by the refresh task and every reader
+    time::SystemTime,                  // When the figures were fetched
+};
+#[cfg(feature = "market")]
+use std::{error::Error, time::Duration};
+
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Serialize;
+use serde_json::Value;
+#[cfg(feature = "market")]
+use tokio::sync::watch;                // The tracker's symbols, which can change while running
+#[cfg(feature = "market")]
+use tracing::{info, warn};
+
+#[cfg(feature = "market")]
+use crate::config::MarketConfig;
+use crate::watchlist::compact;
+
+// What the provider knows about one asset
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetInfo {
+    pub coin_id: String,                     // The provider's id, e.g. "bitcoin"
+    pub rank: Option<u32>,                   // By market cap; None for unranked coins
+    pub circulating_supply: Option<Decimal>,
+    pub volume_24h_usd: Option<Decimal>,     // Across every venue the provider follows
+    pub fetched_at: SystemTime,
+}
+
+// The figures for one symbol at its live price
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MarketInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub market_cap: Option<Decimal>,         // Price times circulating supply, in the quote currency
+    #[serde(rename = "market_cap_rank", skip_serializing_if = "Option::is_none")]
+    pub rank: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circulating_supply: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_24h_usd: Option<Decimal>,
+    #[serde(skip)]
+    pub quote: String,                       // What the market cap is in, e.g. "USD"
+}
+
+// "#1  cap 1.28T USD  supply 19.70M  vol 24h $32.10B"
+impl fmt::Display for MarketInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(rank) = self.rank {
+            parts.push(format!("#{}", rank));
+        }
+        if let Some(cap) = self.market_cap {
+            parts.push(format!("cap {} {}", compact(cap), self.quote));
+        }
+        if let Some(supply) = self.circulating_supply {
+            parts.push(format!("supply {}", compact(supply)));
+        }
+        if let Some(volume) = self.volume_24h_usd {
+            parts.push(format!("vol 24h ${}", compact(volume)));
+        }
+        f.write_str(&parts.join("  "))
+    }
+}
+
+// Every asset's figures, keyed by base asset ("BTC"). Cheap to clone, like `FxRates`.
+#[derive(Debug, Clone, Default)]
+pub struct MarketData {
+    assets: Arc<RwLock<HashMap<String, AssetInfo>>>,
+}
+
+impl MarketData {
+    // Replace the figures of the assets in `assets`, keeping the others
+    pub fn update(&self, assets: HashMap<String, AssetInfo>) {
+        self.assets.write().unwrap().extend(assets);
+    }
+
+    pub fn get(&self, base: &str) -> Option<AssetInfo> {
+        self.assets.read().unwrap().get(base).cloned()
+    }
+
+    // The figures for `symbol` at `price`, or None when the provider hasn't got its asset
+    pub fn info(&self, symbol: &str, price: Decimal) -> Option<MarketInfo> {
+        let (base, quote) = symbol.split_once('-')?;
+        let asset = self.get(base)?;
+        Some(MarketInfo {
+            market_cap: asset.circulating_supply.map(|supply| (supply * price).round_dp(2)),
+            rank: asset.rank,
+            circulating_supply: asset.circulating_supply,
+            volume_24h_usd: asset.volume_24h_usd,
+            quote: quote.to_string(),
+        })
+    }
+}
+
+// The base assets of `symbols`, once each: BTC for both BTC-USD and BTC-EUR
+pub fn base_assets(symbols: &BTreeSet<String>) -> Vec<String> {
+    let bases: BTreeSet<&str> = symbols.iter().filter_map(|s| s.split_once('-')).map(|(base, _)| base).collect();
+    bases.into_iter().map(str::to_string).collect()
+}
+
+// A /coins/markets response: [{"id":"bitcoin","symbol":"btc","market_cap_rank":1,
+// "circulating_supply":19700000.0,"total_volume":32100000000,...}, ...]. Entries are
+// keyed by the base asset `ids` maps to their id, or else by their ticker; when several
+// coins share a ticker, the best ranked one wins.
+pub fn parse_markets(body: &Value, ids: &HashMap<String, String>) -> HashMap<String, AssetInfo> {
+    let mut assets: HashMap<String, AssetInfo> = HashMap::new();
+    for coin in body.as_array().map(Vec::as_slice).unwrap_or_default() {
+        let (Some(id), Some(ticker)) = (coin["id"].as_str(), coin["symbol"].as_str()) else {
+            continue;
+        };
+        let base = match ids.iter().find(|(_, wanted)| *wanted == id) {
+            Some((base, _)) => base.clone(),
+            None if ids.contains_key(&ticker.to_uppercase()) => continue,  // Not the coin that was asked for
+            None => ticker.to_uppercase(),
+        };
+        let number = |field: &str| coin[field].as_f64().and_then(Decimal::from_f64).map(|d| d.round_dp(2).normalize());
+        let info = AssetInfo {
+            coin_id: id.to_string(),
+            rank: coin["market_cap_rank"].as_u64().and_then(|r| u32::try_from(r).ok()),
+            circulating_supply: number("circulating_supply").filter(|s| !s.is_zero()),
+            volume_24h_usd: number("total_volume"),
+            fetched_at: SystemTime::now(),
+        };
+        let better = |old: &AssetInfo| info.rank.is_some_and(|new| old.rank.is_none_or(|old| new < old));
+        if assets.get(&base).is_none_or(better) {
+            assets.insert(base, info);
+        }
+    }
+    assets
+}
+
+// Fetch the figures for `bases`: those with a configured id by id, the rest by ticker
+#[cfg(feature = "market")]
+pub async fn fetch(client: &reqwest::Client, config: &MarketConfig, bases: &[String]) -> Result<HashMap<String, AssetInfo>, Box<dyn Error>> {
+    let ids: HashMap<String, String> = config.ids.iter().map(|(base, id)| (base.trim().to_uppercase(), id.trim().to_string())).collect();
+    let (by_id, by_ticker): (Vec<&String>, Vec<&String>) = bases.iter().partition(|base| ids.contains_key(*base));
+
+    let mut assets = HashMap::new();
+    if !by_id.is_empty() {
+        let wanted: Vec<&str> = by_id.iter().map(|base| ids[*base].as_str()).collect();
+        assets.extend(parse_markets(&get_markets(client, config, "ids", &wanted.join(",")).await?, &ids));
+    }
+    if !by_ticker.is_empty() {
+        let wanted: Vec<String> = by_ticker.iter().map(|base| base.to_lowercase()).collect();
+        assets.extend(parse_markets(&get_markets(client, config, "symbols", &wanted.join(",")).await?, &ids));
+    }
+    Ok(assets)
+}
+
+// One /coins/markets request, with the API key header the plan wants (demo or pro)
+#[cfg(feature = "market")]
+async fn get_markets(client: &reqwest::Client, config: &MarketConfig, key: &str, value: &str) -> Result<Value, Box<dyn Error>> {
+    let mut url = url::Url::parse(&format!("{}/coins/markets", config.url.trim_end_matches('/')))?;
+    url.query_pairs_mut()
+        .append_pair("vs_currency", "usd")
+        .append_pair(key, value)
+        .append_pair("include_tokens", "top")  // One coin per ticker: the one with the largest cap
+        .append_pair("per_page", "250");
+    let mut request = client.get(url);
+    if let Some(api_key) = &config.api_key {
+        let header = if config.url.contains("pro-api.") { "x-cg-pro-api-key" } else { "x-cg-demo-api-key" };
+        request = request.header(header, api_key);
+    }
+    Ok(request.send().await?.error_for_status()?.json().await?)
+}
+
+// Keep `market` up to date for the tracked symbols: every `config.refresh`, and as soon
+// as a symbol is added whose asset has no figures yet. A failed fetch keeps the
+// previous figures.
+#[cfg(feature = "market")]
+pub fn start_refresh(market: MarketData, config: MarketConfig, mut symbols: watch::Receiver<BTreeSet<String>>, network: crate::net::Network) {
+    let client = network
+        .http_client()
+        .user_agent(concat!("crabbycryptotracker/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+
+    tokio::spawn(async move {
+        loop {
+            let bases = base_assets(&symbols.borrow_and_update());
+            if !bases.is_empty() {
+                match fetch(&client, &config, &bases).await {
+                    Ok(assets) => {
+                        info!(assets = assets.len(), of = bases.len(), "Fetched market data");
+                        market.update(assets);
+                    }
+                    Err(e) => warn!(error = %e, "Couldn't fetch market data; keeping the previous figures"),
+                }
+            }
+
+            let next = tokio::time::sleep(config.refresh);
+            tokio::pin!(next);
+            loop {
+                tokio::select! {
+                    () = &mut next => break,
+                    changed = symbols.changed() => {
+                        if changed.is_err() {
+                            return;  // The tracker is gone
+                        }
+                        if base_assets(&symbols.borrow()).iter().any(|base| market.get(base).is_none()) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn market_cap_follows_the_live_price() {
+        let body = json!([
+            {"id": "bitcoin", "symbol": "btc", "market_cap_rank": 1, "circulating_supply": 19_700_000.0, "total_volume": 32_100_000_000u64},
+            {"id": "wrapped-bitcoin", "symbol": "wbtc", "market_cap_rank": 15, "circulating_supply": 150_000.0, "total_volume": 1e8},
+            {"id": "uni-fake", "symbol": "uni", "market_cap_rank": 900, "circulating_supply": 1e9, "total_volume": 10},
+            {"id": "uniswap", "symbol": "uni", "market_cap_rank": 30, "circulating_supply": 600_000_000.0, "total_volume": 2e8},
+            {"id": "eth-clone", "symbol": "eth", "market_cap_rank": 2000, "circulating_supply": 1, "total_volume": 1}
+        ]);
+        // ETH is pinned to an id the response doesn't have, so its clone is ignored
+        let ids = HashMap::from([("ETH".to_string(), "ethereum".to_string())]);
+        let assets = parse_markets(&body, &ids);
+        assert_eq!(assets.len(), 3);
+        assert_eq!(assets["UNI"].coin_id, "uniswap");
+        assert!(!assets.contains_key("ETH"));
+
+        let market = MarketData::default();
+        market.update(assets);
+        let info = market.info("BTC-USD", "65000".parse().unwrap()).unwrap();
+        assert_eq!(info.market_cap, Some(Decimal::from(1_280_500_000_000u64)));
+        assert_eq!(info.to_string(), "#1  cap 1.28T USD  supply 19.70M  vol 24h $32.10B");
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            json!({"market_cap": "1280500000000", "market_cap_rank": 1, "circulating_supply": "19700000", "volume_24h_usd": "32100000000"})
+        );
+        assert_eq!(market.info("DOGE-USD", Decimal::ONE), None);
+
+        let symbols = BTreeSet::from(["BTC-USD".to_string(), "BTC-EUR".to_string(), "ETH-USD".to_string()]);
+        assert_eq!(base_assets(&symbols), ["BTC", "ETH"]);
+    }
+}
@@ -0,0 +1,201 @@
+// Prometheus metrics: what the tracker is doing, in a form Prometheus can scrape
+// (served as GET /metrics by the REST API).
+//
+//   crabby_price{exchange, symbol}                  latest price (gauge)
+//   crabby_messages_received_total{exchange}        WebSocket messages read (counter)
+//   crabby_reconnects_total{exchange}               reconnect attempts (counter)
+//   crabby_dropped_messages_total{exchange}         messages missing according to sequence numbers (counter)
+//   crabby_message_processing_seconds{exchange}     time to validate, parse and store one message (histogram)
+//   crabby_feed_latency_seconds{exchange}           exchange timestamp to local receive time (histogram)
+//   crabby_symbol_messages_total{exchange, symbol}  messages about each symbol (counter)
+//   crabby_dropped_events_total{consumer}           updates a slow consumer never got (counter)
+//
+// Each `Metrics` has its own registry rather than using the process-wide default,
+// so several trackers (or tests) in one process don't clash.
+
+use std::time::Duration;
+
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use rust_decimal::prelude::ToPrimitive;
+
+use crate::store::PriceUpdate;
+
+// Content type of `Metrics::render` output
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// Processing a message takes microseconds, so the buckets start far below Prometheus' defaults
+const PROCESSING_BUCKETS: &[f64] = &[0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1];
+
+// Feed latency is network plus exchange-side queueing: milliseconds to seconds
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+// Cheap to clone: every clone updates the same registry
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    price: GaugeVec,
+    messages: IntCounterVec,
+    reconnects: IntCounterVec,
+    dropped: IntCounterVec,
+    processing: HistogramVec,
+    latency: HistogramVec,
+    symbol_messages: IntCounterVec,
+    dropped_events: IntCounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let price = GaugeVec::new(Opts::new("crabby_price", "Latest price"), &["exchange", "symbol"])
+            .expect("valid metric");
+        let messages = IntCounterVec::new(
+            Opts::new("crabby_messages_received_total", "WebSocket messages received"),
+            &["exchange"],
+        )
+        .expect("valid metric");
+        let reconnects =
+            IntCounterVec::new(Opts::new("crabby_reconnects_total", "WebSocket reconnect attempts"), &["exchange"])
+                .expect("valid metric");
+        let dropped = IntCounterVec::new(
+            Opts::new("crabby_dropped_messages_total", "Messages missing according to sequence numbers"),
+            &["exchange"],
+        )
+        .expect("valid metric");
+        let processing = HistogramVec::new(
+            HistogramOpts::new("crabby_message_processing_seconds", "Time to validate, parse and store one message")
+                .buckets(PROCESSING_BUCKETS.to_vec()),
+            &["exchange"],
+        )
+        .expect("valid metric");
+        let latency = HistogramVec::new(
+            HistogramOpts::new("crabby_feed_latency_seconds", "Time from the exchange's message timestamp to receiving it")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["exchange"],
+        )
+        .expect("valid metric");
+        let symbol_messages = IntCounterVec::new(
+            Opts::new("crabby_symbol_messages_total", "Messages received about each symbol"),
+            &["exchange", "symbol"],
+        )
+        .expect("valid metric");
+        let dropped_events = IntCounterVec::new(
+            Opts::new("crabby_dropped_events_total", "Updates dropped because a consumer fell behind"),
+            &["consumer"],
+        )
+        .expect("valid metric");
+
+        // Names are unique within this fresh registry, so registering can't fail
+        registry.register(Box::new(price.clone())).expect("register metric");
+        registry.register(Box::new(messages.clone())).expect("register metric");
+        registry.register(Box::new(reconnects.clone())).expect("register metric");
+        registry.register(Box::new(dropped.clone())).expect("register metric");
+        registry.register(Box::new(processing.clone())).expect("register metric");
+        registry.register(Box::new(latency.clone())).expect("register metric");
+        registry.register(Box::new(symbol_messages.clone())).expect("register metric");
+        registry.register(Box::new(dropped_events.clone())).expect("register metric");
+
+        Self { registry, price, messages, reconnects, dropped, processing, latency, symbol_messages, dropped_events }
+    }
+
+    // A new latest price (called by the store on every update)
+    pub fn record_price(&self, update: &PriceUpdate) {
+        if let Some(price) = update.price.to_f64() {
+            self.price.with_label_values(&[update.exchange, update.symbol.as_str()]).set(price);
+        }
+    }
+
+    // Drop the price gauge and message counter of a symbol that is no longer tracked
+    pub fn forget_price(&self, exchange: &str, symbol: &str) {
+        let _ = self.price.remove_label_values(&[exchange, symbol]);  // Err if it never had a price
+        let _ = self.symbol_messages.remove_label_values(&[exchange, symbol]);
+    }
+
+    // One WebSocket message read from `exchange`
+    pub fn record_message(&self, exchange: &str) {
+        self.messages.with_label_values(&[exchange]).inc();
+    }
+
+    // One reconnect attempt to `exchange`
+    pub fn record_reconnect(&self, exchange: &str) {
+        self.reconnects.with_label_values(&[exchange]).inc();
+    }
+
+    // `count` messages from `exchange` that never arrived (a sequence number gap)
+    pub fn record_dropped(&self, exchange: &str, count: u64) {
+        self.dropped.with_label_values(&[exchange]).inc_by(count);
+    }
+
+    // How long one message from `exchange` took to handle
+    pub fn observe_processing(&self, exchange: &str, elapsed: Duration) {
+        self.processing.with_label_values(&[exchange]).observe(elapsed.as_secs_f64());
+    }
+
+    // One message about `symbol`, and how long after the exchange's timestamp it arrived
+    // (if it had one). Clock skew can make that negative; it counts as zero.
+    pub fn record_symbol_message(&self, exchange: &str, symbol: &str, latency: Option<f64>) {
+        self.symbol_messages.with_label_values(&[exchange, symbol]).inc();
+        if let Some(seconds) = latency {
+            self.latency.with_label_values(&[exchange]).observe(seconds.max(0.0));
+        }
+    }
+
+    // `count` updates that `consumer` (the alert engine, SQLite storage...) fell too far
+    // behind to get: a full queue, or a broadcast receiver that lagged
+    pub fn record_dropped_events(&self, consumer: &str, count: u64) {
+        self.dropped_events.with_label_values(&[consumer]).inc_by(count);
+    }
+
+    // Everything in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = Vec::new();
+        // Only fails on I/O errors, which writing into a Vec can't produce
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut out);
+        String::from_utf8(out).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn renders_prices_counters_and_histograms() {
+        let metrics = Metrics::new();
+        metrics.record_price(&PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: "65000.5".parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::now(),
+        });
+        metrics.record_message("coinbase");
+        metrics.record_message("coinbase");
+        metrics.record_reconnect("kraken");
+        metrics.record_dropped("coinbase", 3);
+        metrics.observe_processing("coinbase", Duration::from_micros(20));
+        metrics.record_symbol_message("coinbase", "BTC-USD", Some(0.08));
+        metrics.record_symbol_message("coinbase", "BTC-USD", None);
+
+        let text = metrics.render();
+        assert!(text.contains(r#"crabby_price{exchange="coinbase",symbol="BTC-USD"} 65000.5"#), "{}", text);
+        assert!(text.contains(r#"crabby_messages_received_total{exchange="coinbase"} 2"#), "{}", text);
+        assert!(text.contains(r#"crabby_reconnects_total{exchange="kraken"} 1"#), "{}", text);
+        assert!(text.contains(r#"crabby_dropped_messages_total{exchange="coinbase"} 3"#), "{}", text);
+        assert!(text.contains(r#"crabby_message_processing_seconds_count{exchange="coinbase"} 1"#), "{}", text);
+        assert!(text.contains(r#"crabby_feed_latency_seconds_bucket{exchange="coinbase",le="0.1"} 1"#), "{}", text);
+        assert!(text.contains(r#"crabby_symbol_messages_total{exchange="coinbase",symbol="BTC-USD"} 2"#), "{}", text);
+
+        metrics.forget_price("coinbase", "BTC-USD");
+        assert!(!metrics.render().contains("crabby_symbol_messages_total{"));
+    }
+}
@@ -0,0 +1,106 @@
+// Native desktop notifications (notification center on macOS, toast on Windows,
+// D-Bus notification daemon on Linux), for rules that set `desktop = true`.
+
+use notify_rust::Notification;
+
+use super::Notifier;
+use crate::alerts::Alert;
+
+pub struct DesktopNotifier {
+    show: Box<dyn Fn(Notification) + Send + Sync>,  // Puts the notification on screen
+}
+
+impl DesktopNotifier {
+    pub fn new() -> Self {
+        Self { show: Box::new(show) }
+    }
+}
+
+impl Default for DesktopNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Notifier for DesktopNotifier {
+    fn notify(&self, alert: &Alert) {
+        if !alert.desktop {
+            return;  // This rule only wants the console
+        }
+
+        let mut notification = Notification::new();
+        notification
+            .appname("CrabbyCryptoTracker")
+            .summary(&format!("{} ${}", alert.symbol, alert.price))
+            .body(&format!("{} on {}\n{}", alert.rule, alert.exchange, alert.detail));
+        (self.show)(notification);
+    }
+}
+
+fn show(notification: Notification) {
+    // Showing a notification talks to the OS and can block; keep it off the alert task
+    std::thread::spawn(move || {
+        if let Err(e) = notification.show() {
+            tracing::warn!(error = %e, "Desktop notification failed");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+    use std::time::{Instant, SystemTime};
+
+    use crate::alerts::AlertEngine;
+    use crate::store::PriceUpdate;
+
+    fn tick(symbol: &str, price: &str) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn only_desktop_rules_reach_the_desktop() {
+        let mut engine = AlertEngine::from_toml(
+            r#"
+            [[alert]]
+            symbol = "BTC-USD"
+            above = 70000
+            desktop = true
+
+            [[alert]]
+            symbol = "ETH-USD"
+            below = 3000
+            "#,
+        )
+        .unwrap();
+        let shown = Arc::new(Mutex::new(Vec::new()));  // (summary, body) of each notification
+        let desktop = DesktopNotifier {
+            show: Box::new({
+                let shown = shown.clone();
+                move |n: Notification| shown.lock().unwrap().push((n.summary, n.body))
+            }),
+        };
+
+        let t0 = Instant::now();
+        let mut alerts = engine.evaluate(&tick("BTC-USD", "70001.5"), t0);
+        alerts.extend(engine.evaluate(&tick("ETH-USD", "2999"), t0));
+        assert_eq!(alerts.len(), 2);  // Both fire; the console gets them all
+        for alert in &alerts {
+            desktop.notify(alert);
+        }
+
+        let shown = shown.lock().unwrap();
+        assert_eq!(shown.len(), 1);
+        assert_eq!(shown[0].0, "BTC-USD $70001.5");
+        assert!(shown[0].1.starts_with(&format!("{} on coinbase\n", alerts[0].rule)));
+    }
+}
@@ -0,0 +1,175 @@
+// Notification sinks: where fired alerts get delivered.
+
+use std::collections::HashMap;  // Group notifiers by symbol
+use std::time::Duration;      // Webhook timeouts
+
+use serde::Deserialize;
+use tracing::warn;                // Console alerts are log events
+
+use crate::alerts::Alert;
+use crate::config::deserialize_duration;
+
+#[cfg(feature = "desktop")]
+mod desktop;
+#[cfg(feature = "webhook")]
+mod webhook;
+
+#[cfg(feature = "desktop")]
+pub use desktop::DesktopNotifier;
+#[cfg(feature = "webhook")]
+pub use webhook::WebhookNotifier;
+
+// Anything that can deliver an alert. `notify` is called from the alert task, so
+// implementations that do slow I/O should hand the work off instead of blocking.
+pub trait Notifier: Send + Sync {
+    fn notify(&self, alert: &Alert);
+
+    // Text that isn't an alert, e.g. the end-of-day report. Only notifiers that deliver
+    // text to people (webhooks, Telegram) do anything with it.
+    fn message(&self, _text: &str) {}
+}
+
+// Logs alerts (at warn level, so they stand out); always enabled
+pub struct ConsoleNotifier;
+
+impl Notifier for ConsoleNotifier {
+    fn notify(&self, alert: &Alert) {
+        warn!(
+            rule = %alert.rule,
+            exchange = alert.exchange,
+            symbol = %alert.symbol,
+            price = %alert.price,
+            "🚨 ALERT {}",
+            alert.detail
+        );
+    }
+}
+
+// Sends each alert to the notifier of its symbol's group ([symbol_groups.<name>] with
+// `webhooks`), and the others to `fallback` (the global [[webhooks]]). Text that isn't
+// an alert, like the end-of-day report, only goes to the fallback.
+pub struct GroupRouter {
+    fallback: Option<Box<dyn Notifier>>,
+    groups: Vec<Box<dyn Notifier>>,
+    by_symbol: HashMap<String, usize>,  // Uppercase symbol → index into `groups`
+}
+
+impl GroupRouter {
+    pub fn new(fallback: Option<Box<dyn Notifier>>) -> Self {
+        Self { fallback, groups: Vec::new(), by_symbol: HashMap::new() }
+    }
+
+    // Send the alerts for `symbols` to `notifier` instead
+    pub fn route(&mut self, symbols: &[String], notifier: Box<dyn Notifier>) {
+        self.groups.push(notifier);
+        for symbol in symbols {
+            self.by_symbol.insert(symbol.to_uppercase(), self.groups.len() - 1);
+        }
+    }
+}
+
+impl Notifier for GroupRouter {
+    fn notify(&self, alert: &Alert) {
+        match self.by_symbol.get(&alert.symbol.to_uppercase()) {
+            Some(&i) => self.groups[i].notify(alert),
+            None => {
+                if let Some(fallback) = &self.fallback {
+                    fallback.notify(alert);
+                }
+            }
+        }
+    }
+
+    fn message(&self, text: &str) {
+        if let Some(fallback) = &self.fallback {
+            fallback.message(text);
+        }
+    }
+}
+
+// Shape of the JSON body a webhook receives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    #[default]
+    Generic,  // {"rule": ..., "symbol": ..., "price": ..., ...}
+    Slack,    // {"text": "..."} for Slack incoming webhooks
+    Discord,  // {"content": "..."} for Discord webhooks
+}
+
+// One [[webhooks]] entry in the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    #[serde(default = "default_webhook_timeout", deserialize_with = "deserialize_duration")]
+    pub timeout: Duration,        // Per attempt
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,             // Extra attempts after the first one fails
+}
+
+fn default_webhook_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+impl WebhookConfig {
+    // A generic webhook with default timeout and retries
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into(), format: WebhookFormat::Generic, timeout: default_webhook_timeout(), retries: default_webhook_retries() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+
+    // Remembers what it was sent, as "symbol" or "text:..."
+    #[derive(Clone, Default)]
+    struct Inbox(Arc<Mutex<Vec<String>>>);
+
+    impl Notifier for Inbox {
+        fn notify(&self, alert: &Alert) {
+            self.0.lock().unwrap().push(alert.symbol.clone());
+        }
+
+        fn message(&self, text: &str) {
+            self.0.lock().unwrap().push(format!("text:{}", text));
+        }
+    }
+
+    fn alert(symbol: &str) -> Alert {
+        Alert {
+            rule: format!("{} above 1", symbol),
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            price: rust_decimal::Decimal::ONE,
+            detail: String::new(),
+            fired_at: SystemTime::now(),
+            desktop: false,
+            trade: None,
+        }
+    }
+
+    #[test]
+    fn group_alerts_go_to_the_group_and_the_rest_to_the_fallback() {
+        let (global, speculative) = (Inbox::default(), Inbox::default());
+        let mut router = GroupRouter::new(Some(Box::new(global.clone())));
+        router.route(&["DOGE-USD".to_string()], Box::new(speculative.clone()));
+
+        router.notify(&alert("doge-usd"));
+        router.notify(&alert("BTC-USD"));
+        router.message("daily report");
+
+        assert_eq!(*speculative.0.lock().unwrap(), vec!["doge-usd"]);
+        assert_eq!(*global.0.lock().unwrap(), vec!["BTC-USD", "text:daily report"]);
+    }
+}
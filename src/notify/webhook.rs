@@ -0,0 +1,142 @@
+// Webhook notifications: POSTs a JSON payload (generic, Slack or Discord shaped) to
+// every configured URL when an alert fires.
+//
+// `notify` only queues the alert; a background task does the HTTP work with a
+// per-attempt timeout and retries with backoff, so a slow or dead endpoint can never
+// stall the alert task (and through it the feeds). If the queue fills up because
+// the endpoints are that far behind, new alerts are dropped with a warning.
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use super::{Notifier, WebhookConfig, WebhookFormat};
+use crate::alerts::Alert;
+use crate::backoff::Backoff;
+use crate::net::Network;
+use crate::store::epoch_ms;
+
+// Alerts waiting to be sent before new ones are dropped
+const QUEUE_CAPACITY: usize = 256;
+
+// What the sender task delivers
+enum Outgoing {
+    Alert(Alert),
+    Text(String),   // A message such as the daily report
+}
+
+pub struct WebhookNotifier {
+    queue: mpsc::Sender<Outgoing>,
+}
+
+impl WebhookNotifier {
+    // Start the sender task. Must be called from inside a Tokio runtime.
+    pub fn new(hooks: Vec<WebhookConfig>, network: &Network) -> Self {
+        let (queue, mut rx) = mpsc::channel::<Outgoing>(QUEUE_CAPACITY);
+        let client = network
+            .http_client()
+            .user_agent(concat!("crabbycryptotracker/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+
+        tokio::spawn(async move {
+            while let Some(outgoing) = rx.recv().await {
+                for hook in &hooks {
+                    let body = match &outgoing {
+                        Outgoing::Alert(alert) => payload(hook.format, alert),
+                        Outgoing::Text(text) => text_payload(hook.format, text),
+                    };
+                    deliver(&client, hook, &body).await;
+                }
+            }
+        });
+        Self { queue }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, alert: &Alert) {
+        if self.queue.try_send(Outgoing::Alert(alert.clone())).is_err() {
+            warn!(rule = %alert.rule, "Webhook queue full, dropped alert");
+        }
+    }
+
+    fn message(&self, text: &str) {
+        if self.queue.try_send(Outgoing::Text(text.to_string())).is_err() {
+            warn!("Webhook queue full, dropped message");
+        }
+    }
+}
+
+// POST one payload, retrying failures (network errors, timeouts, non-2xx) with backoff
+async fn deliver(client: &reqwest::Client, hook: &WebhookConfig, body: &Value) {
+    let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(10));
+    for attempt in 0..=hook.retries {
+        let result = client.post(&hook.url).timeout(hook.timeout).json(body).send().await;
+        let error = match result.and_then(|r| r.error_for_status()) {
+            Ok(_) => return,
+            Err(e) => e,
+        };
+        if attempt == hook.retries {
+            warn!(url = %hook.url, attempts = attempt + 1, %error, "Webhook failed");
+            return;
+        }
+        tokio::time::sleep(backoff.next_delay()).await;
+    }
+}
+
+// JSON body in the shape the receiving service expects
+fn payload(format: WebhookFormat, alert: &Alert) -> Value {
+    let text = format!(
+        "🚨 {} — {} on {}: ${} ({})",
+        alert.rule, alert.symbol, alert.exchange, alert.price, alert.detail
+    );
+    match format {
+        WebhookFormat::Slack => json!({ "text": text }),
+        WebhookFormat::Discord => json!({ "content": text }),
+        WebhookFormat::Generic => json!({
+            "rule": alert.rule,
+            "exchange": alert.exchange,
+            "symbol": alert.symbol,
+            "price": alert.price,
+            "detail": alert.detail,
+            "timestamp_ms": epoch_ms(alert.fired_at),
+        }),
+    }
+}
+
+// JSON body for a plain message
+fn text_payload(format: WebhookFormat, text: &str) -> Value {
+    match format {
+        WebhookFormat::Slack => json!({ "text": text }),
+        WebhookFormat::Discord => json!({ "content": text }),
+        WebhookFormat::Generic => json!({ "message": text }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn payload_matches_each_format() {
+        let alert = Alert {
+            rule: "BTC-USD above 70000".into(),
+            exchange: "coinbase",
+            symbol: "BTC-USD".into(),
+            price: "70001.5".parse().unwrap(),
+            detail: "price 70001.5 > 70000".into(),
+            fired_at: SystemTime::UNIX_EPOCH,
+            desktop: false,
+            trade: None,
+        };
+
+        assert!(payload(WebhookFormat::Slack, &alert)["text"].as_str().unwrap().contains("BTC-USD above 70000"));
+        assert!(payload(WebhookFormat::Discord, &alert)["content"].is_string());
+        let generic = payload(WebhookFormat::Generic, &alert);
+        assert_eq!((generic["price"].as_str(), generic["timestamp_ms"].as_u64()), (Some("70001.5"), Some(0)));
+    }
+}
@@ -0,0 +1,178 @@
+// Level 2 order books: every price level on both sides of the book, kept up to date
+// from a snapshot followed by incremental changes (Coinbase's `level2_batch` channel).
+//
+// Only the top of the book (best bid, best ask, spread) is shown today, but the full
+// depth is kept so it's there for anything that needs it later.
+
+use std::collections::BTreeMap;  // Price levels in order
+
+use crate::store::{entry, get, BySymbol};  // One book per (exchange, symbol)
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+// Which side of the book a level is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Bid,  // Buyers
+    Ask,  // Sellers
+}
+
+// An order book message, already parsed by the exchange connector
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookEvent {
+    // The complete book; replaces whatever we had
+    Snapshot { symbol: String, bids: Vec<(Decimal, Decimal)>, asks: Vec<(Decimal, Decimal)> },
+    // New sizes for some levels (size 0 removes the level)
+    Update { symbol: String, changes: Vec<(Side, Decimal, Decimal)> },
+}
+
+impl BookEvent {
+    // Exchange-native symbol the event is for
+    pub fn symbol(&self) -> &str {
+        match self {
+            BookEvent::Snapshot { symbol, .. } | BookEvent::Update { symbol, .. } => symbol,
+        }
+    }
+}
+
+// Best prices on each side, shown next to the last trade price
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TopOfBook {
+    pub best_bid: Option<Decimal>,
+    pub best_ask: Option<Decimal>,
+    pub spread: Option<Decimal>,  // best_ask − best_bid, when both sides have orders
+}
+
+// Price -> size for each side of one book
+#[derive(Debug, Default, Clone)]
+pub struct OrderBook {
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl OrderBook {
+    // Replace the whole book
+    pub fn apply_snapshot(&mut self, bids: &[(Decimal, Decimal)], asks: &[(Decimal, Decimal)]) {
+        self.bids = bids.iter().copied().filter(|(_, size)| !size.is_zero()).collect();
+        self.asks = asks.iter().copied().filter(|(_, size)| !size.is_zero()).collect();
+    }
+
+    // Set the size at one price level; a size of zero means the level is gone
+    pub fn apply_change(&mut self, side: Side, price: Decimal, size: Decimal) {
+        let levels = match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        if size.is_zero() {
+            levels.remove(&price);
+        } else {
+            levels.insert(price, size);
+        }
+    }
+
+    // Highest price someone is willing to pay
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.bids.keys().next_back().copied()
+    }
+
+    // Lowest price someone is willing to sell at
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.asks.keys().next().copied()
+    }
+
+    pub fn top(&self) -> TopOfBook {
+        let (best_bid, best_ask) = (self.best_bid(), self.best_ask());
+        let spread = best_bid.zip(best_ask).map(|(bid, ask)| ask - bid);
+        TopOfBook { best_bid, best_ask, spread }
+    }
+}
+
+// Every book, keyed by (exchange, our symbol). Owned by the store's state thread, which
+// is the only one to change or read it, so there's no lock.
+#[derive(Debug, Default)]
+pub struct OrderBooks {
+    books: BySymbol<OrderBook>,
+}
+
+impl OrderBooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Fold one event into the book for `symbol`. Updates that arrive before the
+    // first snapshot are dropped: without a snapshot they'd describe a partial book.
+    pub fn apply(&mut self, exchange: &'static str, symbol: &str, event: &BookEvent) {
+        match event {
+            BookEvent::Snapshot { bids, asks, .. } => {
+                entry(&mut self.books, exchange, symbol).apply_snapshot(bids, asks);
+            }
+            BookEvent::Update { changes, .. } => {
+                if let Some(book) = self.books.get_mut(symbol).and_then(|exchanges| exchanges.get_mut(exchange)) {
+                    for &(side, price, size) in changes {
+                        book.apply_change(side, price, size);
+                    }
+                }
+            }
+        }
+    }
+
+    // Forget one book, so it's rebuilt from the next snapshot
+    pub fn remove(&mut self, exchange: &str, symbol: &str) {
+        if let Some(exchanges) = self.books.get_mut(symbol) {
+            exchanges.remove(exchange);
+        }
+    }
+
+    // Forget the books of one symbol on every exchange (it's no longer tracked)
+    pub fn remove_symbol(&mut self, symbol: &str) {
+        self.books.remove(symbol);
+    }
+
+    // Best bid/ask for one symbol on one exchange, if we have its book
+    pub fn top(&self, exchange: &str, symbol: &str) -> Option<TopOfBook> {
+        get(&self.books, exchange, symbol).map(OrderBook::top)
+    }
+
+    // The top of every book, keyed like the books
+    pub fn tops(&self) -> impl Iterator<Item = (&'static str, &String, TopOfBook)> + '_ {
+        self.books.iter().flat_map(|(symbol, exchanges)| exchanges.iter().map(move |(exchange, book)| (*exchange, symbol, book.top())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn snapshot_then_updates_track_the_top_of_book() {
+        let mut books = OrderBooks::new();
+        let update = |changes| BookEvent::Update { symbol: "BTC-USD".into(), changes };
+
+        // Updates before the snapshot are ignored
+        books.apply("coinbase", "BTC-USD", &update(vec![(Side::Bid, d("1"), d("1"))]));
+        assert_eq!(books.top("coinbase", "BTC-USD"), None);
+
+        books.apply(
+            "coinbase",
+            "BTC-USD",
+            &BookEvent::Snapshot {
+                symbol: "BTC-USD".into(),
+                bids: vec![(d("100.0"), d("1")), (d("99.5"), d("2"))],
+                asks: vec![(d("100.5"), d("1")), (d("101"), d("3"))],
+            },
+        );
+        let top = books.top("coinbase", "BTC-USD").unwrap();
+        assert_eq!((top.best_bid, top.best_ask, top.spread), (Some(d("100.0")), Some(d("100.5")), Some(d("0.5"))));
+
+        // A better bid arrives, then the best ask is taken out
+        books.apply("coinbase", "BTC-USD", &update(vec![(Side::Bid, d("100.25"), d("0.1"))]));
+        books.apply("coinbase", "BTC-USD", &update(vec![(Side::Ask, d("100.5"), d("0"))]));
+        let top = books.top("coinbase", "BTC-USD").unwrap();
+        assert_eq!((top.best_bid, top.best_ask, top.spread), (Some(d("100.25")), Some(d("101")), Some(d("0.75"))));
+    }
+}
@@ -0,0 +1,512 @@
+// Paper trading: simulated orders filled against the live prices, with a simulated cash
+// balance, positions and a history of every fill (exportable as CSV). Nothing is ever
+// sent to an exchange.
+//
+//     [paper]
+//     enabled = true
+//     cash = 10000            # Starting balance, in `currency`
+//     currency = "USD"        # Only pairs quoted in it can be traded
+//     fee_pct = 0.1           # Charged on every fill
+//     export = "paper.csv"    # Write the fill history here on shutdown
+//
+//     [[paper.rules]]         # Orders placed automatically, once per crossing
+//     symbol = "BTC-USD"
+//     side = "buy"
+//     quantity = 0.01
+//     below = 60000           # (or `above`)
+//
+// Manual orders are typed at the prompt while the tracker runs (see control.rs):
+//
+//     buy BTC-USD 0.01             market order: fills at the next price
+//     sell BTC-USD 0.01 @ 70000    limit order: fills once the price reaches 70000
+//
+// Fills use the last trade price of whichever exchange reports the symbol next, with no
+// slippage or order book depth: a rough simulation, good for trying out rules.
+
+use std::{
+    collections::HashMap,             // Positions and latest prices per symbol
+    error::Error,                     // Trait to return errors from our functions
+    fmt,                              // Printable account summary
+    path::{Path, PathBuf},            // CSV export
+    sync::{Arc, Mutex},               // Shared between the price task and the command prompt
+    time::SystemTime,                 // Fill and order times
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::fx::quote_currency;
+use crate::store::{epoch_ms, PriceStore, PriceUpdate};
+
+// [paper] section of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaperConfig {
+    pub enabled: bool,
+    pub cash: Decimal,                // Starting balance
+    pub currency: String,             // Quote currency of the balance (and of every tradable pair)
+    pub fee_pct: Decimal,             // Fee per fill, as a % of its value
+    pub export: Option<PathBuf>,      // Fill history CSV written on shutdown
+    pub rules: Vec<PaperRule>,        // Automatic orders
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cash: Decimal::from(10_000),
+            currency: "USD".to_string(),
+            fee_pct: Decimal::new(1, 1),  // 0.1%
+            export: None,
+            rules: Vec::new(),
+        }
+    }
+}
+
+// Buy or sell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        })
+    }
+}
+
+// An automatic order: placed when the price crosses `above` or `below`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PaperRule {
+    pub symbol: String,
+    pub side: Side,
+    pub quantity: Decimal,
+    #[serde(default)]
+    pub above: Option<Decimal>,
+    #[serde(default)]
+    pub below: Option<Decimal>,
+}
+
+impl PaperRule {
+    // What's wrong with this rule, if anything
+    pub fn check(&self) -> Result<(), String> {
+        if self.quantity <= Decimal::ZERO {
+            return Err("quantity must be greater than zero".to_string());
+        }
+        if self.above.is_some() == self.below.is_some() {
+            return Err("set exactly one of `above` or `below`".to_string());
+        }
+        Ok(())
+    }
+
+    fn triggered(&self, price: Decimal) -> bool {
+        self.above.is_some_and(|level| price > level) || self.below.is_some_and(|level| price < level)
+    }
+}
+
+impl fmt::Display for PaperRule {
+    // "buy 0.01 BTC-USD below 60000"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.side, self.quantity, self.symbol)?;
+        match (self.above, self.below) {
+            (Some(level), _) => write!(f, " above {}", level),
+            (_, Some(level)) => write!(f, " below {}", level),
+            _ => Ok(()),
+        }
+    }
+}
+
+// An order waiting for a price
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub id: u64,
+    pub side: Side,
+    pub symbol: String,
+    pub quantity: Decimal,
+    pub limit: Option<Decimal>,   // None = market order
+    pub source: String,           // "manual", or the rule that placed it
+}
+
+impl fmt::Display for Order {
+    // "#3 sell 0.01 BTC-USD @ 70000 (manual)"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {} {} {}", self.id, self.side, self.quantity, self.symbol)?;
+        match self.limit {
+            Some(limit) => write!(f, " @ {}", limit)?,
+            None => write!(f, " at market")?,
+        }
+        write!(f, " ({})", self.source)
+    }
+}
+
+// One executed order, as exported
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Fill {
+    pub timestamp_ms: u64,
+    pub order_id: u64,
+    pub side: Side,
+    pub symbol: String,
+    pub quantity: Decimal,
+    pub price: Decimal,
+    pub fee: Decimal,
+    pub realized_pnl: Decimal,    // Sells only: proceeds minus fee minus average cost
+    pub cash: Decimal,            // Balance after the fill
+    pub equity: Decimal,          // Cash plus positions at the latest prices, after the fill
+}
+
+// Coins held and what they cost on average
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Position {
+    quantity: Decimal,
+    cost: Decimal,  // Total paid, fees included
+}
+
+// The simulated account
+#[derive(Debug)]
+pub struct PaperTrader {
+    config: PaperConfig,
+    cash: Decimal,
+    positions: HashMap<String, Position>,
+    prices: HashMap<String, Decimal>,   // Latest price per symbol, from any exchange
+    orders: Vec<Order>,                 // Waiting to fill
+    fills: Vec<Fill>,
+    realized_pnl: Decimal,
+    rules_active: Vec<bool>,            // Was each rule's condition true on the last update?
+    next_id: u64,
+}
+
+// Shared between the task following prices and the command prompt
+pub type SharedPaper = Arc<Mutex<PaperTrader>>;
+
+impl PaperTrader {
+    pub fn new(config: PaperConfig) -> Self {
+        Self {
+            cash: config.cash,
+            rules_active: vec![false; config.rules.len()],
+            config,
+            positions: HashMap::new(),
+            prices: HashMap::new(),
+            orders: Vec::new(),
+            fills: Vec::new(),
+            realized_pnl: Decimal::ZERO,
+            next_id: 1,
+        }
+    }
+
+    // A trader for the [paper] settings, or None when paper trading is off
+    pub fn from_config(config: &PaperConfig) -> Option<Self> {
+        config.enabled.then(|| Self::new(config.clone()))
+    }
+
+    // Symbols the automatic rules need prices for
+    pub fn rule_symbols(&self) -> Vec<String> {
+        self.config.rules.iter().map(|r| r.symbol.clone()).collect()
+    }
+
+    // Queue an order; it fills on the next price of its symbol (market) or once the price
+    // reaches `limit`. Returns the order id.
+    pub fn place(&mut self, side: Side, symbol: &str, quantity: Decimal, limit: Option<Decimal>, source: &str) -> Result<u64, String> {
+        if quote_currency(symbol) != self.config.currency {
+            return Err(format!("{} isn't quoted in {}, the paper account's currency", symbol, self.config.currency));
+        }
+        if quantity <= Decimal::ZERO {
+            return Err("quantity must be greater than zero".to_string());
+        }
+        if limit.is_some_and(|l| l <= Decimal::ZERO) {
+            return Err("limit price must be greater than zero".to_string());
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.orders.push(Order { id, side, symbol: symbol.to_string(), quantity, limit, source: source.to_string() });
+        Ok(id)
+    }
+
+    // Remove a waiting order; false when there's no such order (or it already filled)
+    pub fn cancel(&mut self, id: u64) -> bool {
+        let before = self.orders.len();
+        self.orders.retain(|o| o.id != id);
+        self.orders.len() < before
+    }
+
+    pub fn orders(&self) -> &[Order] {
+        &self.orders
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    // Take in one price: place the orders of rules that just triggered, then fill what
+    // the price allows. Returns the new fills and the orders that had to be rejected.
+    pub fn on_update(&mut self, update: &PriceUpdate) -> (Vec<Fill>, Vec<(Order, String)>) {
+        let price = update.price;
+        self.prices.insert(update.symbol.clone(), price);
+
+        // Rules are edge-triggered like alerts: one order per crossing
+        for i in 0..self.config.rules.len() {
+            let rule = &self.config.rules[i];
+            if rule.symbol != update.symbol {
+                continue;
+            }
+            let triggered = rule.triggered(price);
+            if triggered && !self.rules_active[i] {
+                let (side, symbol, quantity, source) = (rule.side, rule.symbol.clone(), rule.quantity, format!("rule: {}", rule));
+                if let Err(e) = self.place(side, &symbol, quantity, None, &source) {
+                    warn!(rule = %source, error = %e, "Paper rule couldn't place its order");
+                }
+            }
+            self.rules_active[i] = triggered;
+        }
+
+        let (mut fills, mut rejected) = (Vec::new(), Vec::new());
+        let ready: Vec<Order> = self
+            .orders
+            .extract_if(.., |o| {
+                o.symbol == update.symbol
+                    && match (o.side, o.limit) {
+                        (_, None) => true,
+                        (Side::Buy, Some(limit)) => price <= limit,
+                        (Side::Sell, Some(limit)) => price >= limit,
+                    }
+            })
+            .collect();
+        for order in ready {
+            match self.fill(&order, price, update.received_at) {
+                Ok(fill) => fills.push(fill),
+                Err(reason) => rejected.push((order, reason)),
+            }
+        }
+        (fills, rejected)
+    }
+
+    // Execute one order at `price`, if the account can afford it
+    fn fill(&mut self, order: &Order, price: Decimal, at: SystemTime) -> Result<Fill, String> {
+        let value = order.quantity * price;
+        let fee = (value * self.config.fee_pct / Decimal::ONE_HUNDRED).round_dp(8);
+        let position = self.positions.entry(order.symbol.clone()).or_default();
+        let realized_pnl = match order.side {
+            Side::Buy => {
+                if value + fee > self.cash {
+                    return Err(format!("not enough cash: {} needed, {} available", (value + fee).round_dp(2), self.cash.round_dp(2)));
+                }
+                self.cash -= value + fee;
+                position.quantity += order.quantity;
+                position.cost += value + fee;
+                Decimal::ZERO
+            }
+            Side::Sell => {
+                if order.quantity > position.quantity {
+                    if position.quantity.is_zero() {
+                        self.positions.remove(&order.symbol);  // Don't leave an empty position behind
+                        return Err(format!("no {} held", order.symbol));
+                    }
+                    return Err(format!("only {} {} held", position.quantity, order.symbol));
+                }
+                let cost = position.cost * order.quantity / position.quantity;  // Average cost of what's sold
+                position.quantity -= order.quantity;
+                position.cost -= cost;
+                self.cash += value - fee;
+                value - fee - cost
+            }
+        };
+        if position.quantity.is_zero() {
+            self.positions.remove(&order.symbol);
+        }
+        self.realized_pnl += realized_pnl;
+        let fill = Fill {
+            timestamp_ms: epoch_ms(at),
+            order_id: order.id,
+            side: order.side,
+            symbol: order.symbol.clone(),
+            quantity: order.quantity,
+            price,
+            fee,
+            realized_pnl: realized_pnl.round_dp(8),
+            cash: self.cash.round_dp(8),
+            equity: self.equity().round_dp(8),
+        };
+        self.fills.push(fill.clone());
+        Ok(fill)
+    }
+
+    // Cash plus every position at its latest price (or its cost, before there is one)
+    pub fn equity(&self) -> Decimal {
+        let positions: Decimal =
+            self.positions.iter().map(|(symbol, p)| self.prices.get(symbol).map_or(p.cost, |price| p.quantity * price)).sum();
+        self.cash + positions
+    }
+
+    // The account at the latest prices
+    pub fn summary(&self) -> PaperSummary {
+        let mut positions: Vec<PaperPosition> = self
+            .positions
+            .iter()
+            .map(|(symbol, p)| {
+                let price = self.prices.get(symbol).copied();
+                PaperPosition {
+                    symbol: symbol.clone(),
+                    quantity: p.quantity,
+                    average_cost: (p.cost / p.quantity).round_dp(8),
+                    price,
+                    unrealized_pnl: price.map(|price| p.quantity * price - p.cost),
+                }
+            })
+            .collect();
+        positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        let equity = self.equity();
+        PaperSummary {
+            currency: self.config.currency.clone(),
+            cash: self.cash,
+            positions,
+            equity,
+            realized_pnl: self.realized_pnl,
+            total_pnl: equity - self.config.cash,
+            open_orders: self.orders.len(),
+            fills: self.fills.len(),
+        }
+    }
+
+    // Write every fill so far as CSV (header row included)
+    pub fn export_csv<P: AsRef<Path>>(&self, path: P) -> Result<usize, Box<dyn Error>> {
+        let mut writer = csv::Writer::from_path(path)?;
+        if self.fills.is_empty() {
+            // serialize() writes the header with the first row; an empty history still gets one
+            writer.write_record(["timestamp_ms", "order_id", "side", "symbol", "quantity", "price", "fee", "realized_pnl", "cash", "equity"])?;
+        }
+        for fill in &self.fills {
+            writer.serialize(fill)?;
+        }
+        writer.flush()?;
+        Ok(self.fills.len())
+    }
+}
+
+// One position in the summary
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaperPosition {
+    pub symbol: String,
+    pub quantity: Decimal,
+    pub average_cost: Decimal,            // Per coin, fees included
+    pub price: Option<Decimal>,           // Latest price, once there is one
+    pub unrealized_pnl: Option<Decimal>,
+}
+
+// The simulated account at one point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaperSummary {
+    pub currency: String,
+    pub cash: Decimal,
+    pub positions: Vec<PaperPosition>,
+    pub equity: Decimal,          // Cash plus positions
+    pub realized_pnl: Decimal,    // From closed (sold) quantities
+    pub total_pnl: Decimal,       // Equity minus the starting balance
+    pub open_orders: usize,
+    pub fills: usize,
+}
+
+impl fmt::Display for PaperSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for p in &self.positions {
+            match (p.price, p.unrealized_pnl) {
+                (Some(price), Some(pnl)) => {
+                    writeln!(f, "{} x{}: avg {} now {}  P&L {:+.2}", p.symbol, p.quantity, p.average_cost, price, pnl)?
+                }
+                _ => writeln!(f, "{} x{}: avg {}", p.symbol, p.quantity, p.average_cost)?,
+            }
+        }
+        write!(
+            f,
+            "Cash {:.2} {}  Equity {:.2}  P&L {:+.2} (realized {:+.2})  {} fills, {} open orders",
+            self.cash, self.currency, self.equity, self.total_pnl, self.realized_pnl, self.fills, self.open_orders
+        )
+    }
+}
+
+// Feed every update from `store` to the trader, logging fills and rejected orders
+pub fn spawn_paper(trader: SharedPaper, store: &PriceStore) -> tokio::task::JoinHandle<()> {
+    let mut rx = store.subscribe_updates();
+    let metrics = store.metrics().clone();  // Counts what the task misses
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(update) => {
+                    let (fills, rejected) = trader.lock().unwrap().on_update(&update);
+                    for fill in fills {
+                        info!(
+                            order = fill.order_id,
+                            side = %fill.side,
+                            symbol = %fill.symbol,
+                            quantity = %fill.quantity,
+                            price = %fill.price,
+                            cash = %fill.cash.round_dp(2),
+                            "Paper order filled"
+                        );
+                    }
+                    for (order, reason) in rejected {
+                        warn!(order = %order, %reason, "Paper order rejected");
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    warn!(skipped = n, "Paper trader fell behind");
+                    metrics.record_dropped_events("paper", n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    fn tick(price: &str) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: d(price),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn fills_orders_against_prices_and_tracks_pnl() {
+        let rule = PaperRule { symbol: "BTC-USD".into(), side: Side::Buy, quantity: d("0.1"), above: None, below: Some(d("50000")) };
+        let config = PaperConfig { enabled: true, cash: d("10000"), fee_pct: Decimal::ZERO, rules: vec![rule], ..PaperConfig::default() };
+        let mut paper = PaperTrader::from_config(&config).unwrap();
+
+        // The rule buys once when the price drops below 50000
+        let (fills, _) = paper.on_update(&tick("49000"));
+        assert_eq!((fills.len(), fills[0].price, fills[0].cash), (1, d("49000"), d("5100")));
+        assert!(paper.on_update(&tick("48000")).0.is_empty(), "still below: no second order");
+
+        // A limit sell waits for its price
+        paper.place(Side::Sell, "BTC-USD", d("0.1"), Some(d("55000")), "manual").unwrap();
+        assert!(paper.on_update(&tick("54000")).0.is_empty());
+        let (fills, _) = paper.on_update(&tick("55500"));
+        assert_eq!((fills[0].realized_pnl, fills[0].cash), (d("650"), d("10650")));
+
+        // Can't sell what isn't held, or trade pairs in another currency
+        paper.place(Side::Sell, "BTC-USD", d("1"), None, "manual").unwrap();
+        let (_, rejected) = paper.on_update(&tick("55000"));
+        assert_eq!(rejected.len(), 1);
+        assert!(paper.place(Side::Buy, "BTC-EUR", d("1"), None, "manual").is_err());
+
+        let summary = paper.summary();
+        assert_eq!((summary.equity, summary.total_pnl, summary.fills), (d("10650"), d("650"), 2));
+    }
+}
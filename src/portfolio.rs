@@ -0,0 +1,333 @@
+// Portfolio tracking: joins a holdings file (what you own and what you paid) with the
+// live price map to get the current value, unrealized P&L per position and the
+// change over the last 24 hours.
+//
+// Holdings file (CSV, header required):
+//
+//     symbol,quantity,cost_basis
+//     BTC-USD,0.5,25000
+//     ETH-USD,4,9000
+//
+// `cost_basis` is the total amount paid for the position, not the price per coin.
+// Simulated DCA buys (see dca.rs) are added to the holdings while the tracker runs and
+// written back to the file.
+
+use std::{
+    error::Error,             // Trait to return errors from our functions
+    fmt,                      // Printable summary
+    fs::File,                 // Open the holdings file
+    path::Path,               // Path to the holdings file
+    sync::RwLock,             // Holdings grow with simulated DCA buys
+};
+
+use csv::{ReaderBuilder, Trim};  // CSV parser
+use rust_decimal::Decimal;       // Exact money arithmetic
+use serde::{Deserialize, Serialize};
+
+use crate::fx::{quote_currency, FxRates};
+use crate::store::{PriceStore, PriceUpdate};
+
+// One line of the holdings file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holding {
+    pub symbol: String,        // e.g. "BTC-USD"
+    pub quantity: Decimal,     // Coins held
+    pub cost_basis: Decimal,   // Total paid, in the quote currency
+}
+
+// Raw CSV row; numbers are parsed by hand so they never pass through a float
+#[derive(Debug, Deserialize)]
+struct HoldingRow {
+    symbol: String,
+    quantity: String,
+    cost_basis: String,
+}
+
+// Read the holdings file (see the module comment for the format)
+pub fn load_holdings<P: AsRef<Path>>(path: P) -> Result<Vec<Holding>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let mut rdr = ReaderBuilder::new().has_headers(true).trim(Trim::All).from_reader(file);
+
+    let mut holdings = Vec::new();
+    for (i, row) in rdr.deserialize::<HoldingRow>().enumerate() {
+        let row = row?;
+        let number = |field: &str, text: &str| {
+            text.parse::<Decimal>().map_err(|e| format!("row {}: bad {} \"{}\": {}", i + 1, field, text, e))
+        };
+        holdings.push(Holding {
+            symbol: row.symbol.to_uppercase(),
+            quantity: number("quantity", &row.quantity)?,
+            cost_basis: number("cost_basis", &row.cost_basis)?,
+        });
+    }
+    Ok(holdings)
+}
+
+// One holding valued at the latest price
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Position {
+    pub symbol: String,
+    pub quantity: Decimal,
+    pub cost_basis: Decimal,
+    pub price: Option<Decimal>,               // None until the first update for the symbol arrives
+    pub value: Option<Decimal>,               // quantity × price
+    pub unrealized_pnl: Option<Decimal>,      // value − cost basis
+    pub unrealized_pnl_pct: Option<Decimal>,  // ...as a percentage of the cost basis
+    pub change_24h: Option<Decimal>,          // Value change since 24h ago, when the exchange reports the open
+}
+
+// The whole portfolio at one moment
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Valuation {
+    pub positions: Vec<Position>,
+    pub total_value: Decimal,                 // Sum over positions that have a price
+    pub total_cost: Decimal,                  // Cost basis of those same positions
+    pub unrealized_pnl: Decimal,
+    pub unrealized_pnl_pct: Option<Decimal>,
+    pub change_24h: Decimal,                  // Sum over positions that report a 24h open
+    pub change_24h_pct: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub converted: Option<ConvertedTotals>,   // Totals in the display currency, when one is set
+}
+
+// The totals in the display currency (see fx.rs). Only worked out when every priced
+// position is quoted in USD, so nothing gets converted that wasn't in dollars.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConvertedTotals {
+    pub currency: String,                     // e.g. "EUR"
+    pub rate: Decimal,                        // Units of `currency` per USD
+    pub total_value: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub change_24h: Decimal,
+}
+
+// Holdings plus the maths to value them
+#[derive(Debug)]
+pub struct Portfolio {
+    holdings: RwLock<Vec<Holding>>,
+}
+
+impl Portfolio {
+    pub fn new(holdings: Vec<Holding>) -> Self {
+        Self { holdings: RwLock::new(holdings) }
+    }
+
+    // Load holdings from a CSV file
+    pub fn from_csv<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(load_holdings(path)?))
+    }
+
+    pub fn holdings(&self) -> Vec<Holding> {
+        self.holdings.read().unwrap().clone()
+    }
+
+    // What one coin of the position cost on average; None without a position
+    pub fn average_cost(&self, symbol: &str) -> Option<Decimal> {
+        let holdings = self.holdings.read().unwrap();
+        let holding = holdings.iter().find(|h| h.symbol == symbol && h.quantity > Decimal::ZERO)?;
+        Some(holding.cost_basis / holding.quantity)
+    }
+
+    // A purchase of `quantity` coins for `cost` in all: added to the symbol's holding, or a
+    // new one
+    pub fn add(&self, symbol: &str, quantity: Decimal, cost: Decimal) {
+        let mut holdings = self.holdings.write().unwrap();
+        match holdings.iter_mut().find(|h| h.symbol == symbol) {
+            Some(holding) => {
+                holding.quantity += quantity;
+                holding.cost_basis += cost;
+            }
+            None => holdings.push(Holding { symbol: symbol.to_string(), quantity, cost_basis: cost }),
+        }
+    }
+
+    // Write the holdings in the format `load_holdings` reads
+    pub fn save_csv<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(["symbol", "quantity", "cost_basis"])?;
+        for h in self.holdings.read().unwrap().iter() {
+            writer.write_record([h.symbol.clone(), h.quantity.normalize().to_string(), h.cost_basis.normalize().to_string()])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Value every holding at the latest price from any exchange, with the totals also
+    // in the display currency when one is configured
+    pub async fn value(&self, store: &PriceStore) -> Valuation {
+        let prices = store.view().await;  // One round trip to the store for every holding
+        let mut valuation = self.value_with(|symbol| prices.latest(symbol));
+        valuation.convert(store.fx());
+        valuation
+    }
+
+    // Same as `value`, with the price lookup supplied by the caller
+    pub fn value_with(&self, latest: impl Fn(&str) -> Option<PriceUpdate>) -> Valuation {
+        let holdings = self.holdings.read().unwrap();
+        let mut total = Valuation {
+            positions: Vec::with_capacity(holdings.len()),
+            total_value: Decimal::ZERO,
+            total_cost: Decimal::ZERO,
+            unrealized_pnl: Decimal::ZERO,
+            unrealized_pnl_pct: None,
+            change_24h: Decimal::ZERO,
+            change_24h_pct: None,
+            converted: None,
+        };
+        let mut value_24h_ago = Decimal::ZERO;  // Value of the positions that report an open
+
+        for holding in holdings.iter() {
+            let update = latest(&holding.symbol);
+            let price = update.as_ref().map(|u| u.price);
+            let value = price.map(|p| p * holding.quantity);
+            let pnl = value.map(|v| v - holding.cost_basis);
+            let change_24h = update
+                .as_ref()
+                .and_then(|u| u.open_24h.map(|open| (u.price - open) * holding.quantity));
+
+            if let (Some(value), Some(pnl)) = (value, pnl) {
+                total.total_value += value;
+                total.total_cost += holding.cost_basis;
+                total.unrealized_pnl += pnl;
+            }
+            if let (Some(value), Some(change)) = (value, change_24h) {
+                total.change_24h += change;
+                value_24h_ago += value - change;
+            }
+
+            total.positions.push(Position {
+                symbol: holding.symbol.clone(),
+                quantity: holding.quantity,
+                cost_basis: holding.cost_basis,
+                price,
+                value,
+                unrealized_pnl: pnl,
+                unrealized_pnl_pct: pnl.and_then(|p| percent(p, holding.cost_basis)),
+                change_24h,
+            });
+        }
+
+        total.unrealized_pnl_pct = percent(total.unrealized_pnl, total.total_cost);
+        total.change_24h_pct = percent(total.change_24h, value_24h_ago);
+        total
+    }
+}
+
+impl Valuation {
+    // Fill in `converted` from the current display currency rate, if there is one
+    pub fn convert(&mut self, fx: &FxRates) {
+        let Some(rate) = fx.current() else { return };
+        if self.positions.iter().any(|p| p.price.is_some() && quote_currency(&p.symbol) != "USD") {
+            return;  // Mixed quote currencies: the totals aren't in USD to begin with
+        }
+        let amount = |usd: Decimal| (usd * rate.per_usd).round_dp(2);
+        self.converted = Some(ConvertedTotals {
+            currency: rate.currency,
+            rate: rate.per_usd,
+            total_value: amount(self.total_value),
+            unrealized_pnl: amount(self.unrealized_pnl),
+            change_24h: amount(self.change_24h),
+        });
+    }
+}
+
+// `part` as a percentage of `whole`, rounded to 2 decimals (None when `whole` is zero)
+fn percent(part: Decimal, whole: Decimal) -> Option<Decimal> {
+    (!whole.is_zero()).then(|| (part / whole * Decimal::ONE_HUNDRED).round_dp(2))
+}
+
+// "+12.34%" or "—" when unknown
+fn show_pct(pct: Option<Decimal>) -> String {
+    pct.map(|p| format!("{:+.2}%", p)).unwrap_or_else(|| "—".to_string())
+}
+
+// Text block for the periodic terminal output
+impl fmt::Display for Valuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for p in &self.positions {
+            match (p.value, p.unrealized_pnl) {
+                (Some(value), Some(pnl)) => writeln!(
+                    f,
+                    "{} x{}: ${:.2}  P&L {:+.2} ({})  24h {}",
+                    p.symbol,
+                    p.quantity,
+                    value,
+                    pnl,
+                    show_pct(p.unrealized_pnl_pct),
+                    p.change_24h.map(|c| format!("{:+.2}", c)).unwrap_or_else(|| "—".to_string()),
+                )?,
+                _ => writeln!(f, "{} x{}: no price yet", p.symbol, p.quantity)?,
+            }
+        }
+        write!(
+            f,
+            "Total: ${:.2}  P&L {:+.2} ({})  24h {:+.2} ({})",
+            self.total_value,
+            self.unrealized_pnl,
+            show_pct(self.unrealized_pnl_pct),
+            self.change_24h,
+            show_pct(self.change_24h_pct)
+        )?;
+        if let Some(c) = &self.converted {
+            write!(
+                f,
+                "\n       ≈ {:.2} {}  P&L ≈ {:+.2} {}  24h ≈ {:+.2} {}  (at {} {} per USD)",
+                c.total_value, c.currency, c.unrealized_pnl, c.currency, c.change_24h, c.currency, c.rate, c.currency
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn d(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    fn update(symbol: &str, price: &str, open_24h: Option<&str>) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            price: d(price),
+            open_24h: open_24h.map(d),
+            size: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn values_positions_and_totals() {
+        let portfolio = Portfolio::new(vec![
+            Holding { symbol: "BTC-USD".into(), quantity: d("0.5"), cost_basis: d("25000") },
+            Holding { symbol: "ETH-USD".into(), quantity: d("4"), cost_basis: d("9000") },
+            Holding { symbol: "SOL-USD".into(), quantity: d("10"), cost_basis: d("1500") },
+        ]);
+        let v = portfolio.value_with(|symbol| match symbol {
+            "BTC-USD" => Some(update(symbol, "60000", Some("50000"))),
+            "ETH-USD" => Some(update(symbol, "2000", None)),
+            _ => None,  // No SOL price yet
+        });
+
+        let btc = &v.positions[0];
+        assert_eq!((btc.value, btc.unrealized_pnl), (Some(d("30000")), Some(d("5000"))));
+        assert_eq!((btc.unrealized_pnl_pct, btc.change_24h), (Some(d("20")), Some(d("5000"))));
+        assert_eq!(v.positions[1].unrealized_pnl, Some(d("-1000")));
+        assert_eq!(v.positions[2].value, None);
+
+        // Totals only include priced positions; 24h change only those with an open
+        assert_eq!((v.total_value, v.total_cost, v.unrealized_pnl), (d("38000"), d("34000"), d("4000")));
+        assert_eq!((v.change_24h, v.change_24h_pct), (d("5000"), Some(d("20"))));
+
+        // With a display currency the totals are converted too
+        let (mut v, fx) = (v, FxRates::default());
+        fx.set(crate::fx::Rate { currency: "EUR".into(), per_usd: d("0.9"), fetched_at: SystemTime::now() });
+        v.convert(&fx);
+        let converted = v.converted.as_ref().unwrap();
+        assert_eq!((converted.total_value, converted.unrealized_pnl), (d("34200"), d("3600")));
+        assert!(v.to_string().ends_with("(at 0.9 EUR per USD)"));
+    }
+}
@@ -0,0 +1,92 @@
+// Symbol validation against each exchange's product list. A typo like "BTC-UDS" is
+// accepted by the WebSocket subscribe, but no data ever arrives for it; checking the
+// REST products endpoint at startup turns that silence into a clear error.
+//
+// Fetching needs the "validate" feature (reqwest); the comparison itself is always
+// available.
+
+use std::collections::HashSet;  // Known exchange-native symbols
+
+use crate::exchange::Exchange;
+
+// Our symbols that `exchange` doesn't list among `products` (its native symbols)
+pub fn unknown_symbols(exchange: &dyn Exchange, symbols: &[String], products: &HashSet<String>) -> Vec<String> {
+    symbols.iter().filter(|s| !products.contains(&exchange.native_symbol(s))).cloned().collect()
+}
+
+// Unknown symbols per exchange (exchanges with none are left out). An exchange whose
+// product list can't be fetched is reported and skipped: being offline or rate limited
+// shouldn't stop the tracker from starting.
+#[cfg(feature = "validate")]
+pub async fn check_symbols(
+    exchanges: &[Box<dyn Exchange>],
+    symbols: &[String],
+    network: &crate::net::Network,
+) -> Vec<(&'static str, Vec<String>)> {
+    let client = match network
+        .http_client()
+        .user_agent(concat!("crabbycryptotracker/", env!("CARGO_PKG_VERSION")))
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!(error = %e, "Symbol check skipped");
+            return Vec::new();
+        }
+    };
+
+    let mut unknown = Vec::new();
+    for exchange in exchanges {
+        let Some(url) = exchange.products_url() else { continue };
+        match fetch_products(&client, exchange.as_ref(), url).await {
+            Ok(products) => {
+                let missing = unknown_symbols(exchange.as_ref(), symbols, &products);
+                if !missing.is_empty() {
+                    unknown.push((exchange.name(), missing));
+                }
+            }
+            Err(e) => tracing::warn!(exchange = exchange.name(), error = %e, "Couldn't fetch the product list, symbols not checked"),
+        }
+    }
+    unknown
+}
+
+// Download and parse one exchange's product list
+#[cfg(feature = "validate")]
+async fn fetch_products(
+    client: &reqwest::Client,
+    exchange: &dyn Exchange,
+    url: &str,
+) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
+    let body: serde_json::Value = client.get(url).send().await?.error_for_status()?.json().await?;
+    let products: HashSet<String> = exchange.parse_products(&body).into_iter().collect();
+    if products.is_empty() {
+        return Err("the response listed no products".into());  // Changed format? Better not to reject everything
+    }
+    Ok(products)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::{Coinbase, Kraken};
+    use serde_json::json;
+
+    #[test]
+    fn typos_and_delisted_products_are_unknown() {
+        let symbols = ["BTC-USD".to_string(), "BTC-UDS".to_string(), "LUNA-USD".to_string()];
+
+        let body = json!([
+            {"id": "BTC-USD", "status": "online"},
+            {"id": "LUNA-USD", "status": "delisted"}
+        ]);
+        let products = Coinbase.parse_products(&body).into_iter().collect();
+        assert_eq!(unknown_symbols(&Coinbase, &symbols, &products), ["BTC-UDS", "LUNA-USD"]);
+
+        // Kraken's REST API says XBT where its WebSocket says BTC
+        let body = json!({"error": [], "result": {"XXBTZUSD": {"wsname": "XBT/USD"}}});
+        let products = Kraken.parse_products(&body).into_iter().collect();
+        assert_eq!(unknown_symbols(&Kraken, &symbols[..1], &products), Vec::<String>::new());
+    }
+}
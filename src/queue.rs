@@ -0,0 +1,283 @@
+// Bounded queues between the async side (feeds, the store's broadcast) and consumers
+// that run at their own pace on a thread of their own: the SQLite writer, the CSV
+// snapshot writer. However slow a consumer gets, its queue stays the same size, and
+// what happens to the overflow is spelled out:
+//
+//   DropOldest      make room by dropping the oldest queued item; for things that are
+//                   only worth showing while fresh
+//   Block(timeout)  wait up to `timeout` for the consumer to make room, then drop the
+//                   new item; for storage, where a short stall shouldn't lose anything
+//                   but a dead disk mustn't hold up everything else
+//
+// Dropped items are counted (`dropped`, and crabby_dropped_events_total{consumer} once
+// the queue reports to a `Metrics`), so a slow consumer shows up instead of going quiet.
+//
+// The consumer side blocks a thread (`pop`, `pop_timeout`). On the producer side,
+// `send` waits asynchronously (only `Block` ever waits) and `push` never waits: with a
+// `Block` queue full, it drops the new item straight away.
+//
+// Display consumers read the store's broadcast channel directly, which already behaves
+// like DropOldest: a receiver that falls behind skips to the newest updates, and the
+// number skipped is counted the same way (`Metrics::record_dropped_events`).
+
+use std::{
+    collections::VecDeque,                            // The queued items
+    fmt,                                              // Closed's message
+    sync::{
+        atomic::{AtomicU64, Ordering},                // Drop counter
+        mpsc::RecvTimeoutError,                       // Same results as a std channel
+        Arc, Condvar, Mutex, OnceLock,                // Shared between producers and the consumer
+    },
+    time::{Duration, Instant},                        // Timeouts
+};
+
+use tokio::sync::Notify;                              // Wakes producers waiting for room
+
+use crate::metrics::Metrics;
+
+// What to do with an item when the queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    DropOldest,       // Evict the oldest queued item to make room
+    Block(Duration),  // Wait this long for room, then drop the new item
+}
+
+// The consumer has gone away (`close` was called)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("queue closed")
+    }
+}
+
+impl std::error::Error for Closed {}
+
+// Cheap to clone: every clone is a handle on the same queue
+pub struct BoundedQueue<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    consumer: &'static str,       // Label for the dropped-events counter, e.g. "sqlite"
+    capacity: usize,
+    overflow: Overflow,
+    state: Mutex<State<T>>,
+    ready: Condvar,               // An item arrived, or the queue was closed
+    space: Notify,                // The consumer took an item
+    dropped: AtomicU64,
+    metrics: OnceLock<Metrics>,   // Where drops are reported, once attached to a store
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+impl<T> Clone for BoundedQueue<T> {
+    fn clone(&self) -> Self {
+        Self { shared: Arc::clone(&self.shared) }
+    }
+}
+
+impl<T> BoundedQueue<T> {
+    // `consumer` names the queue in the metrics; `capacity` is at least 1
+    pub fn new(consumer: &'static str, capacity: usize, overflow: Overflow) -> Self {
+        let capacity = capacity.max(1);
+        let shared = Shared {
+            consumer,
+            capacity,
+            overflow,
+            state: Mutex::new(State { items: VecDeque::with_capacity(capacity.min(1024)), closed: false }),
+            ready: Condvar::new(),
+            space: Notify::new(),
+            dropped: AtomicU64::new(0),
+            metrics: OnceLock::new(),
+        };
+        Self { shared: Arc::new(shared) }
+    }
+
+    // Count drops in `metrics` as well (the first call wins)
+    pub fn report_to(&self, metrics: &Metrics) {
+        let _ = self.shared.metrics.set(metrics.clone());
+    }
+
+    // Queue an item without waiting. A full queue drops the oldest item (DropOldest) or
+    // this one (Block); either way it's counted.
+    pub fn push(&self, item: T) -> Result<(), Closed> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.closed {
+            return Err(Closed);
+        }
+        if state.items.len() >= self.shared.capacity {
+            match self.shared.overflow {
+                Overflow::DropOldest => {
+                    state.items.pop_front();
+                }
+                Overflow::Block(_) => {
+                    drop(state);
+                    self.count_dropped(1);
+                    return Ok(());
+                }
+            }
+            self.count_dropped(1);
+        }
+        state.items.push_back(item);
+        self.shared.ready.notify_one();
+        Ok(())
+    }
+
+    // Queue an item, waiting for room up to the Block timeout before dropping it
+    pub async fn send(&self, item: T) -> Result<(), Closed> {
+        let Overflow::Block(timeout) = self.shared.overflow else {
+            return self.push(item);
+        };
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // Register for the wake-up before looking, so one between the two isn't missed
+            let room = self.shared.space.notified();
+            tokio::pin!(room);
+            room.as_mut().enable();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if state.closed {
+                    return Err(Closed);
+                }
+                if state.items.len() < self.shared.capacity {
+                    state.items.push_back(item);
+                    self.shared.ready.notify_one();
+                    return Ok(());
+                }
+            }
+            if tokio::time::timeout_at(deadline, room).await.is_err() {
+                return self.push(item);  // Out of time: one last look, then it's dropped
+            }
+        }
+    }
+
+    // Items lost some other way before they reached the queue (e.g. a lagging broadcast
+    // receiver), so the count covers everything this consumer missed
+    pub fn count_dropped(&self, count: u64) {
+        self.shared.dropped.fetch_add(count, Ordering::Relaxed);
+        if let Some(metrics) = self.shared.metrics.get() {
+            metrics.record_dropped_events(self.shared.consumer, count);
+        }
+    }
+
+    // Items dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Take the next item, waiting for one; None once the queue is closed and empty
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.shared.space.notify_waiters();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.shared.ready.wait(state).unwrap();
+        }
+    }
+
+    // Take the next item, waiting at most `wait`. Like a std channel: Timeout when nothing
+    // came, Disconnected once the queue is closed and empty.
+    pub fn pop_timeout(&self, wait: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + wait;
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.shared.space.notify_waiters();
+                return Ok(item);
+            }
+            if state.closed {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self.shared.ready.wait_timeout(state, left).unwrap().0;
+        }
+    }
+
+    // Refuse new items; the consumer still gets what's queued, then None / Disconnected
+    pub fn close(&self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.ready.notify_all();
+        self.shared.space.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow_policies_drop_and_count() {
+        let metrics = Metrics::new();
+
+        // Display-style: the newest items survive
+        let display = BoundedQueue::new("display", 2, Overflow::DropOldest);
+        display.report_to(&metrics);
+        for i in 1..=4 {
+            display.push(i).unwrap();
+        }
+        assert_eq!((display.pop(), display.pop(), display.dropped()), (Some(3), Some(4), 2));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            // Storage-style: `send` waits for the consumer to make room...
+            let storage = BoundedQueue::new("sqlite", 1, Overflow::Block(Duration::from_secs(5)));
+            storage.report_to(&metrics);
+            storage.send(1).await.unwrap();
+            let consumer = storage.clone();
+            let taken = std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                consumer.pop_timeout(Duration::from_secs(1))
+            });
+            storage.send(2).await.unwrap();
+            assert_eq!(taken.join().unwrap(), Ok(1));
+            assert_eq!(storage.dropped(), 0);
+
+            // ...but only for so long: a stalled consumer costs the new item, not the feed
+            let stalled = BoundedQueue::new("csv", 1, Overflow::Block(Duration::from_millis(20)));
+            stalled.send("kept").await.unwrap();
+            let started = Instant::now();
+            stalled.send("dropped").await.unwrap();
+            assert!(started.elapsed() >= Duration::from_millis(20));
+            stalled.push("dropped too").unwrap();  // Never waits
+            assert_eq!(stalled.dropped(), 2);
+
+            // Closing hands over what's left, then says so
+            stalled.close();
+            assert_eq!(stalled.send("late").await, Err(Closed));
+            assert_eq!(stalled.pop_timeout(Duration::ZERO), Ok("kept"));
+            assert_eq!(stalled.pop_timeout(Duration::ZERO), Err(RecvTimeoutError::Disconnected));
+        });
+        assert_eq!(dropped_events(&metrics), (2, 0));
+    }
+
+    // crabby_dropped_events_total for the display and sqlite queues
+    fn dropped_events(metrics: &Metrics) -> (u64, u64) {
+        let text = metrics.render();
+        let value = |consumer: &str| {
+            let prefix = format!("crabby_dropped_events_total{{consumer=\"{}\"}} ", consumer);
+            text.lines().find_map(|l| l.strip_prefix(&prefix)).map_or(0, |v| v.parse().unwrap())
+        };
+        (value("display"), value("sqlite"))
+    }
+}
@@ -0,0 +1,336 @@
+// Recording raw WebSocket messages and replaying them later.
+//
+// `--record messages.ndjson` copies every text frame the feeds receive into a file,
+// one JSON object per line:
+//
+//   {"ts_ms":1718000000123,"exchange":"coinbase","frame":"{\"type\":\"ticker\",...}"}
+//
+// `--replay messages.ndjson` feeds those frames back through the same parsing and
+// storage code as a live connection (`feed::handle_text_frame`), with the original
+// gaps between them, sped up (`--replay-speed 10`), or as fast as possible
+// (`--replay-speed max`). Handy for reproducing a bug, or developing offline.
+// `backtest --from messages.ndjson` runs the same frames, as fast as possible, through
+// the alert rules instead (see backtest.rs).
+//
+// Like the other file writers, recording happens on a dedicated writer thread.
+
+use std::{
+    collections::{BTreeSet, HashMap},                  // Tracked symbols; connectors by name
+    fs::{File, OpenOptions},                           // The recording file
+    io::{self, BufRead, BufWriter, Write},             // Buffered NDJSON output; reading it back for backtests
+    path::{Path, PathBuf},                             // Recording file names
+    str::FromStr,                                      // "10" / "max" speeds
+    sync::{mpsc, Arc},                                 // Channel from the feeds to the writer thread; shared connectors
+    thread::{self, JoinHandle},                        // The dedicated writer thread
+    time::{Duration, SystemTime},                      // Frame timestamps
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, BufReader};           // Reading the recording line by line
+use tokio::sync::watch;                                // Symbol set and shutdown signal
+use tokio::time::{sleep_until, Instant};               // Replaying at the recorded pace
+use tracing::{info, warn};
+
+use crate::exchange::{self, Exchange};
+use crate::feed::{handle_text_frame, native_symbols, FrameStats};
+use crate::store::{epoch_ms, PriceStore};
+
+// One line of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub ts_ms: u64,        // When it was received (Unix epoch milliseconds)
+    pub exchange: String,  // Connector name, e.g. "coinbase"
+    pub frame: String,     // The text frame exactly as received
+}
+
+// How fast to replay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    Factor(f64),  // 1 = as recorded, 10 = ten times faster
+    Max,          // No waiting between frames at all
+}
+
+impl Default for ReplaySpeed {
+    fn default() -> Self {
+        ReplaySpeed::Factor(1.0)
+    }
+}
+
+impl FromStr for ReplaySpeed {
+    type Err = String;
+
+    // "max", or a factor like "1", "10" or "10x"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim().to_lowercase();
+        if text == "max" {
+            return Ok(ReplaySpeed::Max);
+        }
+        match text.trim_end_matches('x').parse::<f64>() {
+            Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(ReplaySpeed::Factor(factor)),
+            _ => Err(format!("invalid replay speed \"{}\" (expected a factor like 1 or 10x, or max)", s)),
+        }
+    }
+}
+
+// Handle given to the feeds; cheap to clone. Frames sent after the recorder was
+// closed are dropped.
+#[derive(Clone)]
+pub struct RecordSink {
+    tx: mpsc::Sender<Option<RecordedFrame>>,  // None asks the writer thread to stop
+}
+
+impl RecordSink {
+    pub fn record(&self, exchange: &str, frame: &str, received_at: SystemTime) {
+        let frame = RecordedFrame { ts_ms: epoch_ms(received_at), exchange: exchange.to_string(), frame: frame.to_string() };
+        let _ = self.tx.send(Some(frame));  // Err only means the writer has already stopped
+    }
+}
+
+// Owns the recording file and its writer thread
+pub struct Recorder {
+    sink: RecordSink,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    // Open `path` for appending and start the writer thread
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (tx, rx) = mpsc::channel::<Option<RecordedFrame>>();
+        let thread = thread::Builder::new().name("recorder".to_string()).spawn(move || {
+            let mut out = BufWriter::new(file);
+            let mut next = rx.recv();
+            while let Ok(Some(frame)) = next {
+                let written = serde_json::to_writer(&mut out, &frame).map_err(io::Error::from).and_then(|_| out.write_all(b"\n"));
+                if let Err(e) = written {
+                    warn!(error = %e, "Couldn't write to the recording, stopped recording");
+                    return;
+                }
+                // Flush whenever the queue runs dry, so a crash loses as little as possible
+                next = match rx.try_recv() {
+                    Ok(frame) => Ok(frame),
+                    Err(mpsc::TryRecvError::Empty) => {
+                        let _ = out.flush();
+                        rx.recv()
+                    }
+                    Err(mpsc::TryRecvError::Disconnected) => break,
+                };
+            }
+            let _ = out.flush();
+        })?;
+        Ok(Self { sink: RecordSink { tx }, thread: Some(thread) })
+    }
+
+    pub fn sink(&self) -> RecordSink {
+        self.sink.clone()
+    }
+
+    // Write out what's queued and wait for the file to be flushed
+    pub fn close(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        let _ = self.sink.tx.send(None);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Whether `path` holds a recording rather than snapshot ticks: its first line is a
+// recorded frame
+pub fn is_recording(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let first = io::BufReader::new(file).lines().map_while(Result::ok).find(|line| !line.trim().is_empty());
+    first.is_some_and(|line| serde_json::from_str::<RecordedFrame>(&line).is_ok())
+}
+
+// A whole recording, in file order (the order the frames arrived in)
+pub fn read_recording(path: &Path) -> io::Result<Vec<RecordedFrame>> {
+    let mut frames = Vec::new();
+    for (i, line) in io::BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let frame = serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e)))?;
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+// Hands recorded frames to the frame handling a live feed uses, with the connector
+// each one came from
+pub struct FrameFeeder {
+    connectors: Vec<Arc<dyn Exchange>>,                            // Configured ones, which may carry settings
+    exchanges: HashMap<String, Option<Arc<dyn Exchange>>>,         // Looked up once per name
+    to_common: HashMap<&'static str, HashMap<String, String>>,     // Native → our symbol names, per connector
+    symbols: BTreeSet<String>,
+    order_books: bool,
+    frame_stats: FrameStats,
+}
+
+impl FrameFeeder {
+    pub fn new(connectors: Vec<Arc<dyn Exchange>>, symbols: BTreeSet<String>, order_books: bool) -> Self {
+        Self {
+            connectors,
+            exchanges: HashMap::new(),
+            to_common: HashMap::new(),
+            symbols,
+            order_books,
+            frame_stats: FrameStats::default(),
+        }
+    }
+
+    // Follow these symbols from the next frame on
+    pub fn set_symbols(&mut self, symbols: BTreeSet<String>) {
+        self.symbols = symbols;
+        self.to_common.clear();
+    }
+
+    // The connector for a recorded exchange name: the configured one, or a plain one
+    fn exchange(&mut self, name: &str) -> Option<Arc<dyn Exchange>> {
+        if !self.exchanges.contains_key(name) {
+            let configured = self.connectors.iter().find(|ex| ex.name().eq_ignore_ascii_case(name.trim())).cloned();
+            let connector = configured.or_else(|| exchange::by_name(name).map(Arc::from));
+            self.exchanges.insert(name.to_string(), connector);
+        }
+        self.exchanges[name].clone()
+    }
+
+    // Apply one frame to `store` as if it had just been received. False when no connector
+    // this build knows sent it.
+    pub async fn feed(&mut self, recorded: &RecordedFrame, store: &PriceStore) -> bool {
+        let Some(exchange) = self.exchange(&recorded.exchange) else {
+            return false;
+        };
+        if !self.to_common.contains_key(exchange.name()) {
+            self.to_common.insert(exchange.name(), native_symbols(exchange.as_ref(), &self.symbols));
+        }
+        store.metrics().record_message(exchange.name());
+        let received_at = SystemTime::UNIX_EPOCH + Duration::from_millis(recorded.ts_ms);
+        let to_common = &self.to_common[exchange.name()];
+        handle_text_frame(exchange.as_ref(), &recorded.frame, received_at, to_common, self.order_books, store, &mut self.frame_stats).await;
+        true
+    }
+}
+
+// Feed a recording through the normal frame handling into `store`, for the symbols in
+// `symbols` (which may change while replaying, like a live feed's). Frames are parsed by
+// the matching connector in `connectors` (which may carry settings, like the DEX pools),
+// or a plain one by name. Returns at the end of the file, or early when `shutdown` flips
+// to true.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_replay(
+    path: PathBuf,
+    connectors: Vec<Arc<dyn Exchange>>,
+    speed: ReplaySpeed,
+    mut symbols: watch::Receiver<BTreeSet<String>>,
+    store: PriceStore,
+    order_books: bool,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "Couldn't open the recording");
+            return;
+        }
+    };
+    let mut lines = BufReader::new(file).lines();
+
+    let mut feeder = FrameFeeder::new(connectors, symbols.borrow_and_update().clone(), order_books);
+    let (mut replayed, mut skipped) = (0u64, 0u64);
+    let started = Instant::now();
+    let mut first_ts = None;  // Timestamp of the first frame: the start of the recording
+
+    info!(path = %path.display(), ?speed, "Replaying recording");
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line,
+            _ = shutdown.changed() => return,
+        };
+        let line = match line {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                warn!(error = %e, "Couldn't read the recording");
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let recorded: RecordedFrame = match serde_json::from_str(&line) {
+            Ok(recorded) => recorded,
+            Err(e) => {
+                skipped += 1;
+                warn!(error = %e, "Skipped a malformed line in the recording");
+                continue;
+            }
+        };
+        // Wait until this frame's moment comes round, relative to the first one
+        if let ReplaySpeed::Factor(factor) = speed {
+            let first = *first_ts.get_or_insert(recorded.ts_ms);
+            let offset = Duration::from_millis(recorded.ts_ms.saturating_sub(first)).div_f64(factor);
+            tokio::select! {
+                _ = sleep_until(started + offset) => {}
+                _ = shutdown.changed() => return,
+            }
+        } else {
+            tokio::task::yield_now().await;  // Let the consumers keep up a little
+        }
+
+        // Symbols may have been added or removed meanwhile
+        if symbols.has_changed().unwrap_or(false) {
+            feeder.set_symbols(symbols.borrow_and_update().clone());
+        }
+
+        if feeder.feed(&recorded, &store).await {
+            replayed += 1;
+        } else {
+            skipped += 1;  // Not a connector this build knows
+        }
+    }
+    info!(frames = replayed, skipped, "Replay finished");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_frames_replay_into_the_store() {
+        let path = std::env::temp_dir().join(format!("crabby-recording-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let recorder = Recorder::create(&path).unwrap();
+        let sink = recorder.sink();
+        let at = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_000);
+        sink.record("coinbase", r#"{"type":"ticker","product_id":"BTC-USD","price":"65000.5"}"#, at);
+        sink.record("coinbase", r#"{"type":"ticker","product_id":"DOGE-USD","price":"0.1"}"#, at);
+        recorder.close();
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        let store = PriceStore::new();
+        let symbols = watch::channel(BTreeSet::from(["BTC-USD".to_string()])).1;
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        runtime.block_on(run_replay(path.clone(), Vec::new(), ReplaySpeed::Max, symbols, store.clone(), false, shutdown));
+
+        let update = runtime.block_on(store.latest_on("coinbase", "BTC-USD")).unwrap();
+        assert_eq!(update.price, "65000.5".parse().unwrap());
+        assert_eq!(update.received_at, at);  // Recorded time, not replay time
+        assert!(runtime.block_on(store.latest("DOGE-USD")).is_none(), "untracked symbols are skipped");
+        assert_eq!("10x".parse(), Ok(ReplaySpeed::Factor(10.0)));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,526 @@
+// Redis sink: keeps a `price:{symbol}` key per symbol (with a TTL, so a stopped
+// tracker doesn't leave stale prices behind) and PUBLISHes every update as JSON on a
+// channel. Other services then read the latest price with a plain `GET price:BTC-USD`,
+// or `SUBSCRIBE prices` to follow along, without talking to the exchanges themselves.
+//
+// With several exchanges the key holds whichever exchange reported last; the JSON on
+// the channel says which one it was.
+//
+// Commands are queued and written by a background task, pipelined: everything queued
+// since the last round trip goes out in one write, then the replies are read back.
+// The same rules as the other sinks apply: a slow Redis fills the queue and an
+// unreachable one gets nothing (it's reconnected with backoff), and in both cases
+// updates are dropped rather than held up.
+
+use std::{io, time::Duration};
+
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::{broadcast::error::RecvError, mpsc},
+    task::JoinHandle,
+    time::timeout,
+};
+use tracing::{info, warn};
+
+use crate::backoff::Backoff;
+use crate::config::RedisConfig;
+use crate::store::{PriceStore, PriceUpdate};
+
+// Updates waiting for Redis before new ones are dropped
+const QUEUE_CAPACITY: usize = 4096;
+
+// Most updates sent in one pipelined round trip
+const MAX_PIPELINE: usize = 256;
+
+// Per round trip, connecting included
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Longest bulk string accepted in a reply. SET and PUBLISH only ever get short answers:
+// a length beyond this is a broken or hostile server, not something to allocate for.
+const MAX_BULK_LEN: usize = 1 << 20;
+
+// Messages understood by the writer task
+enum Command {
+    Update(PriceUpdate),
+    Close,  // Write what's queued and stop
+}
+
+// Where to connect and what to write, from the [redis] settings
+#[derive(Debug, Clone)]
+struct Options {
+    host: String,
+    port: u16,
+    username: Option<String>,  // Redis 6 ACL user; plain AUTH without it
+    password: Option<String>,
+    database: u32,             // SELECT, from the url's path
+    key_prefix: String,
+    ttl: Duration,             // Zero: keys don't expire
+    channel: String,           // Empty: don't publish
+}
+
+// Handle to the writer task
+pub struct RedisSink {
+    queue: mpsc::Sender<Command>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl RedisSink {
+    // A sink for the [redis] settings, or None when no url is set.
+    // Must be called from inside a Tokio runtime.
+    pub fn from_config(config: &RedisConfig) -> Result<Option<Self>, String> {
+        let Some(url) = &config.url else {
+            return Ok(None);
+        };
+        let parsed = url::Url::parse(url).map_err(|e| format!("{}: {}", url, e))?;
+        if parsed.scheme() != "redis" {
+            return Err(format!("unsupported scheme \"{}\" (expected redis://)", parsed.scheme()));
+        }
+        let database = match parsed.path().trim_start_matches('/') {
+            "" => 0,
+            db => db.parse().map_err(|_| format!("\"{}\" isn't a database number", db))?,
+        };
+        let options = Options {
+            host: parsed.host_str().ok_or("no Redis host")?.to_string(),
+            port: parsed.port().unwrap_or(6379),
+            username: Some(parsed.username().to_string()).filter(|u| !u.is_empty()),
+            password: config.password.clone().or_else(|| parsed.password().map(str::to_string)),
+            database,
+            key_prefix: config.key_prefix.clone(),
+            ttl: config.ttl,
+            channel: config.channel.clone(),
+        };
+        let (queue, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let task = tokio::spawn(run(options, rx));
+        Ok(Some(Self { queue, task: Some(task) }))
+    }
+
+    // Queue one update. Never blocks: with the queue full, it's dropped.
+    pub fn record(&self, update: PriceUpdate) {
+        if self.queue.try_send(Command::Update(update)).is_err() {
+            warn!("Redis queue full, dropped a price update");
+        }
+    }
+
+    // Spawn a task that writes every update from `store`
+    pub fn attach(&self, store: &PriceStore) -> JoinHandle<()> {
+        let queue = self.queue.clone();
+        let mut rx = store.subscribe_updates();
+        tokio::spawn(async move {
+            let mut dropped = 0u64;
+            loop {
+                match rx.recv().await {
+                    Ok(update) => match queue.try_send(Command::Update(update)) {
+                        Ok(()) if dropped > 0 => {
+                            warn!(dropped, "Redis caught up again, some updates were dropped");
+                            dropped = 0;
+                        }
+                        Ok(()) => {}
+                        Err(mpsc::error::TrySendError::Full(_)) => dropped += 1,
+                        Err(mpsc::error::TrySendError::Closed(_)) => break,  // Sink was closed
+                    },
+                    Err(RecvError::Lagged(n)) => dropped += n,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    // Write what's queued and wait for the task (at most IO_TIMEOUT)
+    pub async fn close(mut self) {
+        let _ = timeout(IO_TIMEOUT, self.queue.send(Command::Close)).await;
+        if let Some(task) = self.task.take()
+            && timeout(IO_TIMEOUT, task).await.is_err()
+        {
+            warn!("Redis didn't take the last updates in time");
+        }
+    }
+}
+
+// The writer task: connect, write until something breaks, back off, reconnect
+async fn run(options: Options, mut rx: mpsc::Receiver<Command>) {
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+    loop {
+        let result = match timeout(IO_TIMEOUT, connect(&options)).await {
+            Ok(Ok(conn)) => {
+                info!(host = %options.host, port = options.port, db = options.database, "Connected to Redis");
+                backoff.reset();
+                write_updates(conn, &options, &mut rx).await
+            }
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out")),
+        };
+        match result {
+            Ok(()) => break,  // Closed on purpose
+            Err(e) => {
+                let delay = backoff.next_delay();
+                warn!(host = %options.host, error = %e, ?delay, "Redis connection lost, reconnecting");
+                // Updates arriving meanwhile are dropped; a Close still ends the task right away
+                let (wait, mut dropped) = (tokio::time::sleep(delay), 0u64);
+                tokio::pin!(wait);
+                loop {
+                    tokio::select! {
+                        () = &mut wait => break,
+                        command = rx.recv() => match command {
+                            Some(Command::Update(_)) => dropped += 1,
+                            Some(Command::Close) | None => return,
+                        },
+                    }
+                }
+                if dropped > 0 {
+                    warn!(dropped, "Redis unreachable, updates dropped");
+                }
+            }
+        }
+    }
+}
+
+type Connection = BufReader<TcpStream>;
+
+// Connect, then AUTH and SELECT as configured
+async fn connect(options: &Options) -> io::Result<Connection> {
+    let stream = TcpStream::connect((options.host.as_str(), options.port)).await?;
+    stream.set_nodelay(true)?;
+    let mut conn = BufReader::new(stream);
+    let mut setup = Vec::new();
+    match (&options.username, &options.password) {
+        (Some(user), Some(password)) => setup.push(command(&["AUTH", user, password])),
+        (None, Some(password)) => setup.push(command(&["AUTH", password])),
+        _ => {}
+    }
+    if options.database != 0 {
+        setup.push(command(&["SELECT", &options.database.to_string()]));
+    }
+    if !setup.is_empty() {
+        conn.write_all(&setup.concat()).await?;
+        for _ in 0..setup.len() {
+            read_reply(&mut conn).await?.map_err(|e| io::Error::other(format!("Redis refused the setup: {}", e)))?;
+        }
+    }
+    Ok(conn)
+}
+
+// Pipeline queued updates until Close (Ok) or a connection error
+async fn write_updates(mut conn: Connection, options: &Options, rx: &mut mpsc::Receiver<Command>) -> io::Result<()> {
+    loop {
+        // Wait for one update, then take whatever else is already queued
+        let mut updates = Vec::new();
+        let mut closing = false;
+        match rx.recv().await {
+            Some(Command::Update(update)) => updates.push(update),
+            Some(Command::Close) | None => closing = true,
+        }
+        while !closing && updates.len() < MAX_PIPELINE {
+            match rx.try_recv() {
+                Ok(Command::Update(update)) => updates.push(update),
+                Ok(Command::Close) => closing = true,
+                Err(_) => break,
+            }
+        }
+
+        let commands: Vec<Vec<u8>> = updates.iter().flat_map(|u| commands(options, u)).collect();
+        if !commands.is_empty() {
+            timeout(IO_TIMEOUT, round_trip(&mut conn, &commands))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no reply from Redis"))??;
+        }
+        if closing {
+            return Ok(());
+        }
+    }
+}
+
+// Send a batch of commands and read one reply per command
+async fn round_trip(conn: &mut Connection, commands: &[Vec<u8>]) -> io::Result<()> {
+    conn.write_all(&commands.concat()).await?;
+    let mut errors = 0;
+    let mut last_error = String::new();
+    for _ in commands {
+        if let Err(e) = read_reply(conn).await? {
+            errors += 1;
+            last_error = e;
+        }
+    }
+    if errors > 0 {
+        warn!(errors, error = %last_error, "Redis rejected some commands");
+    }
+    Ok(())
+}
+
+// SET price:{symbol} (with its TTL) and PUBLISH the update as JSON
+fn commands(options: &Options, update: &PriceUpdate) -> Vec<Vec<u8>> {
+    let key = format!("{}:{}", options.key_prefix, update.symbol);
+    let price = update.price.to_string();
+    let mut out = Vec::with_capacity(2);
+    if options.ttl.is_zero() {
+        out.push(command(&["SET", &key, &price]));
+    } else {
+        out.push(command(&["SET", &key, &price, "PX", &options.ttl.as_millis().to_string()]));
+    }
+    if !options.channel.is_empty() {
+        let json = serde_json::to_string(update).unwrap_or_default();
+        out.push(command(&["PUBLISH", &options.channel, &json]));
+    }
+    out
+}
+
+// One command in RESP: an array of bulk strings
+fn command(args: &[&str]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+// Read one complete reply; Ok(Err(message)) for a Redis error reply
+async fn read_reply<R: AsyncBufRead + Unpin>(conn: &mut R) -> io::Result<Result<(), String>> {
+    let mut remaining = 1u64;  // Values still to read; arrays add their elements
+    let mut error = None;
+    let mut line = String::new();
+    while remaining > 0 {
+        remaining -= 1;
+        line.clear();
+        if conn.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Redis closed the connection"));
+        }
+        let (kind, rest) = line.trim_end().split_at_checked(1).unwrap_or(("", ""));
+        let count = || rest.parse::<i64>().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad length in reply"));
+        match kind {
+            "+" | ":" => {}
+            "-" => error = Some(rest.to_string()),
+            "$" => {
+                let len = count()?;
+                if len >= 0 {
+                    let len = usize::try_from(len).ok().filter(|&len| len <= MAX_BULK_LEN).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("reply of {} bytes, more than the {} allowed", len, MAX_BULK_LEN))
+                    })?;
+                    let mut data = vec![0; len + 2];  // Plus the CRLF
+                    conn.read_exact(&mut data).await?;
+                }
+            }
+            "*" => remaining += count()?.max(0) as u64,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected reply {:?}", line))),
+        }
+    }
+    Ok(error.map_or(Ok(()), Err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedisConfig;
+    use tokio::net::TcpListener;
+
+    // Read one RESP command as its arguments
+    async fn read_command(conn: &mut Connection) -> Vec<String> {
+        let mut line = String::new();
+        conn.read_line(&mut line).await.unwrap();
+        let count: usize = line.trim_end()[1..].parse().unwrap();
+        let mut args = Vec::new();
+        for _ in 0..count {
+            line.clear();
+            conn.read_line(&mut line).await.unwrap();  // $len
+            line.clear();
+            conn.read_line(&mut line).await.unwrap();
+            args.push(line.trim_end().to_string());
+        }
+        args
+    }
+
+    fn update(price: &str) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: std::time::SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn sets_keys_with_a_ttl_and_publishes_updates() {
+        run(async {
+            // A Redis that answers AUTH, SET and PUBLISH and keeps what it was sent
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let server = tokio::spawn(async move {
+                let (client, _) = listener.accept().await.unwrap();
+                let mut client = BufReader::new(client);
+                let mut received = Vec::new();
+                while received.len() < 3 {
+                    let args = read_command(&mut client).await;
+                    let reply: &[u8] = if args[0] == "PUBLISH" { b":0\r\n" } else { b"+OK\r\n" };
+                    client.write_all(reply).await.unwrap();
+                    received.push(args);
+                }
+                received
+            });
+
+            let config = RedisConfig { url: Some(format!("redis://:secret@127.0.0.1:{}", port)), ..RedisConfig::default() };
+            let sink = RedisSink::from_config(&config).unwrap().unwrap();
+            sink.record(update("65000.01"));
+            sink.close().await;
+
+            let received = server.await.unwrap();
+            assert_eq!(received[0], ["AUTH", "secret"]);
+            assert_eq!(received[1], ["SET", "price:BTC-USD", "65000.01", "PX", "60000"]);
+            assert_eq!(received[2][..2], ["PUBLISH", "prices"]);
+            assert!(received[2][2].contains(r#""symbol":"BTC-USD""#));
+        });
+    }
+
+    #[test]
+    fn commands_and_replies_round_trip() {
+        assert_eq!(command(&["SET", "price:BTC-USD", "65000"]), b"*3\r\n$3\r\nSET\r\n$13\r\nprice:BTC-USD\r\n$5\r\n65000\r\n");
+        assert_eq!(command(&["PUBLISH", "prices", ""]), b"*3\r\n$7\r\nPUBLISH\r\n$6\r\nprices\r\n$0\r\n\r\n");
+
+        // Every kind of reply, one after the other: each read takes exactly one
+        let replies = concat!(
+            "+OK\r\n",
+            ":42\r\n",
+            "$5\r\nhe\r\no\r\n",                      // Bulk strings can hold CRLF
+            "$-1\r\n",                                 // Null
+            "*3\r\n$3\r\nfoo\r\n*1\r\n:1\r\n+OK\r\n",  // Nested arrays
+            "*-1\r\n",
+            "-ERR unknown command 'FOO'\r\n",
+            "*2\r\n-WRONGTYPE not a string\r\n:1\r\n", // An error inside an array
+        );
+        let mut conn = replies.as_bytes();
+        run(async {
+            for _ in 0..6 {
+                assert_eq!(read_reply(&mut conn).await.unwrap(), Ok(()));
+            }
+            assert_eq!(read_reply(&mut conn).await.unwrap(), Err("ERR unknown command 'FOO'".to_string()));
+            assert_eq!(read_reply(&mut conn).await.unwrap(), Err("WRONGTYPE not a string".to_string()));
+            assert_eq!(read_reply(&mut conn).await.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        });
+    }
+
+    #[test]
+    fn malformed_and_oversized_replies_are_errors() {
+        run(async {
+            for (reply, kind) in [
+                ("?what\r\n", io::ErrorKind::InvalidData),
+                ("\r\n", io::ErrorKind::InvalidData),
+                ("$five\r\nhello\r\n", io::ErrorKind::InvalidData),
+                ("*many\r\n", io::ErrorKind::InvalidData),
+                ("$1048577\r\n", io::ErrorKind::InvalidData),             // Over MAX_BULK_LEN, never allocated
+                ("$9223372036854775807\r\n", io::ErrorKind::InvalidData),
+                ("$5\r\nhel", io::ErrorKind::UnexpectedEof),              // Cut off
+                ("*2\r\n+OK\r\n", io::ErrorKind::UnexpectedEof),
+            ] {
+                let err = read_reply(&mut reply.as_bytes()).await.unwrap_err();
+                assert_eq!(err.kind(), kind, "{:?}: {}", reply, err);
+            }
+            let mut largest = format!("${}\r\n", MAX_BULK_LEN).into_bytes();
+            largest.extend(std::iter::repeat_n(b'x', MAX_BULK_LEN));
+            largest.extend_from_slice(b"\r\n");
+            assert_eq!(read_reply(&mut largest.as_slice()).await.unwrap(), Ok(()));
+        });
+    }
+
+    #[test]
+    fn error_replies_keep_the_connection() {
+        run(async {
+            // A Redis out of memory for the first SET, fine again for the next
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let (refused_tx, refused) = tokio::sync::oneshot::channel();
+            let server = tokio::spawn(async move {
+                let (client, _) = listener.accept().await.unwrap();
+                let mut client = BufReader::new(client);
+                let first = read_command(&mut client).await;
+                client.write_all(b"-OOM command not allowed when used memory > 'maxmemory'\r\n").await.unwrap();
+                refused_tx.send(()).unwrap();
+                let second = read_command(&mut client).await;  // Same connection
+                client.write_all(b"+OK\r\n").await.unwrap();
+                (first, second)
+            });
+
+            let config = RedisConfig { url: Some(format!("redis://127.0.0.1:{}", port)), channel: String::new(), ..RedisConfig::default() };
+            let sink = RedisSink::from_config(&config).unwrap().unwrap();
+            sink.record(update("65000"));
+            refused.await.unwrap();
+            sink.record(update("65001"));
+            sink.close().await;
+
+            let (first, second) = server.await.unwrap();
+            assert_eq!(first[..3], ["SET", "price:BTC-USD", "65000"]);
+            assert_eq!(second[..3], ["SET", "price:BTC-USD", "65001"]);
+        });
+    }
+
+    #[test]
+    fn refused_setup_fails_the_connection() {
+        run(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let server = tokio::spawn(async move {
+                let (client, _) = listener.accept().await.unwrap();
+                let mut client = BufReader::new(client);
+                let auth = read_command(&mut client).await;
+                client.write_all(b"-WRONGPASS invalid username-password pair\r\n").await.unwrap();
+                auth
+            });
+
+            let options = Options {
+                host: "127.0.0.1".to_string(),
+                port,
+                username: Some("tracker".to_string()),
+                password: Some("wrong".to_string()),
+                database: 0,
+                key_prefix: "price".to_string(),
+                ttl: Duration::ZERO,
+                channel: String::new(),
+            };
+            let err = connect(&options).await.unwrap_err();
+            assert!(err.to_string().contains("WRONGPASS"), "{}", err);
+            assert_eq!(server.await.unwrap(), ["AUTH", "tracker", "wrong"]);
+        });
+    }
+
+    #[test]
+    fn reconnects_with_backoff_after_the_server_drops() {
+        run(async {
+            // A Redis that hangs up on the first update without answering, then behaves
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let (back_tx, back) = tokio::sync::oneshot::channel();
+            let server = tokio::spawn(async move {
+                let (client, _) = listener.accept().await.unwrap();
+                let mut client = BufReader::new(client);
+                let lost = read_command(&mut client).await;
+                drop(client);
+                let dropped_at = std::time::Instant::now();
+
+                let (client, _) = listener.accept().await.unwrap();
+                let waited = dropped_at.elapsed();
+                back_tx.send(()).unwrap();
+                let mut client = BufReader::new(client);
+                let again = read_command(&mut client).await;
+                client.write_all(b"+OK\r\n").await.unwrap();
+                (lost, waited, again)
+            });
+
+            let config = RedisConfig { url: Some(format!("redis://127.0.0.1:{}", port)), channel: String::new(), ..RedisConfig::default() };
+            let sink = RedisSink::from_config(&config).unwrap().unwrap();
+            sink.record(update("65000"));
+            back.await.unwrap();
+            sink.record(update("65002"));
+            sink.close().await;
+
+            let (lost, waited, again) = server.await.unwrap();
+            assert_eq!(lost[..3], ["SET", "price:BTC-USD", "65000"]);
+            assert!(waited >= Duration::from_millis(500), "reconnected after {:?}, without backing off", waited);
+            assert_eq!(again[..3], ["SET", "price:BTC-USD", "65002"]);
+        });
+    }
+}
@@ -0,0 +1,310 @@
+// Local WebSocket relay: rebroadcasts the normalized price updates (and candle
+// closes) to any number of downstream clients, so a browser dashboard or another tool
+// can follow the feed without opening its own exchange connection.
+//
+// Connect to ws://127.0.0.1:9001/ for everything, or pick symbols up front with
+// ws://127.0.0.1:9001/?symbols=BTC-USD,ETH-USD. The filter can be changed later by
+// sending:
+//
+//   {"action":"subscribe","symbols":["SOL-USD"]}
+//   {"action":"unsubscribe","symbols":["ETH-USD"]}
+//
+// The relay sends one JSON object per message, tagged by "type":
+//
+//   {"type":"ticker","exchange":"coinbase","symbol":"BTC-USD","price":"65000.5",...}
+//   {"type":"candle","exchange":"coinbase","symbol":"BTC-USD","interval":"1m","open":...}
+//   {"type":"subscriptions","all":false,"symbols":["BTC-USD"]}   (after every change)
+//
+// New clients first get the latest price of every symbol they asked for. A client
+// that can't keep up skips updates rather than slowing the others down.
+
+use std::{
+    collections::BTreeSet,   // A client's symbol filter
+    io,                      // Bind errors
+    net::SocketAddr,         // Listen address, client addresses
+    time::Duration,          // Candle intervals
+};
+
+use futures_util::{SinkExt, StreamExt};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::{handshake::server::{Request, Response}, Message};
+use tracing::{debug, info, warn};
+
+use crate::candles::Candle;
+use crate::store::{PriceStore, PriceUpdate};
+
+// Candle closes a slow client may fall behind by before it misses some
+const CANDLE_CHANNEL_CAPACITY: usize = 256;
+
+// A finished candle, as sent to clients
+#[derive(Debug, Clone, Serialize)]
+pub struct CandleEvent {
+    pub exchange: &'static str,
+    pub symbol: String,
+    pub interval: String,   // e.g. "1m"
+    pub start_ms: u64,      // Bucket start, milliseconds since the Unix epoch
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub trades: u64,
+}
+
+impl From<&Candle> for CandleEvent {
+    fn from(c: &Candle) -> Self {
+        Self {
+            exchange: c.exchange,
+            symbol: c.symbol.clone(),
+            interval: humantime::format_duration(Duration::from_secs(c.interval.as_secs())).to_string(),
+            start_ms: c.start_ms,
+            open: c.open,
+            high: c.high,
+            low: c.low,
+            close: c.close,
+            volume: c.volume,
+            trades: c.trades,
+        }
+    }
+}
+
+// Everything the relay sends
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Event<'a> {
+    Ticker(&'a PriceUpdate),
+    Candle(&'a CandleEvent),
+    Subscriptions { all: bool, symbols: Vec<&'a String> },
+    Error { message: String },
+}
+
+// What clients may send
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum ClientMessage {
+    Subscribe { symbols: Vec<String> },
+    Unsubscribe { symbols: Vec<String> },
+}
+
+// Which symbols one client wants
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Filter {
+    symbols: Option<BTreeSet<String>>,  // None = everything
+}
+
+impl Filter {
+    // From a "?symbols=BTC-USD,ETH-USD" query string; no such parameter means everything
+    fn from_query(query: Option<&str>) -> Self {
+        let symbols = query.into_iter().flat_map(|q| q.split('&')).find_map(|pair| pair.strip_prefix("symbols="));
+        Self { symbols: symbols.map(|list| list.split(',').filter(|s| !s.is_empty()).map(normalize).collect()) }
+    }
+
+    fn matches(&self, symbol: &str) -> bool {
+        self.symbols.as_ref().is_none_or(|set| set.contains(symbol))
+    }
+
+    fn apply(&mut self, message: ClientMessage) {
+        match message {
+            // Subscribing from "everything" starts an explicit list
+            ClientMessage::Subscribe { symbols } => {
+                self.symbols.get_or_insert_with(BTreeSet::new).extend(symbols.iter().map(|s| normalize(s)))
+            }
+            ClientMessage::Unsubscribe { symbols } => {
+                if let Some(set) = &mut self.symbols {
+                    for symbol in &symbols {
+                        set.remove(&normalize(symbol));
+                    }
+                }
+            }
+        }
+    }
+
+    fn event(&self) -> Event<'_> {
+        Event::Subscriptions { all: self.symbols.is_none(), symbols: self.symbols.iter().flatten().collect() }
+    }
+}
+
+// "btc-usd" and "BTC-USD" are the same symbol
+fn normalize(symbol: &str) -> String {
+    symbol.trim().to_uppercase()
+}
+
+// The listening relay. Bind it first (so a busy port fails at startup), hand
+// `candle_sink` to the candle aggregator, then `run` it.
+pub struct Relay {
+    listener: TcpListener,
+    candles: broadcast::Sender<CandleEvent>,
+}
+
+impl Relay {
+    pub async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let (candles, _) = broadcast::channel(CANDLE_CHANNEL_CAPACITY);
+        Ok(Self { listener, candles })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Callback for `spawn_candles`: passes every closed candle on to the clients
+    pub fn candle_sink(&self) -> impl Fn(&Candle) + Send + 'static {
+        let candles = self.candles.clone();
+        move |candle: &Candle| {
+            let _ = candles.send(CandleEvent::from(candle));  // Err only means no clients right now
+        }
+    }
+
+    // Accept clients until the task is dropped
+    pub async fn run(self, store: PriceStore) {
+        if let Ok(addr) = self.listener.local_addr() {
+            info!("WebSocket relay listening on ws://{}", addr);
+        }
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    // Subscribe before the handshake so nothing slips through in between
+                    let (updates, candles) = (store.subscribe_updates(), self.candles.subscribe());
+                    tokio::spawn(serve_client(stream, peer, store.clone(), updates, candles));
+                }
+                Err(e) => warn!(error = %e, "Relay couldn't accept a connection"),
+            }
+        }
+    }
+}
+
+// One client, from handshake to disconnect
+async fn serve_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    store: PriceStore,
+    mut updates: broadcast::Receiver<PriceUpdate>,
+    mut candles: broadcast::Receiver<CandleEvent>,
+) {
+    let mut query = None;
+    #[allow(clippy::result_large_err)]  // The callback's signature is tungstenite's
+    let handshake = tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response: Response| {
+        query = request.uri().query().map(str::to_string);
+        Ok(response)
+    });
+    let mut ws = match handshake.await {
+        Ok(ws) => ws,
+        Err(e) => {
+            debug!(%peer, error = %e, "Relay handshake failed");
+            return;
+        }
+    };
+    let mut filter = Filter::from_query(query.as_deref());
+    info!(%peer, symbols = ?filter.symbols, "Relay client connected");
+
+    let result: Result<(), tokio_tungstenite::tungstenite::Error> = async {
+        // Start with what's already known, then follow the stream
+        send(&mut ws, &filter.event()).await?;
+        for update in store.snapshot().await.iter().filter(|u| filter.matches(&u.symbol)) {
+            send(&mut ws, &Event::Ticker(update)).await?;
+        }
+        loop {
+            tokio::select! {
+                incoming = ws.next() => match incoming {
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(message) => {
+                            filter.apply(message);
+                            send(&mut ws, &filter.event()).await?;
+                        }
+                        Err(e) => send(&mut ws, &Event::Error { message: format!("invalid request: {}", e) }).await?,
+                    },
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => {}  // Pings are answered by tungstenite; binary is ignored
+                    Some(Err(e)) => return Err(e),
+                },
+                update = updates.recv() => match update {
+                    Ok(update) if filter.matches(&update.symbol) => send(&mut ws, &Event::Ticker(&update)).await?,
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(n)) => {
+                        debug!(%peer, skipped = n, "Relay client fell behind");
+                        store.metrics().record_dropped_events("relay", n);
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                candle = candles.recv() => match candle {
+                    Ok(candle) if filter.matches(&candle.symbol) => send(&mut ws, &Event::Candle(&candle)).await?,
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(n)) => {
+                        debug!(%peer, skipped = n, "Relay client fell behind on candles");
+                        store.metrics().record_dropped_events("relay", n);
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
+    .await;
+    match result {
+        Ok(()) => info!(%peer, "Relay client disconnected"),
+        Err(e) => info!(%peer, error = %e, "Relay client dropped"),
+    }
+}
+
+async fn send<S>(ws: &mut S, event: &Event<'_>) -> Result<(), tokio_tungstenite::tungstenite::Error>
+where
+    S: SinkExt<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+{
+    let text = serde_json::to_string(event).unwrap_or_default();  // Our own types always serialize
+    ws.send(Message::Text(text)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::time::SystemTime;
+
+    fn tick(symbol: &str, price: &str) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn clients_get_only_the_symbols_they_asked_for() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let store = PriceStore::new();
+            store.update(tick("BTC-USD", "65000")).await;
+            let relay = Relay::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+            let (addr, candle_sink) = (relay.local_addr().unwrap(), relay.candle_sink());
+            tokio::spawn(relay.run(store.clone()));
+
+            let url = format!("ws://{}/?symbols=btc-usd", addr);
+            let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+            let mut next = async || -> Value {
+                let Some(Ok(Message::Text(text))) = client.next().await else { panic!("relay closed") };
+                serde_json::from_str(&text).unwrap()
+            };
+
+            assert_eq!(next().await["symbols"][0], "BTC-USD");
+            assert_eq!(next().await["price"], "65000");  // Latest known price first
+
+            store.update(tick("ETH-USD", "3000")).await;  // Filtered out
+            store.update(tick("BTC-USD", "65100")).await;
+            let update = next().await;
+            assert_eq!((update["type"].as_str(), update["price"].as_str()), (Some("ticker"), Some("65100")));
+
+            let mut candle = crate::candles::CandleAggregator::new(vec![Duration::from_secs(60)], 1);
+            candle.ingest(&PriceUpdate { received_at: SystemTime::UNIX_EPOCH, ..tick("BTC-USD", "1") });
+            for closed in candle.ingest(&PriceUpdate { received_at: SystemTime::UNIX_EPOCH + Duration::from_secs(60), ..tick("BTC-USD", "2") }) {
+                candle_sink(&closed);
+            }
+            let closed = next().await;
+            assert_eq!((closed["type"].as_str(), closed["interval"].as_str()), (Some("candle"), Some("1m")));
+        });
+    }
+}
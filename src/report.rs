@@ -0,0 +1,364 @@
+// When the periodic price table is printed: every symbol on `[output] interval` unless
+// a group gives it its own, and nothing at all during quiet hours (alerts, the API and
+// the NDJSON output carry on as usual).
+//
+//     [output]
+//     interval = "30s"                  # Everything not in a group
+//     quiet_hours = ["22:00-07:00"]     # No printing overnight (CRABBY_QUIET_HOURS)
+//     utc_offset = "+01:00"             # Quiet hours are in this time zone (default UTC)
+//
+//     [[output.groups]]
+//     symbols = ["BTC-USD", "ETH-USD"]
+//     interval = "10s"
+//
+//     [[output.groups]]
+//     symbols = ["DOGE-USD", "SHIB-USD"]
+//     interval = "5m"
+//
+// The printer wakes on the greatest common divisor of all the intervals and prints the
+// symbols whose interval is a multiple of the time elapsed, so a 10s and a 30s group
+// line up on every third wake. Portfolio and paper trading summaries follow `interval`.
+//
+// Pressing Enter (or `kill -USR1 <pid>`) prints the whole table right away, whatever the
+// intervals and quiet hours say. Between printouts, a busy symbol can also be followed
+// update by update, printing only every Nth one:
+//
+//     [output]
+//     every_nth = { BTC-USD = 10 }      # --every-nth BTC-USD=10 (CRABBY_EVERY_NTH)
+
+use std::{
+    collections::HashMap,               // Updates seen per (exchange, symbol)
+    fmt,                                // "22:00-07:00" and "+01:00" back as text
+    str::FromStr,                       // Parsing them
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+use tracing::info;
+
+use crate::config::{check_symbol, deserialize_duration, OutputConfig};
+use crate::store::PriceUpdate;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+// [[output.groups]]: symbols printed on their own interval
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportGroup {
+    pub symbols: Vec<String>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+}
+
+// A daily window with no printing, e.g. "22:00-07:00" (may wrap past midnight)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct QuietHours {
+    start: u16,   // Minutes after midnight, inclusive
+    end: u16,     // ...exclusive
+}
+
+impl QuietHours {
+    pub fn contains(&self, minute_of_day: u16) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute_of_day)
+        } else {
+            minute_of_day >= self.start || minute_of_day < self.end
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once('-').ok_or_else(|| format!("\"{}\": expected a range like 22:00-07:00", s))?;
+        let (start, end) = (clock(start)?, clock(end)?);
+        if start == end {
+            return Err(format!("\"{}\": start and end are the same", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}-{:02}:{:02}", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+    }
+}
+
+// "07:00" or "7:30" as minutes after midnight
+pub fn clock(text: &str) -> Result<u16, String> {
+    let text = text.trim();
+    let parsed = text.split_once(':').and_then(|(h, m)| Some((h.parse::<u16>().ok()?, m.parse::<u16>().ok()?)));
+    match parsed {
+        Some((h, m)) if h < 24 && m < 60 && text.len() >= 4 => Ok(h * 60 + m),
+        _ => Err(format!("\"{}\" is not a time of day like 07:00", text)),
+    }
+}
+
+// The time zone quiet hours are written in, as a fixed offset from UTC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct UtcOffset {
+    minutes: i16,
+}
+
+impl FromStr for UtcOffset {
+    type Err = String;
+
+    // "+01:00", "-05:30", "+9", "UTC" or "Z"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        if text.eq_ignore_ascii_case("utc") || text.eq_ignore_ascii_case("z") {
+            return Ok(Self::default());
+        }
+        let invalid = || format!("\"{}\" is not a UTC offset like +01:00 or -05:30", s);
+        let (sign, rest) = match text.as_bytes().first() {
+            Some(b'+') => (1, &text[1..]),
+            Some(b'-') => (-1, &text[1..]),
+            _ => return Err(invalid()),
+        };
+        let (h, m) = rest.split_once(':').unwrap_or((rest, "0"));
+        let (h, m): (i16, i16) = (h.parse().map_err(|_| invalid())?, m.parse().map_err(|_| invalid())?);
+        if h > 14 || !(0..60).contains(&m) {
+            return Err(invalid());
+        }
+        Ok(Self { minutes: sign * (h * 60 + m) })
+    }
+}
+
+impl TryFrom<String> for UtcOffset {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl UtcOffset {
+    // Minutes after local midnight at `time`
+    pub fn minute_of_day(&self, time: SystemTime) -> u16 {
+        self.local_minutes(time).rem_euclid(MINUTES_PER_DAY) as u16
+    }
+
+    // Minutes since 1970-01-01 00:00 local time, at `time`
+    pub fn local_minutes(&self, time: SystemTime) -> i64 {
+        time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 60) as i64 + i64::from(self.minutes)
+    }
+
+    // The instant of a local time given as minutes since 1970-01-01 00:00
+    pub fn instant(&self, local_minutes: i64) -> SystemTime {
+        let utc = (local_minutes - i64::from(self.minutes)).max(0) as u64;
+        UNIX_EPOCH + Duration::from_secs(utc * 60)
+    }
+
+    // The local date at `time`, e.g. "2026-10-15"
+    pub fn date(&self, time: SystemTime) -> String {
+        let (year, month, day) = civil_date(self.local_minutes(time).div_euclid(MINUTES_PER_DAY));
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+// Year, month and day of a day counted from 1970-01-01 (the proleptic Gregorian
+// calendar, after Howard Hinnant's civil_from_days)
+pub fn civil_date(day: i64) -> (i64, u8, u8) {
+    let z = day + 719_468;  // Days since 0000-03-01
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let march_based = (5 * day_of_year + 2) / 153;  // 0 = March
+    let day = (day_of_year - (153 * march_based + 2) / 5 + 1) as u8;
+    let month = if march_based < 10 { march_based + 3 } else { march_based - 9 } as u8;
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+// Decides, on each wake-up, what the printer prints
+#[derive(Debug, Clone)]
+pub struct Reporter {
+    tick: Duration,                   // How often to wake
+    default_every: u64,               // Wakes between printouts of ungrouped symbols
+    groups: Vec<(Vec<String>, u64)>,  // Each group's symbols and wakes between printouts
+    quiet_hours: Vec<QuietHours>,
+    utc_offset: UtcOffset,
+    quiet: bool,                      // Whether we're in quiet hours now (to log the changes)
+}
+
+impl Reporter {
+    pub fn from_config(config: &OutputConfig) -> Self {
+        let millis = |d: Duration| (d.as_millis() as u64).max(1);
+        let tick = config.groups.iter().map(|g| millis(g.interval)).fold(millis(config.interval), gcd);
+        Self {
+            tick: Duration::from_millis(tick),
+            default_every: millis(config.interval) / tick,
+            groups: config.groups.iter().map(|g| (g.symbols.clone(), millis(g.interval) / tick)).collect(),
+            quiet_hours: config.quiet_hours.clone(),
+            utc_offset: config.utc_offset,
+            quiet: false,
+        }
+    }
+
+    pub fn tick(&self) -> Duration {
+        self.tick
+    }
+
+    // How often `symbol` is printed
+    pub fn interval(&self, symbol: &str) -> Duration {
+        self.tick * self.every(symbol) as u32
+    }
+
+    // How often the portfolio and paper trading summaries are printed
+    pub fn summary_interval(&self) -> Duration {
+        self.tick * self.default_every as u32
+    }
+
+    fn every(&self, symbol: &str) -> u64 {
+        self.groups
+            .iter()
+            .find(|(symbols, _)| symbols.iter().any(|s| s.eq_ignore_ascii_case(symbol)))
+            .map_or(self.default_every, |(_, every)| *every)
+    }
+
+    // Is `symbol` printed on wake-up number `wake` (counting from 1)?
+    pub fn due(&self, symbol: &str, wake: u64) -> bool {
+        wake.is_multiple_of(self.every(symbol))
+    }
+
+    // ...and the portfolio and paper trading summaries?
+    pub fn summary_due(&self, wake: u64) -> bool {
+        wake.is_multiple_of(self.default_every)
+    }
+
+    // Whether printing is paused at `now`. Logs when quiet hours start and end.
+    pub fn is_quiet(&mut self, now: SystemTime) -> bool {
+        let minute = self.utc_offset.minute_of_day(now);
+        let quiet = self.quiet_hours.iter().any(|q| q.contains(minute));
+        if quiet != self.quiet {
+            info!(quiet_hours = %self.quiet_hours.iter().map(|q| q.to_string()).collect::<Vec<_>>().join(", "),
+                  "{}", if quiet { "Quiet hours: periodic printing paused" } else { "Quiet hours over: printing again" });
+            self.quiet = quiet;
+        }
+        quiet
+    }
+}
+
+// "BTC-USD=10": print every 10th update of BTC-USD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EveryNth {
+    pub symbol: String,
+    pub n: u64,
+}
+
+impl FromStr for EveryNth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (symbol, n) = s.split_once('=').ok_or_else(|| format!("\"{}\": expected SYMBOL=N, e.g. BTC-USD=10", s))?;
+        let symbol = symbol.trim().to_uppercase();
+        check_symbol(&symbol)?;
+        match n.trim().parse() {
+            Ok(n) if n > 0 => Ok(Self { symbol, n }),
+            _ => Err(format!("\"{}\": N must be a whole number of at least 1", s)),
+        }
+    }
+}
+
+// Picks every Nth update of the symbols in `[output] every_nth`, counting per exchange
+#[derive(Debug, Clone, Default)]
+pub struct UpdateSampler {
+    every: HashMap<String, u64>,
+    seen: HashMap<(&'static str, String), u64>,
+}
+
+impl UpdateSampler {
+    pub fn from_config(config: &OutputConfig) -> Self {
+        let every = config.every_nth.iter().map(|(symbol, n)| (symbol.to_uppercase(), *n)).collect();
+        Self { every, seen: HashMap::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.every.is_empty()
+    }
+
+    // The update's number on its exchange when it's one to print, None otherwise
+    pub fn sample(&mut self, update: &PriceUpdate) -> Option<u64> {
+        let every = *self.every.get(&update.symbol)?;
+        let seen = self.seen.entry((update.exchange, update.symbol.clone())).or_default();
+        *seen += 1;
+        seen.is_multiple_of(every).then_some(*seen)
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_print_on_their_own_interval_and_quiet_hours_wrap_midnight() {
+        let config = OutputConfig {
+            interval: Duration::from_secs(30),
+            groups: vec![
+                ReportGroup { symbols: vec!["BTC-USD".to_string()], interval: Duration::from_secs(10) },
+                ReportGroup { symbols: vec!["DOGE-USD".to_string()], interval: Duration::from_secs(5 * 60) },
+            ],
+            quiet_hours: vec!["22:00-07:00".parse().unwrap()],
+            utc_offset: "+01:00".parse().unwrap(),
+            ..OutputConfig::default()
+        };
+        let mut reporter = Reporter::from_config(&config);
+        assert_eq!(reporter.tick(), Duration::from_secs(10));
+        assert_eq!(reporter.interval("btc-usd"), Duration::from_secs(10));
+        assert_eq!(reporter.interval("ETH-USD"), Duration::from_secs(30));
+        let printed = |symbol: &str| (1..=30).filter(|&wake| reporter.due(symbol, wake)).count();
+        assert_eq!((printed("BTC-USD"), printed("ETH-USD"), printed("DOGE-USD")), (30, 10, 1));
+        assert!(reporter.summary_due(3) && !reporter.summary_due(4));
+
+        // 21:30 UTC is 22:30 at +01:00: quiet until 06:00 UTC
+        let at = |h: u64, m: u64| UNIX_EPOCH + Duration::from_secs(((19_000 * 24 + h) * 60 + m) * 60);
+        assert!(!reporter.is_quiet(at(20, 59)));
+        assert!(reporter.is_quiet(at(21, 30)));
+        assert!(reporter.is_quiet(at(5, 59)));
+        assert!(!reporter.is_quiet(at(6, 0)));
+
+        assert!("12:00-12:00".parse::<QuietHours>().is_err());
+        assert!("25:00-07:00".parse::<QuietHours>().is_err());
+        assert_eq!("7:05-9:00".parse::<QuietHours>().unwrap().to_string(), "07:05-09:00");
+        assert_eq!("-05:30".parse::<UtcOffset>().unwrap().minute_of_day(UNIX_EPOCH), 18 * 60 + 30);
+    }
+
+    #[test]
+    fn sampler_picks_every_nth_update_per_exchange() {
+        let every: EveryNth = "btc-usd=3".parse().unwrap();
+        assert_eq!(every, EveryNth { symbol: "BTC-USD".to_string(), n: 3 });
+        assert!("BTC-USD=0".parse::<EveryNth>().is_err());
+        assert!("BTC-USD".parse::<EveryNth>().is_err());
+
+        let config = OutputConfig { every_nth: [(every.symbol, every.n)].into(), ..OutputConfig::default() };
+        let mut sampler = UpdateSampler::from_config(&config);
+        let update = |exchange: &'static str, symbol: &str| PriceUpdate {
+            exchange,
+            symbol: symbol.to_string(),
+            price: rust_decimal::Decimal::ONE,
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::now(),
+        };
+        let picked: Vec<Option<u64>> = (0..6).map(|_| sampler.sample(&update("coinbase", "BTC-USD"))).collect();
+        assert_eq!(picked, [None, None, Some(3), None, None, Some(6)]);
+        assert_eq!(sampler.sample(&update("kraken", "BTC-USD")), None);  // Kraken counts on its own
+        assert_eq!(sampler.sample(&update("coinbase", "ETH-USD")), None);
+    }
+}
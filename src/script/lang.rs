@@ -0,0 +1,559 @@
+// The rule script language: a small subset of Rhai's syntax, interpreted straight from
+// the syntax tree. Enough for conditions over live data, nothing more:
+//
+//     // RSI oversold while the last hour dropped more than 5%
+//     let rsi = rsi("BTC-USD", 14);
+//     if rsi < 30 && change("BTC-USD", "1h") < -0.05 {
+//         alert("BTC-USD", "oversold: RSI " + rsi);
+//     } else if price("ETH-USD") > 5000 {
+//         alert("ETH-USD", "above 5000");
+//     }
+//
+// Statements: `let name = expr;`, `if cond { ... } else if ... { ... } else { ... }` and
+// function calls. Expressions: numbers (exact decimals), "strings", true/false, names
+// bound with `let`, calls, ( ), ! and unary -, * / %, + - (+ also joins strings),
+// < <= > >= == !=, && and || (both short-circuit). `//` starts a comment.
+//
+// A value that isn't known yet (no price, too few candles) is `()`. Comparisons with it
+// are false and arithmetic with it stays `()`, so a rule never fires on missing data.
+// The functions themselves come from a `Host` (see mod.rs).
+
+use std::{collections::HashMap, fmt};
+
+use rust_decimal::Decimal;
+
+// A runtime value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Num(Decimal),
+    Str(String),
+    Bool(bool),
+    Unit,  // Not known (yet)
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Num(n) => write!(f, "{}", n.normalize()),
+            Value::Str(s) => f.write_str(s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Unit => f.write_str("()"),
+        }
+    }
+}
+
+// What the script can call. `line` is where the call is written, so a host can tell two
+// calls of the same function apart.
+pub trait Host {
+    fn call(&mut self, name: &str, args: &[Value], line: usize) -> Result<Value, String>;
+}
+
+// Syntax tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Value(Value),
+    Var(String),
+    Call(String, Vec<Expr>, usize),  // Function, arguments, line
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Let(String, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),  // Condition, then, else (empty when there's none)
+    Expr(Expr),
+}
+
+// A parsed script
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    statements: Vec<Stmt>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = lex(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let mut statements = Vec::new();
+        while !parser.at_end() {
+            statements.push(parser.statement()?);
+        }
+        Ok(Self { statements })
+    }
+
+    // Run the whole script once
+    pub fn run(&self, host: &mut dyn Host) -> Result<(), String> {
+        let mut vars = HashMap::new();
+        run_block(&self.statements, &mut vars, host)
+    }
+
+    // Every call to `function` whose first argument is a string literal: the symbols the
+    // script asks about
+    pub fn literal_args(&self, function: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut found = Vec::new();
+        let mut visit = |expr: &Expr| collect_literals(expr, &function, &mut found);
+        walk(&self.statements, &mut visit);
+        found
+    }
+
+    // Does the script call `function` anywhere?
+    pub fn calls(&self, function: impl Fn(&str) -> bool) -> bool {
+        let mut found = false;
+        walk(&self.statements, &mut |expr: &Expr| found |= calls(expr, &function));
+        found
+    }
+}
+
+fn walk(statements: &[Stmt], visit: &mut impl FnMut(&Expr)) {
+    for statement in statements {
+        match statement {
+            Stmt::Let(_, expr) | Stmt::Expr(expr) => visit(expr),
+            Stmt::If(cond, then, otherwise) => {
+                visit(cond);
+                walk(then, visit);
+                walk(otherwise, visit);
+            }
+        }
+    }
+}
+
+fn calls(expr: &Expr, function: &impl Fn(&str) -> bool) -> bool {
+    match expr {
+        Expr::Call(name, args, _) => function(name) || args.iter().any(|a| calls(a, function)),
+        Expr::Not(e) | Expr::Neg(e) => calls(e, function),
+        Expr::Binary(a, _, b) => calls(a, function) || calls(b, function),
+        Expr::Value(_) | Expr::Var(_) => false,
+    }
+}
+
+fn collect_literals(expr: &Expr, function: &impl Fn(&str) -> bool, found: &mut Vec<String>) {
+    match expr {
+        Expr::Call(name, args, _) => {
+            if function(name)
+                && let Some(Expr::Value(Value::Str(s))) = args.first()
+                && !found.contains(s)
+            {
+                found.push(s.clone());
+            }
+            args.iter().for_each(|a| collect_literals(a, function, found));
+        }
+        Expr::Not(e) | Expr::Neg(e) => collect_literals(e, function, found),
+        Expr::Binary(a, _, b) => {
+            collect_literals(a, function, found);
+            collect_literals(b, function, found);
+        }
+        Expr::Value(_) | Expr::Var(_) => {}
+    }
+}
+
+// Evaluation
+
+fn run_block(statements: &[Stmt], vars: &mut HashMap<String, Value>, host: &mut dyn Host) -> Result<(), String> {
+    for statement in statements {
+        match statement {
+            Stmt::Let(name, expr) => {
+                let value = eval(expr, vars, host)?;
+                vars.insert(name.clone(), value);
+            }
+            Stmt::If(cond, then, otherwise) => {
+                let branch = if truthy(&eval(cond, vars, host)?)? { then } else { otherwise };
+                run_block(branch, vars, host)?;
+            }
+            Stmt::Expr(expr) => {
+                eval(expr, vars, host)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Conditions must be true/false; `()` counts as false
+fn truthy(value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        Value::Unit => Ok(false),
+        other => Err(format!("expected true or false, got {}", other)),
+    }
+}
+
+fn eval(expr: &Expr, vars: &HashMap<String, Value>, host: &mut dyn Host) -> Result<Value, String> {
+    match expr {
+        Expr::Value(v) => Ok(v.clone()),
+        Expr::Var(name) => vars.get(name).cloned().ok_or_else(|| format!("unknown variable `{}`", name)),
+        Expr::Call(name, args, line) => {
+            let args = args.iter().map(|a| eval(a, vars, host)).collect::<Result<Vec<_>, _>>()?;
+            host.call(name, &args, *line).map_err(|e| format!("line {}: {}", line, e))
+        }
+        Expr::Not(e) => match eval(e, vars, host)? {
+            Value::Unit => Ok(Value::Unit),
+            v => Ok(Value::Bool(!truthy(&v)?)),
+        },
+        Expr::Neg(e) => match eval(e, vars, host)? {
+            Value::Num(n) => Ok(Value::Num(-n)),
+            Value::Unit => Ok(Value::Unit),
+            other => Err(format!("can't negate {}", other)),
+        },
+        Expr::Binary(a, Op::And, b) => match truthy(&eval(a, vars, host)?)? {
+            false => Ok(Value::Bool(false)),
+            true => Ok(Value::Bool(truthy(&eval(b, vars, host)?)?)),
+        },
+        Expr::Binary(a, Op::Or, b) => match truthy(&eval(a, vars, host)?)? {
+            true => Ok(Value::Bool(true)),
+            false => Ok(Value::Bool(truthy(&eval(b, vars, host)?)?)),
+        },
+        Expr::Binary(a, op, b) => binary(eval(a, vars, host)?, *op, eval(b, vars, host)?),
+    }
+}
+
+fn binary(a: Value, op: Op, b: Value) -> Result<Value, String> {
+    use Value::*;
+    Ok(match (a, op, b) {
+        // Joining strings; numbers and the rest are shown as text
+        (Str(a), Op::Add, b) => Str(format!("{}{}", a, b)),
+        (a, Op::Add, Str(b)) => Str(format!("{}{}", a, b)),
+        (Unit, Op::Eq | Op::Ne, Unit) => Bool(op == Op::Eq),
+        (Unit, Op::Lt | Op::Le | Op::Gt | Op::Ge | Op::Eq, _) | (_, Op::Lt | Op::Le | Op::Gt | Op::Ge | Op::Eq, Unit) => Bool(false),
+        (Unit, Op::Ne, _) | (_, Op::Ne, Unit) => Bool(true),
+        (Unit, _, _) | (_, _, Unit) => Unit,
+        (Num(a), op, Num(b)) => match op {
+            Op::Add => Num(a + b),
+            Op::Sub => Num(a - b),
+            Op::Mul => Num(a * b),
+            Op::Div | Op::Rem if b.is_zero() => Unit,  // No answer rather than an error
+            Op::Div => Num(a / b),
+            Op::Rem => Num(a % b),
+            Op::Lt => Bool(a < b),
+            Op::Le => Bool(a <= b),
+            Op::Gt => Bool(a > b),
+            Op::Ge => Bool(a >= b),
+            Op::Eq => Bool(a == b),
+            Op::Ne => Bool(a != b),
+            Op::And | Op::Or => unreachable!("short-circuited in eval"),
+        },
+        (a, Op::Eq, b) => Bool(a == b),
+        (a, Op::Ne, b) => Bool(a != b),
+        (a, op, b) => return Err(format!("can't apply {:?} to {} and {}", op, a, b)),
+    })
+}
+
+// Lexing
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(Decimal),
+    Str(String),
+    Ident(String),
+    Punct(&'static str),
+}
+
+// Longest first, so "<=" wins over "<"
+const PUNCTUATION: [&str; 21] =
+    ["&&", "||", "<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", "{", "}", ",", ";", "="];
+
+// Tokens with the line each starts on, for error messages
+fn lex(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = source.chars().collect();
+    let (mut i, mut line) = (0, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+            let number = text.parse().map_err(|_| format!("line {}: bad number `{}`", line, text))?;
+            tokens.push((Token::Num(number), line));
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None | Some('\n') => return Err(format!("line {}: unterminated string", line)),
+                    Some('"') => break,
+                    Some('\\') => {
+                        text.push(match chars.get(i + 1) {
+                            Some('n') => '\n',
+                            Some(&other) => other,
+                            None => return Err(format!("line {}: unterminated string", line)),
+                        });
+                        i += 2;
+                    }
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push((Token::Str(text), line));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let punct = PUNCTUATION
+                .iter()
+                .find(|p| rest.starts_with(**p))
+                .ok_or_else(|| format!("line {}: unexpected `{}`", line, c))?;
+            tokens.push((Token::Punct(punct), line));
+            i += punct.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// Parsing (recursive descent, one function per precedence level)
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn error(&self, message: &str) -> String {
+        match self.tokens.get(self.pos).or(self.tokens.last()) {
+            Some((_, line)) => format!("line {}: {}", line, message),
+            None => message.to_string(),
+        }
+    }
+
+    // Consume `punct` if it's next
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat(punct) { Ok(()) } else { Err(self.error(&format!("expected `{}`", punct))) }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(word)) if word == keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        if self.eat_keyword("let") {
+            let name = match self.tokens.get(self.pos) {
+                Some((Token::Ident(name), _)) => name.clone(),
+                _ => return Err(self.error("expected a name after `let`")),
+            };
+            self.pos += 1;
+            self.expect("=")?;
+            let value = self.expression()?;
+            self.expect(";")?;
+            return Ok(Stmt::Let(name, value));
+        }
+        if self.eat_keyword("if") {
+            return self.if_statement();
+        }
+        let expr = self.expression()?;
+        // The semicolon may be left off the last statement of a block
+        if !self.eat(";") && !self.at_end() && self.peek() != Some(&Token::Punct("}")) {
+            return Err(self.error("expected `;`"));
+        }
+        Ok(Stmt::Expr(expr))
+    }
+
+    // After `if`
+    fn if_statement(&mut self) -> Result<Stmt, String> {
+        let cond = self.expression()?;
+        let then = self.block()?;
+        let otherwise = if self.eat_keyword("else") {
+            if self.eat_keyword("if") { vec![self.if_statement()?] } else { self.block()? }
+        } else {
+            Vec::new()
+        };
+        self.eat(";");
+        Ok(Stmt::If(cond, then, otherwise))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, String> {
+        self.expect("{")?;
+        let mut statements = Vec::new();
+        while !self.eat("}") {
+            if self.at_end() {
+                return Err(self.error("missing `}`"));
+            }
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        self.binary_level(0)
+    }
+
+    // Operators by precedence, loosest first; comparisons don't chain
+    fn binary_level(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: [&[(&str, Op)]; 5] = [
+            &[("||", Op::Or)],
+            &[("&&", Op::And)],
+            &[("<=", Op::Le), (">=", Op::Ge), ("==", Op::Eq), ("!=", Op::Ne), ("<", Op::Lt), (">", Op::Gt)],
+            &[("+", Op::Add), ("-", Op::Sub)],
+            &[("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)],
+        ];
+        let Some(ops) = LEVELS.get(level) else { return self.unary() };
+        let mut left = self.binary_level(level + 1)?;
+        while let Some(&(_, op)) = ops.iter().find(|(punct, _)| self.peek() == Some(&Token::Punct(punct))) {
+            self.pos += 1;
+            let right = self.binary_level(level + 1)?;
+            left = Expr::Binary(Box::new(left), op, Box::new(right));
+            if level == 2 {
+                break;
+            }
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let Some((token, line)) = self.tokens.get(self.pos).cloned() else {
+            return Err(self.error("unexpected end of script"));
+        };
+        self.pos += 1;
+        match token {
+            Token::Num(n) => Ok(Expr::Value(Value::Num(n))),
+            Token::Str(s) => Ok(Expr::Value(Value::Str(s))),
+            Token::Ident(word) if word == "true" || word == "false" => Ok(Expr::Value(Value::Bool(word == "true"))),
+            Token::Ident(name) if self.eat("(") => {
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.expression()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Call(name, args, line))
+            }
+            Token::Ident(name) => Ok(Expr::Var(name)),
+            Token::Punct("(") => {
+                // `()` is the unknown value; anything else is a parenthesized expression
+                if self.eat(")") {
+                    return Ok(Expr::Value(Value::Unit));
+                }
+                let inner = self.expression()?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Token::Punct(p) => {
+                self.pos -= 1;
+                Err(self.error(&format!("unexpected `{}`", p)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers price() from a table and records alert() calls
+    struct Fake {
+        alerts: Vec<String>,
+    }
+
+    impl Host for Fake {
+        fn call(&mut self, name: &str, args: &[Value], _line: usize) -> Result<Value, String> {
+            match (name, args) {
+                ("price", [Value::Str(s)]) if s == "BTC-USD" => Ok(Value::Num(Decimal::from(60_000))),
+                ("price", [_]) => Ok(Value::Unit),
+                ("alert", [_, message]) => {
+                    self.alerts.push(message.to_string());
+                    Ok(Value::Unit)
+                }
+                _ => Err(format!("unknown function `{}`", name)),
+            }
+        }
+    }
+
+    fn run(source: &str) -> Result<Vec<String>, String> {
+        let mut host = Fake { alerts: Vec::new() };
+        Script::parse(source)?.run(&mut host)?;
+        Ok(host.alerts)
+    }
+
+    #[test]
+    fn evaluates_conditions_over_host_values() {
+        let script = r#"
+            // Precedence, decimals and string joining
+            let p = price("BTC-USD");
+            if p > 50_000 && 1 + 2 * 3 == 7 && !(p < 0) {
+                alert("BTC-USD", "at " + p / 1000 + "k");
+            } else if true {
+                alert("BTC-USD", "not reached")
+            }
+            if price("DOGE-USD") < 1 || price("DOGE-USD") >= 1 { alert("DOGE-USD", "unknown prices never match"); }
+            if price("DOGE-USD") == () { alert("DOGE-USD", "but can be checked for") }
+        "#;
+        assert_eq!(run(script), Ok(vec!["at 60k".to_string(), "but can be checked for".to_string()]));
+        assert_eq!(
+            Script::parse(script).unwrap().literal_args(|f| f == "price"),
+            ["BTC-USD", "DOGE-USD"]
+        );
+
+        assert!(run("if 1 { }").unwrap_err().contains("expected true or false"));
+        assert_eq!(run("let x = 1;\nlet y = x +;").unwrap_err(), "line 2: unexpected `;`");
+        assert!(run("nope(1);").unwrap_err().contains("unknown function"));
+        assert!(Script::parse("if 1 < 2 < 3 { }").is_err(), "comparisons don't chain");
+    }
+}
@@ -0,0 +1,342 @@
+// Rule scripts: alert conditions written as a small script instead of static thresholds,
+// evaluated against the live prices, stats and indicators every `[script] interval`, and
+// reloaded whenever the file changes (a version that doesn't parse is reported and the
+// previous one keeps running).
+//
+//     let rsi = rsi("BTC-USD", 14);
+//     if rsi < 30 && change("BTC-USD", "1h") < -0.05 {
+//         alert("BTC-USD", "oversold: RSI " + rsi);
+//     }
+//
+// Functions (SYMBOL is a string like "BTC-USD"; a value that isn't known yet is `()`):
+//
+//   price(SYMBOL)              latest price, from any exchange
+//   change(SYMBOL, WINDOW)     change over "1m", "5m" or "1h" as a fraction (-0.05 = -5%)
+//   high(SYMBOL), low(SYMBOL)  rolling 1h high and low
+//   volatility(SYMBOL)         std dev of per-sample % returns over the last hour
+//   sma(SYMBOL, N), ema(SYMBOL, N), rsi(SYMBOL, N), macd(SYMBOL)
+//                              indicators on the [indicators] candle interval (needs candles)
+//   alert(SYMBOL, MESSAGE)     notify like an alert rule (console, webhooks, Telegram...)
+//   buy(SYMBOL, QUANTITY), sell(SYMBOL, QUANTITY)
+//                              notify and place an order, like an alert rule's `trade`
+//                              (real only with --enable-trading; simulated by `backtest`).
+//                              A third argument makes it a limit order at that price.
+//
+// Like alert rules, an alert(), buy() or sell() call fires on the edge: when it's reached
+// after not being reached on the previous run, and at most once per `cooldown`. Symbols
+// named in the script are tracked even if they aren't listed anywhere else.
+//
+// The language is in lang.rs.
+
+mod lang;
+
+use std::{
+    collections::{HashMap, HashSet},  // Last fired per alert() call; calls reached on the last run
+    error::Error,                     // Trait to return errors from our functions
+    fs,                               // Read (and re-read) the script
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+pub use lang::{Host, Script, Value};
+
+use crate::alerts::{Alert, TradeAction};
+use crate::config::{check_symbol, deserialize_duration};
+use crate::indicators::{Indicator, Indicators};
+use crate::notify::Notifier;
+use crate::paper::Side;
+use crate::store::{PriceStore, PriceView};
+
+// Functions whose first argument is a symbol
+const SYMBOL_FUNCTIONS: [&str; 12] =
+    ["price", "change", "high", "low", "volatility", "sma", "ema", "rsi", "macd", "alert", "buy", "sell"];
+// ...those of them that need candles
+const INDICATOR_FUNCTIONS: [&str; 4] = ["sma", "ema", "rsi", "macd"];
+// ...and those that place orders
+const ORDER_FUNCTIONS: [&str; 2] = ["buy", "sell"];
+
+// [script] section of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptConfig {
+    pub path: Option<PathBuf>,       // Off unless set
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,          // How often the script runs
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub cooldown: Duration,          // Minimum time between two notifications of one alert() call
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self { path: None, interval: Duration::from_secs(1), cooldown: Duration::from_secs(5 * 60) }
+    }
+}
+
+// The loaded script and what it remembers between runs
+pub struct ScriptRunner {
+    path: PathBuf,
+    script: Script,
+    modified: Option<SystemTime>,                    // Of the file, when it was last read
+    cooldown: Duration,
+    indicators: Option<Indicators>,                  // Set once candles are running
+    last_fired: HashMap<(usize, String), Instant>,   // Per alert() call (line) and symbol
+    active: HashSet<(usize, String)>,                // alert() calls reached on the previous run
+    last_error: Option<String>,                      // Logged once, not on every run
+}
+
+impl ScriptRunner {
+    // Read and parse the script; a script that doesn't parse is an error here, unlike on reload
+    pub fn load(config: &ScriptConfig) -> Result<Option<Self>, Box<dyn Error>> {
+        let Some(path) = &config.path else { return Ok(None) };
+        let (script, modified) = read(path)?;
+        Ok(Some(Self {
+            path: path.clone(),
+            script,
+            modified,
+            cooldown: config.cooldown,
+            indicators: None,
+            last_fired: HashMap::new(),
+            active: HashSet::new(),
+            last_error: None,
+        }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Symbols the script mentions by name, to be tracked
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = Vec::new();
+        for symbol in self.script.literal_args(|f| SYMBOL_FUNCTIONS.contains(&f)) {
+            let symbol = symbol.to_uppercase();
+            if check_symbol(&symbol).is_ok() && !symbols.contains(&symbol) {
+                symbols.push(symbol);
+            }
+        }
+        symbols
+    }
+
+    // Does the script call any indicator function?
+    pub fn uses_indicators(&self) -> bool {
+        self.script.calls(|f| INDICATOR_FUNCTIONS.contains(&f))
+    }
+
+    // Does the script call buy() or sell()?
+    pub fn places_orders(&self) -> bool {
+        self.script.calls(|f| ORDER_FUNCTIONS.contains(&f))
+    }
+
+    // Where sma()/ema()/rsi()/macd() read their values from. Without it they fail.
+    pub fn use_indicators(&mut self, indicators: Indicators) {
+        self.indicators = Some(indicators);
+    }
+
+    // Run the script once (picking up a changed file first) against `prices`; returns the
+    // alerts that fire
+    pub fn evaluate(&mut self, prices: &PriceView, now: Instant) -> Vec<Alert> {
+        self.reload_if_changed();
+
+        let mut host = Live { prices, indicators: self.indicators.as_ref(), reached: Vec::new() };
+        let result = self.script.run(&mut host);
+        let reached = host.reached;
+        match result {
+            Ok(()) => self.last_error = None,
+            Err(e) => {
+                if self.last_error.as_ref() != Some(&e) {
+                    warn!(path = %self.path.display(), error = %e, "Script failed");
+                }
+                self.last_error = Some(e);
+            }
+        }
+
+        let rule = format!("script {}", self.path.file_name().unwrap_or_default().to_string_lossy());
+        let mut alerts = Vec::new();
+        let mut active = HashSet::new();
+        for (line, symbol, message, trade) in reached {
+            let key = (line, symbol.clone());
+            let cooled_down = self.last_fired.get(&key).is_none_or(|t| now.duration_since(*t) >= self.cooldown);
+            if !self.active.contains(&key) && cooled_down {
+                self.last_fired.insert(key.clone(), now);
+                let latest = prices.latest(&symbol);
+                alerts.push(Alert {
+                    rule: rule.clone(),
+                    exchange: latest.as_ref().map_or("script", |u| u.exchange),
+                    price: latest.map_or(Decimal::ZERO, |u| u.price),
+                    symbol,
+                    detail: message,
+                    fired_at: SystemTime::now(),
+                    desktop: false,
+                    trade,
+                });
+            }
+            active.insert(key);
+        }
+        self.active = active;
+        alerts
+    }
+
+    fn reload_if_changed(&mut self) {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return;
+        }
+        self.modified = modified;  // Don't retry a broken version until it changes again
+        match read(&self.path) {
+            Ok((script, _)) => {
+                self.script = script;
+                self.active.clear();  // Lines may mean something else now
+                self.last_fired.clear();
+                self.last_error = None;
+                info!(path = %self.path.display(), "Reloaded script");
+            }
+            Err(e) => warn!(path = %self.path.display(), error = %e, "Script not reloaded; the previous version keeps running"),
+        }
+    }
+}
+
+// The script's text, parsed, and when the file was last changed
+fn read(path: &Path) -> Result<(Script, Option<SystemTime>), Box<dyn Error>> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let script = Script::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok((script, modified))
+}
+
+// The functions, answered from the live state
+struct Live<'a> {
+    prices: &'a PriceView,  // Taken once per run, so every line sees the same moment
+    indicators: Option<&'a Indicators>,
+    reached: Vec<(usize, String, String, Option<TradeAction>)>,  // alert()/buy()/sell() calls: line, symbol, message, order
+}
+
+impl Host for Live<'_> {
+    fn call(&mut self, name: &str, args: &[Value], line: usize) -> Result<Value, String> {
+        let symbol = match args.first() {
+            Some(Value::Str(s)) => s.to_uppercase(),
+            _ if SYMBOL_FUNCTIONS.contains(&name) => return Err(format!("{}() takes a symbol first, e.g. {}(\"BTC-USD\")", name, name)),
+            _ => return Err(format!("unknown function `{}`", name)),
+        };
+        let latest = self.prices.latest(&symbol);
+        let stats = || latest.as_ref().and_then(|u| self.prices.stats(u.exchange, &symbol));
+        let number = |n: Option<Decimal>| n.map_or(Value::Unit, Value::Num);
+        let indicator = |indicator: Indicator| -> Result<Value, String> {
+            let indicators = self.indicators.ok_or("indicators need candles ([candles] enabled = true)")?;
+            Ok(number(latest.as_ref().and_then(|u| indicators.reading(&indicator, u.exchange, &symbol)).map(|r| r.value)))
+        };
+        let period = || match args.get(1) {
+            Some(Value::Num(n)) if n.is_integer() && *n > Decimal::ZERO && let Some(n) = n.to_usize() => Ok(n),
+            _ => Err(format!("{}() takes a period as its second argument, e.g. {}(\"BTC-USD\", 14)", name, name)),
+        };
+
+        match (name, args.len()) {
+            ("price", 1) => Ok(number(latest.as_ref().map(|u| u.price))),
+            ("change", 2) => {
+                let pct = match &args[1] {
+                    Value::Str(w) if w == "1m" => stats().and_then(|s| s.change_1m_pct),
+                    Value::Str(w) if w == "5m" => stats().and_then(|s| s.change_5m_pct),
+                    Value::Str(w) if w == "1h" => stats().and_then(|s| s.change_1h_pct),
+                    other => return Err(format!("change() window must be \"1m\", \"5m\" or \"1h\", not {}", other)),
+                };
+                Ok(number(pct.map(|p| p / Decimal::ONE_HUNDRED)))
+            }
+            ("high", 1) => Ok(number(stats().and_then(|s| s.high_1h))),
+            ("low", 1) => Ok(number(stats().and_then(|s| s.low_1h))),
+            ("volatility", 1) => Ok(number(stats().and_then(|s| s.volatility))),
+            ("sma", 2) => indicator(Indicator::Sma(period()?)),
+            ("ema", 2) => indicator(Indicator::Ema(period()?)),
+            ("rsi", 2) => indicator(Indicator::Rsi(period()?)),
+            ("macd", 1) => indicator(Indicator::Macd { fast: 12, slow: 26, signal: 9 }),
+            ("alert", 2) => {
+                self.reached.push((line, symbol, args[1].to_string(), None));
+                Ok(Value::Unit)
+            }
+            ("buy" | "sell", 2 | 3) => {
+                let side = if name == "buy" { Side::Buy } else { Side::Sell };
+                let (quantity, limit) = match (&args[1], args.get(2)) {
+                    (Value::Num(quantity), None) => (*quantity, None),
+                    (Value::Num(quantity), Some(Value::Num(limit))) => (*quantity, Some(*limit)),
+                    _ => return Err(format!("{}() takes a quantity and optionally a limit price, e.g. {}(\"BTC-USD\", 0.01)", name, name)),
+                };
+                let trade = TradeAction { side, quantity, limit };
+                trade.check()?;
+                self.reached.push((line, symbol, trade.to_string(), Some(trade)));
+                Ok(Value::Unit)
+            }
+            (name, n) if SYMBOL_FUNCTIONS.contains(&name) => Err(format!("{}() doesn't take {} arguments", name, n)),
+            (name, _) => Err(format!("unknown function `{}`", name)),
+        }
+    }
+}
+
+// Run the script every `interval`, sending what fires to all notifiers
+pub fn spawn_script(
+    mut runner: ScriptRunner,
+    store: &PriceStore,
+    interval: Duration,
+    notifiers: Vec<Box<dyn Notifier>>,
+) -> tokio::task::JoinHandle<()> {
+    let store = store.clone();
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            ticks.tick().await;
+            for alert in runner.evaluate(&store.view().await, Instant::now()) {
+                for notifier in &notifiers {
+                    notifier.notify(&alert);
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::PriceUpdate;
+
+    fn tick(price: u32) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: price.into(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn alerts_fire_on_the_edge_and_the_file_is_reloaded() {
+        let path = std::env::temp_dir().join(format!("crabby-script-{}.rhai", std::process::id()));
+        fs::write(&path, "if price(\"btc-usd\") > 60000 { alert(\"BTC-USD\", \"above \" + price(\"BTC-USD\")); }").unwrap();
+        let config = ScriptConfig { path: Some(path.clone()), ..ScriptConfig::default() };
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let store = PriceStore::new();
+        let view = || runtime.block_on(store.view());
+        let mut runner = ScriptRunner::load(&config).unwrap().unwrap();
+        assert_eq!(runner.symbols(), ["BTC-USD"]);
+        assert!(!runner.uses_indicators());
+
+        let start = Instant::now();
+        assert!(runner.evaluate(&view(), start).is_empty(), "no price yet");
+        runtime.block_on(store.update(tick(61000)));
+        let fired = runner.evaluate(&view(), start);
+        assert_eq!((fired.len(), fired[0].detail.as_str(), fired[0].exchange), (1, "above 61000", "coinbase"));
+        assert!(runner.evaluate(&view(), start + Duration::from_secs(1)).is_empty(), "still true: no new alert");
+
+        // A version that doesn't parse leaves the old one running
+        fs::write(&path, "if price(").unwrap();
+        runner.modified = None;
+        assert!(runner.evaluate(&view(), start + Duration::from_secs(2)).is_empty());
+        fs::write(&path, "alert(\"BTC-USD\", \"always\")").unwrap();
+        runner.modified = None;
+        assert_eq!(runner.evaluate(&view(), start + Duration::from_secs(3))[0].detail, "always");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+// Sequence numbers. Some channels number every message of a product one higher than
+// the last (Binance's trade ids, Coinbase's full channel): there a jump means messages
+// were dropped somewhere between the exchange and us. Others only number them in order: Coinbase's ticker
+// shares its product's numbers with every other channel, so tickers jump on a healthy
+// feed and only show which message is newer. Either way, a number at or below the last
+// one is a late or repeated message that must not overwrite newer data.
+
+use std::collections::HashMap;  // Last sequence number per symbol
+
+// What one sequence number says about the stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceCheck {
+    First,      // Nothing to compare with yet (new symbol, or just resynced)
+    InOrder,    // Exactly one more than the last
+    Gap(u64),   // This many messages are missing before this one
+    Old,        // Not newer than one already seen: skip it
+}
+
+// How a channel numbers its messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numbering {
+    Contiguous,   // One higher each message: a jump is a gap
+    Increasing,   // Only ever higher: jumps are normal
+}
+
+// Last sequence number seen per symbol
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    last: HashMap<String, u64>,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Check `sequence` against the last one seen for `symbol`, and remember it if newer
+    pub fn observe(&mut self, symbol: &str, sequence: u64, numbering: Numbering) -> SequenceCheck {
+        let Some(last) = self.last.get_mut(symbol) else {
+            self.last.insert(symbol.to_string(), sequence);
+            return SequenceCheck::First;
+        };
+        if sequence <= *last {
+            return SequenceCheck::Old;
+        }
+        let missed = sequence - *last - 1;
+        *last = sequence;
+        if missed == 0 || numbering == Numbering::Increasing { SequenceCheck::InOrder } else { SequenceCheck::Gap(missed) }
+    }
+
+    // Start over for `symbol`, e.g. after resubscribing or reconnecting
+    pub fn forget(&mut self, symbol: &str) {
+        self.last.remove(symbol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_gaps_and_old_messages_per_symbol() {
+        let mut tracker = SequenceTracker::new();
+        let contiguous = |tracker: &mut SequenceTracker, symbol, sequence| tracker.observe(symbol, sequence, Numbering::Contiguous);
+        assert_eq!(contiguous(&mut tracker, "BTC-USD", 100), SequenceCheck::First);
+        assert_eq!(contiguous(&mut tracker, "BTC-USD", 101), SequenceCheck::InOrder);
+        assert_eq!(contiguous(&mut tracker, "ETH-USD", 7), SequenceCheck::First);  // Independent per symbol
+        assert_eq!(contiguous(&mut tracker, "BTC-USD", 105), SequenceCheck::Gap(3));
+        assert_eq!(contiguous(&mut tracker, "BTC-USD", 104), SequenceCheck::Old);
+        assert_eq!(contiguous(&mut tracker, "BTC-USD", 105), SequenceCheck::Old);
+        assert_eq!(contiguous(&mut tracker, "BTC-USD", 106), SequenceCheck::InOrder);
+
+        tracker.forget("BTC-USD");
+        assert_eq!(contiguous(&mut tracker, "BTC-USD", 500), SequenceCheck::First);
+
+        // Numbers that are only increasing never make a gap, but still put late ones aside
+        assert_eq!(tracker.observe("ETH-USD", 90, Numbering::Increasing), SequenceCheck::InOrder);
+        assert_eq!(tracker.observe("ETH-USD", 80, Numbering::Increasing), SequenceCheck::Old);
+    }
+}
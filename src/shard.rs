@@ -0,0 +1,130 @@
+// Sharding: one exchange's symbols spread over several WebSocket connections, each
+// with at most `per_connection` symbols. Coinbase (and the others) cope badly with
+// hundreds of products on one socket, and with everything on one socket a single
+// slow or dropped connection stalls every symbol.
+//
+// Each shard is an ordinary `feed::run_feed` with its own symbol set, reconnect loop
+// and backoff; they all write to the same store, so the output stays merged. A symbol
+// stays on its shard for as long as it's tracked (moving it would mean a needless
+// unsubscribe/resubscribe); new symbols fill the first shard with room, and a new
+// connection opens only when every shard is full.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use tokio::{sync::watch, task::JoinSet};
+use tracing::{info, info_span, Instrument};
+
+use crate::exchange::Exchange;
+use crate::feed::{run_feed, FeedOptions};
+use crate::store::PriceStore;
+
+// Which symbols go on which connection
+#[derive(Debug, Default)]
+pub struct ShardPlan {
+    per_connection: usize,
+    shards: Vec<BTreeSet<String>>,
+}
+
+impl ShardPlan {
+    pub fn new(per_connection: usize) -> Self {
+        Self { per_connection: per_connection.max(1), shards: Vec::new() }
+    }
+
+    // Bring the plan in line with the tracked symbols: forget the ones that are gone,
+    // place the new ones. Shards emptied this way stay open for later symbols.
+    pub fn assign(&mut self, symbols: &BTreeSet<String>) {
+        for shard in &mut self.shards {
+            shard.retain(|s| symbols.contains(s));
+        }
+        for symbol in symbols {
+            if self.shards.iter().any(|shard| shard.contains(symbol)) {
+                continue;
+            }
+            match self.shards.iter_mut().find(|shard| shard.len() < self.per_connection) {
+                Some(shard) => {
+                    shard.insert(symbol.clone());
+                }
+                None => self.shards.push(BTreeSet::from([symbol.clone()])),
+            }
+        }
+    }
+
+    pub fn shards(&self) -> &[BTreeSet<String>] {
+        &self.shards
+    }
+}
+
+// Keep `exchange` connected to `symbols` through as many feeds as the plan needs.
+// Ends once every shard has stopped after a shutdown; dropping (aborting) it stops them all.
+pub async fn run_sharded(
+    exchange: Arc<dyn Exchange>,
+    mut symbols: watch::Receiver<BTreeSet<String>>,
+    store: PriceStore,
+    options: FeedOptions,
+    per_connection: usize,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut plan = ShardPlan::new(per_connection);
+    let mut shard_symbols: Vec<watch::Sender<BTreeSet<String>>> = Vec::new();  // One per running shard
+    let mut shards = JoinSet::new();  // Aborts every shard if this task is dropped
+
+    loop {
+        plan.assign(&symbols.borrow_and_update());
+        for (index, wanted) in plan.shards().iter().enumerate() {
+            match shard_symbols.get(index) {
+                Some(tx) => {
+                    tx.send_if_modified(|current| {
+                        let changed = current != wanted;
+                        if changed {
+                            current.clone_from(wanted);
+                        }
+                        changed
+                    });
+                }
+                None => {
+                    info!(shard = index, symbols = wanted.len(), "Adding a connection");
+                    let (tx, rx) = watch::channel(wanted.clone());
+                    let feed = run_feed(Arc::clone(&exchange), rx, store.clone(), options.clone(), shutdown.clone());
+                    shards.spawn(feed.instrument(info_span!("shard", index)));
+                    shard_symbols.push(tx);
+                }
+            }
+        }
+
+        tokio::select! {
+            changed = symbols.changed() => if changed.is_err() { break },
+            _ = shutdown.wait_for(|stop| *stop) => break,
+        }
+    }
+    // The shards saw the shutdown too; let them close their connections cleanly
+    while shards.join_next().await.is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(symbols: &[&str]) -> BTreeSet<String> {
+        symbols.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn symbols_stay_on_their_shard_and_fill_gaps_first() {
+        let mut plan = ShardPlan::new(2);
+        plan.assign(&set(&["A", "B", "C"]));
+        assert_eq!(plan.shards(), [set(&["A", "B"]), set(&["C"])]);
+
+        // Dropping A leaves a gap that D fills; B and C don't move
+        plan.assign(&set(&["B", "C", "D"]));
+        assert_eq!(plan.shards(), [set(&["B", "D"]), set(&["C"])]);
+
+        plan.assign(&set(&["B", "C", "D", "E", "F"]));
+        assert_eq!(plan.shards(), [set(&["B", "D"]), set(&["C", "E"]), set(&["F"])]);
+
+        // Emptied shards are kept (their connections stay open) and reused
+        plan.assign(&set(&[]));
+        assert_eq!(plan.shards().len(), 3);
+        plan.assign(&set(&["G"]));
+        assert_eq!(plan.shards()[0], set(&["G"]));
+    }
+}
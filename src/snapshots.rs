@@ -0,0 +1,309 @@
+// Price snapshots appended to a CSV file, for opening in a spreadsheet later without
+// a database. Either every update is written ("tick"), or every symbol's latest price
+// once per interval.
+//
+// Files rotate once they reach `max_rows` data rows (spreadsheets struggle with huge
+// sheets): prices.csv becomes prices.1.csv, prices.1.csv becomes prices.2.csv and so
+// on, keeping at most `keep` old files. Every file starts with its own header row.
+//
+// Like SQLite storage, the file I/O happens on a dedicated writer thread, fed through a
+// bounded queue: rows wait up to QUEUE_TIMEOUT for room, then they're dropped and counted.
+
+use std::{
+    fs::{self, File, OpenOptions},                   // The CSV files
+    io::{self, BufRead, BufReader},                  // Counting rows already in the file
+    path::{Path, PathBuf},                           // Current and rotated file names
+    str::FromStr,                                    // "tick" / "1m" schedules
+    thread::{self, JoinHandle},                      // The dedicated writer thread
+    time::{Duration, SystemTime},                    // Snapshot interval and timestamps
+};
+
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::queue::{BoundedQueue, Overflow};
+use crate::store::{epoch_ms, PriceStore, PriceUpdate};
+
+// Column names, written at the top of every file
+const HEADER: [&str; 7] = ["time", "timestamp_ms", "exchange", "symbol", "price", "open_24h", "size"];
+
+// Batches of rows waiting for the writer thread, and how long a new one waits for room
+const QUEUE_CAPACITY: usize = 10_000;
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// When rows are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Tick,             // One row per price update, as it arrives
+    Every(Duration),  // The latest price of every symbol, once per interval
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    // "tick", or a duration like "30s" / "1m"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "tick" => Ok(Schedule::Tick),
+            text => match humantime::parse_duration(text) {
+                Ok(every) if !every.is_zero() => Ok(Schedule::Every(every)),
+                Ok(_) => Err("interval must be greater than zero".to_string()),
+                Err(e) => Err(format!("{} (expected \"tick\" or a duration like \"1m\")", e)),
+            },
+        }
+    }
+}
+
+// Where and how to write
+#[derive(Debug, Clone)]
+pub struct SnapshotConfig {
+    pub path: PathBuf,        // Current file, e.g. "prices.csv" (appended to if it exists)
+    pub schedule: Schedule,
+    pub max_rows: usize,      // Data rows per file before rotating
+    pub keep: usize,          // Rotated files to keep; older ones are deleted
+}
+
+impl SnapshotConfig {
+    // One snapshot a minute, 100k rows per file, five old files
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), schedule: Schedule::Every(Duration::from_secs(60)), max_rows: 100_000, keep: 5 }
+    }
+}
+
+// A CSV file that starts over (and shifts the old ones along) when it gets too long
+pub struct RotatingCsv {
+    config: SnapshotConfig,
+    writer: csv::Writer<File>,
+    rows: usize,  // Data rows in the current file
+}
+
+impl RotatingCsv {
+    // Open the file for appending, writing the header if it's new or empty
+    pub fn open(config: SnapshotConfig) -> io::Result<Self> {
+        let rows = match File::open(&config.path) {
+            Ok(file) => BufReader::new(file).lines().count().saturating_sub(1),  // Minus the header
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        let writer = Self::create_writer(&config.path)?;
+        let mut csv = Self { config, writer, rows };
+        if fs::metadata(&csv.config.path)?.len() == 0 {
+            csv.write_header()?;
+        }
+        Ok(csv)
+    }
+
+    // Append one row, timestamped `at`, rotating first if the file is full
+    pub fn write(&mut self, at: SystemTime, update: &PriceUpdate) -> io::Result<()> {
+        if self.rows >= self.config.max_rows {
+            self.rotate()?;
+        }
+        let optional = |value: Option<rust_decimal::Decimal>| value.map(|v| v.to_string()).unwrap_or_default();
+        self.writer.write_record([
+            humantime::format_rfc3339_millis(at).to_string(),
+            epoch_ms(at).to_string(),
+            update.exchange.to_string(),
+            update.symbol.clone(),
+            update.price.to_string(),
+            optional(update.open_24h),
+            optional(update.size),
+        ])?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    // prices.csv -> prices.1.csv -> prices.2.csv ..., dropping the oldest, then start a new file
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.config.keep == 0 {
+            fs::remove_file(&self.config.path)?;
+        } else {
+            for n in (1..self.config.keep).rev() {
+                let from = rotated_path(&self.config.path, n);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.config.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.config.path, rotated_path(&self.config.path, 1))?;
+        }
+        self.writer = Self::create_writer(&self.config.path)?;
+        self.rows = 0;
+        self.write_header()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.writer.write_record(HEADER)?;
+        Ok(())
+    }
+
+    fn create_writer(path: &Path) -> io::Result<csv::Writer<File>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(csv::Writer::from_writer(file))
+    }
+}
+
+// The name of the n-th rotated file: "prices.csv" -> "prices.<n>.csv"
+pub fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+// Rows for the writer thread, all stamped with the same time
+type Rows = (SystemTime, Vec<PriceUpdate>);
+
+// Handle to the writer thread
+pub struct SnapshotWriter {
+    schedule: Schedule,
+    queue: BoundedQueue<Rows>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl SnapshotWriter {
+    // Open the file (so a bad path fails at startup) and start the writer thread
+    pub fn open(config: SnapshotConfig) -> io::Result<Self> {
+        let schedule = config.schedule;
+        let mut csv = RotatingCsv::open(config)?;
+
+        let queue: BoundedQueue<Rows> = BoundedQueue::new("csv", QUEUE_CAPACITY, Overflow::Block(QUEUE_TIMEOUT));
+        let consumer = queue.clone();
+        let writer = thread::Builder::new()
+            .name("csv-writer".to_string())
+            .spawn(move || {
+                let mut reported = 0;  // Drops already logged
+                while let Some((at, updates)) = consumer.pop() {
+                    let written = updates.iter().try_for_each(|update| csv.write(at, update)).and_then(|_| csv.flush());
+                    if let Err(e) = written {
+                        warn!(error = %e, "Writing price snapshots failed");
+                    }
+                    let dropped = consumer.dropped();
+                    if dropped > reported {
+                        warn!(dropped = dropped - reported, "CSV snapshots fell behind, updates not written");
+                        reported = dropped;
+                    }
+                }
+                let _ = csv.flush();
+            })
+            .expect("failed to spawn CSV writer thread");
+
+        Ok(Self { schedule, queue, writer: Some(writer) })
+    }
+
+    // Spawn a task that feeds `store` into the file according to the schedule
+    pub fn attach(&self, store: &PriceStore) -> tokio::task::JoinHandle<()> {
+        let queue = self.queue.clone();
+        queue.report_to(store.metrics());
+        match self.schedule {
+            Schedule::Tick => {
+                let mut rx = store.subscribe_updates();
+                tokio::spawn(async move {
+                    loop {
+                        match rx.recv().await {
+                            Ok(update) => {
+                                if queue.send((update.received_at, vec![update])).await.is_err() {
+                                    break;  // Writer thread has shut down
+                                }
+                            }
+                            Err(RecvError::Lagged(n)) => queue.count_dropped(n),
+                            Err(RecvError::Closed) => break,
+                        }
+                    }
+                })
+            }
+            Schedule::Every(every) => {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    let mut ticks = tokio::time::interval(every);
+                    ticks.tick().await;  // The first tick is immediate; wait a full interval for prices
+                    loop {
+                        ticks.tick().await;
+                        let rows = store.snapshot().await;
+                        if !rows.is_empty() && queue.send((SystemTime::now(), rows)).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            }
+        }
+    }
+
+    // Write anything queued and wait for the writer thread to finish
+    pub fn close(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.queue.close();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl Drop for SnapshotWriter {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(price: &str) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: Some("0.5".parse().unwrap()),
+            received_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn files_rotate_with_a_header_each() {
+        let dir = std::env::temp_dir().join(format!("crabby-snapshots-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("prices.csv");
+        let config = SnapshotConfig { path: path.clone(), schedule: Schedule::Tick, max_rows: 2, keep: 1 };
+
+        let mut csv = RotatingCsv::open(config.clone()).unwrap();
+        for price in ["1", "2", "3"] {
+            csv.write(SystemTime::UNIX_EPOCH, &update(price)).unwrap();
+        }
+        csv.flush().unwrap();
+        drop(csv);
+
+        // Reopening appends to the current file instead of starting over
+        let mut csv = RotatingCsv::open(config).unwrap();
+        for price in ["4", "5"] {
+            csv.write(SystemTime::UNIX_EPOCH, &update(price)).unwrap();
+        }
+        csv.flush().unwrap();
+
+        // 1,2 | 3 -> rotated; then 3,4 | 5 -> rotated again, replacing the oldest file
+        let prices = |p: &Path| -> Vec<String> {
+            let text = fs::read_to_string(p).unwrap();
+            assert!(text.starts_with("time,timestamp_ms,exchange,symbol,price,open_24h,size\n"));
+            text.lines().skip(1).map(|l| l.split(',').nth(4).unwrap().to_string()).collect()
+        };
+        assert_eq!(prices(&path), ["5"]);
+        assert_eq!(prices(&dir.join("prices.1.csv")), ["3", "4"]);
+        assert!(fs::read_to_string(&path).unwrap().contains("1970-01-01T00:00:00.000Z,0,coinbase,BTC-USD,5,,0.5"));
+        assert!(!dir.join("prices.2.csv").exists(), "only `keep` old files are kept");
+
+        assert_eq!("tick".parse(), Ok(Schedule::Tick));
+        assert_eq!("1m".parse(), Ok(Schedule::Every(Duration::from_secs(60))));
+        assert!("0s".parse::<Schedule>().is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,308 @@
+// Where a feed's messages come from. A feed (feed.rs) asks its `FeedSource` for a
+// connection, sends the exchange's subscribe messages down it and reads frames back;
+// everything after that (validation, parsing, the store, candles, alerts) is the same
+// whichever source it is.
+//
+//   WebSocketSource   the exchange's own WebSocket server (through the proxy and CA
+//                     certificates of `net`), or any other URL, e.g. a local test server
+//   MockSource        scripted frames over an in-process channel, for tests: no network
+//
+// A mock connection delivers its scripted frames and then stays open, so more can be
+// pushed while the feed runs; `close` ends it the way a dropped connection would, and
+// the feed reconnects (taking the next script, if there is one). A connection scripted
+// with `script_disconnect` ends by itself after its frames.
+//
+//     let mock = MockSource::new();
+//     mock.push("coinbase", r#"{"type":"ticker","product_id":"BTC-USD","price":"65000"}"#);
+//     tracker.use_source(Arc::new(mock.clone()));
+//     tracker.subscribe(&["BTC-USD".to_string()]);
+//
+// `MockSource::from_recording` scripts the frames of a `--record` file, one connection
+// per exchange, so fixtures are recordings of the real feeds.
+
+use std::{
+    collections::{HashMap, VecDeque},  // Per-exchange scripts, channels and sent messages
+    error::Error,                      // Connection failures
+    fs,                                // Fixture files
+    io,                                // ...and their errors
+    path::Path,                        // Where the fixture is
+    pin::Pin,                          // Boxed sink and stream halves
+    sync::{Arc, Mutex},                // Mock state shared between the test and the feeds
+};
+
+use futures_util::{future::BoxFuture, sink, stream, Sink, Stream, StreamExt};
+use tokio::sync::mpsc;                                  // Frames into a mock connection
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use url::Url;                                           // Where to connect
+
+use crate::exchange::Exchange;
+use crate::net::Network;
+use crate::recording::RecordedFrame;
+
+// The two halves of one connection: messages to the exchange, frames from it
+pub type MessageSink = Pin<Box<dyn Sink<Message, Error = WsError> + Send>>;
+pub type MessageStream = Pin<Box<dyn Stream<Item = Result<Message, WsError>> + Send>>;
+
+// Opens connections for the feeds; shared by all of them
+pub trait FeedSource: Send + Sync {
+    // One new connection to `exchange`. The feed calls this again after every disconnect.
+    fn connect<'a>(&'a self, exchange: &'a dyn Exchange) -> BoxFuture<'a, Result<(MessageSink, MessageStream), Box<dyn Error>>>;
+}
+
+// The real thing: the exchange's WebSocket URL (or `url`, when set) through `network`
+#[derive(Clone, Default)]
+pub struct WebSocketSource {
+    network: Network,
+    url: Option<Url>,  // Instead of every exchange's own URL
+}
+
+impl WebSocketSource {
+    pub fn new(network: Network) -> Self {
+        Self { network, url: None }
+    }
+
+    // Connect every exchange to this URL instead, e.g. a local server playing the exchange
+    pub fn at(mut self, url: Url) -> Self {
+        self.url = Some(url);
+        self
+    }
+}
+
+impl FeedSource for WebSocketSource {
+    fn connect<'a>(&'a self, exchange: &'a dyn Exchange) -> BoxFuture<'a, Result<(MessageSink, MessageStream), Box<dyn Error>>> {
+        Box::pin(async move {
+            let url = match &self.url {
+                Some(url) => url.clone(),
+                None => Url::parse(exchange.url())?,
+            };
+            let ws_stream = self.network.connect_websocket(&url).await?;
+            let (write, read) = ws_stream.split();
+            Ok((Box::pin(write) as MessageSink, Box::pin(read) as MessageStream))
+        })
+    }
+}
+
+// Scripted connections for tests. Cheap to clone: clones share the scripts and can
+// push frames into, and read what was sent on, the feeds' connections.
+#[derive(Clone, Default)]
+pub struct MockSource {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    scripts: HashMap<String, VecDeque<Script>>,               // Each coming connection, per exchange
+    open: HashMap<String, mpsc::UnboundedSender<String>>,     // The current connection's channel
+    sent: HashMap<String, Vec<String>>,                       // What the feeds sent (subscriptions...)
+    connections: HashMap<String, usize>,                      // Connections opened so far
+}
+
+// What one mock connection does
+#[derive(Default)]
+struct Script {
+    frames: Vec<String>,
+    disconnect: bool,  // Ends after the frames, instead of staying open
+}
+
+impl MockSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Script the frames of a recording (`--record`): each exchange's frames, in order, on
+    // its first connection
+    pub fn from_recording(path: &Path) -> io::Result<Self> {
+        let mock = Self::new();
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let recorded: RecordedFrame = serde_json::from_str(line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: line {}: {}", path.display(), i + 1, e)))?;
+            mock.push(&recorded.exchange, &recorded.frame);
+        }
+        Ok(mock)
+    }
+
+    // Frames for a later connection to `exchange`, after those already scripted
+    pub fn script(&self, exchange: &str, frames: &[&str]) {
+        self.add_script(exchange, frames, false);
+    }
+
+    // Like `script`, but the connection drops after its frames and the feed reconnects
+    pub fn script_disconnect(&self, exchange: &str, frames: &[&str]) {
+        self.add_script(exchange, frames, true);
+    }
+
+    fn add_script(&self, exchange: &str, frames: &[&str], disconnect: bool) {
+        let mut state = self.state.lock().unwrap();
+        let frames = frames.iter().map(|f| f.to_string()).collect();
+        state.scripts.entry(exchange.to_string()).or_default().push_back(Script { frames, disconnect });
+    }
+
+    // One more frame on `exchange`'s open connection, or on its next one while there is none
+    pub fn push(&self, exchange: &str, frame: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(open) = state.open.get(exchange)
+            && open.send(frame.to_string()).is_ok()
+        {
+            return;
+        }
+        let scripts = state.scripts.entry(exchange.to_string()).or_default();
+        match scripts.back_mut() {
+            Some(script) => script.frames.push(frame.to_string()),
+            None => scripts.push_back(Script { frames: vec![frame.to_string()], disconnect: false }),
+        }
+    }
+
+    // End `exchange`'s open connection once its frames so far are read, like a server
+    // closing it. The feed reconnects.
+    pub fn close(&self, exchange: &str) {
+        self.state.lock().unwrap().open.remove(exchange);
+    }
+
+    // Every message the feeds sent to `exchange`, over all its connections
+    pub fn sent(&self, exchange: &str) -> Vec<String> {
+        self.state.lock().unwrap().sent.get(exchange).cloned().unwrap_or_default()
+    }
+
+    // How many connections to `exchange` were opened
+    pub fn connections(&self, exchange: &str) -> usize {
+        self.state.lock().unwrap().connections.get(exchange).copied().unwrap_or(0)
+    }
+}
+
+impl FeedSource for MockSource {
+    fn connect<'a>(&'a self, exchange: &'a dyn Exchange) -> BoxFuture<'a, Result<(MessageSink, MessageStream), Box<dyn Error>>> {
+        let name = exchange.name();
+        let (tx, rx) = mpsc::unbounded_channel();
+        {
+            let mut state = self.state.lock().unwrap();
+            *state.connections.entry(name.to_string()).or_default() += 1;
+            let script = state.scripts.get_mut(name).and_then(VecDeque::pop_front).unwrap_or_default();
+            for frame in script.frames {
+                let _ = tx.send(frame);
+            }
+            // Dropping the sender ends the stream once the frames are read
+            if !script.disconnect {
+                state.open.insert(name.to_string(), tx);
+            }
+        }
+
+        let incoming = stream::unfold(rx, |mut rx| async move {
+            let frame = rx.recv().await?;
+            Some((Ok(Message::Text(frame)), rx))
+        });
+        let state = Arc::clone(&self.state);
+        let outgoing = sink::unfold((), move |(), message: Message| {
+            if let Message::Text(text) = message {
+                state.lock().unwrap().sent.entry(name.to_string()).or_default().push(text);
+            }
+            async { Ok::<_, WsError>(()) }
+        });
+        Box::pin(async move { Ok((Box::pin(outgoing) as MessageSink, Box::pin(incoming) as MessageStream)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::alerts::{spawn_alerts, Alert, AlertEngine};
+    use crate::notify::Notifier;
+    use crate::PriceTracker;
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    // A Coinbase tracker on `mock`, without the stale-feed check (no heartbeats)
+    fn tracker(mock: &MockSource) -> PriceTracker {
+        let mut tracker = PriceTracker::new();
+        tracker.detect_stale_feeds(None);
+        tracker.use_source(Arc::new(mock.clone()));
+        tracker
+    }
+
+    fn ticker(symbol: &str, price: &str) -> String {
+        format!(r#"{{"type":"ticker","product_id":"{}","price":"{}"}}"#, symbol, price)
+    }
+
+    // Keeps the alerts it's given
+    #[derive(Clone, Default)]
+    struct Inbox(Arc<Mutex<Vec<Alert>>>);
+
+    impl Notifier for Inbox {
+        fn notify(&self, alert: &Alert) {
+            self.0.lock().unwrap().push(alert.clone());
+        }
+    }
+
+    // Wait (a few seconds at most) for `check` to hold
+    async fn eventually(what: &str, mut check: impl FnMut() -> bool) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while !check() {
+            assert!(tokio::time::Instant::now() < deadline, "timed out waiting for {}", what);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[test]
+    fn alerts_fire_on_a_recorded_breakout() {
+        let mock = MockSource::from_recording(&fixture("coinbase_breakout.ndjson")).unwrap();
+        let rules = "[[alert]]\nsymbol = \"BTC-USD\"\nabove = 70000\ncooldown = \"0s\"\n\n\
+                     [[alert]]\nsymbol = \"ETH-USD\"\nbelow = 3000\n";
+        let engine = AlertEngine::from_toml(rules).unwrap();
+        let mut tracker = tracker(&mock);
+
+        run(async {
+            let inbox = Inbox::default();
+            spawn_alerts(engine, tracker.store(), vec![Box::new(inbox.clone())]);
+            tracker.subscribe(&["BTC-USD".to_string(), "ETH-USD".to_string()]);
+
+            // Up through 70000, a tick further up, back down and through it again; ETH stays above 3000
+            eventually("two alerts", || inbox.0.lock().unwrap().len() >= 2).await;
+            tokio::time::sleep(Duration::from_millis(100)).await;  // Nothing else is coming
+            let fired: Vec<(String, String)> = inbox.0.lock().unwrap().iter().map(|a| (a.rule.clone(), a.price.to_string())).collect();
+            assert_eq!(
+                fired,
+                [("BTC-USD above 70000", "70250.00"), ("BTC-USD above 70000", "70100.00")].map(|(r, p)| (r.to_string(), p.to_string()))
+            );
+            tracker.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn a_scripted_disconnect_reconnects_and_resubscribes() {
+        let mock = MockSource::new();
+        mock.script_disconnect("coinbase", &[&ticker("BTC-USD", "65000")]);
+        mock.script("coinbase", &[&ticker("BTC-USD", "65500")]);
+        let mut tracker = tracker(&mock);
+
+        run(async {
+            let mut updates = tracker.updates();
+            tracker.subscribe(&["BTC-USD".to_string()]);
+            let mut prices = Vec::new();
+            for _ in 0..2 {
+                let update = tokio::time::timeout(Duration::from_secs(5), updates.next()).await.expect("an update").unwrap();
+                prices.push(update.price.to_string());
+            }
+            assert_eq!(prices, ["65000", "65500"]);
+
+            // One subscription per connection; the second connection stays open for more
+            assert_eq!(mock.connections("coinbase"), 2);
+            assert_eq!(mock.sent("coinbase").len(), 2);
+            mock.push("coinbase", &ticker("BTC-USD", "65600"));
+            let update = tokio::time::timeout(Duration::from_secs(5), updates.next()).await.expect("an update").unwrap();
+            assert_eq!(update.price.to_string(), "65600");
+            assert_eq!(mock.connections("coinbase"), 2);
+            tracker.shutdown().await;
+        });
+    }
+}
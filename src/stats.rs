@@ -0,0 +1,166 @@
+// Rolling statistics per symbol: percent change over 1m/5m/1h, the high and low of
+// the last hour, and a simple volatility figure, computed from a short in-memory
+// price history that `PriceStore` keeps for every (exchange, symbol).
+//
+// The history holds at most one sample per second, so a busy symbol costs at most
+// 3600 samples, however many ticks it gets.
+
+use std::{
+    collections::VecDeque,          // Samples, oldest first
+    fmt,                            // One-line summary for the terminal output
+    time::{Duration, SystemTime},   // Sample times and windows
+};
+
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::Serialize;
+
+// The percent-change windows that are reported
+pub const WINDOWS: [Duration; 3] = [Duration::from_secs(60), Duration::from_secs(300), Duration::from_secs(3600)];
+
+// How much history is kept (the longest window)
+pub const HISTORY: Duration = Duration::from_secs(3600);
+
+// Recent prices of one symbol on one exchange
+#[derive(Debug, Default, Clone)]
+pub struct PriceHistory {
+    samples: VecDeque<(SystemTime, Decimal)>,
+}
+
+impl PriceHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add a price. Ticks within the same second replace each other; samples older
+    // than `HISTORY` (measured from the newest one) are dropped.
+    pub fn record(&mut self, at: SystemTime, price: Decimal) {
+        match self.samples.back_mut() {
+            Some((last, _)) if at < *last => return,  // Out of order (e.g. a late seed); ignore
+            Some((last, last_price)) if same_second(*last, at) => {
+                *last = at;
+                *last_price = price;
+            }
+            _ => self.samples.push_back((at, price)),
+        }
+        // Drop what nothing needs any more, keeping the newest sample at or before the
+        // cut-off: it's the reference point for the 1h change
+        let cutoff = at.checked_sub(HISTORY);
+        while let Some(cutoff) = cutoff
+            && let Some(&(next, _)) = self.samples.get(1)
+            && next <= cutoff
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    // % change from the price `window` before the newest sample to the newest one.
+    // None until the history reaches back that far.
+    pub fn change_pct(&self, window: Duration) -> Option<Decimal> {
+        let &(now, last) = self.samples.back()?;
+        let cutoff = now.checked_sub(window)?;
+        let (_, reference) = self.samples.iter().rev().find(|(at, _)| *at <= cutoff)?;
+        (!reference.is_zero()).then(|| ((last - reference) / reference * Decimal::ONE_HUNDRED).round_dp(2))
+    }
+
+    // Highest and lowest price over the kept history
+    pub fn high_low(&self) -> Option<(Decimal, Decimal)> {
+        let high = self.samples.iter().map(|&(_, p)| p).max()?;
+        let low = self.samples.iter().map(|&(_, p)| p).min()?;
+        Some((high, low))
+    }
+
+    // Standard deviation of the sample-to-sample % returns: a rough "how jumpy is it"
+    // number, comparable between symbols. None with fewer than three samples.
+    pub fn volatility(&self) -> Option<Decimal> {
+        let returns: Vec<f64> = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .filter(|((_, a), _)| !a.is_zero())
+            .filter_map(|(&(_, a), &(_, b))| ((b - a) / a * Decimal::ONE_HUNDRED).to_f64())
+            .collect();
+        if returns.len() < 2 {
+            return None;
+        }
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+        Decimal::from_f64_retain(variance.sqrt()).map(|v| v.round_dp(4))
+    }
+
+    // Everything at once, for output
+    pub fn stats(&self) -> Stats {
+        let [m1, m5, h1] = WINDOWS.map(|w| self.change_pct(w));
+        let (high, low) = self.high_low().unzip();
+        Stats { change_1m_pct: m1, change_5m_pct: m5, change_1h_pct: h1, high_1h: high, low_1h: low, volatility: self.volatility() }
+    }
+}
+
+// Rolling statistics of one symbol at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Stats {
+    pub change_1m_pct: Option<Decimal>,
+    pub change_5m_pct: Option<Decimal>,
+    pub change_1h_pct: Option<Decimal>,
+    pub high_1h: Option<Decimal>,
+    pub low_1h: Option<Decimal>,
+    pub volatility: Option<Decimal>,  // Std dev of per-sample % returns
+}
+
+// "1m +0.12%  5m —  1h —  high 65000 / low 64000  vol 0.0100"
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pct = |p: Option<Decimal>| p.map(|p| format!("{:+.2}%", p)).unwrap_or_else(|| "—".to_string());
+        write!(f, "1m {}  5m {}  1h {}", pct(self.change_1m_pct), pct(self.change_5m_pct), pct(self.change_1h_pct))?;
+        if let (Some(high), Some(low)) = (self.high_1h, self.low_1h) {
+            write!(f, "  high {} / low {}", high, low)?;
+        }
+        if let Some(vol) = self.volatility {
+            write!(f, "  vol {}", vol)?;
+        }
+        Ok(())
+    }
+}
+
+fn same_second(a: SystemTime, b: SystemTime) -> bool {
+    let secs = |t: SystemTime| t.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    secs(a) == secs(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn d(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn changes_high_low_and_volatility_over_the_windows() {
+        let mut history = PriceHistory::new();
+        history.record(at(1000), d("100"));
+        assert_eq!(history.change_pct(WINDOWS[0]), None, "no history a minute back yet");
+
+        history.record(at(1000 + 240), d("110"));
+        history.record(at(1000 + 240), d("104"));  // Same second: replaces 110 (so it's no longer the high)
+        history.record(at(1000 + 300), d("102"));
+
+        assert_eq!(history.change_pct(WINDOWS[0]), Some(d("-1.92")));  // 104 -> 102
+        assert_eq!(history.change_pct(WINDOWS[1]), Some(d("2.00")));   // 100 -> 102
+        assert_eq!(history.change_pct(WINDOWS[2]), None);
+        assert_eq!(history.high_low(), Some((d("104"), d("100"))));
+        assert!(history.volatility().unwrap() > Decimal::ZERO);
+
+        // Two hours later only the reference point for the 1h change is kept
+        history.record(at(1000 + 300 + 7200), d("51"));
+        assert_eq!(history.change_pct(WINDOWS[2]), Some(d("-50.00")));
+        assert_eq!(history.samples.len(), 2);
+    }
+}
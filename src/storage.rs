@@ -0,0 +1,319 @@
+// Persists every price update to a local SQLite database so history survives
+// after the program exits.
+//
+// SQLite calls block, so they never run on the WebSocket loop: updates are handed
+// to a dedicated writer thread over a bounded queue, and that thread inserts them in
+// batches (one transaction per batch) to keep disk I/O cheap.
+//
+// When the disk stalls, the queue fills and new updates wait up to `queue_timeout`
+// for room before they're dropped (and counted), so a stuck database costs history
+// rather than memory or the rest of the tracker.
+
+use std::{
+    path::{Path, PathBuf},                           // Location of the database file
+    sync::mpsc::RecvTimeoutError,                    // Waiting on the queue with a deadline
+    thread::{self, JoinHandle},                      // The dedicated writer thread
+    time::{Duration, Instant},                       // Batch timing
+};
+
+use rusqlite::{params, Connection, OpenFlags};       // SQLite bindings
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+
+use crate::candles::Candle;
+use crate::queue::{BoundedQueue, Overflow};
+use crate::store::{epoch_ms, PriceStore, PriceUpdate};
+
+// Where and how often to write
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    pub path: PathBuf,             // SQLite database file (created if missing)
+    pub batch_size: usize,         // Write as soon as this many updates are queued...
+    pub flush_interval: Duration,  // ...or when this much time has passed since the last write
+    pub queue: usize,              // Updates waiting for the writer at most...
+    pub queue_timeout: Duration,   // ...and how long a new one waits for room before it's dropped
+}
+
+impl StorageConfig {
+    // Sensible defaults for a given database path
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            batch_size: 500,
+            flush_interval: Duration::from_secs(1),
+            queue: 50_000,
+            queue_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+// Messages understood by the writer thread
+enum Command {
+    Record(PriceUpdate),  // Queue one update for the next batch
+    Candle(Candle),       // Queue one closed candle for the next batch
+}
+
+// Handle to the writer thread. Cloning isn't needed: share it via `attach`.
+pub struct Storage {
+    queue: BoundedQueue<Command>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Storage {
+    // Open (or create) the database, make sure the table exists, and start the writer thread
+    pub fn open(config: StorageConfig) -> rusqlite::Result<Self> {
+        let conn = Connection::open(&config.path)?;
+        init_schema(&conn)?;
+
+        let queue = BoundedQueue::new("sqlite", config.queue, Overflow::Block(config.queue_timeout));
+        let consumer = queue.clone();
+        let writer = thread::Builder::new()
+            .name("sqlite-writer".to_string())
+            .spawn(move || writer_loop(conn, consumer, config))
+            .expect("failed to spawn SQLite writer thread");
+
+        Ok(Self { queue, writer: Some(writer) })
+    }
+
+    // Queue one update. Never blocks: with the queue full, it's dropped.
+    pub fn record(&self, update: PriceUpdate) {
+        let _ = self.queue.push(Command::Record(update));  // Err only if the writer already stopped
+    }
+
+    // Queue one closed candle (stored in the `candles` table)
+    pub fn record_candle(&self, candle: Candle) {
+        let _ = self.queue.push(Command::Candle(candle));
+    }
+
+    // A cheap, cloneable callback that queues candles; handy for `candles::spawn_candles`
+    pub fn candle_sink(&self) -> impl Fn(&Candle) + Send + 'static {
+        let queue = self.queue.clone();
+        move |candle: &Candle| {
+            let _ = queue.push(Command::Candle(candle.clone()));
+        }
+    }
+
+    // Spawn a task that writes every update published by `store`. With the writer behind,
+    // the task waits (up to `queue_timeout` per update) rather than the feed.
+    pub fn attach(&self, store: &PriceStore) -> tokio::task::JoinHandle<()> {
+        let queue = self.queue.clone();
+        queue.report_to(store.metrics());
+        let mut rx = store.subscribe_updates();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(update) => {
+                        if queue.send(Command::Record(update)).await.is_err() {
+                            break;  // Writer thread has shut down
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => queue.count_dropped(n),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    // Updates and candles dropped so far because the writer couldn't keep up
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped()
+    }
+
+    // Write any queued updates and wait for the writer thread to finish
+    pub fn close(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.queue.close();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl Drop for Storage {
+    // Don't lose the last partial batch if the handle is simply dropped
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// One row read back from the database
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredTick {
+    pub ts_ms: i64,        // Receive time, milliseconds since the Unix epoch
+    pub exchange: String,
+    pub symbol: String,
+    pub price: String,
+}
+
+// Read recorded history in time order, optionally for a single symbol
+pub fn read_history<P: AsRef<Path>>(path: P, symbol: Option<&str>) -> rusqlite::Result<Vec<StoredTick>> {
+    read_range(path, symbol, None, None)
+}
+
+// The same, limited to `since_ms <= ts_ms < until_ms` (either end open)
+pub fn read_range<P: AsRef<Path>>(
+    path: P,
+    symbol: Option<&str>,
+    since_ms: Option<i64>,
+    until_ms: Option<i64>,
+) -> rusqlite::Result<Vec<StoredTick>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "SELECT ts_ms, exchange, symbol, price FROM prices
+         WHERE (?1 IS NULL OR symbol = ?1) AND (?2 IS NULL OR ts_ms >= ?2) AND (?3 IS NULL OR ts_ms < ?3)
+         ORDER BY ts_ms, id",
+    )?;
+    let rows = stmt.query_map(params![symbol, since_ms, until_ms], |row| {
+        Ok(StoredTick { ts_ms: row.get(0)?, exchange: row.get(1)?, symbol: row.get(2)?, price: row.get(3)? })
+    })?;
+    rows.collect()
+}
+
+// Create the table (and an index for per-symbol time range queries) if they don't exist yet
+fn init_schema(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS prices (
+             id       INTEGER PRIMARY KEY,
+             exchange TEXT    NOT NULL,
+             symbol   TEXT    NOT NULL,
+             price    TEXT    NOT NULL,  -- decimal text, so no digits are lost
+             ts_ms    INTEGER NOT NULL   -- receive time, milliseconds since the Unix epoch
+         );
+         CREATE INDEX IF NOT EXISTS prices_symbol_ts ON prices (symbol, ts_ms);
+         CREATE TABLE IF NOT EXISTS candles (
+             exchange      TEXT    NOT NULL,
+             symbol        TEXT    NOT NULL,
+             interval_secs INTEGER NOT NULL,
+             start_ms      INTEGER NOT NULL,  -- bucket start, milliseconds since the Unix epoch
+             open          REAL    NOT NULL,
+             high          REAL    NOT NULL,
+             low           REAL    NOT NULL,
+             close         REAL    NOT NULL,
+             volume        REAL    NOT NULL,
+             trades        INTEGER NOT NULL,
+             PRIMARY KEY (exchange, symbol, interval_secs, start_ms)
+         );",
+    )
+}
+
+// Runs on the writer thread: collect updates and write them out in batches
+fn writer_loop(mut conn: Connection, queue: BoundedQueue<Command>, config: StorageConfig) {
+    let mut batch: Vec<PriceUpdate> = Vec::with_capacity(config.batch_size);
+    let mut candles: Vec<Candle> = Vec::new();
+    let mut last_flush = Instant::now();
+    let mut reported = 0;  // Drops already logged
+
+    loop {
+        // Wait for the next update, but no longer than the time left until the next flush
+        let wait = config.flush_interval.saturating_sub(last_flush.elapsed());
+        let closing = match queue.pop_timeout(wait) {
+            Ok(Command::Record(update)) => {
+                batch.push(update);
+                false
+            }
+            Ok(Command::Candle(candle)) => {
+                candles.push(candle);
+                false
+            }
+            Err(RecvTimeoutError::Disconnected) => true,
+            Err(RecvTimeoutError::Timeout) => false,
+        };
+
+        let due = batch.len() >= config.batch_size || last_flush.elapsed() >= config.flush_interval;
+        if (due || closing) && (!batch.is_empty() || !candles.is_empty()) {
+            if let Err(e) = insert_batch(&mut conn, &batch, &candles) {
+                error!(updates = batch.len(), candles = candles.len(), error = %e, "SQLite write failed");
+            }
+            batch.clear();
+            candles.clear();
+        }
+        if due {
+            last_flush = Instant::now();
+            // Once per flush at most, so a full queue doesn't flood the log
+            let dropped = queue.dropped();
+            if dropped > reported {
+                warn!(dropped = dropped - reported, "SQLite storage fell behind, updates not saved");
+                reported = dropped;
+            }
+        }
+        if closing {
+            break;
+        }
+    }
+}
+
+// Insert a batch of updates and candles inside a single transaction
+fn insert_batch(conn: &mut Connection, batch: &[PriceUpdate], candles: &[Candle]) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO prices (exchange, symbol, price, ts_ms) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for update in batch {
+            let ts_ms = epoch_ms(update.received_at) as i64;
+            stmt.execute(params![update.exchange, update.symbol, update.price.to_string(), ts_ms])?;
+        }
+
+        // A candle can be re-written (e.g. after a restart), so the newest version wins
+        let mut stmt = tx.prepare_cached(
+            "INSERT OR REPLACE INTO candles
+                 (exchange, symbol, interval_secs, start_ms, open, high, low, close, volume, trades)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+        for c in candles {
+            stmt.execute(params![
+                c.exchange,
+                c.symbol,
+                c.interval.as_secs() as i64,
+                c.start_ms as i64,
+                real(c.open),
+                real(c.high),
+                real(c.low),
+                real(c.close),
+                real(c.volume),
+                c.trades as i64
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+// SQLite has no decimal type; candle columns are REAL
+fn real(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn queued_updates_are_written_on_close() {
+        let path = std::env::temp_dir().join(format!("crabby-storage-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let storage = Storage::open(StorageConfig::new(&path)).unwrap();
+        for price in ["100", "101", "102"] {
+            storage.record(PriceUpdate {
+                exchange: "coinbase",
+                symbol: "BTC-USD".to_string(),
+                price: price.parse().unwrap(),
+                open_24h: None,
+                size: None,
+                received_at: SystemTime::now(),
+            });
+        }
+        storage.close();
+
+        let rows: Vec<String> = read_history(&path, Some("BTC-USD")).unwrap().into_iter().map(|t| t.price).collect();
+        assert_eq!(rows, ["100", "101", "102"]);
+        assert!(read_history(&path, Some("ETH-USD")).unwrap().is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,726 @@
+// Shared price state: the latest update and a short rolling history per (exchange,
+// symbol), plus a broadcast channel so any number of consumers can follow updates
+// as they happen. The state lives on its own thread and is reached by message passing:
+// requests go through a bounded queue and questions are answered on a oneshot channel,
+// both awaited, so callers never block a runtime worker and a busy state thread slows
+// the feeds down instead of piling up requests.
+
+use std::{
+    collections::{HashMap, HashSet},  // State per symbol and exchange; stale symbols in a view
+    sync::Arc,
+    sync::atomic::{AtomicU64, Ordering},  // Lock-free update counter
+    time::{Duration, SystemTime},  // When an update was received; tick history windows
+};
+
+use rust_decimal::Decimal;    // Exact prices
+use serde::{Serialize, Serializer};  // JSON output (REST API, NDJSON)
+use tokio::sync::broadcast;   // Fan-out channel for live updates
+use tokio::sync::{mpsc, oneshot};  // Requests to the state thread, and its answers
+
+use crate::exchange::Ticker;
+use crate::fx::FxRates;
+use crate::market::MarketData;
+use crate::health::{FeedHealth, FeedStats};
+use crate::metrics::Metrics;
+use crate::orderbook::{BookEvent, OrderBooks, TopOfBook};
+use crate::stats::{PriceHistory, Stats};
+use crate::ticks::{TickBuffer, TickLimits};
+use crate::volume::{TradeVolumes, VolumeStats};
+use crate::funding::{FundingInfo, FundingRates};
+
+// How many updates a slow subscriber may fall behind before it starts missing some
+const UPDATE_CHANNEL_CAPACITY: usize = 1024;
+
+// Requests waiting for the state thread before senders have to wait too
+const REQUEST_QUEUE: usize = 4096;
+
+// One price update from one exchange
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PriceUpdate {
+    pub exchange: &'static str,   // Which venue sent it (e.g. "coinbase")
+    pub symbol: String,           // Our symbol name (e.g. "BTC-USD")
+    pub price: Decimal,           // Last trade price
+    pub open_24h: Option<Decimal>, // Price 24 hours ago, if the exchange reports it
+    pub size: Option<Decimal>,    // Size of the last trade, if the exchange reports it
+    #[serde(rename = "timestamp_ms", serialize_with = "serialize_epoch_ms")]
+    pub received_at: SystemTime,  // Local time the update arrived
+}
+
+impl PriceUpdate {
+    // Turn a connector's raw ticker into a typed update. Fails if any number doesn't parse.
+    pub fn from_ticker(
+        exchange: &'static str,
+        symbol: String,
+        ticker: &Ticker,
+        received_at: SystemTime,
+    ) -> Result<Self, PriceError> {
+        let optional = |field, value: &Option<String>| value.as_deref().map(|v| parse_decimal(field, v)).transpose();
+        Ok(Self {
+            exchange,
+            symbol,
+            price: parse_decimal("price", &ticker.price)?,
+            open_24h: optional("open_24h", &ticker.open_24h)?,
+            size: optional("size", &ticker.size)?,
+            received_at,
+        })
+    }
+}
+
+// A number from the exchange that isn't a valid decimal
+#[derive(Debug)]
+pub struct PriceError {
+    pub field: &'static str,  // Which ticker field ("price", "open_24h", "size")
+    pub value: String,        // The text we got
+    pub source: rust_decimal::Error,
+}
+
+impl std::fmt::Display for PriceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bad {} {:?}: {}", self.field, self.value, self.source)
+    }
+}
+
+impl std::error::Error for PriceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+// Parse "65000.01" (or scientific notation like "1e-7", which some JSON encoders produce)
+pub(crate) fn parse_decimal(field: &'static str, value: &str) -> Result<Decimal, PriceError> {
+    let value = value.trim();
+    value
+        .parse::<Decimal>()
+        .or_else(|e| Decimal::from_scientific(value).map_err(|_| e))
+        .map_err(|source| PriceError { field, value: value.to_string(), source })
+}
+
+// Timestamps go out as milliseconds since the Unix epoch, which every JSON consumer understands
+fn serialize_epoch_ms<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(epoch_ms(*time))
+}
+
+// Milliseconds since the Unix epoch (0 for clocks set before 1970)
+pub fn epoch_ms(time: SystemTime) -> u64 {
+    time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+// Everything the state thread can be asked to do. Changes are fire-and-forget;
+// queries carry a channel for the answer.
+enum Request {
+    Update(PriceUpdate),
+    Seed(PriceUpdate),
+    SeedHistory(Vec<PriceUpdate>),
+    SetStale(&'static str, String, bool),
+    Remove(String, Reply<()>),  // Answered once it's done, so metrics and snapshots agree right away
+    Latest(String, Reply<Option<PriceUpdate>>),
+    LatestOn(String, String, Reply<Option<PriceUpdate>>),
+    Stats(String, String, Reply<Option<Stats>>),
+    IsStale(String, String, Reply<bool>),
+    Stale(Reply<StaleSet>),
+    Snapshot(Reply<Vec<PriceUpdate>>),
+    View(Reply<PriceView>),
+    KeepTicks(TickLimits),
+    Ticks(String, String, SystemTime, Reply<Vec<PriceUpdate>>),
+    Book(&'static str, String, BookEvent),
+    ForgetBook(&'static str, String),
+    Top(String, String, Reply<Option<TopOfBook>>),
+    Trade(&'static str, String, SystemTime, Decimal, Decimal),
+    Volumes(String, String, Vec<Duration>, SystemTime, Reply<Option<Vec<VolumeStats>>>),
+    VolumeSpike(String, String, Duration, SystemTime, Reply<Option<(Decimal, Decimal)>>),
+    Message(&'static str, String, SystemTime, Option<SystemTime>),
+    Funding(&'static str, String, FundingInfo),
+    FundingOf(String, String, Reply<Option<FundingInfo>>),
+    FundingRates(Reply<Vec<(&'static str, String, FundingInfo)>>),
+    FeedStats(SystemTime, Reply<Vec<FeedStats>>),
+}
+
+type Reply<T> = oneshot::Sender<T>;
+
+// Every (exchange, symbol) currently marked stale
+pub type StaleSet = HashSet<(&'static str, String)>;
+
+// Per symbol, then per exchange: a lookup is two hash gets, and everything about one
+// symbol (to remove it, or find its latest price anywhere) sits together
+pub(crate) type BySymbol<T> = HashMap<String, HashMap<&'static str, T>>;
+
+pub(crate) fn get<'a, T>(map: &'a BySymbol<T>, exchange: &str, symbol: &str) -> Option<&'a T> {
+    map.get(symbol)?.get(exchange)
+}
+
+pub(crate) fn entry<'a, T: Default>(map: &'a mut BySymbol<T>, exchange: &'static str, symbol: &str) -> &'a mut T {
+    if !map.contains_key(symbol) {
+        map.insert(symbol.to_string(), HashMap::new());
+    }
+    map.get_mut(symbol).unwrap().entry(exchange).or_default()
+}
+
+// The price state itself, and everything else the feeds write on their hot path. Only
+// the state thread ever touches it, so no locks.
+#[derive(Default)]
+struct State {
+    prices: BySymbol<Option<PriceUpdate>>,
+    history: BySymbol<PriceHistory>,   // For % change, high/low, volatility
+    stale: BySymbol<bool>,             // Symbols whose feed went silent
+    ticks: BySymbol<TickBuffer>,       // Every recent tick, for charts and /history
+    tick_limits: TickLimits,
+    books: OrderBooks,                 // Level 2 order books, when enabled
+    trades: TradeVolumes,              // Rolling trade volume, when the trade channel is on
+    health: FeedHealth,                // Message rate and exchange-to-us latency per symbol
+    funding: FundingRates,             // Mark price, index price and funding rate of perpetual contracts
+}
+
+impl State {
+    // The state thread: apply requests in the order they were sent until every store handle is gone
+    fn run(mut self, mut requests: mpsc::Receiver<Request>, metrics: Metrics) {
+        while let Some(request) = requests.blocking_recv() {
+            self.handle(request, &metrics);
+        }
+    }
+
+    fn handle(&mut self, request: Request, metrics: &Metrics) {
+        // A reply can only fail if the asker gave up waiting, which is fine to ignore
+        match request {
+            Request::Update(update) => {
+                entry(&mut self.history, update.exchange, &update.symbol).record(update.received_at, update.price);
+                if self.tick_limits.max_ticks > 0 {
+                    entry(&mut self.ticks, update.exchange, &update.symbol).push(update.clone(), self.tick_limits);
+                }
+                self.prices.entry(update.symbol.clone()).or_default().insert(update.exchange, Some(update));
+            }
+            Request::Seed(update) => {
+                let price = entry(&mut self.prices, update.exchange, &update.symbol);
+                if price.is_none() {
+                    *price = Some(update);
+                }
+            }
+            Request::SeedHistory(points) => {
+                for point in points {
+                    entry(&mut self.history, point.exchange, &point.symbol).record(point.received_at, point.price);
+                }
+            }
+            Request::SetStale(exchange, symbol, stale) => {
+                if stale {
+                    *entry(&mut self.stale, exchange, &symbol) = true;
+                } else if let Some(exchanges) = self.stale.get_mut(&symbol) {
+                    exchanges.remove(exchange);
+                }
+            }
+            Request::Remove(symbol, reply) => {
+                for exchange in self.prices.remove(&symbol).unwrap_or_default().into_keys() {
+                    metrics.forget_price(exchange, &symbol);
+                }
+                self.history.remove(&symbol);
+                self.stale.remove(&symbol);
+                self.ticks.remove(&symbol);
+                self.books.remove_symbol(&symbol);
+                self.trades.remove_symbol(&symbol);
+                self.health.remove_symbol(&symbol);
+                self.funding.remove_symbol(&symbol);
+                let _ = reply.send(());
+            }
+            Request::Latest(symbol, reply) => {
+                let _ = reply.send(self.latest(&symbol).cloned());
+            }
+            Request::LatestOn(exchange, symbol, reply) => {
+                let _ = reply.send(get(&self.prices, &exchange, &symbol).cloned().flatten());
+            }
+            Request::Stats(exchange, symbol, reply) => {
+                let _ = reply.send(get(&self.history, &exchange, &symbol).map(PriceHistory::stats));
+            }
+            Request::IsStale(exchange, symbol, reply) => {
+                let _ = reply.send(get(&self.stale, &exchange, &symbol).copied().unwrap_or_default());
+            }
+            Request::Stale(reply) => {
+                let _ = reply.send(self.stale_set());
+            }
+            Request::Snapshot(reply) => {
+                let _ = reply.send(self.snapshot());
+            }
+            Request::View(reply) => {
+                let prices = self.snapshot();
+                let stats = prices
+                    .iter()
+                    .filter_map(|u| Some(((u.exchange, u.symbol.clone()), get(&self.history, u.exchange, &u.symbol)?.stats())))
+                    .collect();
+                let tops = self.books.tops().map(|(exchange, symbol, top)| ((exchange, symbol.clone()), top)).collect();
+                let _ = reply.send(PriceView { prices, stats, stale: self.stale_set(), tops });
+            }
+            Request::KeepTicks(limits) => {
+                self.tick_limits = limits;
+                if limits.max_ticks == 0 {
+                    self.ticks.clear();
+                }
+            }
+            Request::Ticks(exchange, symbol, since, reply) => {
+                let ticks = get(&self.ticks, &exchange, &symbol).map(|t| t.since(since));
+                let _ = reply.send(ticks.unwrap_or_default());
+            }
+            Request::Book(exchange, symbol, event) => self.books.apply(exchange, &symbol, &event),
+            Request::ForgetBook(exchange, symbol) => self.books.remove(exchange, &symbol),
+            Request::Top(exchange, symbol, reply) => {
+                let _ = reply.send(self.books.top(&exchange, &symbol));
+            }
+            Request::Trade(exchange, symbol, at, price, size) => self.trades.record(exchange, &symbol, at, price, size),
+            Request::Volumes(exchange, symbol, windows, now, reply) => {
+                let _ = reply.send(self.trades.stats(&exchange, &symbol, &windows, now));
+            }
+            Request::VolumeSpike(exchange, symbol, window, now, reply) => {
+                let _ = reply.send(self.trades.spike(&exchange, &symbol, window, now));
+            }
+            Request::Message(exchange, symbol, received_at, sent_at) => self.health.record(exchange, &symbol, received_at, sent_at),
+            Request::FeedStats(now, reply) => {
+                let _ = reply.send(self.health.stats(now));
+            }
+            Request::Funding(exchange, symbol, info) => self.funding.record(exchange, &symbol, info),
+            Request::FundingOf(exchange, symbol, reply) => {
+                let _ = reply.send(self.funding.get(&exchange, &symbol));
+            }
+            Request::FundingRates(reply) => {
+                let _ = reply.send(self.funding.all());
+            }
+        }
+    }
+
+    // Most recent price of `symbol` on any exchange
+    fn latest(&self, symbol: &str) -> Option<&PriceUpdate> {
+        self.prices.get(symbol)?.values().flatten().max_by_key(|u| u.received_at)
+    }
+
+    fn stale_set(&self) -> StaleSet {
+        self.stale
+            .iter()
+            .flat_map(|(symbol, exchanges)| exchanges.iter().filter(|(_, stale)| **stale).map(|(e, _)| (*e, symbol.clone())))
+            .collect()
+    }
+
+    // Every latest price, sorted by symbol then exchange
+    fn snapshot(&self) -> Vec<PriceUpdate> {
+        let mut all: Vec<PriceUpdate> = self.prices.values().flat_map(|exchanges| exchanges.values().flatten().cloned()).collect();
+        all.sort_by(|a, b| (&a.symbol, a.exchange).cmp(&(&b.symbol, b.exchange)));
+        all
+    }
+}
+
+// Every latest price with its statistics, stale mark and top of book, as of one moment:
+// for code that looks up many of them at once (the printout, the portfolio, scripts)
+// without asking the state thread each time
+#[derive(Debug, Clone, Default)]
+pub struct PriceView {
+    prices: Vec<PriceUpdate>,                        // Sorted by symbol then exchange
+    stats: HashMap<(&'static str, String), Stats>,
+    stale: StaleSet,
+    tops: HashMap<(&'static str, String), TopOfBook>,  // Only with order books on
+}
+
+impl PriceView {
+    // Most recent price for a symbol from any exchange
+    pub fn latest(&self, symbol: &str) -> Option<PriceUpdate> {
+        self.on_every_exchange(symbol).max_by_key(|u| u.received_at).cloned()
+    }
+
+    // Latest price for a symbol on one specific exchange
+    pub fn latest_on(&self, exchange: &str, symbol: &str) -> Option<PriceUpdate> {
+        self.on_every_exchange(symbol).find(|u| u.exchange == exchange).cloned()
+    }
+
+    // Rolling statistics for one symbol on one exchange
+    pub fn stats(&self, exchange: &'static str, symbol: &str) -> Option<Stats> {
+        self.stats.get(&(exchange, symbol.to_string())).cloned()
+    }
+
+    // Whether the last price of `symbol` on `exchange` may be out of date
+    pub fn is_stale(&self, exchange: &'static str, symbol: &str) -> bool {
+        self.stale.contains(&(exchange, symbol.to_string()))
+    }
+
+    // Best bid/ask for one symbol on one exchange, if its book is kept
+    pub fn top(&self, exchange: &'static str, symbol: &str) -> Option<TopOfBook> {
+        self.tops.get(&(exchange, symbol.to_string())).copied()
+    }
+
+    // Every latest price, sorted by symbol then exchange
+    pub fn snapshot(&self) -> &[PriceUpdate] {
+        &self.prices
+    }
+
+    // The prices of one symbol (next to each other, since they're sorted by symbol)
+    fn on_every_exchange<'a>(&'a self, symbol: &'a str) -> impl Iterator<Item = &'a PriceUpdate> + 'a {
+        let start = self.prices.partition_point(|u| u.symbol.as_str() < symbol);
+        self.prices[start..].iter().take_while(move |u| u.symbol == symbol)
+    }
+}
+
+// Cheap to clone: every clone talks to the same state thread and shares the same channel.
+//
+// The prices, history, stale marks, order books, trade volumes, funding rates and feed
+// health are owned by one thread and changed only through messages, so the feeds' hot path is a channel
+// send (plus the broadcast) instead of a lock per map shared with every reader. Readers ask the thread and await its answer;
+// requests are handled in order, so a reader that just saw an update on the broadcast
+// channel always finds it in `latest` too. The request queue is bounded: when the state
+// thread falls behind, writers wait for room instead of the queue growing.
+#[derive(Clone)]
+pub struct PriceStore {
+    requests: mpsc::Sender<Request>,  // To the state thread
+    updates: broadcast::Sender<PriceUpdate>,
+    received: Arc<AtomicU64>,  // Total updates seen since start
+    metrics: Metrics,          // Prometheus metrics for this tracker
+    fx: FxRates,               // Display currency rate, when one is configured
+    market: MarketData,        // Circulating supply and rank per asset, with [market]
+}
+
+impl Default for PriceStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceStore {
+    // Starts the state thread, which stops by itself once the last clone is dropped
+    pub fn new() -> Self {
+        let (updates, _) = broadcast::channel(UPDATE_CHANNEL_CAPACITY);
+        let (requests, rx) = mpsc::channel(REQUEST_QUEUE);
+        let metrics = Metrics::new();
+        let thread_metrics = metrics.clone();
+        std::thread::Builder::new()
+            .name("price-state".to_string())
+            .spawn(move || State::default().run(rx, thread_metrics))
+            .expect("failed to start the price state thread");
+        Self {
+            requests,
+            updates,
+            received: Arc::new(AtomicU64::new(0)),
+            metrics,
+            fx: FxRates::default(),
+            market: MarketData::default(),
+        }
+    }
+
+    // Hand a request to the state thread, waiting for room in its queue
+    async fn send(&self, request: Request) {
+        let _ = self.requests.send(request).await;  // Only fails once the thread is gone, i.e. never while a store exists
+    }
+
+    // Ask the state thread something and wait for the answer
+    async fn ask<T: Default>(&self, request: impl FnOnce(Reply<T>) -> Request) -> T {
+        let (reply, answer) = oneshot::channel();
+        self.send(request(reply)).await;
+        answer.await.unwrap_or_default()
+    }
+
+    // Record a new price and notify subscribers
+    pub async fn update(&self, update: PriceUpdate) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.metrics.record_price(&update);
+        self.send(Request::Update(update.clone())).await;
+        let _ = self.updates.send(update);  // Err only means nobody is listening right now
+    }
+
+    // Fill in a price from history (e.g. the backfill) unless a live one already arrived.
+    // Seeds aren't broadcast or counted: they aren't new updates.
+    pub async fn seed(&self, update: PriceUpdate) {
+        self.send(Request::Seed(update)).await;
+    }
+
+    // Fill in the rolling history from older price points (oldest first), e.g. backfilled
+    // candle closes, so the % changes don't have to wait an hour for live data
+    pub async fn seed_history(&self, points: &[PriceUpdate]) {
+        self.send(Request::SeedHistory(points.to_vec())).await;
+    }
+
+    // Rolling statistics for one symbol on one exchange, once it has a price
+    pub async fn stats(&self, exchange: &str, symbol: &str) -> Option<Stats> {
+        self.ask(|reply| Request::Stats(exchange.to_string(), symbol.to_string(), reply)).await
+    }
+
+    // Prune a symbol that is no longer tracked: its prices, order books, trades, funding,
+    // feed health and price gauges
+    pub async fn remove(&self, symbol: &str) {
+        self.ask(|reply| Request::Remove(symbol.to_string(), reply)).await;
+    }
+
+    // Mark a symbol stale on one exchange (no data or heartbeat for too long), or fresh
+    // again once something arrives
+    pub async fn set_stale(&self, exchange: &'static str, symbol: &str, stale: bool) {
+        self.send(Request::SetStale(exchange, symbol.to_string(), stale)).await;
+    }
+
+    // Whether the last price of `symbol` on `exchange` may be out of date
+    pub async fn is_stale(&self, exchange: &str, symbol: &str) -> bool {
+        self.ask(|reply| Request::IsStale(exchange.to_string(), symbol.to_string(), reply)).await
+    }
+
+    // Every (exchange, symbol) whose last price may be out of date; usually few or none
+    pub async fn stale(&self) -> StaleSet {
+        self.ask(Request::Stale).await
+    }
+
+    // Most recent price for a symbol from any exchange
+    pub async fn latest(&self, symbol: &str) -> Option<PriceUpdate> {
+        self.ask(|reply| Request::Latest(symbol.to_string(), reply)).await
+    }
+
+    // Latest price for a symbol on one specific exchange
+    pub async fn latest_on(&self, exchange: &str, symbol: &str) -> Option<PriceUpdate> {
+        self.ask(|reply| Request::LatestOn(exchange.to_string(), symbol.to_string(), reply)).await
+    }
+
+    // Copy of every latest price, sorted by symbol then exchange for stable output
+    pub async fn snapshot(&self) -> Vec<PriceUpdate> {
+        self.ask(Request::Snapshot).await
+    }
+
+    // Every latest price with its statistics and stale mark, in one round trip
+    pub async fn view(&self) -> PriceView {
+        self.ask(Request::View).await
+    }
+
+    // `snapshot` for code running outside the async runtime, e.g. on the dashboard's
+    // thread. Panics when called from async code, which should use `snapshot`.
+    pub fn blocking_snapshot(&self) -> Vec<PriceUpdate> {
+        let (reply, answer) = oneshot::channel();
+        let _ = self.requests.blocking_send(Request::Snapshot(reply));
+        answer.blocking_recv().unwrap_or_default()
+    }
+
+    // How many recent ticks to keep per (exchange, symbol) for `ticks`; 1000 or an hour
+    // by default. A `max_ticks` of 0 turns the tick history off.
+    pub async fn keep_ticks(&self, limits: TickLimits) {
+        self.send(Request::KeepTicks(limits)).await;
+    }
+
+    // Ticks of one symbol on one exchange from the last `window` (everything kept if None),
+    // oldest first
+    pub async fn ticks(&self, exchange: &str, symbol: &str, window: Option<Duration>) -> Vec<PriceUpdate> {
+        let since = window.and_then(|w| SystemTime::now().checked_sub(w)).unwrap_or(SystemTime::UNIX_EPOCH);
+        self.ask(|reply| Request::Ticks(exchange.to_string(), symbol.to_string(), since, reply)).await
+    }
+
+    // Fold one order book event into the book of `symbol` on `exchange`
+    pub async fn apply_book(&self, exchange: &'static str, symbol: &str, event: BookEvent) {
+        self.send(Request::Book(exchange, symbol.to_string(), event)).await;
+    }
+
+    // Forget one order book, so it's rebuilt from the next snapshot
+    pub async fn forget_book(&self, exchange: &'static str, symbol: &str) {
+        self.send(Request::ForgetBook(exchange, symbol.to_string())).await;
+    }
+
+    // Best bid/ask for one symbol on one exchange, if order books are on and it has one
+    pub async fn top(&self, exchange: &str, symbol: &str) -> Option<TopOfBook> {
+        self.ask(|reply| Request::Top(exchange.to_string(), symbol.to_string(), reply)).await
+    }
+
+    // One trade from a trade channel, for the rolling volumes
+    pub async fn record_trade(&self, exchange: &'static str, symbol: &str, at: SystemTime, price: Decimal, size: Decimal) {
+        self.send(Request::Trade(exchange, symbol.to_string(), at, price, size)).await;
+    }
+
+    // Volume, trade count and VWAP over each of `windows` up to `now`, or None before the
+    // first trade (or when trades aren't followed)
+    pub async fn volumes(&self, exchange: &str, symbol: &str, windows: &[Duration], now: SystemTime) -> Option<Vec<VolumeStats>> {
+        let (exchange, symbol, windows) = (exchange.to_string(), symbol.to_string(), windows.to_vec());
+        self.ask(|reply| Request::Volumes(exchange, symbol, windows, now, reply)).await
+    }
+
+    // Volume of the last `window` and the average per window over the rest of the hour
+    // (see `TradeVolume::spike`)
+    pub async fn volume_spike(&self, exchange: &str, symbol: &str, window: Duration, now: SystemTime) -> Option<(Decimal, Decimal)> {
+        self.ask(|reply| Request::VolumeSpike(exchange.to_string(), symbol.to_string(), window, now, reply)).await
+    }
+
+    // One message about `symbol` arrived, sent by the exchange at `sent_at` if it says
+    pub async fn record_message(&self, exchange: &'static str, symbol: &str, received_at: SystemTime, sent_at: Option<SystemTime>) {
+        self.send(Request::Message(exchange, symbol.to_string(), received_at, sent_at)).await;
+    }
+
+    // Messages per second and latency of every symbol on every feed, sorted by symbol then
+    // exchange
+    pub async fn feed_stats(&self, now: SystemTime) -> Vec<FeedStats> {
+        self.ask(|reply| Request::FeedStats(now, reply)).await
+    }
+
+    // The latest mark price event of a perpetual contract
+    pub async fn record_funding(&self, exchange: &'static str, symbol: &str, info: FundingInfo) {
+        self.send(Request::Funding(exchange, symbol.to_string(), info)).await;
+    }
+
+    // Mark price, index price and funding rate of one contract, if its exchange sends any
+    pub async fn funding(&self, exchange: &str, symbol: &str) -> Option<FundingInfo> {
+        self.ask(|reply| Request::FundingOf(exchange.to_string(), symbol.to_string(), reply)).await
+    }
+
+    // Every contract's figures, sorted by symbol then exchange (none unless a futures
+    // exchange is used)
+    pub async fn funding_rates(&self) -> Vec<(&'static str, String, FundingInfo)> {
+        self.ask(Request::FundingRates).await
+    }
+
+    // Total number of updates received so far
+    pub fn update_count(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    // Prometheus metrics shared by the feeds and the /metrics endpoint
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    // Rate for showing prices in the display currency (none unless configured)
+    pub fn fx(&self) -> &FxRates {
+        &self.fx
+    }
+
+    // Supply, volume and rank for market caps (empty unless [market] is enabled)
+    pub fn market(&self) -> &MarketData {
+        &self.market
+    }
+
+    // Updates sent but not yet seen by every receiver
+    pub fn pending_updates(&self) -> usize {
+        self.updates.len()
+    }
+
+    // New receiver for live updates (only sees updates sent after this call)
+    pub fn subscribe_updates(&self) -> broadcast::Receiver<PriceUpdate> {
+        self.updates.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn update(exchange: &'static str, price: &str, secs: u64) -> PriceUpdate {
+        PriceUpdate {
+            exchange,
+            symbol: "BTC-USD".to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap()
+    }
+
+    #[test]
+    fn latest_picks_most_recent_exchange() {
+        runtime().block_on(async {
+            let store = PriceStore::new();
+            store.update(update("coinbase", "100", 2)).await;
+            store.update(update("kraken", "101", 1)).await;
+
+            assert_eq!(store.latest("BTC-USD").await.unwrap().price, Decimal::from(100));
+            assert_eq!(store.latest_on("kraken", "BTC-USD").await.unwrap().price, Decimal::from(101));
+            assert!(store.latest("ETH-USD").await.is_none());
+
+            // A view answers the same lookups without going back to the state thread
+            store.set_stale("kraken", "BTC-USD", true).await;
+            let view = store.view().await;
+            assert_eq!(view.latest("BTC-USD").unwrap().price, Decimal::from(100));
+            assert_eq!(view.latest_on("kraken", "BTC-USD").unwrap().price, Decimal::from(101));
+            assert!(view.is_stale("kraken", "BTC-USD") && !view.is_stale("coinbase", "BTC-USD"));
+            assert_eq!(view.stats("coinbase", "BTC-USD"), store.stats("coinbase", "BTC-USD").await);
+            assert_eq!(view.snapshot().len(), 2);
+            assert!(view.latest("ETH-USD").is_none());
+        });
+    }
+
+    #[test]
+    fn broadcast_updates_are_already_visible_to_queries() {
+        // Readers in other tasks answer from the state thread, in order with the writes
+        runtime().block_on(async {
+            let store = PriceStore::new();
+            let mut rx = store.subscribe_updates();
+            let reader = {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    for _ in 0..100 {
+                        let seen = rx.recv().await.unwrap();
+                        if store.latest_on(seen.exchange, &seen.symbol).await.unwrap().received_at < seen.received_at {
+                            return false;
+                        }
+                    }
+                    true
+                })
+            };
+            for secs in 0..100 {
+                store.update(update("coinbase", "100", secs)).await;
+            }
+            assert!(reader.await.unwrap());
+            assert_eq!(store.update_count(), 100);
+            assert_eq!(store.snapshot().await.len(), 1);
+        });
+    }
+
+    #[test]
+    fn removed_symbols_disappear_from_every_exchange() {
+        runtime().block_on(async {
+            let store = PriceStore::new();
+            store.update(update("coinbase", "100", 1)).await;
+            store.update(update("kraken", "101", 2)).await;
+
+            store.remove("BTC-USD").await;
+            assert!(store.snapshot().await.is_empty());
+            assert!(!store.metrics().render().contains("symbol=\"BTC-USD\""));
+        });
+    }
+
+    #[test]
+    fn stale_marks_clear_and_go_with_removed_symbols() {
+        runtime().block_on(async {
+            let store = PriceStore::new();
+            store.set_stale("coinbase", "BTC-USD", true).await;
+            assert!(store.is_stale("coinbase", "BTC-USD").await);
+            assert!(!store.is_stale("kraken", "BTC-USD").await);
+
+            store.set_stale("coinbase", "BTC-USD", false).await;
+            assert!(!store.is_stale("coinbase", "BTC-USD").await);
+
+            store.set_stale("coinbase", "ETH-USD", true).await;
+            store.remove("ETH-USD").await;
+            assert!(!store.is_stale("coinbase", "ETH-USD").await);
+        });
+    }
+
+    #[test]
+    fn books_trades_and_feed_health_live_with_the_prices() {
+        runtime().block_on(async {
+            let store = PriceStore::new();
+            let (bids, asks) = (vec![("100".parse().unwrap(), Decimal::ONE)], vec![("101".parse().unwrap(), Decimal::ONE)]);
+            store.apply_book("coinbase", "BTC-USD", BookEvent::Snapshot { symbol: "BTC-USD".into(), bids, asks }).await;
+            let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+            store.record_trade("coinbase", "BTC-USD", at, Decimal::from(100), Decimal::from(2)).await;
+            store.record_message("coinbase", "BTC-USD", at, Some(at)).await;
+            store.update(update("coinbase", "100", 1_700_000_000)).await;
+
+            assert_eq!(store.top("coinbase", "BTC-USD").await.and_then(|t| t.spread), Some(Decimal::ONE));
+            assert_eq!(store.view().await.top("coinbase", "BTC-USD"), store.top("coinbase", "BTC-USD").await);
+            let volumes = store.volumes("coinbase", "BTC-USD", &[Duration::from_secs(60)], at).await.unwrap();
+            assert_eq!((volumes[0].volume, volumes[0].trades), (Decimal::from(2), 1));
+            assert_eq!(store.feed_stats(at).await[0].messages, 1);
+
+            store.forget_book("coinbase", "BTC-USD").await;
+            assert_eq!(store.top("coinbase", "BTC-USD").await, None);
+            store.remove("BTC-USD").await;
+            assert!(store.volumes("coinbase", "BTC-USD", &[Duration::from_secs(60)], at).await.is_none());
+            assert!(store.feed_stats(at).await.is_empty());
+        });
+    }
+
+    #[test]
+    fn tickers_parse_into_exact_decimals() {
+        let ticker = |price: &str| Ticker { symbol: "BTC-USD".into(), price: price.into(), open_24h: None, size: Some("1e-3".into()) };
+
+        let update = PriceUpdate::from_ticker("coinbase", "BTC-USD".into(), &ticker("0.1"), SystemTime::now()).unwrap();
+        assert_eq!(update.price + "0.2".parse::<Decimal>().unwrap(), "0.3".parse::<Decimal>().unwrap());
+        assert_eq!(update.size, Some("0.001".parse().unwrap()));
+
+        let err = PriceUpdate::from_ticker("coinbase", "BTC-USD".into(), &ticker("n/a"), SystemTime::now()).unwrap_err();
+        assert_eq!(err.field, "price");
+    }
+}
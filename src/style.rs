@@ -0,0 +1,117 @@
+// Styling for the periodic printout: each price in green or red by its direction
+// since the previous printout, with an arrow and the change, and the whole line in
+// bold reverse video when it moved more than `[output] highlight_pct`. Without color
+// (a pipe or a file, NO_COLOR, `--color never`) the arrows and changes stay, and big
+// movers get a "!!" in front instead.
+
+use std::io::IsTerminal;  // Color only for a real terminal in auto mode
+
+use rust_decimal::Decimal;
+
+use crate::config::ColorMode;
+
+// ANSI escape sequences
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const BOLD_REVERSE: &str = "\x1b[1;7m";
+const RESET: &str = "\x1b[0m";
+
+impl ColorMode {
+    // Whether to write escape codes to stdout
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            // https://no-color.org: any non-empty NO_COLOR turns color off
+            ColorMode::Auto => std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+        }
+    }
+}
+
+// How a price moved between two printouts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Movement {
+    pub delta: Decimal,
+    pub pct: Option<Decimal>,  // None when the previous price was zero
+}
+
+impl Movement {
+    pub fn between(previous: Decimal, price: Decimal) -> Self {
+        let delta = price - previous;
+        let pct = (!previous.is_zero()).then(|| (delta / previous * Decimal::ONE_HUNDRED).round_dp(2));
+        Self { delta, pct }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Styler {
+    color: bool,
+    highlight_pct: Option<Decimal>,  // Moves at least this big (either way) stand out
+}
+
+impl Styler {
+    // `highlight_pct` of zero turns highlighting off
+    pub fn new(color: bool, highlight_pct: Decimal) -> Self {
+        Self { color, highlight_pct: (highlight_pct > Decimal::ZERO).then_some(highlight_pct) }
+    }
+
+    // "$65012.5 ▲ +12.5 (+0.02%)" in green, "▼" in red, "=" uncolored; just the price
+    // when there's nothing to compare with yet
+    pub fn price(&self, text: &str, movement: Option<Movement>) -> String {
+        let Some(movement) = movement else {
+            return text.to_string();
+        };
+        let pct = movement.pct.map(|p| format!(" ({:+}%)", p)).unwrap_or_default();
+        let (color, shown) = if movement.delta > Decimal::ZERO {
+            (GREEN, format!("{} ▲ +{}{}", text, movement.delta, pct))
+        } else if movement.delta < Decimal::ZERO {
+            (RED, format!("{} ▼ {}{}", text, movement.delta, pct))
+        } else {
+            return format!("{} =", text);
+        };
+        if self.color { format!("{}{}{}", color, shown, RESET) } else { shown }
+    }
+
+    // A whole output line, made to stand out when its move passed the threshold
+    pub fn line(&self, line: &str, movement: Option<Movement>) -> String {
+        let big = match (self.highlight_pct, movement.and_then(|m| m.pct)) {
+            (Some(threshold), Some(pct)) => pct.abs() >= threshold,
+            _ => false,
+        };
+        match (big, self.color) {
+            // Re-apply the highlight after the price's own reset
+            (true, true) => format!("{}{}{}", BOLD_REVERSE, line.replace(RESET, &format!("{}{}", RESET, BOLD_REVERSE)), RESET),
+            (true, false) => format!("!! {}", line),
+            (false, _) => line.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn arrows_colors_and_highlights_follow_the_move() {
+        let up = Movement::between(d("100"), d("102.5"));
+        let down = Movement::between(d("100"), d("99.9"));
+        assert_eq!(up, Movement { delta: d("2.5"), pct: Some(d("2.50")) });
+
+        let plain = Styler::new(false, d("2"));
+        assert_eq!(plain.price("$102.5", Some(up)), "$102.5 ▲ +2.5 (+2.50%)");
+        assert_eq!(plain.price("$99.9", Some(down)), "$99.9 ▼ -0.1 (-0.10%)");
+        assert_eq!(plain.price("$100", Some(Movement::between(d("100"), d("100")))), "$100 =");
+        assert_eq!(plain.price("$100", None), "$100");
+        assert_eq!(plain.line("BTC-USD", Some(up)), "!! BTC-USD");
+        assert_eq!(plain.line("BTC-USD", Some(down)), "BTC-USD");
+
+        let colored = Styler::new(true, Decimal::ZERO);  // No highlighting
+        assert_eq!(colored.price("$99.9", Some(down)), "\x1b[31m$99.9 ▼ -0.1 (-0.10%)\x1b[0m");
+        assert_eq!(colored.line("BTC-USD", Some(up)), "BTC-USD");
+        assert_eq!(Styler::new(true, d("1")).line("a \x1b[0m b", Some(up)), "\x1b[1;7ma \x1b[0m\x1b[1;7m b\x1b[0m");
+    }
+}
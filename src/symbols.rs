@@ -0,0 +1,114 @@
+// Loading the list of symbols (product ids) to track, and (with the "watch" feature)
+// following the symbols CSV so edits take effect without a restart.
+
+use std::{
+    error::Error,             // Trait to return errors from our functions
+    fs::File,                 // Used to open the CSV file
+    path::Path,               // Used to handle file paths
+};
+
+use csv::ReaderBuilder;       // CSV parser
+
+#[cfg(feature = "watch")]
+use crate::PriceTracker;
+
+// How long to wait after a change before reloading: editors often save in several
+// steps (truncate, write, rename), and we only want to read the finished file
+#[cfg(feature = "watch")]
+const RELOAD_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
+// Function that reads a CSV file and extracts a list of product IDs (symbols)
+pub fn load_symbols_from_csv<P: AsRef<Path>>(path: P) -> Result<Vec<String>, Box<dyn Error>> {
+    let file = File::open(path)?;  // Open the file, `?` handles error forwarding
+    let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(file); // CSV reader that skips the header
+    let mut symbols = Vec::new();  // To store our symbols
+
+    // Iterate over each row in the CSV
+    for result in rdr.records() {
+        let record = result?;  // Handle any row-level parsing errors
+
+        // Extract the first column (assumes CSV has a single "symbol" column)
+        if let Some(sym) = record.get(0) {
+            symbols.push(sym.trim().to_string()); // Trim whitespace and store symbol
+        }
+    }
+
+    Ok(symbols)  // Return the vector of symbols
+}
+
+// Symbols to start and stop tracking to get from `current` to `wanted`
+#[derive(Debug, Default, PartialEq)]
+pub struct SymbolDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+// Compare the tracked symbols with the wanted ones. Symbols in `keep` (e.g. portfolio
+// holdings) are never removed, even when they aren't wanted.
+pub fn diff_symbols(current: &[String], wanted: &[String], keep: &[String]) -> SymbolDiff {
+    SymbolDiff {
+        added: wanted.iter().filter(|s| !current.contains(s)).cloned().collect(),
+        removed: current.iter().filter(|s| !wanted.contains(s) && !keep.contains(s)).cloned().collect(),
+    }
+}
+
+// Watch the symbols CSV and, whenever it changes, subscribe `tracker` to new symbols
+// and unsubscribe it from removed ones. Runs until the watcher can't be started (Err)
+// or the watcher goes away. A file that can't be read, or is empty, is reported and
+// ignored, so a half-saved file never wipes out every subscription.
+#[cfg(feature = "watch")]
+pub async fn follow_symbols_file(path: &Path, tracker: &PriceTracker, keep: &[String]) -> Result<(), Box<dyn Error>> {
+    use ::notify::{RecursiveMode, Watcher};  // `::` because our own `notify` module shares the name
+
+    // Watch the directory rather than the file: editors that save by renaming a new
+    // file over the old one would otherwise leave us watching a deleted file
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name().ok_or("the symbols file path has no file name")?.to_owned();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = ::notify::recommended_watcher(move |event: ::notify::Result<::notify::Event>| {
+        if let Ok(event) = event
+            && !event.kind.is_access()
+            && event.paths.iter().any(|p| p.file_name() == Some(name.as_os_str()))
+        {
+            let _ = tx.send(());
+        }
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    while rx.recv().await.is_some() {
+        tokio::time::sleep(RELOAD_DELAY).await;
+        while rx.try_recv().is_ok() {}  // Fold the rest of this burst of events into one reload
+
+        let wanted = match load_symbols_from_csv(path) {
+            Ok(symbols) if symbols.iter().any(|s| !s.is_empty()) => symbols,
+            Ok(_) => {
+                tracing::warn!(path = %path.display(), "Symbols file lists no symbols, keeping the current ones");
+                continue;
+            }
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "Reloading the symbols file failed, keeping the current symbols");
+                continue;
+            }
+        };
+        let diff = diff_symbols(&tracker.symbols(), &wanted, keep);
+        if diff == SymbolDiff::default() {
+            continue;  // Saved without changing the symbols
+        }
+        tracker.subscribe(&diff.added);
+        tracker.unsubscribe(&diff.removed).await;
+        tracing::info!(path = %path.display(), added = ?diff.added, removed = ?diff.removed, "Symbols file changed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_adds_new_and_removes_missing_symbols_except_kept_ones() {
+        let list = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let diff = diff_symbols(&list(&["BTC-USD", "ETH-USD", "SOL-USD"]), &list(&["BTC-USD", "ADA-USD"]), &list(&["SOL-USD"]));
+        assert_eq!(diff, SymbolDiff { added: list(&["ADA-USD"]), removed: list(&["ETH-USD"]) });
+    }
+}
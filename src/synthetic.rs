@@ -0,0 +1,244 @@
+// Synthetic crosses: prices for pairs no tracked exchange quotes directly, implied from
+// the pairs that are tracked. Tracking BTC-USD and ETH-BTC gives ETH-USD as
+// ETH-BTC × BTC-USD; USD-BTC is 1 / BTC-USD.
+//
+//     [synthetic]
+//     symbols = ["ETH-USD", "SOL-EUR"]
+//
+// Each symbol is priced along the shortest chain of tracked pairs from its base to its
+// quote currency (at most three, each used either way round). The result goes into the
+// store like any other price, under the exchange name "derived", so the table, the API,
+// alert rules and the portfolio valuation all see it, labelled as such. A derived price
+// is recomputed whenever one of its legs moves, and held back while a leg has no price
+// yet or its feed is stale. The 24h open is derived the same way when every leg has one.
+//
+// A synthetic symbol isn't subscribed on the exchanges (unless it's in `symbols` too, in
+// which case the direct and the derived price show side by side).
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},  // Latest leg prices; the search over currencies
+    fmt,                                        // Routes, printed
+};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::store::{PriceStore, PriceUpdate};
+
+// Exchange name of every derived price
+pub const DERIVED: &str = "derived";
+
+// Longest chain of pairs a synthetic price is worked out through
+const MAX_LEGS: usize = 3;
+
+// Significant digits kept of a derived price (the division of an inverted leg doesn't end)
+const SIGNIFICANT_DIGITS: u32 = 12;
+
+// [synthetic] section of the config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyntheticConfig {
+    pub symbols: Vec<String>,   // Pairs to derive, e.g. "ETH-USD"
+}
+
+// "eth/usd " and "ETH-USD" are the same pair
+pub fn normalize(symbol: &str) -> String {
+    symbol.trim().replace('/', "-").to_uppercase()
+}
+
+// One tracked pair in a route, used as quoted or inverted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leg {
+    pub symbol: String,
+    pub inverted: bool,   // 1 / price: the route goes from its quote to its base currency
+}
+
+// How a synthetic symbol is priced: the product of its legs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub symbol: String,
+    pub legs: Vec<Leg>,
+}
+
+// "ETH-USD = ETH-BTC × BTC-USD", "USD-BTC = 1/BTC-USD"
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = ", self.symbol)?;
+        for (i, leg) in self.legs.iter().enumerate() {
+            let sep = if i == 0 { "" } else { " × " };
+            let inv = if leg.inverted { "1/" } else { "" };
+            write!(f, "{}{}{}", sep, inv, leg.symbol)?;
+        }
+        Ok(())
+    }
+}
+
+impl Route {
+    // The shortest chain of `tracked` pairs from `symbol`'s base to its quote currency.
+    // The symbol itself is left out, so a directly tracked pair can be derived as well.
+    pub fn find(symbol: &str, tracked: &[String]) -> Option<Self> {
+        let (base, quote) = symbol.split_once('-')?;
+        let mut pairs: Vec<(&str, &str, &String)> = tracked
+            .iter()
+            .filter(|s| s.as_str() != symbol)
+            .filter_map(|s| s.split_once('-').map(|(b, q)| (b, q, s)))
+            .collect();
+        pairs.sort();  // Same route every time when there are several as short
+
+        // Breadth first over currencies: each pair leads from its base to its quote, or back
+        let mut seen = HashSet::from([base]);
+        let mut queue = VecDeque::from([(base, Vec::new())]);
+        while let Some((currency, legs)) = queue.pop_front() {
+            if currency == quote {
+                return Some(Self { symbol: symbol.to_string(), legs });
+            }
+            if legs.len() == MAX_LEGS {
+                continue;
+            }
+            for (b, q, pair) in &pairs {
+                let next = match currency {
+                    c if c == *b => Some((*q, false)),
+                    c if c == *q => Some((*b, true)),
+                    _ => None,
+                };
+                if let Some((next, inverted)) = next
+                    && seen.insert(next)
+                {
+                    let mut legs = legs.clone();
+                    legs.push(Leg { symbol: pair.to_string(), inverted });
+                    queue.push_back((next, legs));
+                }
+            }
+        }
+        None
+    }
+
+    fn uses(&self, symbol: &str) -> bool {
+        self.legs.iter().any(|leg| leg.symbol == symbol)
+    }
+}
+
+// Latest price of every leg, and the derived prices they make
+pub struct SyntheticPrices {
+    routes: Vec<Route>,
+    latest: HashMap<String, PriceUpdate>,   // Per leg symbol, from whichever exchange was last
+}
+
+impl SyntheticPrices {
+    pub fn new(routes: Vec<Route>) -> Self {
+        Self { routes, latest: HashMap::new() }
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    // The derived prices that change with this update. Legs whose feed `is_stale` hold
+    // their routes back.
+    pub fn evaluate(&mut self, update: &PriceUpdate, is_stale: impl Fn(&PriceUpdate) -> bool) -> Vec<PriceUpdate> {
+        if update.exchange == DERIVED || !self.routes.iter().any(|r| r.uses(&update.symbol)) {
+            return Vec::new();
+        }
+        self.latest.insert(update.symbol.clone(), update.clone());
+        self.routes
+            .iter()
+            .filter(|route| route.uses(&update.symbol))
+            .filter_map(|route| {
+                let legs: Vec<(&Leg, &PriceUpdate)> =
+                    route.legs.iter().map(|leg| self.latest.get(&leg.symbol).map(|u| (leg, u))).collect::<Option<_>>()?;
+                if legs.iter().any(|(_, u)| is_stale(u)) {
+                    return None;
+                }
+                let price = product(legs.iter().map(|(leg, u)| (*leg, Some(u.price))))?;
+                Some(PriceUpdate {
+                    exchange: DERIVED,
+                    symbol: route.symbol.clone(),
+                    price,
+                    open_24h: product(legs.iter().map(|(leg, u)| (*leg, u.open_24h))),
+                    size: None,
+                    received_at: update.received_at,
+                })
+            })
+            .collect()
+    }
+}
+
+// Multiply the legs' values together, dividing by the inverted ones; None when one is
+// missing or zero
+fn product<'a>(values: impl Iterator<Item = (&'a Leg, Option<Decimal>)>) -> Option<Decimal> {
+    let mut total = Decimal::ONE;
+    for (leg, value) in values {
+        let value = value.filter(|v| *v > Decimal::ZERO)?;
+        total = if leg.inverted { total.checked_div(value)? } else { total.checked_mul(value)? };
+    }
+    Some(total.round_sf(SIGNIFICANT_DIGITS).unwrap_or(total).normalize())
+}
+
+// Derive the prices from every update in `store`, and put them back into it
+pub fn spawn_synthetic(mut synthetic: SyntheticPrices, store: &PriceStore) -> tokio::task::JoinHandle<()> {
+    let mut rx = store.subscribe_updates();
+    let store = store.clone();
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(update) => {
+                    let stale = store.stale().await;
+                    for derived in synthetic.evaluate(&update, |leg| stale.contains(&(leg.exchange, leg.symbol.clone()))) {
+                        store.update(derived).await;
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!(skipped = n, "Synthetic prices fell behind");
+                    store.metrics().record_dropped_events("synthetic", n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn tick(symbol: &str, price: &str, open: &str) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: symbol.to_string(),
+            price: price.parse().unwrap(),
+            open_24h: Some(open.parse().unwrap()),
+            size: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn crosses_are_priced_along_the_shortest_route() {
+        let tracked: Vec<String> = ["BTC-USD", "ETH-BTC", "EUR-USD", "SOL-ETH"].map(String::from).to_vec();
+        let route = |symbol: &str| Route::find(symbol, &tracked).map(|r| r.to_string());
+        assert_eq!(route("ETH-USD").as_deref(), Some("ETH-USD = ETH-BTC × BTC-USD"));
+        assert_eq!(route("USD-BTC").as_deref(), Some("USD-BTC = 1/BTC-USD"));
+        assert_eq!(route("BTC-EUR").as_deref(), Some("BTC-EUR = BTC-USD × 1/EUR-USD"));
+        assert_eq!(route("SOL-EUR"), None, "four legs is too many");
+        assert_eq!(route("DOGE-USD"), None);
+        assert_eq!(normalize(" eth/usd"), "ETH-USD");
+
+        let routes = ["ETH-USD", "BTC-EUR"].iter().map(|s| Route::find(s, &tracked).unwrap()).collect();
+        let mut synthetic = SyntheticPrices::new(routes);
+        let fresh = |_: &PriceUpdate| false;
+        assert!(synthetic.evaluate(&tick("ETH-BTC", "0.05", "0.05"), fresh).is_empty(), "no BTC-USD yet");
+
+        let derived = synthetic.evaluate(&tick("BTC-USD", "60000", "50000"), fresh);
+        let prices: Vec<(&str, String, Option<String>)> =
+            derived.iter().map(|u| (u.symbol.as_str(), u.price.to_string(), u.open_24h.map(|o| o.to_string()))).collect();
+        assert_eq!(prices, [("ETH-USD", "3000".to_string(), Some("2500".to_string()))]);
+        assert_eq!(derived[0].exchange, DERIVED);
+
+        // Derived prices are never legs themselves, and a stale leg holds its route back
+        assert!(synthetic.evaluate(&derived[0], fresh).is_empty());
+        assert!(synthetic.evaluate(&tick("EUR-USD", "1.2", "1.2"), |u| u.symbol == "EUR-USD").is_empty());
+        assert_eq!(synthetic.evaluate(&tick("EUR-USD", "1.2", "1.2"), fresh)[0].price, Decimal::from(50_000));
+    }
+}
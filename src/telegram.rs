@@ -0,0 +1,287 @@
+// Telegram bot: pushes fired alerts to one or more chats and answers commands sent
+// to it there, using the same live state as everything else.
+//
+//   /price BTC-USD            latest price (and 1m/5m/1h stats, indicators) on every exchange
+//   /price BTC-USD kraken     ...on one exchange
+//   /prices                   every tracked symbol
+//   /portfolio                holdings valued at the latest prices, with P&L
+//   /help                     the list above
+//
+// Create a bot with @BotFather, put its token in [telegram] (or CRABBY_TELEGRAM_TOKEN)
+// and list the chats allowed to use it in `chat_ids`. Messages from any other chat
+// are ignored, so a stranger who finds the bot can't query your portfolio.
+//
+// The bot long-polls the Bot API's getUpdates in its own task; sending goes through a
+// queue like the webhooks, so a slow Telegram never holds up the alert task.
+
+use std::{sync::Arc, time::Duration};
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::alerts::Alert;
+use crate::backoff::Backoff;
+use crate::config::TelegramConfig;
+use crate::indicators::Indicators;
+use crate::net::Network;
+use crate::notify::Notifier;
+use crate::portfolio::Portfolio;
+use crate::store::PriceStore;
+
+// Messages waiting to be sent before new ones are dropped
+const QUEUE_CAPACITY: usize = 256;
+// Telegram rejects longer messages
+const MAX_MESSAGE_LEN: usize = 4096;
+
+const HELP: &str = "Commands:\n\
+/price SYMBOL [EXCHANGE] – latest price, e.g. /price BTC-USD\n\
+/prices – every tracked symbol\n\
+/portfolio – holdings, value and P&L\n\
+/help – this message";
+
+// Body of every Bot API response
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,  // Why, when `ok` is false
+}
+
+// One entry of getUpdates; only text messages matter here
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+// The bot: a Bot API client plus the chats it talks to. Cheap to clone.
+#[derive(Clone)]
+pub struct TelegramBot {
+    client: reqwest::Client,
+    base_url: Arc<str>,     // https://api.telegram.org/bot<token>
+    chat_ids: Arc<[i64]>,
+    poll_timeout: Duration,
+}
+
+impl TelegramBot {
+    // A bot for the [telegram] settings, or None when no token is set
+    pub fn from_config(config: &TelegramConfig, network: &Network) -> Option<Self> {
+        let token = config.token.as_deref()?;
+        let client = network
+            .http_client()
+            .user_agent(concat!("crabbycryptotracker/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+        Some(Self {
+            client,
+            base_url: format!("https://api.telegram.org/bot{}", token).into(),
+            chat_ids: config.chat_ids.clone().into(),
+            poll_timeout: config.poll_timeout,
+        })
+    }
+
+    // A notifier that sends fired alerts to every chat. Must be called from inside a
+    // Tokio runtime (it starts the sender task).
+    pub fn notifier(&self) -> TelegramNotifier {
+        let (queue, mut rx) = mpsc::channel::<String>(QUEUE_CAPACITY);
+        let bot = self.clone();
+        tokio::spawn(async move {
+            while let Some(text) = rx.recv().await {
+                for &chat in bot.chat_ids.iter() {
+                    bot.send(chat, &text).await;
+                }
+            }
+        });
+        TelegramNotifier { queue }
+    }
+
+    // Answer commands until the task is dropped. Network errors are retried with backoff.
+    pub async fn run(self, store: PriceStore, portfolio: Option<Arc<Portfolio>>, indicators: Option<Indicators>) {
+        info!(chats = self.chat_ids.len(), "Telegram bot listening for commands");
+        let mut offset = 0;  // First update not handled yet
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        loop {
+            let updates = match self.get_updates(offset).await {
+                Ok(updates) => updates,
+                Err(e) => {
+                    let delay = backoff.next_delay();
+                    warn!(error = %e, retry_in = ?delay, "Telegram getUpdates failed");
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            };
+            backoff.reset();
+            for update in updates {
+                offset = offset.max(update.update_id + 1);
+                let Some(Message { chat, text: Some(text) }) = update.message else { continue };
+                if !self.chat_ids.contains(&chat.id) {
+                    debug!(chat = chat.id, "Ignored a message from a chat that isn't configured");
+                    continue;
+                }
+                if let Some(reply) = answer(&text, &store, portfolio.as_deref(), indicators.as_ref()).await {
+                    self.send(chat.id, &reply).await;
+                }
+            }
+        }
+    }
+
+    // Long-poll for new messages; returns after `poll_timeout` even when there are none
+    async fn get_updates(&self, offset: i64) -> Result<Vec<Update>, String> {
+        let response = self
+            .client
+            .get(format!("{}/getUpdates", self.base_url))
+            .query(&[("offset", offset.to_string()), ("timeout", self.poll_timeout.as_secs().to_string())])
+            .query(&[("allowed_updates", r#"["message"]"#)])
+            .timeout(self.poll_timeout + Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| e.without_url().to_string())?;  // The URL contains the token
+        let body: ApiResponse<Vec<Update>> = response.json().await.map_err(|e| e.without_url().to_string())?;
+        match body {
+            ApiResponse { ok: true, result: Some(updates), .. } => Ok(updates),
+            ApiResponse { description, .. } => Err(description.unwrap_or_else(|| "request rejected".to_string())),
+        }
+    }
+
+    // Send one plain-text message, logging (not retrying) failures
+    async fn send(&self, chat: i64, text: &str) {
+        let text: String = text.chars().take(MAX_MESSAGE_LEN).collect();
+        let result = self
+            .client
+            .post(format!("{}/sendMessage", self.base_url))
+            .timeout(Duration::from_secs(10))
+            .json(&json!({ "chat_id": chat, "text": text }))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = result {
+            warn!(chat, error = %e.without_url(), "Telegram sendMessage failed");
+        }
+    }
+}
+
+// Delivers alerts to the bot's chats
+pub struct TelegramNotifier {
+    queue: mpsc::Sender<String>,
+}
+
+impl Notifier for TelegramNotifier {
+    fn notify(&self, alert: &Alert) {
+        let text = format!("🚨 {}\n{} on {}: ${}\n{}", alert.rule, alert.symbol, alert.exchange, alert.price, alert.detail);
+        if self.queue.try_send(text).is_err() {
+            warn!(rule = %alert.rule, "Telegram queue full, dropped alert");
+        }
+    }
+
+    fn message(&self, text: &str) {
+        if self.queue.try_send(text.to_string()).is_err() {
+            warn!("Telegram queue full, dropped message");
+        }
+    }
+}
+
+// The reply to one message, or None when it isn't a command we know
+pub async fn answer(text: &str, store: &PriceStore, portfolio: Option<&Portfolio>, indicators: Option<&Indicators>) -> Option<String> {
+    let mut words = text.split_whitespace();
+    // In groups commands arrive as "/price@my_bot"
+    let command = words.next()?.split('@').next()?.to_lowercase();
+    let args: Vec<&str> = words.collect();
+
+    let reply = match command.as_str() {
+        "/start" | "/help" => HELP.to_string(),
+        "/price" => {
+            let Some(symbol) = args.first().map(|s| s.to_uppercase()) else {
+                return Some("Usage: /price SYMBOL [EXCHANGE], e.g. /price BTC-USD".to_string());
+            };
+            let exchange = args.get(1).map(|e| e.to_lowercase());
+            let prices = store.view().await;
+            let mut lines = Vec::new();
+            for update in prices.snapshot() {
+                if update.symbol != symbol || exchange.as_deref().is_some_and(|e| e != update.exchange) {
+                    continue;
+                }
+                match store.fx().convert(update.price, &update.symbol) {
+                    Some(converted) => lines.push(format!("{} {}: ${} ({})", update.exchange, update.symbol, update.price, converted)),
+                    None => lines.push(format!("{} {}: ${}", update.exchange, update.symbol, update.price)),
+                }
+                if let Some(stats) = prices.stats(update.exchange, &update.symbol) {
+                    lines.push(format!("  {}", stats));
+                }
+                if let Some(indicators) = indicators {
+                    let readings: Vec<String> =
+                        indicators.readings(update.exchange, &update.symbol).iter().map(|r| r.to_string()).collect();
+                    if !readings.is_empty() {
+                        lines.push(format!("  {}", readings.join("  ")));
+                    }
+                }
+            }
+            if lines.is_empty() {
+                format!("No price for {} yet", symbol)
+            } else {
+                lines.join("\n")
+            }
+        }
+        "/prices" => {
+            let lines: Vec<String> =
+                store.snapshot().await.iter().map(|u| format!("{} {}: ${}", u.exchange, u.symbol, u.price)).collect();
+            if lines.is_empty() { "No prices yet".to_string() } else { lines.join("\n") }
+        }
+        "/portfolio" => match portfolio {
+            Some(portfolio) => portfolio.value(store).await.to_string(),
+            None => "No portfolio configured (--portfolio holdings.csv)".to_string(),
+        },
+        _ if command.starts_with('/') => format!("Unknown command {}\n\n{}", command, HELP),
+        _ => return None,  // Ordinary chatter
+    };
+    Some(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portfolio::Holding;
+    use crate::store::PriceUpdate;
+    use std::time::SystemTime;
+
+    #[test]
+    fn answers_commands_from_the_live_state() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let store = PriceStore::new();
+            store.update(PriceUpdate {
+                exchange: "coinbase",
+                symbol: "BTC-USD".to_string(),
+                price: "65000".parse().unwrap(),
+                open_24h: None,
+                size: None,
+                received_at: SystemTime::now(),
+            }).await;
+            let portfolio = Portfolio::new(vec![Holding {
+                symbol: "BTC-USD".to_string(),
+                quantity: "0.5".parse().unwrap(),
+                cost_basis: "30000".parse().unwrap(),
+            }]);
+
+            let price = answer("/price@crabby_bot btc-usd", &store, None, None).await.unwrap();
+            assert!(price.starts_with("coinbase BTC-USD: $65000"), "{}", price);
+            assert_eq!(answer("/price BTC-USD kraken", &store, None, None).await.unwrap(), "No price for BTC-USD yet");
+            assert!(answer("/portfolio", &store, Some(&portfolio), None).await.unwrap().contains("P&L +2500.00"));
+            assert!(answer("/portfolio", &store, None, None).await.unwrap().starts_with("No portfolio"));
+            assert!(answer("/nope", &store, None, None).await.unwrap().starts_with("Unknown command"));
+            assert_eq!(answer("hello bot", &store, None, None).await, None);
+        });
+    }
+}
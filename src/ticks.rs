@@ -0,0 +1,131 @@
+// Recent ticks per (exchange, symbol): a bounded ring buffer of the raw updates, so
+// charts and "how much did it move in the last 5 minutes" work without a database.
+// `PriceStore` keeps one per series; `PriceStore::ticks` and `GET /history/{symbol}`
+// read them.
+//
+// Unlike `stats::PriceHistory` (one sample per second, for the rolling stats), every
+// tick is kept, up to `max_ticks` of them and no older than `max_age`, whichever
+// limit is hit first.
+
+use std::{
+    collections::VecDeque,          // Ticks, oldest first
+    time::{Duration, SystemTime},
+};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::store::PriceUpdate;
+
+// How much each series keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickLimits {
+    pub max_ticks: usize,    // 0 keeps nothing
+    pub max_age: Duration,   // Measured back from the newest tick
+}
+
+impl Default for TickLimits {
+    fn default() -> Self {
+        Self { max_ticks: 1000, max_age: Duration::from_secs(3600) }
+    }
+}
+
+// The ring buffer of one series
+#[derive(Debug, Default, Clone)]
+pub struct TickBuffer {
+    ticks: VecDeque<PriceUpdate>,
+}
+
+impl TickBuffer {
+    // Add the newest tick and drop whatever falls outside the limits
+    pub fn push(&mut self, update: PriceUpdate, limits: TickLimits) {
+        let newest = update.received_at;
+        self.ticks.push_back(update);
+        while self.ticks.len() > limits.max_ticks {
+            self.ticks.pop_front();
+        }
+        if let Some(cutoff) = newest.checked_sub(limits.max_age) {
+            while self.ticks.front().is_some_and(|t| t.received_at < cutoff) {
+                self.ticks.pop_front();
+            }
+        }
+    }
+
+    // Ticks received at or after `since`, oldest first
+    pub fn since(&self, since: SystemTime) -> Vec<PriceUpdate> {
+        // Ticks are in arrival order, so everything after the first match qualifies
+        let start = self.ticks.partition_point(|t| t.received_at < since);
+        self.ticks.range(start..).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+}
+
+// What a run of ticks adds up to, for charts' axes and quick change figures
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TickSummary {
+    pub count: usize,
+    pub first: Decimal,
+    pub last: Decimal,
+    pub change_pct: Option<Decimal>,  // First to last; None if the first price is zero
+    pub high: Decimal,
+    pub low: Decimal,
+}
+
+impl TickSummary {
+    // None for no ticks at all
+    pub fn of(ticks: &[PriceUpdate]) -> Option<Self> {
+        let (first, last) = (ticks.first()?.price, ticks.last()?.price);
+        Some(Self {
+            count: ticks.len(),
+            first,
+            last,
+            change_pct: (!first.is_zero()).then(|| ((last - first) / first * Decimal::ONE_HUNDRED).round_dp(2)),
+            high: ticks.iter().map(|t| t.price).max()?,
+            low: ticks.iter().map(|t| t.price).min()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(price: u32, secs: u64) -> PriceUpdate {
+        PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: price.into(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_ticks_within_both_limits() {
+        let limits = TickLimits { max_ticks: 3, max_age: Duration::from_secs(60) };
+        let mut buffer = TickBuffer::default();
+        for (price, secs) in [(100, 1000), (101, 1001), (102, 1002), (103, 1003)] {
+            buffer.push(tick(price, secs), limits);
+        }
+        assert_eq!(buffer.len(), 3, "the oldest tick went to make room");
+
+        // A minute later the older ticks age out, however few there are
+        buffer.push(tick(110, 1063), limits);
+        let kept = buffer.since(SystemTime::UNIX_EPOCH);
+        assert_eq!(kept, [tick(103, 1003), tick(110, 1063)]);
+        assert_eq!(buffer.since(SystemTime::UNIX_EPOCH + Duration::from_secs(1010)), [tick(110, 1063)]);
+
+        let summary = TickSummary::of(&kept).unwrap();
+        assert_eq!((summary.first, summary.last, summary.high, summary.low), (103.into(), 110.into(), 110.into(), 103.into()));
+        assert_eq!(summary.change_pct, Some("6.80".parse().unwrap()));
+        assert!(TickSummary::of(&[]).is_none());
+    }
+}
@@ -0,0 +1,462 @@
+// Live trading on Coinbase Advanced Trade: place, cancel and list real orders, by hand at
+// the prompt or from alert rules with a `trade` action. Nothing is sent unless the tracker
+// is started with --enable-trading, and even then every order is only logged ("dry run")
+// until `[trading] dry_run = false`. Each order must also fit the size limits:
+//
+//     [trading]
+//     dry_run = true             # Log orders instead of sending them (CRABBY_TRADING_DRY_RUN)
+//     max_order_value = 100      # Largest order in the quote currency (quantity × price)
+//     max_order_quantity = 0.01  # Largest order in coins (optional)
+//
+//     [[alerts]]
+//     symbol = "BTC-USD"
+//     below = 60000
+//     trade = { side = "buy", quantity = 0.001 }                  # market order...
+//     # trade = { side = "buy", quantity = 0.001, limit = 59500 } # ...or a limit order (good till cancelled)
+//
+// At the prompt (see control.rs):
+//
+//     trade buy BTC-USD 0.001 [@ 59500]
+//     trade orders
+//     trade cancel ORDER_ID
+//
+// Live DCA plans (see dca.rs) buy a fixed amount of the quote currency at market through
+// the same checks.
+//
+// Orders go through the [account] API key, which needs the "Trade" permission. A market
+// order's value is worked out at the latest price, so it can't be checked (and isn't
+// placed) before the symbol has one. An order Coinbase doesn't answer within the request
+// timeout is sent again, up to three times in all, with the same client order id, which
+// Coinbase never places twice.
+
+use std::{
+    error::Error,                                 // Trait to return errors from our functions
+    fmt,                                          // Printable open orders
+    sync::{atomic::{AtomicU64, Ordering}, Arc},   // Client order ids; shared with the alert task
+    time::SystemTime,                             // Client order ids
+};
+
+use futures_util::future::BoxFuture;
+use reqwest::Method;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::account::CoinbaseAccount;
+use crate::alerts::{Alert, TradeAction};
+use crate::config::TradingConfig;
+use crate::dca::OrderPlacer;
+use crate::notify::Notifier;
+use crate::paper::Side;
+use crate::store::epoch_ms;
+
+const ORDERS_PATH: &str = "/api/v3/brokerage/orders";
+const CANCEL_PATH: &str = "/api/v3/brokerage/orders/batch_cancel";
+const LIST_PATH: &str = "/api/v3/brokerage/orders/historical/batch";
+
+// Alert orders waiting to be sent before new ones are dropped
+const QUEUE_CAPACITY: usize = 64;
+
+// Times an order is sent when Coinbase doesn't answer in time
+const PLACE_ATTEMPTS: u32 = 3;
+
+// What became of an order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placed {
+    DryRun,             // Passed the checks, only logged
+    Order(String),      // Sent; Coinbase's order id
+}
+
+// One open order, as listed by Coinbase
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OpenOrder {
+    pub order_id: String,
+    pub product_id: String,
+    pub side: String,                       // "BUY" or "SELL"
+    pub status: String,                     // "OPEN", "PENDING"...
+    #[serde(default)]
+    pub created_time: String,
+    #[serde(default)]
+    pub order_configuration: Value,         // e.g. {"limit_limit_gtc": {"base_size": ..., "limit_price": ...}}
+}
+
+impl OpenOrder {
+    // A field of whichever order type this is
+    fn setting(&self, name: &str) -> Option<&str> {
+        self.order_configuration.as_object()?.values().find_map(|kind| kind.get(name)?.as_str())
+    }
+}
+
+impl fmt::Display for OpenOrder {
+    // "BUY 0.001 BTC-USD @ 59500 (OPEN, 8f1c...)"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.side, self.setting("base_size").unwrap_or("?"), self.product_id)?;
+        if let Some(limit) = self.setting("limit_price") {
+            write!(f, " @ {}", limit)?;
+        }
+        write!(f, " ({}, {})", self.status, self.order_id)
+    }
+}
+
+impl TradingConfig {
+    // Check one order against the limits. `price` is the latest price, needed to value a
+    // market order. Returns the order's value in the quote currency.
+    pub fn check(&self, action: &TradeAction, price: Option<Decimal>) -> Result<Decimal, String> {
+        action.check()?;
+        if let Some(max) = self.max_order_quantity
+            && action.quantity > max
+        {
+            return Err(format!("{} coins is over the {} limit (max_order_quantity)", action.quantity, max));
+        }
+        let price = action.limit.or(price).ok_or("no price yet to value a market order")?;
+        let value = action.quantity * price;
+        if value > self.max_order_value {
+            return Err(format!("{} is over the {} limit (max_order_value)", value.round_dp(2), self.max_order_value));
+        }
+        Ok(value)
+    }
+}
+
+// Body of POST /orders: market orders are immediate-or-cancel, limit orders good till cancelled
+pub fn order_body(client_order_id: &str, symbol: &str, action: &TradeAction) -> Value {
+    let configuration = match action.limit {
+        Some(limit) => json!({
+            "limit_limit_gtc": { "base_size": action.quantity.to_string(), "limit_price": limit.to_string(), "post_only": false }
+        }),
+        None => json!({ "market_market_ioc": { "base_size": action.quantity.to_string() } }),
+    };
+    json!({
+        "client_order_id": client_order_id,
+        "product_id": symbol,
+        "side": action.side.to_string().to_uppercase(),
+        "order_configuration": configuration,
+    })
+}
+
+// Body of POST /orders for a market buy of `amount` in the quote currency (DCA)
+pub fn amount_order_body(client_order_id: &str, symbol: &str, amount: Decimal) -> Value {
+    json!({
+        "client_order_id": client_order_id,
+        "product_id": symbol,
+        "side": "BUY",
+        "order_configuration": { "market_market_ioc": { "quote_size": amount.to_string() } },
+    })
+}
+
+// Response of POST /orders
+#[derive(Debug, Deserialize)]
+struct CreateOrderResponse {
+    success: bool,
+    #[serde(default)]
+    success_response: Option<OrderId>,
+    #[serde(default)]
+    error_response: Option<Value>,      // {"error": ..., "message": ..., "preview_failure_reason": ...}
+}
+
+#[derive(Debug, Deserialize)]
+struct OrderId {
+    order_id: String,
+}
+
+// Response of POST /orders/batch_cancel
+#[derive(Debug, Deserialize)]
+struct CancelResponse {
+    results: Vec<CancelResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CancelResult {
+    success: bool,
+    #[serde(default)]
+    failure_reason: String,
+}
+
+// Response of GET /orders/historical/batch
+#[derive(Debug, Deserialize)]
+struct OrdersPage {
+    orders: Vec<OpenOrder>,
+}
+
+// Places orders within the limits, or just logs them in a dry run
+pub struct Trader {
+    config: TradingConfig,
+    account: CoinbaseAccount,
+    next_id: AtomicU64,   // Makes each client order id unique within a run
+}
+
+impl Trader {
+    pub fn new(config: TradingConfig, account: CoinbaseAccount) -> Self {
+        Self { config, account, next_id: AtomicU64::new(1) }
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.config.dry_run
+    }
+
+    // Check an order and send it (or log it, in a dry run). `source` says who asked for
+    // it ("manual" or the alert rule) and goes into the log.
+    pub async fn place(&self, symbol: &str, action: &TradeAction, price: Option<Decimal>, source: &str) -> Result<Placed, Box<dyn Error>> {
+        let value = self.config.check(action, price).map_err(|e| format!("order refused: {}", e))?;
+        if self.config.dry_run {
+            info!(%symbol, order = %action, %value, source, "[dry run] Would place order");
+            return Ok(Placed::DryRun);
+        }
+        let body = order_body(&self.client_order_id(), symbol, action);
+        self.submit(symbol, body, &action.to_string(), value, source).await
+    }
+
+    // Buy `amount` worth (in the quote currency) of `symbol` at market. `price`, the latest
+    // price, turns it into a quantity for the size limits.
+    pub async fn buy_amount(&self, symbol: &str, amount: Decimal, price: Decimal, source: &str) -> Result<Placed, Box<dyn Error>> {
+        if price <= Decimal::ZERO {
+            return Err("order refused: no price to size it".into());
+        }
+        let estimate = TradeAction { side: Side::Buy, quantity: (amount / price).round_dp(8), limit: None };
+        let value = self.config.check(&estimate, Some(price)).map_err(|e| format!("order refused: {}", e))?;
+        let order = format!("buy {} worth at market", amount);
+        if self.config.dry_run {
+            info!(%symbol, %order, quantity = %estimate.quantity, source, "[dry run] Would place order");
+            return Ok(Placed::DryRun);
+        }
+        let body = amount_order_body(&self.client_order_id(), symbol, amount);
+        self.submit(symbol, body, &order, value, source).await
+    }
+
+    // Coinbase doesn't create a second order for a client_order_id it has seen, so
+    // `submit` can send an order again with the same id when a request times out
+    fn client_order_id(&self) -> String {
+        format!("crabby-{}-{}", epoch_ms(SystemTime::now()), self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    // POST one order; Coinbase's order id, or why it was turned down. A request that
+    // timed out may still have placed the order, so it's sent again as it was, same
+    // client_order_id and all, rather than given up on; any other failure is final.
+    async fn submit(&self, symbol: &str, body: Value, order: &str, value: Decimal, source: &str) -> Result<Placed, Box<dyn Error>> {
+        let mut attempt = 1;
+        let response = loop {
+            match self.account.request::<CreateOrderResponse>(Method::POST, ORDERS_PATH, &[], Some(body.clone())).await {
+                Err(e) if attempt < PLACE_ATTEMPTS && timed_out(e.as_ref()) => {
+                    warn!(%symbol, %order, attempt, client_order_id = %body["client_order_id"], "No answer to the order in time, sending it again");
+                    attempt += 1;
+                }
+                response => break response,
+            }
+        };
+        let error = match response {
+            Ok(CreateOrderResponse { success: true, success_response: Some(OrderId { order_id }), .. }) => {
+                info!(%symbol, %order, %value, order_id = %order_id, source, "Placed order");
+                return Ok(Placed::Order(order_id));
+            }
+            Ok(response) => format!("order rejected: {}", rejection(response.error_response.as_ref())),
+            Err(e) => e.to_string(),
+        };
+        match attempt {
+            1 => Err(error.into()),
+            _ => Err(format!("{} (an earlier attempt timed out and may have been placed; see `trade orders`)", error).into()),
+        }
+    }
+
+    // Cancel one open order (only logged in a dry run)
+    pub async fn cancel(&self, order_id: &str) -> Result<(), Box<dyn Error>> {
+        if self.config.dry_run {
+            info!(order_id, "[dry run] Would cancel order");
+            return Ok(());
+        }
+        let body = json!({ "order_ids": [order_id] });
+        let response: CancelResponse = self.account.request(Method::POST, CANCEL_PATH, &[], Some(body)).await?;
+        match response.results.first() {
+            Some(result) if result.success => Ok(()),
+            Some(result) => Err(format!("cancel failed: {}", result.failure_reason).into()),
+            None => Err("cancel failed: empty response".into()),
+        }
+    }
+
+    // Orders still waiting on the exchange (listed for real, even in a dry run)
+    pub async fn open_orders(&self) -> Result<Vec<OpenOrder>, Box<dyn Error>> {
+        let query = [("order_status", "OPEN".to_string()), ("limit", "250".to_string())];
+        let page: OrdersPage = self.account.request(Method::GET, LIST_PATH, &query, None).await?;
+        Ok(page.orders)
+    }
+
+    // A notifier that places the order of every alert that carries one
+    pub fn notifier(self: &Arc<Self>) -> TradeNotifier {
+        let (tx, mut rx) = mpsc::channel::<Alert>(QUEUE_CAPACITY);
+        let trader = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some(alert) = rx.recv().await {
+                let Some(action) = &alert.trade else { continue };
+                if let Err(e) = trader.place(&alert.symbol, action, Some(alert.price), &alert.rule).await {
+                    warn!(rule = %alert.rule, order = %action, error = %e, "Alert order failed");
+                }
+            }
+        });
+        TradeNotifier { tx }
+    }
+}
+
+// Hands alerts with a `trade` action to the trader's task
+pub struct TradeNotifier {
+    tx: mpsc::Sender<Alert>,
+}
+
+impl Notifier for TradeNotifier {
+    fn notify(&self, alert: &Alert) {
+        if alert.trade.is_some() && self.tx.try_send(alert.clone()).is_err() {
+            warn!(rule = %alert.rule, "Order queue full, dropping an alert order");
+        }
+    }
+}
+
+// Live DCA plans buy through the trader
+impl OrderPlacer for Trader {
+    fn buy<'a>(&'a self, symbol: &'a str, amount: Decimal, price: Decimal, source: &'a str) -> BoxFuture<'a, Result<Option<String>, String>> {
+        Box::pin(async move {
+            match self.buy_amount(symbol, amount, price, source).await {
+                Ok(Placed::DryRun) => Ok(None),
+                Ok(Placed::Order(order_id)) => Ok(Some(order_id)),
+                Err(e) => Err(e.to_string()),
+            }
+        })
+    }
+}
+
+// Did the request get no answer in time (as opposed to a refusal, or a failure to connect)?
+fn timed_out(error: &(dyn Error + 'static)) -> bool {
+    error.downcast_ref::<reqwest::Error>().is_some_and(reqwest::Error::is_timeout)
+}
+
+// Coinbase's reason for turning an order down, from whichever field it put it in
+fn rejection(error: Option<&Value>) -> String {
+    let Some(error) = error else {
+        return "no reason given".to_string();
+    };
+    ["message", "preview_failure_reason", "error"]
+        .iter()
+        .find_map(|field| error.get(field)?.as_str().filter(|s| !s.is_empty()))
+        .unwrap_or("no reason given")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::account::tests::local_account;
+
+    fn d(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn orders_must_fit_the_limits_and_serialize_for_coinbase() {
+        let config = TradingConfig { max_order_quantity: Some(d("0.01")), ..TradingConfig::default() };
+        let market = TradeAction { side: Side::Buy, quantity: d("0.001"), limit: None };
+        assert_eq!(config.check(&market, Some(d("60000"))), Ok(d("60")));
+        assert!(config.check(&market, None).is_err(), "a market order needs a price to be valued");
+        assert!(config.check(&market, Some(d("120000"))).unwrap_err().contains("max_order_value"));
+        let big = TradeAction { quantity: d("0.02"), limit: Some(d("1")), ..market.clone() };
+        assert!(config.check(&big, None).unwrap_err().contains("max_order_quantity"));
+
+        let limit = TradeAction { side: Side::Sell, quantity: d("0.001"), limit: Some(d("70000")) };
+        assert_eq!(config.check(&limit, None), Ok(d("70")));
+        assert_eq!(
+            order_body("crabby-1", "BTC-USD", &limit),
+            json!({
+                "client_order_id": "crabby-1",
+                "product_id": "BTC-USD",
+                "side": "SELL",
+                "order_configuration": { "limit_limit_gtc": { "base_size": "0.001", "limit_price": "70000", "post_only": false } },
+            })
+        );
+        assert_eq!(order_body("crabby-2", "BTC-USD", &market)["order_configuration"], json!({ "market_market_ioc": { "base_size": "0.001" } }));
+        assert_eq!(amount_order_body("crabby-3", "BTC-USD", d("50"))["order_configuration"], json!({ "market_market_ioc": { "quote_size": "50" } }));
+
+        let open: OpenOrder = serde_json::from_value(json!({
+            "order_id": "abc", "product_id": "BTC-USD", "side": "SELL", "status": "OPEN",
+            "order_configuration": { "limit_limit_gtc": { "base_size": "0.001", "limit_price": "70000" } },
+        }))
+        .unwrap();
+        assert_eq!(open.to_string(), "SELL 0.001 BTC-USD @ 70000 (OPEN, abc)");
+        assert_eq!(rejection(Some(&json!({ "error": "INSUFFICIENT_FUND", "message": "" }))), "INSUFFICIENT_FUND");
+    }
+
+    // Answers the order requests that come in on `listener` with `replies` in turn, None
+    // being no answer at all; the client_order_id of each request, and the unanswered
+    // connections (still open)
+    async fn serve(listener: TcpListener, replies: Vec<Option<(u16, Value)>>) -> (Vec<String>, Vec<TcpStream>) {
+        let (mut ids, mut unanswered) = (Vec::new(), Vec::new());
+        for reply in replies {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut chunk = [0; 4096];
+            let body = loop {
+                let n = socket.read(&mut chunk).await.unwrap();
+                request.extend_from_slice(&chunk[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head.lines().find_map(|l| l.to_lowercase().strip_prefix("content-length: ")?.parse::<usize>().ok()).unwrap();
+                    if body.len() == length {
+                        break serde_json::from_str::<Value>(body).unwrap();
+                    }
+                }
+            };
+            ids.push(body["client_order_id"].as_str().unwrap().to_string());
+            match reply {
+                Some((status, body)) => {
+                    let body = body.to_string();
+                    let response = format!("HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", status, body.len(), body);
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                }
+                None => unanswered.push(socket),
+            }
+        }
+        (ids, unanswered)
+    }
+
+    // Place a 0.001 BTC limit order through a trader pointed at a server answering with `replies`
+    fn place(replies: Vec<Option<(u16, Value)>>) -> (Result<Placed, String>, Vec<String>) {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let account = local_account(&format!("http://{}", listener.local_addr().unwrap()), Duration::from_millis(200));
+            let server = tokio::spawn(serve(listener, replies));
+            let trader = Trader::new(TradingConfig { dry_run: false, ..TradingConfig::default() }, account);
+            let action = TradeAction { side: Side::Buy, quantity: d("0.001"), limit: Some(d("60000")) };
+            let placed = trader.place("BTC-USD", &action, None, "test").await.map_err(|e| e.to_string());
+            (placed, server.await.unwrap().0)
+        })
+    }
+
+    #[test]
+    fn an_order_that_times_out_is_sent_again_with_the_same_id() {
+        let placed = Some((200, json!({ "success": true, "success_response": { "order_id": "o-1" } })));
+        let (result, ids) = place(vec![None, placed]);
+        assert_eq!(result, Ok(Placed::Order("o-1".to_string())));
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids[0], ids[1]);
+
+        // Rejected the second time: that may be the duplicate id, so the first may stand
+        let rejected = Some((200, json!({ "success": false, "error_response": { "error": "INVALID_LIMIT_PRICE" } })));
+        let (result, _) = place(vec![None, rejected]);
+        assert_eq!(result.unwrap_err(), "order rejected: INVALID_LIMIT_PRICE (an earlier attempt timed out and may have been placed; see `trade orders`)");
+    }
+
+    #[test]
+    fn orders_are_sent_at_most_three_times_and_only_after_a_timeout() {
+        let (result, ids) = place(vec![None, None, None]);
+        assert!(result.unwrap_err().ends_with("(an earlier attempt timed out and may have been placed; see `trade orders`)"));
+        assert_eq!(ids.len(), 3);
+        assert!(ids.iter().all(|id| *id == ids[0]));
+
+        let (result, ids) = place(vec![Some((500, json!({ "error": "INTERNAL" })))]);
+        assert_eq!(result.unwrap_err(), r#"/api/v3/brokerage/orders 500 Internal Server Error: {"error":"INTERNAL"}"#);
+        assert_eq!(ids.len(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+// InfluxDB backend: batches go out as line protocol to the v2 write endpoint
+// (`POST /api/v2/write?org=...&bucket=...&precision=ms`), which InfluxDB 1.8+ serves too.
+//
+//     ticks,exchange=coinbase,symbol=BTC-USD price=65000.01,size=0.5 1700000000123
+//     candles,exchange=coinbase,symbol=BTC-USD,interval=1m open=1,high=2,low=1,close=2,volume=3,trades=4i 1700000040000
+
+use std::{fmt::Write as _, time::Duration};  // Building the request body
+
+use futures_util::future::BoxFuture;
+
+use super::{Backend, Point, WriteError};
+use crate::config::TsdbConfig;
+use crate::net::Network;
+use crate::store::epoch_ms;
+
+pub struct InfluxBackend {
+    client: reqwest::Client,
+    write_url: url::Url,           // Endpoint with org, bucket and precision already in the query
+    token: Option<String>,         // Sent as `Authorization: Token ...`
+    timeout: Duration,             // Per request
+}
+
+impl InfluxBackend {
+    pub fn new(config: &TsdbConfig, network: &Network) -> Result<Self, String> {
+        let base = config.url.as_deref().ok_or("no [tsdb] url")?;
+        let mut write_url = url::Url::parse(base)
+            .and_then(|u| u.join("api/v2/write"))
+            .map_err(|e| format!("{}: {}", base, e))?;
+        {
+            let mut query = write_url.query_pairs_mut();
+            if !config.org.is_empty() {
+                query.append_pair("org", &config.org);
+            }
+            query.append_pair("bucket", &config.bucket).append_pair("precision", "ms");
+        }
+        let client = network
+            .http_client()
+            .user_agent(concat!("crabbycryptotracker/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self { client, write_url, token: config.token.clone(), timeout: config.timeout })
+    }
+}
+
+impl Backend for InfluxBackend {
+    fn name(&self) -> &'static str {
+        "influxdb"
+    }
+
+    fn write<'a>(&'a mut self, points: &'a [Point]) -> BoxFuture<'a, Result<(), WriteError>> {
+        Box::pin(async move {
+            let body: String = points.iter().map(line).collect();
+            let mut request = self.client.post(self.write_url.clone()).timeout(self.timeout).body(body);
+            if let Some(token) = &self.token {
+                request = request.header("Authorization", format!("Token {}", token));
+            }
+            let response = request.send().await.map_err(|e| WriteError::retryable(e.to_string()))?;
+            let status = response.status();
+            if status.is_success() {
+                return Ok(());
+            }
+            // 4xx means the request itself is wrong (bad token, unknown bucket, bad line):
+            // sending it again won't help. Overload (429) and server errors might clear up.
+            let text = response.text().await.unwrap_or_default();
+            let message = format!("{}: {}", status, text.trim().chars().take(200).collect::<String>());
+            if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                Err(WriteError::fatal(message))
+            } else {
+                Err(WriteError::retryable(message))
+            }
+        })
+    }
+}
+
+// One point as a line of line protocol (newline included)
+fn line(point: &Point) -> String {
+    let mut out = String::new();
+    match point {
+        Point::Tick(update) => {
+            let _ = write!(out, "ticks,exchange={},symbol={} price={}", tag(update.exchange), tag(&update.symbol), update.price);
+            if let Some(size) = update.size {
+                let _ = write!(out, ",size={}", size);
+            }
+            let _ = writeln!(out, " {}", epoch_ms(update.received_at));
+        }
+        Point::Candle(c) => {
+            let interval = humantime::format_duration(c.interval).to_string();
+            let _ = writeln!(
+                out,
+                "candles,exchange={},symbol={},interval={} open={},high={},low={},close={},volume={},trades={}i {}",
+                tag(c.exchange),
+                tag(&c.symbol),
+                tag(&interval),
+                c.open,
+                c.high,
+                c.low,
+                c.close,
+                c.volume,
+                c.trades,
+                c.start_ms
+            );
+        }
+    }
+    out
+}
+
+// Tag values escape commas, spaces and equals signs
+fn tag(value: &str) -> String {
+    value.replace(',', "\\,").replace(' ', "\\ ").replace('=', "\\=")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candles::Candle;
+    use crate::store::PriceUpdate;
+    use std::time::SystemTime;
+
+    #[test]
+    fn formats_ticks_and_candles_as_line_protocol() {
+        let update = PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: "65000.01".parse().unwrap(),
+            open_24h: None,
+            size: Some("0.5".parse().unwrap()),
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+        };
+        assert_eq!(line(&Point::Tick(update)), "ticks,exchange=coinbase,symbol=BTC-USD price=65000.01,size=0.5 1700000000123\n");
+
+        let candle = Candle {
+            exchange: "kraken",
+            symbol: "ETH USD".to_string(),  // Not a real symbol, but shows the escaping
+            interval: Duration::from_secs(60),
+            start_ms: 1_700_000_040_000,
+            open: 1.into(),
+            high: 2.into(),
+            low: 1.into(),
+            close: 2.into(),
+            volume: 3.into(),
+            trades: 4,
+        };
+        assert_eq!(
+            line(&Point::Candle(candle)),
+            "candles,exchange=kraken,symbol=ETH\\ USD,interval=1m open=1,high=2,low=1,close=2,volume=3,trades=4i 1700000040000\n"
+        );
+    }
+}
@@ -0,0 +1,318 @@
+// Time-series database sink: every tick (and every closed candle) written to InfluxDB
+// or TimescaleDB, picked by the URL's scheme:
+//
+//     [tsdb]
+//     url = "http://localhost:8086"                      # InfluxDB (line protocol over HTTP)
+//     # url = "postgres://crabby@localhost:5432/crabby"  # TimescaleDB (or plain PostgreSQL)
+//     token = "..."       # InfluxDB API token, or the PostgreSQL password
+//     bucket = "crypto"   # InfluxDB only, like `org`
+//
+// The database must never be able to stall the feeds. Points are handed over with a
+// non-blocking `try_send` into a bounded queue; a background task writes them in
+// batches, retries a failed batch with backoff and then gives up on it. While the
+// database is down or slow the queue fills and new points are dropped (and counted)
+// instead of piling up in memory; writing picks up again by itself once it's back.
+
+use std::{
+    fmt,                                           // Printable write errors
+    sync::{
+        atomic::{AtomicU64, Ordering},             // Dropped point counter, shared with the writer
+        Arc,
+    },
+    time::Duration,                                // Flush and retry timing
+};
+
+use futures_util::future::BoxFuture;               // Backends are trait objects with async writes
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc},     // The bounded queue in front of the writer
+    task::JoinHandle,
+    time::{sleep, sleep_until, timeout, Instant},
+};
+use tracing::{error, info, warn};
+
+use crate::backoff::Backoff;
+use crate::candles::Candle;
+use crate::config::TsdbConfig;
+use crate::net::Network;
+use crate::store::{PriceStore, PriceUpdate};
+
+mod influx;
+mod timescale;
+
+pub use influx::InfluxBackend;
+pub use timescale::TimescaleBackend;
+
+// How long `close` waits for the last batch before giving up on it
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// One row for the database
+#[derive(Debug, Clone, PartialEq)]
+pub enum Point {
+    Tick(PriceUpdate),
+    Candle(Candle),
+}
+
+// Why a batch wasn't written
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteError {
+    pub message: String,
+    pub retryable: bool,  // Worth trying again (network trouble, overload), or not (bad data, bad credentials)
+}
+
+impl WriteError {
+    pub fn retryable(message: impl Into<String>) -> Self {
+        Self { message: message.into(), retryable: true }
+    }
+
+    pub fn fatal(message: impl Into<String>) -> Self {
+        Self { message: message.into(), retryable: false }
+    }
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// A database the sink can write to. Backends only do the I/O; batching, retries and
+// dropping points are the sink's job.
+pub trait Backend: Send {
+    fn name(&self) -> &'static str;
+
+    // Write one batch, all or nothing
+    fn write<'a>(&'a mut self, points: &'a [Point]) -> BoxFuture<'a, Result<(), WriteError>>;
+}
+
+// The backend for a [tsdb] url: http(s):// is InfluxDB, postgres:// is TimescaleDB
+pub fn backend(config: &TsdbConfig, network: &Network) -> Result<Box<dyn Backend>, String> {
+    let url = config.url.as_deref().ok_or("no [tsdb] url")?;
+    let parsed = url::Url::parse(url).map_err(|e| format!("{}: {}", url, e))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(Box::new(InfluxBackend::new(config, network)?)),
+        "postgres" | "postgresql" => Ok(Box::new(TimescaleBackend::new(config)?)),
+        other => Err(format!("unsupported scheme \"{}\" (expected http, https or postgres)", other)),
+    }
+}
+
+// How the writer batches and retries
+#[derive(Debug, Clone)]
+pub struct SinkSettings {
+    pub batch_size: usize,         // Write as soon as this many points are queued...
+    pub flush_interval: Duration,  // ...or when this much time has passed since the last write
+    pub queue: usize,              // Points waiting to be written before new ones are dropped
+    pub retries: u32,              // Extra attempts per batch
+}
+
+// Messages understood by the writer task
+enum Command {
+    Point(Point),
+    Close,  // Write whatever is queued and stop
+}
+
+// Handle to the writer task
+pub struct TsdbSink {
+    tx: mpsc::Sender<Command>,
+    dropped: Arc<AtomicU64>,       // Points lost to a full queue or a failed batch
+    writer: Option<JoinHandle<()>>,
+}
+
+impl TsdbSink {
+    // Start the writer task. Must be called from inside a Tokio runtime.
+    pub fn start(backend: Box<dyn Backend>, settings: SinkSettings) -> Self {
+        let (tx, rx) = mpsc::channel(settings.queue.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer = tokio::spawn(writer_loop(backend, rx, settings, Arc::clone(&dropped)));
+        Self { tx, dropped, writer: Some(writer) }
+    }
+
+    // A sink for the [tsdb] settings, or None when no url is set
+    pub fn from_config(config: &TsdbConfig, network: &Network) -> Result<Option<Self>, String> {
+        if config.url.is_none() {
+            return Ok(None);
+        }
+        let settings = SinkSettings {
+            batch_size: config.batch_size,
+            flush_interval: config.flush_interval,
+            queue: config.queue,
+            retries: config.retries,
+        };
+        Ok(Some(Self::start(backend(config, network)?, settings)))
+    }
+
+    // Queue one point. Never blocks: with the queue full, the point is dropped.
+    pub fn record(&self, point: Point) {
+        enqueue(&self.tx, &self.dropped, point);
+    }
+
+    // Points dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    // A cheap, cloneable callback that queues candles; handy for `candles::spawn_candles`
+    pub fn candle_sink(&self) -> impl Fn(&Candle) + Send + 'static {
+        let (tx, dropped) = (self.tx.clone(), Arc::clone(&self.dropped));
+        move |candle: &Candle| enqueue(&tx, &dropped, Point::Candle(candle.clone()))
+    }
+
+    // Spawn a task that queues every update published by `store`
+    pub fn attach(&self, store: &PriceStore) -> JoinHandle<()> {
+        let (tx, dropped) = (self.tx.clone(), Arc::clone(&self.dropped));
+        let mut rx = store.subscribe_updates();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(update) => {
+                        if tx.is_closed() {
+                            break;  // Writer task has shut down
+                        }
+                        enqueue(&tx, &dropped, Point::Tick(update));
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        dropped.fetch_add(n, Ordering::Relaxed);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    // Write what's queued and stop, waiting at most CLOSE_TIMEOUT for a database that's down.
+    // Returns how many points were dropped in all.
+    pub async fn close(mut self) -> u64 {
+        let _ = timeout(CLOSE_TIMEOUT, self.tx.send(Command::Close)).await;
+        if let Some(writer) = self.writer.take()
+            && timeout(CLOSE_TIMEOUT, writer).await.is_err()
+        {
+            warn!("Time-series database didn't take the last batch in time, giving up on it");
+        }
+        self.dropped()
+    }
+}
+
+fn enqueue(tx: &mpsc::Sender<Command>, dropped: &AtomicU64, point: Point) {
+    if tx.try_send(Command::Point(point)).is_err() {
+        dropped.fetch_add(1, Ordering::Relaxed);  // Reported by the writer, so a full queue doesn't flood the log
+    }
+}
+
+// Runs as a task: collect points and write them out in batches
+async fn writer_loop(mut backend: Box<dyn Backend>, mut rx: mpsc::Receiver<Command>, settings: SinkSettings, dropped: Arc<AtomicU64>) {
+    let mut batch: Vec<Point> = Vec::with_capacity(settings.batch_size);
+    let mut deadline = Instant::now() + settings.flush_interval;
+    let mut reported = 0;  // Dropped count already logged
+
+    loop {
+        let closing = tokio::select! {
+            command = rx.recv() => match command {
+                Some(Command::Point(point)) => {
+                    batch.push(point);
+                    false
+                }
+                Some(Command::Close) | None => true,
+            },
+            () = sleep_until(deadline) => false,
+        };
+
+        let due = batch.len() >= settings.batch_size || Instant::now() >= deadline;
+        if (due || closing) && !batch.is_empty() {
+            if let Err(e) = write_with_retries(backend.as_mut(), &batch, settings.retries).await {
+                error!(backend = backend.name(), points = batch.len(), error = %e, "Time-series write failed, batch dropped");
+                dropped.fetch_add(batch.len() as u64, Ordering::Relaxed);
+            }
+            batch.clear();
+        }
+        if due {
+            deadline = Instant::now() + settings.flush_interval;
+            let total = dropped.load(Ordering::Relaxed);
+            if total > reported {
+                warn!(backend = backend.name(), dropped = total - reported, total, "Time-series database falling behind, points dropped");
+                reported = total;
+            }
+        }
+        if closing {
+            break;
+        }
+    }
+    info!(backend = backend.name(), "Time-series writer stopped");
+}
+
+// Write one batch, retrying what's worth retrying with backoff
+async fn write_with_retries(backend: &mut dyn Backend, batch: &[Point], retries: u32) -> Result<(), WriteError> {
+    let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(10));
+    let mut attempt = 0;
+    loop {
+        match backend.write(batch).await {
+            Ok(()) => return Ok(()),
+            Err(e) if !e.retryable || attempt == retries => return Err(e),
+            Err(e) => {
+                let delay = backoff.next_delay();
+                warn!(backend = backend.name(), attempt = attempt + 1, error = %e, ?delay, "Time-series write failed, retrying");
+                sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    // Fails the first `failures` writes, then keeps what it's given
+    struct Flaky {
+        failures: u32,
+        written: Arc<Mutex<Vec<Point>>>,
+    }
+
+    impl Backend for Flaky {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn write<'a>(&'a mut self, points: &'a [Point]) -> BoxFuture<'a, Result<(), WriteError>> {
+            Box::pin(async move {
+                if self.failures > 0 {
+                    self.failures -= 1;
+                    return Err(WriteError::retryable("connection refused"));
+                }
+                self.written.lock().unwrap().extend_from_slice(points);
+                Ok(())
+            })
+        }
+    }
+
+    fn tick(price: &str) -> Point {
+        Point::Tick(PriceUpdate {
+            exchange: "coinbase",
+            symbol: "BTC-USD".to_string(),
+            price: price.parse().unwrap(),
+            open_24h: None,
+            size: None,
+            received_at: SystemTime::UNIX_EPOCH,
+        })
+    }
+
+    #[test]
+    fn retries_failed_batches_and_drops_what_the_queue_cannot_hold() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let written = Arc::new(Mutex::new(Vec::new()));
+            let backend = Flaky { failures: 2, written: Arc::clone(&written) };
+            let settings = SinkSettings { batch_size: 2, flush_interval: Duration::from_secs(1), queue: 3, retries: 3 };
+            let sink = TsdbSink::start(Box::new(backend), settings);
+
+            // Nothing has been taken off the queue yet, so the fourth point doesn't fit
+            for price in ["1", "2", "3", "4"] {
+                sink.record(tick(price));
+            }
+            assert_eq!(sink.dropped(), 1);
+
+            sink.close().await;  // Two failed attempts, then everything queued is written
+            assert_eq!(*written.lock().unwrap(), vec![tick("1"), tick("2"), tick("3")]);
+        });
+    }
+}
@@ -0,0 +1,221 @@
+// Trade volume from the exchanges' trade channels (Coinbase "matches", Binance
+// "<symbol>@trade", Kraken "trade"): per symbol and exchange, how much traded, in how
+// many trades, and at what volume-weighted average price (VWAP) over rolling windows.
+//
+//     [trades]
+//     enabled = true
+//     windows = ["1m", "5m", "1h"]   # shown in the periodic output
+//
+// The same figures drive "volume spike" alert rules: the volume of the last window
+// compared with the average volume per window over the rest of the hour.
+//
+// Trades are summed into one bucket per second and an hour of buckets is kept, so a
+// busy symbol costs at most 3600 buckets, however many trades it gets.
+
+use std::{
+    collections::VecDeque,             // Buckets, oldest first
+    fmt,                               // One-line summary for the terminal output
+    time::{Duration, SystemTime},      // Trade times and windows
+};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::config::deserialize_durations;
+use crate::store::{entry, get, BySymbol};  // One series per (exchange, symbol)
+
+// How much trade history is kept (the longest window)
+pub const HISTORY: Duration = Duration::from_secs(3600);
+
+// [trades] section of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TradeConfig {
+    pub enabled: bool,             // Subscribe to the trade channel
+    #[serde(deserialize_with = "deserialize_durations")]
+    pub windows: Vec<Duration>,    // Rolling windows shown in the output, at most 1h
+}
+
+impl Default for TradeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            windows: vec![Duration::from_secs(60), Duration::from_secs(300), Duration::from_secs(3600)],
+        }
+    }
+}
+
+// Everything traded within one second
+#[derive(Debug, Clone)]
+struct Bucket {
+    second: u64,        // Seconds since the Unix epoch
+    volume: Decimal,    // Sum of trade sizes (base currency)
+    notional: Decimal,  // Sum of price × size (quote currency), for the VWAP
+    trades: u64,
+}
+
+// Recent trades of one symbol on one exchange
+#[derive(Debug, Default, Clone)]
+pub struct TradeVolume {
+    buckets: VecDeque<Bucket>,
+    first_second: Option<u64>,  // When the first trade was seen, to know how much history we have
+}
+
+impl TradeVolume {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add one trade. Buckets older than `HISTORY` (measured from this one) are dropped.
+    pub fn record(&mut self, at: SystemTime, price: Decimal, size: Decimal) {
+        let second = epoch_secs(at);
+        self.first_second.get_or_insert(second);
+        match self.buckets.back_mut() {
+            // A late trade (e.g. the clock stepped back) goes into the newest bucket
+            Some(last) if second <= last.second => last.add(price, size),
+            _ => {
+                let mut bucket = Bucket { second, volume: Decimal::ZERO, notional: Decimal::ZERO, trades: 0 };
+                bucket.add(price, size);
+                self.buckets.push_back(bucket);
+            }
+        }
+        let cutoff = second.saturating_sub(HISTORY.as_secs());
+        while self.buckets.front().is_some_and(|b| b.second <= cutoff) {
+            self.buckets.pop_front();
+        }
+    }
+
+    // Volume, trade count and VWAP over the `window` up to `now`
+    pub fn window(&self, window: Duration, now: SystemTime) -> VolumeStats {
+        let now = epoch_secs(now);
+        let (volume, notional, trades) = self.sum(now.saturating_sub(window.as_secs()), now);
+        let vwap = (!volume.is_zero()).then(|| (notional / volume).round_dp(8).normalize());
+        VolumeStats { window, volume: volume.normalize(), trades, vwap }
+    }
+
+    // Volume over the `window` up to `now` and the average volume per `window` over the
+    // rest of the kept history before it. None until there's at least one full window of
+    // history before the current one, or when nothing traded in it.
+    pub fn spike(&self, window: Duration, now: SystemTime) -> Option<(Decimal, Decimal)> {
+        let (now, window) = (epoch_secs(now), window.as_secs().max(1));
+        let start = self.first_second?.max(now.saturating_sub(HISTORY.as_secs()));
+        let baseline_secs = now.saturating_sub(start).checked_sub(window).filter(|&secs| secs >= window)?;
+        let (recent, _, _) = self.sum(now - window, now);
+        let (baseline, _, _) = self.sum(start.saturating_sub(1), now - window);  // From `start` itself
+        let average = baseline * Decimal::from(window) / Decimal::from(baseline_secs);
+        (!average.is_zero()).then_some((recent.normalize(), average))
+    }
+
+    // Totals of the buckets in (from, to]
+    fn sum(&self, from: u64, to: u64) -> (Decimal, Decimal, u64) {
+        self.buckets
+            .iter()
+            .filter(|b| b.second > from && b.second <= to)
+            .fold((Decimal::ZERO, Decimal::ZERO, 0), |(v, n, t), b| (v + b.volume, n + b.notional, t + b.trades))
+    }
+}
+
+impl Bucket {
+    fn add(&mut self, price: Decimal, size: Decimal) {
+        self.volume += size;
+        self.notional += price * size;
+        self.trades += 1;
+    }
+}
+
+// Trading activity of one symbol over one window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeStats {
+    pub window: Duration,
+    pub volume: Decimal,        // Base currency, e.g. BTC
+    pub trades: u64,
+    pub vwap: Option<Decimal>,  // None when nothing traded
+}
+
+// "1m 12.5 (34 trades, VWAP 65000.12)"
+impl fmt::Display for VolumeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({} trades", humantime::format_duration(self.window), self.volume, self.trades)?;
+        if let Some(vwap) = self.vwap {
+            write!(f, ", VWAP {}", vwap)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// Every series, keyed by (exchange, our symbol). Owned by the store's state thread, like
+// `OrderBooks`, so there's no lock.
+#[derive(Debug, Default)]
+pub struct TradeVolumes {
+    series: BySymbol<TradeVolume>,
+}
+
+impl TradeVolumes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, exchange: &'static str, symbol: &str, at: SystemTime, price: Decimal, size: Decimal) {
+        entry(&mut self.series, exchange, symbol).record(at, price, size);
+    }
+
+    // Stats for each of `windows` up to `now`, or None before the first trade
+    pub fn stats(&self, exchange: &str, symbol: &str, windows: &[Duration], now: SystemTime) -> Option<Vec<VolumeStats>> {
+        let volume = get(&self.series, exchange, symbol)?;
+        Some(windows.iter().map(|&w| volume.window(w, now)).collect())
+    }
+
+    // See `TradeVolume::spike`
+    pub fn spike(&self, exchange: &str, symbol: &str, window: Duration, now: SystemTime) -> Option<(Decimal, Decimal)> {
+        get(&self.series, exchange, symbol)?.spike(window, now)
+    }
+
+    // Forget one symbol on every exchange (it's no longer tracked)
+    pub fn remove_symbol(&mut self, symbol: &str) {
+        self.series.remove(symbol);
+    }
+}
+
+fn epoch_secs(at: SystemTime) -> u64 {
+    at.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+    }
+
+    fn d(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn rolling_volume_vwap_and_spikes() {
+        let mut volume = TradeVolume::new();
+        let minute = Duration::from_secs(60);
+
+        volume.record(at(0), d("100"), d("1"));
+        volume.record(at(0), d("200"), d("1"));  // Same second, same bucket
+        let stats = volume.window(minute, at(30));
+        assert_eq!((stats.volume, stats.trades, stats.vwap), (d("2"), 2, Some(d("150"))));
+        assert_eq!(stats.to_string(), "1m 2 (2 trades, VWAP 150)");
+        assert_eq!(volume.window(minute, at(120)).vwap, None, "nothing in the last minute");
+        assert_eq!(volume.spike(minute, at(60)), None, "no history before the window yet");
+
+        // One unit a minute for ten minutes, then ten units in the eleventh
+        for m in 1..10 {
+            volume.record(at(m * 60), d("100"), d("1"));
+        }
+        volume.record(at(650), d("100"), d("10"));
+        let (recent, average) = volume.spike(minute, at(660)).unwrap();
+        assert_eq!(recent, d("10"));
+        assert_eq!(average.round_dp(2), d("1.1"));  // 11 units over the 10 minutes before
+
+        // An hour later the old buckets are gone
+        volume.record(at(5000), d("1"), d("1"));
+        assert_eq!(volume.buckets.len(), 1);
+    }
+}
@@ -0,0 +1,262 @@
+// Watchlists: named groups of symbols ("majors", "defi", "memes") summed up as one line
+// each in the periodic summary and the dashboard: an index value, the average 24h
+// change of the members and the top mover.
+//
+//     [watchlists.majors]
+//     symbols = ["BTC-USD", "ETH-USD", "SOL-USD"]
+//     weighting = "market-cap"          # or "equal" (the default)
+//     supply = { BTC-USD = 19_700_000, ETH-USD = 120_000_000, SOL-USD = 470_000_000 }
+//
+//     [watchlists.memes]
+//     symbols = ["DOGE-USD", "SHIB-USD"]
+//
+// The index starts at 100 and follows the members from the first price each one had
+// this session. Equal weighting averages the members' moves; market-cap weighting
+// weighs each by price times `supply` (the circulating supply, which no feed sends, so
+// the config gives it), the way the big crypto indices do. Members join the index as
+// their first price arrives, at their weight but without moving it.
+//
+// A symbol listed in a watchlist is tracked like any other. When several exchanges
+// quote it, the most recent price counts.
+
+use std::{
+    collections::{BTreeMap, HashMap},  // Watchlists by name; first price per symbol
+    fmt,                               // One-line summary
+    sync::{Arc, Mutex},                // Base prices shared by the printout and the dashboard
+};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::store::{PriceStore, PriceUpdate};
+
+// How members count towards a watchlist's index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Weighting {
+    #[default]
+    Equal,       // Every member moves the index the same
+    MarketCap,   // By price times circulating supply
+}
+
+impl fmt::Display for Weighting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Weighting::Equal => "equal",
+            Weighting::MarketCap => "market-cap",
+        })
+    }
+}
+
+// One [watchlists.<name>] table
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchlistConfig {
+    pub symbols: Vec<String>,
+    pub weighting: Weighting,
+    pub supply: BTreeMap<String, Decimal>,  // Circulating supply per symbol, for market-cap weighting
+}
+
+// What the summary line shows for one watchlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchlistSummary {
+    pub name: String,
+    pub weighting: Weighting,
+    pub members: usize,
+    pub priced: usize,                          // Members with a price so far
+    pub index: Option<Decimal>,                 // 100 at the start of the session
+    pub market_cap: Option<Decimal>,            // Of the priced members, when weighted by it
+    pub avg_change_pct: Option<Decimal>,        // Mean 24h change of the members that report an open
+    pub top_mover: Option<(String, Decimal)>,   // Largest 24h move either way
+}
+
+// "majors (market-cap, 3/3): index 101.24  cap $1.52T  avg 24h +1.84%  top SOL-USD +6.10%"
+impl fmt::Display for WatchlistSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}, {}/{})", self.name, self.weighting, self.priced, self.members)?;
+        match self.index {
+            Some(index) => write!(f, ": index {:.2}", index)?,
+            None => return write!(f, ": no prices yet"),
+        }
+        if let Some(cap) = self.market_cap {
+            write!(f, "  cap ${}", compact(cap))?;
+        }
+        if let Some(avg) = self.avg_change_pct {
+            write!(f, "  avg 24h {:+.2}%", avg)?;
+        }
+        if let Some((symbol, change)) = &self.top_mover {
+            write!(f, "  top {} {:+.2}%", symbol, change)?;
+        }
+        Ok(())
+    }
+}
+
+// Big dollar amounts the way people say them: 1.52T, 830.10B, 12.00M
+pub(crate) fn compact(value: Decimal) -> String {
+    let units = [(Decimal::from(1_000_000_000_000u64), "T"), (Decimal::from(1_000_000_000), "B"), (Decimal::from(1_000_000), "M")];
+    match units.iter().find(|(size, _)| value.abs() >= *size) {
+        Some((size, unit)) => format!("{:.2}{}", value / size, unit),
+        None => format!("{:.2}", value),
+    }
+}
+
+// Every configured watchlist plus the first price of each member. Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct Watchlists {
+    lists: Arc<Vec<(String, WatchlistConfig)>>,
+    bases: Arc<Mutex<HashMap<String, Decimal>>>,  // First price seen per symbol
+}
+
+impl Watchlists {
+    // Symbols are upper-cased like everywhere else; names keep the config's (sorted) order
+    pub fn new(configs: &BTreeMap<String, WatchlistConfig>) -> Self {
+        let lists = configs
+            .iter()
+            .map(|(name, cfg)| {
+                let cfg = WatchlistConfig {
+                    symbols: cfg.symbols.iter().map(|s| s.trim().to_uppercase()).collect(),
+                    weighting: cfg.weighting,
+                    supply: cfg.supply.iter().map(|(s, supply)| (s.trim().to_uppercase(), *supply)).collect(),
+                };
+                (name.clone(), cfg)
+            })
+            .collect();
+        Self { lists: Arc::new(lists), bases: Arc::default() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.lists.iter().map(|(name, _)| name.as_str())
+    }
+
+    // The members of one watchlist, if there is one by that name
+    pub fn symbols(&self, name: &str) -> Option<&[String]> {
+        self.lists.iter().find(|(n, _)| n == name).map(|(_, cfg)| cfg.symbols.as_slice())
+    }
+
+    // Every member of every watchlist, once
+    pub fn all_symbols(&self) -> Vec<String> {
+        let mut all: Vec<String> = Vec::new();
+        for symbol in self.lists.iter().flat_map(|(_, cfg)| &cfg.symbols) {
+            if !all.contains(symbol) {
+                all.push(symbol.clone());
+            }
+        }
+        all
+    }
+
+    // Every watchlist summed up at the store's latest prices
+    pub async fn summaries(&self, store: &PriceStore) -> Vec<WatchlistSummary> {
+        let prices = store.view().await;
+        self.summaries_with(|symbol| prices.latest(symbol))
+    }
+
+    // Same as `summaries`, with the price lookup supplied by the caller
+    pub fn summaries_with(&self, latest: impl Fn(&str) -> Option<PriceUpdate>) -> Vec<WatchlistSummary> {
+        let mut bases = self.bases.lock().unwrap();
+        self.lists
+            .iter()
+            .map(|(name, cfg)| {
+                let prices: Vec<(&String, PriceUpdate)> = cfg.symbols.iter().filter_map(|s| latest(s).map(|u| (s, u))).collect();
+                for (symbol, update) in &prices {
+                    bases.entry(symbol.to_string()).or_insert(update.price);
+                }
+                summarize(name, cfg, &prices, &bases)
+            })
+            .collect()
+    }
+}
+
+// One watchlist's figures from its members' latest updates
+fn summarize(name: &str, cfg: &WatchlistConfig, prices: &[(&String, PriceUpdate)], bases: &HashMap<String, Decimal>) -> WatchlistSummary {
+    // Each member's move since its first price, with its weight in the index
+    let mut weighted = Decimal::ZERO;
+    let mut total_weight = Decimal::ZERO;
+    let mut market_cap = Decimal::ZERO;
+    for (symbol, update) in prices {
+        let base = bases[symbol.as_str()];
+        if base.is_zero() {
+            continue;
+        }
+        let supply = cfg.supply.get(symbol.as_str()).copied().unwrap_or_default();
+        let weight = match cfg.weighting {
+            Weighting::Equal => Decimal::ONE,
+            Weighting::MarketCap => base * supply,
+        };
+        weighted += weight * update.price / base;
+        total_weight += weight;
+        market_cap += update.price * supply;
+    }
+    let index = (!total_weight.is_zero()).then(|| (weighted / total_weight * Decimal::ONE_HUNDRED).round_dp(4));
+
+    // 24h moves, for the members whose exchange reports an open
+    let changes: Vec<(&String, Decimal)> = prices
+        .iter()
+        .filter_map(|(symbol, u)| u.open_24h.filter(|o| !o.is_zero()).map(|open| (*symbol, (u.price - open) / open * Decimal::ONE_HUNDRED)))
+        .collect();
+    let avg_change_pct = (!changes.is_empty()).then(|| (changes.iter().map(|(_, c)| c).sum::<Decimal>() / Decimal::from(changes.len())).round_dp(4));
+    let top_mover = changes.iter().max_by_key(|(_, c)| c.abs()).map(|(s, c)| (s.to_string(), c.round_dp(4)));
+
+    WatchlistSummary {
+        name: name.to_string(),
+        weighting: cfg.weighting,
+        members: cfg.symbols.len(),
+        priced: prices.len(),
+        index,
+        market_cap: (cfg.weighting == Weighting::MarketCap && !prices.is_empty()).then_some(market_cap),
+        avg_change_pct,
+        top_mover,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn index_follows_members_by_weight() {
+        let configs: BTreeMap<String, WatchlistConfig> = toml::from_str(
+            r#"
+            [majors]
+            symbols = ["btc-usd", "ETH-USD"]
+            weighting = "market-cap"
+            supply = { BTC-USD = 10, ETH-USD = 100 }
+
+            [memes]
+            symbols = ["DOGE-USD", "ETH-USD"]
+            "#,
+        )
+        .unwrap();
+        let watchlists = Watchlists::new(&configs);
+        assert_eq!(watchlists.all_symbols(), ["BTC-USD", "ETH-USD", "DOGE-USD"]);
+
+        let prices = |btc: &str, eth: &str| {
+            let (btc, eth) = (btc.to_string(), eth.to_string());
+            move |symbol: &str| {
+                let (price, open) = match symbol {
+                    "BTC-USD" => (btc.parse().unwrap(), "100".parse().unwrap()),
+                    "ETH-USD" => (eth.parse().unwrap(), "10".parse().unwrap()),
+                    _ => return None,
+                };
+                Some(PriceUpdate { exchange: "coinbase", symbol: symbol.to_string(), price, open_24h: Some(open), size: None, received_at: SystemTime::now() })
+            }
+        };
+        // First look: everything at its base
+        let start = watchlists.summaries_with(prices("100", "10"));
+        assert_eq!(start[0].index, Some(Decimal::ONE_HUNDRED));
+        assert_eq!(start[1].to_string(), "memes (equal, 1/2): index 100.00  avg 24h +0.00%  top ETH-USD +0.00%");
+
+        // BTC up 10% with a cap of 1100, ETH down 5% with 950: the index is up 2.5%...
+        let later = watchlists.summaries_with(prices("110", "9.5"));
+        assert_eq!(later[0].index, Some("102.5".parse().unwrap()));
+        assert_eq!(later[0].market_cap, Some(Decimal::from(2050)));
+        assert_eq!(later[0].top_mover, Some(("BTC-USD".to_string(), Decimal::from(10))));
+        assert_eq!(later[0].to_string(), "majors (market-cap, 2/2): index 102.50  cap $2050.00  avg 24h +2.50%  top BTC-USD +10.00%");
+        // ...while the equal-weighted list only has ETH, which fell
+        assert_eq!(later[1].index, Some(Decimal::from(95)));
+    }
+}
@@ -0,0 +1,213 @@
+// The whole pipeline (connection, frame validation, parsing, the store, candles and
+// alert rules) against scripted feeds instead of Coinbase: `MockSource` for frames over
+// an in-process channel, and a local WebSocket server for the real connection code.
+//
+// Fixtures are recordings in the `--record` format (tests/fixtures/*.ndjson).
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+use crabbycryptotracker::{
+    alerts::AlertEngine,
+    candles::{spawn_candles, CandleAggregator},
+    net::Network,
+    source::{MockSource, WebSocketSource},
+    PriceTracker, PriceUpdate,
+};
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap()
+}
+
+// A Coinbase tracker reading from `source`, without the stale-feed check (the mock
+// sends no heartbeats unless told to)
+fn tracker(source: MockSource) -> PriceTracker {
+    let mut tracker = PriceTracker::new();
+    tracker.detect_stale_feeds(None);
+    tracker.use_source(Arc::new(source));
+    tracker
+}
+
+fn symbols(symbols: &[&str]) -> Vec<String> {
+    symbols.iter().map(|s| s.to_string()).collect()
+}
+
+fn ticker(symbol: &str, price: &str) -> String {
+    format!(r#"{{"type":"ticker","product_id":"{}","price":"{}","open_24h":"64000"}}"#, symbol, price)
+}
+
+// The next `n` updates, failing the test if they take more than a few seconds
+async fn next_updates(updates: &mut (impl futures_util::Stream<Item = PriceUpdate> + Unpin), n: usize) -> Vec<PriceUpdate> {
+    let mut received = Vec::with_capacity(n);
+    while received.len() < n {
+        match tokio::time::timeout(Duration::from_secs(5), updates.next()).await {
+            Ok(Some(update)) => received.push(update),
+            _ => panic!("expected {} updates, got {}: {:?}", n, received.len(), received),
+        }
+    }
+    received
+}
+
+#[test]
+fn recorded_frames_flow_into_the_store_and_candles() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/coinbase_ticker.ndjson");
+    let mock = MockSource::from_recording(&fixture).unwrap();
+    let mut tracker = tracker(mock.clone());
+
+    runtime().block_on(async {
+        let candles = Arc::new(Mutex::new(CandleAggregator::new(vec![Duration::from_secs(60)], 10)));
+        spawn_candles(Arc::clone(&candles), tracker.store(), |_| {});
+        let mut updates = tracker.updates();
+        tracker.subscribe(&symbols(&["BTC-USD", "ETH-USD"]));
+
+        // The truncated frame, the bad price and the untracked DOGE-USD ticker are dropped
+        let received = next_updates(&mut updates, 4).await;
+        let prices: Vec<(String, String)> = received.iter().map(|u| (u.symbol.clone(), u.price.to_string())).collect();
+        assert_eq!(
+            prices,
+            [("BTC-USD", "65000.00"), ("ETH-USD", "3500.25"), ("BTC-USD", "65100.00"), ("BTC-USD", "64950.50")]
+                .map(|(s, p)| (s.to_string(), p.to_string()))
+        );
+        assert_eq!(tracker.latest("BTC-USD").await.unwrap().open_24h, Some("64000.00".parse().unwrap()));
+        assert!(tracker.latest("DOGE-USD").await.is_none());
+
+        // One subscribe message for both symbols, in Coinbase's format
+        let sent = mock.sent("coinbase");
+        assert_eq!(sent.len(), 1);
+        let subscribe: serde_json::Value = serde_json::from_str(&sent[0]).unwrap();
+        assert_eq!(subscribe["type"], "subscribe");
+        assert_eq!(subscribe["channels"][0], serde_json::json!({"name": "ticker", "product_ids": ["BTC-USD", "ETH-USD"]}));
+
+        // The candle task folds the same updates into OHLC (a tick may straddle a minute)
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let btc = candles.lock().unwrap().candles("coinbase", "BTC-USD", Duration::from_secs(60));
+            if btc.last().is_some_and(|c| c.close == "64950.50".parse().unwrap()) {
+                assert_eq!(btc.iter().map(|c| c.high).max(), Some("65100.00".parse().unwrap()));
+                break;
+            }
+            assert!(Instant::now() < deadline, "candles never caught up: {:?}", btc);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        tracker.shutdown().await;
+    });
+}
+
+#[test]
+fn alerts_fire_on_pushed_prices_and_survive_a_reconnect() {
+    let mock = MockSource::new();
+    let mut tracker = tracker(mock.clone());
+    let mut engine = AlertEngine::from_toml("[[alert]]\nsymbol = \"BTC-USD\"\nabove = 70000\ncooldown = \"0s\"\n").unwrap();
+
+    runtime().block_on(async {
+        let mut updates = tracker.updates();
+        tracker.subscribe(&symbols(&["BTC-USD"]));
+
+        mock.push("coinbase", &ticker("BTC-USD", "69000"));
+        mock.push("coinbase", &ticker("BTC-USD", "70500"));
+        let fired: Vec<_> = next_updates(&mut updates, 2).await.iter().flat_map(|u| engine.evaluate(u, Instant::now())).collect();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].rule, "BTC-USD above 70000");
+        assert_eq!(fired[0].price, "70500".parse().unwrap());
+
+        // The server drops the connection: the feed reconnects and subscribes again
+        mock.close("coinbase");
+        mock.push("coinbase", &ticker("BTC-USD", "69500"));
+        mock.push("coinbase", &ticker("BTC-USD", "71000"));
+        let fired: Vec<_> = next_updates(&mut updates, 2).await.iter().flat_map(|u| engine.evaluate(u, Instant::now())).collect();
+        assert_eq!(fired.len(), 1, "crossed again after the reconnect");
+        assert_eq!(mock.connections("coinbase"), 2);
+        assert_eq!(mock.sent("coinbase").len(), 2);
+        tracker.shutdown().await;
+    });
+}
+
+#[test]
+fn websocket_source_reads_from_a_local_server() {
+    runtime().block_on(async {
+        // A one-connection server playing Coinbase: answers the subscription with a ticker
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap()).parse().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let subscribe = ws.next().await.unwrap().unwrap().into_text().unwrap();
+            ws.send(Message::Text(ticker("ETH-USD", "3600.5"))).await.unwrap();
+            while ws.next().await.is_some() {}  // Until the client closes
+            subscribe
+        });
+
+        let mut tracker = PriceTracker::new();
+        tracker.detect_stale_feeds(None);
+        tracker.use_source(Arc::new(WebSocketSource::new(Network::default()).at(url)));
+        let mut updates = tracker.updates();
+        tracker.subscribe(&symbols(&["ETH-USD"]));
+
+        let received = next_updates(&mut updates, 1).await;
+        assert_eq!((received[0].symbol.as_str(), received[0].price), ("ETH-USD", "3600.5".parse().unwrap()));
+        tracker.shutdown().await;  // Sends a Close frame, which ends the server's loop
+        let subscribe = server.await.unwrap();
+        assert!(subscribe.contains("\"subscribe\"") && subscribe.contains("ETH-USD"), "{}", subscribe);
+    });
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn shutdown_closes_the_connection_and_flushes_storage() {
+    use crabbycryptotracker::storage::{read_history, Storage, StorageConfig};
+
+    let path = std::env::temp_dir().join(format!("crabby-shutdown-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    runtime().block_on(async {
+        // A server that sends three tickers and then waits for the client to say goodbye
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap()).parse().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await.unwrap().unwrap();  // The subscription
+            for price in ["65000", "65100", "65200"] {
+                ws.send(Message::Text(ticker("BTC-USD", price))).await.unwrap();
+            }
+            while let Some(Ok(message)) = ws.next().await {
+                if message.is_close() {
+                    return true;
+                }
+            }
+            false
+        });
+
+        // Nothing gets written before the shutdown: only closing the storage flushes
+        let mut config = StorageConfig::new(&path);
+        config.flush_interval = Duration::from_secs(3600);
+        let storage = Storage::open(config).unwrap();
+        let mut tracker = PriceTracker::new();
+        tracker.detect_stale_feeds(None);
+        tracker.use_source(Arc::new(WebSocketSource::new(Network::default()).at(url)));
+        let attached = storage.attach(tracker.store());
+        let mut updates = tracker.updates();
+        tracker.subscribe(&symbols(&["BTC-USD"]));
+        next_updates(&mut updates, 3).await;
+        assert!(read_history(&path, Some("BTC-USD")).unwrap().is_empty(), "written before the shutdown");
+
+        // The same order as the binary: feeds first, then the sinks
+        let started = Instant::now();
+        tracker.shutdown().await;
+        assert!(started.elapsed() < Duration::from_secs(1), "the feed had to be aborted");
+        assert!(server.await.unwrap(), "no Close frame before the connection ended");
+        drop(tracker);  // The last of the store: the storage task sees the end of the updates
+        tokio::time::timeout(Duration::from_secs(5), attached).await.expect("storage task still running").unwrap();
+        storage.close();
+    });
+
+    let prices: Vec<String> = read_history(&path, Some("BTC-USD")).unwrap().into_iter().map(|t| t.price).collect();
+    assert_eq!(prices, ["65000", "65100", "65200"]);
+    std::fs::remove_file(&path).unwrap();
+}